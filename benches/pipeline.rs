@@ -0,0 +1,128 @@
+//! Measures the cost of the stages of the DaaS pipeline, so that performance regressions are caught and
+//! optimizations are measurable.
+//!
+//! + serialization - serializing and deserializing DaaS documents with large data objects
+//! + storage - the latency of upserting DaaS documents in the LocalStorage
+//! + broker - the rate of brokering DaaS documents to the InMemoryBroker
+//! + ingestion - the throughput of validating, storing and brokering DaaS documents like the listener
+//!
+//! Run all the benchmarks with `cargo bench`, or a group of them with `cargo bench -- storage`.
+//! The benchmarks use `criterion`, which warms up, detects the outliers and compares each run against the saved
+//! baseline, (e.g.: `cargo bench -- --save-baseline main` and `cargo bench -- --baseline main`).
+
+extern crate criterion;
+extern crate daas;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use daas::doc::DaaSDoc;
+use daas::eventing::broker::DaaSKafkaProcessor;
+use daas::eventing::memory::InMemoryBroker;
+use daas::service::listener::DaaSListener;
+use daas::storage::local::LocalStorage;
+use daas::storage::DaaSDocStorage;
+use daas::testing;
+use std::env;
+use std::fs;
+
+// The directory of the DaaS documents that are stored by the benchmarks
+const BENCH_DIR: &str = "./tmp/bench";
+
+// Returns a DaaS document with a data object of the size
+fn get_large_doc(uid: usize, size: usize) -> DaaSDoc {
+    let mut doc = testing::get_daas_doc(
+        "iStore".to_string(),
+        uid,
+        "order".to_string(),
+        "clothing".to_string(),
+    );
+    doc.data_obj = vec![b'x'; size];
+    doc
+}
+
+fn serialization(c: &mut Criterion) {
+    let mut group = c.benchmark_group("serialization");
+
+    for size in [1024, 64 * 1024, 1024 * 1024].iter() {
+        let mut doc = get_large_doc(1, *size);
+        let serialized = doc.serialize();
+        let name = format!("{}KB", size / 1024);
+        group.throughput(Throughput::Bytes(*size as u64));
+
+        group.bench_function(BenchmarkId::new("serialize", &name), |b| {
+            b.iter(|| doc.serialize())
+        });
+        group.bench_function(BenchmarkId::new("deserialize", &name), |b| {
+            b.iter(|| DaaSDoc::from_serialized(serialized.as_bytes()).unwrap())
+        });
+    }
+
+    group.finish();
+}
+
+fn storage(c: &mut Criterion) {
+    let mut group = c.benchmark_group("storage");
+    let storage = LocalStorage::new(BENCH_DIR.to_string());
+
+    let mut uid = 0;
+    group.bench_function("upsert/new", |b| {
+        b.iter(|| {
+            uid += 1;
+            storage.upsert_daas_doc(get_large_doc(uid, 1024)).unwrap()
+        })
+    });
+    let mut doc = storage.upsert_daas_doc(get_large_doc(0, 1024)).unwrap();
+    group.bench_function("upsert/revision", |b| {
+        b.iter(|| {
+            doc = storage.upsert_daas_doc(doc.clone()).unwrap();
+        })
+    });
+
+    group.finish();
+    let _ = fs::remove_dir_all(BENCH_DIR);
+}
+
+fn broker(c: &mut Criterion) {
+    let mut group = c.benchmark_group("broker");
+    group.throughput(Throughput::Bytes(1024));
+    let broker = InMemoryBroker::new();
+    let mut doc = get_large_doc(1, 1024);
+
+    group.bench_function("publish", |b| {
+        b.iter(|| broker.broker_message(&mut doc, "genesis").unwrap())
+    });
+    let topics: Vec<String> = ["order", "order.clothing", "iStore", "genesis"]
+        .iter()
+        .map(|t| t.to_string())
+        .collect();
+    group.bench_function("fan-out/4-topics", |b| {
+        b.iter(|| broker.broker_message_to_topics(&mut doc, &topics).unwrap())
+    });
+
+    group.finish();
+}
+
+fn ingestion(c: &mut Criterion) {
+    let mut group = c.benchmark_group("ingestion");
+    group.throughput(Throughput::Bytes(1024));
+    env::set_var("DAAS_LOCAL_STORAGE", BENCH_DIR);
+    let broker = InMemoryBroker::new();
+
+    let mut uid = 0;
+    group.bench_function("process_data", |b| {
+        b.iter(|| {
+            uid += 1;
+            DaaSListener::process_data_with_broker(
+                get_large_doc(100_000 + uid, 1024),
+                Some("genesis".to_string()),
+                broker.clone(),
+            )
+            .unwrap()
+        })
+    });
+
+    group.finish();
+    let _ = fs::remove_dir_all(BENCH_DIR);
+}
+
+criterion_group!(benches, serialization, storage, broker, ingestion);
+criterion_main!(benches);
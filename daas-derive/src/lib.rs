@@ -0,0 +1,120 @@
+//! The derive macros of the DaaS SDK, (see the `daas` crate).
+//!
+//! `#[derive(AuthorExtractor)]` writes the boilerplate of an Author Extractor, so only the extraction of the author
+//! is implemented, (see the `ExtractAuthor` trait). The structure must have a `name: String` field and implement
+//! `Clone`. The derive implements the default functions of the `AuthorExtractor` trait, (the other fields are
+//! initialized with their `Default`) and the `FromRequest` trait of actix-web.
+//!
+//! ```ignore
+//! use daas::service::extractor::{AuthorExtractor, ExtractAuthor};
+//!
+//! #[derive(AuthorExtractor, Debug, Clone)]
+//! pub struct HeaderAuthor {
+//!     name: String,
+//! }
+//!
+//! impl ExtractAuthor for HeaderAuthor {
+//!     fn extract_author(
+//!         &mut self,
+//!         req: &HttpRequest,
+//!         _payload: &mut actix_web::dev::Payload,
+//!     ) -> Result<String, MissingAuthorError> {
+//!         ...
+//!     }
+//! }
+//! ```
+
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Error, Fields};
+
+#[proc_macro_derive(AuthorExtractor)]
+pub fn derive_author_extractor(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    match author_extractor(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+fn author_extractor(input: &DeriveInput) -> Result<proc_macro2::TokenStream, Error> {
+    let ident = &input.ident;
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(Error::new(
+                    Span::call_site(),
+                    "AuthorExtractor can only be derived for a struct with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(Error::new(
+                Span::call_site(),
+                "AuthorExtractor can only be derived for a struct",
+            ))
+        }
+    };
+
+    if !input.generics.params.is_empty() {
+        return Err(Error::new(
+            Span::call_site(),
+            "AuthorExtractor can't be derived for a generic struct",
+        ));
+    }
+
+    if !fields
+        .iter()
+        .any(|f| f.ident.as_ref().map(|i| i == "name").unwrap_or(false))
+    {
+        return Err(Error::new(
+            Span::call_site(),
+            "AuthorExtractor requires a `name: String` field",
+        ));
+    }
+
+    // the other fields are initialized with their default
+    let defaults = fields
+        .iter()
+        .filter_map(|f| f.ident.as_ref())
+        .filter(|i| *i != "name")
+        .map(|i| quote! { #i: ::std::default::Default::default(), });
+
+    Ok(quote! {
+        impl ::daas::service::extractor::AuthorExtractor for #ident {
+            fn extract_author(
+                &mut self,
+                req: &::daas::macros::__private::HttpRequest,
+                payload: &mut ::daas::macros::__private::Payload,
+            ) -> ::std::result::Result<::std::string::String, ::daas::errors::MissingAuthorError> {
+                <Self as ::daas::service::extractor::ExtractAuthor>::extract_author(self, req, payload)
+            }
+
+            fn get_name(&self) -> ::std::string::String {
+                self.name.clone()
+            }
+
+            fn new() -> Self {
+                Self {
+                    name: "Anonymous".to_string(),
+                    #(#defaults)*
+                }
+            }
+
+            fn set_name(
+                &mut self,
+                name: ::std::string::String,
+            ) -> ::std::result::Result<Self, ::daas::errors::MissingAuthorError> {
+                self.name = name;
+                Ok(self.clone())
+            }
+        }
+
+        ::daas::author_from_request!(#ident);
+    })
+}
@@ -1,24 +1,22 @@
-#[macro_use]
-extern crate daas;
 extern crate actix_web;
+extern crate daas;
 
 use actix_web::{web, App, HttpServer};
 use daas::service::listener::{DaaSListener, DaaSListenerService};
 use pbd::dtc::middleware::actix::*;
 use pbd::dua::middleware::actix::*;
 
-use actix_web::{FromRequest, HttpRequest};
+use actix_web::HttpRequest;
 use daas::errors::MissingAuthorError;
-use daas::macros;
-use daas::service::extractor::{AuthorExtractor, LocalError};
-use futures::future::{err, ok, Ready};
-/// Build our own Author Extractor
-use serde::{Deserialize, Serialize};
+use daas::service::extractor::{AuthorExtractor, ExtractAuthor};
 
-// Use macros to crate our structure
-author_struct!(MyAuthor);
+/// Build our own Author Extractor, (the derive writes the default functions and the FromRequest trait)
+#[derive(AuthorExtractor, Debug, Clone)]
+pub struct MyAuthor {
+    name: String,
+}
 
-impl AuthorExtractor for MyAuthor {
+impl ExtractAuthor for MyAuthor {
     fn extract_author(
         &mut self,
         _req: &HttpRequest,
@@ -26,16 +24,8 @@ impl AuthorExtractor for MyAuthor {
     ) -> Result<String, MissingAuthorError> {
         Ok("Knot, Tellin".to_string())
     }
-
-    // Use macros to write the default functions
-    author_fn_get_name!();
-    author_fn_new!();
-    author_fn_set_name!();
 }
 
-// Use macros to write the implmentation of the FromRequest trait
-author_from_request!(MyAuthor);
-
 #[actix_rt::main]
 async fn main() -> std::io::Result<()> {
     std::env::set_var("RUST_LOG", "warn");
@@ -1,9 +1,13 @@
 extern crate actix_web;
 extern crate daas;
 
+use actix_web::middleware::Compress;
 use actix_web::{web, App, HttpServer};
+use daas::service::compression::Decompression;
+use daas::service::cors::Cors;
 use daas::service::extractor::Base64Author;
 use daas::service::listener::{DaaSListener, DaaSListenerService};
+use daas::storage::local::LocalStorage;
 use pbd::dtc::middleware::actix::*;
 use pbd::dua::middleware::actix::*;
 
@@ -14,17 +18,35 @@ async fn main() -> std::io::Result<()> {
     //std::env::set_var("DAAS_LOCAL_STORAGE", "C:\\tmp");
     env_logger::init();
 
+    // replay any documents that were being written when the service last stopped
+    match LocalStorage::new(LocalStorage::get_local_path()).recover() {
+        Ok(report) => println!("Recovered {} DaaS documents.", report.recovered.len()),
+        Err(err) => println!("Could not recover the local storage: {}", err),
+    }
+
     HttpServer::new(|| {
         App::new()
             .wrap(DUAEnforcer::default())
             .wrap(DTCEnforcer::default())
+            // compresses the responses and rejects the request bodies that can't be decompressed
+            .wrap(Compress::default())
+            .wrap(Decompression)
+            // answers the preflight requests of the browser-based producers before they reach the enforcers
+            .wrap(Cors::new().with_origin("http://localhost:3000".to_string()))
             .service(
                 web::resource(&DaaSListener::get_service_health_path())
                     .route(web::get().to(DaaSListener::health)),
             )
+            .service(
+                web::resource(&DaaSListener::get_service_status_path())
+                    .route(web::get().to(DaaSListener::status)),
+            )
             .service(
                 web::resource(&DaaSListener::get_service_path())
-                    .route(web::post().to(DaaSListener::index::<Base64Author>)),
+                    .route(web::post().to(DaaSListener::index::<Base64Author>))
+                    .route(web::get().to(DaaSListener::retrieve::<Base64Author>))
+                    .route(web::patch().to(DaaSListener::patch::<Base64Author>))
+                    .route(web::delete().to(DaaSListener::delete::<Base64Author>)),
             )
     })
     .bind("localhost:8088")
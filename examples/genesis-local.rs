@@ -0,0 +1,34 @@
+extern crate daas;
+extern crate kafka;
+
+use daas::service::processor::{DaaSGenesisProcessorService, DaasGenesisProcessor};
+use daas::storage::local::LocalStorage;
+use kafka::consumer::{FetchOffset, GroupOffsetStorage};
+use std::io;
+
+// The DaaS documents are provisioned to the .objects directory of the local storage path instead of a S3 Bucket,
+// (see the DAAS_LOCAL_STORAGE environment variable)
+fn main() {
+    std::env::set_var("RUST_LOG", "info");
+    env_logger::init();
+    let hosts = vec!["localhost:9092".to_string()];
+    let store = LocalStorage::new(LocalStorage::get_local_path());
+
+    let stopper = DaasGenesisProcessor::run_with_store(
+        hosts,
+        FetchOffset::Earliest,
+        GroupOffsetStorage::Kafka,
+        store,
+    );
+
+    println!("Genesis processor (local) is running ...");
+    println!("Press [Enter] to stop the Genesis processor.");
+
+    let mut input = String::new();
+    match io::stdin().read_line(&mut input) {
+        Ok(_n) => {
+            DaasGenesisProcessor::stop(stopper);
+        }
+        Err(error) => println!("error: {}", error),
+    }
+}
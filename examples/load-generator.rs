@@ -0,0 +1,126 @@
+extern crate base64;
+extern crate daas;
+extern crate pbd;
+extern crate reqwest;
+
+use daas::doc::DaaSDoc;
+use daas::testing;
+use pbd::dtc::DTC_HEADER;
+use pbd::dua::DUA_HEADER;
+use std::env;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Sends the DaaS document to the listener and returns how long it took
+fn send(client: &reqwest::blocking::Client, url: &str, doc: &DaaSDoc) -> Option<Duration> {
+    let start = Instant::now();
+    let rspns = client
+        .post(format!(
+            "{}/{}/{}/{}/{}",
+            url, doc.category, doc.subcategory, doc.source_name, doc.source_uid
+        ))
+        .header("Content-Type", "application/json")
+        .header(
+            "Authorization",
+            base64::encode(format!("{}:password", doc.author).as_bytes()),
+        )
+        .header(
+            DUA_HEADER,
+            serde_json::to_string(&doc.data_usage_agreements).unwrap(),
+        )
+        .header(
+            DTC_HEADER,
+            base64::encode(doc.data_tracker.serialize().as_bytes()),
+        )
+        .body(doc.data_obj.clone())
+        .send();
+
+    match rspns {
+        Ok(r) if r.status().is_success() => Some(start.elapsed()),
+        Ok(r) => {
+            println!("{} was rejected with {}", doc._id, r.status());
+            None
+        }
+        Err(err) => {
+            println!("{} could not be sent. {}", doc._id, err);
+            None
+        }
+    }
+}
+
+// usage: cargo run --example load-generator -- [url] [requests] [threads] [payload bytes]
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let url = Arc::new(
+        args.get(1)
+            .cloned()
+            .unwrap_or_else(|| "http://localhost:8088".to_string()),
+    );
+    let requests: usize = args.get(2).and_then(|a| a.parse().ok()).unwrap_or(1000);
+    let threads: usize = args.get(3).and_then(|a| a.parse().ok()).unwrap_or(8);
+    let payload: usize = args.get(4).and_then(|a| a.parse().ok()).unwrap_or(1024);
+
+    println!(
+        "Sending {} DaaS documents ({} bytes) to {} using {} threads ...",
+        requests, payload, url, threads
+    );
+
+    let start = Instant::now();
+    let workers: Vec<_> = (0..threads)
+        .map(|t| {
+            let url = url.clone();
+            thread::spawn(move || {
+                let client = reqwest::blocking::Client::new();
+                let mut latencies = Vec::new();
+                let mut failures = 0;
+
+                for i in (t..requests).step_by(threads) {
+                    let mut doc = testing::get_daas_doc(
+                        "loadgen".to_string(),
+                        i,
+                        "order".to_string(),
+                        "clothing".to_string(),
+                    );
+                    let mut data = br#"{"status": "new", "filler": ""#.to_vec();
+                    data.extend(vec![b'x'; payload]);
+                    data.extend(br#""}"#.to_vec());
+                    doc.data_obj = data;
+
+                    match send(&client, &url, &doc) {
+                        Some(l) => latencies.push(l),
+                        None => failures += 1,
+                    }
+                }
+
+                (latencies, failures)
+            })
+        })
+        .collect();
+
+    let mut latencies = Vec::new();
+    let mut failures = 0;
+    for worker in workers {
+        let (l, f) = worker.join().unwrap();
+        latencies.extend(l);
+        failures += f;
+    }
+    let elapsed = start.elapsed();
+
+    latencies.sort();
+    println!(
+        "{} succeeded and {} failed in {:.2?} ({:.0} docs/s)",
+        latencies.len(),
+        failures,
+        elapsed,
+        latencies.len() as f64 / elapsed.as_secs_f64()
+    );
+    if !latencies.is_empty() {
+        println!(
+            "latency median: {:.2?}  p99: {:.2?}  max: {:.2?}",
+            latencies[latencies.len() / 2],
+            latencies[(latencies.len() * 99 / 100).min(latencies.len() - 1)],
+            latencies[latencies.len() - 1]
+        );
+    }
+}
@@ -1,6 +1,7 @@
 extern crate daas;
 extern crate kafka;
 
+use daas::config::DaasConfig;
 use daas::service::processor::{DaaSProcessor, DaaSProcessorMessage, DaaSProcessorService};
 use kafka::consumer::{Consumer, FetchOffset, GroupOffsetStorage};
 use serde_json::value::Value;
@@ -14,7 +15,8 @@ fn main() {
 
     // configuration settings
     let hosts = vec!["localhost:9092".to_string()];
-    let topic = "order.clothing".to_string();
+    // the topic prefix of the environment, (e.g.: DAAS_TOPIC_PREFIX=dev)
+    let topic = DaasConfig::from_env().topic("order.clothing");
 
     // parameters
     let (tx, rx) = channel();
@@ -0,0 +1,856 @@
+//! The audit module records who did what to which DaaS document, when and with which outcome.
+//!
+//! Every ingestion, retrieval, deletion, brokering and decryption of a DaaS document is recorded as an `AuditEvent`
+//! in an append-only `AuditSink`, (a file, a Kafka topic or a storage device). The events are recorded by wrapping
+//! the storage device in an `AuditedStorage`, the broker in an `AuditedBroker` and by decrypting through
+//! `AuditLog::decrypt()`. The listener records the ingestions when an `AuditLog` is registered as application data,
+//! (e.g.: App::new().data(AuditLog::new(FileAuditSink::new("./audit/daas.log".to_string())))).
+//!
+//! # Examples
+//!
+//! ```
+//! extern crate daas;
+//!
+//! use daas::audit::{AuditAction, AuditLog, AuditQuery, AuditedStorage, InMemoryAuditSink};
+//! use daas::storage::DaaSDocStorage;
+//! use daas::storage::memory::InMemoryStorage;
+//! use daas::testing;
+//!
+//! fn main() {
+//!     let log = AuditLog::new(InMemoryAuditSink::new());
+//!     let storage = AuditedStorage::new(InMemoryStorage::new(), log.clone(), "order_service".to_string());
+//!     let doc = storage.upsert_daas_doc(testing::get_default_daas_doc()).unwrap();
+//!     storage.get_doc_by_id(doc._id.clone(), None).unwrap();
+//!
+//!     let retrievals = log.query(&AuditQuery::new().with_doc_id(doc._id).with_action(AuditAction::Retrieve)).unwrap();
+//!     assert_eq!(retrievals.len(), 1);
+//!     assert_eq!(retrievals[0].actor, "order_service".to_string());
+//! }
+//! ```
+
+use super::*;
+use crate::doc::{DaaSDoc, SourceId};
+use crate::errors::*;
+#[cfg(feature = "kafka")]
+use crate::eventing::broker::DaaSKafkaProcessor;
+#[cfg(any(feature = "security", feature = "security-rustcrypto"))]
+use crate::security::DaaSSecurityGuard;
+use crate::storage::{DaaSDocStorage, Page};
+#[cfg(feature = "kafka")]
+use kafka::client::KafkaClient;
+#[cfg(feature = "kafka")]
+use kafka::producer::{Producer, Record, RequiredAcks};
+use pbd::dtc::Tracker;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+#[cfg(feature = "kafka")]
+use std::time::Duration;
+
+/// The category of the DaaS documents that hold the audit events in a storage device
+pub const AUDIT_CATEGORY: &str = "audit";
+
+/// Represents what was done to the DaaS document
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditAction {
+    /// The DaaS document was received and saved
+    Ingest,
+    /// The DaaS document was read
+    Retrieve,
+    /// The DaaS document was (soft) deleted
+    Delete,
+    /// The DaaS document was sent to a topic
+    Broker,
+    /// The data of the DaaS document was decrypted
+    Decrypt,
+}
+
+impl fmt::Display for AuditAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            AuditAction::Ingest => "ingest",
+            AuditAction::Retrieve => "retrieve",
+            AuditAction::Delete => "delete",
+            AuditAction::Broker => "broker",
+            AuditAction::Decrypt => "decrypt",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Represents if the action succeeded
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditOutcome {
+    /// The action succeeded
+    Success,
+    /// The action failed, (e.g.: the DaaS document wasn't found)
+    Failure,
+}
+
+impl<T, E> From<&Result<T, E>> for AuditOutcome {
+    fn from(result: &Result<T, E>) -> Self {
+        match result {
+            Ok(_) => AuditOutcome::Success,
+            Err(_) => AuditOutcome::Failure,
+        }
+    }
+}
+
+/// Represents an action on a DaaS document, (who, when, what and the outcome)
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AuditEvent {
+    /// When the action happened, (Unix seconds)
+    pub timestamp: u64,
+    /// Who (the user or service) performed the action
+    pub actor: String,
+    /// What was done
+    pub action: AuditAction,
+    /// The identifier of the DaaS document
+    pub doc_id: String,
+    /// If the action succeeded
+    pub outcome: AuditOutcome,
+    /// The details of the action, (e.g.: the topic the DaaS document was brokered to)
+    pub detail: Option<String>,
+}
+
+impl AuditEvent {
+    /// Constructs an AuditEvent that happened now
+    ///
+    /// # Arguments
+    ///
+    /// * actor: String - Who (the user or service) performed the action.</br>
+    /// * action: AuditAction - What was done.</br>
+    /// * doc_id: String - The identifier of the DaaS document.</br>
+    /// * outcome: AuditOutcome - If the action succeeded.</br>
+    pub fn new(
+        actor: String,
+        action: AuditAction,
+        doc_id: String,
+        outcome: AuditOutcome,
+    ) -> AuditEvent {
+        AuditEvent {
+            timestamp: get_unix_now!(),
+            actor,
+            action,
+            doc_id,
+            outcome,
+            detail: None,
+        }
+    }
+
+    /// Sets the details of the action
+    pub fn with_detail(mut self, detail: String) -> AuditEvent {
+        self.detail = Some(detail);
+        self
+    }
+}
+
+/// Trait for the append-only sinks of the audit events
+pub trait AuditSink {
+    /// Appends the audit event to the sink
+    ///
+    /// # Arguments
+    ///
+    /// * event: &AuditEvent - The audit event to record.</br>
+    fn append(&self, event: &AuditEvent) -> Result<(), AuditError>;
+
+    /// Returns the audit events in the order they were appended. Not supported by default, (e.g.: a Kafka topic is
+    /// queried by its consumers instead).
+    fn events(&self) -> Result<Vec<AuditEvent>, AuditError> {
+        warn!("The audit sink doesn't support reading the audit events.");
+        Err(AuditError)
+    }
+}
+
+/// An audit sink that keeps the audit events in memory, (e.g.: for testing)
+#[derive(Clone, Default)]
+pub struct InMemoryAuditSink {
+    events: Arc<Mutex<Vec<AuditEvent>>>,
+}
+
+impl InMemoryAuditSink {
+    /// Constructs an empty InMemoryAuditSink. Clones share the same audit events.
+    pub fn new() -> InMemoryAuditSink {
+        InMemoryAuditSink::default()
+    }
+}
+
+impl AuditSink for InMemoryAuditSink {
+    fn append(&self, event: &AuditEvent) -> Result<(), AuditError> {
+        self.events.lock().unwrap().push(event.clone());
+        Ok(())
+    }
+
+    fn events(&self) -> Result<Vec<AuditEvent>, AuditError> {
+        Ok(self.events.lock().unwrap().clone())
+    }
+}
+
+/// An audit sink that appends the audit events to a file as newline delimited JSON
+pub struct FileAuditSink {
+    /// The path of the audit file
+    pub path: String,
+    lock: Mutex<()>,
+}
+
+impl FileAuditSink {
+    /// Constructor
+    ///
+    /// # Arguments
+    ///
+    /// * path: String - The path of the audit file, which is created if it doesn't exist.</br>
+    pub fn new(path: String) -> FileAuditSink {
+        FileAuditSink {
+            path,
+            lock: Mutex::new(()),
+        }
+    }
+}
+
+impl AuditSink for FileAuditSink {
+    fn append(&self, event: &AuditEvent) -> Result<(), AuditError> {
+        let _lock = self.lock.lock().unwrap();
+
+        if let Some(dir) = Path::new(&self.path).parent() {
+            if fs::create_dir_all(dir).is_err() {
+                error!(
+                    "Could not create the directory of the audit file {}.",
+                    self.path
+                );
+                return Err(AuditError);
+            }
+        }
+
+        let line = format!("{}\n", serde_json::to_string(event).unwrap());
+        match OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut f| f.write_all(line.as_bytes()))
+        {
+            Ok(_) => Ok(()),
+            Err(err) => {
+                error!(
+                    "Could not write the audit file {}. Error: {}",
+                    self.path, err
+                );
+                Err(AuditError)
+            }
+        }
+    }
+
+    fn events(&self) -> Result<Vec<AuditEvent>, AuditError> {
+        let file = match fs::File::open(&self.path) {
+            Ok(f) => f,
+            Err(_err) if !Path::new(&self.path).exists() => return Ok(Vec::new()),
+            Err(err) => {
+                error!(
+                    "Could not read the audit file {}. Error: {}",
+                    self.path, err
+                );
+                return Err(AuditError);
+            }
+        };
+
+        Ok(BufReader::new(file)
+            .lines()
+            .map_while(|l| l.ok())
+            .filter(|l| !l.trim().is_empty())
+            .filter_map(|l| match serde_json::from_str(&l) {
+                Ok(e) => Some(e),
+                Err(_err) => {
+                    warn!(
+                        "Skipped an unreadable line of the audit file {}.",
+                        self.path
+                    );
+                    None
+                }
+            })
+            .collect())
+    }
+}
+
+/// An audit sink that produces the audit events (as JSON keyed by the identifier of the DaaS document) to a Kafka topic
+#[cfg(feature = "kafka")]
+pub struct KafkaAuditSink {
+    /// The Kafka brokers, (e.g.: localhost:9092)
+    pub brokers: Vec<String>,
+    /// The topic of the audit events
+    pub topic: String,
+    producer: Mutex<Option<Producer>>,
+}
+
+#[cfg(feature = "kafka")]
+impl KafkaAuditSink {
+    /// Constructor
+    ///
+    /// # Arguments
+    ///
+    /// * brokers: Vec<String> - The Kafka brokers, (e.g.: localhost:9092).</br>
+    /// * topic: String - The topic of the audit events.</br>
+    pub fn new(brokers: Vec<String>, topic: String) -> KafkaAuditSink {
+        KafkaAuditSink {
+            brokers,
+            topic,
+            producer: Mutex::new(None),
+        }
+    }
+}
+
+#[cfg(feature = "kafka")]
+impl AuditSink for KafkaAuditSink {
+    fn append(&self, event: &AuditEvent) -> Result<(), AuditError> {
+        let value = serde_json::to_string(event).unwrap();
+        let mut producer = self.producer.lock().unwrap();
+
+        if producer.is_none() {
+            match Producer::from_hosts(self.brokers.clone())
+                .with_ack_timeout(Duration::from_secs(1))
+                .with_required_acks(RequiredAcks::One)
+                .create()
+            {
+                Ok(p) => *producer = Some(p),
+                Err(err) => {
+                    error!(
+                        "Could not connect to the Kafka brokers for auditing. Error: {}",
+                        err
+                    );
+                    return Err(AuditError);
+                }
+            }
+        }
+
+        let record = Record::from_key_value(&self.topic, event.doc_id.as_str(), value.as_bytes());
+        match producer.as_mut().unwrap().send(&record) {
+            Ok(_) => Ok(()),
+            Err(err) => {
+                // reconnect on the next audit event
+                *producer = None;
+                error!(
+                    "Could not send the audit event to {}. Error: {}",
+                    self.topic, err
+                );
+                Err(AuditError)
+            }
+        }
+    }
+}
+
+/// An audit sink that saves each audit event as a DaaS document of the `audit` category in a storage device,
+/// (e.g.: an S3 bucket with object locking)
+pub struct StorageAuditSink<S: DaaSDocStorage> {
+    /// The storage device of the audit events
+    pub storage: S,
+}
+
+impl<S: DaaSDocStorage> StorageAuditSink<S> {
+    /// Constructor
+    ///
+    /// # Arguments
+    ///
+    /// * storage: S - The storage device of the audit events.</br>
+    pub fn new(storage: S) -> StorageAuditSink<S> {
+        StorageAuditSink { storage }
+    }
+}
+
+impl<S: DaaSDocStorage> AuditSink for StorageAuditSink<S> {
+    fn append(&self, event: &AuditEvent) -> Result<(), AuditError> {
+        // each audit event is a new DaaS document, so the audit events are never overwritten
+        let uid = format!("{}-{:08x}", event.timestamp, rand::random::<u32>());
+        let id = DaaSDoc::make_id(
+            AUDIT_CATEGORY.to_string(),
+            event.action.to_string(),
+            "daas".to_string(),
+            uid.clone(),
+        );
+        let doc = DaaSDoc::new(
+            "daas".to_string(),
+            SourceId::Text(uid),
+            AUDIT_CATEGORY.to_string(),
+            event.action.to_string(),
+            event.actor.clone(),
+            Vec::new(),
+            Tracker::new(id),
+            serde_json::to_vec(event).unwrap(),
+        );
+
+        match self.storage.upsert_daas_doc(doc) {
+            Ok(_d) => Ok(()),
+            Err(err) => {
+                error!("Could not save the audit event. {}", err);
+                Err(AuditError)
+            }
+        }
+    }
+
+    fn events(&self) -> Result<Vec<AuditEvent>, AuditError> {
+        let prefix = format!("{}{}", AUDIT_CATEGORY, DELIMITER);
+        let ids = self.storage.list_doc_ids().map_err(|_e| AuditError)?;
+        let mut events: Vec<AuditEvent> = ids
+            .into_iter()
+            .filter(|id| id.starts_with(&prefix))
+            .filter_map(|id| self.storage.get_doc_by_id(id, None).ok())
+            .filter_map(|doc| serde_json::from_slice(&doc.data_obj).ok())
+            .collect();
+
+        events.sort_by_key(|e| e.timestamp);
+        Ok(events)
+    }
+}
+
+/// Represents the filter of the audit events, where the criteria that aren't set match all the audit events
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AuditQuery {
+    /// Who performed the action
+    pub actor: Option<String>,
+    /// What was done
+    pub action: Option<AuditAction>,
+    /// The identifier of the DaaS document
+    pub doc_id: Option<String>,
+    /// If the action succeeded
+    pub outcome: Option<AuditOutcome>,
+    /// The earliest time of the action, (Unix seconds)
+    pub from: Option<u64>,
+    /// The latest time of the action, (Unix seconds)
+    pub to: Option<u64>,
+}
+
+impl AuditQuery {
+    /// Constructs an AuditQuery that matches all the audit events
+    pub fn new() -> AuditQuery {
+        AuditQuery::default()
+    }
+
+    /// Only matches the actions of the actor
+    pub fn with_actor(mut self, actor: String) -> AuditQuery {
+        self.actor = Some(actor);
+        self
+    }
+
+    /// Only matches the action
+    pub fn with_action(mut self, action: AuditAction) -> AuditQuery {
+        self.action = Some(action);
+        self
+    }
+
+    /// Only matches the actions on the DaaS document
+    pub fn with_doc_id(mut self, doc_id: String) -> AuditQuery {
+        self.doc_id = Some(doc_id);
+        self
+    }
+
+    /// Only matches the actions with the outcome
+    pub fn with_outcome(mut self, outcome: AuditOutcome) -> AuditQuery {
+        self.outcome = Some(outcome);
+        self
+    }
+
+    /// Only matches the actions from (and including) the time to (and including) the time
+    ///
+    /// # Arguments
+    ///
+    /// * from: u64 - The earliest time of the action, (Unix seconds).</br>
+    /// * to: u64 - The latest time of the action, (Unix seconds).</br>
+    pub fn between(mut self, from: u64, to: u64) -> AuditQuery {
+        self.from = Some(from);
+        self.to = Some(to);
+        self
+    }
+
+    /// Determines if the audit event matches the criteria
+    pub fn matches(&self, event: &AuditEvent) -> bool {
+        self.actor.iter().all(|a| *a == event.actor)
+            && self.action.iter().all(|a| *a == event.action)
+            && self.doc_id.iter().all(|d| *d == event.doc_id)
+            && self.outcome.iter().all(|o| *o == event.outcome)
+            && self.from.iter().all(|f| *f <= event.timestamp)
+            && self.to.iter().all(|t| *t >= event.timestamp)
+    }
+}
+
+/// Represents the audit log of the DaaS documents. Clones share the same sink.
+#[derive(Clone)]
+pub struct AuditLog {
+    sink: Arc<dyn AuditSink + Send + Sync>,
+}
+
+impl AuditLog {
+    /// Constructor
+    ///
+    /// # Arguments
+    ///
+    /// * sink: T - The append-only sink of the audit events.</br>
+    pub fn new<T: AuditSink + Send + Sync + 'static>(sink: T) -> AuditLog {
+        AuditLog {
+            sink: Arc::new(sink),
+        }
+    }
+
+    /// Records the audit event. A failure to record is logged and returned, so the caller decides if the action
+    /// should fail.
+    ///
+    /// # Arguments
+    ///
+    /// * event: AuditEvent - The audit event to record.</br>
+    pub fn record(&self, event: AuditEvent) -> Result<(), AuditError> {
+        let rslt = self.sink.append(&event);
+        if rslt.is_err() {
+            error!(
+                "Could not record the {} of DaaS document {} by {}.",
+                event.action, event.doc_id, event.actor
+            );
+        }
+        rslt
+    }
+
+    /// Returns the audit events that match the query, in the order they were recorded
+    ///
+    /// # Arguments
+    ///
+    /// * query: &AuditQuery - The criteria of the audit events.</br>
+    pub fn query(&self, query: &AuditQuery) -> Result<Vec<AuditEvent>, AuditError> {
+        Ok(self
+            .sink
+            .events()?
+            .into_iter()
+            .filter(|e| query.matches(e))
+            .collect())
+    }
+
+    /// Returns all the audit events of the DaaS document, (i.e.: its access history)
+    pub fn history(&self, doc_id: String) -> Result<Vec<AuditEvent>, AuditError> {
+        self.query(&AuditQuery::new().with_doc_id(doc_id))
+    }
+
+    /// Returns the audit events of the failed actions since the time, (e.g.: to alert on denied access)
+    pub fn failures_since(&self, from: u64) -> Result<Vec<AuditEvent>, AuditError> {
+        self.query(
+            &AuditQuery::new()
+                .with_outcome(AuditOutcome::Failure)
+                .between(from, u64::MAX),
+        )
+    }
+
+    /// Decrypts the data of the DaaS document and records the decryption
+    ///
+    /// # Arguments
+    ///
+    /// * guard: &DaaSSecurityGuard - The security guard that encrypted the data.</br>
+    /// * actor: &str - Who is decrypting the data.</br>
+    /// * doc: &DaaSDoc - The DaaS document with the encrypted data.</br>
+    #[cfg(any(feature = "security", feature = "security-rustcrypto"))]
+    pub fn decrypt(
+        &self,
+        guard: &DaaSSecurityGuard,
+        actor: &str,
+        doc: &DaaSDoc,
+    ) -> Result<Vec<u8>, DecryptionError> {
+        let rslt = guard.decrypt_data(&doc.data_obj);
+        let _ = self.record(AuditEvent::new(
+            actor.to_string(),
+            AuditAction::Decrypt,
+            doc._id.clone(),
+            AuditOutcome::from(&rslt),
+        ));
+        rslt
+    }
+}
+
+/// Represents a storage device that records the ingestions, retrievals and deletions of the DaaS documents
+pub struct AuditedStorage<S: DaaSDocStorage> {
+    /// The storage device that manages the DaaS documents
+    pub storage: S,
+    /// Who is using the storage device, (e.g.: the name of the service)
+    pub actor: String,
+    log: AuditLog,
+}
+
+impl<S: DaaSDocStorage> AuditedStorage<S> {
+    /// Constructor
+    ///
+    /// # Arguments
+    ///
+    /// * storage: S - The storage device that manages the DaaS documents.</br>
+    /// * log: AuditLog - The audit log.</br>
+    /// * actor: String - Who is using the storage device, (e.g.: the name of the service).</br>
+    pub fn new(storage: S, log: AuditLog, actor: String) -> AuditedStorage<S> {
+        AuditedStorage {
+            storage,
+            actor,
+            log,
+        }
+    }
+
+    fn audit<T, E>(&self, action: AuditAction, doc_id: String, rslt: Result<T, E>) -> Result<T, E> {
+        let _ = self.log.record(AuditEvent::new(
+            self.actor.clone(),
+            action,
+            doc_id,
+            AuditOutcome::from(&rslt),
+        ));
+        rslt
+    }
+}
+
+impl<S: DaaSDocStorage> DaaSDocStorage for AuditedStorage<S> {
+    fn upsert_daas_doc(&self, daas_doc: DaaSDoc) -> Result<DaaSDoc, UpsertError> {
+        let doc_id = daas_doc._id.clone();
+        let rslt = self.storage.upsert_daas_doc(daas_doc);
+        self.audit(AuditAction::Ingest, doc_id, rslt)
+    }
+
+    fn get_doc_by_id(
+        &self,
+        doc_id: String,
+        doc_rev: Option<String>,
+    ) -> Result<DaaSDoc, RetrieveError> {
+        let rslt = self.storage.get_doc_by_id(doc_id.clone(), doc_rev);
+        self.audit(AuditAction::Retrieve, doc_id, rslt)
+    }
+
+    fn list_doc_ids(&self) -> Result<Vec<String>, RetrieveError> {
+        self.storage.list_doc_ids()
+    }
+
+    fn list_doc_ids_page(
+        &self,
+        cursor: Option<String>,
+        page_size: usize,
+    ) -> Result<Page<String>, RetrieveError> {
+        self.storage.list_doc_ids_page(cursor, page_size)
+    }
+
+    fn soft_delete_daas_doc(&self, doc_id: String) -> Result<DaaSDoc, UpsertError> {
+        let rslt = self.storage.soft_delete_daas_doc(doc_id.clone());
+        self.audit(AuditAction::Delete, doc_id, rslt)
+    }
+
+    fn restore_daas_doc(&self, doc_id: String) -> Result<DaaSDoc, UpsertError> {
+        self.storage.restore_daas_doc(doc_id)
+    }
+
+    fn set_legal_hold(&self, doc_id: String, hold: bool) -> Result<DaaSDoc, UpsertError> {
+        self.storage.set_legal_hold(doc_id, hold)
+    }
+}
+
+/// Represents a broker that records the brokering of the DaaS documents
+#[derive(Clone)]
+#[cfg(feature = "kafka")]
+pub struct AuditedBroker<B: DaaSKafkaProcessor> {
+    /// The broker that sends the DaaS documents
+    pub broker: B,
+    /// Who is brokering the DaaS documents, (e.g.: the name of the service)
+    pub actor: String,
+    log: AuditLog,
+}
+
+#[cfg(feature = "kafka")]
+impl<B: DaaSKafkaProcessor> AuditedBroker<B> {
+    /// Constructor
+    ///
+    /// # Arguments
+    ///
+    /// * broker: B - The broker that sends the DaaS documents.</br>
+    /// * log: AuditLog - The audit log.</br>
+    /// * actor: String - Who is brokering the DaaS documents, (e.g.: the name of the service).</br>
+    pub fn new(broker: B, log: AuditLog, actor: String) -> AuditedBroker<B> {
+        AuditedBroker { broker, actor, log }
+    }
+}
+
+#[cfg(feature = "kafka")]
+impl<B: DaaSKafkaProcessor> DaaSKafkaProcessor for AuditedBroker<B> {
+    /// Not audited, since there is no audit log to record to
+    fn broker_message_with_client(
+        client: KafkaClient,
+        doc: &mut DaaSDoc,
+        topic: &str,
+    ) -> Result<(), kafka::error::ErrorKind> {
+        B::broker_message_with_client(client, doc, topic)
+    }
+
+    fn broker_message(
+        &self,
+        doc: &mut DaaSDoc,
+        topic: &str,
+    ) -> Result<(), kafka::error::ErrorKind> {
+        let rslt = self.broker.broker_message(doc, topic);
+        let _ = self.log.record(
+            AuditEvent::new(
+                self.actor.clone(),
+                AuditAction::Broker,
+                doc._id.clone(),
+                AuditOutcome::from(&rslt),
+            )
+            .with_detail(topic.to_string()),
+        );
+        rslt
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eventing::memory::InMemoryBroker;
+    #[cfg(any(feature = "security", feature = "security-rustcrypto"))]
+    use crate::security::StaticKeyProvider;
+    use crate::storage::memory::InMemoryStorage;
+    use crate::testing;
+
+    fn get_event(actor: &str, action: AuditAction, timestamp: u64) -> AuditEvent {
+        let mut event = AuditEvent::new(
+            actor.to_string(),
+            action,
+            "order~clothing~iStore~5000".to_string(),
+            AuditOutcome::Success,
+        );
+        event.timestamp = timestamp;
+        event
+    }
+
+    #[test]
+    fn test_query() {
+        let log = AuditLog::new(InMemoryAuditSink::new());
+        log.record(get_event("alice", AuditAction::Ingest, 100))
+            .unwrap();
+        log.record(get_event("bob", AuditAction::Retrieve, 200))
+            .unwrap();
+        let mut failed = get_event("bob", AuditAction::Decrypt, 300);
+        failed.outcome = AuditOutcome::Failure;
+        log.record(failed).unwrap();
+
+        assert_eq!(log.query(&AuditQuery::new()).unwrap().len(), 3);
+        assert_eq!(
+            log.query(&AuditQuery::new().with_actor("bob".to_string()))
+                .unwrap()
+                .len(),
+            2
+        );
+        assert_eq!(
+            log.query(&AuditQuery::new().between(150, 300))
+                .unwrap()
+                .len(),
+            2
+        );
+        assert_eq!(
+            log.failures_since(0).unwrap()[0].action,
+            AuditAction::Decrypt
+        );
+        assert!(log.history("other".to_string()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_file_sink() {
+        let path = format!("./tmp/audit-{}/daas.log", rand::random::<u32>());
+        let sink = FileAuditSink::new(path.clone());
+        assert!(sink.events().unwrap().is_empty());
+
+        sink.append(&get_event("alice", AuditAction::Ingest, 100))
+            .unwrap();
+        sink.append(&get_event("bob", AuditAction::Delete, 200))
+            .unwrap();
+
+        // the audit file is only appended to
+        let sink = FileAuditSink::new(path.clone());
+        sink.append(&get_event("carol", AuditAction::Retrieve, 300))
+            .unwrap();
+        let events = sink.events().unwrap();
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[1], get_event("bob", AuditAction::Delete, 200));
+        assert_eq!(fs::read_to_string(path).unwrap().lines().count(), 3);
+    }
+
+    #[test]
+    fn test_storage_sink() {
+        let sink = StorageAuditSink::new(InMemoryStorage::new());
+        sink.append(&get_event("alice", AuditAction::Ingest, 100))
+            .unwrap();
+        sink.append(&get_event("alice", AuditAction::Ingest, 100))
+            .unwrap();
+        sink.storage
+            .upsert_daas_doc(testing::get_default_daas_doc())
+            .unwrap();
+
+        assert_eq!(sink.events().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_kafka_sink_unreachable() {
+        let sink = KafkaAuditSink::new(vec!["localhost:1".to_string()], "audit".to_string());
+
+        assert!(sink
+            .append(&get_event("alice", AuditAction::Ingest, 100))
+            .is_err());
+        assert!(sink.events().is_err());
+    }
+
+    #[test]
+    fn test_audited_storage() {
+        let log = AuditLog::new(InMemoryAuditSink::new());
+        let storage = AuditedStorage::new(InMemoryStorage::new(), log.clone(), "svc".to_string());
+        let doc = storage
+            .upsert_daas_doc(testing::get_default_daas_doc())
+            .unwrap();
+        storage.get_doc_by_id(doc._id.clone(), None).unwrap();
+        storage.soft_delete_daas_doc(doc._id.clone()).unwrap();
+        assert!(storage.get_doc_by_id("missing".to_string(), None).is_err());
+
+        let actions: Vec<AuditAction> = log
+            .history(doc._id)
+            .unwrap()
+            .iter()
+            .map(|e| e.action)
+            .collect();
+        assert_eq!(
+            actions,
+            vec![
+                AuditAction::Ingest,
+                AuditAction::Retrieve,
+                AuditAction::Delete
+            ]
+        );
+        assert_eq!(log.failures_since(0).unwrap()[0].doc_id, "missing");
+    }
+
+    #[test]
+    fn test_audited_broker() {
+        let log = AuditLog::new(InMemoryAuditSink::new());
+        let broker = AuditedBroker::new(InMemoryBroker::new(), log.clone(), "svc".to_string());
+        let mut doc = testing::get_default_daas_doc();
+        broker.broker_message(&mut doc, "genesis").unwrap();
+
+        let events = log
+            .query(&AuditQuery::new().with_action(AuditAction::Broker))
+            .unwrap();
+        assert_eq!(events[0].detail, Some("genesis".to_string()));
+        assert_eq!(broker.broker.messages("genesis").len(), 1);
+    }
+
+    #[cfg(any(feature = "security", feature = "security-rustcrypto"))]
+    #[test]
+    fn test_decrypt() {
+        let log = AuditLog::new(InMemoryAuditSink::new());
+        let guard = DaaSSecurityGuard::new(Box::new(StaticKeyProvider::new(
+            DaaSSecurityGuard::generate_symmetric_key(),
+        )));
+        let mut doc = testing::get_default_daas_doc();
+        let data = doc.data_obj.clone();
+        doc.data_obj = guard.encrypt_data(&data).unwrap();
+
+        assert_eq!(log.decrypt(&guard, "bob", &doc).unwrap(), data);
+        doc.data_obj = data;
+        assert!(log.decrypt(&guard, "bob", &doc).is_err());
+        assert_eq!(
+            log.query(&AuditQuery::new().with_action(AuditAction::Decrypt))
+                .unwrap()
+                .len(),
+            2
+        );
+    }
+}
@@ -0,0 +1,350 @@
+//! The chaos module injects faults into the storage devices and brokers so that the resilience paths of a
+//! deployment, (e.g.: the dead letter queue, the outbox and the retries) can be tested (requires the `chaos` feature).
+//!
+//! A `FaultInjector` decides which operations fail based on the configured rates. When it is given a seed, the same
+//! operations fail on every run, so the resilience tests are deterministic. The `ChaosStorage` fails upserts and the
+//! `ChaosBroker` fails, delays or silently drops the brokered messages.
+//!
+//! # Examples
+//!
+//! ```
+//! extern crate daas;
+//!
+//! use daas::chaos::{ChaosStorage, FaultConfig, FaultInjector};
+//! use daas::storage::DaaSDocStorage;
+//! use daas::storage::memory::InMemoryStorage;
+//! use daas::testing;
+//!
+//! fn main() {
+//!     let faults = FaultInjector::new(FaultConfig::new().with_upsert_failure_rate(1.0)).with_seed(42);
+//!     let storage = ChaosStorage::new(InMemoryStorage::new(), faults.clone());
+//!
+//!     assert!(storage.upsert_daas_doc(testing::get_default_daas_doc()).is_err());
+//!     assert_eq!(faults.counts().failed_upserts, 1);
+//! }
+//! ```
+
+use super::*;
+use crate::doc::*;
+use crate::errors::*;
+use crate::eventing::broker::DaaSKafkaProcessor;
+use crate::storage::DaaSDocStorage;
+use kafka::client::KafkaClient;
+use kafka::error::ErrorKind;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// Represents the rates (0.0 to 1.0) at which the faults are injected
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FaultConfig {
+    /// The rate of the storage upserts that fail
+    pub upsert_failure_rate: f64,
+    /// The rate of the broker publishes that fail
+    pub publish_failure_rate: f64,
+    /// The rate of the broker publishes that are delayed
+    pub publish_delay_rate: f64,
+    /// How long the delayed publishes wait
+    pub publish_delay: Duration,
+    /// The rate of the brokered messages that are silently dropped
+    pub drop_rate: f64,
+}
+
+impl FaultConfig {
+    /// Constructs a FaultConfig that doesn't inject any faults
+    pub fn new() -> FaultConfig {
+        FaultConfig::default()
+    }
+
+    /// Sets the rate of the storage upserts that fail
+    ///
+    /// # Arguments
+    ///
+    /// * rate: f64 - The rate, (e.g.: 0.1 for 10% of the upserts).</br>
+    pub fn with_upsert_failure_rate(mut self, rate: f64) -> FaultConfig {
+        self.upsert_failure_rate = rate;
+        self
+    }
+
+    /// Sets the rate of the broker publishes that fail
+    ///
+    /// # Arguments
+    ///
+    /// * rate: f64 - The rate, (e.g.: 0.1 for 10% of the publishes).</br>
+    pub fn with_publish_failure_rate(mut self, rate: f64) -> FaultConfig {
+        self.publish_failure_rate = rate;
+        self
+    }
+
+    /// Sets the rate of the broker publishes that are delayed and how long they wait
+    ///
+    /// # Arguments
+    ///
+    /// * rate: f64 - The rate, (e.g.: 0.1 for 10% of the publishes).</br>
+    /// * delay: Duration - How long the delayed publishes wait.</br>
+    pub fn with_publish_delay(mut self, rate: f64, delay: Duration) -> FaultConfig {
+        self.publish_delay_rate = rate;
+        self.publish_delay = delay;
+        self
+    }
+
+    /// Sets the rate of the brokered messages that are silently dropped, (the publish appears to succeed)
+    ///
+    /// # Arguments
+    ///
+    /// * rate: f64 - The rate, (e.g.: 0.1 for 10% of the messages).</br>
+    pub fn with_drop_rate(mut self, rate: f64) -> FaultConfig {
+        self.drop_rate = rate;
+        self
+    }
+}
+
+/// Represents the number of faults that have been injected
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FaultCounts {
+    /// The number of storage upserts that failed
+    pub failed_upserts: usize,
+    /// The number of broker publishes that failed
+    pub failed_publishes: usize,
+    /// The number of broker publishes that were delayed
+    pub delayed_publishes: usize,
+    /// The number of brokered messages that were dropped
+    pub dropped_messages: usize,
+}
+
+/// Decides which operations fail. Clones of the `FaultInjector` share the same random numbers and counts.
+#[derive(Clone)]
+pub struct FaultInjector {
+    /// The rates at which the faults are injected
+    pub config: FaultConfig,
+    rng: Arc<Mutex<StdRng>>,
+    counts: Arc<Mutex<FaultCounts>>,
+}
+
+impl FaultInjector {
+    /// Constructs a FaultInjector that injects the faults at random
+    ///
+    /// # Arguments
+    ///
+    /// * config: FaultConfig - The rates at which the faults are injected.</br>
+    pub fn new(config: FaultConfig) -> FaultInjector {
+        FaultInjector {
+            config,
+            rng: Arc::new(Mutex::new(StdRng::from_entropy())),
+            counts: Arc::new(Mutex::new(FaultCounts::default())),
+        }
+    }
+
+    /// Seeds the random numbers so the same operations fail on every run
+    ///
+    /// # Arguments
+    ///
+    /// * seed: u64 - The seed of the random numbers.</br>
+    pub fn with_seed(mut self, seed: u64) -> FaultInjector {
+        self.rng = Arc::new(Mutex::new(StdRng::seed_from_u64(seed)));
+        self
+    }
+
+    /// Returns the number of faults that have been injected
+    pub fn counts(&self) -> FaultCounts {
+        *self.counts.lock().unwrap()
+    }
+
+    // Determines if the fault is injected, (a rate of 0.0 never draws a random number)
+    fn inject(&self, rate: f64, count: fn(&mut FaultCounts)) -> bool {
+        if rate <= 0.0 {
+            return false;
+        }
+
+        let injected = self.rng.lock().unwrap().gen::<f64>() < rate;
+        if injected {
+            count(&mut self.counts.lock().unwrap());
+        }
+        injected
+    }
+}
+
+/// Represents a storage device that fails a rate of the upserts
+pub struct ChaosStorage<S: DaaSDocStorage> {
+    /// The storage device that manages the DaaS documents
+    pub storage: S,
+    /// The injector of the faults
+    pub faults: FaultInjector,
+}
+
+impl<S: DaaSDocStorage> ChaosStorage<S> {
+    /// Constructor
+    ///
+    /// # Arguments
+    ///
+    /// * storage: S - The storage device that manages the DaaS documents.</br>
+    /// * faults: FaultInjector - The injector of the faults.</br>
+    pub fn new(storage: S, faults: FaultInjector) -> ChaosStorage<S> {
+        ChaosStorage { storage, faults }
+    }
+}
+
+impl<S: DaaSDocStorage> DaaSDocStorage for ChaosStorage<S> {
+    fn upsert_daas_doc(&self, daas_doc: DaaSDoc) -> Result<DaaSDoc, UpsertError> {
+        if self
+            .faults
+            .inject(self.faults.config.upsert_failure_rate, |c| {
+                c.failed_upserts += 1
+            })
+        {
+            warn!("Injected a failure of the upsert of {}.", daas_doc._id);
+            return Err(UpsertError);
+        }
+
+        self.storage.upsert_daas_doc(daas_doc)
+    }
+
+    fn get_doc_by_id(
+        &self,
+        doc_id: String,
+        doc_rev: Option<String>,
+    ) -> Result<DaaSDoc, RetrieveError> {
+        self.storage.get_doc_by_id(doc_id, doc_rev)
+    }
+
+    fn list_doc_ids(&self) -> Result<Vec<String>, RetrieveError> {
+        self.storage.list_doc_ids()
+    }
+}
+
+/// Represents a broker that fails, delays or drops a rate of the brokered messages
+#[derive(Clone)]
+pub struct ChaosBroker<B: DaaSKafkaProcessor> {
+    /// The broker that sends the DaaS documents
+    pub broker: B,
+    /// The injector of the faults
+    pub faults: FaultInjector,
+}
+
+impl<B: DaaSKafkaProcessor> ChaosBroker<B> {
+    /// Constructor
+    ///
+    /// # Arguments
+    ///
+    /// * broker: B - The broker that sends the DaaS documents.</br>
+    /// * faults: FaultInjector - The injector of the faults.</br>
+    pub fn new(broker: B, faults: FaultInjector) -> ChaosBroker<B> {
+        ChaosBroker { broker, faults }
+    }
+}
+
+impl<B: DaaSKafkaProcessor> DaaSKafkaProcessor for ChaosBroker<B> {
+    /// No faults are injected, since there is no fault injector
+    fn broker_message_with_client(
+        client: KafkaClient,
+        doc: &mut DaaSDoc,
+        topic: &str,
+    ) -> Result<(), kafka::error::ErrorKind> {
+        B::broker_message_with_client(client, doc, topic)
+    }
+
+    fn broker_message(
+        &self,
+        doc: &mut DaaSDoc,
+        topic: &str,
+    ) -> Result<(), kafka::error::ErrorKind> {
+        let config = &self.faults.config;
+
+        if self
+            .faults
+            .inject(config.publish_delay_rate, |c| c.delayed_publishes += 1)
+        {
+            debug!("Injected a delay of the publish of {}.", doc._id);
+            thread::sleep(config.publish_delay);
+        }
+        if self
+            .faults
+            .inject(config.publish_failure_rate, |c| c.failed_publishes += 1)
+        {
+            warn!("Injected a failure of the publish of {}.", doc._id);
+            return Err(ErrorKind::NoHostReachable);
+        }
+        if self
+            .faults
+            .inject(config.drop_rate, |c| c.dropped_messages += 1)
+        {
+            warn!("Injected a drop of the message of {}.", doc._id);
+            return Ok(());
+        }
+
+        self.broker.broker_message(doc, topic)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eventing::memory::InMemoryBroker;
+    use crate::storage::memory::InMemoryStorage;
+    use crate::testing;
+
+    // Returns which of the upserts of the seeded storage failed
+    fn failed_upserts(seed: u64) -> Vec<bool> {
+        let faults =
+            FaultInjector::new(FaultConfig::new().with_upsert_failure_rate(0.5)).with_seed(seed);
+        let storage = ChaosStorage::new(InMemoryStorage::new(), faults);
+
+        (0..20)
+            .map(|_| {
+                storage
+                    .upsert_daas_doc(testing::get_default_daas_doc())
+                    .is_err()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_seeded_faults_are_deterministic() {
+        let failed = failed_upserts(7);
+
+        assert_eq!(failed, failed_upserts(7));
+        assert!(failed.iter().any(|f| *f));
+        assert!(failed.iter().any(|f| !*f));
+    }
+
+    #[test]
+    fn test_no_faults() {
+        let faults = FaultInjector::new(FaultConfig::new());
+        let storage = ChaosStorage::new(InMemoryStorage::new(), faults.clone());
+        let broker = ChaosBroker::new(InMemoryBroker::new(), faults.clone());
+        let mut doc = storage
+            .upsert_daas_doc(testing::get_default_daas_doc())
+            .unwrap();
+
+        assert!(broker.broker_message(&mut doc, "genesis").is_ok());
+        assert_eq!(broker.broker.messages("genesis").len(), 1);
+        assert_eq!(faults.counts(), FaultCounts::default());
+    }
+
+    #[test]
+    fn test_broker_faults() {
+        let memory = InMemoryBroker::new();
+        let failing = ChaosBroker::new(
+            memory.clone(),
+            FaultInjector::new(
+                FaultConfig::new()
+                    .with_publish_failure_rate(1.0)
+                    .with_publish_delay(1.0, Duration::from_millis(1)),
+            ),
+        );
+        let dropping = ChaosBroker::new(
+            memory.clone(),
+            FaultInjector::new(FaultConfig::new().with_drop_rate(1.0)),
+        );
+        let mut doc = testing::get_default_daas_doc();
+
+        assert!(failing.broker_message(&mut doc, "genesis").is_err());
+        assert!(dropping.broker_message(&mut doc, "genesis").is_ok());
+        assert!(memory.messages("genesis").is_empty());
+        assert_eq!(failing.faults.counts().failed_publishes, 1);
+        assert_eq!(failing.faults.counts().delayed_publishes, 1);
+        assert_eq!(dropping.faults.counts().dropped_messages, 1);
+    }
+}
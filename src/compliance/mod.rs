@@ -0,0 +1,7 @@
+//! The compliance module contains the utilities for fulfilling the requests of the data subjects, (i.e.: the persons
+//! the data is about) under privacy regulations such as the GDPR and CCPA.
+
+use super::*;
+use crate::errors::*;
+
+pub mod sar;
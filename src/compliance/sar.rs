@@ -0,0 +1,427 @@
+//! Data subject access requests (SAR), where a person asks for a copy of all the data that is held about them.
+//!
+//! The `SubjectAccessRequest` searches the storage devices for the DaaS documents of the data subject, (identified by
+//! the source name and source uid, or by a metadata entry such as `customer_id`) and gathers all their revisions and
+//! lineage, (i.e.: the Data Tracker Chain) into a portable `SarBundle`. The bundle is JSON, where the data of each
+//! revision is included as JSON when possible, as text when it is UTF-8 and otherwise base64 encoded.
+//!
+//! # Examples
+//!
+//! ```
+//! extern crate daas;
+//!
+//! use daas::compliance::sar::{SubjectAccessRequest, SubjectSelector};
+//! use daas::storage::DaaSDocStorage;
+//! use daas::storage::memory::InMemoryStorage;
+//! use daas::testing;
+//!
+//! fn main() {
+//!     let storage = InMemoryStorage::new();
+//!     storage.upsert_daas_doc(testing::get_default_daas_doc()).unwrap();
+//!
+//!     let sar = SubjectAccessRequest::new(SubjectSelector::by_source("iStore".to_string(), 5000));
+//!     let bundle = sar.gather(&[("primary", &storage)]).unwrap();
+//!
+//!     assert_eq!(bundle.documents.len(), 1);
+//!     assert!(bundle.to_json().contains("order~clothing~iStore~5000"));
+//! }
+//! ```
+
+use super::*;
+use crate::doc::{DaaSDoc, SourceId};
+use crate::storage::DaaSDocStorage;
+use pbd::dua::DUA;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::io::Write;
+
+/// The version of the format of the SarBundle
+pub const SAR_FORMAT_VERSION: u32 = 1;
+
+/// Represents how the DaaS documents of the data subject are identified
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SubjectSelector {
+    /// The DaaS documents that a data source provided about the data subject
+    Source {
+        source_name: String,
+        source_uid: SourceId,
+    },
+    /// The DaaS documents with the metadata entry, (e.g.: customer_id=12345)
+    Meta { key: String, value: String },
+}
+
+impl SubjectSelector {
+    /// Selects the DaaS documents by the source name and source uid
+    ///
+    /// # Arguments
+    ///
+    /// * source_name: String - The name of the data source.</br>
+    /// * source_uid: usize, String or &str - The unique identifier that the data source provided.</br>
+    pub fn by_source<U: Into<SourceId>>(source_name: String, source_uid: U) -> SubjectSelector {
+        SubjectSelector::Source {
+            source_name,
+            source_uid: source_uid.into(),
+        }
+    }
+
+    /// Selects the DaaS documents by a metadata entry that identifies the data subject
+    ///
+    /// # Arguments
+    ///
+    /// * key: String - The metadata key, (e.g.: customer_id).</br>
+    /// * value: String - The metadata value that identifies the data subject.</br>
+    pub fn by_meta(key: String, value: String) -> SubjectSelector {
+        SubjectSelector::Meta { key, value }
+    }
+
+    /// Determines if the DaaS document is about the data subject
+    pub fn matches(&self, doc: &DaaSDoc) -> bool {
+        match self {
+            SubjectSelector::Source {
+                source_name,
+                source_uid,
+            } => doc.source_name == *source_name && doc.source_uid == *source_uid,
+            SubjectSelector::Meta { key, value } => doc.meta_data.get(key) == Some(value),
+        }
+    }
+}
+
+/// Represents a step in the lineage of the DaaS document, (i.e.: a Marker of the Data Tracker Chain)
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LineageEntry {
+    /// The position in the Data Tracker Chain
+    pub index: usize,
+    /// When the data was handled, (Unix seconds)
+    pub timestamp: u64,
+    /// Who handled the data
+    pub actor_id: String,
+    /// The identifier of the data
+    pub data_id: String,
+}
+
+/// Represents a revision of a DaaS document in the SarBundle
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SarRevision {
+    /// The revision number
+    pub rev: Option<String>,
+    /// When the revision was saved, (Unix seconds)
+    pub last_updated: u64,
+    /// Who provided the data
+    pub author: String,
+    /// The agreements the data may be used under
+    pub data_usage_agreements: Vec<DUA>,
+    /// The metadata
+    pub meta_data: BTreeMap<String, String>,
+    /// The tags
+    pub tags: Vec<String>,
+    /// If the revision is (soft) deleted
+    pub deleted: bool,
+    /// The data, (JSON, text or base64 encoded)
+    pub data: Value,
+}
+
+impl SarRevision {
+    fn from_doc(doc: &DaaSDoc) -> SarRevision {
+        let data = match serde_json::from_slice::<Value>(&doc.data_obj) {
+            Ok(v) => v,
+            Err(_e) => match String::from_utf8(doc.data_obj.clone()) {
+                Ok(text) => Value::String(text),
+                Err(_e) => json!({ "base64": base64::encode(&doc.data_obj) }),
+            },
+        };
+
+        SarRevision {
+            rev: doc._rev.clone(),
+            last_updated: doc.last_updated,
+            author: doc.author.clone(),
+            data_usage_agreements: doc.data_usage_agreements.clone(),
+            meta_data: doc.meta_data.clone(),
+            tags: doc.tags.clone(),
+            deleted: doc.deleted,
+            data,
+        }
+    }
+}
+
+/// Represents a DaaS document of the data subject in the SarBundle
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SarDocument {
+    /// The name of the storage device the DaaS document was found in
+    pub storage: String,
+    /// The unique identifier
+    pub doc_id: String,
+    /// The name of the category
+    pub category: String,
+    /// The name of the subcategory
+    pub subcategory: String,
+    /// The name of the data source
+    pub source_name: String,
+    /// The revisions, from the first to the latest
+    pub revisions: Vec<SarRevision>,
+    /// The lineage of the latest revision
+    pub lineage: Vec<LineageEntry>,
+}
+
+/// Represents the export of all the data that is held about the data subject
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SarBundle {
+    /// The version of the format of the bundle
+    pub format_version: u32,
+    /// When the bundle was generated, (Unix seconds)
+    pub generated_at: u64,
+    /// How the data subject was identified
+    pub subject: SubjectSelector,
+    /// The DaaS documents of the data subject
+    pub documents: Vec<SarDocument>,
+    /// The storage devices that couldn't be searched, (e.g.: they don't support listing the DaaS documents)
+    pub incomplete: Vec<String>,
+}
+
+impl SarBundle {
+    /// Returns the bundle as (pretty printed) JSON
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    /// Writes the bundle as JSON, (e.g.: to a file that is sent to the data subject)
+    ///
+    /// # Arguments
+    ///
+    /// * writer: W - Where the bundle is written.</br>
+    pub fn write_to<W: Write>(&self, mut writer: W) -> Result<(), RetrieveError> {
+        match writer.write_all(self.to_json().as_bytes()) {
+            Ok(_) => Ok(()),
+            Err(err) => {
+                error!("Could not write the subject access bundle. Error: {}", err);
+                Err(RetrieveError)
+            }
+        }
+    }
+}
+
+/// Represents a data subject access request
+#[derive(Debug, Clone)]
+pub struct SubjectAccessRequest {
+    /// How the data subject is identified
+    pub subject: SubjectSelector,
+    /// If the (soft) deleted DaaS documents are included, (default: true)
+    pub include_deleted: bool,
+}
+
+impl SubjectAccessRequest {
+    /// Constructor
+    ///
+    /// # Arguments
+    ///
+    /// * subject: SubjectSelector - How the data subject is identified.</br>
+    pub fn new(subject: SubjectSelector) -> SubjectAccessRequest {
+        SubjectAccessRequest {
+            subject,
+            include_deleted: true,
+        }
+    }
+
+    /// Sets if the (soft) deleted DaaS documents are included
+    pub fn with_deleted(mut self, include_deleted: bool) -> SubjectAccessRequest {
+        self.include_deleted = include_deleted;
+        self
+    }
+
+    /// Searches the storage devices for the DaaS documents of the data subject and gathers their revisions and lineage.
+    /// A storage device that can't be searched is listed as incomplete instead of failing the request.
+    ///
+    /// # Arguments
+    ///
+    /// * storages: &[(&str, &dyn DaaSDocStorage)] - The storage devices to search, with the names to report them by.</br>
+    pub fn gather(
+        &self,
+        storages: &[(&str, &dyn DaaSDocStorage)],
+    ) -> Result<SarBundle, RetrieveError> {
+        let mut bundle = SarBundle {
+            format_version: SAR_FORMAT_VERSION,
+            generated_at: get_unix_now!(),
+            subject: self.subject.clone(),
+            documents: Vec::new(),
+            incomplete: Vec::new(),
+        };
+
+        for (name, storage) in storages.iter() {
+            let doc_ids = match storage.list_doc_ids() {
+                Ok(ids) => ids,
+                Err(_err) => {
+                    warn!(
+                        "Could not search the storage device {} for the data subject.",
+                        name
+                    );
+                    bundle.incomplete.push(name.to_string());
+                    continue;
+                }
+            };
+
+            for doc_id in doc_ids {
+                let latest = match storage.get_doc_by_id(doc_id.clone(), None) {
+                    Ok(d) => d,
+                    Err(_err) => continue,
+                };
+                if !self.subject.matches(&latest) || (latest.deleted && !self.include_deleted) {
+                    continue;
+                }
+
+                bundle
+                    .documents
+                    .push(SubjectAccessRequest::gather_doc(name, *storage, latest));
+            }
+        }
+
+        info!(
+            "Gathered {} DaaS documents for the subject access request.",
+            bundle.documents.len()
+        );
+        Ok(bundle)
+    }
+
+    fn gather_doc(name: &str, storage: &dyn DaaSDocStorage, latest: DaaSDoc) -> SarDocument {
+        // the revisions that can't be found, (e.g.: purged) are skipped
+        let mut revisions: Vec<SarRevision> =
+            match latest._rev.as_ref().and_then(|r| r.parse::<usize>().ok()) {
+                Some(n) => (1..n)
+                    .filter_map(|r| {
+                        storage
+                            .get_doc_by_id(latest._id.clone(), Some(r.to_string()))
+                            .ok()
+                    })
+                    .map(|d| SarRevision::from_doc(&d))
+                    .collect(),
+                None => Vec::new(),
+            };
+        revisions.push(SarRevision::from_doc(&latest));
+
+        let lineage = (0..latest.data_tracker.len())
+            .filter_map(|i| latest.data_tracker.get(i))
+            .map(|m| LineageEntry {
+                index: m.identifier.index,
+                timestamp: m.identifier.timestamp,
+                actor_id: m.identifier.actor_id,
+                data_id: m.identifier.data_id,
+            })
+            .collect();
+
+        SarDocument {
+            storage: name.to_string(),
+            doc_id: latest._id.clone(),
+            category: latest.category.clone(),
+            subcategory: latest.subcategory.clone(),
+            source_name: latest.source_name.clone(),
+            revisions,
+            lineage,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::local::LocalStorage;
+    use crate::storage::memory::InMemoryStorage;
+    use crate::testing;
+
+    fn get_doc(src: &str, uid: usize, cat: &str) -> DaaSDoc {
+        testing::get_daas_doc(
+            src.to_string(),
+            uid,
+            cat.to_string(),
+            "clothing".to_string(),
+        )
+    }
+
+    #[test]
+    fn test_selector_matches() {
+        let mut doc = testing::get_default_daas_doc();
+        doc.add_meta("customer_id".to_string(), "12345".to_string());
+
+        assert!(SubjectSelector::by_source("iStore".to_string(), 5000).matches(&doc));
+        assert!(!SubjectSelector::by_source("iStore".to_string(), 5001).matches(&doc));
+        assert!(
+            SubjectSelector::by_meta("customer_id".to_string(), "12345".to_string()).matches(&doc)
+        );
+        assert!(
+            !SubjectSelector::by_meta("customer_id".to_string(), "1".to_string()).matches(&doc)
+        );
+    }
+
+    #[test]
+    fn test_gather_across_storages() {
+        let primary = InMemoryStorage::new();
+        let mut doc = primary
+            .upsert_daas_doc(get_doc("iStore", 5000, "order"))
+            .unwrap();
+        doc.add_tag("priority".to_string());
+        primary.upsert_daas_doc(doc).unwrap();
+        primary
+            .upsert_daas_doc(get_doc("iStore", 6000, "order"))
+            .unwrap();
+
+        let local = LocalStorage::new(format!("./tmp/sar-{}", rand::random::<u32>()));
+        local
+            .upsert_daas_doc(get_doc("iStore", 5000, "return"))
+            .unwrap();
+
+        let sar = SubjectAccessRequest::new(SubjectSelector::by_source("iStore".to_string(), 5000));
+        let bundle = sar
+            .gather(&[("primary", &primary), ("local", &local)])
+            .unwrap();
+
+        assert_eq!(bundle.documents.len(), 2);
+        assert!(bundle.incomplete.is_empty());
+        let order = &bundle.documents[0];
+        assert_eq!(order.storage, "primary".to_string());
+        assert_eq!(order.revisions.len(), 2);
+        assert!(order.revisions[1].tags.contains(&"priority".to_string()));
+        assert_eq!(order.revisions[0].data, json!({"status": "new"}));
+        assert_eq!(order.lineage[0].data_id, order.doc_id);
+        assert_eq!(bundle.documents[1].category, "return".to_string());
+    }
+
+    #[test]
+    fn test_gather_excludes_deleted() {
+        let storage = InMemoryStorage::new();
+        let doc = storage
+            .upsert_daas_doc(get_doc("iStore", 5000, "order"))
+            .unwrap();
+        storage.soft_delete_daas_doc(doc._id).unwrap();
+        let selector = SubjectSelector::by_source("iStore".to_string(), 5000);
+
+        let bundle = SubjectAccessRequest::new(selector.clone())
+            .gather(&[("primary", &storage)])
+            .unwrap();
+        assert!(bundle.documents[0].revisions[1].deleted);
+        let bundle = SubjectAccessRequest::new(selector)
+            .with_deleted(false)
+            .gather(&[("primary", &storage)])
+            .unwrap();
+        assert!(bundle.documents.is_empty());
+    }
+
+    #[test]
+    fn test_bundle_is_portable() {
+        let storage = InMemoryStorage::new();
+        let mut doc = get_doc("iStore", 5000, "order");
+        doc.data_obj = vec![0, 159, 146, 150];
+        storage.upsert_daas_doc(doc).unwrap();
+
+        let bundle =
+            SubjectAccessRequest::new(SubjectSelector::by_source("iStore".to_string(), 5000))
+                .gather(&[("primary", &storage)])
+                .unwrap();
+        let mut exported = Vec::new();
+        bundle.write_to(&mut exported).unwrap();
+
+        let imported: SarBundle = serde_json::from_slice(&exported).unwrap();
+        assert_eq!(imported.to_json(), bundle.to_json());
+        assert_eq!(
+            imported.documents[0].revisions[0].data,
+            json!({"base64": "AJ+Slg=="})
+        );
+    }
+}
@@ -0,0 +1,101 @@
+//! The config module contains the configuration of the DaaS services that shouldn't be hard-coded or read from bare
+//! environment variables, (e.g.: the Kafka credentials, S3 keys and signing secrets).
+//!
+//! The `DaasConfig` holds the settings that are shared by all the DaaS services of an environment, such as the prefix
+//! of the topics, (e.g.: `dev.` or `prod.`) so multiple environments can share one Kafka cluster without cross-talk.
+//!
+//! # Examples
+//!
+//! ```
+//! extern crate daas;
+//!
+//! use daas::config::DaasConfig;
+//!
+//! fn main() {
+//!     let config = DaasConfig::new().with_topic_prefix("dev".to_string());
+//!
+//!     assert_eq!(config.topic("genesis"), "dev.genesis".to_string());
+//! }
+//! ```
+
+use super::*;
+use crate::errors::*;
+
+pub mod secrets;
+
+/// The environment variable of the prefix of the topics, (e.g.: dev)
+pub const TOPIC_PREFIX_VAR: &str = "DAAS_TOPIC_PREFIX";
+
+/// Represents the settings that are shared by the DaaS services of an environment
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DaasConfig {
+    /// The prefix of the topics and consumer groups, (e.g.: dev.)
+    pub topic_prefix: Option<String>,
+}
+
+impl DaasConfig {
+    /// Constructs a DaasConfig without a topic prefix
+    pub fn new() -> DaasConfig {
+        DaasConfig::default()
+    }
+
+    /// Constructs the DaasConfig from the environment variables, (i.e.: DAAS_TOPIC_PREFIX)
+    pub fn from_env() -> DaasConfig {
+        match env::var(TOPIC_PREFIX_VAR) {
+            Ok(prefix) => DaasConfig::new().with_topic_prefix(prefix),
+            Err(_err) => DaasConfig::new(),
+        }
+    }
+
+    /// Sets the prefix of the topics, which is separated from the topic name with a `.`
+    ///
+    /// # Arguments
+    ///
+    /// * prefix: String - The prefix of the topics, (e.g.: dev).</br>
+    pub fn with_topic_prefix(mut self, prefix: String) -> DaasConfig {
+        let prefix = prefix.trim().trim_end_matches('.').to_string();
+        self.topic_prefix = match prefix.is_empty() {
+            true => None,
+            false => Some(format!("{}.", prefix)),
+        };
+        self
+    }
+
+    /// Returns the name of the topic (or consumer group) in the environment, (e.g.: dev.genesis).
+    /// A name that already has the prefix isn't prefixed again.
+    ///
+    /// # Arguments
+    ///
+    /// * name: &str - The name of the topic, (e.g.: genesis).</br>
+    pub fn topic(&self, name: &str) -> String {
+        match &self.topic_prefix {
+            Some(prefix) if !name.starts_with(prefix.as_str()) => format!("{}{}", prefix, name),
+            _ => name.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_topic() {
+        let config = DaasConfig::new().with_topic_prefix("prod.".to_string());
+
+        assert_eq!(config.topic_prefix, Some("prod.".to_string()));
+        assert_eq!(config.topic("genesis"), "prod.genesis".to_string());
+        assert_eq!(config.topic("prod.genesis"), "prod.genesis".to_string());
+    }
+
+    #[test]
+    fn test_topic_without_prefix() {
+        assert_eq!(DaasConfig::new().topic("genesis"), "genesis".to_string());
+        assert_eq!(
+            DaasConfig::new()
+                .with_topic_prefix(" ".to_string())
+                .topic("genesis"),
+            "genesis".to_string()
+        );
+    }
+}
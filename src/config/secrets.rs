@@ -0,0 +1,653 @@
+//! The secrets used by the DaaS services, (e.g.: Kafka credentials, S3 keys, signing secrets and encryption keys).
+//!
+//! The secrets are read through a `SecretProvider`, so they can be sourced from wherever the deployment manages
+//! them: environment variables, files (e.g.: Kubernetes or Docker secrets), HashiCorp Vault or AWS Secrets Manager.
+//! The providers read the secret each time it is requested, so a rotated secret is picked up without redeploying.
+//! Wrap a provider in a `CachedSecretProvider` to limit how often the secret store is called.
+//!
+//! A `Secret` never shows its value when it is formatted, so it can't end up in the logs by accident.
+//!
+//! # Examples
+//!
+//! ```
+//! extern crate daas;
+//!
+//! use daas::config::secrets::{CachedSecretProvider, EnvSecretProvider, SecretProvider};
+//! use std::env;
+//! use std::time::Duration;
+//!
+//! fn main() {
+//!     env::set_var("DAAS_KAFKA_PASSWORD", "p@ssw0rd");
+//!     let provider = CachedSecretProvider::new(Box::new(EnvSecretProvider::new("DAAS_".to_string())), Duration::from_secs(60));
+//!     let secret = provider.get_secret("KAFKA_PASSWORD").unwrap();
+//!
+//!     assert_eq!(secret.expose_str().unwrap(), "p@ssw0rd");
+//!     assert_eq!(format!("{:?}", secret), "Secret(***)");
+//! }
+//! ```
+
+use super::*;
+#[cfg(any(feature = "security", feature = "security-rustcrypto"))]
+use crate::security::{DaaSSecurityGuard, KeyProvider};
+#[cfg(feature = "s3")]
+use rusoto_core::signature::SignedRequest;
+#[cfg(feature = "s3")]
+use rusoto_core::{Client, Region};
+#[cfg(feature = "s3")]
+use serde_json::json;
+#[cfg(any(feature = "http", feature = "s3"))]
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+#[cfg(feature = "s3")]
+use tokio::runtime::Runtime;
+
+/// Represents the value of a secret, which is never shown when it is formatted
+#[derive(Clone, PartialEq)]
+pub struct Secret(Vec<u8>);
+
+impl Secret {
+    /// Constructor
+    ///
+    /// # Arguments
+    ///
+    /// * value: Vec<u8> - The value of the secret.</br>
+    pub fn new(value: Vec<u8>) -> Secret {
+        Secret(value)
+    }
+
+    /// Returns the value of the secret
+    pub fn expose(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Returns the value of the secret as text
+    pub fn expose_str(&self) -> Result<&str, SecretError> {
+        std::str::from_utf8(&self.0).map_err(|_e| SecretError)
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Secret(***)")
+    }
+}
+
+impl fmt::Display for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "***")
+    }
+}
+
+/// Trait for the providers of the secrets
+pub trait SecretProvider {
+    /// Returns the current value of the secret
+    ///
+    /// # Arguments
+    ///
+    /// * name: &str - The name of the secret, (e.g.: KAFKA_PASSWORD).</br>
+    fn get_secret(&self, name: &str) -> Result<Secret, SecretError>;
+}
+
+/// A secret provider that reads the secrets from environment variables
+pub struct EnvSecretProvider {
+    prefix: String,
+}
+
+impl EnvSecretProvider {
+    /// Constructor
+    ///
+    /// # Arguments
+    ///
+    /// * prefix: String - The prefix of the names of the environment variables, (e.g.: DAAS_).</br>
+    pub fn new(prefix: String) -> EnvSecretProvider {
+        EnvSecretProvider { prefix }
+    }
+}
+
+impl SecretProvider for EnvSecretProvider {
+    fn get_secret(&self, name: &str) -> Result<Secret, SecretError> {
+        let var_name = format!("{}{}", self.prefix, name);
+
+        match env::var(&var_name) {
+            Ok(value) => Ok(Secret::new(value.into_bytes())),
+            Err(_e) => {
+                error!(
+                    "Could not read the secret from environment variable {}.",
+                    var_name
+                );
+                Err(SecretError)
+            }
+        }
+    }
+}
+
+/// A secret provider that reads each secret from a file in a directory, (e.g.: /run/secrets)
+pub struct FileSecretProvider {
+    dir: String,
+}
+
+impl FileSecretProvider {
+    /// Constructor
+    ///
+    /// # Arguments
+    ///
+    /// * dir: String - The directory with a file per secret, named after the secret.</br>
+    pub fn new(dir: String) -> FileSecretProvider {
+        FileSecretProvider { dir }
+    }
+}
+
+impl SecretProvider for FileSecretProvider {
+    fn get_secret(&self, name: &str) -> Result<Secret, SecretError> {
+        let path = format!("{}/{}", self.dir, name);
+
+        match fs::read(&path) {
+            Ok(mut value) => {
+                // editors and `echo` add a trailing line break
+                while value.last() == Some(&b'\n') || value.last() == Some(&b'\r') {
+                    value.pop();
+                }
+                Ok(Secret::new(value))
+            }
+            Err(e) => {
+                error!("Could not read the secret file {} because of {}.", path, e);
+                Err(SecretError)
+            }
+        }
+    }
+}
+
+/// A secret provider that reads the secrets from a HashiCorp Vault KV (version 2) secrets engine (requires the `http`
+/// feature)
+#[cfg(feature = "http")]
+pub struct VaultSecretProvider {
+    /// The address of Vault, (e.g.: https://vault.example.com:8200)
+    pub address: String,
+    /// The mount path of the secrets engine, (default: secret)
+    pub mount: String,
+    /// The path of the secret in the secrets engine, whose keys are the names of the secrets, (e.g.: daas/listener)
+    pub path: String,
+    /// How long to wait for Vault, (default: 5 seconds)
+    pub timeout: Duration,
+    token: Secret,
+}
+
+#[cfg(feature = "http")]
+impl VaultSecretProvider {
+    /// Constructor
+    ///
+    /// # Arguments
+    ///
+    /// * address: String - The address of Vault, (e.g.: https://vault.example.com:8200).</br>
+    /// * token: Secret - The Vault token.</br>
+    /// * path: String - The path of the secret in the secrets engine, (e.g.: daas/listener).</br>
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// extern crate daas;
+    ///
+    /// use daas::config::secrets::{Secret, VaultSecretProvider};
+    ///
+    /// fn main() {
+    ///     let token = Secret::new(b"s.1234567890".to_vec());
+    ///     let provider = VaultSecretProvider::new("http://127.0.0.1:8200".to_string(), token, "daas/listener".to_string())
+    ///         .with_mount("kv".to_string());
+    ///
+    ///     assert_eq!(provider.mount, "kv".to_string());
+    /// }
+    /// ```
+    pub fn new(address: String, token: Secret, path: String) -> VaultSecretProvider {
+        VaultSecretProvider {
+            address: address.trim_end_matches('/').to_string(),
+            mount: "secret".to_string(),
+            path,
+            timeout: Duration::from_secs(5),
+            token,
+        }
+    }
+
+    /// Sets the mount path of the secrets engine
+    pub fn with_mount(mut self, mount: String) -> VaultSecretProvider {
+        self.mount = mount;
+        self
+    }
+
+    /// Sets how long to wait for Vault
+    pub fn with_timeout(mut self, timeout: Duration) -> VaultSecretProvider {
+        self.timeout = timeout;
+        self
+    }
+}
+
+#[cfg(feature = "http")]
+impl SecretProvider for VaultSecretProvider {
+    fn get_secret(&self, name: &str) -> Result<Secret, SecretError> {
+        let url = format!("{}/v1/{}/data/{}", self.address, self.mount, self.path);
+        let token = self.token.expose_str()?;
+        let client = match reqwest::blocking::Client::builder()
+            .timeout(self.timeout)
+            .build()
+        {
+            Ok(c) => c,
+            Err(e) => {
+                error!("Could not build the Vault client. Error: {}", e);
+                return Err(SecretError);
+            }
+        };
+
+        let body: Value = match client
+            .get(url.clone())
+            .header("X-Vault-Token", token)
+            .send()
+            .and_then(|r| r.error_for_status())
+            .and_then(|r| r.bytes())
+        {
+            Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+            Err(e) => {
+                error!(
+                    "Could not read the secret {} from {}. Error: {}",
+                    name, url, e
+                );
+                return Err(SecretError);
+            }
+        };
+
+        match body.pointer("/data/data").and_then(|d| d.get(name)) {
+            Some(Value::String(value)) => Ok(Secret::new(value.as_bytes().to_vec())),
+            _ => {
+                error!("Secret {} not found in {}.", name, url);
+                Err(SecretError)
+            }
+        }
+    }
+}
+
+/// A secret provider that reads the secrets from AWS Secrets Manager, using the default AWS credentials chain (requires the `s3` feature)
+#[cfg(feature = "s3")]
+pub struct AwsSecretsManagerProvider {
+    /// The region of AWS Secrets Manager
+    pub region: Region,
+}
+
+#[cfg(feature = "s3")]
+impl AwsSecretsManagerProvider {
+    /// Constructor
+    ///
+    /// # Arguments
+    ///
+    /// * region: Region - The region of AWS Secrets Manager, (e.g.: Region::UsEast1).</br>
+    pub fn new(region: Region) -> AwsSecretsManagerProvider {
+        AwsSecretsManagerProvider { region }
+    }
+
+    // Calls the GetSecretValue action
+    async fn get_secret_value(&self, name: &str) -> Result<Value, String> {
+        let mut request = SignedRequest::new("POST", "secretsmanager", &self.region, "/");
+        request.set_content_type("application/x-amz-json-1.1".to_string());
+        request.add_header("x-amz-target", "secretsmanager.GetSecretValue");
+        request.set_payload(Some(json!({ "SecretId": name }).to_string().into_bytes()));
+
+        let mut response = Client::shared()
+            .sign_and_dispatch(request)
+            .await
+            .map_err(|e| format!("{:?}", e))?;
+        let response = response.buffer().await.map_err(|e| e.to_string())?;
+
+        match response.status.is_success() {
+            true => serde_json::from_slice(&response.body).map_err(|e| e.to_string()),
+            false => Err(format!("status {}", response.status)),
+        }
+    }
+}
+
+#[cfg(feature = "s3")]
+impl SecretProvider for AwsSecretsManagerProvider {
+    /// Reads the SecretString, (or the SecretBinary) of the secret, where the name is the secret's name or ARN
+    fn get_secret(&self, name: &str) -> Result<Secret, SecretError> {
+        let rt = Runtime::new().unwrap();
+
+        match rt.block_on(self.get_secret_value(name)) {
+            Ok(value) => match (value.get("SecretString"), value.get("SecretBinary")) {
+                (Some(Value::String(s)), _) => Ok(Secret::new(s.as_bytes().to_vec())),
+                (_, Some(Value::String(b))) => match base64::decode(b) {
+                    Ok(bytes) => Ok(Secret::new(bytes)),
+                    Err(_e) => Err(SecretError),
+                },
+                _ => {
+                    error!("Secret {} has no value in AWS Secrets Manager.", name);
+                    Err(SecretError)
+                }
+            },
+            Err(e) => {
+                error!(
+                    "Could not read the secret {} from AWS Secrets Manager. Error: {}",
+                    name, e
+                );
+                Err(SecretError)
+            }
+        }
+    }
+}
+
+/// A secret provider that caches the secrets of another provider for a while, so a rotated secret is picked up
+/// after the time to live
+pub struct CachedSecretProvider {
+    /// How long a secret is cached
+    pub ttl: Duration,
+    provider: Box<dyn SecretProvider + Send + Sync>,
+    cache: Mutex<HashMap<String, (Secret, Instant)>>,
+}
+
+impl CachedSecretProvider {
+    /// Constructor
+    ///
+    /// # Arguments
+    ///
+    /// * provider: Box<dyn SecretProvider + Send + Sync> - The provider of the secrets.</br>
+    /// * ttl: Duration - How long a secret is cached.</br>
+    pub fn new(
+        provider: Box<dyn SecretProvider + Send + Sync>,
+        ttl: Duration,
+    ) -> CachedSecretProvider {
+        CachedSecretProvider {
+            ttl,
+            provider,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Removes the cached secrets, (e.g.: right after the secrets were rotated)
+    pub fn clear(&self) {
+        self.cache.lock().unwrap().clear();
+    }
+}
+
+impl SecretProvider for CachedSecretProvider {
+    fn get_secret(&self, name: &str) -> Result<Secret, SecretError> {
+        if let Some((secret, at)) = self.cache.lock().unwrap().get(name) {
+            if at.elapsed() < self.ttl {
+                return Ok(secret.clone());
+            }
+        }
+
+        let secret = self.provider.get_secret(name)?;
+        self.cache
+            .lock()
+            .unwrap()
+            .insert(name.to_string(), (secret.clone(), Instant::now()));
+        Ok(secret)
+    }
+}
+
+/// A key provider that reads the base64 encoded symmetric key of the `DaaSSecurityGuard` from a secret provider (requires the `security` or `security-rustcrypto` feature)
+#[cfg(any(feature = "security", feature = "security-rustcrypto"))]
+pub struct SecretKeyProvider {
+    provider: Box<dyn SecretProvider + Send + Sync>,
+    name: String,
+}
+
+#[cfg(any(feature = "security", feature = "security-rustcrypto"))]
+impl SecretKeyProvider {
+    /// Constructor
+    ///
+    /// # Arguments
+    ///
+    /// * provider: Box<dyn SecretProvider + Send + Sync> - The provider of the secret.</br>
+    /// * name: String - The name of the secret that holds the base64 encoded key.</br>
+    pub fn new(provider: Box<dyn SecretProvider + Send + Sync>, name: String) -> SecretKeyProvider {
+        SecretKeyProvider { provider, name }
+    }
+}
+
+#[cfg(any(feature = "security", feature = "security-rustcrypto"))]
+impl KeyProvider for SecretKeyProvider {
+    fn get_symmetric_key(&self) -> Result<Vec<u8>, BadKeyPairError> {
+        let secret = self
+            .provider
+            .get_secret(&self.name)
+            .map_err(|_e| BadKeyPairError)?;
+
+        match secret.expose_str().map(base64::decode) {
+            Ok(Ok(key)) => Ok(key),
+            _ => {
+                error!("Secret {} is not a base64 encoded key.", self.name);
+                Err(BadKeyPairError)
+            }
+        }
+    }
+}
+
+/// A key provider that unwraps the symmetric key of the `DaaSSecurityGuard` with a passphrase encrypted private key, (RSA with OAEP padding or X25519).
+/// The passphrase is read from a secret provider, so neither the private key nor the symmetric key is kept as plain text at rest.
+#[cfg(any(feature = "security", feature = "security-rustcrypto"))]
+pub struct WrappedKeyProvider {
+    provider: Box<dyn SecretProvider + Send + Sync>,
+    passphrase_name: String,
+    private_key_path: String,
+    wrapped_key: Vec<u8>,
+}
+
+#[cfg(any(feature = "security", feature = "security-rustcrypto"))]
+impl WrappedKeyProvider {
+    /// Constructor
+    ///
+    /// # Arguments
+    ///
+    /// * provider: Box<dyn SecretProvider + Send + Sync> - The provider of the passphrase.</br>
+    /// * passphrase_name: String - The name of the secret that holds the passphrase of the private key.</br>
+    /// * private_key_path: String - The path of the encrypted PEM private key, (see `DaaSSecurityGuard::write_keypair()`).</br>
+    /// * wrapped_key: Vec<u8> - The symmetric key that was wrapped with the public key.</br>
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// extern crate daas;
+    ///
+    /// use daas::config::secrets::{EnvSecretProvider, WrappedKeyProvider};
+    /// use daas::security::{DaaSSecurityGuard, KeyProvider};
+    /// use std::env;
+    /// use std::fs;
+    ///
+    /// fn main() {
+    ///     let (private_key, public_key) = DaaSSecurityGuard::generate_x25519_keypair().unwrap();
+    ///     let key = DaaSSecurityGuard::generate_symmetric_key();
+    ///     let wrapped = DaaSSecurityGuard::wrap_symmetric_key_x25519(&public_key, &key).unwrap();
+    ///
+    ///     fs::create_dir_all("./tmp/doctest-keys").unwrap();
+    ///     DaaSSecurityGuard::write_keypair(&private_key, &public_key, "./tmp/doctest-keys/daas.pem", b"p@ssw0rd").unwrap();
+    ///     env::set_var("DAAS_DOCTEST_KEY_PASSPHRASE", "p@ssw0rd");
+    ///
+    ///     let provider = WrappedKeyProvider::new(
+    ///         Box::new(EnvSecretProvider::new("DAAS_".to_string())),
+    ///         "DOCTEST_KEY_PASSPHRASE".to_string(),
+    ///         "./tmp/doctest-keys/daas.pem".to_string(),
+    ///         wrapped,
+    ///     );
+    ///
+    ///     assert_eq!(provider.get_symmetric_key().unwrap(), key);
+    /// }
+    /// ```
+    pub fn new(
+        provider: Box<dyn SecretProvider + Send + Sync>,
+        passphrase_name: String,
+        private_key_path: String,
+        wrapped_key: Vec<u8>,
+    ) -> WrappedKeyProvider {
+        WrappedKeyProvider {
+            provider,
+            passphrase_name,
+            private_key_path,
+            wrapped_key,
+        }
+    }
+}
+
+#[cfg(any(feature = "security", feature = "security-rustcrypto"))]
+impl KeyProvider for WrappedKeyProvider {
+    fn get_symmetric_key(&self) -> Result<Vec<u8>, BadKeyPairError> {
+        let passphrase = self
+            .provider
+            .get_secret(&self.passphrase_name)
+            .map_err(|_e| BadKeyPairError)?;
+        let encrypted = fs::read(&self.private_key_path).map_err(|err| {
+            error!(
+                "Could not read the private key {}. {}",
+                self.private_key_path, err
+            );
+            BadKeyPairError
+        })?;
+        let private_key = DaaSSecurityGuard::decrypt_private_key(&encrypted, passphrase.expose())?;
+
+        DaaSSecurityGuard::unwrap_symmetric_key_any(&private_key, &self.wrapped_key)
+            .map_err(|_e| BadKeyPairError)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::security::RsaPadding;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    fn get_file_provider(name: &str, value: &str) -> (FileSecretProvider, String) {
+        let dir = format!("./tmp/secrets-{}", rand::random::<u32>());
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(format!("{}/{}", dir, name), value).unwrap();
+        (FileSecretProvider::new(dir.clone()), dir)
+    }
+
+    #[test]
+    fn test_secret_redacted() {
+        let secret = Secret::new(b"p@ssw0rd".to_vec());
+
+        assert_eq!(format!("{:?}", secret), "Secret(***)".to_string());
+        assert_eq!(format!("{}", secret), "***".to_string());
+        assert_eq!(secret.expose(), b"p@ssw0rd");
+    }
+
+    #[test]
+    fn test_env_provider() {
+        env::set_var("DAAS_TEST_SECRET", "p@ssw0rd");
+        let provider = EnvSecretProvider::new("DAAS_TEST_".to_string());
+
+        assert_eq!(provider.get_secret("SECRET").unwrap().expose(), b"p@ssw0rd");
+        assert!(provider.get_secret("MISSING").is_err());
+    }
+
+    #[test]
+    fn test_file_provider_rotated() {
+        let (provider, dir) = get_file_provider("kafka_password", "p@ssw0rd\n");
+        assert_eq!(
+            provider.get_secret("kafka_password").unwrap().expose(),
+            b"p@ssw0rd"
+        );
+
+        fs::write(format!("{}/kafka_password", dir), "rotated").unwrap();
+        assert_eq!(
+            provider.get_secret("kafka_password").unwrap().expose(),
+            b"rotated"
+        );
+        assert!(provider.get_secret("missing").is_err());
+    }
+
+    #[test]
+    fn test_cached_provider() {
+        let (provider, dir) = get_file_provider("kafka_password", "p@ssw0rd");
+        let cached = CachedSecretProvider::new(Box::new(provider), Duration::from_secs(60));
+        cached.get_secret("kafka_password").unwrap();
+
+        fs::write(format!("{}/kafka_password", dir), "rotated").unwrap();
+        assert_eq!(
+            cached.get_secret("kafka_password").unwrap().expose(),
+            b"p@ssw0rd"
+        );
+        cached.clear();
+        assert_eq!(
+            cached.get_secret("kafka_password").unwrap().expose(),
+            b"rotated"
+        );
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn test_vault_provider() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = format!("http://{}", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0; 4096];
+            let len = stream.read(&mut buf).unwrap();
+            let request = String::from_utf8_lossy(&buf[..len]).to_string();
+            let body =
+                r#"{"data":{"data":{"kafka_password":"p@ssw0rd"},"metadata":{"version":3}}}"#;
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                body.len(),
+                body
+            )
+            .unwrap();
+            request
+        });
+
+        let provider = VaultSecretProvider::new(
+            address,
+            Secret::new(b"s.token".to_vec()),
+            "daas/listener".to_string(),
+        );
+        assert_eq!(
+            provider.get_secret("kafka_password").unwrap().expose(),
+            b"p@ssw0rd"
+        );
+
+        let request = server.join().unwrap().to_lowercase();
+        assert!(request.starts_with("get /v1/secret/data/daas/listener "));
+        assert!(request.contains("x-vault-token: s.token"));
+    }
+
+    #[test]
+    fn test_secret_key_provider() {
+        let key = DaaSSecurityGuard::generate_symmetric_key();
+        let (provider, _dir) = get_file_provider("daas_key", &base64::encode(&key));
+        let guard = DaaSSecurityGuard::new(Box::new(SecretKeyProvider::new(
+            Box::new(provider),
+            "daas_key".to_string(),
+        )));
+
+        let encrypted = guard.encrypt_data(b"hello world").unwrap();
+        assert_eq!(
+            guard.decrypt_data(&encrypted).unwrap(),
+            b"hello world".to_vec()
+        );
+    }
+    #[test]
+    fn test_wrapped_key_provider() {
+        let key = DaaSSecurityGuard::generate_symmetric_key();
+        let (private_key, public_key) = DaaSSecurityGuard::generate_rsa_keypair(2048).unwrap();
+        let wrapped =
+            DaaSSecurityGuard::wrap_symmetric_key(&public_key, &key, RsaPadding::Oaep).unwrap();
+        let (provider, dir) = get_file_provider("key_passphrase", "p@ssw0rd");
+        let path = format!("{}/daas.pem", dir);
+        DaaSSecurityGuard::write_keypair(&private_key, &public_key, &path, b"p@ssw0rd").unwrap();
+
+        let key_provider = WrappedKeyProvider::new(
+            Box::new(provider),
+            "key_passphrase".to_string(),
+            path.clone(),
+            wrapped.clone(),
+        );
+        assert_eq!(key_provider.get_symmetric_key().unwrap(), key);
+
+        let (wrong, _dir) = get_file_provider("key_passphrase", "wrong");
+        let key_provider =
+            WrappedKeyProvider::new(Box::new(wrong), "key_passphrase".to_string(), path, wrapped);
+        assert!(key_provider.get_symmetric_key().is_err());
+    }
+}
@@ -45,12 +45,190 @@ use crate::errors::*;
 use crate::*;
 use pbd::dtc::Tracker;
 use pbd::dua::DUA;
-use serde_json::Value;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
 use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+pub mod deidentify;
+pub mod migrate;
 
 // Repesentation of a map for storing metadata about the data object
 type Metadata = BTreeMap<String, String>;
 
+/// Represents the unique identifier that the data source provides, which is either numeric or textual (e.g.: a UUID).
+/// Numeric identifiers are serialized as numbers so that existing DaaS documents remain compatible.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+#[serde(untagged)]
+pub enum SourceId {
+    /// A numeric identifier, (e.g.: 5000)
+    Number(usize),
+    /// A textual identifier, (e.g.: 0b8d3c5e-8f4a-4c1a-9d4e-2f6a1b7c9e0d)
+    Text(String),
+}
+
+impl SourceId {
+    // Returns the identifier as it is used in the unique identifier of the DaaS document
+    fn to_id_component(&self) -> String {
+        match self {
+            SourceId::Number(n) => n.to_string(),
+            SourceId::Text(t) => DaaSDoc::escape_id_component(t),
+        }
+    }
+}
+
+impl fmt::Display for SourceId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SourceId::Number(n) => write!(f, "{}", n),
+            SourceId::Text(t) => write!(f, "{}", t),
+        }
+    }
+}
+
+impl FromStr for SourceId {
+    type Err = DaaSDocError;
+
+    /// Parses the identifier, (e.g.: from the listener route) preferring a numeric identifier
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.parse::<usize>() {
+            Ok(n) => Ok(SourceId::Number(n)),
+            Err(_) if !s.is_empty() => Ok(SourceId::Text(s.to_string())),
+            Err(_) => Err(DaaSDocError),
+        }
+    }
+}
+
+impl From<usize> for SourceId {
+    fn from(n: usize) -> Self {
+        SourceId::Number(n)
+    }
+}
+
+impl From<String> for SourceId {
+    fn from(t: String) -> Self {
+        SourceId::Text(t)
+    }
+}
+
+impl From<&str> for SourceId {
+    fn from(t: &str) -> Self {
+        SourceId::Text(t.to_string())
+    }
+}
+
+impl PartialEq<usize> for SourceId {
+    fn eq(&self, other: &usize) -> bool {
+        *self == SourceId::Number(*other)
+    }
+}
+
+/// Represents a JSON Pointer (RFC 6901) to a field of the data object, (e.g.: /customer/name)
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct JsonPointer(String);
+
+impl JsonPointer {
+    /// Returns the unescaped reference tokens of the pointer, (e.g.: ["customer", "name"])
+    pub fn tokens(&self) -> Vec<String> {
+        match self.0.is_empty() {
+            true => Vec::new(),
+            false => self.0[1..]
+                .split('/')
+                .map(|t| t.replace("~1", "/").replace("~0", "~"))
+                .collect(),
+        }
+    }
+}
+
+impl fmt::Display for JsonPointer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl FromStr for JsonPointer {
+    type Err = DaaSDocError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.is_empty() || s.starts_with('/') {
+            true => Ok(JsonPointer(s.to_string())),
+            false => Err(DaaSDocError),
+        }
+    }
+}
+
+/// Represents the retention periods of the data based upon its Data Usage Agreements
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct RetentionPolicy {
+    /// The retention period (seconds) of each Data Usage Agreement (agreement name, seconds)
+    pub periods: BTreeMap<String, u64>,
+    /// The retention period (seconds) for Data Usage Agreements that don't have a retention period
+    pub default_period: Option<u64>,
+}
+
+impl RetentionPolicy {
+    /// Constructs a RetentionPolicy without any retention periods, (the data never expires)
+    pub fn new() -> RetentionPolicy {
+        RetentionPolicy::default()
+    }
+
+    /// Sets the retention period of a Data Usage Agreement
+    ///
+    /// # Arguments
+    ///
+    /// * agreement_name: String - The name of the Data Usage Agreement, (e.g.: billing).</br>
+    /// * seconds: u64 - The number of seconds after the agreement was made that the data may be kept.</br>
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// extern crate pbd;
+    /// extern crate daas;
+    ///
+    /// use pbd::dua::DUA;
+    /// use daas::doc::RetentionPolicy;
+    ///
+    /// fn main() {
+    ///     let policy = RetentionPolicy::new().with_period("billing".to_string(), 86400);
+    ///     let duas = vec![DUA::new("billing".to_string(),"https://dua.org/agreements/v1/billing.pdf".to_string(),1553988607)];
+    ///
+    ///     assert_eq!(policy.expires_at(&duas), Some(1554075007));
+    /// }
+    /// ```
+    pub fn with_period(mut self, agreement_name: String, seconds: u64) -> RetentionPolicy {
+        self.periods.insert(agreement_name, seconds);
+        self
+    }
+
+    /// Sets the retention period for the Data Usage Agreements that don't have a retention period
+    ///
+    /// # Arguments
+    ///
+    /// * seconds: u64 - The number of seconds after the agreement was made that the data may be kept.</br>
+    pub fn with_default_period(mut self, seconds: u64) -> RetentionPolicy {
+        self.default_period = Some(seconds);
+        self
+    }
+
+    /// Returns when the data expires, which is the earliest expiration of its Data Usage Agreements
+    ///
+    /// # Arguments
+    ///
+    /// * duas: &[DUA] - The Data Usage Agreements of the data.</br>
+    pub fn expires_at(&self, duas: &[DUA]) -> Option<u64> {
+        duas.iter()
+            .filter_map(|dua| {
+                self.periods
+                    .get(&dua.agreement_name)
+                    .cloned()
+                    .or(self.default_period)
+                    .map(|p| dua.agreed_dtm.saturating_add(p))
+            })
+            .min()
+    }
+}
+
 /// Represents an existing DaaS document (after it has been saved and assigned a _rev value)
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct DaaSDoc {
@@ -58,10 +236,13 @@ pub struct DaaSDoc {
     pub _id: String,
     /// The revision number
     pub _rev: Option<String>,
+    /// The version of the schema that the document was written with, (see `migrate`)
+    #[serde(default = "migrate::legacy_schema_version")]
+    pub schema_version: u32,
     /// The name of the data source
     pub source_name: String,
     /// The unique identifier that the data source provides
-    pub source_uid: usize,
+    pub source_uid: SourceId,
     /// The name of the category (e.g.: order)
     pub category: String,
     /// The name of the subcategory (e.g.: clothing)
@@ -70,6 +251,22 @@ pub struct DaaSDoc {
     pub author: String,
     /// The indicator that represents if the document is waiting to be processed (processed = true, needs to be processed = false)
     pub process_ind: bool,
+    /// The status of the document in the stages of the listener and processors, (see `DocStatus`)
+    #[serde(default)]
+    pub status: DocStatus,
+    /// The indicator that represents if the document has been (soft) deleted, so that it is kept until it is purged
+    #[serde(default)]
+    pub deleted: bool,
+    /// The indicator that represents if the document is under a legal hold, so that it is never purged
+    #[serde(default)]
+    pub legal_hold: bool,
+    /// The Unix Epoch time when the data expires and must no longer be used, (see `RetentionPolicy`)
+    #[serde(default)]
+    pub expires_at: Option<u64>,
+    /// The monotonic sequence number of the DaaS document within its source (source_name, source_uid), which is
+    /// allocated at ingestion when a sequencer is registered, (see `eventing::sequence`)
+    #[serde(default)]
+    pub sequence: Option<u64>,
     /// The Unix Epoch time when the document was last updated, (e.g.: 1555972752)
     pub last_updated: u64,
     /// The list of Data Usage Agreements for the data represented in the DaaS Document
@@ -77,8 +274,10 @@ pub struct DaaSDoc {
     /// The Data Tracker Chain that represents the lineage of the DaaS Document
     pub data_tracker: Tracker,
     // The list of metadata about the data object (key, value)
+    #[serde(default)]
     pub meta_data: Metadata,
     // List of tags to provide context about the data object
+    #[serde(default)]
     pub tags: Vec<String>,
     /// The byte slice that represents the data from the data source managed by the DaaS document
     pub data_obj: Vec<u8>,
@@ -89,10 +288,12 @@ pub struct DaaSDoc {
 struct DaaSDocNoRev {
     /// The unique identifier
     pub _id: String,
+    /// The version of the schema that the document was written with
+    pub schema_version: u32,
     /// The name of the data source
     pub source_name: String,
     /// The unique identifier that the data source provides
-    pub source_uid: usize,
+    pub source_uid: SourceId,
     /// The name of the category (e.g.: order)
     pub category: String,
     /// The name of the subcategory (e.g.: clothing)
@@ -111,6 +312,283 @@ struct DaaSDocNoRev {
     pub data_obj: Vec<u8>,
 }
 
+/// Represents a change of a single envelope attribute between two DaaS documents
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FieldChange {
+    /// The value of the attribute in the original document
+    pub old: Value,
+    /// The value of the attribute in the compared document
+    pub new: Value,
+}
+
+/// Represents the structured differences between two DaaS documents (e.g.: two revisions of the same document)
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DocDiff {
+    /// The revision of the original document
+    pub from_rev: Option<String>,
+    /// The revision of the compared document
+    pub to_rev: Option<String>,
+    /// The envelope attributes that have changed (attribute name, change)
+    pub fields: BTreeMap<String, FieldChange>,
+    /// The metadata entries that only exist in the compared document
+    pub meta_added: Metadata,
+    /// The metadata entries that only exist in the original document
+    pub meta_removed: Metadata,
+    /// The metadata entries whose values have changed (key, change)
+    pub meta_changed: BTreeMap<String, FieldChange>,
+    /// The tags that only exist in the compared document
+    pub tags_added: Vec<String>,
+    /// The tags that only exist in the original document
+    pub tags_removed: Vec<String>,
+    /// The indicator that represents if the data object has changed
+    pub data_changed: bool,
+    /// A JSON Patch (RFC 6902) of the data object, when both data objects are JSON
+    pub data_patch: Option<Value>,
+}
+
+impl DocDiff {
+    /// Determines if there are no differences between the two DaaS documents
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+            && self.meta_added.is_empty()
+            && self.meta_removed.is_empty()
+            && self.meta_changed.is_empty()
+            && self.tags_added.is_empty()
+            && self.tags_removed.is_empty()
+            && !self.data_changed
+    }
+}
+
+/// The statuses of a DaaS document as it moves through the stages of the listener and processors, so operators can
+/// find where a DaaS document is stuck.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum DocStatus {
+    /// The data has been received, but not saved yet (default)
+    #[default]
+    Received,
+    /// The DaaS document has been saved and is waiting to be brokered
+    Stored,
+    /// The DaaS document has been sent to the broker
+    Brokered,
+    /// A processor has provisioned the DaaS document, (e.g.: to the object store)
+    Provisioned,
+    /// A stage couldn't process the DaaS document, so it needs to be retried
+    Failed,
+    /// The DaaS document has been moved to the cold storage device
+    Archived,
+    /// The DaaS document has been (soft) deleted
+    Deleted,
+}
+
+impl DocStatus {
+    /// Determines if the DaaS document can change from this status to the next status.
+    /// Changing to the same status is allowed, so the stages can be retried.
+    ///
+    /// # Arguments
+    ///
+    /// * next: DocStatus - The next status.</br>
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// extern crate daas;
+    ///
+    /// use daas::doc::DocStatus;
+    ///
+    /// fn main() {
+    ///     assert!(DocStatus::Stored.can_transition_to(DocStatus::Brokered));
+    ///     assert!(!DocStatus::Received.can_transition_to(DocStatus::Provisioned));
+    /// }
+    /// ```
+    pub fn can_transition_to(self, next: DocStatus) -> bool {
+        use DocStatus::*;
+
+        self == next
+            || match self {
+                Received => matches!(next, Stored | Failed | Deleted),
+                Stored => matches!(next, Brokered | Failed | Archived | Deleted),
+                // a brokered DaaS document can be stored again, (i.e.: a new revision)
+                Brokered => matches!(next, Stored | Provisioned | Failed | Archived | Deleted),
+                Provisioned => matches!(next, Stored | Brokered | Archived | Deleted),
+                Failed => matches!(next, Stored | Brokered | Provisioned | Archived | Deleted),
+                // the archived DaaS documents are stored again when they are rehydrated
+                Archived => matches!(next, Stored | Deleted),
+                // the deleted DaaS documents are stored again when they are restored
+                Deleted => matches!(next, Stored),
+            }
+    }
+}
+
+/// The strategies for generating the unique identifier of a DaaS document.
+/// Every strategy keeps the category~subcategory~source_name prefix so that storage and routing by category still work.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum IdStrategy {
+    /// category~subcategory~source_name~source_uid, (default) so that resubmitting a source_uid upserts a new revision
+    #[default]
+    Deterministic,
+    /// category~subcategory~source_name~source_uid-nanoseconds, so that every submission of a source_uid is a new document
+    TimestampSuffixed,
+    /// category~subcategory~source_name~uuid, using a time ordered UUID (version 7)
+    UuidV7,
+    /// category~subcategory~source_name~sha256, using a hash of the source_uid and data so that identical submissions are upserted
+    Hash,
+}
+
+impl IdStrategy {
+    /// Returns the unique identifier of the DaaS document based on the strategy
+    ///
+    /// # Arguments
+    ///
+    /// * cat: String - The name of the category (e.g.: order).</br>
+    /// * sub: String - The name of the subcategory (e.g.: clothing).</br>
+    /// * src_name: String - The name of the data source.</br>
+    /// * src_uid: usize, String or &str - The unique identifier that the data source provided, (see `SourceId`).</br>
+    /// * data: &[u8] - The data from the data source.</br>
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// extern crate daas;
+    ///
+    /// use daas::doc::IdStrategy;
+    ///
+    /// fn main() {
+    ///     let id = IdStrategy::UuidV7.make_id("order".to_string(), "clothing".to_string(), "iStore".to_string(), 5000, b"{}");
+    ///     
+    ///     assert!(id.starts_with("order~clothing~iStore~"));
+    /// }
+    /// ```
+    pub fn make_id<U: Into<SourceId>>(
+        &self,
+        cat: String,
+        subcat: String,
+        src_name: String,
+        src_uid: U,
+        data: &[u8],
+    ) -> String {
+        let src_uid = src_uid.into();
+        let unique = match self {
+            IdStrategy::Deterministic => src_uid.to_id_component(),
+            IdStrategy::TimestampSuffixed => format!(
+                "{}-{}",
+                src_uid.to_id_component(),
+                SystemTime::now()
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .unwrap()
+                    .as_nanos()
+            ),
+            IdStrategy::UuidV7 => uuid::Uuid::now_v7().to_string(),
+            IdStrategy::Hash => {
+                let mut content = src_uid.to_string().into_bytes();
+                content.push(0);
+                content.extend_from_slice(data);
+                Sha256::digest(&content)
+                    .iter()
+                    .map(|b| format!("{:02x}", b))
+                    .collect()
+            }
+        };
+
+        format!(
+            "{}{}",
+            DaaSDoc::make_id_prefix(&cat, &subcat, &src_name),
+            unique
+        )
+    }
+}
+
+// The number of bytes of the data object that are serialized at a time by the DaaSDocChunks
+const CHUNK_DATA_BATCH: usize = 8 * 1024;
+
+/// Represents the serialized DaaS document as chunks of bytes, (see `DaaSDoc::to_chunks()`).
+/// The envelope is serialized up front, but the data object is serialized as the chunks are read,
+/// so large DaaS documents can be written (e.g.: uploaded in parts) without holding the entire serialized document in memory.
+pub struct DaaSDocChunks<'a> {
+    head: Option<Vec<u8>>,
+    data: &'a [u8],
+    next_byte: usize,
+    tail: Option<Vec<u8>>,
+    pending: Vec<u8>,
+    pending_pos: usize,
+    size: usize,
+    len: usize,
+}
+
+impl<'a> DaaSDocChunks<'a> {
+    /// Returns the total number of bytes of the serialized DaaS document
+    pub fn serialized_len(&self) -> usize {
+        self.len
+    }
+
+    // Serializes the next piece of the document, returning false when there is nothing left
+    fn refill(&mut self) -> bool {
+        self.pending.clear();
+        self.pending_pos = 0;
+
+        if let Some(head) = self.head.take() {
+            self.pending = head;
+        } else if self.next_byte < self.data.len() {
+            let end = (self.next_byte + CHUNK_DATA_BATCH).min(self.data.len());
+            for b in self.data[self.next_byte..end].iter() {
+                if self.next_byte > 0 {
+                    self.pending.push(b',');
+                }
+                push_number(&mut self.pending, *b);
+                self.next_byte += 1;
+            }
+        } else if let Some(tail) = self.tail.take() {
+            self.pending = tail;
+        } else {
+            return false;
+        }
+
+        true
+    }
+}
+
+impl<'a> Iterator for DaaSDocChunks<'a> {
+    type Item = Vec<u8>;
+
+    fn next(&mut self) -> Option<Vec<u8>> {
+        let mut chunk = Vec::with_capacity(self.size.min(self.len));
+
+        while chunk.len() < self.size {
+            if self.pending_pos < self.pending.len() {
+                let n = (self.size - chunk.len()).min(self.pending.len() - self.pending_pos);
+                chunk.extend_from_slice(&self.pending[self.pending_pos..self.pending_pos + n]);
+                self.pending_pos += n;
+            } else if !self.refill() {
+                break;
+            }
+        }
+
+        match chunk.is_empty() {
+            true => None,
+            false => Some(chunk),
+        }
+    }
+}
+
+// Writes the byte as a JSON number, (the same way serde_json serializes the data object)
+fn push_number(buf: &mut Vec<u8>, b: u8) {
+    if b >= 100 {
+        buf.push(b'0' + b / 100);
+    }
+    if b >= 10 {
+        buf.push(b'0' + (b / 10) % 10);
+    }
+    buf.push(b'0' + b % 10);
+}
+
+// The number of bytes of the byte as a JSON number
+fn number_len(b: u8) -> usize {
+    match b {
+        0..=9 => 1,
+        10..=99 => 2,
+        _ => 3,
+    }
+}
+
 impl DaaSDoc {
     /// Delimiter used for building the unique identifier value for the DaaS document
     //pub const DELIMITER: &'static str = "~";
@@ -120,7 +598,7 @@ impl DaaSDoc {
     /// # Arguments
     ///
     /// * src_name: String - The name of the data source.</br>
-    /// * src_uid: usize - The unique identifier that the data source provided.</br>
+    /// * src_uid: usize, String or &str - The unique identifier that the data source provided, (see `SourceId`).</br>
     /// * cat: String - The name of the category (e.g.: order).</br>
     /// * sub: String - The name of the subcategory (e.g.: clothing).</br>
     /// * auth: String - The name of the auithor who created the document.</br>
@@ -156,9 +634,9 @@ impl DaaSDoc {
     ///     println!("{:?}", doc._id);
     /// }
     /// ```
-    pub fn new(
+    pub fn new<U: Into<SourceId>>(
         src_name: String,
-        src_uid: usize,
+        src_uid: U,
         cat: String,
         subcat: String,
         auth: String,
@@ -166,17 +644,29 @@ impl DaaSDoc {
         dtc: Tracker,
         data: Vec<u8>,
     ) -> DaaSDoc {
-        let this_id = DaaSDoc::make_id(cat.clone(), subcat.clone(), src_name.clone(), src_uid);
+        let src_uid = src_uid.into();
+        let this_id = DaaSDoc::make_id(
+            cat.clone(),
+            subcat.clone(),
+            src_name.clone(),
+            src_uid.clone(),
+        );
 
         DaaSDoc {
             _id: this_id.clone(),
             _rev: None,
+            schema_version: migrate::CURRENT_SCHEMA_VERSION,
             source_name: src_name,
             source_uid: src_uid,
             category: cat,
             subcategory: subcat,
             author: auth,
             process_ind: false,
+            status: DocStatus::Received,
+            deleted: false,
+            legal_hold: false,
+            expires_at: None,
+            sequence: None,
             last_updated: get_unix_now!(),
             data_usage_agreements: duas,
             data_tracker: dtc,
@@ -186,6 +676,51 @@ impl DaaSDoc {
         }
     }
 
+    /// Assigns a new unique identifier to the DaaS document using the ID strategy.
+    /// The Data Tracker Chain keeps referencing the deterministic identifier, (see `DaaSDoc::make_id()`).
+    ///
+    /// # Arguments
+    ///
+    /// * strategy: IdStrategy - The strategy used to generate the unique identifier.</br>
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// extern crate pbd;
+    /// extern crate daas;
+    ///
+    /// use pbd::dua::DUA;
+    /// use pbd::dtc::Tracker;
+    /// use daas::doc::{DaaSDoc, IdStrategy};
+    ///
+    /// fn main() {
+    ///     let src = "iStore".to_string();
+    ///     let uid = 5000;
+    ///     let cat = "order".to_string();
+    ///     let sub = "clothing".to_string();
+    ///     let auth = "istore_app".to_string();
+    ///     let mut dua = Vec::new();
+    ///     dua.push(DUA::new("billing".to_string(),"https://dua.org/agreements/v1/billing.pdf".to_string(),1553988607));
+    ///     let tracker = Tracker::new(DaaSDoc::make_id(cat.clone(), sub.clone(), src.clone(), uid.clone()));
+    ///     let data = String::from(r#"{"status": "new"}"#).as_bytes().to_vec();
+    ///     
+    ///     let doc = DaaSDoc::new(src, uid, cat, sub, auth, dua, tracker, data).with_id_strategy(IdStrategy::TimestampSuffixed);
+    ///     
+    ///     assert!(doc._id.starts_with("order~clothing~iStore~5000-"));
+    ///     assert!(doc.validate().is_ok());
+    /// }
+    /// ```
+    pub fn with_id_strategy(mut self, strategy: IdStrategy) -> DaaSDoc {
+        self._id = strategy.make_id(
+            self.category.clone(),
+            self.subcategory.clone(),
+            self.source_name.clone(),
+            self.source_uid.clone(),
+            &self.data_obj,
+        );
+        self
+    }
+
     /// Adds an entry to the metadata
     ///
     /// # Arguments
@@ -268,6 +803,79 @@ impl DaaSDoc {
         let _ = &self.tags.push(tag);
     }
 
+    /// Removes all the occurrences of a tag, returning true if the tag was found
+    ///
+    /// # Arguments
+    ///
+    /// * tag: String - The textual label to remove.</br>
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// extern crate daas;
+    ///
+    /// use daas::testing;
+    ///
+    /// fn main() {
+    ///     let mut doc = testing::get_default_daas_doc();
+    ///     doc.add_tag("foo".to_string());
+    ///
+    ///     assert!(doc.remove_tag("foo".to_string()));
+    ///     assert!(!doc.has_tag("foo".to_string()));
+    /// }
+    /// ```
+    pub fn remove_tag(&mut self, tag: String) -> bool {
+        let count = self.tags.len();
+        self.tags.retain(|t| *t != tag);
+        self.tags.len() != count
+    }
+
+    /// Replaces all the tags
+    ///
+    /// # Arguments
+    ///
+    /// * tags: Vec<String> - The textual labels that provide context about the data object.</br>
+    pub fn set_tags(&mut self, tags: Vec<String>) {
+        self.tags = tags;
+    }
+
+    /// Removes an entry from the metadata, returning its value if the key was found
+    ///
+    /// # Arguments
+    ///
+    /// * key: String - The key used to identify the name of the metadata property.</br>
+    pub fn remove_meta(&mut self, key: String) -> Option<String> {
+        self.meta_data.remove(&key)
+    }
+
+    /// Merges entries into the metadata, replacing the values of existing keys
+    ///
+    /// # Arguments
+    ///
+    /// * entries: BTreeMap<String, String> - The metadata entries (key, value) to merge.</br>
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// extern crate daas;
+    ///
+    /// use daas::testing;
+    /// use std::collections::BTreeMap;
+    ///
+    /// fn main() {
+    ///     let mut doc = testing::get_default_daas_doc();
+    ///     let mut entries = BTreeMap::new();
+    ///     entries.insert("foo".to_string(), "bar".to_string());
+    ///     entries.insert("status".to_string(), "new".to_string());
+    ///     doc.merge_meta(entries);
+    ///
+    ///     assert_eq!(doc.get_meta("foo".to_string()), "bar");
+    /// }
+    /// ```
+    pub fn merge_meta(&mut self, entries: BTreeMap<String, String>) {
+        self.meta_data.extend(entries);
+    }
+
     /// Returns the data source json value as a reference
     ///
     /// #Example
@@ -304,7 +912,304 @@ impl DaaSDoc {
         &mut self.data_obj
     }
 
-    /// Constructs a DaaSDoc object from a serialized string
+    /// Compares the DaaSDoc with another DaaSDoc and returns the structured differences
+    ///
+    /// # Arguments
+    ///
+    /// * other: &DaaSDoc - The DaaS document to compare against (e.g.: a later revision).</br>
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// extern crate pbd;
+    /// extern crate daas;
+    ///
+    /// use pbd::dua::DUA;
+    /// use pbd::dtc::Tracker;
+    /// use daas::doc::{DaaSDoc};
+    ///
+    /// fn main() {
+    ///     let src = "iStore".to_string();
+    ///     let uid = 5000;
+    ///     let cat = "order".to_string();
+    ///     let sub = "clothing".to_string();
+    ///     let auth = "istore_app".to_string();
+    ///     let mut dua = Vec::new();
+    ///     dua.push(DUA::new("billing".to_string(),"https://dua.org/agreements/v1/billing.pdf".to_string(),1553988607));
+    ///     let tracker = Tracker::new(DaaSDoc::make_id(cat.clone(), sub.clone(), src.clone(), uid.clone()));
+    ///     let data = String::from(r#"{"status": "new"}"#).as_bytes().to_vec();
+    ///
+    ///     let doc = DaaSDoc::new(src.clone(), uid, cat.clone(), sub.clone(), auth.clone(), dua, tracker, data);
+    ///     let mut other = doc.clone();
+    ///     other.add_tag("foo".to_string());
+    ///     other.data_obj = String::from(r#"{"status": "shipped"}"#).as_bytes().to_vec();
+    ///
+    ///     let diff = doc.diff(&other);
+    ///     assert_eq!(diff.tags_added, vec!["foo".to_string()]);
+    ///     assert!(diff.data_changed);
+    /// }
+    /// ```
+    pub fn diff(&self, other: &DaaSDoc) -> DocDiff {
+        // compare the envelope attributes generically so new attributes are always included
+        let mut fields = BTreeMap::new();
+        let old_env = serde_json::to_value(self).unwrap();
+        let new_env = serde_json::to_value(other).unwrap();
+        let skip = ["_rev", "meta_data", "tags", "data_obj"];
+
+        if let (Value::Object(old_map), Value::Object(new_map)) = (&old_env, &new_env) {
+            let mut keys: Vec<&String> = old_map.keys().chain(new_map.keys()).collect();
+            keys.sort();
+            keys.dedup();
+
+            for key in keys.into_iter().filter(|k| !skip.contains(&k.as_str())) {
+                let old = old_map.get(key).cloned().unwrap_or(Value::Null);
+                let new = new_map.get(key).cloned().unwrap_or(Value::Null);
+                if old != new {
+                    fields.insert(key.clone(), FieldChange { old, new });
+                }
+            }
+        }
+
+        // compare the metadata
+        let mut meta_added = Metadata::new();
+        let mut meta_removed = Metadata::new();
+        let mut meta_changed = BTreeMap::new();
+
+        for (key, val) in other.meta_data.iter() {
+            match self.meta_data.get(key) {
+                Some(old) if old != val => {
+                    meta_changed.insert(
+                        key.clone(),
+                        FieldChange {
+                            old: Value::String(old.clone()),
+                            new: Value::String(val.clone()),
+                        },
+                    );
+                }
+                Some(_) => {}
+                None => {
+                    meta_added.insert(key.clone(), val.clone());
+                }
+            }
+        }
+
+        for (key, val) in self.meta_data.iter() {
+            if !other.meta_data.contains_key(key) {
+                meta_removed.insert(key.clone(), val.clone());
+            }
+        }
+
+        // compare the tags
+        let tags_added = other
+            .tags
+            .iter()
+            .filter(|t| !self.tags.contains(t))
+            .cloned()
+            .collect();
+        let tags_removed = self
+            .tags
+            .iter()
+            .filter(|t| !other.tags.contains(t))
+            .cloned()
+            .collect();
+
+        // compare the data object, providing a JSON Patch when possible
+        let data_changed = self.data_obj != other.data_obj;
+        let data_patch = match (
+            serde_json::from_slice::<Value>(&self.data_obj),
+            serde_json::from_slice::<Value>(&other.data_obj),
+        ) {
+            (Ok(old), Ok(new)) => {
+                let mut ops = Vec::new();
+                json_patch("", &old, &new, &mut ops);
+                Some(Value::Array(ops))
+            }
+            _ => None,
+        };
+
+        DocDiff {
+            from_rev: self._rev.clone(),
+            to_rev: other._rev.clone(),
+            fields,
+            meta_added,
+            meta_removed,
+            meta_changed,
+            tags_added,
+            tags_removed,
+            data_changed,
+            data_patch,
+        }
+    }
+
+    /// Returns only the fields of the (JSON) data object that the pointers refer to, nested at the same paths, so
+    /// consumers don't have to receive the entire data object when only a few values are needed.
+    /// Fields that don't exist are left out, and the elements of arrays are keyed by their index.
+    /// Returns `Value::Null` if the data object isn't JSON.
+    ///
+    /// # Arguments
+    ///
+    /// * fields: &[JsonPointer] - The JSON Pointers of the fields to return, (e.g.: /customer/name).</br>
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// extern crate daas;
+    /// extern crate serde_json;
+    ///
+    /// use daas::doc::JsonPointer;
+    /// use daas::testing;
+    /// use serde_json::json;
+    ///
+    /// fn main() {
+    ///     let mut doc = testing::get_default_daas_doc();
+    ///     doc.data_obj = br#"{"status": "new", "customer": {"name": "J. Doe", "email": "jdoe@example.com"}}"#.to_vec();
+    ///     let fields: Vec<JsonPointer> = vec!["/status".parse().unwrap(), "/customer/name".parse().unwrap()];
+    ///
+    ///     assert_eq!(doc.project(&fields), json!({"status": "new", "customer": {"name": "J. Doe"}}));
+    /// }
+    /// ```
+    pub fn project(&self, fields: &[JsonPointer]) -> Value {
+        let data: Value = match serde_json::from_slice(&self.data_obj) {
+            Ok(d) => d,
+            Err(_e) => {
+                warn!("The data of DaaS document {} isn't JSON.", self._id);
+                return Value::Null;
+            }
+        };
+        let mut projection = Value::Object(serde_json::Map::new());
+
+        for field in fields.iter() {
+            let value = match data.pointer(&field.to_string()) {
+                Some(v) => v.clone(),
+                None => continue,
+            };
+            let tokens = field.tokens();
+            let (last, parents) = match tokens.split_last() {
+                Some(t) => t,
+                // the empty pointer refers to the entire data object
+                None => return data,
+            };
+            let mut node = &mut projection;
+            for token in parents.iter() {
+                node = match node {
+                    Value::Object(map) => map
+                        .entry(token.clone())
+                        .or_insert_with(|| Value::Object(serde_json::Map::new())),
+                    // an earlier pointer already returned the entire parent, (e.g.: /customer and /customer/name)
+                    _ => break,
+                };
+            }
+            if let Value::Object(map) = node {
+                map.insert(last.clone(), value);
+            }
+        }
+
+        projection
+    }
+
+    /// Applies a JSON Merge Patch (RFC 7396) to the (JSON) data of the DaaS document, where the fields of the patch
+    /// replace the fields of the data and the fields that are null are removed
+    ///
+    /// # Arguments
+    ///
+    /// * patch: &Value - The JSON Merge Patch.</br>
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// extern crate daas;
+    /// extern crate serde_json;
+    ///
+    /// use daas::testing;
+    /// use serde_json::json;
+    ///
+    /// fn main() {
+    ///     let mut doc = testing::get_default_daas_doc();
+    ///     doc.merge_patch(&json!({"status": "shipped", "carrier": "UPS"})).unwrap();
+    ///
+    ///     assert_eq!(String::from_utf8(doc.data_obj).unwrap(), r#"{"carrier":"UPS","status":"shipped"}"#);
+    /// }
+    /// ```
+    pub fn merge_patch(&mut self, patch: &Value) -> Result<(), DaaSDocError> {
+        let mut data: Value = match serde_json::from_slice(&self.data_obj) {
+            Ok(d) => d,
+            Err(_e) => {
+                warn!("The data of DaaS document {} isn't JSON.", self._id);
+                return Err(DaaSDocError);
+            }
+        };
+
+        merge_patch(&mut data, patch);
+        self.data_obj = serde_json::to_vec(&data).unwrap();
+        Ok(())
+    }
+
+    /// Marks the DaaS document as (soft) deleted. The revisions are kept until they are purged by the storage.
+    pub fn mark_deleted(&mut self) {
+        self.deleted = true;
+        self.status = DocStatus::Deleted;
+    }
+
+    /// Restores a (soft) deleted DaaS document
+    pub fn restore(&mut self) {
+        self.deleted = false;
+        self.status = DocStatus::Stored;
+    }
+
+    /// Changes the status of the DaaS document, if the transition is allowed, (see `DocStatus::can_transition_to()`)
+    ///
+    /// # Arguments
+    ///
+    /// * status: DocStatus - The next status.</br>
+    pub fn set_status(&mut self, status: DocStatus) -> Result<(), StatusTransitionError> {
+        match self.status.can_transition_to(status) {
+            true => {
+                self.status = status;
+                Ok(())
+            }
+            false => {
+                warn!(
+                    "The DaaS document {} can't change from {:?} to {:?}.",
+                    self._id, self.status, status
+                );
+                Err(StatusTransitionError)
+            }
+        }
+    }
+
+    /// Places or releases the legal hold on the DaaS document
+    ///
+    /// # Arguments
+    ///
+    /// * hold: bool - The indicator that represents if the document is under a legal hold.</br>
+    pub fn set_legal_hold(&mut self, hold: bool) {
+        self.legal_hold = hold;
+    }
+
+    /// Sets when the DaaS document expires based upon the retention policy of its Data Usage Agreements
+    ///
+    /// # Arguments
+    ///
+    /// * policy: &RetentionPolicy - The retention periods of the Data Usage Agreements.</br>
+    pub fn apply_retention(&mut self, policy: &RetentionPolicy) {
+        self.expires_at = policy.expires_at(&self.data_usage_agreements);
+    }
+
+    /// Determines if the data of the DaaS document has expired
+    pub fn is_expired(&self) -> bool {
+        self.is_expired_at(get_unix_now!())
+    }
+
+    /// Determines if the data of the DaaS document has expired at a point in time
+    ///
+    /// # Arguments
+    ///
+    /// * now: u64 - The Unix Epoch time to compare the expiration with.</br>
+    pub fn is_expired_at(&self, now: u64) -> bool {
+        self.expires_at.is_some_and(|e| e <= now)
+    }
+
+    /// Constructs a DaaSDoc object from a serialized string, upgrading older schema versions (see `migrate`)
     ///
     /// # Arguments
     ///
@@ -325,13 +1230,7 @@ impl DaaSDoc {
     /// }
     /// ```
     pub fn from_serialized(serialized: &[u8]) -> Result<DaaSDoc, DaaSDocError> {
-        match serde_json::from_slice(&serialized) {
-            Ok(doc) => Ok(doc),
-            Err(err) => {
-                error!("{}", err);
-                Err(DaaSDocError)
-            }
-        }
+        migrate::from_serialized(serialized)
     }
 
     /// Returns the value of a metadata entry
@@ -446,25 +1345,86 @@ impl DaaSDoc {
     ///     assert!(doc.has_tag("foo".to_string()));
     /// }
     /// ```
-    pub fn has_tag(&self, tag: String) -> bool {
-        self.tags.contains(&tag)
+    pub fn has_tag(&self, tag: String) -> bool {
+        self.tags.contains(&tag)
+    }
+
+    /// A shared function that returns the unique identifier
+    ///
+    /// # Arguments
+    ///
+    /// * cat: String - The name of the category (e.g.: order).</br>
+    /// * sub: String - The name of the subcategory (e.g.: clothing).</br>
+    /// * src_name: String - The name of the data source.</br>
+    /// * src_uid: usize, String or &str - The unique identifier that the data source provided, (see `SourceId`).</br>
+    ///
+    pub fn make_id<U: Into<SourceId>>(
+        cat: String,
+        subcat: String,
+        src_name: String,
+        src_uid: U,
+    ) -> String {
+        format!(
+            "{}{}",
+            DaaSDoc::make_id_prefix(&cat, &subcat, &src_name),
+            src_uid.into().to_id_component()
+        )
+    }
+
+    // Returns the escaped category~subcategory~source_name~ that all the unique identifiers start with
+    fn make_id_prefix(cat: &str, subcat: &str, src_name: &str) -> String {
+        format!(
+            "{}{}{}{}{}{}",
+            DaaSDoc::escape_id_component(cat),
+            DELIMITER,
+            DaaSDoc::escape_id_component(subcat),
+            DELIMITER,
+            DaaSDoc::escape_id_component(src_name),
+            DELIMITER
+        )
+    }
+
+    /// Percent-encodes the characters of an identifier component that would make the identifier ambiguous
+    /// or the storage path unsafe, (the delimiter, path separators, `%` and a leading `.`)
+    ///
+    /// # Arguments
+    ///
+    /// * component: &str - The category, subcategory or source name.</br>
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// extern crate daas;
+    ///
+    /// use daas::doc::DaaSDoc;
+    ///
+    /// fn main() {
+    ///     assert_eq!(DaaSDoc::escape_id_component("iStore"), "iStore".to_string());
+    ///     assert_eq!(DaaSDoc::escape_id_component("../i~Store"), "%2E.%2Fi%7EStore".to_string());
+    /// }
+    /// ```
+    pub fn escape_id_component(component: &str) -> String {
+        component
+            .chars()
+            .enumerate()
+            .map(|(i, c)| match c {
+                '~' => "%7E".to_string(),
+                '/' => "%2F".to_string(),
+                '\\' => "%5C".to_string(),
+                '%' => "%25".to_string(),
+                '.' if i == 0 => "%2E".to_string(),
+                _ => c.to_string(),
+            })
+            .collect()
     }
 
-    /// A shared function that returns the unique identifier
+    /// Determines if the identifier component can be used without being escaped, (see `escape_id_component()`)
     ///
     /// # Arguments
     ///
-    /// * cat: String - The name of the category (e.g.: order).</br>
-    /// * sub: String - The name of the subcategory (e.g.: clothing).</br>
-    /// * src_name: String - The name of the data source.</br>
-    /// * src_uid: usize - The unique identifier that the data source provided.</br>
-    ///
-    pub fn make_id(cat: String, subcat: String, src_name: String, src_uid: usize) -> String {
-        format!(
-            "{}{}{}{}{}{}{}",
-            cat, DELIMITER, subcat, DELIMITER, src_name, DELIMITER, src_uid
-        )
-        .to_string()
+    /// * component: &str - The category, subcategory or source name.</br>
+    pub fn is_valid_id_component(component: &str) -> bool {
+        !component.is_empty() && DaaSDoc::escape_id_component(component) == component
     }
 
     /// Serializes the DaaSDoc object
@@ -503,6 +1463,114 @@ impl DaaSDoc {
         serde_json::to_string(&self).unwrap()
     }
 
+    /// Serializes the DaaSDoc object to bytes without changing or cloning it, so the same bytes can be shared,
+    /// (e.g.: when brokering the DaaS document to many topics)
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// extern crate daas;
+    ///
+    /// use daas::doc::DaaSDoc;
+    /// use daas::testing;
+    ///
+    /// fn main() {
+    ///     let doc = testing::get_default_daas_doc();
+    ///     let bytes = doc.to_bytes();
+    ///
+    ///     assert_eq!(DaaSDoc::from_serialized(&bytes).unwrap()._id, doc._id);
+    /// }
+    /// ```
+    pub fn to_bytes(&self) -> Vec<u8> {
+        serde_json::to_vec(self).unwrap()
+    }
+
+    /// Serializes the DaaSDoc object incrementally as chunks of bytes, so that large DaaS documents can be written
+    /// without holding the entire serialized document in memory. The chunks concatenate to the same bytes as `to_bytes()`.
+    ///
+    /// # Arguments
+    ///
+    /// * size: usize - The number of bytes of the chunks (the last chunk may be smaller).</br>
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// extern crate daas;
+    ///
+    /// use daas::testing;
+    ///
+    /// fn main() {
+    ///     let doc = testing::get_default_daas_doc();
+    ///     let chunks = doc.to_chunks(64);
+    ///
+    ///     assert_eq!(chunks.serialized_len(), doc.to_bytes().len());
+    ///     assert_eq!(chunks.collect::<Vec<Vec<u8>>>().concat(), doc.to_bytes());
+    /// }
+    /// ```
+    pub fn to_chunks(&self, size: usize) -> DaaSDocChunks<'_> {
+        // the data object is the last attribute, so the envelope ends with the empty array and the closing brace
+        let mut head = serde_json::to_vec(&self.clone_envelope()).unwrap();
+        let tail = head.split_off(head.len() - 2);
+        let data_len = match self.data_obj.len() {
+            0 => 0,
+            n => n - 1 + self.data_obj.iter().map(|b| number_len(*b)).sum::<usize>(),
+        };
+
+        DaaSDocChunks {
+            len: head.len() + data_len + tail.len(),
+            head: Some(head),
+            data: &self.data_obj,
+            next_byte: 0,
+            tail: Some(tail),
+            pending: Vec::new(),
+            pending_pos: 0,
+            size: size.max(1),
+        }
+    }
+
+    /// Returns a copy of the DaaS document without the data object, so that the envelope can be shared cheaply,
+    /// (e.g.: for logging, routing or tracking the DaaS document in other threads) without copying a large data object
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// extern crate daas;
+    ///
+    /// use daas::testing;
+    ///
+    /// fn main() {
+    ///     let doc = testing::get_default_daas_doc();
+    ///     let envelope = doc.clone_envelope();
+    ///
+    ///     assert_eq!(envelope._id, doc._id);
+    ///     assert!(envelope.data_obj.is_empty());
+    /// }
+    /// ```
+    pub fn clone_envelope(&self) -> DaaSDoc {
+        DaaSDoc {
+            _id: self._id.clone(),
+            _rev: self._rev.clone(),
+            schema_version: self.schema_version,
+            source_name: self.source_name.clone(),
+            source_uid: self.source_uid.clone(),
+            category: self.category.clone(),
+            subcategory: self.subcategory.clone(),
+            author: self.author.clone(),
+            process_ind: self.process_ind,
+            status: self.status,
+            deleted: self.deleted,
+            legal_hold: self.legal_hold,
+            expires_at: self.expires_at,
+            sequence: self.sequence,
+            last_updated: self.last_updated,
+            data_usage_agreements: self.data_usage_agreements.clone(),
+            data_tracker: self.data_tracker.clone(),
+            meta_data: self.meta_data.clone(),
+            tags: self.tags.clone(),
+            data_obj: Vec::new(),
+        }
+    }
+
     /// Serializes the DaaSDoc object without the _rev attribute
     ///
     /// #Example
@@ -538,6 +1606,7 @@ impl DaaSDoc {
     pub fn serialize_without_rev(&mut self) -> String {
         let no_rev: DaaSDocNoRev = DaaSDocNoRev {
             _id: self._id.clone(),
+            schema_version: self.schema_version,
             source_name: self.source_name.clone(),
             source_uid: self.source_uid.clone(),
             category: self.category.clone(),
@@ -559,6 +1628,7 @@ impl DaaSDoc {
     ///
     /// + Must have at least one Dat Usage Agreement
     /// + Must have a Data Tracker Chain that has not been tampered with or replaced with a fake one
+    /// + Must not have expired data, (see `RetentionPolicy`)
     ///
     /// #Example
     ///
@@ -610,6 +1680,8 @@ impl DaaSDoc {
             Err(err) => return Err(err),
         };
 
+        self.validate_not_expired()?;
+
         match chck {
             true => Ok(self),
             false => return Err(DaaSSecurityError::ValidationError),
@@ -617,7 +1689,16 @@ impl DaaSDoc {
     }
 
     fn validate_matching_tracker(&self) -> Result<(), DaaSSecurityError> {
-        match self.data_tracker.get(0).unwrap().identifier.data_id == self._id {
+        let data_id = self.data_tracker.get(0).unwrap().identifier.data_id;
+        let source_id = DaaSDoc::make_id(
+            self.category.clone(),
+            self.subcategory.clone(),
+            self.source_name.clone(),
+            self.source_uid.clone(),
+        );
+
+        // the tracker references either the identifier or the deterministic identifier (see `IdStrategy`)
+        match data_id == self._id || data_id == source_id {
             true => Ok(()),
             false => {
                 warn!(
@@ -642,6 +1723,19 @@ impl DaaSDoc {
         }
     }
 
+    fn validate_not_expired(&self) -> Result<(), DaaSSecurityError> {
+        match self.is_expired() {
+            false => Ok(()),
+            true => {
+                warn!(
+                    "DaaS detected expired data in docoument {} and has rejected it.",
+                    self._id
+                );
+                Err(DaaSSecurityError::ExpiredDataError)
+            }
+        }
+    }
+
     fn validate_has_usage_agreement(&self) -> Result<(), DaaSSecurityError> {
         match self.data_usage_agreements.is_empty() {
             false => match self.data_usage_agreements[0].agreed_dtm < get_unix_now!() {
@@ -662,6 +1756,80 @@ impl DaaSDoc {
     }
 }
 
+// Escapes a key so that it can be used as a reference token in a JSON Pointer (RFC 6901)
+fn escape_pointer(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+// Applies the JSON Merge Patch (RFC 7396) to the target value
+fn merge_patch(target: &mut Value, patch: &Value) {
+    let patch_map = match patch {
+        Value::Object(m) => m,
+        // a patch that isn't an object replaces the entire target
+        _ => {
+            *target = patch.clone();
+            return;
+        }
+    };
+    if !target.is_object() {
+        *target = Value::Object(serde_json::Map::new());
+    }
+    let target_map = target.as_object_mut().unwrap();
+
+    for (key, value) in patch_map.iter() {
+        match value {
+            Value::Null => {
+                target_map.remove(key);
+            }
+            _ => merge_patch(target_map.entry(key.clone()).or_insert(Value::Null), value),
+        }
+    }
+}
+
+// Builds the JSON Patch (RFC 6902) operations that transform the old value into the new value
+pub(crate) fn json_patch(path: &str, old: &Value, new: &Value, ops: &mut Vec<Value>) {
+    match (old, new) {
+        (Value::Object(old_map), Value::Object(new_map)) => {
+            for (key, old_val) in old_map.iter() {
+                let child = format!("{}/{}", path, escape_pointer(key));
+                match new_map.get(key) {
+                    Some(new_val) => json_patch(&child, old_val, new_val, ops),
+                    None => ops.push(json!({"op": "remove", "path": child})),
+                }
+            }
+            for (key, new_val) in new_map.iter() {
+                if !old_map.contains_key(key) {
+                    let child = format!("{}/{}", path, escape_pointer(key));
+                    ops.push(json!({"op": "add", "path": child, "value": new_val}));
+                }
+            }
+        }
+        (Value::Array(old_arr), Value::Array(new_arr)) => {
+            let common = old_arr.len().min(new_arr.len());
+            for idx in 0..common {
+                json_patch(
+                    &format!("{}/{}", path, idx),
+                    &old_arr[idx],
+                    &new_arr[idx],
+                    ops,
+                );
+            }
+            // remove from the end so the remaining indexes stay valid
+            for idx in (common..old_arr.len()).rev() {
+                ops.push(json!({"op": "remove", "path": format!("{}/{}", path, idx)}));
+            }
+            for val in new_arr.iter().skip(common) {
+                ops.push(json!({"op": "add", "path": format!("{}/-", path), "value": val}));
+            }
+        }
+        _ => {
+            if old != new {
+                ops.push(json!({"op": "replace", "path": path, "value": new}));
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -710,6 +1878,161 @@ mod tests {
         ))
     }
 
+    #[test]
+    fn test_retention_policy() {
+        let duas = vec![
+            DUA::new("billing".to_string(), "billing.pdf".to_string(), 1000),
+            DUA::new("marketing".to_string(), "marketing.pdf".to_string(), 2000),
+        ];
+
+        assert_eq!(RetentionPolicy::new().expires_at(&duas), None);
+        assert_eq!(
+            RetentionPolicy::new()
+                .with_period("marketing".to_string(), 100)
+                .expires_at(&duas),
+            Some(2100)
+        );
+        assert_eq!(
+            RetentionPolicy::new()
+                .with_period("marketing".to_string(), 100)
+                .with_default_period(500)
+                .expires_at(&duas),
+            Some(1500)
+        );
+    }
+
+    #[test]
+    fn test_validate_expired() {
+        let mut doc = get_default_daasdoc();
+        doc.apply_retention(&RetentionPolicy::new().with_period("billing".to_string(), 60));
+
+        assert_eq!(doc.expires_at, Some(1553988667));
+        assert!(doc.is_expired());
+        assert!(!doc.is_expired_at(1553988666));
+        match doc.validate() {
+            Err(DaaSSecurityError::ExpiredDataError) => {}
+            _ => panic!("The expired DaaS document was not rejected."),
+        }
+    }
+
+    #[test]
+    fn test_source_id_text() {
+        let uid = "0b8d3c5e-8f4a-4c1a".to_string();
+        let dtc = get_dtc(
+            "iStore".to_string(),
+            0,
+            "order".to_string(),
+            "clothing".to_string(),
+        );
+        let mut doc = DaaSDoc::new(
+            "iStore".to_string(),
+            uid.clone(),
+            "order".to_string(),
+            "clothing".to_string(),
+            "istore_app".to_string(),
+            get_dua(),
+            dtc,
+            Vec::new(),
+        );
+
+        assert_eq!(
+            doc._id,
+            "order~clothing~iStore~0b8d3c5e-8f4a-4c1a".to_string()
+        );
+        assert_eq!(doc.source_uid, SourceId::Text(uid));
+        assert!(doc
+            .serialize()
+            .contains(r#""source_uid":"0b8d3c5e-8f4a-4c1a""#));
+    }
+
+    #[test]
+    fn test_source_id_numeric_compatible() {
+        let mut doc = get_default_daasdoc();
+        let serialized = doc.serialize();
+        let doc = DaaSDoc::from_serialized(serialized.as_bytes()).unwrap();
+
+        assert!(serialized.contains(r#""source_uid":5000"#));
+        assert_eq!(doc.source_uid, 5000);
+        assert_eq!("5000".parse::<SourceId>().unwrap(), SourceId::Number(5000));
+        assert_eq!(
+            "a~b".parse::<SourceId>().unwrap(),
+            SourceId::Text("a~b".to_string())
+        );
+        assert_eq!(
+            DaaSDoc::make_id(
+                "order".to_string(),
+                "clothing".to_string(),
+                "iStore".to_string(),
+                "a~b"
+            ),
+            "order~clothing~iStore~a%7Eb".to_string()
+        );
+    }
+
+    #[test]
+    fn test_make_id_escaped() {
+        let id = DaaSDoc::make_id(
+            "order".to_string(),
+            "../..".to_string(),
+            "i~Store/west".to_string(),
+            5000,
+        );
+
+        assert_eq!(id, "order~%2E.%2F..~i%7EStore%2Fwest~5000".to_string());
+        assert_eq!(id.split(DELIMITER).count(), 4);
+    }
+
+    #[test]
+    fn test_is_valid_id_component() {
+        assert!(DaaSDoc::is_valid_id_component("iStore"));
+        assert!(DaaSDoc::is_valid_id_component("i.Store"));
+        assert!(!DaaSDoc::is_valid_id_component(""));
+        assert!(!DaaSDoc::is_valid_id_component(".."));
+        assert!(!DaaSDoc::is_valid_id_component("i~Store"));
+        assert!(!DaaSDoc::is_valid_id_component("i\\Store"));
+        assert!(!DaaSDoc::is_valid_id_component("100%"));
+    }
+
+    #[test]
+    fn test_id_strategy_deterministic() {
+        let doc = get_default_daasdoc();
+        let id = doc.clone().with_id_strategy(IdStrategy::default())._id;
+
+        assert_eq!(id, doc._id);
+    }
+
+    #[test]
+    fn test_id_strategy_unique() {
+        let doc = get_default_daasdoc();
+        let first = doc.clone().with_id_strategy(IdStrategy::TimestampSuffixed);
+        let second = doc.clone().with_id_strategy(IdStrategy::TimestampSuffixed);
+        let uuid = doc.clone().with_id_strategy(IdStrategy::UuidV7);
+
+        assert_ne!(first._id, second._id);
+        assert!(first._id.starts_with("order~clothing~iStore~5000-"));
+        assert_eq!(uuid._id.split(DELIMITER).count(), 4);
+        assert_ne!(
+            uuid._id,
+            doc.clone().with_id_strategy(IdStrategy::UuidV7)._id
+        );
+        assert!(uuid.validate().is_ok());
+    }
+
+    #[test]
+    fn test_id_strategy_hash() {
+        let doc = get_default_daasdoc();
+        let hashed = doc.clone().with_id_strategy(IdStrategy::Hash);
+        let mut changed = doc.clone();
+        changed.data_obj = String::from(r#"{"status": "shipped"}"#).as_bytes().to_vec();
+
+        assert_eq!(
+            hashed._id,
+            doc.clone().with_id_strategy(IdStrategy::Hash)._id
+        );
+        assert_ne!(hashed._id, changed.with_id_strategy(IdStrategy::Hash)._id);
+        assert_eq!(hashed._id.split(DELIMITER).last().unwrap().len(), 64);
+    }
+
     #[test]
     fn test_has_tag_ok() {
         let mut doc = get_default_daasdoc();
@@ -720,6 +2043,41 @@ mod tests {
         assert_eq!(doc.has_tag("me".to_string()), false);
     }
 
+    #[test]
+    fn test_remove_tag_ok() {
+        let mut doc = get_default_daasdoc();
+        doc.add_tag("foo".to_string());
+        doc.add_tag("bar".to_string());
+
+        assert_eq!(doc.remove_tag("foo".to_string()), true);
+        assert_eq!(doc.remove_tag("foo".to_string()), false);
+        assert_eq!(doc.has_tag("bar".to_string()), true);
+    }
+
+    #[test]
+    fn test_set_tags_ok() {
+        let mut doc = get_default_daasdoc();
+        doc.add_tag("foo".to_string());
+        doc.set_tags(vec!["bar".to_string()]);
+
+        assert_eq!(doc.has_tag("foo".to_string()), false);
+        assert_eq!(doc.has_tag("bar".to_string()), true);
+    }
+
+    #[test]
+    fn test_meta_mutation_ok() {
+        let mut doc = get_default_daasdoc();
+        doc.add_meta("foo".to_string(), "1".to_string());
+        let mut entries = BTreeMap::new();
+        entries.insert("foo".to_string(), "2".to_string());
+        entries.insert("bar".to_string(), "3".to_string());
+        doc.merge_meta(entries);
+
+        assert_eq!(doc.get_meta("foo".to_string()), "2".to_string());
+        assert_eq!(doc.remove_meta("bar".to_string()), Some("3".to_string()));
+        assert_eq!(doc.remove_meta("bar".to_string()), None);
+    }
+
     #[test]
     fn test_new_obj_ok() {
         let _doc = get_default_daasdoc();
@@ -727,6 +2085,138 @@ mod tests {
         assert!(true);
     }
 
+    #[test]
+    fn test_diff_no_changes() {
+        let doc = get_default_daasdoc();
+        let diff = doc.diff(&doc.clone());
+
+        assert!(diff.is_empty());
+        assert_eq!(diff.data_patch, Some(json!([])));
+    }
+
+    #[test]
+    fn test_diff_envelope_meta_tags() {
+        let mut doc = get_default_daasdoc();
+        doc.add_meta("foo".to_string(), "bar".to_string());
+        doc.add_meta("old".to_string(), "gone".to_string());
+        doc.add_tag("foo".to_string());
+        let mut other = doc.clone();
+        other._rev = Some("1".to_string());
+        other.author = "another_app".to_string();
+        other.add_meta("foo".to_string(), "baz".to_string());
+        other.add_meta("new".to_string(), "here".to_string());
+        other.meta_data.remove("old");
+        other.tags = vec!["bar".to_string()];
+
+        let diff = doc.diff(&other);
+        assert_eq!(diff.from_rev, None);
+        assert_eq!(diff.to_rev, Some("1".to_string()));
+        assert_eq!(diff.fields.len(), 1);
+        assert_eq!(diff.fields.get("author").unwrap().new, json!("another_app"));
+        assert_eq!(diff.meta_added.get("new").unwrap(), "here");
+        assert_eq!(diff.meta_removed.get("old").unwrap(), "gone");
+        assert_eq!(diff.meta_changed.get("foo").unwrap().old, json!("bar"));
+        assert_eq!(diff.tags_added, vec!["bar".to_string()]);
+        assert_eq!(diff.tags_removed, vec!["foo".to_string()]);
+        assert!(!diff.data_changed);
+    }
+
+    #[test]
+    fn test_diff_json_patch() {
+        let doc = get_default_daasdoc();
+        let mut other = doc.clone();
+        other.data_obj = String::from(r#"{"status": "shipped", "items": [1], "a/b": true}"#)
+            .as_bytes()
+            .to_vec();
+
+        let diff = doc.diff(&other);
+        assert!(diff.data_changed);
+        assert_eq!(
+            diff.data_patch.unwrap(),
+            json!([
+                {"op": "replace", "path": "/status", "value": "shipped"},
+                {"op": "add", "path": "/a~1b", "value": true},
+                {"op": "add", "path": "/items", "value": [1]}
+            ])
+        );
+    }
+
+    #[test]
+    fn test_diff_binary_data() {
+        let doc = get_default_daasdoc();
+        let mut other = doc.clone();
+        other.data_obj = vec![0, 159, 146, 150];
+
+        let diff = doc.diff(&other);
+        assert!(diff.data_changed);
+        assert!(diff.data_patch.is_none());
+    }
+
+    #[test]
+    fn test_json_pointer() {
+        let ptr: JsonPointer = "/a~1b/c~0d".parse().unwrap();
+        assert_eq!(ptr.tokens(), vec!["a/b".to_string(), "c~d".to_string()]);
+        assert!("".parse::<JsonPointer>().unwrap().tokens().is_empty());
+        assert!("status".parse::<JsonPointer>().is_err());
+    }
+
+    #[test]
+    fn test_project() {
+        let mut doc = get_default_daasdoc();
+        doc.data_obj = br#"{"status": "new", "customer": {"name": "J. Doe", "email": "jdoe@example.com"}, "items": [{"sku": 1}, {"sku": 2}]}"#.to_vec();
+        let fields: Vec<JsonPointer> = vec![
+            "/customer/name",
+            "/items/1/sku",
+            "/missing",
+            "/customer/name/first",
+        ]
+        .iter()
+        .map(|f| f.parse().unwrap())
+        .collect();
+
+        assert_eq!(
+            doc.project(&fields),
+            json!({"customer": {"name": "J. Doe"}, "items": {"1": {"sku": 2}}})
+        );
+        assert_eq!(
+            doc.project(&[
+                "/customer".parse().unwrap(),
+                "/customer/name".parse().unwrap()
+            ]),
+            json!({"customer": {"name": "J. Doe", "email": "jdoe@example.com"}})
+        );
+        assert_eq!(
+            doc.project(&["".parse().unwrap()]),
+            serde_json::from_slice::<Value>(&doc.data_obj).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_merge_patch() {
+        let mut doc = get_default_daasdoc();
+        doc.data_obj =
+            br#"{"status": "new", "customer": {"name": "J. Doe", "email": "jdoe@example.com"}, "lines": [1, 2]}"#
+                .to_vec();
+        doc.merge_patch(&json!({"status": "shipped", "customer": {"email": null}, "lines": [3]}))
+            .unwrap();
+
+        assert_eq!(
+            serde_json::from_slice::<Value>(&doc.data_obj).unwrap(),
+            json!({"status": "shipped", "customer": {"name": "J. Doe"}, "lines": [3]})
+        );
+
+        doc.data_obj = b"name=jdoe".to_vec();
+        assert!(doc.merge_patch(&json!({"status": "shipped"})).is_err());
+    }
+
+    #[test]
+    fn test_project_not_json() {
+        let mut doc = get_default_daasdoc();
+        doc.data_obj = b"name=jdoe".to_vec();
+
+        assert_eq!(doc.project(&["/name".parse().unwrap()]), Value::Null);
+    }
+
     #[test]
     fn test_doc_id_ok() {
         let src = "iStore".to_string();
@@ -924,4 +2414,54 @@ mod tests {
 
         assert_eq!(doc.get_tags().len(), 2);
     }
+
+    #[test]
+    fn test_doc_status_transitions() {
+        let mut doc = get_default_daasdoc();
+        assert_eq!(doc.status, DocStatus::Received);
+
+        assert!(doc.set_status(DocStatus::Provisioned).is_err());
+        assert!(doc.set_status(DocStatus::Stored).is_ok());
+        assert!(doc.set_status(DocStatus::Brokered).is_ok());
+        assert!(doc.set_status(DocStatus::Provisioned).is_ok());
+        assert!(doc.set_status(DocStatus::Received).is_err());
+        assert_eq!(doc.status, DocStatus::Provisioned);
+
+        doc.mark_deleted();
+        assert_eq!(doc.status, DocStatus::Deleted);
+        assert!(doc.set_status(DocStatus::Archived).is_err());
+        doc.restore();
+        assert_eq!(doc.status, DocStatus::Stored);
+    }
+    #[test]
+    fn test_to_chunks() {
+        let mut doc = get_default_daasdoc();
+        doc.data_obj = (0..=255).cycle().take(10_000).collect();
+        doc.add_tag("foo".to_string());
+
+        for size in [1, 7, 1024, 1_000_000].iter() {
+            let chunks = doc.to_chunks(*size);
+            assert_eq!(chunks.serialized_len(), doc.to_bytes().len());
+
+            let chunks: Vec<Vec<u8>> = chunks.collect();
+            assert!(chunks.iter().rev().skip(1).all(|c| c.len() == *size));
+            assert_eq!(chunks.concat(), doc.to_bytes());
+        }
+
+        doc.data_obj = Vec::new();
+        assert_eq!(
+            doc.to_chunks(16).collect::<Vec<Vec<u8>>>().concat(),
+            doc.to_bytes()
+        );
+    }
+    #[test]
+    fn test_clone_envelope() {
+        let mut doc = get_default_daasdoc();
+        doc.add_tag("foo".to_string());
+        let mut envelope = doc.clone_envelope();
+
+        assert!(envelope.data_obj.is_empty());
+        envelope.data_obj = doc.data_obj.clone();
+        assert_eq!(envelope.to_bytes(), doc.to_bytes());
+    }
 }
@@ -0,0 +1,167 @@
+//! The `deidentify` module makes redacted copies of DaaS documents, where the sensitive fields of the data and the
+//! sensitive metadata have been removed, so the copies can be shared more broadly, (e.g.: on the analytics topics).
+//!
+//! The sensitive fields are named by their dotted path in the JSON data object, (e.g.: `customer.email`). When the
+//! path runs through an array, the field is removed from every element of the array. Data that isn't JSON can't be
+//! redacted field by field, so it is removed altogether.
+//!
+//! # Examples
+//!
+//! ```
+//! extern crate daas;
+//!
+//! use daas::doc::deidentify::DeidentificationRules;
+//! use daas::testing;
+//!
+//! fn main() {
+//!     let rules = DeidentificationRules::new().with_field("customer.email".to_string());
+//!     let mut doc = testing::get_default_daas_doc();
+//!     doc.data_obj = br#"{"status": "new", "customer": {"email": "jdoe@example.com", "country": "US"}}"#.to_vec();
+//!
+//!     let redacted = rules.redact(&doc);
+//!
+//!     assert_eq!(String::from_utf8(redacted.data_obj).unwrap(), r#"{"customer":{"country":"US"},"status":"new"}"#);
+//! }
+//! ```
+
+use super::DaaSDoc;
+use log::*;
+use serde_json::Value;
+
+/// The tag of the redacted copies of the DaaS documents
+pub const REDACTED_TAG: &str = "redacted";
+
+/// Represents which fields and metadata of the DaaS documents are sensitive
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct DeidentificationRules {
+    /// The dotted paths of the sensitive fields of the data, (e.g.: customer.email)
+    pub fields: Vec<String>,
+    /// The keys of the sensitive metadata
+    pub meta_keys: Vec<String>,
+}
+
+impl DeidentificationRules {
+    /// Constructs DeidentificationRules without any sensitive fields
+    pub fn new() -> DeidentificationRules {
+        DeidentificationRules::default()
+    }
+
+    /// Marks the field of the data as sensitive
+    ///
+    /// # Arguments
+    ///
+    /// * path: String - The dotted path of the field, (e.g.: customer.email).</br>
+    pub fn with_field(mut self, path: String) -> DeidentificationRules {
+        self.fields.push(path);
+        self
+    }
+
+    /// Marks the metadata as sensitive
+    ///
+    /// # Arguments
+    ///
+    /// * key: String - The key of the metadata, (e.g.: client-ip).</br>
+    pub fn with_meta_key(mut self, key: String) -> DeidentificationRules {
+        self.meta_keys.push(key);
+        self
+    }
+
+    // Removes the field at the path from the value, and from every element of the arrays along the path
+    fn remove_path(value: &mut Value, path: &[&str]) {
+        match (value, path) {
+            (Value::Array(items), _) => items
+                .iter_mut()
+                .for_each(|item| Self::remove_path(item, path)),
+            (Value::Object(map), [field]) => {
+                map.remove(*field);
+            }
+            (Value::Object(map), [field, rest @ ..]) => {
+                if let Some(child) = map.get_mut(*field) {
+                    Self::remove_path(child, rest);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Returns a copy of the DaaS document without the sensitive fields and metadata, which is tagged `redacted`
+    ///
+    /// # Arguments
+    ///
+    /// * doc: &DaaSDoc - The DaaS document to redact.</br>
+    pub fn redact(&self, doc: &DaaSDoc) -> DaaSDoc {
+        let mut copy = doc.clone();
+
+        copy.data_obj = match serde_json::from_slice::<Value>(&doc.data_obj) {
+            Ok(mut data) => {
+                for path in self.fields.iter() {
+                    let path: Vec<&str> = path.split('.').collect();
+                    Self::remove_path(&mut data, &path);
+                }
+                serde_json::to_vec(&data).unwrap()
+            }
+            Err(_e) => {
+                warn!(
+                    "The data of DaaS document {} isn't JSON, so it was removed from the redacted copy.",
+                    doc._id
+                );
+                Vec::new()
+            }
+        };
+
+        for key in self.meta_keys.iter() {
+            copy.remove_meta(key.clone());
+        }
+        copy.add_tag(REDACTED_TAG.to_string());
+
+        copy
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    fn get_doc(data: &str) -> DaaSDoc {
+        let mut doc = testing::get_default_daas_doc();
+        doc.data_obj = data.as_bytes().to_vec();
+        doc.add_meta("client-ip".to_string(), "10.0.0.1".to_string());
+        doc.add_meta("content-type".to_string(), "application/json".to_string());
+        doc
+    }
+
+    #[test]
+    fn test_redact_fields_and_meta() {
+        let rules = DeidentificationRules::new()
+            .with_field("ssn".to_string())
+            .with_field("items.card.number".to_string())
+            .with_field("missing.field".to_string())
+            .with_meta_key("client-ip".to_string());
+        let doc = get_doc(
+            r#"{"ssn": "123-45-6789", "items": [{"sku": 1, "card": {"number": "4111"}}, {"sku": 2}]}"#,
+        );
+
+        let mut redacted = rules.redact(&doc);
+        let data: Value = serde_json::from_slice(&redacted.data_obj).unwrap();
+        assert_eq!(
+            data,
+            serde_json::json!({"items": [{"sku": 1, "card": {}}, {"sku": 2}]})
+        );
+        assert!(!redacted.meta_data.contains_key("client-ip"));
+        assert_eq!(
+            redacted.get_meta("content-type".to_string()),
+            "application/json".to_string()
+        );
+        assert!(redacted.has_tag(REDACTED_TAG.to_string()));
+        assert_eq!(redacted._id, doc._id);
+    }
+
+    #[test]
+    fn test_redact_not_json() {
+        let rules = DeidentificationRules::new();
+        let redacted = rules.redact(&get_doc("name=jdoe"));
+
+        assert!(redacted.data_obj.is_empty());
+    }
+}
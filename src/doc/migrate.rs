@@ -0,0 +1,229 @@
+//! The `migrate` module upgrades older serialized forms of the DaaS document to the current schema, so that
+//! long-lived archives (e.g.: S3 buckets) remain readable as the DaaSDoc evolves.
+//!
+//! Every serialized DaaS document carries the `schema_version` it was written with. DaaS documents that were
+//! written before the schema was versioned don't have a `schema_version` and are treated as version 1.
+//! When a DaaS document is loaded, the upgrade steps are applied in order until it is the current version.
+//!
+//! | version | changes |
+//! |---|---|
+//! | 1 | the original schema (`meta_data` and `tags` may be missing) |
+//! | 2 | adds the `schema_version` |
+//! | 3 | adds the `deleted` and `legal_hold` lifecycle flags |
+//! | 4 | adds the `expires_at` |
+//! | 5 | adds the `status` |
+//! | 6 | adds the `sequence` |
+//!
+//! # Examples
+//!
+//! ```
+//! extern crate daas;
+//!
+//! use daas::doc::migrate;
+//!
+//! fn main() {
+//!     let serialized = r#"{"_id":"order~clothing~iStore~5000","_rev":null,"source_name":"iStore","source_uid":5000,"category":"order","subcategory":"clothing","author":"istore_app","process_ind":false,"last_updated":1553988607,"data_usage_agreements":[{"agreement_name":"billing","location":"www.dua.org/billing.pdf","agreed_dtm":1553988607}],"data_tracker":{"chain":[{"identifier":{"data_id":"order~clothing~iStore~5000","index":0,"timestamp":0,"actor_id":"","previous_hash":"0"},"hash":"72259503327276020952102368672148358485","nonce":5}]},"data_obj":[123,125]}"#;
+//!     let doc = migrate::from_serialized(serialized.as_bytes()).unwrap();
+//!
+//!     assert_eq!(doc.schema_version, migrate::CURRENT_SCHEMA_VERSION);
+//!     assert!(doc.tags.is_empty());
+//! }
+//! ```
+
+use super::DaaSDoc;
+use crate::errors::DaaSDocError;
+use log::*;
+use serde_json::{json, Map, Value};
+
+/// The schema version of the DaaS documents that are created by this version of the SDK
+pub const CURRENT_SCHEMA_VERSION: u32 = 6;
+/// The schema version of the DaaS documents that were written before the schema was versioned
+pub const LEGACY_SCHEMA_VERSION: u32 = 1;
+
+// Repesentation of a serialized DaaS document
+type SerializedDoc = Map<String, Value>;
+
+/// Returns the schema version that is assumed when a serialized DaaS document doesn't have one
+pub fn legacy_schema_version() -> u32 {
+    LEGACY_SCHEMA_VERSION
+}
+
+/// Returns the schema version of a serialized DaaS document
+///
+/// # Arguments
+///
+/// * doc: &Value - The serialized DaaS document.</br>
+pub fn schema_version(doc: &Value) -> u32 {
+    doc.get("schema_version")
+        .and_then(|v| v.as_u64())
+        .map(|v| v as u32)
+        .unwrap_or(LEGACY_SCHEMA_VERSION)
+}
+
+/// Upgrades a serialized DaaS document to the current schema version
+///
+/// # Arguments
+///
+/// * doc: Value - The serialized DaaS document.</br>
+///
+/// #Example
+///
+/// ```
+/// extern crate daas;
+/// extern crate serde_json;
+///
+/// use daas::doc::migrate;
+///
+/// fn main() {
+///     let doc = migrate::upgrade(serde_json::json!({"_id": "order~clothing~iStore~5000"})).unwrap();
+///
+///     assert_eq!(migrate::schema_version(&doc), migrate::CURRENT_SCHEMA_VERSION);
+/// }
+/// ```
+pub fn upgrade(doc: Value) -> Result<Value, DaaSDocError> {
+    let mut version = schema_version(&doc);
+    let mut doc: SerializedDoc = match doc {
+        Value::Object(d) => d,
+        _ => {
+            error!("The serialized DaaS document is not an object.");
+            return Err(DaaSDocError);
+        }
+    };
+
+    if version > CURRENT_SCHEMA_VERSION {
+        error!(
+            "The DaaS document has schema version {} which is newer than the supported version {}.",
+            version, CURRENT_SCHEMA_VERSION
+        );
+        return Err(DaaSDocError);
+    }
+
+    while version < CURRENT_SCHEMA_VERSION {
+        match version {
+            1 => upgrade_v1(&mut doc),
+            2 => upgrade_v2(&mut doc),
+            3 => upgrade_v3(&mut doc),
+            4 => upgrade_v4(&mut doc),
+            5 => upgrade_v5(&mut doc),
+            _ => {
+                error!("There is no upgrade for schema version {}.", version);
+                return Err(DaaSDocError);
+            }
+        }
+        version += 1;
+        doc.insert("schema_version".to_string(), json!(version));
+    }
+
+    Ok(Value::Object(doc))
+}
+
+/// Constructs a DaaSDoc object from a serialized string of any supported schema version
+///
+/// # Arguments
+///
+/// * serialized: &[u8] - The string that represents the serialized object.</br>
+pub fn from_serialized(serialized: &[u8]) -> Result<DaaSDoc, DaaSDocError> {
+    let doc = match serde_json::from_slice::<Value>(serialized) {
+        Ok(d) => upgrade(d)?,
+        Err(err) => {
+            error!("{}", err);
+            return Err(DaaSDocError);
+        }
+    };
+
+    match serde_json::from_value(doc) {
+        Ok(d) => Ok(d),
+        Err(err) => {
+            error!("{}", err);
+            Err(DaaSDocError)
+        }
+    }
+}
+
+// version 1 -> 2: the metadata and tags are optional in version 1
+fn upgrade_v1(doc: &mut SerializedDoc) {
+    doc.entry("meta_data").or_insert_with(|| json!({}));
+    doc.entry("tags").or_insert_with(|| json!([]));
+}
+
+// version 2 -> 3: the lifecycle flags didn't exist in version 2
+fn upgrade_v2(doc: &mut SerializedDoc) {
+    doc.entry("deleted").or_insert_with(|| json!(false));
+    doc.entry("legal_hold").or_insert_with(|| json!(false));
+}
+
+// version 3 -> 4: the data didn't expire in version 3
+fn upgrade_v3(doc: &mut SerializedDoc) {
+    doc.entry("expires_at").or_insert(Value::Null);
+}
+
+// version 4 -> 5: the status is derived from the lifecycle flags and the process indicator
+fn upgrade_v4(doc: &mut SerializedDoc) {
+    let flag =
+        |doc: &SerializedDoc, key: &str| doc.get(key).and_then(|v| v.as_bool()).unwrap_or(false);
+    let status = match (flag(doc, "deleted"), flag(doc, "process_ind")) {
+        (true, _) => "Deleted",
+        (false, true) => "Brokered",
+        (false, false) => "Stored",
+    };
+    doc.entry("status").or_insert_with(|| json!(status));
+}
+
+// version 5 -> 6: the DaaS documents weren't sequenced in version 5
+fn upgrade_v5(doc: &mut SerializedDoc) {
+    doc.entry("sequence").or_insert(Value::Null);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_legacy_doc() -> Value {
+        json!({
+            "_id":"order~clothing~iStore~5000",
+            "_rev":"1",
+            "source_name":"iStore",
+            "source_uid":5000,
+            "category":"order",
+            "subcategory":"clothing",
+            "author":"istore_app",
+            "process_ind":false,
+            "last_updated":1553988607,
+            "data_usage_agreements":[{"agreement_name":"billing","location":"www.dua.org/billing.pdf","agreed_dtm":1553988607}],
+            "data_tracker":{"chain":[{"identifier":{"data_id":"order~clothing~iStore~5000","index":0,"timestamp":0,"actor_id":"","previous_hash":"0"},"hash":"72259503327276020952102368672148358485","nonce":5}]},
+            "data_obj":[123,125]
+        })
+    }
+
+    #[test]
+    fn test_upgrade_legacy() {
+        let doc = upgrade(get_legacy_doc()).unwrap();
+
+        assert_eq!(schema_version(&doc), CURRENT_SCHEMA_VERSION);
+        assert_eq!(doc["meta_data"], json!({}));
+        assert_eq!(doc["tags"], json!([]));
+        assert_eq!(doc["deleted"], json!(false));
+        assert_eq!(doc["legal_hold"], json!(false));
+        assert_eq!(doc["status"], json!("Stored"));
+        assert_eq!(doc["sequence"], Value::Null);
+    }
+
+    #[test]
+    fn test_upgrade_keeps_values() {
+        let mut legacy = get_legacy_doc();
+        legacy["tags"] = json!(["foo"]);
+        let doc = from_serialized(legacy.to_string().as_bytes()).unwrap();
+
+        assert_eq!(doc.tags, vec!["foo".to_string()]);
+        assert_eq!(doc._rev, Some("1".to_string()));
+    }
+
+    #[test]
+    fn test_upgrade_newer_version() {
+        let mut doc = get_legacy_doc();
+        doc["schema_version"] = json!(CURRENT_SCHEMA_VERSION + 1);
+
+        assert!(upgrade(doc).is_err());
+        assert!(upgrade(json!([])).is_err());
+    }
+}
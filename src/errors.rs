@@ -1,8 +1,10 @@
-use actix_web::ResponseError;
 use std::error;
 use std::fmt;
 
 // struct
+#[derive(Debug, Clone)]
+pub struct AuditError;
+
 #[derive(Debug, Clone)]
 pub struct BadKeyPairError;
 
@@ -12,6 +14,9 @@ pub struct BadAgreementError;
 #[derive(Debug, Clone)]
 pub struct BrokerError;
 
+#[derive(Debug, Clone)]
+pub struct CheckpointError;
+
 #[derive(Debug, Clone)]
 pub struct DaaSDocError;
 
@@ -21,18 +26,51 @@ pub struct DecryptionError;
 #[derive(Debug, Clone)]
 pub struct EncryptionError;
 
+#[derive(Debug, Clone)]
+pub struct ExpiredDataError;
+
+#[derive(Debug, Clone)]
+pub struct GrantError;
+
+#[derive(Debug, Clone)]
+pub struct InvalidSignatureError;
+
 #[derive(Debug, Clone)]
 pub struct MissingAgreementError;
 
 #[derive(Debug, Clone)]
 pub struct MissingAuthorError;
 
+#[derive(Debug, Clone)]
+pub struct RegistryError;
+
+#[derive(Debug, Clone)]
+pub struct ReplayedDataError;
+
 #[derive(Debug, Clone)]
 pub struct RetrieveError;
 
+#[derive(Debug, Clone)]
+pub struct SearchError;
+
+#[derive(Debug, Clone)]
+pub struct SecretError;
+
+#[derive(Debug, Clone)]
+pub struct SequenceError;
+
+#[derive(Debug, Clone)]
+pub struct StatusTransitionError;
+
 #[derive(Debug, Clone)]
 pub struct TamperedDataError;
 
+#[derive(Debug, Clone)]
+pub struct TlsConfigError;
+
+#[derive(Debug, Clone)]
+pub struct TransformError;
+
 #[derive(Debug, Clone)]
 pub struct UpsertError;
 
@@ -50,6 +88,9 @@ pub enum DaaSSecurityError {
     BadAgreementError,
     DecryptionError,
     EncryptionError,
+    ExpiredDataError,
+    InvalidSignatureError,
+    ReplayedDataError,
     TamperedDataError,
     MissingAgreementError,
     ValidationError,
@@ -72,6 +113,9 @@ pub mod daaserror {
         BadAgreementError,
         DecryptionError,
         EncryptionError,
+        ExpiredDataError,
+        InvalidSignatureError,
+        ReplayedDataError,
         TamperedDataError,
         MissingAgreementError,
         ValidationError,
@@ -98,6 +142,13 @@ pub mod daaserror {
 }
 
 //impl
+impl fmt::Display for AuditError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Unable to record the audit event.")
+    }
+}
+impl error::Error for AuditError {}
+
 impl fmt::Display for BadKeyPairError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "Bad key pair provided.")
@@ -119,6 +170,13 @@ impl fmt::Display for BrokerError {
 }
 impl error::Error for BrokerError {}
 
+impl fmt::Display for CheckpointError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Unable to load or save the checkpoint.")
+    }
+}
+impl error::Error for CheckpointError {}
+
 impl fmt::Display for DaaSDocError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "Unable to perform the operation on the DaaS document!")
@@ -140,6 +198,33 @@ impl fmt::Display for EncryptionError {
 }
 impl error::Error for EncryptionError {}
 
+impl fmt::Display for ExpiredDataError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "DaaS document rejected. The data has expired.")
+    }
+}
+impl error::Error for ExpiredDataError {}
+
+impl fmt::Display for GrantError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "The decryption grant is invalid, expired or has been used."
+        )
+    }
+}
+impl error::Error for GrantError {}
+
+impl fmt::Display for InvalidSignatureError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Request rejected. The signature is missing, invalid or has been replayed."
+        )
+    }
+}
+impl error::Error for InvalidSignatureError {}
+
 impl fmt::Display for MissingAgreementError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "Missing a usage agreement for the DaaS document.")
@@ -153,7 +238,23 @@ impl fmt::Display for MissingAuthorError {
     }
 }
 impl error::Error for MissingAuthorError {}
-impl ResponseError for MissingAuthorError {}
+
+impl fmt::Display for RegistryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Unable to load the category registry.")
+    }
+}
+impl error::Error for RegistryError {}
+
+impl fmt::Display for ReplayedDataError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "DaaS document rejected. The Data Tracker Chain has expired or been replayed."
+        )
+    }
+}
+impl error::Error for ReplayedDataError {}
 
 impl fmt::Display for RetrieveError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -162,6 +263,34 @@ impl fmt::Display for RetrieveError {
 }
 impl error::Error for RetrieveError {}
 
+impl fmt::Display for SearchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Unable to search the DaaS documents.")
+    }
+}
+impl error::Error for SearchError {}
+
+impl fmt::Display for SecretError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Unable to read the secret.")
+    }
+}
+impl error::Error for SecretError {}
+
+impl fmt::Display for SequenceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Unable to allocate the sequence number.")
+    }
+}
+impl error::Error for SequenceError {}
+
+impl fmt::Display for StatusTransitionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "The DaaS document can't change to the status.")
+    }
+}
+impl error::Error for StatusTransitionError {}
+
 impl fmt::Display for TamperedDataError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "DaaS document rejected. Tampered data data detected.")
@@ -169,6 +298,20 @@ impl fmt::Display for TamperedDataError {
 }
 impl error::Error for TamperedDataError {}
 
+impl fmt::Display for TlsConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Unable to configure TLS with the certificate and key.")
+    }
+}
+impl error::Error for TlsConfigError {}
+
+impl fmt::Display for TransformError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Unable to transform the payload.")
+    }
+}
+impl error::Error for TransformError {}
+
 impl fmt::Display for UpsertError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "Unable to save or update the DaaS document.")
@@ -291,4 +434,118 @@ mod tests {
             "Unable to validate the DaaS document.".to_string()
         );
     }
+
+    #[test]
+    fn test_error_13() {
+        let err = SearchError.clone();
+        assert_eq!(
+            format!("{}", err),
+            "Unable to search the DaaS documents.".to_string()
+        );
+    }
+
+    #[test]
+    fn test_error_14() {
+        let err = ExpiredDataError.clone();
+        assert_eq!(
+            format!("{}", err),
+            "DaaS document rejected. The data has expired.".to_string()
+        );
+    }
+
+    #[test]
+    fn test_error_15() {
+        let err = InvalidSignatureError.clone();
+        assert_eq!(
+            format!("{}", err),
+            "Request rejected. The signature is missing, invalid or has been replayed.".to_string()
+        );
+    }
+
+    #[test]
+    fn test_error_16() {
+        let err = ReplayedDataError.clone();
+        assert_eq!(
+            format!("{}", err),
+            "DaaS document rejected. The Data Tracker Chain has expired or been replayed."
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn test_error_17() {
+        let err = SecretError.clone();
+        assert_eq!(format!("{}", err), "Unable to read the secret.".to_string());
+    }
+
+    #[test]
+    fn test_error_18() {
+        let err = AuditError.clone();
+        assert_eq!(
+            format!("{}", err),
+            "Unable to record the audit event.".to_string()
+        );
+    }
+
+    #[test]
+    fn test_error_19() {
+        let err = TlsConfigError.clone();
+        assert_eq!(
+            format!("{}", err),
+            "Unable to configure TLS with the certificate and key.".to_string()
+        );
+    }
+
+    #[test]
+    fn test_error_20() {
+        let err = TransformError.clone();
+        assert_eq!(
+            format!("{}", err),
+            "Unable to transform the payload.".to_string()
+        );
+    }
+
+    #[test]
+    fn test_error_21() {
+        let err = RegistryError.clone();
+        assert_eq!(
+            format!("{}", err),
+            "Unable to load the category registry.".to_string()
+        );
+    }
+
+    #[test]
+    fn test_error_22() {
+        let err = StatusTransitionError.clone();
+        assert_eq!(
+            format!("{}", err),
+            "The DaaS document can't change to the status.".to_string()
+        );
+    }
+    #[test]
+    fn test_error_23() {
+        let err = GrantError.clone();
+        assert_eq!(
+            format!("{}", err),
+            "The decryption grant is invalid, expired or has been used.".to_string()
+        );
+    }
+
+    #[test]
+    fn test_error_24() {
+        let err = CheckpointError.clone();
+        assert_eq!(
+            format!("{}", err),
+            "Unable to load or save the checkpoint.".to_string()
+        );
+    }
+
+    #[test]
+    fn test_error_25() {
+        let err = SequenceError.clone();
+        assert_eq!(
+            format!("{}", err),
+            "Unable to allocate the sequence number.".to_string()
+        );
+    }
 }
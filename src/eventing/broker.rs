@@ -1,14 +1,26 @@
 use super::*;
+use crate::config::DaasConfig;
 use crate::doc::DaaSDoc;
+use crate::eventing::headers::DaaSMessageHeaders;
+use crate::eventing::partition::PartitionStrategy;
 use kafka::client::KafkaClient;
 use kafka::error::{ErrorKind, KafkaCode};
-use kafka::producer::{Producer, Record, RequiredAcks};
+use kafka::producer::{Compression, Producer, Record, RequiredAcks};
+use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::Duration;
 
+// The records waiting to be sent (topic, key, value)
+type Records = Vec<(String, String, Vec<u8>)>;
+
 pub trait DaaSKafkaProcessor {
+    /// Returns the topic of the DaaS document, (category.subcategory.source_name with the topic prefix of the
+    /// environment, see `DaasConfig`)
     fn make_topic(doc: DaaSDoc) -> String {
-        format!("{}.{}.{}", doc.category, doc.subcategory, doc.source_name)
+        DaasConfig::from_env().topic(&format!(
+            "{}.{}.{}",
+            doc.category, doc.subcategory, doc.source_name
+        ))
     }
     fn broker_message_with_client<'a, 'b>(
         client: KafkaClient,
@@ -20,10 +32,96 @@ pub trait DaaSKafkaProcessor {
         doc: &'a mut DaaSDoc,
         topic: &'b str,
     ) -> Result<(), kafka::error::ErrorKind>;
+    /// Sends the DaaS document to each of the topics, stopping at the first topic that fails,
+    /// (brokers can override it to serialize the DaaS document only once for all the topics)
+    ///
+    /// # Arguments
+    ///
+    /// * doc: &mut DaaSDoc - The DaaS document to broker.</br>
+    /// * topics: &[String] - The names of the topics.</br>
+    fn broker_message_to_topics(
+        &self,
+        doc: &mut DaaSDoc,
+        topics: &[String],
+    ) -> Result<(), kafka::error::ErrorKind> {
+        for topic in topics.iter() {
+            self.broker_message(doc, topic)?;
+        }
+        Ok(())
+    }
+}
+
+/// Represents how the DaaSKafkaBroker produces the messages
+#[derive(Debug, Clone, Copy)]
+pub struct DaaSKafkaProducerConfig {
+    /// The compression of the messages, (default: NONE)
+    pub compression: Compression,
+    /// The acknowledgements required from the Kafka brokers, (default: One)
+    pub required_acks: RequiredAcks,
+    /// How long to wait for the acknowledgements, (default: 1 second)
+    pub ack_timeout: Duration,
+    /// How long a queued message waits for more messages to be batched with, (default: 5 milliseconds)
+    pub linger: Duration,
+    /// The number of queued messages that are sent immediately as a batch, (default: 100)
+    pub batch_size: usize,
+}
+
+impl Default for DaaSKafkaProducerConfig {
+    fn default() -> Self {
+        DaaSKafkaProducerConfig {
+            compression: Compression::NONE,
+            required_acks: RequiredAcks::One,
+            ack_timeout: Duration::from_secs(1),
+            linger: Duration::from_millis(5),
+            batch_size: 100,
+        }
+    }
+}
+
+impl DaaSKafkaProducerConfig {
+    /// Sets the compression of the messages, (e.g.: Compression::SNAPPY)
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Sets the acknowledgements required from the Kafka brokers
+    pub fn with_required_acks(mut self, required_acks: RequiredAcks) -> Self {
+        self.required_acks = required_acks;
+        self
+    }
+
+    /// Sets how long to wait for the acknowledgements
+    pub fn with_ack_timeout(mut self, ack_timeout: Duration) -> Self {
+        self.ack_timeout = ack_timeout;
+        self
+    }
+
+    /// Sets how long a queued message waits for more messages to be batched with
+    pub fn with_linger(mut self, linger: Duration) -> Self {
+        self.linger = linger;
+        self
+    }
+
+    /// Sets the number of queued messages that are sent immediately as a batch
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
 }
 
+/// Represents a Kafka broker. The producer is created when the first message is sent and is reused
+/// by the later messages and by the clones of the DaaSKafkaBroker.
+#[derive(Clone)]
 pub struct DaaSKafkaBroker {
     pub brokers: Vec<String>,
+    pub config: DaaSKafkaProducerConfig,
+    /// How the key that the messages are partitioned by is chosen, (default: `PartitionStrategy::from_env()`)
+    pub partitioning: PartitionStrategy,
+    /// Determines if the messages are sent in the envelope with the headers, (default: false)
+    pub envelope: bool,
+    producer: Arc<Mutex<Option<Producer>>>,
+    queue: Arc<Mutex<Records>>,
 }
 
 impl DaaSKafkaProcessor for DaaSKafkaBroker {
@@ -69,25 +167,265 @@ impl DaaSKafkaProcessor for DaaSKafkaBroker {
         Ok(())
     }
 
+    /// Sends the DaaS document using the long-lived producer of the broker
     fn broker_message<'a, 'b>(
         &self,
         doc: &'a mut DaaSDoc,
         topic: &'b str,
     ) -> Result<(), kafka::error::ErrorKind> {
-        let client = KafkaClient::new(self.brokers.clone());
+        self.send_records(vec![(
+            topic.to_string(),
+            self.partitioning.partition_key(doc),
+            self.message_value(doc),
+        )])
+    }
+
+    /// Serializes the DaaS document once and sends the same bytes to all the topics as a single batch
+    fn broker_message_to_topics(
+        &self,
+        doc: &mut DaaSDoc,
+        topics: &[String],
+    ) -> Result<(), kafka::error::ErrorKind> {
+        let value = self.message_value(doc);
+        let key = self.partitioning.partition_key(doc);
+        let batch: Vec<Record<&str, &[u8]>> = topics
+            .iter()
+            .map(|topic| Record {
+                key: key.as_str(),
+                value: value.as_slice(),
+                topic: topic.as_str(),
+                partition: -1,
+            })
+            .collect();
 
-        DaaSKafkaBroker::broker_message_with_client(client, doc, topic)
+        self.send_batch(&batch)
     }
 }
 
 impl DaaSKafkaBroker {
     pub fn new(brokers: Vec<String>) -> DaaSKafkaBroker {
-        DaaSKafkaBroker { brokers: brokers }
+        DaaSKafkaBroker {
+            brokers,
+            config: DaaSKafkaProducerConfig::default(),
+            partitioning: PartitionStrategy::from_env(),
+            envelope: false,
+            producer: Arc::new(Mutex::new(None)),
+            queue: Arc::new(Mutex::new(Vec::new())),
+        }
     }
 
     pub fn default() -> DaaSKafkaBroker {
-        DaaSKafkaBroker {
-            brokers: vec!["localhost:9092".to_string()],
+        DaaSKafkaBroker::new(vec!["localhost:9092".to_string()])
+    }
+
+    /// Sets how the messages are produced
+    ///
+    /// # Arguments
+    ///
+    /// * config: DaaSKafkaProducerConfig - The compression, acknowledgements and batching of the messages.</br>
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// extern crate daas;
+    /// extern crate kafka;
+    ///
+    /// use daas::eventing::broker::{DaaSKafkaBroker, DaaSKafkaProducerConfig};
+    /// use kafka::producer::{Compression, RequiredAcks};
+    ///
+    /// fn main() {
+    ///     let broker = DaaSKafkaBroker::default().with_config(
+    ///         DaaSKafkaProducerConfig::default()
+    ///             .with_compression(Compression::SNAPPY)
+    ///             .with_required_acks(RequiredAcks::All)
+    ///             .with_batch_size(500),
+    ///     );
+    ///
+    ///     assert_eq!(broker.config.batch_size, 500);
+    /// }
+    /// ```
+    pub fn with_config(mut self, config: DaaSKafkaProducerConfig) -> DaaSKafkaBroker {
+        self.config = config;
+        self
+    }
+
+    /// Sets how the key that the messages are partitioned by is chosen, so the order of the DaaS documents of an
+    /// entity can be kept, (see `daas::eventing::partition`)
+    ///
+    /// # Arguments
+    ///
+    /// * partitioning: PartitionStrategy - How the partition key of a DaaS document is chosen.</br>
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// extern crate daas;
+    ///
+    /// use daas::eventing::broker::DaaSKafkaBroker;
+    /// use daas::eventing::partition::PartitionStrategy;
+    ///
+    /// fn main() {
+    ///     let broker = DaaSKafkaBroker::default()
+    ///         .with_partitioning(PartitionStrategy::PayloadField("/customer/id".to_string()));
+    ///
+    ///     println!("{:?}", broker.partitioning);
+    /// }
+    /// ```
+    pub fn with_partitioning(mut self, partitioning: PartitionStrategy) -> DaaSKafkaBroker {
+        self.partitioning = partitioning;
+        self
+    }
+
+    /// Sends the messages in the versioned envelope with the headers of the DaaS documents, so that consumers can
+    /// route and filter the messages without deserializing the DaaS documents, (see `daas::eventing::headers`).
+    /// The consumers must support the envelope, (e.g.: `DaaSProcessor`).
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// extern crate daas;
+    ///
+    /// use daas::eventing::broker::DaaSKafkaBroker;
+    ///
+    /// fn main() {
+    ///     let broker = DaaSKafkaBroker::default().with_envelope();
+    ///
+    ///     assert!(broker.envelope);
+    /// }
+    /// ```
+    pub fn with_envelope(mut self) -> DaaSKafkaBroker {
+        self.envelope = true;
+        self
+    }
+
+    // Returns the message value of the DaaS document, (which is wrapped in the envelope with the headers if configured)
+    fn message_value(&self, doc: &DaaSDoc) -> Vec<u8> {
+        match self.envelope {
+            true => DaaSMessageHeaders::from_doc(doc).to_envelope(&doc.to_bytes()),
+            false => doc.to_bytes(),
+        }
+    }
+
+    /// Sends the DaaS documents to the topic as a single batch
+    ///
+    /// # Arguments
+    ///
+    /// * docs: &mut [DaaSDoc] - The DaaS documents to broker.</br>
+    /// * topic: &str - The name of the topic.</br>
+    pub fn broker_messages(
+        &self,
+        docs: &mut [DaaSDoc],
+        topic: &str,
+    ) -> Result<(), kafka::error::ErrorKind> {
+        let records = docs
+            .iter_mut()
+            .map(|doc| {
+                (
+                    topic.to_string(),
+                    self.partitioning.partition_key(doc),
+                    self.message_value(doc),
+                )
+            })
+            .collect();
+
+        self.send_records(records)
+    }
+
+    /// Queues the DaaS document to be sent in a batch. The queue is sent once it has `batch_size` messages,
+    /// or when the `linger` of the first queued message has passed.
+    ///
+    /// # Arguments
+    ///
+    /// * doc: &mut DaaSDoc - The DaaS document to broker.</br>
+    /// * topic: &str - The name of the topic.</br>
+    pub fn queue_message(&self, doc: &mut DaaSDoc, topic: &str) {
+        let queued = {
+            let mut queue = self.queue.lock().unwrap();
+            queue.push((
+                topic.to_string(),
+                self.partitioning.partition_key(doc),
+                self.message_value(doc),
+            ));
+            queue.len()
+        };
+
+        if queued >= self.config.batch_size {
+            self.flush_and_log();
+        } else if queued == 1 {
+            let broker = self.clone();
+            thread::spawn(move || {
+                thread::sleep(broker.config.linger);
+                broker.flush_and_log();
+            });
+        }
+    }
+
+    /// Returns the number of messages that are queued to be sent
+    pub fn queued(&self) -> usize {
+        self.queue.lock().unwrap().len()
+    }
+
+    /// Sends the queued messages
+    pub fn flush(&self) -> Result<(), kafka::error::ErrorKind> {
+        let records: Records = self.queue.lock().unwrap().drain(..).collect();
+
+        match records.is_empty() {
+            true => Ok(()),
+            false => self.send_records(records),
+        }
+    }
+
+    fn flush_and_log(&self) {
+        if let Err(err) = self.flush() {
+            error!("Failed to send the queued messages. Error: {:?}", err);
+        }
+    }
+
+    // Sends the records using the long-lived producer, which is (re)created when needed.
+    // A new producer loads the metadata of the topics, so a failed send is retried up to 3 times.
+    pub(crate) fn send_records(&self, records: Records) -> Result<(), kafka::error::ErrorKind> {
+        let batch: Vec<Record<&str, &[u8]>> = records
+            .iter()
+            .map(|(topic, key, value)| Record {
+                key: key.as_str(),
+                value: value.as_slice(),
+                topic: topic.as_str(),
+                partition: -1,
+            })
+            .collect();
+
+        self.send_batch(&batch)
+    }
+
+    // Sends the batch of records, (which can share the same value)
+    fn send_batch(&self, batch: &[Record<&str, &[u8]>]) -> Result<(), kafka::error::ErrorKind> {
+        let mut producer = self.producer.lock().unwrap();
+        let mut attempt = 0;
+
+        loop {
+            attempt += 1;
+
+            if producer.is_none() {
+                *producer = Some(
+                    Producer::from_hosts(self.brokers.clone())
+                        .with_compression(self.config.compression)
+                        .with_required_acks(self.config.required_acks)
+                        .with_ack_timeout(self.config.ack_timeout)
+                        .create()?,
+                );
+            }
+
+            match producer.as_mut().unwrap().send_all(batch) {
+                Ok(_) => return Ok(()),
+                Err(err) => {
+                    *producer = None;
+                    if attempt > 2 {
+                        return Err(err.into());
+                    }
+                    debug!("Attempt #{} to send to the Kafka broker...", attempt);
+                    thread::sleep(Duration::from_secs(1));
+                }
+            }
         }
     }
 }
@@ -150,6 +488,62 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_producer_config() {
+        let config = DaaSKafkaProducerConfig::default()
+            .with_compression(Compression::GZIP)
+            .with_required_acks(RequiredAcks::All)
+            .with_ack_timeout(Duration::from_secs(5))
+            .with_linger(Duration::from_millis(20))
+            .with_batch_size(0);
+        let broker = DaaSKafkaBroker::default().with_config(config);
+
+        assert_eq!(broker.config.batch_size, 1);
+        assert_eq!(broker.config.linger, Duration::from_millis(20));
+        assert_eq!(broker.config.ack_timeout, Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_with_partitioning() {
+        let broker = DaaSKafkaBroker::default().with_partitioning(PartitionStrategy::Category);
+        let shared = broker.clone();
+
+        assert_eq!(
+            shared.partitioning.partition_key(&get_daas_doc()),
+            "order".to_string()
+        );
+    }
+
+    #[test]
+    fn test_with_envelope() {
+        let doc = get_daas_doc();
+        assert_eq!(
+            DaaSKafkaBroker::default().message_value(&doc),
+            doc.to_bytes()
+        );
+
+        let value = DaaSKafkaBroker::default()
+            .with_envelope()
+            .message_value(&doc);
+        let (headers, found) = DaaSMessageHeaders::from_envelope(&value).unwrap();
+        assert_eq!(headers.dua_summary, "billing".to_string());
+        assert_eq!(found, doc.to_bytes().as_slice());
+    }
+
+    #[test]
+    fn test_queue_message() {
+        let broker = DaaSKafkaBroker::default().with_config(
+            DaaSKafkaProducerConfig::default()
+                .with_linger(Duration::from_secs(60))
+                .with_batch_size(10),
+        );
+        let shared = broker.clone();
+
+        broker.queue_message(&mut get_daas_doc(), "testTopic");
+        broker.queue_message(&mut get_daas_doc(), "testTopic");
+        assert_eq!(shared.queued(), 2);
+    }
+
     #[test]
     fn test_send_message() {
         let my_broker = DaaSKafkaBroker::default();
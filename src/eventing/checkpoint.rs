@@ -0,0 +1,496 @@
+//! The checkpoints of the processors, (i.e.: the next offset of each partition of a topic that a consumer group processes).
+//!
+//! Kafka stores the offsets of the consumer groups itself, (see `GroupOffsetStorage::Kafka`). A `CheckpointStore`
+//! keeps the progress of the processors somewhere else, (e.g.: a local file) so that brokers that don't store
+//! offsets can be used and replay tooling can read and rewind the checkpoints. The handler of a processor is wrapped
+//! with `Checkpoints::handler()`, which skips the messages before the checkpoint and moves the checkpoint forward
+//! after each message that is processed, (so a message may be processed again if the processor stops in between).
+//!
+//! The SDK provides the `InMemoryCheckpointStore` and the `FileCheckpointStore`. Stores that are shared by the instances
+//! of a processor, (e.g.: Redis or DynamoDB) implement the `CheckpointStore` trait with their own client.
+//!
+//! # Examples
+//!
+//! ```
+//! extern crate daas;
+//!
+//! use daas::eventing::checkpoint::{Checkpoints, CheckpointStore, InMemoryCheckpointStore};
+//! use std::sync::Arc;
+//!
+//! fn main() {
+//!     let store = InMemoryCheckpointStore::new();
+//!     let checkpoints = Checkpoints::new("order-processor".to_string(), Arc::new(store.clone()));
+//!
+//!     // rewind the processor so that it processes the topic again from the 10th message
+//!     checkpoints.rewind("order.clothing", 0, 10).unwrap();
+//!
+//!     assert_eq!(store.load("order-processor", "order.clothing", 0).unwrap(), Some(10));
+//! }
+//! ```
+
+use super::*;
+use crate::doc::DaaSDoc;
+use crate::errors::daaserror::DaaSProcessingError;
+use crate::errors::CheckpointError;
+use crate::service::processor::{DaaSDocHandler, DaaSDocHandlerRef, DaaSProcessorMessage};
+use kafka::client::KafkaClient;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+// The next offset of each consumer group, topic and partition
+type Offsets = Arc<Mutex<HashMap<(String, String, i32), i64>>>;
+
+/// Represents the progress of a consumer group on a partition of a topic
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Checkpoint {
+    /// The consumer group, (e.g.: the name of the processor)
+    pub group: String,
+    /// The topic
+    pub topic: String,
+    /// The partition of the topic
+    pub partition: i32,
+    /// The offset of the next message to process
+    pub offset: i64,
+}
+
+impl Checkpoint {
+    /// Constructor
+    ///
+    /// # Arguments
+    ///
+    /// * group: String - The consumer group, (e.g.: the name of the processor).</br>
+    /// * topic: String - The topic.</br>
+    /// * partition: i32 - The partition of the topic.</br>
+    /// * offset: i64 - The offset of the next message to process.</br>
+    pub fn new(group: String, topic: String, partition: i32, offset: i64) -> Checkpoint {
+        Checkpoint {
+            group,
+            topic,
+            partition,
+            offset,
+        }
+    }
+}
+
+/// Stores the checkpoints of the consumer groups
+pub trait CheckpointStore {
+    /// Returns the offset of the next message the consumer group processes, (None if there is no checkpoint yet)
+    ///
+    /// # Arguments
+    ///
+    /// * group: &str - The consumer group.</br>
+    /// * topic: &str - The topic.</br>
+    /// * partition: i32 - The partition of the topic.</br>
+    fn load(
+        &self,
+        group: &str,
+        topic: &str,
+        partition: i32,
+    ) -> Result<Option<i64>, CheckpointError>;
+
+    /// Saves the checkpoint, replacing the previous checkpoint of the consumer group, topic and partition
+    ///
+    /// # Arguments
+    ///
+    /// * checkpoint: &Checkpoint - The checkpoint to save.</br>
+    fn save(&self, checkpoint: &Checkpoint) -> Result<(), CheckpointError>;
+
+    /// Returns all the checkpoints of the consumer group
+    ///
+    /// # Arguments
+    ///
+    /// * group: &str - The consumer group.</br>
+    fn list(&self, group: &str) -> Result<Vec<Checkpoint>, CheckpointError>;
+
+    /// Removes the checkpoint, so the consumer group processes the partition from the start again
+    ///
+    /// # Arguments
+    ///
+    /// * group: &str - The consumer group.</br>
+    /// * topic: &str - The topic.</br>
+    /// * partition: i32 - The partition of the topic.</br>
+    fn remove(&self, group: &str, topic: &str, partition: i32) -> Result<(), CheckpointError>;
+}
+
+/// A checkpoint store that keeps the checkpoints in memory, (e.g.: for tests). Clones share the same checkpoints.
+#[derive(Clone, Default)]
+pub struct InMemoryCheckpointStore {
+    checkpoints: Offsets,
+}
+
+impl InMemoryCheckpointStore {
+    /// Constructs an empty InMemoryCheckpointStore
+    pub fn new() -> InMemoryCheckpointStore {
+        InMemoryCheckpointStore::default()
+    }
+}
+
+impl CheckpointStore for InMemoryCheckpointStore {
+    fn load(
+        &self,
+        group: &str,
+        topic: &str,
+        partition: i32,
+    ) -> Result<Option<i64>, CheckpointError> {
+        Ok(self
+            .checkpoints
+            .lock()
+            .unwrap()
+            .get(&(group.to_string(), topic.to_string(), partition))
+            .copied())
+    }
+
+    fn save(&self, checkpoint: &Checkpoint) -> Result<(), CheckpointError> {
+        self.checkpoints.lock().unwrap().insert(
+            (
+                checkpoint.group.clone(),
+                checkpoint.topic.clone(),
+                checkpoint.partition,
+            ),
+            checkpoint.offset,
+        );
+        Ok(())
+    }
+
+    fn list(&self, group: &str) -> Result<Vec<Checkpoint>, CheckpointError> {
+        let mut list: Vec<Checkpoint> = self
+            .checkpoints
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|((g, _, _), _)| g == group)
+            .map(|((g, t, p), o)| Checkpoint::new(g.clone(), t.clone(), *p, *o))
+            .collect();
+        list.sort_by(|a, b| (&a.topic, a.partition).cmp(&(&b.topic, b.partition)));
+        Ok(list)
+    }
+
+    fn remove(&self, group: &str, topic: &str, partition: i32) -> Result<(), CheckpointError> {
+        self.checkpoints
+            .lock()
+            .unwrap()
+            .remove(&(group.to_string(), topic.to_string(), partition));
+        Ok(())
+    }
+}
+
+/// A checkpoint store that keeps the checkpoints of each consumer group in a JSON file in a local directory
+pub struct FileCheckpointStore {
+    /// The directory of the checkpoint files
+    pub path: String,
+    // serializes the read-modify-write of the files within the process
+    lock: Mutex<()>,
+}
+
+impl FileCheckpointStore {
+    /// Constructor
+    ///
+    /// # Arguments
+    ///
+    /// * path: String - The directory of the checkpoint files.</br>
+    pub fn new(path: String) -> FileCheckpointStore {
+        FileCheckpointStore {
+            path,
+            lock: Mutex::new(()),
+        }
+    }
+
+    fn get_file_path(&self, group: &str) -> String {
+        Path::new(&self.path)
+            .join(format!("{}.json", group))
+            .to_string_lossy()
+            .to_string()
+    }
+
+    fn read(&self, group: &str) -> Result<Vec<Checkpoint>, CheckpointError> {
+        match fs::read(self.get_file_path(group)) {
+            Ok(content) => serde_json::from_slice(&content).map_err(|e| {
+                error!("The checkpoints of {} are corrupt. Error: {}", group, e);
+                CheckpointError
+            }),
+            Err(_) => Ok(Vec::new()),
+        }
+    }
+
+    // Writes the checkpoints to a temporary file and then atomically renames it over the file of the group
+    fn write(&self, group: &str, checkpoints: &[Checkpoint]) -> Result<(), CheckpointError> {
+        let path = self.get_file_path(group);
+        let tmp = format!("{}.tmp", path);
+        let rslt = fs::create_dir_all(&self.path)
+            .and_then(|_| fs::write(&tmp, serde_json::to_string(checkpoints).unwrap()))
+            .and_then(|_| fs::rename(&tmp, &path));
+
+        rslt.map_err(|e| {
+            error!("Could not write the checkpoints {} because of {}.", path, e);
+            let _ = fs::remove_file(&tmp);
+            CheckpointError
+        })
+    }
+}
+
+impl CheckpointStore for FileCheckpointStore {
+    fn load(
+        &self,
+        group: &str,
+        topic: &str,
+        partition: i32,
+    ) -> Result<Option<i64>, CheckpointError> {
+        Ok(self
+            .read(group)?
+            .iter()
+            .find(|c| c.topic == topic && c.partition == partition)
+            .map(|c| c.offset))
+    }
+
+    fn save(&self, checkpoint: &Checkpoint) -> Result<(), CheckpointError> {
+        let _guard = self.lock.lock().unwrap();
+        let mut checkpoints = self.read(&checkpoint.group)?;
+
+        checkpoints
+            .retain(|c| !(c.topic == checkpoint.topic && c.partition == checkpoint.partition));
+        checkpoints.push(checkpoint.clone());
+        checkpoints.sort_by(|a, b| (&a.topic, a.partition).cmp(&(&b.topic, b.partition)));
+        self.write(&checkpoint.group, &checkpoints)
+    }
+
+    fn list(&self, group: &str) -> Result<Vec<Checkpoint>, CheckpointError> {
+        self.read(group)
+    }
+
+    fn remove(&self, group: &str, topic: &str, partition: i32) -> Result<(), CheckpointError> {
+        let _guard = self.lock.lock().unwrap();
+        let mut checkpoints = self.read(group)?;
+
+        checkpoints.retain(|c| !(c.topic == topic && c.partition == partition));
+        self.write(group, &checkpoints)
+    }
+}
+
+/// The checkpoint store of a consumer group that can be shared with the threads of the processors
+pub type CheckpointStoreRef = Arc<dyn CheckpointStore + Send + Sync>;
+
+/// Represents the checkpoints of a consumer group
+#[derive(Clone)]
+pub struct Checkpoints {
+    /// The consumer group, (e.g.: the name of the processor)
+    pub group: String,
+    store: CheckpointStoreRef,
+}
+
+impl Checkpoints {
+    /// Constructor
+    ///
+    /// # Arguments
+    ///
+    /// * group: String - The consumer group, (e.g.: the name of the processor).</br>
+    /// * store: CheckpointStoreRef - The store of the checkpoints.</br>
+    pub fn new(group: String, store: CheckpointStoreRef) -> Checkpoints {
+        Checkpoints { group, store }
+    }
+
+    /// Returns the checkpoints of all the topics and partitions of the consumer group
+    pub fn list(&self) -> Result<Vec<Checkpoint>, CheckpointError> {
+        self.store.list(&self.group)
+    }
+
+    /// Moves the checkpoint of the partition to the offset, so the consumer group continues (or replays) from there
+    ///
+    /// # Arguments
+    ///
+    /// * topic: &str - The topic.</br>
+    /// * partition: i32 - The partition of the topic.</br>
+    /// * offset: i64 - The offset of the next message to process.</br>
+    pub fn rewind(&self, topic: &str, partition: i32, offset: i64) -> Result<(), CheckpointError> {
+        self.store.save(&Checkpoint::new(
+            self.group.clone(),
+            topic.to_string(),
+            partition,
+            offset,
+        ))
+    }
+
+    /// Wraps the handler so that the messages before the checkpoint are skipped and the checkpoint is moved forward
+    /// after each message that the handler processes
+    ///
+    /// # Arguments
+    ///
+    /// * handler: DaaSDocHandlerRef - The handler that processes the DaaS documents.</br>
+    pub fn handler(&self, handler: DaaSDocHandlerRef) -> DaaSDocHandlerRef {
+        Arc::new(CheckpointedHandler {
+            checkpoints: self.clone(),
+            handler,
+        })
+    }
+}
+
+// Passes the messages after the checkpoint to the handler and moves the checkpoint forward
+struct CheckpointedHandler {
+    checkpoints: Checkpoints,
+    handler: DaaSDocHandlerRef,
+}
+
+impl DaaSDocHandler for CheckpointedHandler {
+    fn on_start(&self) {
+        self.handler.on_start();
+    }
+
+    fn handle(
+        &self,
+        msg: DaaSProcessorMessage,
+        client: Option<KafkaClient>,
+    ) -> Result<i32, DaaSProcessingError> {
+        let group = &self.checkpoints.group;
+        let (topic, partition, offset) = (msg.topic.to_string(), msg.partition, msg.offset);
+
+        match self.checkpoints.store.load(group, &topic, partition) {
+            Ok(Some(next)) if offset < next => {
+                debug!(
+                    "Skipping DaaS document {} before the checkpoint of {} [topic:{}, partition:{}, offset:{}].",
+                    msg.doc._id, group, topic, partition, offset
+                );
+                return Ok(0);
+            }
+            Ok(_) => {}
+            Err(_) => return Err(DaaSProcessingError::RetrieveError),
+        }
+
+        let rslt = self.handler.handle(msg, client)?;
+
+        // a checkpoint that isn't saved only means the message is processed again
+        if self
+            .checkpoints
+            .store
+            .save(&Checkpoint::new(
+                group.clone(),
+                topic,
+                partition,
+                offset + 1,
+            ))
+            .is_err()
+        {
+            warn!(
+                "Could not move the checkpoint of {} past offset {}.",
+                group, offset
+            );
+        }
+        Ok(rslt)
+    }
+
+    fn on_error(&self, doc: &DaaSDoc, err: &DaaSProcessingError) {
+        self.handler.on_error(doc, err);
+    }
+
+    fn on_shutdown(&self) {
+        self.handler.on_shutdown();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eventing::broker::DaaSKafkaProcessor;
+    use crate::eventing::memory::InMemoryBroker;
+    use crate::testing;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::mpsc::channel;
+    use std::thread;
+    use std::time::Duration;
+
+    struct CountingHandler {
+        handled: AtomicUsize,
+    }
+
+    impl DaaSDocHandler for CountingHandler {
+        fn handle(
+            &self,
+            _msg: DaaSProcessorMessage,
+            _client: Option<KafkaClient>,
+        ) -> Result<i32, DaaSProcessingError> {
+            self.handled.fetch_add(1, Ordering::SeqCst);
+            Ok(1)
+        }
+    }
+
+    #[test]
+    fn test_file_store() {
+        let path = format!("./tmp/checkpoints-{}", rand::random::<u32>());
+        let store = FileCheckpointStore::new(path.clone());
+
+        assert_eq!(store.load("genesis", "order", 0).unwrap(), None);
+        store
+            .save(&Checkpoint::new(
+                "genesis".to_string(),
+                "order".to_string(),
+                0,
+                5,
+            ))
+            .unwrap();
+        store
+            .save(&Checkpoint::new(
+                "genesis".to_string(),
+                "order".to_string(),
+                1,
+                2,
+            ))
+            .unwrap();
+        store
+            .save(&Checkpoint::new(
+                "genesis".to_string(),
+                "order".to_string(),
+                0,
+                7,
+            ))
+            .unwrap();
+
+        let reopened = FileCheckpointStore::new(path.clone());
+        assert_eq!(reopened.load("genesis", "order", 0).unwrap(), Some(7));
+        assert_eq!(reopened.list("genesis").unwrap().len(), 2);
+        assert!(reopened.list("other").unwrap().is_empty());
+
+        reopened.remove("genesis", "order", 1).unwrap();
+        assert_eq!(store.load("genesis", "order", 1).unwrap(), None);
+
+        let _ = fs::remove_dir_all(path);
+    }
+
+    #[test]
+    fn test_checkpointed_handler() {
+        let broker = InMemoryBroker::new();
+        let store = InMemoryCheckpointStore::new();
+        let checkpoints = Checkpoints::new("order-processor".to_string(), Arc::new(store.clone()));
+        let counter = Arc::new(CountingHandler {
+            handled: AtomicUsize::new(0),
+        });
+
+        for _ in 0..3 {
+            broker
+                .broker_message(&mut testing::get_default_daas_doc(), "order")
+                .unwrap();
+        }
+        // the first message has already been processed
+        checkpoints.rewind("order", 0, 1).unwrap();
+
+        let (tx, rx) = channel();
+        let listener = broker.clone();
+        let handler = checkpoints.handler(counter.clone());
+        let worker =
+            thread::spawn(move || listener.start_listening_with_handler("order", &rx, handler));
+        thread::sleep(Duration::from_millis(300));
+        tx.send(true).unwrap();
+        worker.join().unwrap();
+
+        assert_eq!(counter.handled.load(Ordering::SeqCst), 2);
+        assert_eq!(store.load("order-processor", "order", 0).unwrap(), Some(3));
+        assert_eq!(
+            checkpoints.list().unwrap(),
+            vec![Checkpoint::new(
+                "order-processor".to_string(),
+                "order".to_string(),
+                0,
+                3
+            )]
+        );
+    }
+}
@@ -0,0 +1,185 @@
+//! Lightweight events about changes to stored DaaS documents, (e.g.: so downstream indexes stay in sync).
+//!
+//! Unlike the brokered DaaS documents, an event only carries what has changed and never the data object.
+//! Besides the doc-updated events, the lifecycle of the stored DaaS documents (upserted, processed, deleted and
+//! purged because of the retention policy) can be published to a control topic, (see `NotifyingStorage`), so that
+//! external systems, (e.g.: catalogs or billing) can track the activity without polling the storage.
+
+use super::*;
+use crate::doc::{DaaSDoc, DocDiff};
+use crate::eventing::broker::DaaSKafkaBroker;
+use std::collections::BTreeMap;
+use std::time::SystemTime;
+
+/// The type of the event that is published when the tags or metadata of a stored DaaS document change
+pub const DOC_UPDATED: &str = "doc-updated";
+/// The type of the event that is published when a revision of a DaaS document is saved
+pub const DOC_UPSERTED: &str = "doc-upserted";
+/// The type of the event that is published when a DaaS document is marked as processed
+pub const DOC_PROCESSED: &str = "doc-processed";
+/// The type of the event that is published when a DaaS document is (soft) deleted
+pub const DOC_DELETED: &str = "doc-deleted";
+/// The type of the event that is published when a DaaS document is purged because its data has expired
+pub const RETENTION_PURGED: &str = "retention-purged";
+/// The default topic of the lifecycle events
+pub const CONTROL_TOPIC: &str = "daas-control";
+
+/// Represents a change to a stored DaaS document
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DaaSDocEvent {
+    /// The type of event, (e.g.: doc-updated)
+    pub event_type: String,
+    /// The unique identifier of the DaaS document
+    pub doc_id: String,
+    /// The revision of the DaaS document after the change
+    pub doc_rev: Option<String>,
+    /// The tags that were added
+    pub tags_added: Vec<String>,
+    /// The tags that were removed
+    pub tags_removed: Vec<String>,
+    /// The metadata entries that were added or changed (key, new value)
+    pub meta_set: BTreeMap<String, String>,
+    /// The keys of the metadata entries that were removed
+    pub meta_removed: Vec<String>,
+    /// The Unix Epoch time when the event was created
+    pub timestamp: u64,
+}
+
+impl DaaSDocEvent {
+    /// Constructs a doc-updated event from the differences between the previous and the saved revision,
+    /// or None if the tags and metadata haven't changed
+    ///
+    /// # Arguments
+    ///
+    /// * doc: &DaaSDoc - The saved revision of the DaaS document.</br>
+    /// * diff: &DocDiff - The differences between the previous and the saved revision.</br>
+    pub fn doc_updated(doc: &DaaSDoc, diff: &DocDiff) -> Option<DaaSDocEvent> {
+        let mut meta_set = diff.meta_added.clone();
+        for (key, change) in diff.meta_changed.iter() {
+            meta_set.insert(
+                key.clone(),
+                change.new.as_str().unwrap_or_default().to_string(),
+            );
+        }
+
+        let event = DaaSDocEvent {
+            event_type: DOC_UPDATED.to_string(),
+            doc_id: doc._id.clone(),
+            doc_rev: doc._rev.clone(),
+            tags_added: diff.tags_added.clone(),
+            tags_removed: diff.tags_removed.clone(),
+            meta_set,
+            meta_removed: diff.meta_removed.keys().cloned().collect(),
+            timestamp: get_unix_now!(),
+        };
+
+        match event.is_empty() {
+            true => None,
+            false => Some(event),
+        }
+    }
+
+    /// Constructs a lifecycle event of the DaaS document, (e.g.: doc-upserted), which doesn't carry any changes
+    ///
+    /// # Arguments
+    ///
+    /// * event_type: &str - The type of event, (e.g.: doc-processed).</br>
+    /// * doc_id: String - The unique identifier of the DaaS document.</br>
+    /// * doc_rev: Option<String> - The revision of the DaaS document, (None if it has been purged).</br>
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// extern crate daas;
+    ///
+    /// use daas::eventing::event::{DaaSDocEvent, DOC_UPSERTED};
+    ///
+    /// fn main() {
+    ///     let event = DaaSDocEvent::lifecycle(DOC_UPSERTED, "order~clothing~iStore~5000".to_string(), Some("1".to_string()));
+    ///
+    ///     assert_eq!(event.event_type, "doc-upserted".to_string());
+    ///     assert!(event.is_empty());
+    /// }
+    /// ```
+    pub fn lifecycle(event_type: &str, doc_id: String, doc_rev: Option<String>) -> DaaSDocEvent {
+        DaaSDocEvent {
+            event_type: event_type.to_string(),
+            doc_id,
+            doc_rev,
+            tags_added: Vec::new(),
+            tags_removed: Vec::new(),
+            meta_set: BTreeMap::new(),
+            meta_removed: Vec::new(),
+            timestamp: get_unix_now!(),
+        }
+    }
+
+    /// Determines if the event doesn't have any changes
+    pub fn is_empty(&self) -> bool {
+        self.tags_added.is_empty()
+            && self.tags_removed.is_empty()
+            && self.meta_set.is_empty()
+            && self.meta_removed.is_empty()
+    }
+
+    /// Serializes the DaaSDocEvent object
+    pub fn serialize(&self) -> String {
+        serde_json::to_string(&self).unwrap()
+    }
+}
+
+/// Trait for the brokers that publish DaaSDocEvents
+pub trait DaaSEventPublisher {
+    /// Publishes the event to the topic
+    fn publish_event(
+        &self,
+        event: &DaaSDocEvent,
+        topic: &str,
+    ) -> Result<(), kafka::error::ErrorKind>;
+}
+
+impl DaaSEventPublisher for DaaSKafkaBroker {
+    fn publish_event(
+        &self,
+        event: &DaaSDocEvent,
+        topic: &str,
+    ) -> Result<(), kafka::error::ErrorKind> {
+        self.send_records(vec![(
+            topic.to_string(),
+            event.doc_id.clone(),
+            event.serialize().into_bytes(),
+        )])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn test_doc_updated() {
+        let mut before = testing::get_default_daas_doc();
+        before.add_tag("foo".to_string());
+        before.add_meta("status".to_string(), "new".to_string());
+        before.add_meta("old".to_string(), "x".to_string());
+        let mut after = before.clone();
+        after.remove_tag("foo".to_string());
+        after.add_tag("bar".to_string());
+        after.add_meta("status".to_string(), "shipped".to_string());
+        after.remove_meta("old".to_string());
+
+        let event = DaaSDocEvent::doc_updated(&after, &before.diff(&after)).unwrap();
+        assert_eq!(event.event_type, DOC_UPDATED.to_string());
+        assert_eq!(event.tags_added, vec!["bar".to_string()]);
+        assert_eq!(event.tags_removed, vec!["foo".to_string()]);
+        assert_eq!(event.meta_set.get("status"), Some(&"shipped".to_string()));
+        assert_eq!(event.meta_removed, vec!["old".to_string()]);
+    }
+
+    #[test]
+    fn test_doc_updated_no_changes() {
+        let doc = testing::get_default_daas_doc();
+        assert!(DaaSDocEvent::doc_updated(&doc, &doc.diff(&doc)).is_none());
+    }
+}
@@ -0,0 +1,267 @@
+//! The headers of a brokered DaaS document, so that consumers can route and filter the messages without
+//! deserializing the whole DaaS document.
+//!
+//! | Header         | Value                                                        |
+//! |----------------|--------------------------------------------------------------|
+//! | content-type   | The media type of the message value, (application/json)      |
+//! | daas-tenant    | The `tenant` metadata of the DaaS document, (if there is one) |
+//! | traceparent    | The `traceparent` metadata of the DaaS document, (if there is one) |
+//! | daas-schema-id | The schema version of the DaaS document                      |
+//! | daas-dua       | The comma separated names of the Data Usage Agreements       |
+//!
+//! The Kafka protocol supported by the `kafka` crate predates record headers, so the `DaaSKafkaBroker` can't attach them
+//! to the Kafka records. Instead, the broker can send the headers in a versioned envelope, (see
+//! `DaaSKafkaBroker::with_envelope()`), which is the message value of the Kafka record:
+//!
+//! ```text
+//! {"daas-envelope":"1","content-type":"application/json","daas-schema-id":"1","daas-dua":"billing"}\n
+//! {"_id":"order~clothing~iStore~15000", ... }
+//! ```
+//!
+//! The first line is a JSON object of the headers, (including the `daas-envelope` version) and the rest is the
+//! serialized DaaS document, so consumers that aren't using the SDK can route and filter the messages by reading only
+//! the first line. The names of the headers are stable within an envelope version; a header is only removed or
+//! changed in a new envelope version, while new optional headers can be added. Consumers should ignore the headers
+//! they don't know and reject an envelope version that is newer than they support.
+//!
+//! The processors read the headers from the envelope, and derive them from the DaaS document when the message value
+//! is a plain DaaS document, (the default). `to_pairs()` provides the headers for clients that support record headers.
+
+use crate::doc::DaaSDoc;
+use serde_json::{Map, Value};
+
+/// The version of the envelope that the headers are sent in
+pub const ENVELOPE_VERSION: u32 = 1;
+/// The name of the header that has the version of the envelope
+pub const HEADER_ENVELOPE: &str = "daas-envelope";
+/// The media type of a brokered DaaS document
+pub const CONTENT_TYPE: &str = "application/json";
+/// The name of the header that has the media type
+pub const HEADER_CONTENT_TYPE: &str = "content-type";
+/// The name of the header that has the tenant
+pub const HEADER_TENANT: &str = "daas-tenant";
+/// The name of the header that has the trace context, (see W3C Trace Context)
+pub const HEADER_TRACE_CONTEXT: &str = "traceparent";
+/// The name of the header that has the schema version
+pub const HEADER_SCHEMA_ID: &str = "daas-schema-id";
+/// The name of the header that has the names of the Data Usage Agreements
+pub const HEADER_DUA: &str = "daas-dua";
+/// The metadata key of the tenant of a DaaS document
+pub const META_TENANT: &str = "tenant";
+/// The metadata key of the trace context of a DaaS document
+pub const META_TRACE_CONTEXT: &str = "traceparent";
+
+/// Represents the headers of a brokered DaaS document
+#[derive(Debug, Clone, PartialEq)]
+pub struct DaaSMessageHeaders {
+    /// The media type of the message value
+    pub content_type: String,
+    /// The tenant that the DaaS document belongs to
+    pub tenant: Option<String>,
+    /// The trace context of the request that created the DaaS document
+    pub trace_context: Option<String>,
+    /// The schema version of the DaaS document
+    pub schema_id: u32,
+    /// The comma separated names of the Data Usage Agreements
+    pub dua_summary: String,
+}
+
+impl DaaSMessageHeaders {
+    /// Constructs the headers of the DaaS document
+    ///
+    /// # Arguments
+    ///
+    /// * doc: &DaaSDoc - The DaaS document that is brokered.</br>
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// extern crate daas;
+    ///
+    /// use daas::eventing::headers::DaaSMessageHeaders;
+    /// use daas::testing;
+    ///
+    /// fn main() {
+    ///     let mut doc = testing::get_default_daas_doc();
+    ///     doc.add_meta("tenant".to_string(), "acme".to_string());
+    ///     let headers = DaaSMessageHeaders::from_doc(&doc);
+    ///
+    ///     assert_eq!(headers.tenant, Some("acme".to_string()));
+    ///     assert_eq!(headers.dua_summary, "billing".to_string());
+    /// }
+    /// ```
+    pub fn from_doc(doc: &DaaSDoc) -> DaaSMessageHeaders {
+        DaaSMessageHeaders {
+            content_type: CONTENT_TYPE.to_string(),
+            tenant: doc.meta_data.get(META_TENANT).cloned(),
+            trace_context: doc.meta_data.get(META_TRACE_CONTEXT).cloned(),
+            schema_id: doc.schema_version,
+            dua_summary: doc
+                .data_usage_agreements
+                .iter()
+                .map(|dua| dua.agreement_name.clone())
+                .collect::<Vec<String>>()
+                .join(","),
+        }
+    }
+
+    /// Returns the headers as (name, value) pairs, skipping the headers that don't have a value
+    pub fn to_pairs(&self) -> Vec<(String, String)> {
+        let mut pairs = vec![(HEADER_CONTENT_TYPE.to_string(), self.content_type.clone())];
+
+        if let Some(tenant) = &self.tenant {
+            pairs.push((HEADER_TENANT.to_string(), tenant.clone()));
+        }
+        if let Some(trace) = &self.trace_context {
+            pairs.push((HEADER_TRACE_CONTEXT.to_string(), trace.clone()));
+        }
+        pairs.push((HEADER_SCHEMA_ID.to_string(), self.schema_id.to_string()));
+        pairs.push((HEADER_DUA.to_string(), self.dua_summary.clone()));
+
+        pairs
+    }
+
+    /// Returns the envelope of the message value, which is the headers as a JSON object on the first line followed by
+    /// the message value, (see `daas::eventing::headers`)
+    ///
+    /// # Arguments
+    ///
+    /// * value: &[u8] - The message value, (e.g.: the serialized DaaS document).</br>
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// extern crate daas;
+    ///
+    /// use daas::eventing::headers::DaaSMessageHeaders;
+    /// use daas::testing;
+    ///
+    /// fn main() {
+    ///     let doc = testing::get_default_daas_doc();
+    ///     let headers = DaaSMessageHeaders::from_doc(&doc);
+    ///     let envelope = headers.to_envelope(&doc.to_bytes());
+    ///
+    ///     assert_eq!(
+    ///         DaaSMessageHeaders::from_envelope(&envelope),
+    ///         Some((headers, doc.to_bytes().as_slice()))
+    ///     );
+    /// }
+    /// ```
+    pub fn to_envelope(&self, value: &[u8]) -> Vec<u8> {
+        let mut headers = Map::new();
+        headers.insert(
+            HEADER_ENVELOPE.to_string(),
+            Value::String(ENVELOPE_VERSION.to_string()),
+        );
+        for (name, val) in self.to_pairs() {
+            headers.insert(name, Value::String(val));
+        }
+
+        let mut envelope = serde_json::to_vec(&headers).unwrap();
+        envelope.push(b'\n');
+        envelope.extend_from_slice(value);
+        envelope
+    }
+
+    /// Returns the headers and the message value of an envelope, (see `to_envelope()`). Returns None if the message
+    /// value isn't an envelope, (e.g.: a plain DaaS document) or the envelope version isn't supported.
+    ///
+    /// # Arguments
+    ///
+    /// * envelope: &[u8] - The message value of the Kafka record.</br>
+    pub fn from_envelope(envelope: &[u8]) -> Option<(DaaSMessageHeaders, &[u8])> {
+        let end = envelope.iter().position(|b| *b == b'\n')?;
+        let headers: Map<String, Value> = serde_json::from_slice(&envelope[..end]).ok()?;
+        let version: u32 = headers.get(HEADER_ENVELOPE)?.as_str()?.parse().ok()?;
+
+        if version > ENVELOPE_VERSION {
+            return None;
+        }
+
+        let pairs: Vec<(String, String)> = headers
+            .iter()
+            .filter_map(|(n, v)| v.as_str().map(|v| (n.clone(), v.to_string())))
+            .collect();
+
+        DaaSMessageHeaders::from_pairs(&pairs).map(|h| (h, &envelope[end + 1..]))
+    }
+
+    /// Constructs the headers from (name, value) pairs, (e.g.: the headers of a record read by another client).
+    /// Returns None if the content-type or schema id headers are missing.
+    ///
+    /// # Arguments
+    ///
+    /// * pairs: &[(String, String)] - The headers of the message.</br>
+    pub fn from_pairs(pairs: &[(String, String)]) -> Option<DaaSMessageHeaders> {
+        let get = |name: &str| {
+            pairs
+                .iter()
+                .find(|(n, _)| n.as_str() == name)
+                .map(|(_, v)| v.clone())
+        };
+
+        Some(DaaSMessageHeaders {
+            content_type: get(HEADER_CONTENT_TYPE)?,
+            tenant: get(HEADER_TENANT),
+            trace_context: get(HEADER_TRACE_CONTEXT),
+            schema_id: get(HEADER_SCHEMA_ID)?.parse().ok()?,
+            dua_summary: get(HEADER_DUA).unwrap_or_default(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::doc::migrate::CURRENT_SCHEMA_VERSION;
+    use crate::testing;
+
+    #[test]
+    fn test_from_doc() {
+        let mut doc = testing::get_default_daas_doc();
+        doc.add_meta(META_TRACE_CONTEXT.to_string(), "00-abc-def-01".to_string());
+        let headers = DaaSMessageHeaders::from_doc(&doc);
+
+        assert_eq!(headers.content_type, CONTENT_TYPE.to_string());
+        assert_eq!(headers.tenant, None);
+        assert_eq!(headers.trace_context, Some("00-abc-def-01".to_string()));
+        assert_eq!(headers.schema_id, CURRENT_SCHEMA_VERSION);
+    }
+
+    #[test]
+    fn test_pairs() {
+        let mut doc = testing::get_default_daas_doc();
+        doc.add_meta(META_TENANT.to_string(), "acme".to_string());
+        let headers = DaaSMessageHeaders::from_doc(&doc);
+        let pairs = headers.to_pairs();
+
+        assert_eq!(pairs.len(), 4);
+        assert_eq!(DaaSMessageHeaders::from_pairs(&pairs), Some(headers));
+        assert_eq!(DaaSMessageHeaders::from_pairs(&pairs[1..]), None);
+    }
+
+    #[test]
+    fn test_envelope() {
+        let doc = testing::get_default_daas_doc();
+        let headers = DaaSMessageHeaders::from_doc(&doc);
+        let value = doc.to_bytes();
+        let envelope = headers.to_envelope(&value);
+
+        let (found, found_value) = DaaSMessageHeaders::from_envelope(&envelope).unwrap();
+        assert_eq!(found, headers);
+        assert_eq!(found_value, value.as_slice());
+        // the DaaS document can be read from the envelope
+        assert_eq!(DaaSDoc::from_serialized(found_value).unwrap()._id, doc._id);
+
+        // a plain DaaS document isn't an envelope
+        assert_eq!(DaaSMessageHeaders::from_envelope(&value), None);
+
+        // a newer envelope version isn't supported
+        let newer = String::from_utf8(envelope).unwrap().replacen(
+            r#""daas-envelope":"1""#,
+            r#""daas-envelope":"2""#,
+            1,
+        );
+        assert_eq!(DaaSMessageHeaders::from_envelope(newer.as_bytes()), None);
+    }
+}
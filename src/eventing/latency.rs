@@ -0,0 +1,379 @@
+//! Measures how fresh the data is when it reaches the processors, (e.g.: to monitor the SLOs on data freshness).
+//!
+//! The listener stamps the time that a DaaS document was ingested and brokered in its metadata, and a processor
+//! handler that is wrapped with `LatencyMetrics::handler()` stamps the time that the DaaS document was consumed.
+//! The `LatencyMetrics` keep a histogram of the publish latency (ingested to brokered) and the end-to-end latency
+//! (ingested to consumed) of each category, which can be rendered in the Prometheus text format.
+//!
+//! # Examples
+//!
+//! ```
+//! extern crate daas;
+//!
+//! use daas::eventing::latency::{self, LatencyMetrics, END_TO_END_STAGE, CONSUMED_AT_META, INGESTED_AT_META};
+//! use daas::testing;
+//!
+//! fn main() {
+//!     let mut doc = testing::get_default_daas_doc();
+//!     latency::stamp(&mut doc, INGESTED_AT_META);
+//!     latency::stamp(&mut doc, CONSUMED_AT_META);
+//!
+//!     let metrics = LatencyMetrics::new();
+//!     metrics.record(&doc);
+//!
+//!     assert_eq!(metrics.get_histogram(END_TO_END_STAGE, "order").unwrap().count, 1);
+//! }
+//! ```
+
+use super::*;
+use crate::doc::DaaSDoc;
+use crate::errors::daaserror::DaaSProcessingError;
+use crate::service::processor::{DaaSDocHandler, DaaSDocHandlerRef, DaaSProcessorMessage};
+use kafka::client::KafkaClient;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+/// The key of the metadata that has the time (Unix Epoch milliseconds) the DaaS document was ingested
+pub const INGESTED_AT_META: &str = "ingested-at";
+/// The key of the metadata that has the time (Unix Epoch milliseconds) the DaaS document was brokered
+pub const BROKERED_AT_META: &str = "brokered-at";
+/// The key of the metadata that has the time (Unix Epoch milliseconds) the DaaS document was consumed
+pub const CONSUMED_AT_META: &str = "consumed-at";
+/// The stage from the ingestion to the brokering of the DaaS documents
+pub const PUBLISH_STAGE: &str = "publish";
+/// The stage from the ingestion to the consumption of the DaaS documents
+pub const END_TO_END_STAGE: &str = "end-to-end";
+/// The default upper bounds (milliseconds) of the buckets of the histograms
+pub const DEFAULT_BOUNDS: [u64; 11] =
+    [10, 50, 100, 250, 500, 1000, 2500, 5000, 10000, 30000, 60000];
+
+/// Returns the current time in Unix Epoch milliseconds
+pub fn now_millis() -> u64 {
+    match SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
+        Ok(n) => n.as_millis() as u64,
+        Err(_) => panic!("SystemTime before UNIX EPOCH!"),
+    }
+}
+
+/// Sets the metadata of the DaaS document to the current time in Unix Epoch milliseconds
+///
+/// # Arguments
+///
+/// * doc: &mut DaaSDoc - The DaaS document to stamp.</br>
+/// * key: &str - The key of the metadata, (e.g.: ingested-at).</br>
+pub fn stamp(doc: &mut DaaSDoc, key: &str) {
+    doc.add_meta(key.to_string(), now_millis().to_string());
+}
+
+/// Returns the time (Unix Epoch milliseconds) that is stamped in the metadata of the DaaS document, if there is one
+///
+/// # Arguments
+///
+/// * doc: &DaaSDoc - The stamped DaaS document.</br>
+/// * key: &str - The key of the metadata, (e.g.: ingested-at).</br>
+pub fn stamped_at(doc: &DaaSDoc, key: &str) -> Option<u64> {
+    doc.meta_data.get(key).and_then(|v| v.parse::<u64>().ok())
+}
+
+/// Represents the distribution of the latencies (milliseconds) of a stage
+#[derive(Debug, Clone, PartialEq)]
+pub struct LatencyHistogram {
+    /// The upper bounds of the buckets
+    pub bounds: Vec<u64>,
+    /// The number of latencies in each bucket, (the last bucket has the latencies above the upper bounds)
+    pub buckets: Vec<u64>,
+    /// The number of latencies
+    pub count: u64,
+    /// The sum of the latencies
+    pub sum: u64,
+    /// The highest latency
+    pub max: u64,
+}
+
+impl LatencyHistogram {
+    /// Constructs an empty LatencyHistogram
+    ///
+    /// # Arguments
+    ///
+    /// * bounds: Vec<u64> - The upper bounds (milliseconds) of the buckets.</br>
+    pub fn new(mut bounds: Vec<u64>) -> LatencyHistogram {
+        bounds.sort_unstable();
+        bounds.dedup();
+
+        LatencyHistogram {
+            buckets: vec![0; bounds.len() + 1],
+            bounds,
+            count: 0,
+            sum: 0,
+            max: 0,
+        }
+    }
+
+    /// Adds the latency to the histogram
+    ///
+    /// # Arguments
+    ///
+    /// * millis: u64 - The latency in milliseconds.</br>
+    pub fn record(&mut self, millis: u64) {
+        let idx = self
+            .bounds
+            .iter()
+            .position(|b| millis <= *b)
+            .unwrap_or(self.bounds.len());
+
+        self.buckets[idx] += 1;
+        self.count += 1;
+        self.sum += millis;
+        self.max = self.max.max(millis);
+    }
+
+    /// Returns the average latency
+    pub fn mean(&self) -> f64 {
+        match self.count {
+            0 => 0.0,
+            n => self.sum as f64 / n as f64,
+        }
+    }
+
+    /// Returns the upper bound of the bucket of the percentile, (the highest latency if it is above the upper bounds)
+    ///
+    /// # Arguments
+    ///
+    /// * percentile: f64 - The percentile, (e.g.: 99.0).</br>
+    pub fn percentile(&self, percentile: f64) -> Option<u64> {
+        if self.count == 0 {
+            return None;
+        }
+
+        let rank = ((percentile / 100.0) * self.count as f64).ceil().max(1.0) as u64;
+        let mut seen = 0;
+
+        for (idx, n) in self.buckets.iter().enumerate() {
+            seen += n;
+            if seen >= rank {
+                return Some(self.bounds.get(idx).copied().unwrap_or(self.max));
+            }
+        }
+
+        Some(self.max)
+    }
+}
+
+/// Keeps a histogram of the publish and end-to-end latencies of each category of the DaaS documents.
+/// Clones of the `LatencyMetrics` share the same histograms.
+#[derive(Clone)]
+pub struct LatencyMetrics {
+    bounds: Vec<u64>,
+    histograms: Arc<Mutex<BTreeMap<(String, String), LatencyHistogram>>>,
+}
+
+impl Default for LatencyMetrics {
+    fn default() -> Self {
+        LatencyMetrics::new()
+    }
+}
+
+impl LatencyMetrics {
+    /// Constructs a LatencyMetrics that uses the default upper bounds of the buckets
+    pub fn new() -> LatencyMetrics {
+        LatencyMetrics {
+            bounds: DEFAULT_BOUNDS.to_vec(),
+            histograms: Arc::new(Mutex::new(BTreeMap::new())),
+        }
+    }
+
+    /// Sets the upper bounds (milliseconds) of the buckets of the histograms
+    ///
+    /// # Arguments
+    ///
+    /// * bounds: Vec<u64> - The upper bounds of the buckets, (e.g.: the SLO of the data freshness).</br>
+    pub fn with_bounds(mut self, bounds: Vec<u64>) -> LatencyMetrics {
+        self.bounds = bounds;
+        self
+    }
+
+    /// Records the latencies of the DaaS document that are stamped in its metadata.
+    /// The end-to-end latency is only recorded once the DaaS document has been consumed.
+    ///
+    /// # Arguments
+    ///
+    /// * doc: &DaaSDoc - The stamped DaaS document.</br>
+    pub fn record(&self, doc: &DaaSDoc) {
+        let ingested = match stamped_at(doc, INGESTED_AT_META) {
+            Some(t) => t,
+            None => {
+                debug!("DaaS document {} has no ingestion time.", doc._id);
+                return;
+            }
+        };
+
+        for (stage, key) in [
+            (PUBLISH_STAGE, BROKERED_AT_META),
+            (END_TO_END_STAGE, CONSUMED_AT_META),
+        ]
+        .iter()
+        {
+            if let Some(t) = stamped_at(doc, key) {
+                self.record_latency(stage, &doc.category, t.saturating_sub(ingested));
+            }
+        }
+    }
+
+    /// Adds the latency to the histogram of the stage and category
+    ///
+    /// # Arguments
+    ///
+    /// * stage: &str - The stage, (e.g.: end-to-end).</br>
+    /// * category: &str - The category of the DaaS document.</br>
+    /// * millis: u64 - The latency in milliseconds.</br>
+    pub fn record_latency(&self, stage: &str, category: &str, millis: u64) {
+        self.histograms
+            .lock()
+            .unwrap()
+            .entry((stage.to_string(), category.to_string()))
+            .or_insert_with(|| LatencyHistogram::new(self.bounds.clone()))
+            .record(millis);
+    }
+
+    /// Returns the histogram of the stage and category, if a latency has been recorded
+    ///
+    /// # Arguments
+    ///
+    /// * stage: &str - The stage, (e.g.: end-to-end).</br>
+    /// * category: &str - The category of the DaaS documents.</br>
+    pub fn get_histogram(&self, stage: &str, category: &str) -> Option<LatencyHistogram> {
+        self.histograms
+            .lock()
+            .unwrap()
+            .get(&(stage.to_string(), category.to_string()))
+            .cloned()
+    }
+
+    /// Renders the histograms in the Prometheus text format, (e.g.: to be served by a metrics endpoint)
+    pub fn render(&self) -> String {
+        let mut text = String::from(
+            "# HELP daas_latency_milliseconds The latency of the DaaS documents by stage and category.\n# TYPE daas_latency_milliseconds histogram\n",
+        );
+
+        for ((stage, category), histogram) in self.histograms.lock().unwrap().iter() {
+            let labels = format!("stage=\"{}\",category=\"{}\"", stage, category);
+            let mut cumulative = 0;
+
+            for (idx, n) in histogram.buckets.iter().enumerate() {
+                cumulative += n;
+                let le = match histogram.bounds.get(idx) {
+                    Some(b) => b.to_string(),
+                    None => "+Inf".to_string(),
+                };
+                text.push_str(&format!(
+                    "daas_latency_milliseconds_bucket{{{},le=\"{}\"}} {}\n",
+                    labels, le, cumulative
+                ));
+            }
+            text.push_str(&format!(
+                "daas_latency_milliseconds_sum{{{}}} {}\n",
+                labels, histogram.sum
+            ));
+            text.push_str(&format!(
+                "daas_latency_milliseconds_count{{{}}} {}\n",
+                labels, histogram.count
+            ));
+        }
+
+        text
+    }
+
+    /// Wraps the handler so that the DaaS documents are stamped with the time they were consumed and their
+    /// latencies are recorded before they are handled
+    ///
+    /// # Arguments
+    ///
+    /// * handler: DaaSDocHandlerRef - The handler that processes the DaaS documents.</br>
+    pub fn handler(&self, handler: DaaSDocHandlerRef) -> DaaSDocHandlerRef {
+        Arc::new(LatencyHandler {
+            metrics: self.clone(),
+            handler,
+        })
+    }
+}
+
+// Stamps the time the DaaS documents are consumed and records their latencies
+struct LatencyHandler {
+    metrics: LatencyMetrics,
+    handler: DaaSDocHandlerRef,
+}
+
+impl DaaSDocHandler for LatencyHandler {
+    fn on_start(&self) {
+        self.handler.on_start();
+    }
+
+    fn handle(
+        &self,
+        mut msg: DaaSProcessorMessage,
+        client: Option<KafkaClient>,
+    ) -> Result<i32, DaaSProcessingError> {
+        stamp(&mut msg.doc, CONSUMED_AT_META);
+        self.metrics.record(&msg.doc);
+        self.handler.handle(msg, client)
+    }
+
+    fn on_error(&self, doc: &DaaSDoc, err: &DaaSProcessingError) {
+        self.handler.on_error(doc, err);
+    }
+
+    fn on_shutdown(&self) {
+        self.handler.on_shutdown();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn test_histogram() {
+        let mut histogram = LatencyHistogram::new(vec![100, 10, 50]);
+        for millis in [5, 20, 20, 80, 500].iter() {
+            histogram.record(*millis);
+        }
+
+        assert_eq!(histogram.bounds, vec![10, 50, 100]);
+        assert_eq!(histogram.buckets, vec![1, 2, 1, 1]);
+        assert_eq!(histogram.mean(), 125.0);
+        assert_eq!(histogram.percentile(50.0), Some(50));
+        assert_eq!(histogram.percentile(99.0), Some(500));
+        assert_eq!(LatencyHistogram::new(vec![10]).percentile(50.0), None);
+    }
+
+    #[test]
+    fn test_record() {
+        let metrics = LatencyMetrics::new().with_bounds(vec![1000]);
+        let mut doc = testing::get_default_daas_doc();
+        metrics.record(&doc);
+        assert!(metrics.get_histogram(PUBLISH_STAGE, "order").is_none());
+
+        doc.add_meta(INGESTED_AT_META.to_string(), "1000".to_string());
+        doc.add_meta(BROKERED_AT_META.to_string(), "1200".to_string());
+        metrics.record(&doc);
+        doc.add_meta(CONSUMED_AT_META.to_string(), "3000".to_string());
+        metrics.record(&doc);
+
+        assert_eq!(
+            metrics.get_histogram(PUBLISH_STAGE, "order").unwrap().count,
+            2
+        );
+        let end_to_end = metrics.get_histogram(END_TO_END_STAGE, "order").unwrap();
+        assert_eq!(end_to_end.buckets, vec![0, 1]);
+        assert_eq!(end_to_end.sum, 2000);
+
+        let text = metrics.render();
+        assert!(text.contains(
+            "daas_latency_milliseconds_bucket{stage=\"end-to-end\",category=\"order\",le=\"+Inf\"} 1"
+        ));
+        assert!(text
+            .contains("daas_latency_milliseconds_count{stage=\"publish\",category=\"order\"} 2"));
+    }
+}
@@ -0,0 +1,267 @@
+//! An at-least-once ledger for brokering a DaaS document to several topics (fan-out).
+//!
+//! Before the DaaS document is brokered, the fan-out is recorded in the ledger and every topic that received the
+//! DaaS document is marked as delivered. The entry is removed once all the topics received the DaaS document.
+//! If the service stops midway, `FanOutLedger::recover()` brokers the DaaS document to the remaining topics, so the
+//! fan-out is eventually all-or-nothing (a topic may receive the DaaS document more than once).
+
+use super::*;
+use crate::doc::DaaSDoc;
+use crate::errors::BrokerError;
+use crate::eventing::broker::DaaSKafkaProcessor;
+use std::fs;
+use std::path::Path;
+
+// The extension of the ledger entries
+const ENTRY_EXT: &str = "json";
+
+/// Represents a fan-out of a DaaS document that hasn't been delivered to all the topics yet
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FanOutEntry {
+    /// The DaaS document to broker
+    pub doc: DaaSDoc,
+    /// The topics to broker the DaaS document to
+    pub topics: Vec<String>,
+    /// The topics that have received the DaaS document
+    pub delivered: Vec<String>,
+}
+
+impl FanOutEntry {
+    /// Returns the topics that haven't received the DaaS document yet
+    pub fn remaining(&self) -> Vec<String> {
+        self.topics
+            .iter()
+            .filter(|t| !self.delivered.contains(t))
+            .cloned()
+            .collect()
+    }
+}
+
+/// Represents a ledger of the fan-outs that is kept in a local directory
+#[derive(Debug, Clone)]
+pub struct FanOutLedger {
+    /// The directory of the ledger entries
+    pub path: String,
+}
+
+impl FanOutLedger {
+    /// Constructs a FanOutLedger
+    ///
+    /// # Arguments
+    ///
+    /// * path: String - The directory of the ledger entries.</br>
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// extern crate daas;
+    ///
+    /// use daas::eventing::broker::DaaSKafkaProcessor;
+    /// use daas::eventing::ledger::FanOutLedger;
+    /// use daas::eventing::memory::InMemoryBroker;
+    /// use daas::testing;
+    ///
+    /// fn main() {
+    ///     let ledger = FanOutLedger::new("./tmp/ledger".to_string());
+    ///     let broker = InMemoryBroker::new();
+    ///     let topics = vec!["order".to_string(), "order.clothing".to_string()];
+    ///     ledger.fan_out(&broker, &testing::get_default_daas_doc(), topics).unwrap();
+    ///
+    ///     assert_eq!(broker.messages("order.clothing").len(), 1);
+    /// }
+    /// ```
+    pub fn new(path: String) -> FanOutLedger {
+        FanOutLedger { path }
+    }
+
+    /// Brokers the DaaS document to all the topics, recording the delivery of each topic in the ledger.
+    /// If a topic can't be reached, the entry stays in the ledger to be recovered, (see `recover()`).
+    ///
+    /// # Arguments
+    ///
+    /// * broker: &B - The broker.</br>
+    /// * doc: &DaaSDoc - The DaaS document to broker.</br>
+    /// * topics: Vec<String> - The topics to broker the DaaS document to.</br>
+    pub fn fan_out<B: DaaSKafkaProcessor>(
+        &self,
+        broker: &B,
+        doc: &DaaSDoc,
+        topics: Vec<String>,
+    ) -> Result<(), BrokerError> {
+        let entry = FanOutEntry {
+            doc: doc.clone(),
+            topics,
+            delivered: Vec::new(),
+        };
+        self.write_entry(&entry)?;
+        self.deliver(broker, entry)
+    }
+
+    /// Returns the fan-outs that haven't been delivered to all the topics
+    pub fn pending(&self) -> Vec<FanOutEntry> {
+        match fs::read_dir(&self.path) {
+            Ok(entries) => entries
+                .filter_map(|e| e.ok())
+                .filter(|e| e.path().extension().and_then(|x| x.to_str()) == Some(ENTRY_EXT))
+                .filter_map(|e| fs::read(e.path()).ok())
+                .filter_map(|c| serde_json::from_slice(&c).ok())
+                .collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// Brokers the pending fan-outs to the topics that haven't received them yet.
+    /// This should be called when the service starts. Returns the number of fan-outs that were completed.
+    ///
+    /// # Arguments
+    ///
+    /// * broker: &B - The broker.</br>
+    pub fn recover<B: DaaSKafkaProcessor>(&self, broker: &B) -> Result<usize, BrokerError> {
+        let mut completed = 0;
+
+        for entry in self.pending() {
+            info!(
+                "Recovering the fan-out of DaaS document {} to {:?}",
+                entry.doc._id,
+                entry.remaining()
+            );
+            self.deliver(broker, entry)?;
+            completed += 1;
+        }
+
+        Ok(completed)
+    }
+
+    // Brokers the DaaS document to the remaining topics and removes the entry once they have all received it
+    fn deliver<B: DaaSKafkaProcessor>(
+        &self,
+        broker: &B,
+        mut entry: FanOutEntry,
+    ) -> Result<(), BrokerError> {
+        for topic in entry.remaining() {
+            if let Err(err) = broker.broker_message(&mut entry.doc, &topic) {
+                error!(
+                    "Failed to broker DaaS document {} to {}. Error: {:?}",
+                    entry.doc._id, topic, err
+                );
+                return Err(BrokerError);
+            }
+            entry.delivered.push(topic);
+            self.write_entry(&entry)?;
+        }
+
+        let _ = fs::remove_file(self.get_entry_path(&entry.doc));
+        Ok(())
+    }
+
+    // Writes the entry to a temporary file and then atomically renames it over the entry
+    fn write_entry(&self, entry: &FanOutEntry) -> Result<(), BrokerError> {
+        let path = self.get_entry_path(&entry.doc);
+        let tmp = format!("{}.tmp", path);
+        let rslt = fs::create_dir_all(&self.path)
+            .and_then(|_| fs::write(&tmp, serde_json::to_string(entry).unwrap()))
+            .and_then(|_| fs::rename(&tmp, &path));
+
+        match rslt {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                error!(
+                    "Could not write the ledger entry {} because of {}.",
+                    path, e
+                );
+                let _ = fs::remove_file(&tmp);
+                Err(BrokerError)
+            }
+        }
+    }
+
+    fn get_entry_path(&self, doc: &DaaSDoc) -> String {
+        let file = format!(
+            "{}.{}.{}",
+            doc._id,
+            doc._rev.clone().unwrap_or_else(|| "0".to_string()),
+            ENTRY_EXT
+        );
+
+        Path::new(&self.path)
+            .join(file)
+            .to_string_lossy()
+            .to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eventing::memory::InMemoryBroker;
+    use crate::testing;
+    use kafka::client::KafkaClient;
+    use kafka::error::ErrorKind;
+
+    // A broker that can't reach the topic "down"
+    struct PartialBroker {
+        broker: InMemoryBroker,
+    }
+
+    impl DaaSKafkaProcessor for PartialBroker {
+        fn broker_message_with_client(
+            _client: KafkaClient,
+            _doc: &mut DaaSDoc,
+            _topic: &str,
+        ) -> Result<(), ErrorKind> {
+            Err(ErrorKind::NoHostReachable)
+        }
+
+        fn broker_message(&self, doc: &mut DaaSDoc, topic: &str) -> Result<(), ErrorKind> {
+            match topic {
+                "down" => Err(ErrorKind::NoHostReachable),
+                _ => self.broker.broker_message(doc, topic),
+            }
+        }
+    }
+
+    fn get_ledger() -> FanOutLedger {
+        FanOutLedger::new(format!("./tmp/ledger-{}", rand::random::<u32>()))
+    }
+
+    #[test]
+    fn test_fan_out() {
+        let ledger = get_ledger();
+        let broker = InMemoryBroker::new();
+        let topics = vec!["a".to_string(), "b".to_string()];
+
+        assert!(ledger
+            .fan_out(&broker, &testing::get_default_daas_doc(), topics)
+            .is_ok());
+        assert_eq!(broker.messages("a").len(), 1);
+        assert_eq!(broker.messages("b").len(), 1);
+        assert!(ledger.pending().is_empty());
+    }
+
+    #[test]
+    fn test_recover() {
+        let ledger = get_ledger();
+        let broker = InMemoryBroker::new();
+        let partial = PartialBroker {
+            broker: broker.clone(),
+        };
+        let topics = vec!["a".to_string(), "down".to_string(), "b".to_string()];
+
+        assert!(ledger
+            .fan_out(&partial, &testing::get_default_daas_doc(), topics)
+            .is_err());
+        let pending = ledger.pending();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].delivered, vec!["a".to_string()]);
+        assert_eq!(
+            pending[0].remaining(),
+            vec!["down".to_string(), "b".to_string()]
+        );
+
+        assert_eq!(ledger.recover(&broker).unwrap(), 1);
+        assert_eq!(broker.messages("a").len(), 1);
+        assert_eq!(broker.messages("down").len(), 1);
+        assert_eq!(broker.messages("b").len(), 1);
+        assert!(ledger.pending().is_empty());
+    }
+}
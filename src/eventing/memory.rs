@@ -0,0 +1,571 @@
+//! An in-process broker for testing listeners and processors without a running Kafka.
+//!
+//! Every topic is a log of the DaaS documents that were brokered to it. Listeners read a topic from the
+//! beginning (like a Kafka consumer using `FetchOffset::Earliest`) and receive the new documents as they arrive.
+//! Clones of the `InMemoryBroker` share the same topics.
+
+use super::*;
+use crate::doc::DaaSDoc;
+use crate::eventing::broker::DaaSKafkaProcessor;
+use crate::eventing::event::{DaaSDocEvent, DaaSEventPublisher};
+use crate::eventing::headers::DaaSMessageHeaders;
+use crate::eventing::receipt::{DaaSReceiptPublisher, DeliveryReceipt};
+use crate::service::processor::{
+    handle_concurrently, CallbackHandler, DaaSAsyncDocHandler, DaaSDocHandler, DaaSDocHandlerRef,
+    DaaSProcessor, DaaSProcessorCallback, DaaSProcessorMessage, DaaSProcessorService,
+};
+use kafka::client::KafkaClient;
+use kafka::error::ErrorKind;
+use std::collections::HashMap;
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+use tokio::runtime::Runtime;
+
+// How long a listener waits for a new document before checking if it should stop
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+// The DaaS documents of each topic and the signal that a DaaS document has arrived
+type Topics = Arc<(Mutex<HashMap<String, Vec<DaaSDoc>>>, Condvar)>;
+
+// The DaaSDocEvents that have been published to each topic
+type Events = Arc<Mutex<HashMap<String, Vec<DaaSDocEvent>>>>;
+
+// The DeliveryReceipts that have been published to each topic
+type Receipts = Arc<Mutex<HashMap<String, Vec<DeliveryReceipt>>>>;
+
+/// Represents a broker that keeps the topics in memory
+#[derive(Clone, Default)]
+pub struct InMemoryBroker {
+    topics: Topics,
+    events: Events,
+    receipts: Receipts,
+}
+
+impl DaaSKafkaProcessor for InMemoryBroker {
+    /// Not supported, since an in-memory broker can't be reached using a KafkaClient
+    fn broker_message_with_client(
+        _client: KafkaClient,
+        _doc: &mut DaaSDoc,
+        _topic: &str,
+    ) -> Result<(), kafka::error::ErrorKind> {
+        Err(ErrorKind::NoHostReachable)
+    }
+
+    /// Appends the DaaS document to the topic
+    ///
+    /// # Arguments
+    ///
+    /// * doc: &mut DaaSDoc - The DaaS document to broker.</br>
+    /// * topic: &str - The name of the topic.</br>
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// extern crate pbd;
+    /// extern crate daas;
+    ///
+    /// use pbd::dua::DUA;
+    /// use pbd::dtc::Tracker;
+    /// use daas::doc::DaaSDoc;
+    /// use daas::eventing::broker::DaaSKafkaProcessor;
+    /// use daas::eventing::memory::InMemoryBroker;
+    ///
+    /// fn main() {
+    ///     let src = "iStore".to_string();
+    ///     let uid = 5000;
+    ///     let cat = "order".to_string();
+    ///     let sub = "clothing".to_string();
+    ///     let auth = "istore_app".to_string();
+    ///     let mut dua = Vec::new();
+    ///     dua.push(DUA::new("billing".to_string(),"https://dua.org/agreements/v1/billing.pdf".to_string(),1553988607));
+    ///     let tracker = Tracker::new(DaaSDoc::make_id(cat.clone(), sub.clone(), src.clone(), uid.clone()));
+    ///     let data = String::from(r#"{"status": "new"}"#).as_bytes().to_vec();
+    ///     let mut doc = DaaSDoc::new(src, uid, cat, sub, auth, dua, tracker, data);
+    ///
+    ///     let broker = InMemoryBroker::new();
+    ///     broker.broker_message(&mut doc, "genesis").unwrap();
+    ///
+    ///     assert_eq!(broker.messages("genesis").len(), 1);
+    /// }
+    /// ```
+    fn broker_message(
+        &self,
+        doc: &mut DaaSDoc,
+        topic: &str,
+    ) -> Result<(), kafka::error::ErrorKind> {
+        let (topics, arrived) = &*self.topics;
+
+        topics
+            .lock()
+            .unwrap()
+            .entry(topic.to_string())
+            .or_insert_with(Vec::new)
+            .push(doc.clone());
+        arrived.notify_all();
+
+        debug!("Brokered DaaS document {} to topic {}.", doc._id, topic);
+        Ok(())
+    }
+}
+
+impl DaaSEventPublisher for InMemoryBroker {
+    /// Appends the event to the topic
+    fn publish_event(
+        &self,
+        event: &DaaSDocEvent,
+        topic: &str,
+    ) -> Result<(), kafka::error::ErrorKind> {
+        self.events
+            .lock()
+            .unwrap()
+            .entry(topic.to_string())
+            .or_default()
+            .push(event.clone());
+
+        debug!(
+            "Published {} event for {} to topic {}.",
+            event.event_type, event.doc_id, topic
+        );
+        Ok(())
+    }
+}
+
+impl DaaSReceiptPublisher for InMemoryBroker {
+    /// Appends the receipt to the topic
+    fn publish_receipt(
+        &self,
+        receipt: &DeliveryReceipt,
+        topic: &str,
+    ) -> Result<(), kafka::error::ErrorKind> {
+        self.receipts
+            .lock()
+            .unwrap()
+            .entry(topic.to_string())
+            .or_default()
+            .push(receipt.clone());
+
+        debug!(
+            "Published the receipt of {} from {} to topic {}.",
+            receipt.doc_id, receipt.consumer_group, topic
+        );
+        Ok(())
+    }
+}
+
+impl InMemoryBroker {
+    /// Constructs an InMemoryBroker without any topics
+    pub fn new() -> InMemoryBroker {
+        InMemoryBroker::default()
+    }
+
+    /// Returns the DaaS documents that have been brokered to the topic
+    ///
+    /// # Arguments
+    ///
+    /// * topic: &str - The name of the topic.</br>
+    pub fn messages(&self, topic: &str) -> Vec<DaaSDoc> {
+        let (topics, _) = &*self.topics;
+
+        topics
+            .lock()
+            .unwrap()
+            .get(topic)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Returns the DaaSDocEvents that have been published to the topic
+    ///
+    /// # Arguments
+    ///
+    /// * topic: &str - The name of the topic.</br>
+    pub fn events(&self, topic: &str) -> Vec<DaaSDocEvent> {
+        self.events
+            .lock()
+            .unwrap()
+            .get(topic)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Returns the DeliveryReceipts that have been published to the topic
+    ///
+    /// # Arguments
+    ///
+    /// * topic: &str - The name of the topic.</br>
+    pub fn receipts(&self, topic: &str) -> Vec<DeliveryReceipt> {
+        self.receipts
+            .lock()
+            .unwrap()
+            .get(topic)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Calls the callback for every (unexpired) DaaS document brokered to the topic until a message is sent to stop listening,
+    /// (see `DaaSProcessorService::start_listening()`). The callback isn't given a KafkaClient.
+    ///
+    /// # Arguments
+    ///
+    /// * topic: &str - The name of the topic.</br>
+    /// * rx: &Receiver<bool> - The receiver of the message to stop listening.</br>
+    /// * o: Option<&T> - The object that is passed to the callback.</br>
+    /// * callback: fn - The function that processes the DaaS document.</br>
+    pub fn start_listening<T>(
+        &self,
+        topic: &str,
+        rx: &Receiver<bool>,
+        o: Option<&T>,
+        callback: DaaSProcessorCallback<T>,
+    ) {
+        self.listen(topic, rx, &CallbackHandler { o, callback });
+    }
+
+    /// Passes every (unexpired) DaaS document brokered to the topic to the handler until a message is sent to stop listening,
+    /// (see `DaaSProcessorService::start_listening_with_handler()`). The handler isn't given a KafkaClient.
+    ///
+    /// # Arguments
+    ///
+    /// * topic: &str - The name of the topic.</br>
+    /// * rx: &Receiver<bool> - The receiver of the message to stop listening.</br>
+    /// * handler: DaaSDocHandlerRef - The handler that processes the DaaS documents.</br>
+    pub fn start_listening_with_handler(
+        &self,
+        topic: &str,
+        rx: &Receiver<bool>,
+        handler: DaaSDocHandlerRef,
+    ) {
+        self.listen(topic, rx, handler.as_ref());
+    }
+
+    /// Passes every (unexpired) DaaS document brokered to the topic to the asynchronous handler on a tokio runtime
+    /// until a message is sent to stop listening, (see `DaaSProcessor::start_listening_async()`).
+    ///
+    /// # Arguments
+    ///
+    /// * topic: &str - The name of the topic.</br>
+    /// * rx: &Receiver<bool> - The receiver of the message to stop listening.</br>
+    /// * handler: Arc<dyn DaaSAsyncDocHandler> - The handler that processes the DaaS documents.</br>
+    /// * concurrency: usize - The maximum number of DaaS documents that are handled at the same time.</br>
+    pub fn start_listening_async(
+        &self,
+        topic: &str,
+        rx: &Receiver<bool>,
+        handler: Arc<dyn DaaSAsyncDocHandler>,
+        concurrency: usize,
+    ) {
+        let rt = Runtime::new().unwrap();
+        let mut offset = 0;
+
+        rt.block_on(handler.on_start());
+
+        while DaaSProcessor::keep_listening(rx) {
+            let pending = self.pending(topic, offset);
+            let first = offset;
+            offset += pending.len();
+
+            let msgs = pending
+                .iter()
+                .enumerate()
+                .filter(|(_, doc)| {
+                    if doc.is_expired() {
+                        info!(
+                            "Skipping expired DaaS document {} [topic:{}].",
+                            doc._id, topic
+                        );
+                    }
+                    !doc.is_expired()
+                })
+                .map(|(i, doc)| DaaSProcessorMessage {
+                    offset: (first + i) as i64,
+                    key: doc._id.as_bytes(),
+                    doc: doc.clone(),
+                    topic,
+                    partition: 0,
+                    headers: DaaSMessageHeaders::from_doc(doc),
+                })
+                .collect();
+
+            rt.block_on(handle_concurrently(handler.as_ref(), msgs, concurrency));
+        }
+
+        rt.block_on(handler.on_shutdown());
+    }
+
+    // Waits for the DaaS documents brokered to the topic after the offset
+    fn pending(&self, topic: &str, offset: usize) -> Vec<DaaSDoc> {
+        let (topics, arrived) = &*self.topics;
+        let guard = topics.lock().unwrap();
+        let (guard, _) = arrived
+            .wait_timeout_while(guard, POLL_INTERVAL, |t| {
+                t.get(topic).map_or(0, |v| v.len()) <= offset
+            })
+            .unwrap();
+
+        guard
+            .get(topic)
+            .map(|v| v[offset.min(v.len())..].to_vec())
+            .unwrap_or_default()
+    }
+
+    fn listen(&self, topic: &str, rx: &Receiver<bool>, handler: &dyn DaaSDocHandler) {
+        let mut offset = 0;
+
+        handler.on_start();
+
+        while DaaSProcessor::keep_listening(rx) {
+            let pending = self.pending(topic, offset);
+
+            for doc in pending {
+                // expired data must no longer be used, (see `DaaSProcessorService::start_listening()`)
+                if doc.is_expired() {
+                    info!(
+                        "Skipping expired DaaS document {} [topic:{}].",
+                        doc._id, topic
+                    );
+                    offset += 1;
+                    continue;
+                }
+
+                let msg = DaaSProcessorMessage {
+                    offset: offset as i64,
+                    key: doc._id.as_bytes(),
+                    doc: doc.clone(),
+                    topic,
+                    partition: 0,
+                    headers: DaaSMessageHeaders::from_doc(&doc),
+                };
+
+                if let Err(err) = handler.handle(msg, None) {
+                    warn!(
+                        "Could not process the DaasDoc {} [topic:{}, offset:{}]. Error: {:?}",
+                        doc._id, topic, offset, err
+                    );
+                    handler.on_error(&doc, &err);
+                }
+                offset += 1;
+            }
+        }
+
+        handler.on_shutdown();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::daaserror::DaaSProcessingError;
+    use crate::service::processor::DaaSDocFilter;
+    use crate::testing;
+    use std::sync::mpsc::channel;
+    use std::thread;
+
+    fn get_daas_doc(uid: usize) -> DaaSDoc {
+        testing::get_daas_doc(
+            "iStore".to_string(),
+            uid,
+            "order".to_string(),
+            "clothing".to_string(),
+        )
+    }
+
+    #[test]
+    fn test_broker_message() {
+        let broker = InMemoryBroker::new();
+        let shared = broker.clone();
+
+        assert!(broker.broker_message(&mut get_daas_doc(1), "a").is_ok());
+        assert!(broker.broker_message(&mut get_daas_doc(2), "a").is_ok());
+        assert_eq!(shared.messages("a").len(), 2);
+        assert_eq!(shared.messages("b").len(), 0);
+    }
+
+    #[test]
+    fn test_broker_message_with_client() {
+        let client = KafkaClient::new(vec!["localhost:9092".to_string()]);
+        assert!(
+            InMemoryBroker::broker_message_with_client(client, &mut get_daas_doc(1), "a").is_err()
+        );
+    }
+
+    #[test]
+    fn test_start_listening() {
+        let broker = InMemoryBroker::new();
+        let listener = broker.clone();
+        let processed = InMemoryBroker::new();
+        let (tx, rx) = channel();
+
+        broker.broker_message(&mut get_daas_doc(1), "in").unwrap();
+        let mut expired = get_daas_doc(3);
+        expired.expires_at = Some(1553988667);
+        broker.broker_message(&mut expired, "in").unwrap();
+
+        let handle = thread::spawn(move || {
+            listener.start_listening(
+                "in",
+                &rx,
+                Some(&processed.clone()),
+                |mut msg: DaaSProcessorMessage,
+                 _clnt: Option<KafkaClient>,
+                 out: Option<&InMemoryBroker>| {
+                    assert_eq!(msg.headers.schema_id, msg.doc.schema_version);
+                    out.unwrap().broker_message(&mut msg.doc, "out").unwrap();
+                    Ok(1)
+                },
+            );
+            processed
+        });
+
+        broker.broker_message(&mut get_daas_doc(2), "in").unwrap();
+        thread::sleep(Duration::from_millis(300));
+        DaaSProcessor::stop_listening(&tx);
+
+        let processed = handle.join().unwrap();
+        let out = processed.messages("out");
+        assert_eq!(out.len(), 2);
+        assert_eq!(out[1]._id, get_daas_doc(2)._id);
+    }
+    // A handler that holds state: the ids of the processed and failed DaaS documents and its lifecycle
+    #[derive(Default)]
+    struct RecordingHandler {
+        processed: Mutex<Vec<String>>,
+        failed: Mutex<Vec<String>>,
+        lifecycle: Mutex<Vec<&'static str>>,
+    }
+
+    impl DaaSDocHandler for RecordingHandler {
+        fn on_start(&self) {
+            self.lifecycle.lock().unwrap().push("start");
+        }
+
+        fn handle(
+            &self,
+            msg: DaaSProcessorMessage,
+            _client: Option<KafkaClient>,
+        ) -> Result<i32, DaaSProcessingError> {
+            match msg.doc.source_uid == 2 {
+                true => Err(DaaSProcessingError::UpsertError),
+                false => {
+                    self.processed.lock().unwrap().push(msg.doc._id);
+                    Ok(1)
+                }
+            }
+        }
+
+        fn on_error(&self, doc: &DaaSDoc, _err: &DaaSProcessingError) {
+            self.failed.lock().unwrap().push(doc._id.clone());
+        }
+
+        fn on_shutdown(&self) {
+            self.lifecycle.lock().unwrap().push("shutdown");
+        }
+    }
+
+    #[test]
+    fn test_start_listening_with_handler() {
+        let broker = InMemoryBroker::new();
+        let listener = broker.clone();
+        let handler = Arc::new(RecordingHandler::default());
+        let shared = handler.clone();
+        let (tx, rx) = channel();
+
+        broker.broker_message(&mut get_daas_doc(1), "in").unwrap();
+        broker.broker_message(&mut get_daas_doc(2), "in").unwrap();
+
+        let handle = thread::spawn(move || {
+            listener.start_listening_with_handler("in", &rx, shared);
+        });
+
+        thread::sleep(Duration::from_millis(300));
+        DaaSProcessor::stop_listening(&tx);
+        handle.join().unwrap();
+
+        assert_eq!(
+            *handler.processed.lock().unwrap(),
+            vec![get_daas_doc(1)._id]
+        );
+        assert_eq!(*handler.failed.lock().unwrap(), vec![get_daas_doc(2)._id]);
+        assert_eq!(
+            *handler.lifecycle.lock().unwrap(),
+            vec!["start", "shutdown"]
+        );
+    }
+    #[test]
+    fn test_start_listening_with_filter() {
+        let broker = InMemoryBroker::new();
+        let listener = broker.clone();
+        let handler = Arc::new(RecordingHandler::default());
+        let filter = DaaSDocFilter::new().with_tag("priority".to_string());
+        let filtered = filter.handler(handler.clone());
+        let (tx, rx) = channel();
+
+        let mut priority = get_daas_doc(3);
+        priority.add_tag("priority".to_string());
+        broker.broker_message(&mut get_daas_doc(1), "in").unwrap();
+        broker.broker_message(&mut priority, "in").unwrap();
+
+        let handle = thread::spawn(move || {
+            listener.start_listening_with_handler("in", &rx, filtered);
+        });
+
+        thread::sleep(Duration::from_millis(300));
+        DaaSProcessor::stop_listening(&tx);
+        handle.join().unwrap();
+
+        assert_eq!(*handler.processed.lock().unwrap(), vec![priority._id]);
+        assert_eq!(filter.filtered_count(), 1);
+        assert_eq!(
+            *handler.lifecycle.lock().unwrap(),
+            vec!["start", "shutdown"]
+        );
+    }
+
+    // An asynchronous handler that records the most DaaS documents it has handled at the same time
+    #[derive(Default)]
+    struct ConcurrentHandler {
+        running: Mutex<usize>,
+        most: Mutex<usize>,
+        processed: Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl DaaSAsyncDocHandler for ConcurrentHandler {
+        async fn handle(&self, msg: DaaSProcessorMessage<'_>) -> Result<i32, DaaSProcessingError> {
+            {
+                let mut running = self.running.lock().unwrap();
+                *running += 1;
+                let mut most = self.most.lock().unwrap();
+                *most = (*most).max(*running);
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            *self.running.lock().unwrap() -= 1;
+            self.processed.lock().unwrap().push(msg.doc._id);
+            Ok(1)
+        }
+    }
+
+    #[test]
+    fn test_start_listening_async() {
+        let broker = InMemoryBroker::new();
+        let listener = broker.clone();
+        let handler = Arc::new(ConcurrentHandler::default());
+        let shared = handler.clone();
+        let (tx, rx) = channel();
+
+        for uid in 1..=6 {
+            broker.broker_message(&mut get_daas_doc(uid), "in").unwrap();
+        }
+
+        let handle = thread::spawn(move || {
+            listener.start_listening_async("in", &rx, shared, 2);
+        });
+
+        thread::sleep(Duration::from_millis(500));
+        DaaSProcessor::stop_listening(&tx);
+        handle.join().unwrap();
+
+        assert_eq!(handler.processed.lock().unwrap().len(), 6);
+        assert_eq!(*handler.most.lock().unwrap(), 2);
+    }
+}
@@ -4,3 +4,16 @@ use super::*;
 //use crate::errors::*;
 
 pub mod broker;
+pub mod checkpoint;
+pub mod event;
+pub mod headers;
+pub mod latency;
+pub mod ledger;
+pub mod memory;
+pub mod nonblocking;
+pub mod partition;
+pub mod pool;
+pub mod receipt;
+pub mod redact;
+pub mod sequence;
+pub mod sla;
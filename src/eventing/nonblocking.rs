@@ -0,0 +1,124 @@
+//! Brokers the DaaS documents from async code, (e.g.: actix-web handlers or `DaaSAsyncDocHandler`s) without blocking
+//! the executor.
+//!
+//! The `kafka` crate is synchronous, so the `AsyncBroker` runs the publishes of the broker it wraps on the blocking
+//! thread pool of the tokio runtime and awaits them. Any `DaaSKafkaProcessor` can be wrapped, (e.g.: a
+//! `DaaSKafkaBroker` or an `InMemoryBroker` in tests).
+//!
+//! The `AsyncBroker` is only a bridge. The SDK isn't async end-to-end, and the upgrade of the eventing and service
+//! stack to the async ecosystem hasn't been done. It is still open, as these follow-ups:
+//!
+//! 1. Upgrade the services to actix-web 4, (the extractors still use the actix-web 3 `FromRequest` signatures).
+//! 2. Move the S3 storage and the AWS Secrets Manager provider from rusoto to aws-sdk-rust, (they still block on the
+//!    rusoto futures).
+//! 3. Replace the synchronous `kafka` crate with an async Kafka client, (e.g.: rdkafka or rskafka), so the brokers and
+//!    processors can publish and consume without the blocking thread pool.
+//!
+//! Until then, only the publishes of the brokers can be awaited.
+//!
+//! # Examples
+//!
+//! ```
+//! extern crate daas;
+//!
+//! use daas::eventing::memory::InMemoryBroker;
+//! use daas::eventing::nonblocking::AsyncBroker;
+//! use daas::testing;
+//! use tokio::runtime::Runtime;
+//!
+//! fn main() {
+//!     let broker = AsyncBroker::new(InMemoryBroker::new());
+//!     let rt = Runtime::new().unwrap();
+//!
+//!     rt.block_on(broker.broker_message(testing::get_default_daas_doc(), "genesis".to_string())).unwrap();
+//!     assert_eq!(broker.broker.messages("genesis").len(), 1);
+//! }
+//! ```
+
+use super::*;
+use crate::doc::DaaSDoc;
+use crate::eventing::broker::DaaSKafkaProcessor;
+use kafka::error::ErrorKind;
+
+/// Represents a broker that publishes the DaaS documents on the blocking thread pool, so they can be awaited
+#[derive(Clone)]
+pub struct AsyncBroker<B: DaaSKafkaProcessor + Clone + Send + Sync + 'static> {
+    /// The broker that sends the DaaS documents
+    pub broker: B,
+}
+
+impl<B: DaaSKafkaProcessor + Clone + Send + Sync + 'static> AsyncBroker<B> {
+    /// Constructor
+    ///
+    /// # Arguments
+    ///
+    /// * broker: B - The broker that sends the DaaS documents.</br>
+    pub fn new(broker: B) -> AsyncBroker<B> {
+        AsyncBroker { broker }
+    }
+
+    /// Brokers the DaaS document to the topic and gives the DaaS document back once it has been published
+    ///
+    /// # Arguments
+    ///
+    /// * doc: DaaSDoc - The DaaS document to broker.</br>
+    /// * topic: String - The topic to send the DaaS document to.</br>
+    pub async fn broker_message(&self, doc: DaaSDoc, topic: String) -> Result<DaaSDoc, ErrorKind> {
+        self.broker_message_to_topics(doc, vec![topic]).await
+    }
+
+    /// Brokers the DaaS document to all the topics, (see `DaaSKafkaProcessor::broker_message_to_topics()`) and returns
+    /// the DaaS document
+    ///
+    /// # Arguments
+    ///
+    /// * doc: DaaSDoc - The DaaS document to broker.</br>
+    /// * topics: Vec<String> - The topics to send the DaaS document to.</br>
+    pub async fn broker_message_to_topics(
+        &self,
+        mut doc: DaaSDoc,
+        topics: Vec<String>,
+    ) -> Result<DaaSDoc, ErrorKind> {
+        let broker = self.broker.clone();
+
+        match tokio::task::spawn_blocking(move || {
+            broker
+                .broker_message_to_topics(&mut doc, &topics)
+                .map(|_| doc)
+        })
+        .await
+        {
+            Ok(rslt) => rslt,
+            Err(err) => {
+                error!(
+                    "The publish of the DaaS document was aborted. Error: {}",
+                    err
+                );
+                Err(ErrorKind::Msg(err.to_string()))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eventing::memory::InMemoryBroker;
+    use crate::testing;
+    use tokio::runtime::Runtime;
+
+    #[test]
+    fn test_broker_message_to_topics() {
+        let broker = AsyncBroker::new(InMemoryBroker::new());
+        let topics = vec!["order".to_string(), "genesis".to_string()];
+        let rt = Runtime::new().unwrap();
+
+        let doc = rt
+            .block_on(broker.broker_message_to_topics(testing::get_default_daas_doc(), topics))
+            .unwrap();
+
+        assert_eq!(doc._id, testing::get_default_daas_doc()._id);
+        assert_eq!(broker.broker.messages("order").len(), 1);
+        assert_eq!(broker.broker.messages("genesis").len(), 1);
+    }
+}
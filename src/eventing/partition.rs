@@ -0,0 +1,199 @@
+//! Strategies for choosing the key that the DaaS documents are partitioned by when they are published to Kafka.
+//!
+//! Kafka only guarantees the order of the messages within a partition, and the producer sends all the messages with
+//! the same key to the same partition. By default the key is the identifier of the DaaS document, so the revisions of
+//! a DaaS document stay in order but the DaaS documents of the same source or entity are scattered across the
+//! partitions. A `PartitionStrategy` picks another key, (e.g.: the source name, the category or a field of the data).
+//!
+//! The `DaaSKafkaBroker` (and so the DaaS listener and processors) reads the strategy from the `DAAS_PARTITION_KEY`
+//! environment variable, (`document_id`, `source_name`, `category` or `payload:<pointer>`, e.g.: `payload:/customer/id`)
+//! unless it is set with `DaaSKafkaBroker::with_partitioning()`.
+//!
+//! # Examples
+//!
+//! ```
+//! extern crate daas;
+//!
+//! use daas::eventing::broker::DaaSKafkaBroker;
+//! use daas::eventing::partition::PartitionStrategy;
+//! use daas::testing;
+//!
+//! fn main() {
+//!     let broker = DaaSKafkaBroker::default().with_partitioning(PartitionStrategy::SourceName);
+//!
+//!     assert_eq!(broker.partitioning.partition_key(&testing::get_default_daas_doc()), "iStore".to_string());
+//! }
+//! ```
+
+use super::*;
+use crate::doc::DaaSDoc;
+use serde_json::Value;
+use std::env;
+use std::fmt;
+use std::sync::Arc;
+
+/// The environment variable of the partition strategy, (e.g.: source_name)
+pub const PARTITION_KEY_VAR: &str = "DAAS_PARTITION_KEY";
+
+/// The function that returns the partition key of a DaaS document, (see `PartitionStrategy::Custom`)
+pub type PartitionKeyFn = Arc<dyn Fn(&DaaSDoc) -> String + Send + Sync>;
+
+/// Represents how the key that a DaaS document is partitioned by is chosen
+#[derive(Clone, Default)]
+pub enum PartitionStrategy {
+    /// By the identifier of the DaaS document, so the revisions of a DaaS document are in order, (default)
+    #[default]
+    DocumentId,
+    /// By the source name, so the DaaS documents of a source are in order
+    SourceName,
+    /// By the category, so the DaaS documents of a category are in order
+    Category,
+    /// By the value of the field of the JSON data at the pointer, (e.g.: "/customer/id"). DaaS documents whose data
+    /// doesn't have the field are partitioned by their identifier.
+    PayloadField(String),
+    /// By the key that the function returns
+    Custom(PartitionKeyFn),
+}
+
+impl fmt::Debug for PartitionStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PartitionStrategy::DocumentId => write!(f, "DocumentId"),
+            PartitionStrategy::SourceName => write!(f, "SourceName"),
+            PartitionStrategy::Category => write!(f, "Category"),
+            PartitionStrategy::PayloadField(pointer) => write!(f, "PayloadField({:?})", pointer),
+            PartitionStrategy::Custom(_) => write!(f, "Custom(..)"),
+        }
+    }
+}
+
+impl PartitionStrategy {
+    /// Parses the name of the strategy, (`document_id`, `source_name`, `category` or `payload:<pointer>`)
+    ///
+    /// # Arguments
+    ///
+    /// * name: &str - The name of the strategy, (e.g.: payload:/customer/id).</br>
+    pub fn parse(name: &str) -> Option<PartitionStrategy> {
+        match name.trim() {
+            "document_id" => Some(PartitionStrategy::DocumentId),
+            "source_name" => Some(PartitionStrategy::SourceName),
+            "category" => Some(PartitionStrategy::Category),
+            other => other
+                .strip_prefix("payload:")
+                .filter(|pointer| pointer.starts_with('/'))
+                .map(|pointer| PartitionStrategy::PayloadField(pointer.to_string())),
+        }
+    }
+
+    /// Returns the strategy of the environment variable (i.e.: DAAS_PARTITION_KEY), or the default strategy if it
+    /// isn't set or can't be parsed
+    pub fn from_env() -> PartitionStrategy {
+        match env::var(PARTITION_KEY_VAR) {
+            Ok(name) => PartitionStrategy::parse(&name).unwrap_or_else(|| {
+                warn!(
+                    "Unknown partition strategy {}, so the DaaS documents are partitioned by their identifier.",
+                    name
+                );
+                PartitionStrategy::default()
+            }),
+            Err(_err) => PartitionStrategy::default(),
+        }
+    }
+
+    /// Returns the key that the DaaS document is partitioned by
+    ///
+    /// # Arguments
+    ///
+    /// * doc: &DaaSDoc - The DaaS document to publish.</br>
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// extern crate daas;
+    ///
+    /// use daas::eventing::partition::PartitionStrategy;
+    /// use daas::testing;
+    ///
+    /// fn main() {
+    ///     let doc = testing::get_default_daas_doc();
+    ///     let strategy = PartitionStrategy::PayloadField("/status".to_string());
+    ///
+    ///     assert_eq!(strategy.partition_key(&doc), "new".to_string());
+    /// }
+    /// ```
+    pub fn partition_key(&self, doc: &DaaSDoc) -> String {
+        match self {
+            PartitionStrategy::DocumentId => doc._id.clone(),
+            PartitionStrategy::SourceName => doc.source_name.clone(),
+            PartitionStrategy::Category => doc.category.clone(),
+            PartitionStrategy::PayloadField(pointer) => {
+                match serde_json::from_slice::<Value>(&doc.data_obj)
+                    .ok()
+                    .and_then(|data| data.pointer(pointer).cloned())
+                {
+                    Some(Value::String(s)) => s,
+                    Some(value) => value.to_string(),
+                    None => {
+                        debug!(
+                            "DaaS document {} has no field {}, so it is partitioned by its identifier.",
+                            doc._id, pointer
+                        );
+                        doc._id.clone()
+                    }
+                }
+            }
+            PartitionStrategy::Custom(key) => key(doc),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn test_partition_key() {
+        let doc = testing::get_default_daas_doc();
+
+        assert_eq!(PartitionStrategy::default().partition_key(&doc), doc._id);
+        assert_eq!(
+            PartitionStrategy::Category.partition_key(&doc),
+            "order".to_string()
+        );
+        assert_eq!(
+            PartitionStrategy::Custom(Arc::new(|d: &DaaSDoc| d.subcategory.clone()))
+                .partition_key(&doc),
+            "clothing".to_string()
+        );
+    }
+
+    #[test]
+    fn test_parse() {
+        assert!(matches!(
+            PartitionStrategy::parse("source_name"),
+            Some(PartitionStrategy::SourceName)
+        ));
+        assert!(matches!(
+            PartitionStrategy::parse("payload:/customer/id"),
+            Some(PartitionStrategy::PayloadField(ref p)) if p == "/customer/id"
+        ));
+        assert!(PartitionStrategy::parse("payload:customer").is_none());
+        assert!(PartitionStrategy::parse("random").is_none());
+    }
+
+    #[test]
+    fn test_partition_key_payload_field() {
+        let mut doc = testing::get_default_daas_doc();
+        doc.data_obj = br#"{"customer": {"id": 42}}"#.to_vec();
+
+        assert_eq!(
+            PartitionStrategy::PayloadField("/customer/id".to_string()).partition_key(&doc),
+            "42".to_string()
+        );
+        assert_eq!(
+            PartitionStrategy::PayloadField("/order/id".to_string()).partition_key(&doc),
+            doc._id
+        );
+    }
+}
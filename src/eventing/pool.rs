@@ -0,0 +1,141 @@
+//! A pool of Kafka brokers that is shared by the listener threads, so that the producer connections are reused
+//! across requests instead of being opened for every DaaS document.
+//!
+//! The pool is registered as application data of the listener, (e.g.: `App::new().data(BrokerPool::new(hosts, 4))`).
+
+use super::*;
+use crate::doc::DaaSDoc;
+use crate::eventing::broker::{DaaSKafkaBroker, DaaSKafkaProcessor, DaaSKafkaProducerConfig};
+use kafka::client::KafkaClient;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Represents a pool of Kafka brokers, each with a long-lived producer. Clones of the BrokerPool share the same brokers.
+#[derive(Clone)]
+pub struct BrokerPool {
+    /// The hosts of the Kafka brokers, (e.g.: localhost:9092)
+    pub hosts: Vec<String>,
+    /// How the messages are produced
+    pub config: DaaSKafkaProducerConfig,
+    /// The maximum number of brokers in the pool
+    pub size: usize,
+    brokers: Arc<Mutex<Vec<DaaSKafkaBroker>>>,
+    next: Arc<AtomicUsize>,
+}
+
+impl BrokerPool {
+    /// Constructs a BrokerPool. The brokers are created when they are first needed.
+    ///
+    /// # Arguments
+    ///
+    /// * hosts: Vec<String> - The hosts of the Kafka brokers.</br>
+    /// * size: usize - The maximum number of brokers in the pool, (at least 1).</br>
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// extern crate daas;
+    ///
+    /// use daas::eventing::pool::BrokerPool;
+    ///
+    /// fn main() {
+    ///     let pool = BrokerPool::new(vec!["localhost:9092".to_string()], 4);
+    ///     let broker = pool.get();
+    ///
+    ///     assert_eq!(pool.len(), 1);
+    /// }
+    /// ```
+    pub fn new(hosts: Vec<String>, size: usize) -> BrokerPool {
+        BrokerPool {
+            hosts,
+            config: DaaSKafkaProducerConfig::default(),
+            size: size.max(1),
+            brokers: Arc::new(Mutex::new(Vec::new())),
+            next: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Sets how the messages of the brokers in the pool are produced
+    ///
+    /// # Arguments
+    ///
+    /// * config: DaaSKafkaProducerConfig - The compression, acknowledgements and batching of the messages.</br>
+    pub fn with_config(mut self, config: DaaSKafkaProducerConfig) -> BrokerPool {
+        self.config = config;
+        self
+    }
+
+    /// Returns the next broker of the pool (round-robin), creating it if the pool isn't full yet.
+    /// The returned broker shares its producer with the pool.
+    pub fn get(&self) -> DaaSKafkaBroker {
+        let turn = self.next.fetch_add(1, Ordering::Relaxed) % self.size;
+        let mut brokers = self.brokers.lock().unwrap();
+
+        if turn >= brokers.len() {
+            debug!("Adding broker #{} to the pool.", brokers.len() + 1);
+            brokers.push(DaaSKafkaBroker::new(self.hosts.clone()).with_config(self.config));
+            return brokers.last().unwrap().clone();
+        }
+
+        brokers[turn].clone()
+    }
+
+    /// Returns the number of brokers that have been created
+    pub fn len(&self) -> usize {
+        self.brokers.lock().unwrap().len()
+    }
+
+    /// Determines if no brokers have been created yet
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl DaaSKafkaProcessor for BrokerPool {
+    fn broker_message_with_client(
+        client: KafkaClient,
+        doc: &mut DaaSDoc,
+        topic: &str,
+    ) -> Result<(), kafka::error::ErrorKind> {
+        DaaSKafkaBroker::broker_message_with_client(client, doc, topic)
+    }
+
+    /// Sends the DaaS document using the next broker of the pool
+    fn broker_message(
+        &self,
+        doc: &mut DaaSDoc,
+        topic: &str,
+    ) -> Result<(), kafka::error::ErrorKind> {
+        self.get().broker_message(doc, topic)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_round_robin() {
+        let pool = BrokerPool::new(vec!["localhost:9092".to_string()], 2);
+        let shared = pool.clone();
+
+        assert!(pool.is_empty());
+        pool.get();
+        shared.get();
+        pool.get();
+        assert_eq!(pool.len(), 2);
+        assert_eq!(shared.len(), 2);
+    }
+
+    #[test]
+    fn test_get_shares_producer() {
+        let pool = BrokerPool::new(vec!["localhost:9092".to_string()], 1);
+        let mut doc = crate::testing::get_default_daas_doc();
+        let broker = pool.get().with_config(
+            DaaSKafkaProducerConfig::default().with_linger(std::time::Duration::from_secs(60)),
+        );
+
+        broker.queue_message(&mut doc, "genesis");
+        assert_eq!(pool.get().queued(), 1);
+    }
+}
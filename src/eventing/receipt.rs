@@ -0,0 +1,470 @@
+//! Delivery receipts that the downstream processors emit once they have handled a DaaS document, so that the
+//! producers can find out which consumers have processed it.
+//!
+//! A processor's handler is wrapped with `DeliveryReceipts::handler()`, which publishes a `DeliveryReceipt`
+//! (doc id, consumer group and outcome) to the receipts topic after each DaaS document is handled.
+//! The `DeliveryTracker` aggregates the receipts of the topic, keeping the latest receipt of each consumer group.
+//!
+//! # Examples
+//!
+//! ```
+//! extern crate daas;
+//!
+//! use daas::eventing::memory::InMemoryBroker;
+//! use daas::eventing::receipt::{DeliveryReceipt, DeliveryOutcome, DeliveryTracker, DaaSReceiptPublisher, RECEIPTS_TOPIC};
+//! use daas::testing;
+//!
+//! fn main() {
+//!     let broker = InMemoryBroker::new();
+//!     let doc = testing::get_default_daas_doc();
+//!     let receipt = DeliveryReceipt::new(&doc, "billing".to_string(), DeliveryOutcome::Processed);
+//!     broker.publish_receipt(&receipt, RECEIPTS_TOPIC).unwrap();
+//!
+//!     let tracker = DeliveryTracker::new();
+//!     tracker.record_all(broker.receipts(RECEIPTS_TOPIC));
+//!
+//!     assert_eq!(tracker.processed_by(doc._id), vec!["billing".to_string()]);
+//! }
+//! ```
+
+use super::*;
+use crate::doc::DaaSDoc;
+use crate::errors::daaserror::DaaSProcessingError;
+use crate::errors::BrokerError;
+use crate::eventing::broker::DaaSKafkaBroker;
+use crate::service::processor::{
+    DaaSDocHandler, DaaSDocHandlerRef, DaaSProcessor, DaaSProcessorMessage, DaaSProcessorService,
+};
+use kafka::client::KafkaClient;
+use kafka::consumer::Consumer;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+/// The default topic of the delivery receipts
+pub const RECEIPTS_TOPIC: &str = "daas-receipts";
+
+/// The outcome of handling a DaaS document
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeliveryOutcome {
+    /// The DaaS document was processed
+    Processed,
+    /// The DaaS document could not be processed
+    Failed,
+}
+
+/// Represents the acknowledgment of a consumer group that it has handled a DaaS document
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DeliveryReceipt {
+    /// The unique identifier of the DaaS document
+    pub doc_id: String,
+    /// The revision of the DaaS document that was handled
+    pub doc_rev: Option<String>,
+    /// The consumer group of the processor, (e.g.: billing)
+    pub consumer_group: String,
+    /// The outcome of handling the DaaS document
+    pub outcome: DeliveryOutcome,
+    /// The Unix Epoch time when the DaaS document was handled
+    pub timestamp: u64,
+}
+
+impl DeliveryReceipt {
+    /// Constructs a DeliveryReceipt of the DaaS document
+    ///
+    /// # Arguments
+    ///
+    /// * doc: &DaaSDoc - The DaaS document that was handled.</br>
+    /// * consumer_group: String - The consumer group of the processor, (e.g.: billing).</br>
+    /// * outcome: DeliveryOutcome - The outcome of handling the DaaS document.</br>
+    pub fn new(doc: &DaaSDoc, consumer_group: String, outcome: DeliveryOutcome) -> DeliveryReceipt {
+        DeliveryReceipt {
+            doc_id: doc._id.clone(),
+            doc_rev: doc._rev.clone(),
+            consumer_group,
+            outcome,
+            timestamp: get_unix_now!(),
+        }
+    }
+
+    /// Constructs a DeliveryReceipt from the serialized receipt, (e.g.: the value of a Kafka message)
+    ///
+    /// # Arguments
+    ///
+    /// * serialized: &[u8] - The serialized DeliveryReceipt.</br>
+    pub fn from_serialized(serialized: &[u8]) -> Result<DeliveryReceipt, BrokerError> {
+        match serde_json::from_slice(serialized) {
+            Ok(receipt) => Ok(receipt),
+            Err(err) => {
+                warn!("Could not deserialize the delivery receipt. {}", err);
+                Err(BrokerError)
+            }
+        }
+    }
+
+    /// Serializes the DeliveryReceipt object
+    pub fn serialize(&self) -> String {
+        serde_json::to_string(&self).unwrap()
+    }
+}
+
+/// Trait for the brokers that publish DeliveryReceipts
+pub trait DaaSReceiptPublisher {
+    /// Publishes the receipt to the topic
+    fn publish_receipt(
+        &self,
+        receipt: &DeliveryReceipt,
+        topic: &str,
+    ) -> Result<(), kafka::error::ErrorKind>;
+}
+
+impl DaaSReceiptPublisher for DaaSKafkaBroker {
+    fn publish_receipt(
+        &self,
+        receipt: &DeliveryReceipt,
+        topic: &str,
+    ) -> Result<(), kafka::error::ErrorKind> {
+        self.send_records(vec![(
+            topic.to_string(),
+            receipt.doc_id.clone(),
+            receipt.serialize().into_bytes(),
+        )])
+    }
+}
+
+/// Publishes a DeliveryReceipt for each DaaS document that the wrapped handlers of a consumer group have handled
+pub struct DeliveryReceipts<P: DaaSReceiptPublisher + Send + Sync + 'static> {
+    /// The consumer group of the processor, (e.g.: billing)
+    pub consumer_group: String,
+    /// The broker that the receipts are published to
+    pub publisher: Arc<P>,
+    /// The topic that the receipts are published to
+    pub topic: String,
+}
+
+impl<P: DaaSReceiptPublisher + Send + Sync + 'static> DeliveryReceipts<P> {
+    /// Constructs a DeliveryReceipts that publishes to the default receipts topic, (i.e.: daas-receipts)
+    ///
+    /// # Arguments
+    ///
+    /// * consumer_group: String - The consumer group of the processor, (e.g.: billing).</br>
+    /// * publisher: P - The broker that the receipts are published to.</br>
+    pub fn new(consumer_group: String, publisher: P) -> DeliveryReceipts<P> {
+        DeliveryReceipts {
+            consumer_group,
+            publisher: Arc::new(publisher),
+            topic: RECEIPTS_TOPIC.to_string(),
+        }
+    }
+
+    /// Sets the topic that the receipts are published to
+    ///
+    /// # Arguments
+    ///
+    /// * topic: String - The topic of the receipts, (e.g.: dev.daas-receipts).</br>
+    pub fn with_topic(mut self, topic: String) -> DeliveryReceipts<P> {
+        self.topic = topic;
+        self
+    }
+
+    /// Wraps the handler so that a receipt is published after each DaaS document is handled
+    ///
+    /// # Arguments
+    ///
+    /// * handler: DaaSDocHandlerRef - The handler that processes the DaaS documents.</br>
+    pub fn handler(&self, handler: DaaSDocHandlerRef) -> DaaSDocHandlerRef {
+        Arc::new(ReceiptingHandler {
+            consumer_group: self.consumer_group.clone(),
+            publisher: self.publisher.clone(),
+            topic: self.topic.clone(),
+            handler,
+        })
+    }
+}
+
+// Publishes the receipts of the DaaS documents that the handler has handled
+struct ReceiptingHandler<P: DaaSReceiptPublisher> {
+    consumer_group: String,
+    publisher: Arc<P>,
+    topic: String,
+    handler: DaaSDocHandlerRef,
+}
+
+impl<P: DaaSReceiptPublisher> ReceiptingHandler<P> {
+    // Publishes the receipt, (the DaaS document has been handled, so a failure to publish the receipt is only logged)
+    fn publish(&self, doc: &DaaSDoc, outcome: DeliveryOutcome) {
+        let receipt = DeliveryReceipt::new(doc, self.consumer_group.clone(), outcome);
+
+        if let Err(err) = self.publisher.publish_receipt(&receipt, &self.topic) {
+            warn!(
+                "DaaS document {} was handled but the receipt was not published. {}",
+                doc._id, err
+            );
+        }
+    }
+}
+
+impl<P: DaaSReceiptPublisher + Send + Sync> DaaSDocHandler for ReceiptingHandler<P> {
+    fn on_start(&self) {
+        self.handler.on_start();
+    }
+
+    fn handle(
+        &self,
+        msg: DaaSProcessorMessage,
+        client: Option<KafkaClient>,
+    ) -> Result<i32, DaaSProcessingError> {
+        let doc = msg.doc.clone_envelope();
+        let result = self.handler.handle(msg, client);
+
+        match result {
+            Ok(_) => self.publish(&doc, DeliveryOutcome::Processed),
+            Err(_) => self.publish(&doc, DeliveryOutcome::Failed),
+        }
+
+        result
+    }
+
+    fn on_error(&self, doc: &DaaSDoc, err: &DaaSProcessingError) {
+        self.handler.on_error(doc, err);
+    }
+
+    fn on_shutdown(&self) {
+        self.handler.on_shutdown();
+    }
+}
+
+// The latest receipt of each consumer group, by the id of the DaaS document
+type Receipts = Arc<Mutex<HashMap<String, BTreeMap<String, DeliveryReceipt>>>>;
+
+/// Aggregates the DeliveryReceipts so the producers can query which consumers have processed a DaaS document.
+/// Clones of the `DeliveryTracker` share the same receipts.
+#[derive(Clone, Default)]
+pub struct DeliveryTracker {
+    receipts: Receipts,
+}
+
+impl DeliveryTracker {
+    /// Constructs a DeliveryTracker without any receipts
+    pub fn new() -> DeliveryTracker {
+        DeliveryTracker::default()
+    }
+
+    /// Records the receipt, unless a later receipt of the consumer group has already been recorded
+    ///
+    /// # Arguments
+    ///
+    /// * receipt: DeliveryReceipt - The receipt of a consumer group.</br>
+    pub fn record(&self, receipt: DeliveryReceipt) {
+        let mut receipts = self.receipts.lock().unwrap();
+        let groups = receipts.entry(receipt.doc_id.clone()).or_default();
+
+        match groups.get(&receipt.consumer_group) {
+            Some(latest) if latest.timestamp > receipt.timestamp => {}
+            _ => {
+                groups.insert(receipt.consumer_group.clone(), receipt);
+            }
+        }
+    }
+
+    /// Records the receipts, (e.g.: the receipts that were published to an InMemoryBroker)
+    ///
+    /// # Arguments
+    ///
+    /// * receipts: Vec<DeliveryReceipt> - The receipts of the consumer groups.</br>
+    pub fn record_all(&self, receipts: Vec<DeliveryReceipt>) {
+        for receipt in receipts {
+            self.record(receipt);
+        }
+    }
+
+    /// Records the receipts of the receipts topic until a message is sent to stop listening
+    ///
+    /// # Arguments
+    ///
+    /// * consumer: Consumer - The consumer of the receipts topic.</br>
+    /// * rx: &Receiver<bool> - The receiver of the message to stop listening.</br>
+    pub fn start_listening(&self, mut consumer: Consumer, rx: &Receiver<bool>) {
+        while DaaSProcessor::keep_listening(rx) {
+            for messageset in consumer.poll().unwrap().iter() {
+                for message in messageset.messages() {
+                    if let Ok(receipt) = DeliveryReceipt::from_serialized(message.value) {
+                        self.record(receipt);
+                    }
+
+                    if let Err(err) = consumer.consume_message(
+                        messageset.topic(),
+                        messageset.partition(),
+                        message.offset,
+                    ) {
+                        error!("{}", err);
+                    }
+                }
+            }
+            consumer.commit_consumed().unwrap();
+        }
+    }
+
+    /// Returns the latest receipt of each consumer group that has handled the DaaS document
+    ///
+    /// # Arguments
+    ///
+    /// * doc_id: String - The unique identifier of the DaaS document.</br>
+    pub fn get_receipts(&self, doc_id: String) -> Vec<DeliveryReceipt> {
+        self.receipts
+            .lock()
+            .unwrap()
+            .get(&doc_id)
+            .map(|groups| groups.values().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Returns the consumer groups that have processed the DaaS document
+    ///
+    /// # Arguments
+    ///
+    /// * doc_id: String - The unique identifier of the DaaS document.</br>
+    pub fn processed_by(&self, doc_id: String) -> Vec<String> {
+        self.consumers_with(doc_id, DeliveryOutcome::Processed)
+    }
+
+    /// Returns the consumer groups that could not process the DaaS document
+    ///
+    /// # Arguments
+    ///
+    /// * doc_id: String - The unique identifier of the DaaS document.</br>
+    pub fn failed_by(&self, doc_id: String) -> Vec<String> {
+        self.consumers_with(doc_id, DeliveryOutcome::Failed)
+    }
+
+    /// Returns the expected consumer groups that haven't processed the DaaS document yet
+    ///
+    /// # Arguments
+    ///
+    /// * doc_id: String - The unique identifier of the DaaS document.</br>
+    /// * expected: &[String] - The consumer groups that should process the DaaS document.</br>
+    pub fn pending(&self, doc_id: String, expected: &[String]) -> Vec<String> {
+        let processed = self.processed_by(doc_id);
+
+        expected
+            .iter()
+            .filter(|group| !processed.contains(group))
+            .cloned()
+            .collect()
+    }
+
+    // Returns the consumer groups whose latest receipt of the DaaS document has the outcome
+    fn consumers_with(&self, doc_id: String, outcome: DeliveryOutcome) -> Vec<String> {
+        self.get_receipts(doc_id)
+            .into_iter()
+            .filter(|r| r.outcome == outcome)
+            .map(|r| r.consumer_group)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eventing::broker::DaaSKafkaProcessor;
+    use crate::eventing::memory::InMemoryBroker;
+    use crate::testing;
+    use std::sync::mpsc::channel;
+    use std::thread;
+
+    // Processes the DaaS documents of the iStore and fails the others
+    struct StoreHandler {}
+
+    impl DaaSDocHandler for StoreHandler {
+        fn handle(
+            &self,
+            msg: DaaSProcessorMessage,
+            _client: Option<KafkaClient>,
+        ) -> Result<i32, DaaSProcessingError> {
+            match msg.doc.source_name == "iStore" {
+                true => Ok(1),
+                false => Err(DaaSProcessingError::UpsertError),
+            }
+        }
+    }
+
+    #[test]
+    fn test_receipt_serialization() {
+        let doc = testing::get_default_daas_doc();
+        let receipt = DeliveryReceipt::new(&doc, "billing".to_string(), DeliveryOutcome::Failed);
+
+        assert_eq!(
+            DeliveryReceipt::from_serialized(receipt.serialize().as_bytes()).unwrap(),
+            receipt
+        );
+        assert!(DeliveryReceipt::from_serialized(b"{}").is_err());
+    }
+
+    #[test]
+    fn test_tracker_keeps_latest() {
+        let doc = testing::get_default_daas_doc();
+        let tracker = DeliveryTracker::new();
+        let mut failed = DeliveryReceipt::new(&doc, "billing".to_string(), DeliveryOutcome::Failed);
+        failed.timestamp -= 10;
+        let processed =
+            DeliveryReceipt::new(&doc, "billing".to_string(), DeliveryOutcome::Processed);
+
+        tracker.record(processed);
+        tracker.record(failed);
+        tracker.record(DeliveryReceipt::new(
+            &doc,
+            "shipping".to_string(),
+            DeliveryOutcome::Failed,
+        ));
+
+        assert_eq!(
+            tracker.processed_by(doc._id.clone()),
+            vec!["billing".to_string()]
+        );
+        assert_eq!(
+            tracker.failed_by(doc._id.clone()),
+            vec!["shipping".to_string()]
+        );
+        assert_eq!(
+            tracker.pending(
+                doc._id.clone(),
+                &["billing".to_string(), "shipping".to_string()]
+            ),
+            vec!["shipping".to_string()]
+        );
+        assert!(tracker.get_receipts("unknown".to_string()).is_empty());
+    }
+
+    #[test]
+    fn test_handler_publishes_receipts() {
+        let broker = InMemoryBroker::new();
+        let receipts = DeliveryReceipts::new("billing".to_string(), broker.clone())
+            .with_topic("receipts".to_string());
+        let handler = receipts.handler(Arc::new(StoreHandler {}));
+        let mut ok = testing::get_default_daas_doc();
+        let mut bad = testing::get_daas_doc(
+            "Amazon".to_string(),
+            6000,
+            "order".to_string(),
+            "clothing".to_string(),
+        );
+        broker.broker_message(&mut ok, "orders").unwrap();
+        broker.broker_message(&mut bad, "orders").unwrap();
+
+        let (tx, rx) = channel();
+        let listener = broker.clone();
+        let handle = thread::spawn(move || {
+            listener.start_listening_with_handler("orders", &rx, handler);
+        });
+        while broker.receipts("receipts").len() < 2 {
+            thread::sleep(std::time::Duration::from_millis(10));
+        }
+        DaaSProcessor::stop_listening(&tx);
+        handle.join().unwrap();
+
+        let tracker = DeliveryTracker::new();
+        tracker.record_all(broker.receipts("receipts"));
+        assert_eq!(tracker.processed_by(ok._id), vec!["billing".to_string()]);
+        assert_eq!(tracker.failed_by(bad._id), vec!["billing".to_string()]);
+    }
+}
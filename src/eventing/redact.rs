@@ -0,0 +1,135 @@
+//! A broker that only sends redacted copies of the DaaS documents, (see `doc::deidentify`).
+//!
+//! The analytics topics are usually readable by many consumers, so the `RedactingBroker` removes the sensitive fields
+//! before the DaaS document is sent. The full DaaS document is only sent through the genesis topic and provisioned to
+//! the archive, (see `DaaSGenesisProcessorService::run_with_redaction()`).
+
+use super::*;
+use crate::doc::deidentify::DeidentificationRules;
+use crate::doc::DaaSDoc;
+use crate::eventing::broker::DaaSKafkaProcessor;
+use kafka::client::KafkaClient;
+use kafka::error::ErrorKind;
+
+/// Represents a broker that redacts the DaaS documents before they are sent
+#[derive(Clone)]
+pub struct RedactingBroker<B: DaaSKafkaProcessor> {
+    /// The broker that sends the redacted DaaS documents
+    pub broker: B,
+    /// Which fields and metadata are removed
+    pub rules: DeidentificationRules,
+}
+
+impl<B: DaaSKafkaProcessor> RedactingBroker<B> {
+    /// Constructor
+    ///
+    /// # Arguments
+    ///
+    /// * broker: B - The broker that sends the redacted DaaS documents.</br>
+    /// * rules: DeidentificationRules - Which fields and metadata are removed.</br>
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// extern crate daas;
+    ///
+    /// use daas::doc::deidentify::DeidentificationRules;
+    /// use daas::eventing::broker::DaaSKafkaProcessor;
+    /// use daas::eventing::memory::InMemoryBroker;
+    /// use daas::eventing::redact::RedactingBroker;
+    /// use daas::testing;
+    ///
+    /// fn main() {
+    ///     let rules = DeidentificationRules::new().with_field("status".to_string());
+    ///     let broker = RedactingBroker::new(InMemoryBroker::new(), rules);
+    ///     let mut doc = testing::get_default_daas_doc();
+    ///     broker.broker_message(&mut doc, "order").unwrap();
+    ///
+    ///     assert_eq!(broker.broker.messages("order")[0].data_obj, b"{}".to_vec());
+    /// }
+    /// ```
+    pub fn new(broker: B, rules: DeidentificationRules) -> RedactingBroker<B> {
+        RedactingBroker { broker, rules }
+    }
+}
+
+impl<B: DaaSKafkaProcessor> DaaSKafkaProcessor for RedactingBroker<B> {
+    /// Not supported, since the rules aren't available to redact the DaaS document
+    fn broker_message_with_client(
+        _client: KafkaClient,
+        _doc: &mut DaaSDoc,
+        _topic: &str,
+    ) -> Result<(), kafka::error::ErrorKind> {
+        Err(ErrorKind::Msg(
+            "a RedactingBroker can't broker without its rules".to_string(),
+        ))
+    }
+
+    /// Sends a redacted copy of the DaaS document, leaving the DaaS document itself unchanged
+    fn broker_message(
+        &self,
+        doc: &mut DaaSDoc,
+        topic: &str,
+    ) -> Result<(), kafka::error::ErrorKind> {
+        let mut redacted = self.rules.redact(doc);
+        debug!(
+            "Sending a redacted copy of DaaS document {} to {}.",
+            doc._id, topic
+        );
+        self.broker.broker_message(&mut redacted, topic)
+    }
+
+    /// Redacts the DaaS document once and sends the redacted copy to all the topics
+    fn broker_message_to_topics(
+        &self,
+        doc: &mut DaaSDoc,
+        topics: &[String],
+    ) -> Result<(), kafka::error::ErrorKind> {
+        let mut redacted = self.rules.redact(doc);
+        debug!(
+            "Sending a redacted copy of DaaS document {} to {:?}.",
+            doc._id, topics
+        );
+        self.broker.broker_message_to_topics(&mut redacted, topics)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::doc::deidentify::REDACTED_TAG;
+    use crate::eventing::memory::InMemoryBroker;
+    use crate::testing;
+
+    #[test]
+    fn test_broker_redacted_copy() {
+        let rules = DeidentificationRules::new().with_meta_key("client-ip".to_string());
+        let broker = RedactingBroker::new(InMemoryBroker::new(), rules);
+        let mut doc = testing::get_default_daas_doc();
+        doc.add_meta("client-ip".to_string(), "10.0.0.1".to_string());
+        broker.broker_message(&mut doc, "order").unwrap();
+
+        let sent = broker.broker.messages("order")[0].clone();
+        assert!(sent.has_tag(REDACTED_TAG.to_string()));
+        assert!(!sent.meta_data.contains_key("client-ip"));
+        // the DaaS document itself isn't redacted
+        assert_eq!(
+            doc.get_meta("client-ip".to_string()),
+            "10.0.0.1".to_string()
+        );
+    }
+
+    #[test]
+    fn test_broker_redacted_copy_to_topics() {
+        let rules = DeidentificationRules::new().with_field("status".to_string());
+        let broker = RedactingBroker::new(InMemoryBroker::new(), rules);
+        let mut doc = testing::get_default_daas_doc();
+        let topics = vec!["order".to_string(), "order.clothing".to_string()];
+        broker.broker_message_to_topics(&mut doc, &topics).unwrap();
+
+        for topic in topics.iter() {
+            assert_eq!(broker.broker.messages(topic)[0].data_obj, b"{}".to_vec());
+        }
+        assert_ne!(doc.data_obj, b"{}".to_vec());
+    }
+}
@@ -0,0 +1,564 @@
+//! Sequence numbers of the DaaS documents per source, so the consumers can detect missed or reordered events.
+//!
+//! When a `Sequencer` is registered with the DaaS listener, every DaaS document that is ingested is given the next
+//! number of its source (the source name and source uid) in the `sequence` of its envelope, starting at 1. The
+//! numbers are allocated from a `SequenceStore`, (e.g.: a local file so they survive restarts). When the DaaS document
+//! can't be ingested after its number was allocated, the listener gives the number back, (see `Sequencer::release()`)
+//! so the consumers don't see a gap. A number can only be given back while it is the last one of its source.
+//!
+//! On the processor side, `SequenceChecks::handler()` wraps the handler of a processor and compares the sequence of each
+//! DaaS document with the last one of its source. The `SequenceObserver`s are notified of the gaps (a sequence was
+//! skipped) and of the DaaS documents that arrive out of order (a sequence at or below the last one). The handler is
+//! always called, so the checks only report what the processor sees. The DaaS documents of a source are only kept in
+//! order by Kafka if they are published to the same partition, (see `PartitionStrategy::SourceName`).
+//!
+//! # Examples
+//!
+//! ```
+//! extern crate daas;
+//!
+//! use daas::eventing::sequence::{InMemorySequenceStore, SequenceCheck, SequenceChecks, Sequencer};
+//! use daas::testing;
+//! use std::sync::Arc;
+//!
+//! fn main() {
+//!     let sequencer = Sequencer::new(Arc::new(InMemorySequenceStore::new()));
+//!     let checks = SequenceChecks::new();
+//!     let mut first = testing::get_default_daas_doc();
+//!     let mut second = testing::get_default_daas_doc();
+//!     let mut third = testing::get_default_daas_doc();
+//!
+//!     sequencer.assign(&mut first).unwrap();
+//!     sequencer.assign(&mut second).unwrap();
+//!     sequencer.assign(&mut third).unwrap();
+//!
+//!     assert_eq!(checks.check(&first), SequenceCheck::InOrder);
+//!     // the second DaaS document was missed
+//!     assert_eq!(checks.check(&third), SequenceCheck::Gap { expected: 2 });
+//!     assert_eq!(checks.check(&second), SequenceCheck::OutOfOrder { last: 3 });
+//! }
+//! ```
+
+use super::*;
+use crate::doc::{DaaSDoc, SourceId};
+use crate::errors::daaserror::DaaSProcessingError;
+use crate::errors::SequenceError;
+use crate::service::processor::{DaaSDocHandler, DaaSDocHandlerRef, DaaSProcessorMessage};
+use kafka::client::KafkaClient;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+// The last sequence number of each source
+type Sequences = Arc<Mutex<HashMap<String, u64>>>;
+
+/// Returns the key of the source that the DaaS documents are numbered by, (e.g.: iStore~5000)
+///
+/// # Arguments
+///
+/// * source_name: &str - The name of the data source.</br>
+/// * source_uid: &SourceId - The unique identifier that the data source provides.</br>
+pub fn source_key(source_name: &str, source_uid: &SourceId) -> String {
+    format!("{}{}{}", source_name, DELIMITER, source_uid)
+}
+
+/// Stores the last sequence number of each source
+pub trait SequenceStore {
+    /// Allocates the next sequence number of the source, (the first number of a source is 1)
+    ///
+    /// # Arguments
+    ///
+    /// * source: &str - The key of the source, (see `source_key()`).</br>
+    fn next(&self, source: &str) -> Result<u64, SequenceError>;
+
+    /// Returns the last sequence number that was allocated for the source, (None if there is none yet)
+    ///
+    /// # Arguments
+    ///
+    /// * source: &str - The key of the source, (see `source_key()`).</br>
+    fn current(&self, source: &str) -> Result<Option<u64>, SequenceError>;
+
+    /// Gives back the sequence number if it is still the last one that was allocated for the source.
+    /// Returns true if it was given back.
+    ///
+    /// # Arguments
+    ///
+    /// * source: &str - The key of the source, (see `source_key()`).</br>
+    /// * sequence: u64 - The sequence number that isn't used.</br>
+    fn release(&self, source: &str, sequence: u64) -> Result<bool, SequenceError>;
+}
+
+/// A reference to a sequence store that can be shared by the requests of the listener
+pub type SequenceStoreRef = Arc<dyn SequenceStore + Send + Sync>;
+
+/// A sequence store that keeps the sequence numbers in memory, (e.g.: for tests). Clones share the same numbers.
+#[derive(Clone, Default)]
+pub struct InMemorySequenceStore {
+    sequences: Sequences,
+}
+
+impl InMemorySequenceStore {
+    /// Constructs an empty InMemorySequenceStore
+    pub fn new() -> InMemorySequenceStore {
+        InMemorySequenceStore::default()
+    }
+}
+
+impl SequenceStore for InMemorySequenceStore {
+    fn next(&self, source: &str) -> Result<u64, SequenceError> {
+        let mut sequences = self.sequences.lock().unwrap();
+        let next = sequences.get(source).copied().unwrap_or(0) + 1;
+        sequences.insert(source.to_string(), next);
+        Ok(next)
+    }
+
+    fn current(&self, source: &str) -> Result<Option<u64>, SequenceError> {
+        Ok(self.sequences.lock().unwrap().get(source).copied())
+    }
+
+    fn release(&self, source: &str, sequence: u64) -> Result<bool, SequenceError> {
+        let mut sequences = self.sequences.lock().unwrap();
+        match sequences.get(source) == Some(&sequence) {
+            true => {
+                sequences.insert(source.to_string(), sequence - 1);
+                Ok(true)
+            }
+            false => Ok(false),
+        }
+    }
+}
+
+/// A sequence store that keeps the sequence numbers of all the sources in a JSON file
+pub struct FileSequenceStore {
+    /// The path of the JSON file
+    pub path: String,
+    // serializes the read-modify-write of the file within the process
+    lock: Mutex<()>,
+}
+
+impl FileSequenceStore {
+    /// Constructor
+    ///
+    /// # Arguments
+    ///
+    /// * path: String - The path of the JSON file, (e.g.: ./tmp/sequences.json).</br>
+    pub fn new(path: String) -> FileSequenceStore {
+        FileSequenceStore {
+            path,
+            lock: Mutex::new(()),
+        }
+    }
+
+    fn read(&self) -> Result<HashMap<String, u64>, SequenceError> {
+        match fs::read(&self.path) {
+            Ok(content) => serde_json::from_slice(&content).map_err(|e| {
+                error!("The sequences {} are corrupt. Error: {}", self.path, e);
+                SequenceError
+            }),
+            Err(_) => Ok(HashMap::new()),
+        }
+    }
+
+    // Writes the sequences to a temporary file and then atomically renames it over the file
+    fn write(&self, sequences: &HashMap<String, u64>) -> Result<(), SequenceError> {
+        let tmp = format!("{}.tmp", self.path);
+        let rslt = Path::new(&self.path)
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::write(&tmp, serde_json::to_string(sequences).unwrap()))
+            .and_then(|_| fs::rename(&tmp, &self.path));
+
+        rslt.map_err(|e| {
+            error!(
+                "Could not write the sequences {} because of {}.",
+                self.path, e
+            );
+            let _ = fs::remove_file(&tmp);
+            SequenceError
+        })
+    }
+}
+
+impl SequenceStore for FileSequenceStore {
+    fn next(&self, source: &str) -> Result<u64, SequenceError> {
+        let _guard = self.lock.lock().unwrap();
+        let mut sequences = self.read()?;
+        let next = sequences.get(source).copied().unwrap_or(0) + 1;
+
+        sequences.insert(source.to_string(), next);
+        self.write(&sequences)?;
+        Ok(next)
+    }
+
+    fn current(&self, source: &str) -> Result<Option<u64>, SequenceError> {
+        Ok(self.read()?.get(source).copied())
+    }
+
+    fn release(&self, source: &str, sequence: u64) -> Result<bool, SequenceError> {
+        let _guard = self.lock.lock().unwrap();
+        let mut sequences = self.read()?;
+        if sequences.get(source) != Some(&sequence) {
+            return Ok(false);
+        }
+
+        sequences.insert(source.to_string(), sequence - 1);
+        self.write(&sequences)?;
+        Ok(true)
+    }
+}
+
+/// Allocates the sequence numbers of the DaaS documents at ingestion, (e.g.: registered as application data of the
+/// DaaS listener, `App::new().data(Sequencer::new(Arc::new(FileSequenceStore::new(path))))`)
+#[derive(Clone)]
+pub struct Sequencer {
+    store: SequenceStoreRef,
+}
+
+impl Sequencer {
+    /// Constructor
+    ///
+    /// # Arguments
+    ///
+    /// * store: SequenceStoreRef - The store of the sequence numbers.</br>
+    pub fn new(store: SequenceStoreRef) -> Sequencer {
+        Sequencer { store }
+    }
+
+    /// Gives the DaaS document the next sequence number of its source and returns it
+    ///
+    /// # Arguments
+    ///
+    /// * doc: &mut DaaSDoc - The DaaS document that is ingested.</br>
+    pub fn assign(&self, doc: &mut DaaSDoc) -> Result<u64, SequenceError> {
+        let sequence = self
+            .store
+            .next(&source_key(&doc.source_name, &doc.source_uid))?;
+        doc.sequence = Some(sequence);
+        Ok(sequence)
+    }
+
+    /// Gives back the sequence number of the DaaS document that couldn't be ingested, so it is assigned to the next
+    /// DaaS document of the source. Returns false if a later DaaS document of the source already has the next number,
+    /// (the gap remains).
+    ///
+    /// # Arguments
+    ///
+    /// * doc: &DaaSDoc - The DaaS document that couldn't be ingested.</br>
+    pub fn release(&self, doc: &DaaSDoc) -> Result<bool, SequenceError> {
+        match doc.sequence {
+            Some(sequence) => self
+                .store
+                .release(&source_key(&doc.source_name, &doc.source_uid), sequence),
+            None => Ok(false),
+        }
+    }
+}
+
+/// The outcome of comparing the sequence of a DaaS document with the last one of its source
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SequenceCheck {
+    /// The DaaS document doesn't have a sequence number
+    Unsequenced,
+    /// The DaaS document is the next one of its source, (or the first one that is seen)
+    InOrder,
+    /// The sequence numbers between the last one and the DaaS document were skipped
+    Gap {
+        /// The sequence number that was expected next
+        expected: u64,
+    },
+    /// The sequence number of the DaaS document is at or below the last one, (e.g.: a redelivered or reordered event)
+    OutOfOrder {
+        /// The last sequence number of the source
+        last: u64,
+    },
+}
+
+/// Trait for the observers of the sequence checks, (e.g.: for alerting on missed events)
+pub trait SequenceObserver {
+    /// Called when the sequence numbers before the DaaS document were skipped
+    fn on_gap(&self, _doc: &DaaSDoc, _expected: u64) {}
+    /// Called when the DaaS document arrives at or below the last sequence number of its source
+    fn on_out_of_order(&self, _doc: &DaaSDoc, _last: u64) {}
+}
+
+/// Represents the last sequence number that a processor has seen of each source. Clones share the same sequences.
+#[derive(Clone, Default)]
+pub struct SequenceChecks {
+    last: Sequences,
+    observers: Vec<Arc<dyn SequenceObserver + Send + Sync>>,
+}
+
+impl SequenceChecks {
+    /// Constructs the SequenceChecks without any observers
+    pub fn new() -> SequenceChecks {
+        SequenceChecks::default()
+    }
+
+    /// Adds an observer, which is notified after the observers that were added before it
+    pub fn with_observer<O: SequenceObserver + Send + Sync + 'static>(
+        mut self,
+        observer: O,
+    ) -> SequenceChecks {
+        self.observers.push(Arc::new(observer));
+        self
+    }
+
+    /// Returns the last sequence number that was seen of the source, (see `source_key()`)
+    ///
+    /// # Arguments
+    ///
+    /// * source: &str - The key of the source.</br>
+    pub fn last(&self, source: &str) -> Option<u64> {
+        self.last.lock().unwrap().get(source).copied()
+    }
+
+    /// Compares the sequence of the DaaS document with the last one of its source, notifies the observers and moves
+    /// the last sequence number of the source forward
+    ///
+    /// # Arguments
+    ///
+    /// * doc: &DaaSDoc - The DaaS document that is processed.</br>
+    pub fn check(&self, doc: &DaaSDoc) -> SequenceCheck {
+        let sequence = match doc.sequence {
+            Some(s) => s,
+            None => return SequenceCheck::Unsequenced,
+        };
+        let source = source_key(&doc.source_name, &doc.source_uid);
+
+        let check = {
+            let mut last = self.last.lock().unwrap();
+            let check = match last.get(&source).copied() {
+                Some(l) if sequence <= l => SequenceCheck::OutOfOrder { last: l },
+                Some(l) if sequence > l + 1 => SequenceCheck::Gap { expected: l + 1 },
+                _ => SequenceCheck::InOrder,
+            };
+            if sequence > last.get(&source).copied().unwrap_or(0) {
+                last.insert(source.clone(), sequence);
+            }
+            check
+        };
+
+        match check {
+            SequenceCheck::Gap { expected } => {
+                warn!(
+                    "DaaS document {} has sequence {} of {}, but {} was expected.",
+                    doc._id, sequence, source, expected
+                );
+                self.observers.iter().for_each(|o| o.on_gap(doc, expected));
+            }
+            SequenceCheck::OutOfOrder { last } => {
+                warn!(
+                    "DaaS document {} has sequence {} of {}, which is not after {}.",
+                    doc._id, sequence, source, last
+                );
+                self.observers
+                    .iter()
+                    .for_each(|o| o.on_out_of_order(doc, last));
+            }
+            _ => {}
+        }
+
+        check
+    }
+
+    /// Wraps the handler so that the sequence of each DaaS document is checked before the handler processes it
+    ///
+    /// # Arguments
+    ///
+    /// * handler: DaaSDocHandlerRef - The handler that processes the DaaS documents.</br>
+    pub fn handler(&self, handler: DaaSDocHandlerRef) -> DaaSDocHandlerRef {
+        Arc::new(SequenceCheckedHandler {
+            checks: self.clone(),
+            handler,
+        })
+    }
+}
+
+// Checks the sequence of the DaaS documents before passing them to the handler
+struct SequenceCheckedHandler {
+    checks: SequenceChecks,
+    handler: DaaSDocHandlerRef,
+}
+
+impl DaaSDocHandler for SequenceCheckedHandler {
+    fn on_start(&self) {
+        self.handler.on_start();
+    }
+
+    fn handle(
+        &self,
+        msg: DaaSProcessorMessage,
+        client: Option<KafkaClient>,
+    ) -> Result<i32, DaaSProcessingError> {
+        self.checks.check(&msg.doc);
+        self.handler.handle(msg, client)
+    }
+
+    fn on_error(&self, doc: &DaaSDoc, err: &DaaSProcessingError) {
+        self.handler.on_error(doc, err);
+    }
+
+    fn on_shutdown(&self) {
+        self.handler.on_shutdown();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eventing::headers::DaaSMessageHeaders;
+    use crate::testing;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct CountingHandler {
+        handled: AtomicUsize,
+    }
+
+    impl DaaSDocHandler for CountingHandler {
+        fn handle(
+            &self,
+            _msg: DaaSProcessorMessage,
+            _client: Option<KafkaClient>,
+        ) -> Result<i32, DaaSProcessingError> {
+            self.handled.fetch_add(1, Ordering::SeqCst);
+            Ok(1)
+        }
+    }
+
+    #[derive(Default)]
+    struct CountingObserver {
+        gaps: Arc<AtomicUsize>,
+        out_of_order: Arc<AtomicUsize>,
+    }
+
+    impl SequenceObserver for CountingObserver {
+        fn on_gap(&self, _doc: &DaaSDoc, _expected: u64) {
+            self.gaps.fetch_add(1, Ordering::SeqCst);
+        }
+
+        fn on_out_of_order(&self, _doc: &DaaSDoc, _last: u64) {
+            self.out_of_order.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    fn get_doc(uid: usize, sequence: Option<u64>) -> DaaSDoc {
+        let mut doc = testing::get_daas_doc(
+            "iStore".to_string(),
+            uid,
+            "order".to_string(),
+            "clothing".to_string(),
+        );
+        doc.sequence = sequence;
+        doc
+    }
+
+    #[test]
+    fn test_file_sequence_store() {
+        let store = FileSequenceStore::new(format!(
+            "./tmp/sequences-{}/sequences.json",
+            rand::random::<u32>()
+        ));
+
+        assert_eq!(store.current("iStore~5000").unwrap(), None);
+        assert_eq!(store.next("iStore~5000").unwrap(), 1);
+        assert_eq!(store.next("iStore~5000").unwrap(), 2);
+        assert_eq!(store.next("iStore~6000").unwrap(), 1);
+
+        // the sequences survive a restart
+        let reopened = FileSequenceStore::new(store.path.clone());
+        assert_eq!(reopened.current("iStore~5000").unwrap(), Some(2));
+
+        assert!(!store.release("iStore~5000", 1).unwrap());
+        assert!(store.release("iStore~5000", 2).unwrap());
+        assert_eq!(store.current("iStore~5000").unwrap(), Some(1));
+    }
+
+    #[test]
+    fn test_assign() {
+        let sequencer = Sequencer::new(Arc::new(InMemorySequenceStore::new()));
+        let mut doc = get_doc(5000, None);
+        let mut other = get_doc(6000, None);
+
+        assert_eq!(sequencer.assign(&mut doc).unwrap(), 1);
+        assert_eq!(sequencer.assign(&mut doc).unwrap(), 2);
+        assert_eq!(sequencer.assign(&mut other).unwrap(), 1);
+        assert_eq!(doc.sequence, Some(2));
+    }
+
+    #[test]
+    fn test_release() {
+        let sequencer = Sequencer::new(Arc::new(InMemorySequenceStore::new()));
+        let mut doc = get_doc(5000, None);
+        let mut later = get_doc(5000, None);
+
+        sequencer.assign(&mut doc).unwrap();
+        assert!(sequencer.release(&doc).unwrap());
+        assert_eq!(sequencer.assign(&mut doc).unwrap(), 1);
+
+        // the number can't be given back once the next one is allocated
+        sequencer.assign(&mut later).unwrap();
+        assert!(!sequencer.release(&doc).unwrap());
+        assert!(!sequencer.release(&get_doc(6000, None)).unwrap());
+    }
+
+    #[test]
+    fn test_check() {
+        let checks = SequenceChecks::new();
+
+        assert_eq!(
+            checks.check(&get_doc(5000, None)),
+            SequenceCheck::Unsequenced
+        );
+        assert_eq!(
+            checks.check(&get_doc(5000, Some(4))),
+            SequenceCheck::InOrder
+        );
+        assert_eq!(
+            checks.check(&get_doc(5000, Some(5))),
+            SequenceCheck::InOrder
+        );
+        assert_eq!(
+            checks.check(&get_doc(6000, Some(1))),
+            SequenceCheck::InOrder
+        );
+        assert_eq!(
+            checks.check(&get_doc(5000, Some(5))),
+            SequenceCheck::OutOfOrder { last: 5 }
+        );
+        assert_eq!(
+            checks.check(&get_doc(5000, Some(8))),
+            SequenceCheck::Gap { expected: 6 }
+        );
+        assert_eq!(checks.last("iStore~5000"), Some(8));
+    }
+
+    #[test]
+    fn test_handler() {
+        let observer = CountingObserver::default();
+        let (gaps, out_of_order) = (observer.gaps.clone(), observer.out_of_order.clone());
+        let checks = SequenceChecks::new().with_observer(observer);
+        let counting = Arc::new(CountingHandler {
+            handled: AtomicUsize::new(0),
+        });
+        let handler = checks.handler(counting.clone());
+
+        for sequence in [1, 3, 2].iter() {
+            let doc = get_doc(5000, Some(*sequence));
+            let msg = DaaSProcessorMessage {
+                offset: 0,
+                key: &[],
+                headers: DaaSMessageHeaders::from_doc(&doc),
+                doc,
+                topic: "order.clothing.iStore",
+                partition: 0,
+            };
+            assert!(handler.handle(msg, None).is_ok());
+        }
+
+        // the DaaS documents are always handled
+        assert_eq!(counting.handled.load(Ordering::SeqCst), 3);
+        assert_eq!(gaps.load(Ordering::SeqCst), 1);
+        assert_eq!(out_of_order.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,693 @@
+//! Service level agreements on how fast the DaaS documents of each category are processed, and the alerts that are
+//! fired when they are breached.
+//!
+//! An `SlaRule` states the stage that the DaaS documents whose category matches a pattern must reach within a
+//! duration of being ingested, (e.g.: "order.*" documents must be provisioned by the genesis consumers within 60s).
+//! The pattern is matched against `<category>.<subcategory>` where `*` matches anything, (a pattern without a `.`
+//! only matches the category). The stages are measured with the timestamps that are stamped in the metadata,
+//! (see `daas::eventing::latency`) and the delivery receipts of the consumer groups, (see `daas::eventing::receipt`).
+//!
+//! The `SlaWatchdog` watches the DaaS documents that are ingested, (the listener does so when the watchdog is
+//! registered as application data, e.g.: App::new().data(SlaWatchdog::new())) and fires its `SlaAlert`s when a
+//! DaaS document reaches a stage too late, or when the deadline passes before it does, (see `SlaWatchdog::start()`).
+//! The alerts can be written to the log (`LogAlert`), POSTed to a webhook (`WebhookAlert`), counted as a metric
+//! (`SlaMetrics`) or handled by any function.
+//!
+//! # Examples
+//!
+//! ```
+//! extern crate daas;
+//!
+//! use daas::eventing::latency::{INGESTED_AT_META, CONSUMED_AT_META};
+//! use daas::eventing::sla::{LogAlert, SlaMetrics, SlaRule, SlaStage, SlaWatchdog};
+//! use daas::testing;
+//! use std::time::Duration;
+//!
+//! fn main() {
+//!     let metrics = SlaMetrics::new();
+//!     let watchdog = SlaWatchdog::new()
+//!         .with_rule(SlaRule::new("order.*".to_string(), SlaStage::Consumed, Duration::from_secs(60)))
+//!         .with_alert(LogAlert)
+//!         .with_alert(metrics.clone());
+//!
+//!     let mut doc = testing::get_default_daas_doc();
+//!     doc.add_meta(INGESTED_AT_META.to_string(), "1553988607000".to_string());
+//!     doc.add_meta(CONSUMED_AT_META.to_string(), "1553988907000".to_string());
+//!     watchdog.observe(&doc);
+//!
+//!     assert_eq!(metrics.count("order.*", "order"), 1);
+//! }
+//! ```
+
+use super::*;
+use crate::doc::DaaSDoc;
+use crate::errors::daaserror::DaaSProcessingError;
+use crate::eventing::latency::{
+    now_millis, stamp, stamped_at, BROKERED_AT_META, CONSUMED_AT_META, INGESTED_AT_META,
+};
+use crate::eventing::receipt::{DeliveryOutcome, DeliveryReceipt};
+use crate::service::processor::{DaaSDocHandler, DaaSDocHandlerRef, DaaSProcessorMessage};
+use kafka::client::KafkaClient;
+use std::collections::BTreeMap;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// Represents the stage that the DaaS documents must reach
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SlaStage {
+    /// The DaaS document was brokered, (the brokered-at metadata)
+    Brokered,
+    /// The DaaS document was consumed by a processor, (the consumed-at metadata)
+    Consumed,
+    /// The consumer group (e.g.: genesis) processed the DaaS document, (its delivery receipt)
+    ProcessedBy(String),
+}
+
+impl SlaStage {
+    // Returns the time (Unix Epoch milliseconds) that the DaaS document reached the stage, if it is stamped
+    fn reached_at(&self, doc: &DaaSDoc) -> Option<u64> {
+        match self {
+            SlaStage::Brokered => stamped_at(doc, BROKERED_AT_META),
+            SlaStage::Consumed => stamped_at(doc, CONSUMED_AT_META),
+            SlaStage::ProcessedBy(_) => None,
+        }
+    }
+}
+
+/// Represents the stage that the DaaS documents of the matching categories must reach within a duration of being ingested
+#[derive(Debug, Clone, PartialEq)]
+pub struct SlaRule {
+    /// The pattern of the `<category>.<subcategory>` of the DaaS documents, (e.g.: order.*)
+    pub pattern: String,
+    /// The stage that the DaaS documents must reach
+    pub stage: SlaStage,
+    /// How long after being ingested the DaaS documents must reach the stage
+    pub within: Duration,
+}
+
+impl SlaRule {
+    /// Constructor
+    ///
+    /// # Arguments
+    ///
+    /// * pattern: String - The pattern of the `<category>.<subcategory>` of the DaaS documents, (e.g.: order.*).</br>
+    /// * stage: SlaStage - The stage that the DaaS documents must reach.</br>
+    /// * within: Duration - How long after being ingested the DaaS documents must reach the stage.</br>
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// extern crate daas;
+    ///
+    /// use daas::eventing::sla::{SlaRule, SlaStage};
+    /// use daas::testing;
+    /// use std::time::Duration;
+    ///
+    /// fn main() {
+    ///     let rule = SlaRule::new("order.*".to_string(), SlaStage::ProcessedBy("genesis".to_string()), Duration::from_secs(60));
+    ///
+    ///     assert!(rule.matches(&testing::get_default_daas_doc()));
+    /// }
+    /// ```
+    pub fn new(pattern: String, stage: SlaStage, within: Duration) -> SlaRule {
+        SlaRule {
+            pattern,
+            stage,
+            within,
+        }
+    }
+
+    /// Determines if the rule applies to the DaaS document
+    ///
+    /// # Arguments
+    ///
+    /// * doc: &DaaSDoc - The DaaS document.</br>
+    pub fn matches(&self, doc: &DaaSDoc) -> bool {
+        match self.pattern.contains('.') {
+            true => glob_match(
+                self.pattern.as_bytes(),
+                format!("{}.{}", doc.category, doc.subcategory).as_bytes(),
+            ),
+            false => glob_match(self.pattern.as_bytes(), doc.category.as_bytes()),
+        }
+    }
+
+    fn within_millis(&self) -> u64 {
+        self.within.as_millis() as u64
+    }
+}
+
+// Matches the text against the pattern, where `*` matches any sequence of characters
+fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    match (pattern.first(), text.first()) {
+        (None, None) => true,
+        (Some(b'*'), _) => {
+            glob_match(&pattern[1..], text) || (!text.is_empty() && glob_match(pattern, &text[1..]))
+        }
+        (Some(p), Some(t)) if p == t => glob_match(&pattern[1..], &text[1..]),
+        _ => false,
+    }
+}
+
+/// Represents a DaaS document that didn't reach the stage of an SLA rule in time
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SlaBreach {
+    /// The pattern of the rule that was breached
+    pub rule: String,
+    /// The stage of the rule
+    pub stage: SlaStage,
+    /// How long (milliseconds) the rule allows to reach the stage
+    pub within: u64,
+    /// The unique identifier of the DaaS document
+    pub doc_id: String,
+    /// The category of the DaaS document
+    pub category: String,
+    /// The subcategory of the DaaS document
+    pub subcategory: String,
+    /// The source name of the DaaS document
+    pub source_name: String,
+    /// The time (Unix Epoch milliseconds) the DaaS document was ingested
+    pub ingested_at: u64,
+    /// The time (Unix Epoch milliseconds) the DaaS document had to reach the stage by
+    pub deadline: u64,
+    /// The time (Unix Epoch milliseconds) the DaaS document reached the stage, (None if it hasn't yet)
+    pub reached_at: Option<u64>,
+}
+
+/// Trait for the hooks that are called when an SLA rule is breached
+pub trait SlaAlert: Send + Sync {
+    /// Called with each breach of an SLA rule
+    fn on_breach(&self, breach: &SlaBreach);
+}
+
+impl<F: Fn(&SlaBreach) + Send + Sync> SlaAlert for F {
+    fn on_breach(&self, breach: &SlaBreach) {
+        self(breach)
+    }
+}
+
+/// An alert that writes the breaches to the log
+pub struct LogAlert;
+
+impl SlaAlert for LogAlert {
+    fn on_breach(&self, breach: &SlaBreach) {
+        match breach.reached_at {
+            Some(t) => warn!(
+                "SLA {} breached: DaaS document {} reached {:?} after {}ms, (allowed {}ms).",
+                breach.rule,
+                breach.doc_id,
+                breach.stage,
+                t.saturating_sub(breach.ingested_at),
+                breach.within
+            ),
+            None => warn!(
+                "SLA {} breached: DaaS document {} didn't reach {:?} within {}ms.",
+                breach.rule, breach.doc_id, breach.stage, breach.within
+            ),
+        }
+    }
+}
+
+/// An alert that POSTs the serialized breaches to a webhook
+pub struct WebhookAlert {
+    /// The URL of the webhook
+    pub url: String,
+    client: reqwest::blocking::Client,
+}
+
+impl WebhookAlert {
+    /// Constructs a WebhookAlert
+    ///
+    /// # Arguments
+    ///
+    /// * url: String - The URL of the webhook, (e.g.: https://example.com/hooks/sla).</br>
+    /// * timeout: Duration - How long to wait for the webhook to respond.</br>
+    pub fn new(url: String, timeout: Duration) -> WebhookAlert {
+        WebhookAlert {
+            url,
+            client: reqwest::blocking::Client::builder()
+                .timeout(timeout)
+                .build()
+                .unwrap(),
+        }
+    }
+}
+
+impl SlaAlert for WebhookAlert {
+    fn on_breach(&self, breach: &SlaBreach) {
+        let rspns = self
+            .client
+            .post(&self.url)
+            .header("Content-Type", "application/json")
+            .body(serde_json::to_string(breach).unwrap())
+            .send();
+
+        match rspns {
+            Ok(r) if r.status().is_success() => {}
+            Ok(r) => error!("The webhook {} responded with {}.", self.url, r.status()),
+            Err(err) => error!("Could not call the webhook {}. Error: {}", self.url, err),
+        }
+    }
+}
+
+/// An alert that counts the breaches of each rule and category. Clones of the `SlaMetrics` share the same counts.
+#[derive(Clone, Default)]
+pub struct SlaMetrics {
+    counts: Arc<Mutex<BTreeMap<(String, String), u64>>>,
+}
+
+impl SlaMetrics {
+    /// Constructs an SlaMetrics without any breaches
+    pub fn new() -> SlaMetrics {
+        SlaMetrics::default()
+    }
+
+    /// Returns the number of breaches of the rule by the DaaS documents of the category
+    ///
+    /// # Arguments
+    ///
+    /// * rule: &str - The pattern of the rule, (e.g.: order.*).</br>
+    /// * category: &str - The category of the DaaS documents.</br>
+    pub fn count(&self, rule: &str, category: &str) -> u64 {
+        self.counts
+            .lock()
+            .unwrap()
+            .get(&(rule.to_string(), category.to_string()))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Renders the counts in the Prometheus text format, (e.g.: to be served by a metrics endpoint)
+    pub fn render(&self) -> String {
+        let mut text = String::from(
+            "# HELP daas_sla_breaches_total The breaches of the SLA rules by rule and category.\n# TYPE daas_sla_breaches_total counter\n",
+        );
+
+        for ((rule, category), n) in self.counts.lock().unwrap().iter() {
+            text.push_str(&format!(
+                "daas_sla_breaches_total{{rule=\"{}\",category=\"{}\"}} {}\n",
+                rule, category, n
+            ));
+        }
+
+        text
+    }
+}
+
+impl SlaAlert for SlaMetrics {
+    fn on_breach(&self, breach: &SlaBreach) {
+        *self
+            .counts
+            .lock()
+            .unwrap()
+            .entry((breach.rule.clone(), breach.category.clone()))
+            .or_insert(0) += 1;
+    }
+}
+
+/// Represents the watchdog that evaluates the SLA rules and fires the alerts when they are breached.
+/// Clones of the `SlaWatchdog` share the same watched DaaS documents.
+#[derive(Clone)]
+pub struct SlaWatchdog {
+    /// The SLA rules
+    pub rules: Vec<SlaRule>,
+    /// How often the deadlines are checked, (default: 10 seconds)
+    pub interval: Duration,
+    alerts: Vec<Arc<dyn SlaAlert>>,
+    // the DaaS documents that haven't reached the stage of a rule yet, by (document id, index of the rule)
+    pending: Arc<Mutex<BTreeMap<(String, usize), SlaBreach>>>,
+}
+
+impl Default for SlaWatchdog {
+    fn default() -> Self {
+        SlaWatchdog::new()
+    }
+}
+
+impl SlaWatchdog {
+    /// Constructs an SlaWatchdog without any rules or alerts
+    pub fn new() -> SlaWatchdog {
+        SlaWatchdog {
+            rules: Vec::new(),
+            interval: Duration::from_secs(10),
+            alerts: Vec::new(),
+            pending: Arc::new(Mutex::new(BTreeMap::new())),
+        }
+    }
+
+    /// Adds an SLA rule
+    ///
+    /// # Arguments
+    ///
+    /// * rule: SlaRule - The SLA rule.</br>
+    pub fn with_rule(mut self, rule: SlaRule) -> SlaWatchdog {
+        self.rules.push(rule);
+        self
+    }
+
+    /// Adds an alert that is fired when an SLA rule is breached, (e.g.: a `LogAlert`, `WebhookAlert`, `SlaMetrics` or a function)
+    ///
+    /// # Arguments
+    ///
+    /// * alert: A - The alert.</br>
+    pub fn with_alert<A: SlaAlert + 'static>(mut self, alert: A) -> SlaWatchdog {
+        self.alerts.push(Arc::new(alert));
+        self
+    }
+
+    /// Sets how often the deadlines are checked, (see `start()`)
+    pub fn with_interval(mut self, interval: Duration) -> SlaWatchdog {
+        self.interval = interval;
+        self
+    }
+
+    /// Returns the number of watched DaaS documents that haven't reached the stage of a rule yet
+    pub fn pending(&self) -> usize {
+        self.pending.lock().unwrap().len()
+    }
+
+    /// Watches the ingested DaaS document until it reaches the stages of the rules that apply to it.
+    /// Returns the number of rules that apply.
+    ///
+    /// # Arguments
+    ///
+    /// * doc: &DaaSDoc - The ingested DaaS document.</br>
+    pub fn watch(&self, doc: &DaaSDoc) -> usize {
+        let ingested_at = stamped_at(doc, INGESTED_AT_META).unwrap_or_else(now_millis);
+        let mut pending = self.pending.lock().unwrap();
+        let mut count = 0;
+
+        for (idx, rule) in self.rules.iter().enumerate() {
+            if rule.matches(doc) {
+                // a resubmission is measured from the first ingestion
+                pending
+                    .entry((doc._id.clone(), idx))
+                    .or_insert_with(|| SlaWatchdog::breach_of(rule, doc, ingested_at));
+                count += 1;
+            }
+        }
+
+        count
+    }
+
+    /// Evaluates the rules with the stages that are stamped in the metadata of the DaaS document, (e.g.: when it is
+    /// consumed) and fires the alerts for the stages that were reached too late. Returns the breaches.
+    ///
+    /// # Arguments
+    ///
+    /// * doc: &DaaSDoc - The stamped DaaS document.</br>
+    pub fn observe(&self, doc: &DaaSDoc) -> Vec<SlaBreach> {
+        let mut breaches = Vec::new();
+
+        for (idx, rule) in self.rules.iter().enumerate() {
+            if !rule.matches(doc) {
+                continue;
+            }
+            let reached_at = match rule.stage.reached_at(doc) {
+                Some(t) => t,
+                None => continue,
+            };
+            let watched = self.pending.lock().unwrap().remove(&(doc._id.clone(), idx));
+            let breach = match (watched, stamped_at(doc, INGESTED_AT_META)) {
+                (Some(b), _) => b,
+                (None, Some(ingested_at)) => SlaWatchdog::breach_of(rule, doc, ingested_at),
+                (None, None) => continue,
+            };
+
+            if reached_at > breach.deadline {
+                breaches.push(SlaBreach {
+                    reached_at: Some(reached_at),
+                    ..breach
+                });
+            }
+        }
+
+        self.fire(&breaches);
+        breaches
+    }
+
+    /// Evaluates the rules of the watched DaaS document with the delivery receipt of a consumer group, and fires the
+    /// alerts if it was processed too late. Returns the breaches.
+    ///
+    /// # Arguments
+    ///
+    /// * receipt: &DeliveryReceipt - The delivery receipt of the consumer group.</br>
+    pub fn observe_receipt(&self, receipt: &DeliveryReceipt) -> Vec<SlaBreach> {
+        if receipt.outcome != DeliveryOutcome::Processed {
+            return Vec::new();
+        }
+
+        let stage = SlaStage::ProcessedBy(receipt.consumer_group.clone());
+        let reached_at = receipt.timestamp * 1000;
+        let mut pending = self.pending.lock().unwrap();
+        let keys: Vec<(String, usize)> = pending
+            .iter()
+            .filter(|((id, _), b)| *id == receipt.doc_id && b.stage == stage)
+            .map(|(k, _)| k.clone())
+            .collect();
+        let breaches: Vec<SlaBreach> = keys
+            .iter()
+            .filter_map(|k| pending.remove(k))
+            // the receipts only have the time in seconds
+            .filter(|b| reached_at > b.deadline + 999)
+            .map(|b| SlaBreach {
+                reached_at: Some(reached_at),
+                ..b
+            })
+            .collect();
+        drop(pending);
+
+        self.fire(&breaches);
+        breaches
+    }
+
+    /// Fires the alerts for the watched DaaS documents whose deadlines have passed, (see `evaluate_at()`)
+    pub fn evaluate(&self) -> Vec<SlaBreach> {
+        self.evaluate_at(now_millis())
+    }
+
+    /// Fires the alerts for the watched DaaS documents that haven't reached the stage of a rule by its deadline, and
+    /// stops watching them. Returns the breaches.
+    ///
+    /// # Arguments
+    ///
+    /// * now: u64 - The current time in Unix Epoch milliseconds.</br>
+    pub fn evaluate_at(&self, now: u64) -> Vec<SlaBreach> {
+        let mut pending = self.pending.lock().unwrap();
+        let keys: Vec<(String, usize)> = pending
+            .iter()
+            .filter(|(_, b)| b.deadline < now)
+            .map(|(k, _)| k.clone())
+            .collect();
+        let breaches: Vec<SlaBreach> = keys.iter().filter_map(|k| pending.remove(k)).collect();
+        drop(pending);
+
+        self.fire(&breaches);
+        breaches
+    }
+
+    /// Wraps the handler so that the DaaS documents are stamped with the time they were consumed (unless they
+    /// already are) and the rules are evaluated before they are handled
+    ///
+    /// # Arguments
+    ///
+    /// * handler: DaaSDocHandlerRef - The handler that processes the DaaS documents.</br>
+    pub fn handler(&self, handler: DaaSDocHandlerRef) -> DaaSDocHandlerRef {
+        Arc::new(SlaHandler {
+            watchdog: self.clone(),
+            handler,
+        })
+    }
+
+    /// Starts checking the deadlines in a separate thread until a message is sent to stop, (see `stop()`)
+    pub fn start(&self) -> Sender<bool> {
+        let (tx, rx) = channel();
+        let watchdog = self.clone();
+
+        thread::spawn(move || watchdog.run(&rx));
+
+        tx
+    }
+
+    /// Stops the SlaWatchdog
+    ///
+    /// # Arguments
+    ///
+    /// * tx: Sender<bool> - The sender that was returned by `start()`.</br>
+    pub fn stop(tx: Sender<bool>) {
+        let _ = tx.send(true);
+    }
+
+    fn run(&self, rx: &Receiver<bool>) {
+        loop {
+            self.evaluate();
+
+            // wait for the next check, unless a message is sent to stop
+            match rx.recv_timeout(self.interval) {
+                Err(std::sync::mpsc::RecvTimeoutError::Timeout) => {}
+                _ => {
+                    info!("Shutting down SlaWatchdog ...");
+                    break;
+                }
+            }
+        }
+    }
+
+    fn breach_of(rule: &SlaRule, doc: &DaaSDoc, ingested_at: u64) -> SlaBreach {
+        SlaBreach {
+            rule: rule.pattern.clone(),
+            stage: rule.stage.clone(),
+            within: rule.within_millis(),
+            doc_id: doc._id.clone(),
+            category: doc.category.clone(),
+            subcategory: doc.subcategory.clone(),
+            source_name: doc.source_name.clone(),
+            ingested_at,
+            deadline: ingested_at + rule.within_millis(),
+            reached_at: None,
+        }
+    }
+
+    fn fire(&self, breaches: &[SlaBreach]) {
+        for breach in breaches.iter() {
+            for alert in self.alerts.iter() {
+                alert.on_breach(breach);
+            }
+        }
+    }
+}
+
+// Stamps the time the DaaS documents are consumed and evaluates the SLA rules
+struct SlaHandler {
+    watchdog: SlaWatchdog,
+    handler: DaaSDocHandlerRef,
+}
+
+impl DaaSDocHandler for SlaHandler {
+    fn on_start(&self) {
+        self.handler.on_start();
+    }
+
+    fn handle(
+        &self,
+        mut msg: DaaSProcessorMessage,
+        client: Option<KafkaClient>,
+    ) -> Result<i32, DaaSProcessingError> {
+        if stamped_at(&msg.doc, CONSUMED_AT_META).is_none() {
+            stamp(&mut msg.doc, CONSUMED_AT_META);
+        }
+        self.watchdog.observe(&msg.doc);
+        self.handler.handle(msg, client)
+    }
+
+    fn on_error(&self, doc: &DaaSDoc, err: &DaaSProcessingError) {
+        self.handler.on_error(doc, err);
+    }
+
+    fn on_shutdown(&self) {
+        self.handler.on_shutdown();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    fn get_rule(pattern: &str, stage: SlaStage) -> SlaRule {
+        SlaRule::new(pattern.to_string(), stage, Duration::from_secs(60))
+    }
+
+    fn get_stamped_doc(category: &str, subcategory: &str) -> DaaSDoc {
+        let mut doc = testing::get_daas_doc(
+            "iStore".to_string(),
+            5000,
+            category.to_string(),
+            subcategory.to_string(),
+        );
+        doc.add_meta(INGESTED_AT_META.to_string(), "1000".to_string());
+        doc
+    }
+
+    #[test]
+    fn test_matches() {
+        let doc = get_stamped_doc("order", "clothing");
+
+        assert!(get_rule("order.*", SlaStage::Consumed).matches(&doc));
+        assert!(get_rule("order", SlaStage::Consumed).matches(&doc));
+        assert!(get_rule("*.clothing", SlaStage::Consumed).matches(&doc));
+        assert!(get_rule("*", SlaStage::Consumed).matches(&doc));
+        assert!(!get_rule("order.shoes", SlaStage::Consumed).matches(&doc));
+        assert!(!get_rule("ord", SlaStage::Consumed).matches(&doc));
+    }
+
+    #[test]
+    fn test_observe() {
+        let breaches = Arc::new(Mutex::new(Vec::new()));
+        let fired = breaches.clone();
+        let watchdog = SlaWatchdog::new()
+            .with_rule(get_rule("order.*", SlaStage::Brokered))
+            .with_rule(get_rule("order.*", SlaStage::Consumed))
+            .with_alert(move |b: &SlaBreach| fired.lock().unwrap().push(b.clone()));
+        let mut doc = get_stamped_doc("order", "clothing");
+
+        assert_eq!(watchdog.watch(&doc), 2);
+        doc.add_meta(BROKERED_AT_META.to_string(), "2000".to_string());
+        assert!(watchdog.observe(&doc).is_empty());
+        assert_eq!(watchdog.pending(), 1);
+
+        doc.add_meta(CONSUMED_AT_META.to_string(), "61001".to_string());
+        let found = watchdog.observe(&doc);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].stage, SlaStage::Consumed);
+        assert_eq!(found[0].reached_at, Some(61001));
+        assert_eq!(watchdog.pending(), 0);
+        assert_eq!(*breaches.lock().unwrap(), found);
+    }
+
+    #[test]
+    fn test_observe_receipt() {
+        let metrics = SlaMetrics::new();
+        let stage = SlaStage::ProcessedBy("genesis".to_string());
+        let watchdog = SlaWatchdog::new()
+            .with_rule(get_rule("order.*", stage))
+            .with_alert(metrics.clone());
+        let doc = get_stamped_doc("order", "clothing");
+        watchdog.watch(&doc);
+
+        let mut receipt =
+            DeliveryReceipt::new(&doc, "billing".to_string(), DeliveryOutcome::Processed);
+        receipt.timestamp = 120;
+        assert!(watchdog.observe_receipt(&receipt).is_empty());
+        assert_eq!(watchdog.pending(), 1);
+
+        receipt.consumer_group = "genesis".to_string();
+        assert_eq!(watchdog.observe_receipt(&receipt).len(), 1);
+        assert_eq!(metrics.count("order.*", "order"), 1);
+        assert!(metrics
+            .render()
+            .contains("daas_sla_breaches_total{rule=\"order.*\",category=\"order\"} 1"));
+    }
+
+    #[test]
+    fn test_evaluate_at() {
+        let metrics = SlaMetrics::new();
+        let watchdog = SlaWatchdog::new()
+            .with_rule(get_rule("order.*", SlaStage::Consumed))
+            .with_alert(metrics.clone());
+        watchdog.watch(&get_stamped_doc("order", "clothing"));
+        watchdog.watch(&get_stamped_doc("customer", "retail"));
+
+        assert!(watchdog.evaluate_at(61000).is_empty());
+        let breaches = watchdog.evaluate_at(61001);
+        assert_eq!(breaches.len(), 1);
+        assert_eq!(breaches[0].reached_at, None);
+        assert_eq!(watchdog.pending(), 0);
+        // the breach is only alerted once
+        assert!(watchdog.evaluate_at(120000).is_empty());
+        assert_eq!(metrics.count("order.*", "order"), 1);
+    }
+}
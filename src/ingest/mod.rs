@@ -0,0 +1,13 @@
+//! The ingest module contains the connectors of the messaging protocols that the data sources push their records to,
+//! (e.g.: the MQTT brokers of the IoT fleets).
+//!
+//! The connectors implement the `DataSourceConnector` of the sources module, so a `SourceAgent` emits their records as
+//! DaaS documents and acknowledges the messages only after the sink accepted them, (see `DataSourceConnector::commit()`).
+
+use super::*;
+use crate::doc::SourceId;
+use crate::errors::RetrieveError;
+use crate::sources::{DataSourceConnector, SourceRecord};
+use std::time::Duration;
+
+pub mod mqtt;
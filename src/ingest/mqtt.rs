@@ -0,0 +1,832 @@
+//! A connector that subscribes to the topics of an MQTT broker, (MQTT 3.1.1) since the IoT devices rarely speak HTTP
+//! with the custom headers of the listener.
+//!
+//! The category, subcategory, source name and (optionally) the unique identifier of the records are the segments of
+//! the topics, using a topic pattern, (e.g.: daas/{category}/{subcategory}/{source}/{uid}). The connector subscribes
+//! to the topic filter of the pattern, (e.g.: daas/+/+/+/+) and each message that was published since the last poll is
+//! a record. When the pattern doesn't have a `{uid}`, the unique identifier is generated from the time the message was
+//! received. The `SourceAgent` wraps the records in DaaS documents with its Data Usage Agreements and a new Data
+//! Tracker Chain, and the `ListenerSink` feeds them to the processing of the listener.
+//!
+//! The messages of the quality of service 1 and 2 are acknowledged (PUBACK or PUBREC) when their records are
+//! committed, which is only after the sink accepted their DaaS documents. The messages that weren't acknowledged are
+//! redelivered by the broker, provided that the connector has a persistent session, (see `with_persistent_session()`),
+//! so a message is emitted at least once. A persistent session needs a stable client identifier, since the broker
+//! keeps the session by it.
+//!
+//! The connector uses the `rumqttc` client. The connection is encrypted with TLS, (see `with_tls()`), using the
+//! native TLS library, (OpenSSL on Linux). The username and password are only sent over TLS: a connector that has
+//! credentials but no TLS refuses to connect, rather than sending them in clear.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! extern crate daas;
+//!
+//! use daas::eventing::broker::DaaSKafkaBroker;
+//! use daas::ingest::mqtt::MqttConnector;
+//! use daas::sources::{ListenerSink, SourceAgent};
+//! use daas::testing;
+//!
+//! fn main() {
+//!     let connector = MqttConnector::new(
+//!         "localhost:8883".to_string(),
+//!         "devices".to_string(),
+//!         "daas/{category}/{subcategory}/{source}/{uid}".to_string(),
+//!     )
+//!     .with_tls()
+//!     .with_credentials("fleet".to_string(), "secret".to_string())
+//!     .with_persistent_session("daas-mqtt-bridge-1".to_string());
+//!     let agent = SourceAgent::new(
+//!         Box::new(connector),
+//!         "mqtt_bridge".to_string(),
+//!         testing::get_dua(),
+//!         Box::new(ListenerSink::new(DaaSKafkaBroker::default(), None)),
+//!     );
+//!     let tx = agent.start();
+//! }
+//! ```
+
+use super::*;
+use rumqttc::{
+    Client, Connection, Event, Incoming, MqttOptions, Outgoing, Publish, QoS, RecvTimeoutError,
+    SubscribeReasonCode, TlsConfiguration, Transport,
+};
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// the default ports of the broker
+const PORT: u16 = 1883;
+const TLS_PORT: u16 = 8883;
+// the maximum size of a control packet of MQTT 3.1.1
+const MAX_PACKET_SIZE: usize = 268_435_455;
+
+// the placeholders of the topic pattern
+const CATEGORY: &str = "{category}";
+const SUBCATEGORY: &str = "{subcategory}";
+const SOURCE: &str = "{source}";
+const UID: &str = "{uid}";
+
+/// Represents a connector that subscribes to the topics of an MQTT broker
+pub struct MqttConnector {
+    /// The address of the broker, (e.g.: localhost:1883)
+    pub address: String,
+    /// The name of the source, unless it is a segment of the topic, (e.g.: devices)
+    pub source_name: String,
+    /// The pattern of the topics, (e.g.: daas/{category}/{subcategory}/{source}/{uid})
+    pub topic_pattern: String,
+    /// The client identifier of the session, (default: None a random identifier for each clean session)
+    pub client_id: Option<String>,
+    /// Determines if the broker discards the session when the connector disconnects, (default: true)
+    pub clean_session: bool,
+    /// The username and password of the broker, (which are only sent over TLS)
+    pub credentials: Option<(String, String)>,
+    /// Determines if the connection is encrypted with TLS, (default: false)
+    pub tls: bool,
+    /// The PEM certificate of the certificate authority of the broker, (default: None the trusted roots of the system)
+    pub ca: Option<Vec<u8>>,
+    /// The quality of service of the subscription, (0 at most once, 1 at least once or 2 exactly once, default: 1)
+    pub qos: u8,
+    /// The content type of the payloads, (default: application/json)
+    pub content_type: String,
+    /// The keep alive of the session, (default: 30 seconds)
+    pub keep_alive: Duration,
+    /// How long a poll waits for the messages, (default: 100 milliseconds)
+    pub poll_timeout: Duration,
+    client: Option<(Client, Connection)>,
+    // the messages that were received while subscribing, (e.g.: the messages that the persistent session kept)
+    inbox: Vec<Publish>,
+    // the messages that aren't acknowledged yet by their packet identifiers
+    unacknowledged: HashMap<u16, Publish>,
+    sequence: u64,
+    // the number of connections, so the packet identifiers of a lost connection aren't acknowledged
+    connection: u64,
+}
+
+impl MqttConnector {
+    /// Constructs an MqttConnector
+    ///
+    /// # Arguments
+    ///
+    /// * address: String - The address of the broker, (e.g.: localhost:1883).</br>
+    /// * source_name: String - The name of the source, unless the topic pattern has a {source}.</br>
+    /// * topic_pattern: String - The pattern of the topics, (e.g.: daas/{category}/{subcategory}/{source}/{uid}).</br>
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// extern crate daas;
+    ///
+    /// use daas::ingest::mqtt::MqttConnector;
+    ///
+    /// fn main() {
+    ///     let connector = MqttConnector::new(
+    ///         "localhost:1883".to_string(),
+    ///         "devices".to_string(),
+    ///         "daas/{category}/{subcategory}/{source}/{uid}".to_string(),
+    ///     );
+    ///
+    ///     assert_eq!(connector.get_topic_filter(), "daas/+/+/+/+".to_string());
+    /// }
+    /// ```
+    pub fn new(address: String, source_name: String, topic_pattern: String) -> MqttConnector {
+        MqttConnector {
+            address,
+            source_name,
+            topic_pattern,
+            client_id: None,
+            clean_session: true,
+            credentials: None,
+            tls: false,
+            ca: None,
+            qos: 1,
+            content_type: "application/json".to_string(),
+            keep_alive: Duration::from_secs(30),
+            poll_timeout: Duration::from_millis(100),
+            client: None,
+            inbox: Vec::new(),
+            unacknowledged: HashMap::new(),
+            sequence: 0,
+            connection: 0,
+        }
+    }
+
+    /// Sets the client identifier of the session
+    pub fn with_client_id(mut self, client_id: String) -> MqttConnector {
+        self.client_id = Some(client_id);
+        self
+    }
+
+    /// Sets a persistent session, so the broker keeps the subscription and the messages that weren't acknowledged
+    /// while the connector is reconnecting or restarted
+    ///
+    /// # Arguments
+    ///
+    /// * client_id: String - The stable client identifier of the session, (e.g.: daas-mqtt-bridge-1).</br>
+    pub fn with_persistent_session(mut self, client_id: String) -> MqttConnector {
+        self.client_id = Some(client_id);
+        self.clean_session = false;
+        self
+    }
+
+    /// Sets the username and password of the broker, (the connection must be encrypted with TLS)
+    pub fn with_credentials(mut self, username: String, password: String) -> MqttConnector {
+        self.credentials = Some((username, password));
+        self
+    }
+
+    /// Encrypts the connection with TLS, verifying the broker with the trusted roots of the system
+    pub fn with_tls(mut self) -> MqttConnector {
+        self.tls = true;
+        self
+    }
+
+    /// Encrypts the connection with TLS, verifying the broker with the certificate authority
+    ///
+    /// # Arguments
+    ///
+    /// * ca: Vec<u8> - The PEM certificate of the certificate authority of the broker.</br>
+    pub fn with_ca(mut self, ca: Vec<u8>) -> MqttConnector {
+        self.tls = true;
+        self.ca = Some(ca);
+        self
+    }
+
+    /// Sets the quality of service of the subscription, (0, 1 or 2)
+    pub fn with_qos(mut self, qos: u8) -> MqttConnector {
+        self.qos = qos.min(2);
+        self
+    }
+
+    /// Sets the content type of the payloads
+    pub fn with_content_type(mut self, content_type: String) -> MqttConnector {
+        self.content_type = content_type;
+        self
+    }
+
+    /// Sets how long a poll waits for the messages
+    pub fn with_poll_timeout(mut self, poll_timeout: Duration) -> MqttConnector {
+        self.poll_timeout = poll_timeout;
+        self
+    }
+
+    /// Returns the topic filter of the topic pattern, where the placeholders are single level wildcards
+    pub fn get_topic_filter(&self) -> String {
+        self.topic_pattern
+            .split('/')
+            .map(|s| match s.starts_with('{') && s.ends_with('}') {
+                true => "+",
+                false => s,
+            })
+            .collect::<Vec<&str>>()
+            .join("/")
+    }
+
+    /// Returns the record of a message, or None if the topic doesn't match the topic pattern
+    ///
+    /// # Arguments
+    ///
+    /// * topic: &str - The topic of the message, (e.g.: daas/order/clothing/iStore/5000).</br>
+    /// * payload: Vec<u8> - The payload of the message.</br>
+    pub fn make_record(&mut self, topic: &str, payload: Vec<u8>) -> Option<SourceRecord> {
+        let segments: Vec<&str> = topic.split('/').collect();
+        let pattern: Vec<&str> = self.topic_pattern.split('/').collect();
+        if segments.len() != pattern.len() {
+            return None;
+        }
+
+        let mut category = None;
+        let mut subcategory = None;
+        let mut source_name = None;
+        let mut uid = None;
+        for (p, s) in pattern.iter().zip(segments.iter()) {
+            match *p {
+                CATEGORY => category = Some(s.to_string()),
+                SUBCATEGORY => subcategory = Some(s.to_string()),
+                SOURCE => source_name = Some(s.to_string()),
+                UID => uid = Some(s.to_string()),
+                p if p != *s => return None,
+                _ => {}
+            }
+        }
+
+        let uid = uid.unwrap_or_else(|| {
+            self.sequence += 1;
+            let received = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_millis();
+            format!("{}-{}", received, self.sequence)
+        });
+
+        Some(SourceRecord {
+            category: category?,
+            subcategory: subcategory?,
+            source_name,
+            source_uid: uid.parse().unwrap_or(SourceId::Text(uid)),
+            content_type: self.content_type.clone(),
+            data: payload,
+            deleted: false,
+            receipt: None,
+        })
+    }
+
+    // Returns the host and port of the address of the broker
+    fn get_host_port(&self) -> Result<(String, u16), RetrieveError> {
+        let default = match self.tls {
+            true => TLS_PORT,
+            false => PORT,
+        };
+        match self.address.rsplit_once(':') {
+            Some((host, port)) => match port.parse::<u16>() {
+                Ok(p) => Ok((host.to_string(), p)),
+                Err(_e) => {
+                    error!(
+                        "The address of the MQTT broker {} is invalid.",
+                        self.address
+                    );
+                    Err(RetrieveError)
+                }
+            },
+            None => Ok((self.address.clone(), default)),
+        }
+    }
+
+    // Returns the options of the session
+    fn get_options(&self) -> Result<MqttOptions, RetrieveError> {
+        let client_id = match (&self.client_id, self.clean_session) {
+            (Some(id), _) if !id.is_empty() => id.clone(),
+            (_, true) => format!("daas-{}", rand::random::<u32>()),
+            (_, false) => {
+                error!(
+                    "A persistent session with the MQTT broker {} needs a client identifier.",
+                    self.address
+                );
+                return Err(RetrieveError);
+            }
+        };
+        let (host, port) = self.get_host_port()?;
+
+        let mut options = MqttOptions::new(client_id, host, port);
+        options
+            .set_clean_session(self.clean_session)
+            .set_keep_alive(self.keep_alive.max(Duration::from_secs(1)))
+            .set_max_packet_size(MAX_PACKET_SIZE, MAX_PACKET_SIZE)
+            // the messages are acknowledged when their records are committed
+            .set_manual_acks(true);
+
+        if self.tls {
+            let tls = match &self.ca {
+                Some(ca) => TlsConfiguration::SimpleNative {
+                    ca: ca.clone(),
+                    client_auth: None,
+                },
+                None => TlsConfiguration::Native,
+            };
+            options.set_transport(Transport::tls_with_config(tls));
+        }
+
+        if let Some((username, password)) = &self.credentials {
+            if !self.tls {
+                error!(
+                    "Refused to send the credentials to the MQTT broker {} without TLS, (see with_tls()).",
+                    self.address
+                );
+                return Err(RetrieveError);
+            }
+            options.set_credentials(username.clone(), password.clone());
+        }
+
+        Ok(options)
+    }
+
+    // Connects to the broker and subscribes to the topic filter
+    fn connect(&mut self) -> Result<(Client, Connection), RetrieveError> {
+        let qos = match self.qos {
+            0 => QoS::AtMostOnce,
+            1 => QoS::AtLeastOnce,
+            _ => QoS::ExactlyOnce,
+        };
+        let (client, mut connection) = Client::new(self.get_options()?, 100);
+        client
+            .subscribe(self.get_topic_filter(), qos)
+            .map_err(|_e| RetrieveError)?;
+
+        // the acknowledgements of the broker are waited for
+        self.inbox.clear();
+        self.unacknowledged.clear();
+        loop {
+            match connection.recv_timeout(Duration::from_secs(10)) {
+                Ok(Ok(Event::Incoming(Incoming::SubAck(ack)))) => {
+                    if ack.return_codes.contains(&SubscribeReasonCode::Failure) {
+                        error!(
+                            "The MQTT broker {} refused the subscription to {}.",
+                            self.address,
+                            self.get_topic_filter()
+                        );
+                        return Err(RetrieveError);
+                    }
+                    break;
+                }
+                Ok(Ok(Event::Incoming(Incoming::Publish(publish)))) => self.inbox.push(publish),
+                Ok(Ok(_event)) => {}
+                Ok(Err(err)) => {
+                    error!(
+                        "Could not connect to the MQTT broker {}. Error: {}",
+                        self.address, err
+                    );
+                    return Err(RetrieveError);
+                }
+                Err(_err) => {
+                    error!(
+                        "The MQTT broker {} didn't acknowledge the subscription.",
+                        self.address
+                    );
+                    return Err(RetrieveError);
+                }
+            }
+        }
+
+        info!(
+            "Subscribed to {} on the MQTT broker {}.",
+            self.get_topic_filter(),
+            self.address
+        );
+        self.connection += 1;
+        Ok((client, connection))
+    }
+
+    // Adds the messages that were received during the poll timeout.
+    // The messages that were received before an error are kept.
+    fn receive(
+        &mut self,
+        connection: &mut Connection,
+        messages: &mut Vec<Publish>,
+    ) -> Result<(), RetrieveError> {
+        messages.append(&mut self.inbox);
+
+        // until nothing else is received during the poll timeout, (polling the connection also keeps the session alive)
+        loop {
+            match connection.recv_timeout(self.poll_timeout) {
+                Ok(Ok(Event::Incoming(Incoming::Publish(publish)))) => messages.push(publish),
+                Ok(Ok(_event)) => {}
+                Ok(Err(err)) => {
+                    warn!(
+                        "Lost the connection to the MQTT broker {}. Error: {}",
+                        self.address, err
+                    );
+                    return Err(RetrieveError);
+                }
+                Err(RecvTimeoutError::Timeout) => return Ok(()),
+                Err(RecvTimeoutError::Disconnected) => return Err(RetrieveError),
+            }
+        }
+    }
+
+    // Acknowledges the message, (the acknowledgement is sent when the connection is polled)
+    fn acknowledge(&mut self, publish: &Publish) -> Result<(), RetrieveError> {
+        let (client, _connection) = self.client.as_ref().ok_or(RetrieveError)?;
+        client.ack(publish).map_err(|err| {
+            error!(
+                "Could not acknowledge the message {} of the MQTT broker {}. Error: {}",
+                publish.pkid, self.address, err
+            );
+            RetrieveError
+        })
+    }
+}
+
+impl DataSourceConnector for MqttConnector {
+    fn source_name(&self) -> String {
+        self.source_name.clone()
+    }
+
+    fn poll(&mut self) -> Result<Vec<SourceRecord>, RetrieveError> {
+        let (client, mut connection) = match self.client.take() {
+            Some(c) => c,
+            None => self.connect()?,
+        };
+
+        // the connection is dropped on an error, so the connector reconnects (and subscribes again) on the next poll,
+        // but the messages that were received are returned (the broker redelivers them, since they aren't acknowledged)
+        let mut messages = Vec::new();
+        match self.receive(&mut connection, &mut messages) {
+            Ok(_) => self.client = Some((client, connection)),
+            Err(err) if messages.is_empty() => return Err(err),
+            Err(_err) => warn!(
+                "Lost the connection to the MQTT broker {} after {} messages.",
+                self.address,
+                messages.len()
+            ),
+        }
+
+        let mut records = Vec::new();
+        for publish in messages {
+            let payload = publish.payload.to_vec();
+            match self.make_record(&publish.topic, payload) {
+                Some(mut r) => {
+                    if publish.qos != QoS::AtMostOnce {
+                        r.receipt = Some(format!("{}:{}", self.connection, publish.pkid));
+                        self.unacknowledged.insert(publish.pkid, publish);
+                    }
+                    records.push(r);
+                }
+                None => {
+                    warn!(
+                        "Skipped the message of the topic {}, which doesn't match {}.",
+                        publish.topic, self.topic_pattern
+                    );
+                    // the skipped message is acknowledged, so it isn't redelivered
+                    if publish.qos != QoS::AtMostOnce {
+                        let _ = self.acknowledge(&publish);
+                    }
+                }
+            }
+        }
+
+        Ok(records)
+    }
+
+    fn commit(&mut self, record: &SourceRecord) -> Result<(), RetrieveError> {
+        let receipt = match &record.receipt {
+            Some(r) => r,
+            // the messages of the quality of service 0 aren't acknowledged
+            None => return Ok(()),
+        };
+        let (connection, packet_id) = receipt.split_once(':').ok_or(RetrieveError)?;
+        let packet_id = packet_id.parse::<u16>().map_err(|_e| RetrieveError)?;
+
+        // the packet identifiers are only valid for the connection that received the message
+        let publish = match connection == self.connection.to_string() && self.client.is_some() {
+            true => self.unacknowledged.remove(&packet_id),
+            false => None,
+        };
+        match publish {
+            Some(p) => self.acknowledge(&p),
+            None => {
+                warn!(
+                    "The message {} of the MQTT broker {} was received on a lost connection, so it is redelivered.",
+                    packet_id, self.address
+                );
+                Err(RetrieveError)
+            }
+        }
+    }
+
+    fn interval(&self) -> Option<Duration> {
+        // the poll already waits for the messages
+        Some(Duration::from_millis(1))
+    }
+}
+
+impl Drop for MqttConnector {
+    fn drop(&mut self) {
+        if let Some((client, mut connection)) = self.client.take() {
+            // the acknowledgements that are queued are sent before the DISCONNECT
+            if client.disconnect().is_ok() {
+                while let Ok(Ok(event)) = connection.recv_timeout(Duration::from_secs(1)) {
+                    if event == Event::Outgoing(Outgoing::Disconnect) {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{ErrorKind, Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+    use std::time::Instant;
+
+    // the types of the control packets
+    const CONNECT: u8 = 0x10;
+    const CONNACK: u8 = 0x20;
+    const PUBLISH: u8 = 0x30;
+    const PUBACK: u8 = 0x40;
+    const PUBREC: u8 = 0x50;
+    const PUBREL: u8 = 0x62;
+    const SUBSCRIBE: u8 = 0x82;
+    const SUBACK: u8 = 0x90;
+    const DISCONNECT: u8 = 0xe0;
+
+    // Encodes a UTF-8 string of the MQTT protocol, (the length and the bytes)
+    fn encode_string(s: &str) -> Vec<u8> {
+        let mut out = (s.len() as u16).to_be_bytes().to_vec();
+        out.extend(s.as_bytes());
+        out
+    }
+
+    fn write_packet(stream: &mut TcpStream, header: u8, body: &[u8]) {
+        let mut packet = vec![header];
+        let mut len = body.len();
+        loop {
+            let mut byte = (len % 128) as u8;
+            len /= 128;
+            if len > 0 {
+                byte |= 0x80;
+            }
+            packet.push(byte);
+            if len == 0 {
+                break;
+            }
+        }
+        packet.extend(body);
+        stream.write_all(&packet).unwrap();
+    }
+
+    // Reads the next control packet, or None if the connection is closed or idle
+    fn read_packet(stream: &mut TcpStream, buffer: &mut Vec<u8>) -> Option<(u8, Vec<u8>)> {
+        let mut chunk = [0u8; 4096];
+        let started = Instant::now();
+        loop {
+            if buffer.len() >= 2 {
+                let (mut len, mut multiplier, mut pos) = (0, 1, 1);
+                while let Some(byte) = buffer.get(pos) {
+                    len += usize::from(byte & 0x7f) * multiplier;
+                    multiplier *= 128;
+                    pos += 1;
+                    if byte & 0x80 == 0 {
+                        break;
+                    }
+                }
+                if buffer.len() >= pos + len {
+                    let header = buffer[0];
+                    let body = buffer[pos..pos + len].to_vec();
+                    buffer.drain(..pos + len);
+                    return Some((header, body));
+                }
+            }
+            match stream.read(&mut chunk) {
+                Ok(0) => return None,
+                Ok(n) => buffer.extend_from_slice(&chunk[..n]),
+                Err(err)
+                    if (err.kind() == ErrorKind::WouldBlock
+                        || err.kind() == ErrorKind::TimedOut)
+                        && started.elapsed() < Duration::from_secs(5) => {}
+                Err(_err) => return None,
+            }
+        }
+    }
+
+    fn get_connector(address: String) -> MqttConnector {
+        MqttConnector::new(
+            address,
+            "devices".to_string(),
+            "daas/{category}/{subcategory}/{source}/{uid}".to_string(),
+        )
+        .with_client_id("test".to_string())
+    }
+
+    // A broker that accepts the session and publishes the messages, (topic, qos).
+    // Returns the flags of the CONNECT and the acknowledged (PUBACK or PUBREC) packet identifiers.
+    fn start_broker(
+        messages: Vec<(&'static str, u8)>,
+    ) -> (String, std::thread::JoinHandle<(u8, Vec<u8>)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream
+                .set_read_timeout(Some(Duration::from_millis(500)))
+                .unwrap();
+            let mut buffer = Vec::new();
+
+            let (header, connect) = read_packet(&mut stream, &mut buffer).unwrap();
+            assert_eq!(header, CONNECT);
+            write_packet(&mut stream, CONNACK, &[0x00, 0x00]);
+            let (header, body) = read_packet(&mut stream, &mut buffer).unwrap();
+            assert_eq!(header, SUBSCRIBE);
+            write_packet(
+                &mut stream,
+                SUBACK,
+                &[body[0], body[1], *body.last().unwrap()],
+            );
+
+            for (i, (topic, qos)) in messages.iter().enumerate() {
+                let mut body = encode_string(topic);
+                if *qos > 0 {
+                    body.extend(&(i as u16 + 1).to_be_bytes());
+                }
+                body.extend(br#"{"status":"new"}"#);
+                write_packet(&mut stream, PUBLISH | (qos << 1), &body);
+            }
+
+            // the acknowledged packet identifiers
+            let mut acks = Vec::new();
+            while let Some((header, body)) = read_packet(&mut stream, &mut buffer) {
+                match header {
+                    PUBACK => acks.push(body[1]),
+                    PUBREC => {
+                        acks.push(body[1]);
+                        write_packet(&mut stream, PUBREL, &body);
+                    }
+                    DISCONNECT => break,
+                    _ => {}
+                }
+            }
+            (connect[7], acks)
+        });
+        (address, handle)
+    }
+
+    #[test]
+    fn test_topic_filter() {
+        let connector = get_connector("localhost:1883".to_string());
+        assert_eq!(connector.get_topic_filter(), "daas/+/+/+/+".to_string());
+    }
+
+    #[test]
+    fn test_make_record() {
+        let mut connector = get_connector("localhost:1883".to_string());
+
+        let record = connector
+            .make_record("daas/order/clothing/iStore/5000", b"{}".to_vec())
+            .unwrap();
+        assert_eq!(record.category, "order".to_string());
+        assert_eq!(record.subcategory, "clothing".to_string());
+        assert_eq!(record.source_name, Some("iStore".to_string()));
+        assert_eq!(record.source_uid, SourceId::from(5000));
+        assert!(connector
+            .make_record("other/order/clothing/iStore/5000", b"{}".to_vec())
+            .is_none());
+        assert!(connector
+            .make_record("daas/order/clothing/iStore", b"{}".to_vec())
+            .is_none());
+
+        // the unique identifier is generated
+        let mut connector = MqttConnector::new(
+            "localhost:1883".to_string(),
+            "devices".to_string(),
+            "sensors/{category}/{subcategory}".to_string(),
+        );
+        let first = connector
+            .make_record("sensors/reading/temperature", b"{}".to_vec())
+            .unwrap();
+        let second = connector
+            .make_record("sensors/reading/temperature", b"{}".to_vec())
+            .unwrap();
+        assert_eq!(first.source_name, None);
+        assert_ne!(first.source_uid, second.source_uid);
+    }
+
+    #[test]
+    fn test_poll() {
+        let (address, broker) = start_broker(vec![
+            ("daas/order/clothing/iStore/5000", 1),
+            ("daas/order/clothing/iStore/5001", 0),
+            ("other/topic", 0),
+        ]);
+        let mut connector = get_connector(address);
+        let mut records = Vec::new();
+        let mut attempts = 0;
+
+        while records.len() < 2 && attempts < 20 {
+            records.extend(connector.poll().unwrap());
+            attempts += 1;
+        }
+        for record in records.iter() {
+            assert!(connector.commit(record).is_ok());
+        }
+        drop(connector);
+
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].source_uid, SourceId::from(5000));
+        assert_eq!(records[1].data, br#"{"status":"new"}"#.to_vec());
+        // a clean session, (the flags of the CONNECT)
+        assert_eq!(broker.join().unwrap(), (0x02, vec![1]));
+    }
+
+    #[test]
+    fn test_poll_uncommitted() {
+        let (address, broker) = start_broker(vec![("daas/order/clothing/iStore/5000", 1)]);
+        let mut connector = get_connector(address).with_persistent_session("daas-test".to_string());
+        let mut records = Vec::new();
+        let mut attempts = 0;
+
+        while records.is_empty() && attempts < 20 {
+            records.extend(connector.poll().unwrap());
+            attempts += 1;
+        }
+        drop(connector);
+
+        // the message isn't acknowledged, so the broker redelivers it
+        assert_eq!(records.len(), 1);
+        assert_eq!(broker.join().unwrap(), (0x00, Vec::new()));
+    }
+
+    #[test]
+    fn test_persistent_session_client_id() {
+        let (address, _broker) = start_broker(Vec::new());
+        let mut connector = MqttConnector::new(
+            address,
+            "devices".to_string(),
+            "daas/{category}/{subcategory}/{source}/{uid}".to_string(),
+        );
+        connector.clean_session = false;
+
+        assert!(connector.poll().is_err());
+    }
+
+    #[test]
+    fn test_poll_exactly_once() {
+        let (address, broker) = start_broker(vec![("daas/order/clothing/iStore/5000", 2)]);
+        let mut connector = get_connector(address).with_qos(2);
+        let mut records = Vec::new();
+        let mut attempts = 0;
+
+        while records.is_empty() && attempts < 20 {
+            records.extend(connector.poll().unwrap());
+            attempts += 1;
+        }
+        assert!(connector.commit(&records[0]).is_ok());
+        // the packet identifier is only acknowledged once
+        assert!(connector.commit(&records[0]).is_err());
+        drop(connector);
+
+        // the message is received (PUBREC) when its record is committed
+        assert_eq!(broker.join().unwrap(), (0x02, vec![1]));
+    }
+
+    #[test]
+    fn test_credentials_without_tls() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let mut connector =
+            get_connector(address).with_credentials("fleet".to_string(), "secret".to_string());
+
+        assert!(connector.poll().is_err());
+        // the credentials weren't sent, since the connector didn't even connect
+        assert!(listener.accept().is_err());
+    }
+
+    #[test]
+    fn test_get_host_port() {
+        let connector = get_connector("broker.example.com".to_string());
+        assert_eq!(
+            connector.get_host_port().unwrap(),
+            ("broker.example.com".to_string(), 1883)
+        );
+        let connector = connector.with_tls();
+        assert_eq!(
+            connector.get_host_port().unwrap(),
+            ("broker.example.com".to_string(), 8883)
+        );
+        assert!(get_connector("localhost:mqtt".to_string())
+            .get_host_port()
+            .is_err());
+    }
+
+    #[test]
+    fn test_poll_no_broker() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        drop(listener);
+
+        assert!(get_connector(address).poll().is_err());
+    }
+}
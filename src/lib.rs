@@ -1,19 +1,29 @@
-extern crate env_logger;
+// the derive macros refer to the crate by its name, (see daas-derive)
 extern crate futures;
 extern crate log;
 extern crate pbd;
+extern crate self as daas;
 #[macro_use]
 extern crate serde_derive;
+#[cfg(feature = "service")]
 extern crate actix_web;
 extern crate base64;
+#[cfg(feature = "security")]
 extern crate openssl;
 extern crate rand;
+#[cfg(feature = "http")]
+extern crate reqwest;
+#[cfg(feature = "s3")]
 extern crate rusoto_core;
+#[cfg(feature = "s3")]
 extern crate rusoto_s3;
 extern crate serde_json;
+#[cfg(any(feature = "kafka", feature = "s3"))]
 extern crate tokio;
 
+#[cfg(feature = "kafka")]
 use async_trait::async_trait;
+#[cfg(feature = "service")]
 use futures::future::{err, ok, Ready};
 use log::*;
 use std::env;
@@ -21,10 +31,48 @@ use std::time::SystemTime;
 
 pub const DELIMITER: &'static str = "~";
 
+// Formats the Unix time as the date YYYY MM DD joined by the separator, (UTC)
+pub(crate) fn format_date(secs: u64, separator: char) -> String {
+    let days = (secs / 86400) as i64 + 719468;
+    let era = days / 146097;
+    let doe = days - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}{sep}{:02}{sep}{:02}",
+        year,
+        month,
+        day,
+        sep = separator
+    )
+}
+
 #[macro_use]
 pub mod macros;
+pub mod audit;
+#[cfg(feature = "chaos")]
+pub mod chaos;
+pub mod compliance;
+pub mod config;
 pub mod doc;
 pub mod errors;
+#[cfg(feature = "kafka")]
 pub mod eventing;
+#[cfg(feature = "service")]
+pub mod ingest;
+#[cfg(feature = "search")]
+pub mod search;
+#[cfg(any(feature = "security", feature = "security-rustcrypto"))]
+pub mod security;
 pub mod service;
+#[cfg(feature = "kafka")]
+pub mod sinks;
+#[cfg(feature = "service")]
+pub mod sources;
 pub mod storage;
+pub mod testing;
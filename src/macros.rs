@@ -1,6 +1,15 @@
 use futures::future::{err, ok, Ready};
 use std::time::SystemTime;
 
+// The dependencies of the exported macros, so the crates that use the macros don't have to import them
+#[doc(hidden)]
+#[cfg(feature = "service")]
+pub mod __private {
+    pub use actix_web::{dev::Payload, FromRequest, HttpRequest};
+    pub use futures::future::{err, ok, Ready};
+    pub use log::error;
+}
+
 #[macro_export]
 macro_rules! author_struct {
     ( $a:ident ) => {
@@ -34,7 +43,7 @@ macro_rules! author_fn_new {
 #[macro_export]
 macro_rules! author_fn_set_name {
     () => {
-        fn set_name(&mut self, name: String) -> Result<Self, MissingAuthorError> {
+        fn set_name(&mut self, name: String) -> Result<Self, $crate::errors::MissingAuthorError> {
             self.name = name;
             Ok(self.clone())
         }
@@ -44,28 +53,29 @@ macro_rules! author_fn_set_name {
 #[macro_export]
 macro_rules! author_from_request {
     ( $a:ty ) => {
-        impl FromRequest for $a {
+        impl $crate::macros::__private::FromRequest for $a {
             type Config = ();
-            type Future = Ready<Result<Self, Self::Error>>;
-            type Error = LocalError;
+            type Future = $crate::macros::__private::Ready<Result<Self, Self::Error>>;
+            type Error = $crate::service::extractor::LocalError;
             // convert request to future self
             fn from_request(
-                req: &HttpRequest,
-                payload: &mut actix_web::dev::Payload,
+                req: &$crate::macros::__private::HttpRequest,
+                payload: &mut $crate::macros::__private::Payload,
             ) -> Self::Future {
+                use $crate::service::extractor::AuthorExtractor;
                 let mut author = <$a>::new();
 
-                match author.extract_author(req, payload) {
-                    Ok(name) => match author.set_name(name) {
-                        Ok(_) => ok(author),
+                match author.extract_identity(req, payload) {
+                    Ok(identity) => match author.set_name(identity.id) {
+                        Ok(_) => $crate::macros::__private::ok(author),
                         Err(e) => {
-                            error!("{}", e);
-                            err(e)
+                            $crate::macros::__private::error!("{}", e);
+                            $crate::macros::__private::err(e)
                         }
                     },
                     Err(e) => {
-                        error!("{}", e);
-                        err(e)
+                        $crate::macros::__private::error!("{}", e);
+                        $crate::macros::__private::err(e)
                     }
                 }
             }
@@ -88,9 +98,8 @@ macro_rules! get_unix_now {
 mod tests {
     use super::*;
     use crate::errors::MissingAuthorError;
-    use crate::service::extractor::{AuthorExtractor, LocalError};
-    use actix_web::{FromRequest, HttpRequest};
-    use log::*;
+    use crate::service::extractor::AuthorExtractor;
+    use actix_web::HttpRequest;
     use std::{thread, time};
 
     #[test]
@@ -0,0 +1,872 @@
+//! The search module provides full-text and tag searching of DaaS documents across categories (requires the `search` feature).
+//!
+//! The following attributes of the DaaS document are indexed:
+//!
+//! + `id`, `rev`, `category`, `subcategory`, `source_name`, `author`, `tag` and `dua` (exact values), where `dua` is
+//!   the name of a data usage agreement
+//! + `meta` - the metadata, (e.g.: `meta.content-type:application/json`)
+//! + `data` - the fields of a JSON data object, (e.g.: `data.status:new`)
+//! + all the above values as free text, (e.g.: `leather`)
+//!
+//! The indexed DaaS documents are committed in batches, (see `with_batch_size()` and `with_commit_interval()`), so they
+//! can be searched once their batch is committed, (or `commit()` is called).
+//!
+//! The search service only returns the DaaS documents that have a data usage agreement for the purpose of the request,
+//! (the `X-DaaS-Purpose` header) like the retrieve service of the listener. When an `AccessPolicy` is registered as
+//! application data, the requester must also have a role that is authorized for the purpose.
+//!
+//! # Examples
+//!
+//! ```
+//! extern crate pbd;
+//! extern crate daas;
+//!
+//! use pbd::dua::DUA;
+//! use pbd::dtc::Tracker;
+//! use daas::doc::DaaSDoc;
+//! use daas::search::DaaSSearchIndex;
+//!
+//! fn main() {
+//!     let src = "iStore".to_string();
+//!     let uid = 5000;
+//!     let cat = "order".to_string();
+//!     let sub = "clothing".to_string();
+//!     let auth = "istore_app".to_string();
+//!     let mut dua = Vec::new();
+//!     dua.push(DUA::new("billing".to_string(),"https://dua.org/agreements/v1/billing.pdf".to_string(),1553988607));
+//!     let tracker = Tracker::new(DaaSDoc::make_id(cat.clone(), sub.clone(), src.clone(), uid.clone()));
+//!     let data = String::from(r#"{"product": "leather coat", "status": "new"}"#).as_bytes().to_vec();
+//!     let mut doc = DaaSDoc::new(src, uid, cat, sub, auth, dua, tracker, data);
+//!     doc.add_tag("priority".to_string());
+//!
+//!     let index = DaaSSearchIndex::new_in_ram().unwrap();
+//!     index.index_doc(&doc).unwrap();
+//!     index.commit().unwrap();
+//!
+//!     assert_eq!(index.search("tag:priority AND data.status:new", 10).unwrap().len(), 1);
+//!     assert_eq!(index.search_page_for("billing", "data.status:new", None, 10).unwrap().items.len(), 1);
+//!     assert_eq!(index.search_page_for("marketing", "data.status:new", None, 10).unwrap().items.len(), 0);
+//! }
+//! ```
+
+use crate::doc::DaaSDoc;
+use crate::errors::*;
+use crate::service::access::{AccessDecision, AccessPolicy, PURPOSE_HEADER};
+use crate::service::extractor::AuthorExtractor;
+use crate::storage::{decode_cursor, encode_cursor, DaaSDocStorage, Page};
+use actix_web::web::{Data, Query};
+use actix_web::{http, HttpRequest, HttpResponse};
+use log::*;
+use serde_json::{json, Map, Value};
+use std::fs;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tantivy::collector::TopDocs;
+use tantivy::query::{BooleanQuery, Occur, Query as TantivyQuery, QueryParser, TermQuery};
+use tantivy::schema::{Field, IndexRecordOption, Schema, STORED, STRING, TEXT};
+use tantivy::{Index, IndexReader, IndexWriter, ReloadPolicy, TantivyDocument, Term};
+
+/// The header of the search service's response that holds the cursor of the next page of hits
+pub const NEXT_CURSOR_HEADER: &str = "X-DaaS-Next-Cursor";
+
+// The amount of memory (bytes) the index writer may use
+const WRITER_HEAP_SIZE: usize = 50_000_000;
+
+/// Represents a DaaS document that matched a search query
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SearchHit {
+    /// The unique identifier
+    pub _id: String,
+    /// The revision number
+    pub _rev: Option<String>,
+    /// The name of the category (e.g.: order)
+    pub category: String,
+    /// The name of the subcategory (e.g.: clothing)
+    pub subcategory: String,
+    /// The name of the data source
+    pub source_name: String,
+    /// The relevance of the DaaS document to the query
+    pub score: f32,
+}
+
+/// The query string parameters of the search service
+#[derive(Deserialize)]
+pub struct SearchParams {
+    /// The search query, (e.g.: `tag:priority AND data.status:new`)
+    pub q: String,
+    /// The maximum number of hits to return (default: 20)
+    pub limit: Option<usize>,
+    /// The cursor of the previous page, (i.e.: the `X-DaaS-Next-Cursor` header of its response)
+    pub cursor: Option<String>,
+}
+
+/// A full-text search index of DaaS documents
+pub struct DaaSSearchIndex {
+    /// The number of indexed DaaS documents that are committed together, (default: 1000)
+    pub batch_size: usize,
+    /// How long the indexed DaaS documents wait at most to be committed, (default: 1 second)
+    pub commit_interval: Duration,
+    index: Index,
+    reader: IndexReader,
+    writer: Mutex<Batch>,
+    id: Field,
+    dua: Field,
+    text: Field,
+}
+
+// The index writer, with the number of DaaS documents that aren't committed yet and the time of the last commit
+struct Batch {
+    writer: IndexWriter,
+    pending: usize,
+    committed: Instant,
+}
+
+impl DaaSSearchIndex {
+    /// Constructs a search index that is held in memory, (e.g.: for testing)
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// extern crate daas;
+    ///
+    /// use daas::search::DaaSSearchIndex;
+    ///
+    /// fn main() {
+    ///     let index = DaaSSearchIndex::new_in_ram().unwrap();
+    /// }
+    /// ```
+    pub fn new_in_ram() -> Result<DaaSSearchIndex, SearchError> {
+        DaaSSearchIndex::from_index(Index::create_in_ram(DaaSSearchIndex::schema()))
+    }
+
+    /// Constructs a search index that is persisted in a directory (the directory is created if it doesn't exist)
+    ///
+    /// # Arguments
+    ///
+    /// * dir_path: String - The location of the directory where to store the search index.</br>
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// extern crate daas;
+    ///
+    /// use daas::search::DaaSSearchIndex;
+    ///
+    /// fn main() {
+    ///     let index = DaaSSearchIndex::open("./tmp/search-index".to_string()).unwrap();
+    /// }
+    /// ```
+    pub fn open(dir_path: String) -> Result<DaaSSearchIndex, SearchError> {
+        if let Err(err) = fs::create_dir_all(&dir_path) {
+            error!(
+                "Could not create the search index directory {}. {}",
+                dir_path, err
+            );
+            return Err(SearchError);
+        }
+
+        let dir = match tantivy::directory::MmapDirectory::open(&dir_path) {
+            Ok(d) => d,
+            Err(err) => {
+                error!("Could not open the search index {}. {}", dir_path, err);
+                return Err(SearchError);
+            }
+        };
+
+        match Index::open_or_create(dir, DaaSSearchIndex::schema()) {
+            Ok(index) => DaaSSearchIndex::from_index(index),
+            Err(err) => {
+                error!("Could not open the search index {}. {}", dir_path, err);
+                Err(SearchError)
+            }
+        }
+    }
+
+    fn from_index(index: Index) -> Result<DaaSSearchIndex, SearchError> {
+        let schema = index.schema();
+        let reader = index
+            .reader_builder()
+            .reload_policy(ReloadPolicy::Manual)
+            .try_into();
+        let writer = index.writer(WRITER_HEAP_SIZE);
+
+        match (reader, writer) {
+            (Ok(reader), Ok(writer)) => Ok(DaaSSearchIndex {
+                batch_size: 1000,
+                commit_interval: Duration::from_secs(1),
+                id: schema.get_field("id").unwrap(),
+                dua: schema.get_field("dua").unwrap(),
+                text: schema.get_field("text").unwrap(),
+                index,
+                reader,
+                writer: Mutex::new(Batch {
+                    writer,
+                    pending: 0,
+                    committed: Instant::now(),
+                }),
+            }),
+            _ => {
+                error!("Could not create the reader and writer for the search index.");
+                Err(SearchError)
+            }
+        }
+    }
+
+    /// Sets the number of indexed DaaS documents that are committed together
+    ///
+    /// # Arguments
+    ///
+    /// * batch_size: usize - The number of DaaS documents, (e.g.: 1 commits every DaaS document).</br>
+    pub fn with_batch_size(mut self, batch_size: usize) -> DaaSSearchIndex {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Sets how long the indexed DaaS documents wait at most to be committed, (the batch is committed when a DaaS
+    /// document is indexed or searched after the interval)
+    ///
+    /// # Arguments
+    ///
+    /// * commit_interval: Duration - The interval, (e.g.: 1 second).</br>
+    pub fn with_commit_interval(mut self, commit_interval: Duration) -> DaaSSearchIndex {
+        self.commit_interval = commit_interval;
+        self
+    }
+
+    /// Commits the indexed DaaS documents, so they can be searched
+    pub fn commit(&self) -> Result<(), SearchError> {
+        let mut batch = self.writer.lock().unwrap();
+        self.commit_batch(&mut batch)
+    }
+
+    fn commit_batch(&self, batch: &mut Batch) -> Result<(), SearchError> {
+        if batch.pending == 0 {
+            return Ok(());
+        }
+
+        match batch.writer.commit().and_then(|_| self.reader.reload()) {
+            Ok(_) => {
+                debug!("Committed {} DaaS documents for searching.", batch.pending);
+                batch.pending = 0;
+                batch.committed = Instant::now();
+                Ok(())
+            }
+            Err(err) => {
+                error!("Could not commit the search index. {}", err);
+                Err(SearchError)
+            }
+        }
+    }
+
+    // Determines if the batch is full or has waited for the commit interval
+    fn is_due(&self, batch: &Batch) -> bool {
+        batch.pending >= self.batch_size
+            || (batch.pending > 0 && batch.committed.elapsed() >= self.commit_interval)
+    }
+
+    fn schema() -> Schema {
+        let mut builder = Schema::builder();
+        builder.add_text_field("id", STRING | STORED);
+        builder.add_text_field("rev", STRING | STORED);
+        builder.add_text_field("category", STRING | STORED);
+        builder.add_text_field("subcategory", STRING | STORED);
+        builder.add_text_field("source_name", STRING | STORED);
+        builder.add_text_field("author", STRING);
+        builder.add_text_field("tag", STRING);
+        builder.add_text_field("dua", STRING);
+        builder.add_json_field("meta", TEXT);
+        builder.add_json_field("data", TEXT);
+        builder.add_text_field("text", TEXT);
+        builder.build()
+    }
+
+    // Collects the values of a JSON value as free text
+    fn collect_text(value: &Value, text: &mut Vec<String>) {
+        match value {
+            Value::Object(map) => map.values().for_each(|v| Self::collect_text(v, text)),
+            Value::Array(arr) => arr.iter().for_each(|v| Self::collect_text(v, text)),
+            Value::String(s) => text.push(s.clone()),
+            Value::Null => {}
+            other => text.push(other.to_string()),
+        }
+    }
+
+    /// Adds the DaaS document to the search index, replacing any previously indexed revision of the document
+    ///
+    /// # Arguments
+    ///
+    /// * doc: &DaaSDoc - The DaaS document to index.</br>
+    pub fn index_doc(&self, doc: &DaaSDoc) -> Result<(), SearchError> {
+        // only JSON data objects can be searched by field
+        let data = match serde_json::from_slice::<Value>(&doc.data_obj) {
+            Ok(Value::Object(map)) => Value::Object(map),
+            Ok(other) => json!({ "value": other }),
+            Err(_) => Value::Object(Map::new()),
+        };
+        let meta = serde_json::to_value(&doc.meta_data).unwrap();
+
+        let mut text = vec![
+            doc.category.clone(),
+            doc.subcategory.clone(),
+            doc.source_name.clone(),
+            doc.author.clone(),
+        ];
+        text.extend(doc.tags.clone());
+        Self::collect_text(&meta, &mut text);
+        Self::collect_text(&data, &mut text);
+
+        let json_doc = json!({
+            "id": doc._id,
+            "rev": doc._rev.clone().unwrap_or_default(),
+            "category": doc.category,
+            "subcategory": doc.subcategory,
+            "source_name": doc.source_name,
+            "author": doc.author,
+            "tag": doc.tags,
+            "dua": doc
+                .data_usage_agreements
+                .iter()
+                .map(|d| d.agreement_name.clone())
+                .collect::<Vec<String>>(),
+            "meta": meta,
+            "data": data,
+            "text": text.join(" "),
+        });
+
+        let tantivy_doc =
+            match TantivyDocument::parse_json(&self.index.schema(), &json_doc.to_string()) {
+                Ok(d) => d,
+                Err(err) => {
+                    error!("Could not index DaaS document {}. {}", doc._id, err);
+                    return Err(SearchError);
+                }
+            };
+
+        let mut batch = self.writer.lock().unwrap();
+        batch
+            .writer
+            .delete_term(Term::from_field_text(self.id, &doc._id));
+        if let Err(err) = batch.writer.add_document(tantivy_doc) {
+            error!("Could not index DaaS document {}. {}", doc._id, err);
+            return Err(SearchError);
+        }
+
+        debug!("Indexed DaaS document {} for searching.", doc._id);
+        batch.pending += 1;
+        match self.is_due(&batch) {
+            true => self.commit_batch(&mut batch),
+            false => Ok(()),
+        }
+    }
+
+    /// Returns the DaaS documents that match the query, ordered by relevance
+    ///
+    /// # Arguments
+    ///
+    /// * query: &str - The search query, (e.g.: `category:order AND data.status:new`).</br>
+    /// * limit: usize - The maximum number of hits to return.</br>
+    pub fn search(&self, query: &str, limit: usize) -> Result<Vec<SearchHit>, SearchError> {
+        self.search_page(query, None, limit).map(|page| page.items)
+    }
+
+    /// Returns a page of the DaaS documents that match the query, ordered by relevance, continuing after the cursor
+    /// of the previous page. DaaS documents that are indexed while paging can shift the hits between the pages.
+    ///
+    /// # Arguments
+    ///
+    /// * query: &str - The search query, (e.g.: `category:order AND data.status:new`).</br>
+    /// * cursor: Option<String> - The cursor of the previous page, or None for the first page.</br>
+    /// * page_size: usize - The maximum number of hits of the page.</br>
+    pub fn search_page(
+        &self,
+        query: &str,
+        cursor: Option<String>,
+        page_size: usize,
+    ) -> Result<Page<SearchHit>, SearchError> {
+        self.find(None, query, cursor, page_size)
+    }
+
+    /// Returns a page of the DaaS documents that match the query like `search_page()`, limited to the DaaS documents
+    /// that have a data usage agreement for the purpose
+    ///
+    /// # Arguments
+    ///
+    /// * purpose: &str - The purpose of the search, (e.g.: billing).</br>
+    /// * query: &str - The search query, (e.g.: `category:order AND data.status:new`).</br>
+    /// * cursor: Option<String> - The cursor of the previous page, or None for the first page.</br>
+    /// * page_size: usize - The maximum number of hits of the page.</br>
+    pub fn search_page_for(
+        &self,
+        purpose: &str,
+        query: &str,
+        cursor: Option<String>,
+        page_size: usize,
+    ) -> Result<Page<SearchHit>, SearchError> {
+        self.find(Some(purpose), query, cursor, page_size)
+    }
+
+    fn find(
+        &self,
+        purpose: Option<&str>,
+        query: &str,
+        cursor: Option<String>,
+        page_size: usize,
+    ) -> Result<Page<SearchHit>, SearchError> {
+        // the batch that has waited for the commit interval is committed, so it can be searched
+        {
+            let mut batch = self.writer.lock().unwrap();
+            if self.is_due(&batch) {
+                self.commit_batch(&mut batch)?;
+            }
+        }
+
+        let page_size = page_size.max(1);
+        let offset = match cursor.map(|c| decode_cursor(&c).map(|o| o.parse::<usize>())) {
+            None => 0,
+            Some(Ok(Ok(o))) => o,
+            Some(_) => {
+                warn!("Invalid search cursor.");
+                return Err(SearchError);
+            }
+        };
+        let schema = self.index.schema();
+        let parser = QueryParser::for_index(&self.index, vec![self.text]);
+        let qry: Box<dyn TantivyQuery> = match (parser.parse_query(query), purpose) {
+            (Ok(q), None) => q,
+            (Ok(q), Some(p)) => Box::new(BooleanQuery::new(vec![
+                (Occur::Must, q),
+                (
+                    Occur::Must,
+                    Box::new(TermQuery::new(
+                        Term::from_field_text(self.dua, p),
+                        IndexRecordOption::Basic,
+                    )),
+                ),
+            ])),
+            (Err(err), _) => {
+                warn!("Invalid search query [{}]. {}", query, err);
+                return Err(SearchError);
+            }
+        };
+
+        let searcher = self.reader.searcher();
+        let top_docs =
+            match searcher.search(&qry, &TopDocs::with_limit(page_size + 1).and_offset(offset)) {
+                Ok(t) => t,
+                Err(err) => {
+                    error!("Could not search the DaaS documents. {}", err);
+                    return Err(SearchError);
+                }
+            };
+
+        let get = |d: &TantivyDocument, name: &str| -> String {
+            d.get_first(schema.get_field(name).unwrap())
+                .and_then(|v| tantivy::schema::Value::as_str(&v))
+                .unwrap_or_default()
+                .to_string()
+        };
+
+        let mut hits = Vec::new();
+        for (score, address) in top_docs {
+            let d: TantivyDocument = match searcher.doc(address) {
+                Ok(d) => d,
+                Err(err) => {
+                    error!("Could not read the search hit. {}", err);
+                    return Err(SearchError);
+                }
+            };
+            let rev = get(&d, "rev");
+            hits.push(SearchHit {
+                _id: get(&d, "id"),
+                _rev: if rev.is_empty() { None } else { Some(rev) },
+                category: get(&d, "category"),
+                subcategory: get(&d, "subcategory"),
+                source_name: get(&d, "source_name"),
+                score,
+            });
+        }
+
+        let next_cursor = match hits.len() > page_size {
+            true => {
+                hits.truncate(page_size);
+                Some(encode_cursor(&(offset + page_size).to_string()))
+            }
+            false => None,
+        };
+
+        Ok(Page {
+            items: hits,
+            next_cursor,
+        })
+    }
+
+    /// Returns the path of the search service, (e.g.: `/search?q=tag:priority&limit=10&cursor=MTA`)
+    pub fn get_service_search_path() -> String {
+        "/search".to_string()
+    }
+
+    /// The RESTful search service, which only returns the DaaS documents that have a data usage agreement for the
+    /// purpose of the request, (the `X-DaaS-Purpose` header)
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// extern crate actix_web;
+    /// extern crate daas;
+    ///
+    /// use actix_web::{web, App};
+    /// use daas::search::DaaSSearchIndex;
+    /// use daas::testing::MockAuthor;
+    ///
+    /// fn main() {
+    ///     let index = web::Data::new(DaaSSearchIndex::new_in_ram().unwrap());
+    ///
+    ///     let app = App::new()
+    ///         .app_data(index.clone())
+    ///         .service(
+    ///             web::resource(&DaaSSearchIndex::get_service_search_path())
+    ///                 .route(web::get().to(DaaSSearchIndex::search_service::<MockAuthor>)),
+    ///         );
+    /// }
+    /// ```
+    pub fn search_service<A: AuthorExtractor>(
+        index: Data<DaaSSearchIndex>,
+        params: Query<SearchParams>,
+        author: A,
+        req: HttpRequest,
+    ) -> HttpResponse {
+        let purpose = match req
+            .headers()
+            .get(PURPOSE_HEADER)
+            .and_then(|h| h.to_str().ok())
+        {
+            Some(p) if !p.trim().is_empty() => p.trim().to_string(),
+            _ => {
+                return HttpResponse::BadRequest()
+                    .header(http::header::CONTENT_TYPE, "application/json")
+                    .body(r#"{"error":"missing purpose"}"#)
+            }
+        };
+
+        // the roles of the requesters can be registered as application data, (e.g.: App::new().data(AccessPolicy::new()))
+        if let Some(policy) = req.app_data::<Data<AccessPolicy>>() {
+            if let AccessDecision::Denied(agreement) =
+                policy.authorize_purpose(&author.get_identity(), &purpose)
+            {
+                return HttpResponse::Forbidden()
+                    .header(http::header::CONTENT_TYPE, "application/json")
+                    .body(
+                        json!({
+                            "error": "access denied by the data usage agreement",
+                            "agreement": agreement
+                        })
+                        .to_string(),
+                    );
+            }
+        }
+
+        match index.search_page_for(
+            &purpose,
+            &params.q,
+            params.cursor.clone(),
+            params.limit.unwrap_or(20),
+        ) {
+            Ok(page) => {
+                let mut rspns = HttpResponse::Ok();
+                rspns.header(http::header::CONTENT_TYPE, "application/json");
+                if let Some(cursor) = page.next_cursor {
+                    rspns.header(NEXT_CURSOR_HEADER, cursor);
+                }
+                rspns.body(serde_json::to_string(&page.items).unwrap())
+            }
+            Err(_e) => HttpResponse::BadRequest()
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .body(r#"{"error":"invalid search query"}"#),
+        }
+    }
+}
+
+/// A storage device that indexes the DaaS documents for searching when they are upserted
+pub struct SearchableStorage<S: DaaSDocStorage> {
+    /// The storage device that manages the DaaS documents
+    pub storage: S,
+    /// The search index
+    pub index: DaaSSearchIndex,
+}
+
+impl Drop for DaaSSearchIndex {
+    fn drop(&mut self) {
+        // the DaaS documents that are waiting for the commit interval aren't lost
+        let _ = self.commit();
+    }
+}
+
+impl<S: DaaSDocStorage> SearchableStorage<S> {
+    /// Constructor
+    ///
+    /// # Arguments
+    ///
+    /// * storage: S - The storage device that manages the DaaS documents.</br>
+    /// * index: DaaSSearchIndex - The search index.</br>
+    pub fn new(storage: S, index: DaaSSearchIndex) -> SearchableStorage<S> {
+        SearchableStorage { storage, index }
+    }
+}
+
+impl<S: DaaSDocStorage> DaaSDocStorage for SearchableStorage<S> {
+    fn upsert_daas_doc(&self, daas_doc: DaaSDoc) -> Result<DaaSDoc, UpsertError> {
+        let doc = self.storage.upsert_daas_doc(daas_doc)?;
+
+        // the document has been saved, so a failure to index it is not a failure of the upsert
+        if let Err(err) = self.index.index_doc(&doc) {
+            warn!(
+                "DaaS document {} was saved but not indexed. {}",
+                doc._id, err
+            );
+        }
+
+        Ok(doc)
+    }
+
+    fn get_doc_by_id(
+        &self,
+        doc_id: String,
+        doc_rev: Option<String>,
+    ) -> Result<DaaSDoc, RetrieveError> {
+        self.storage.get_doc_by_id(doc_id, doc_rev)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::local::LocalStorage;
+    use crate::testing::MockAuthor;
+    use actix_web::http::StatusCode;
+    use actix_web::test;
+    use pbd::dtc::Tracker;
+    use pbd::dua::DUA;
+
+    fn get_doc(uid: usize, data: &str) -> DaaSDoc {
+        let src = "iStore".to_string();
+        let cat = "order".to_string();
+        let sub = "clothing".to_string();
+        let dua = vec![DUA {
+            agreement_name: "billing".to_string(),
+            location: "www.dua.org/billing.pdf".to_string(),
+            agreed_dtm: 1553988607,
+        }];
+        let dtc = Tracker::new(DaaSDoc::make_id(cat.clone(), sub.clone(), src.clone(), uid));
+
+        DaaSDoc::new(
+            src,
+            uid,
+            cat,
+            sub,
+            "istore_app".to_string(),
+            dua,
+            dtc,
+            data.as_bytes().to_vec(),
+        )
+    }
+
+    #[test]
+    fn test_search_by_tag_meta_and_data() {
+        let index = DaaSSearchIndex::new_in_ram().unwrap();
+        let mut doc1 = get_doc(1, r#"{"product": "leather coat", "status": "new"}"#);
+        doc1.add_tag("priority".to_string());
+        doc1.add_meta("content-type".to_string(), "application/json".to_string());
+        let doc2 = get_doc(2, r#"{"product": "wool hat", "status": "shipped"}"#);
+        index.index_doc(&doc1).unwrap();
+        index.index_doc(&doc2).unwrap();
+        index.commit().unwrap();
+
+        assert_eq!(index.search("tag:priority", 10).unwrap()[0]._id, doc1._id);
+        assert_eq!(
+            index.search("data.status:shipped", 10).unwrap()[0]._id,
+            doc2._id
+        );
+        assert_eq!(index.search("meta.content-type:json", 10).unwrap().len(), 1);
+        assert_eq!(index.search("category:order", 10).unwrap().len(), 2);
+        assert_eq!(index.search("leather", 10).unwrap().len(), 1);
+        assert_eq!(index.search("nothing", 10).unwrap().len(), 0);
+    }
+
+    #[test]
+    fn test_search_replaces_revision() {
+        let index = DaaSSearchIndex::new_in_ram().unwrap();
+        let mut doc = get_doc(3, r#"{"status": "new"}"#);
+        index.index_doc(&doc).unwrap();
+        doc._rev = Some("2".to_string());
+        doc.data_obj = r#"{"status": "shipped"}"#.as_bytes().to_vec();
+        index.index_doc(&doc).unwrap();
+        index.commit().unwrap();
+
+        let hits = index.search("category:order", 10).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0]._rev, Some("2".to_string()));
+        assert_eq!(index.search("data.status:new", 10).unwrap().len(), 0);
+    }
+
+    #[test]
+    fn test_search_bad_query() {
+        let index = DaaSSearchIndex::new_in_ram().unwrap();
+        assert!(index.search("unknown_field:foo", 10).is_err());
+    }
+
+    #[test]
+    fn test_searchable_storage() {
+        let storage = SearchableStorage::new(
+            LocalStorage::new(format!("./tmp/search-{}", rand::random::<u32>())),
+            DaaSSearchIndex::new_in_ram().unwrap(),
+        );
+        let doc = storage
+            .upsert_daas_doc(get_doc(4, r#"{"status": "new"}"#))
+            .unwrap();
+        storage.index.commit().unwrap();
+
+        let hits = storage.index.search("data.status:new", 10).unwrap();
+        assert_eq!(hits[0]._rev, doc._rev);
+    }
+
+    #[test]
+    fn test_search_page() {
+        let index = DaaSSearchIndex::new_in_ram().unwrap();
+        for uid in 0..5 {
+            index
+                .index_doc(&get_doc(uid, r#"{"status": "new"}"#))
+                .unwrap();
+        }
+        index.commit().unwrap();
+
+        let mut ids = Vec::new();
+        let mut cursor = None;
+        loop {
+            let page = index.search_page("data.status:new", cursor, 2).unwrap();
+            assert!(page.items.len() <= 2);
+            ids.extend(page.items.into_iter().map(|h| h._id));
+            cursor = page.next_cursor;
+            if cursor.is_none() {
+                break;
+            }
+        }
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), 5);
+
+        assert!(index
+            .search_page("data.status:new", Some("not-a-cursor!".to_string()), 2)
+            .is_err());
+    }
+
+    #[test]
+    fn test_batch_commit() {
+        let index = DaaSSearchIndex::new_in_ram()
+            .unwrap()
+            .with_batch_size(2)
+            .with_commit_interval(Duration::from_secs(3600));
+
+        index
+            .index_doc(&get_doc(6, r#"{"status": "new"}"#))
+            .unwrap();
+        assert_eq!(index.search("data.status:new", 10).unwrap().len(), 0);
+        // the batch is full
+        index
+            .index_doc(&get_doc(7, r#"{"status": "new"}"#))
+            .unwrap();
+        assert_eq!(index.search("data.status:new", 10).unwrap().len(), 2);
+
+        // the batch has waited for the commit interval
+        let index = index.with_commit_interval(Duration::from_millis(0));
+        index
+            .index_doc(&get_doc(8, r#"{"status": "new"}"#))
+            .unwrap();
+        assert_eq!(index.search("data.status:new", 10).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_search_page_for() {
+        let index = DaaSSearchIndex::new_in_ram().unwrap();
+        let mut doc = get_doc(9, r#"{"status": "new"}"#);
+        index.index_doc(&doc).unwrap();
+        doc = get_doc(10, r#"{"status": "new"}"#);
+        doc.data_usage_agreements[0].agreement_name = "marketing".to_string();
+        index.index_doc(&doc).unwrap();
+        index.commit().unwrap();
+
+        let page = index
+            .search_page_for("marketing", "data.status:new", None, 10)
+            .unwrap();
+        assert_eq!(page.items.len(), 1);
+        assert_eq!(page.items[0]._id, doc._id);
+        assert!(index
+            .search_page_for("research", "data.status:new", None, 10)
+            .unwrap()
+            .items
+            .is_empty());
+    }
+
+    fn call_search_service(
+        index: Data<DaaSSearchIndex>,
+        uri: &str,
+        purpose: Option<&str>,
+        policy: Option<AccessPolicy>,
+    ) -> HttpResponse {
+        let mut req = test::TestRequest::get().uri(uri);
+        if let Some(p) = purpose {
+            req = req.header(PURPOSE_HEADER, p);
+        }
+        if let Some(p) = policy {
+            req = req.data(p);
+        }
+        let req = req.to_http_request();
+        let params = Query::<SearchParams>::from_query(req.query_string()).unwrap();
+
+        DaaSSearchIndex::search_service(index, params, MockAuthor::new(), req)
+    }
+
+    #[test]
+    fn test_search_service() {
+        let index = DaaSSearchIndex::new_in_ram().unwrap();
+        index
+            .index_doc(&get_doc(5, r#"{"status": "new"}"#))
+            .unwrap();
+        index.commit().unwrap();
+        let data = Data::new(index);
+        let uri = "/search?q=data.status:new";
+
+        assert_eq!(
+            call_search_service(data.clone(), uri, Some("billing"), None).status(),
+            StatusCode::OK
+        );
+        assert_eq!(
+            call_search_service(data.clone(), "/search?q=bad:", Some("billing"), None).status(),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            call_search_service(data.clone(), uri, None, None).status(),
+            StatusCode::BAD_REQUEST
+        );
+        // the requester has no role that is authorized for the purpose
+        assert_eq!(
+            call_search_service(data, uri, Some("billing"), Some(AccessPolicy::new())).status(),
+            StatusCode::FORBIDDEN
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_search_service_purpose() {
+        let index = DaaSSearchIndex::new_in_ram().unwrap();
+        index
+            .index_doc(&get_doc(11, r#"{"status": "new"}"#))
+            .unwrap();
+        index.commit().unwrap();
+        let data = Data::new(index);
+
+        // the DaaS document has no agreement for the purpose
+        let rspns = call_search_service(data, "/search?q=data.status:new", Some("marketing"), None);
+        assert_eq!(rspns.status(), StatusCode::OK);
+        let body = test::read_body(test::TestRequest::default().to_srv_response(rspns)).await;
+        assert_eq!(body, "[]".as_bytes());
+    }
+}
@@ -0,0 +1,260 @@
+//! The cryptographic backend that uses OpenSSL, (the `security` feature).
+
+use super::*;
+use openssl::derive::Deriver;
+use openssl::error::ErrorStack;
+use openssl::md::Md;
+use openssl::pkey::{HasPublic, Id, PKey, PKeyRef, Private};
+use openssl::pkey_ctx::PkeyCtx;
+use openssl::rand::rand_bytes;
+use openssl::rsa::{Padding, Rsa};
+use openssl::symm::{decrypt_aead, encrypt_aead, Cipher};
+
+// The cipher used to encrypt the DaaS data
+fn cipher() -> Cipher {
+    Cipher::aes_256_gcm()
+}
+
+fn padding(padding: RsaPadding) -> Padding {
+    match padding {
+        RsaPadding::Oaep => Padding::PKCS1_OAEP,
+        RsaPadding::Pkcs1 => Padding::PKCS1,
+    }
+}
+
+/// The cryptographic backend that encrypts the data with AES-256-GCM, and wraps the keys with RSA or X25519, using
+/// OpenSSL
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OpenSslBackend;
+
+impl CryptoBackend for OpenSslBackend {
+    fn key_len(&self) -> usize {
+        cipher().key_len()
+    }
+
+    fn seal(&self, key: &[u8], data: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+        let mut nonce = vec![0; NONCE_SIZE];
+        let mut tag = vec![0; TAG_SIZE];
+
+        if rand_bytes(&mut nonce).is_err() {
+            return Err(EncryptionError);
+        }
+
+        match encrypt_aead(cipher(), key, Some(&nonce), &[], data, &mut tag) {
+            Ok(cipher_text) => {
+                let mut encrypted = nonce;
+                encrypted.extend(tag);
+                encrypted.extend(cipher_text);
+                Ok(encrypted)
+            }
+            Err(err) => {
+                error!("Could not encrypt the data. {}", err);
+                Err(EncryptionError)
+            }
+        }
+    }
+
+    fn open(&self, key: &[u8], data: &[u8]) -> Result<Vec<u8>, DecryptionError> {
+        if data.len() < NONCE_SIZE + TAG_SIZE {
+            return Err(DecryptionError);
+        }
+
+        let (nonce, rest) = data.split_at(NONCE_SIZE);
+        let (tag, cipher_text) = rest.split_at(TAG_SIZE);
+
+        match decrypt_aead(cipher(), key, Some(nonce), &[], cipher_text, tag) {
+            Ok(plain_text) => Ok(plain_text),
+            Err(err) => {
+                error!("Could not decrypt the data. {}", err);
+                Err(DecryptionError)
+            }
+        }
+    }
+
+    fn random_bytes(&self, len: usize) -> Result<Vec<u8>, BadKeyPairError> {
+        let mut bytes = vec![0; len];
+        rand_bytes(&mut bytes).map_err(|err| {
+            error!("Could not generate the random bytes. {}", err);
+            BadKeyPairError
+        })?;
+        Ok(bytes)
+    }
+
+    fn generate_rsa_keypair(&self, bits: u32) -> Result<(Vec<u8>, Vec<u8>), BadKeyPairError> {
+        let keys = Rsa::generate(bits)
+            .and_then(|rsa| Ok((rsa.private_key_to_pem()?, rsa.public_key_to_pem()?)));
+
+        keys.map_err(|err| {
+            error!("Could not generate the RSA key pair. {}", err);
+            BadKeyPairError
+        })
+    }
+
+    fn generate_x25519_keypair(&self) -> Result<(Vec<u8>, Vec<u8>), BadKeyPairError> {
+        let keys = PKey::generate_x25519()
+            .and_then(|pkey| Ok((pkey.private_key_to_pem_pkcs8()?, pkey.public_key_to_pem()?)));
+
+        keys.map_err(|err| {
+            error!("Could not generate the X25519 key pair. {}", err);
+            BadKeyPairError
+        })
+    }
+
+    fn encrypt_private_key(
+        &self,
+        private_key: &[u8],
+        passphrase: &[u8],
+    ) -> Result<Vec<u8>, BadKeyPairError> {
+        let encrypted = PKey::private_key_from_pem(private_key).and_then(|pkey| {
+            pkey.private_key_to_pem_pkcs8_passphrase(Cipher::aes_256_cbc(), passphrase)
+        });
+
+        encrypted.map_err(|err| {
+            error!("Could not encrypt the private key. {}", err);
+            BadKeyPairError
+        })
+    }
+
+    fn decrypt_private_key(
+        &self,
+        private_key: &[u8],
+        passphrase: &[u8],
+    ) -> Result<Vec<u8>, BadKeyPairError> {
+        let decrypted = PKey::private_key_from_pem_passphrase(private_key, passphrase)
+            .and_then(|pkey| pkey.private_key_to_pem_pkcs8());
+
+        decrypted.map_err(|err| {
+            error!("Could not decrypt the private key. {}", err);
+            BadKeyPairError
+        })
+    }
+
+    fn is_rsa_key(&self, key: &[u8]) -> bool {
+        Rsa::public_key_from_pem(key).is_ok() || Rsa::private_key_from_pem(key).is_ok()
+    }
+
+    fn wrap_key_rsa(
+        &self,
+        public_key: &[u8],
+        key: &[u8],
+        rsa_padding: RsaPadding,
+    ) -> Result<Vec<u8>, EncryptionError> {
+        let rsa = match Rsa::public_key_from_pem(public_key) {
+            Ok(r) => r,
+            Err(err) => {
+                error!("Could not read the RSA public key. {}", err);
+                return Err(EncryptionError);
+            }
+        };
+        check_wrap_size(rsa.size() as usize, key, rsa_padding)?;
+
+        let mut wrapped = vec![0; rsa.size() as usize];
+        match rsa.public_encrypt(key, &mut wrapped, padding(rsa_padding)) {
+            Ok(len) => {
+                wrapped.truncate(len);
+                Ok(wrapped)
+            }
+            Err(err) => {
+                error!("Could not wrap the key. {}", err);
+                Err(EncryptionError)
+            }
+        }
+    }
+
+    fn unwrap_key_rsa(
+        &self,
+        private_key: &[u8],
+        wrapped: &[u8],
+        rsa_padding: RsaPadding,
+    ) -> Result<Vec<u8>, DecryptionError> {
+        let rsa = match Rsa::private_key_from_pem(private_key) {
+            Ok(r) => r,
+            Err(err) => {
+                error!("Could not read the RSA private key. {}", err);
+                return Err(DecryptionError);
+            }
+        };
+
+        if wrapped.len() != rsa.size() as usize {
+            error!("The wrapped key doesn't match the size of the RSA key.");
+            return Err(DecryptionError);
+        }
+
+        // the buffer is the size of the modulus and is truncated to the size of the key, so no bytes are lost
+        let mut key = vec![0; rsa.size() as usize];
+        match rsa.private_decrypt(wrapped, &mut key, padding(rsa_padding)) {
+            Ok(len) => {
+                key.truncate(len);
+                Ok(key)
+            }
+            Err(err) => {
+                error!("Could not unwrap the key. {}", err);
+                Err(DecryptionError)
+            }
+        }
+    }
+
+    fn wrap_key_x25519(&self, public_key: &[u8], key: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+        let wrapping = PKey::public_key_from_pem(public_key).and_then(|recipient| {
+            let ephemeral = PKey::generate_x25519()?;
+            let kek = derive_kek(&ephemeral, &recipient)?;
+            Ok((ephemeral.raw_public_key()?, kek))
+        });
+
+        match wrapping {
+            Ok((ephemeral, kek)) => {
+                let mut wrapped = ephemeral;
+                wrapped.extend(self.seal(&kek, key)?);
+                Ok(wrapped)
+            }
+            Err(err) => {
+                error!("Could not wrap the key. {}", err);
+                Err(EncryptionError)
+            }
+        }
+    }
+
+    fn unwrap_key_x25519(
+        &self,
+        private_key: &[u8],
+        wrapped: &[u8],
+    ) -> Result<Vec<u8>, DecryptionError> {
+        if wrapped.len() < X25519_KEY_SIZE {
+            return Err(DecryptionError);
+        }
+
+        let (ephemeral, sealed) = wrapped.split_at(X25519_KEY_SIZE);
+        let kek = PKey::private_key_from_pem(private_key).and_then(|recipient| {
+            let ephemeral = PKey::public_key_from_raw_bytes(ephemeral, Id::X25519)?;
+            derive_kek(&recipient, &ephemeral)
+        });
+
+        match kek {
+            Ok(kek) => self.open(&kek, sealed),
+            Err(err) => {
+                error!("Could not unwrap the key. {}", err);
+                Err(DecryptionError)
+            }
+        }
+    }
+}
+
+// Derives the key encryption key from the X25519 shared secret using HKDF-SHA256
+fn derive_kek<T: HasPublic>(
+    private_key: &PKeyRef<Private>,
+    public_key: &PKeyRef<T>,
+) -> Result<Vec<u8>, ErrorStack> {
+    let mut deriver = Deriver::new(private_key)?;
+    deriver.set_peer(public_key)?;
+    let secret = deriver.derive_to_vec()?;
+
+    let mut hkdf = PkeyCtx::new_id(Id::HKDF)?;
+    hkdf.derive_init()?;
+    hkdf.set_hkdf_md(Md::sha256())?;
+    hkdf.set_hkdf_key(&secret)?;
+    hkdf.add_hkdf_info(KEK_INFO)?;
+    let mut kek = vec![0; KEY_SIZE];
+    hkdf.derive(Some(&mut kek))?;
+
+    Ok(kek)
+}
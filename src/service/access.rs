@@ -0,0 +1,268 @@
+//! Enforcement of the data usage agreements when the DaaS documents are read.
+//!
+//! The requester declares the purpose of the request in the `X-DaaS-Purpose` header, (e.g.: billing). The data is
+//! only returned when the DaaS document has a data usage agreement for that purpose and, when an `AccessPolicy` is
+//! registered as application data, the requester has a role that is authorized for the purpose. Otherwise the
+//! request is rejected with 403 Forbidden, naming the violated agreement.
+//!
+//! # Examples
+//!
+//! ```
+//! extern crate daas;
+//!
+//! use daas::service::access::{AccessDecision, AccessPolicy};
+//! use daas::testing;
+//!
+//! fn main() {
+//!     let policy = AccessPolicy::new()
+//!         .with_role("finance".to_string(), vec!["billing".to_string()])
+//!         .with_member("alice".to_string(), "finance".to_string());
+//!     let doc = testing::get_default_daas_doc();
+//!
+//!     assert_eq!(policy.authorize("alice", "billing", &doc), AccessDecision::Granted);
+//!     assert_eq!(policy.authorize("bob", "billing", &doc), AccessDecision::Denied("billing".to_string()));
+//! }
+//! ```
+
+use super::extractor::AuthorIdentity;
+use super::*;
+use crate::doc::DaaSDoc;
+use std::collections::HashMap;
+
+/// The header that holds the purpose of the request, (e.g.: billing)
+pub const PURPOSE_HEADER: &str = "X-DaaS-Purpose";
+
+/// Represents the outcome of the evaluation of a request
+#[derive(Debug, Clone, PartialEq)]
+pub enum AccessDecision {
+    /// The data can be returned
+    Granted,
+    /// The data can't be returned because of the named data usage agreement
+    Denied(String),
+}
+
+impl AccessDecision {
+    /// Determines if the data can be returned
+    pub fn is_granted(&self) -> bool {
+        *self == AccessDecision::Granted
+    }
+}
+
+/// Determines if the DaaS document has a data usage agreement for the purpose
+///
+/// # Arguments
+///
+/// * purpose: &str - The purpose of the request, (e.g.: billing).</br>
+/// * doc: &DaaSDoc - The DaaS document that is requested.</br>
+pub fn check_agreements(purpose: &str, doc: &DaaSDoc) -> AccessDecision {
+    match doc
+        .data_usage_agreements
+        .iter()
+        .any(|dua| dua.agreement_name == purpose)
+    {
+        true => AccessDecision::Granted,
+        false => {
+            warn!(
+                "DaaS document {} has no data usage agreement for {}.",
+                doc._id, purpose
+            );
+            AccessDecision::Denied(purpose.to_string())
+        }
+    }
+}
+
+/// Represents the purposes that the roles are authorized for, and the roles of the requesters
+#[derive(Debug, Clone, Default)]
+pub struct AccessPolicy {
+    // the purposes of each role
+    roles: HashMap<String, Vec<String>>,
+    // the roles of each requester
+    members: HashMap<String, Vec<String>>,
+}
+
+impl AccessPolicy {
+    /// Constructs an AccessPolicy without any roles, (so all the requests are denied)
+    pub fn new() -> AccessPolicy {
+        AccessPolicy::default()
+    }
+
+    /// Authorizes the role for the purposes
+    ///
+    /// # Arguments
+    ///
+    /// * role: String - The name of the role, (e.g.: finance).</br>
+    /// * purposes: Vec<String> - The purposes the role is authorized for, (i.e.: the names of the agreements).</br>
+    pub fn with_role(mut self, role: String, purposes: Vec<String>) -> AccessPolicy {
+        self.roles.entry(role).or_default().extend(purposes);
+        self
+    }
+
+    /// Grants the role to the requester
+    ///
+    /// # Arguments
+    ///
+    /// * requester: String - The name of the requester, (i.e.: the author of the request).</br>
+    /// * role: String - The name of the role.</br>
+    pub fn with_member(mut self, requester: String, role: String) -> AccessPolicy {
+        self.members.entry(requester).or_default().push(role);
+        self
+    }
+
+    /// Determines if the requester is authorized to read the DaaS document for the purpose
+    ///
+    /// # Arguments
+    ///
+    /// * requester: &str - The name of the requester.</br>
+    /// * purpose: &str - The purpose of the request, (e.g.: billing).</br>
+    /// * doc: &DaaSDoc - The DaaS document that is requested.</br>
+    pub fn authorize(&self, requester: &str, purpose: &str, doc: &DaaSDoc) -> AccessDecision {
+        self.authorize_identity(&AuthorIdentity::new(requester.to_string()), purpose, doc)
+    }
+
+    /// Determines if the requester is authorized to read the DaaS document for the purpose, using both the roles
+    /// that are granted in the policy and the roles of the identity, (e.g.: the roles in a token)
+    ///
+    /// # Arguments
+    ///
+    /// * identity: &AuthorIdentity - The identity of the requester.</br>
+    /// * purpose: &str - The purpose of the request, (e.g.: billing).</br>
+    /// * doc: &DaaSDoc - The DaaS document that is requested.</br>
+    ///
+    /// #Example
+    ///
+    /// ```rust
+    /// extern crate daas;
+    ///
+    /// use daas::service::access::{AccessDecision, AccessPolicy};
+    /// use daas::service::extractor::AuthorIdentity;
+    /// use daas::testing;
+    ///
+    /// fn main() {
+    ///     let policy = AccessPolicy::new().with_role("finance".to_string(), vec!["billing".to_string()]);
+    ///     let identity = AuthorIdentity::new("carol".to_string()).with_role("finance".to_string());
+    ///     let doc = testing::get_default_daas_doc();
+    ///
+    ///     assert_eq!(policy.authorize_identity(&identity, "billing", &doc), AccessDecision::Granted);
+    /// }
+    /// ```
+    pub fn authorize_identity(
+        &self,
+        identity: &AuthorIdentity,
+        purpose: &str,
+        doc: &DaaSDoc,
+    ) -> AccessDecision {
+        match self.authorize_purpose(identity, purpose) {
+            AccessDecision::Granted => check_agreements(purpose, doc),
+            denied => denied,
+        }
+    }
+
+    /// Determines if the requester has a role that is authorized for the purpose, regardless of the DaaS documents,
+    /// (e.g.: before searching the DaaS documents that have an agreement for the purpose)
+    ///
+    /// # Arguments
+    ///
+    /// * identity: &AuthorIdentity - The identity of the requester.</br>
+    /// * purpose: &str - The purpose of the request, (e.g.: billing).</br>
+    pub fn authorize_purpose(&self, identity: &AuthorIdentity, purpose: &str) -> AccessDecision {
+        let requester = identity.id.as_str();
+        let authorized = self
+            .members
+            .get(requester)
+            .into_iter()
+            .flatten()
+            .chain(identity.roles.iter())
+            .any(|r| {
+                self.roles
+                    .get(r)
+                    .iter()
+                    .any(|p| p.iter().any(|p| p == purpose))
+            });
+
+        if !authorized {
+            warn!(
+                "{} has no role that is authorized for {}.",
+                requester, purpose
+            );
+            return AccessDecision::Denied(purpose.to_string());
+        }
+
+        AccessDecision::Granted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    fn get_policy() -> AccessPolicy {
+        AccessPolicy::new()
+            .with_role("finance".to_string(), vec!["billing".to_string()])
+            .with_role("marketing".to_string(), vec!["campaigns".to_string()])
+            .with_member("alice".to_string(), "finance".to_string())
+            .with_member("bob".to_string(), "marketing".to_string())
+    }
+
+    #[test]
+    fn test_check_agreements() {
+        let doc = testing::get_default_daas_doc();
+
+        assert!(check_agreements("billing", &doc).is_granted());
+        assert_eq!(
+            check_agreements("campaigns", &doc),
+            AccessDecision::Denied("campaigns".to_string())
+        );
+    }
+
+    #[test]
+    fn test_authorize() {
+        let doc = testing::get_default_daas_doc();
+        let policy = get_policy();
+
+        assert!(policy.authorize("alice", "billing", &doc).is_granted());
+        // bob's role is authorized, but the DaaS document has no agreement for campaigns
+        assert_eq!(
+            policy.authorize("bob", "campaigns", &doc),
+            AccessDecision::Denied("campaigns".to_string())
+        );
+        assert!(!policy.authorize("bob", "billing", &doc).is_granted());
+        assert!(!policy.authorize("carol", "billing", &doc).is_granted());
+    }
+
+    #[test]
+    fn test_authorize_identity() {
+        let policy = get_policy();
+        let doc = testing::get_default_daas_doc();
+        let carol = AuthorIdentity::new("carol".to_string());
+
+        assert!(!policy
+            .authorize_identity(&carol, "billing", &doc)
+            .is_granted());
+        // the roles of the identity are combined with the roles that are granted in the policy
+        let carol = carol.with_role("finance".to_string());
+        assert!(policy
+            .authorize_identity(&carol, "billing", &doc)
+            .is_granted());
+        let bob = AuthorIdentity::new("bob".to_string()).with_role("finance".to_string());
+        assert!(policy
+            .authorize_identity(&bob, "billing", &doc)
+            .is_granted());
+        assert!(!policy
+            .authorize_identity(&bob, "unknown", &doc)
+            .is_granted());
+    }
+
+    #[test]
+    fn test_authorize_purpose() {
+        let policy = get_policy();
+        let bob = AuthorIdentity::new("bob".to_string());
+
+        // the agreements of the DaaS documents aren't checked
+        assert!(policy.authorize_purpose(&bob, "campaigns").is_granted());
+        assert_eq!(
+            policy.authorize_purpose(&bob, "billing"),
+            AccessDecision::Denied("billing".to_string())
+        );
+    }
+}
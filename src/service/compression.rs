@@ -0,0 +1,212 @@
+//! Compression of the bodies of the DaaS services, since the JSON data of the sources, (e.g.: IoT devices) is highly
+//! compressible and their bandwidth is often limited.
+//!
+//! The bodies of the requests that are compressed with gzip, deflate or brotli, (see the `Content-Encoding` header)
+//! are decompressed by the extractors of the listener before the DaaS document is built. The `Decompression`
+//! middleware rejects the bodies with an encoding that can't be decompressed with 415 Unsupported Media Type, (and
+//! the supported encodings in the `Accept-Encoding` header) instead of failing to parse them as JSON.
+//!
+//! The responses are compressed by the `Compress` middleware of actix-web, based on the `Accept-Encoding` header of
+//! the request, (e.g.: App::new().wrap(Compress::default()).wrap(Decompression))
+//!
+//! # Examples
+//!
+//! ```
+//! extern crate actix_web;
+//! extern crate daas;
+//!
+//! use actix_web::http::StatusCode;
+//! use actix_web::middleware::Compress;
+//! use actix_web::{test, App};
+//! use daas::service::compression::Decompression;
+//! use daas::testing;
+//!
+//! #[actix_rt::main]
+//! async fn main() {
+//!     let mut app = test::init_service(
+//!         App::new()
+//!             .configure(testing::configure_listener)
+//!             .wrap(Compress::default())
+//!             .wrap(Decompression),
+//!     )
+//!     .await;
+//!     let req = test::TestRequest::post()
+//!         .uri("/order/clothing/iStore/8003")
+//!         .header("Content-Encoding", "zstd")
+//!         .to_request();
+//!     let resp = test::call_service(&mut app, req).await;
+//!
+//!     assert_eq!(resp.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+//! }
+//! ```
+
+use super::*;
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::Error;
+use futures::future::{ok, LocalBoxFuture, Ready};
+use std::task::{Context, Poll};
+
+/// The encodings of the request bodies that can be decompressed
+pub const SUPPORTED_ENCODINGS: [&str; 4] = ["br", "gzip", "deflate", "identity"];
+
+/// Represents the middleware that rejects the request bodies that can't be decompressed
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Decompression;
+
+impl Decompression {
+    /// Returns if the body of a request with the `Content-Encoding` can be decompressed
+    ///
+    /// # Arguments
+    ///
+    /// * encoding: &str - The value of the `Content-Encoding` header, (e.g.: gzip).</br>
+    ///
+    /// #Example
+    ///
+    /// ```rust
+    /// extern crate daas;
+    ///
+    /// use daas::service::compression::Decompression;
+    ///
+    /// fn main() {
+    ///     assert!(Decompression::is_supported("gzip"));
+    ///     // only a single encoding is decompressed
+    ///     assert!(!Decompression::is_supported("gzip, br"));
+    /// }
+    /// ```
+    pub fn is_supported(encoding: &str) -> bool {
+        let encoding = encoding.trim().to_lowercase();
+        SUPPORTED_ENCODINGS.iter().any(|e| *e == encoding)
+    }
+}
+
+impl<S, B> Transform<S> for Decompression
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = DecompressionMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(DecompressionMiddleware { service })
+    }
+}
+
+/// The middleware that applies the Decompression, (see `Decompression`)
+pub struct DecompressionMiddleware<S> {
+    service: S,
+}
+
+impl<S, B> Service for DecompressionMiddleware<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<ServiceResponse<B>, Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        let encoding = req
+            .headers()
+            .get(http::header::CONTENT_ENCODING)
+            .map(|h| h.to_str().unwrap_or("").to_string());
+
+        match encoding {
+            Some(e) if !Decompression::is_supported(&e) => {
+                warn!("Rejected the request body with the encoding {}.", e);
+                let rspns = HttpResponse::UnsupportedMediaType()
+                    .header(http::header::ACCEPT_ENCODING, "br, gzip, deflate")
+                    .finish();
+                Box::pin(ok(req.into_response(rspns.into_body())))
+            }
+            _ => Box::pin(self.service.call(req)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use actix_web::http::StatusCode;
+    use actix_web::middleware::Compress;
+    use actix_web::{test, App};
+    use flate2::write::GzEncoder;
+    use flate2::Compression as Level;
+    use std::io::Write;
+
+    #[test]
+    fn test_is_supported() {
+        assert!(Decompression::is_supported("gzip"));
+        assert!(Decompression::is_supported(" BR "));
+        assert!(Decompression::is_supported("identity"));
+        assert!(!Decompression::is_supported("compress"));
+        assert!(!Decompression::is_supported("gzip, deflate"));
+    }
+
+    #[actix_rt::test]
+    async fn test_compressed_request() {
+        let mut app = test::init_service(
+            App::new()
+                .configure(testing::configure_listener)
+                .wrap(Decompression),
+        )
+        .await;
+        let doc = testing::get_daas_doc(
+            "iStore".to_string(),
+            50000 + rand::random::<u16>() as usize,
+            "order".to_string(),
+            "clothing".to_string(),
+        );
+        let mut encoder = GzEncoder::new(Vec::new(), Level::default());
+        encoder.write_all(&doc.data_obj).unwrap();
+
+        let req = testing::get_listener_request(&doc)
+            .header("Content-Encoding", "gzip")
+            .set_payload(encoder.finish().unwrap())
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let req = testing::get_listener_request(&doc)
+            .header("Content-Encoding", "zstd")
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        assert_eq!(
+            resp.headers().get("Accept-Encoding").unwrap(),
+            "br, gzip, deflate"
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_compressed_response() {
+        let mut app = test::init_service(
+            App::new()
+                .configure(testing::configure_listener)
+                .wrap(Compress::default())
+                .wrap(Decompression),
+        )
+        .await;
+        let req = test::TestRequest::get()
+            .uri("/health")
+            .header("Accept-Encoding", "gzip")
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers().get("Content-Encoding").unwrap(), "gzip");
+    }
+}
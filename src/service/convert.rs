@@ -0,0 +1,407 @@
+//! Conversions of the XML and CSV payloads to JSON, so the legacy producers don't have to convert their data before
+//! sending it to the listener.
+//!
+//! The converters are payload transformers, (see `service::transform`), so they are configured for the categories
+//! that are converted, and the payloads of the other categories are stored as they are received. The converted
+//! payloads have the `application/json` content type.
+//!
+//! * `XmlToJson` converts an XML document (`application/xml`, `text/xml` or `+xml`) to a JSON object with the root
+//!   element as its only key. The attributes of an element are prefixed with `@`, its text is the value of the
+//!   element (or of `#text` when the element also has attributes or children), and repeated children are arrays.
+//! * `CsvToJson` converts a CSV document (`text/csv`) with a header row to a JSON array of objects.
+//!
+//! # Examples
+//!
+//! ```
+//! extern crate daas;
+//!
+//! use daas::service::convert::{CsvToJson, XmlToJson};
+//! use daas::service::transform::{RawPayload, Transformations};
+//!
+//! fn main() {
+//!     let transformations = Transformations::new()
+//!         .with_default_transformer(Box::new(XmlToJson::new()))
+//!         .with_default_transformer(Box::new(CsvToJson::new()));
+//!
+//!     let xml = RawPayload::new("text/xml".to_string(), br#"<order id="5000"><status>new</status></order>"#.to_vec());
+//!     let (payload, _applied) = transformations.apply("order", xml).unwrap();
+//!     assert_eq!(payload.content_type, "application/json".to_string());
+//!     assert_eq!(payload.data, br#"{"order":{"@id":"5000","status":"new"}}"#.to_vec());
+//!
+//!     let csv = RawPayload::new("text/csv".to_string(), b"id,status\n5000,new\n".to_vec());
+//!     let (payload, _applied) = transformations.apply("order", csv).unwrap();
+//!     assert_eq!(payload.data, br#"[{"id":"5000","status":"new"}]"#.to_vec());
+//! }
+//! ```
+
+use super::transform::{PayloadTransformer, RawPayload};
+use super::*;
+use serde_json::{Map, Value};
+use xml::reader::{EventReader, ParserConfig, XmlEvent};
+
+/// The content type of the converted payloads
+pub const JSON_CONTENT_TYPE: &str = "application/json";
+
+// Returns the mime type of the content type, (without its parameters, e.g.: charset)
+fn mime_type(content_type: &str) -> String {
+    content_type
+        .split(';')
+        .next()
+        .unwrap_or("")
+        .trim()
+        .to_lowercase()
+}
+
+// Returns the payload as text
+fn to_text(payload: &RawPayload, transformer: &str) -> Result<String, TransformError> {
+    match String::from_utf8(payload.data.clone()) {
+        Ok(text) => Ok(text),
+        Err(err) => {
+            warn!(
+                "The {} transformer could not read the payload as UTF-8. Error: {}",
+                transformer, err
+            );
+            Err(TransformError)
+        }
+    }
+}
+
+/// A transformer that converts XML payloads to JSON
+#[derive(Debug, Clone, Default)]
+pub struct XmlToJson;
+
+impl XmlToJson {
+    /// Constructs a XmlToJson transformer
+    pub fn new() -> XmlToJson {
+        XmlToJson
+    }
+
+    /// Determines if the content type is XML, (e.g.: application/xml, text/xml or application/atom+xml)
+    pub fn is_xml(content_type: &str) -> bool {
+        let mime = mime_type(content_type);
+        mime == "application/xml" || mime == "text/xml" || mime.ends_with("+xml")
+    }
+
+    /// Converts the XML document to JSON
+    ///
+    /// # Arguments
+    ///
+    /// * xml: &str - The XML document.</br>
+    pub fn convert(xml: &str) -> Result<Value, TransformError> {
+        let parser = ParserConfig::new()
+            .trim_whitespace(true)
+            .cdata_to_characters(true)
+            .ignore_comments(true)
+            .create_reader(xml.as_bytes());
+        XmlToJson::read(parser)
+    }
+
+    fn read<R: std::io::Read>(parser: EventReader<R>) -> Result<Value, TransformError> {
+        // the elements that are open, (their names, attributes and children, and text)
+        let mut open: Vec<(String, Map<String, Value>, String)> = Vec::new();
+
+        for event in parser {
+            match event {
+                Ok(XmlEvent::StartElement {
+                    name, attributes, ..
+                }) => {
+                    let mut fields = Map::new();
+                    for attr in attributes {
+                        fields.insert(
+                            format!("@{}", attr.name.local_name),
+                            Value::String(attr.value),
+                        );
+                    }
+                    open.push((name.local_name, fields, String::new()));
+                }
+                Ok(XmlEvent::Characters(text)) => {
+                    if let Some((_name, _fields, content)) = open.last_mut() {
+                        content.push_str(&text);
+                    }
+                }
+                Ok(XmlEvent::EndElement { .. }) => {
+                    let (name, mut fields, text) = open.pop().ok_or(TransformError)?;
+                    let value = match (fields.is_empty(), text.is_empty()) {
+                        (true, _) => Value::String(text),
+                        (false, true) => Value::Object(fields),
+                        (false, false) => {
+                            fields.insert("#text".to_string(), Value::String(text));
+                            Value::Object(fields)
+                        }
+                    };
+
+                    match open.last_mut() {
+                        Some((_parent, siblings, _text)) => {
+                            // the repeated children are converted to an array
+                            match siblings.get_mut(&name) {
+                                Some(Value::Array(items)) => items.push(value),
+                                Some(existing) => {
+                                    let first = existing.take();
+                                    *existing = Value::Array(vec![first, value]);
+                                }
+                                None => {
+                                    siblings.insert(name, value);
+                                }
+                            }
+                        }
+                        None => {
+                            let mut root = Map::new();
+                            root.insert(name, value);
+                            return Ok(Value::Object(root));
+                        }
+                    }
+                }
+                Ok(_) => {}
+                Err(err) => {
+                    warn!("Could not parse the XML payload. Error: {}", err);
+                    return Err(TransformError);
+                }
+            }
+        }
+
+        Err(TransformError)
+    }
+}
+
+impl PayloadTransformer for XmlToJson {
+    fn name(&self) -> String {
+        "xml-to-json".to_string()
+    }
+
+    fn transform(&self, payload: RawPayload) -> Result<RawPayload, TransformError> {
+        if !XmlToJson::is_xml(&payload.content_type) {
+            return Ok(payload);
+        }
+
+        let value = XmlToJson::convert(&to_text(&payload, &self.name())?)?;
+        Ok(RawPayload::new(
+            JSON_CONTENT_TYPE.to_string(),
+            serde_json::to_vec(&value).unwrap(),
+        ))
+    }
+}
+
+/// A transformer that converts CSV payloads with a header row to JSON
+#[derive(Debug, Clone)]
+pub struct CsvToJson {
+    /// The character that separates the fields, (default: ,)
+    pub delimiter: char,
+}
+
+impl CsvToJson {
+    /// Constructs a CsvToJson transformer of comma separated values
+    pub fn new() -> CsvToJson {
+        CsvToJson { delimiter: ',' }
+    }
+
+    /// Sets the character that separates the fields, (e.g.: ;)
+    pub fn with_delimiter(mut self, delimiter: char) -> CsvToJson {
+        self.delimiter = delimiter;
+        self
+    }
+
+    /// Determines if the content type is CSV, (e.g.: text/csv)
+    pub fn is_csv(content_type: &str) -> bool {
+        let mime = mime_type(content_type);
+        mime == "text/csv" || mime == "application/csv"
+    }
+
+    /// Converts the CSV document to a JSON array of objects, (the header row has the keys of the objects)
+    ///
+    /// # Arguments
+    ///
+    /// * csv: &str - The CSV document.</br>
+    pub fn convert(&self, csv: &str) -> Result<Value, TransformError> {
+        let mut records = self.parse(csv)?.into_iter();
+        let header = match records.next() {
+            Some(h) => h,
+            None => return Ok(Value::Array(Vec::new())),
+        };
+
+        let mut rows = Vec::new();
+        for (line, record) in records.enumerate() {
+            if record.len() != header.len() {
+                warn!(
+                    "Record {} of the CSV payload has {} fields instead of {}.",
+                    line + 1,
+                    record.len(),
+                    header.len()
+                );
+                return Err(TransformError);
+            }
+            rows.push(Value::Object(
+                header
+                    .iter()
+                    .cloned()
+                    .zip(record.into_iter().map(Value::String))
+                    .collect(),
+            ));
+        }
+
+        Ok(Value::Array(rows))
+    }
+
+    // Parses the records of the CSV document, (RFC 4180)
+    fn parse(&self, csv: &str) -> Result<Vec<Vec<String>>, TransformError> {
+        let mut records = Vec::new();
+        let mut record = Vec::new();
+        let mut field = String::new();
+        let mut quoted = false;
+        let mut chars = csv.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match (quoted, c) {
+                (true, '"') => match chars.peek() {
+                    // an escaped quote
+                    Some('"') => {
+                        field.push('"');
+                        chars.next();
+                    }
+                    _ => quoted = false,
+                },
+                (true, c) => field.push(c),
+                (false, '"') if field.is_empty() => quoted = true,
+                (false, '\r') => {}
+                (false, '\n') => {
+                    record.push(std::mem::take(&mut field));
+                    records.push(std::mem::take(&mut record));
+                }
+                (false, c) if c == self.delimiter => record.push(std::mem::take(&mut field)),
+                (false, c) => field.push(c),
+            }
+        }
+
+        if quoted {
+            warn!("The CSV payload has an unterminated quoted field.");
+            return Err(TransformError);
+        }
+        if !field.is_empty() || !record.is_empty() {
+            record.push(field);
+            records.push(record);
+        }
+
+        // the blank lines are skipped
+        Ok(records
+            .into_iter()
+            .filter(|r| !(r.len() == 1 && r[0].is_empty()))
+            .collect())
+    }
+}
+
+impl Default for CsvToJson {
+    fn default() -> Self {
+        CsvToJson::new()
+    }
+}
+
+impl PayloadTransformer for CsvToJson {
+    fn name(&self) -> String {
+        "csv-to-json".to_string()
+    }
+
+    fn transform(&self, payload: RawPayload) -> Result<RawPayload, TransformError> {
+        if !CsvToJson::is_csv(&payload.content_type) {
+            return Ok(payload);
+        }
+
+        let value = self.convert(&to_text(&payload, &self.name())?)?;
+        Ok(RawPayload::new(
+            JSON_CONTENT_TYPE.to_string(),
+            serde_json::to_vec(&value).unwrap(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_is_xml() {
+        assert!(XmlToJson::is_xml("application/xml"));
+        assert!(XmlToJson::is_xml("Text/XML; charset=utf-8"));
+        assert!(XmlToJson::is_xml("application/atom+xml"));
+        assert!(!XmlToJson::is_xml("application/json"));
+    }
+
+    #[test]
+    fn test_xml_convert() {
+        let xml = r#"<?xml version="1.0"?>
+            <order id="5000">
+                <!-- the items -->
+                <item sku="a">shirt</item>
+                <item sku="b">hat</item>
+                <status>new</status>
+                <note><![CDATA[fragile & heavy]]></note>
+                <gift/>
+            </order>"#;
+
+        assert_eq!(
+            XmlToJson::convert(xml).unwrap(),
+            json!({"order": {
+                "@id": "5000",
+                "item": [{"@sku": "a", "#text": "shirt"}, {"@sku": "b", "#text": "hat"}],
+                "status": "new",
+                "note": "fragile & heavy",
+                "gift": ""
+            }})
+        );
+    }
+
+    #[test]
+    fn test_xml_invalid() {
+        assert!(XmlToJson::convert("<order><status>new</order>").is_err());
+        assert!(XmlToJson::convert("").is_err());
+
+        let payload = RawPayload::new("application/xml".to_string(), b"<order>".to_vec());
+        assert!(XmlToJson::new().transform(payload).is_err());
+    }
+
+    #[test]
+    fn test_is_csv() {
+        assert!(CsvToJson::is_csv("text/csv; header=present"));
+        assert!(!CsvToJson::is_csv("text/plain"));
+    }
+
+    #[test]
+    fn test_csv_convert() {
+        let csv = "id,name,note\r\n1,\"Doe, Jo\",\"said \"\"hi\"\"\"\r\n\r\n2,Ann,\"two\nlines\"";
+
+        assert_eq!(
+            CsvToJson::new().convert(csv).unwrap(),
+            json!([
+                {"id": "1", "name": "Doe, Jo", "note": "said \"hi\""},
+                {"id": "2", "name": "Ann", "note": "two\nlines"}
+            ])
+        );
+        assert_eq!(
+            CsvToJson::new()
+                .with_delimiter(';')
+                .convert("id;name\n1;Jo\n")
+                .unwrap(),
+            json!([{"id": "1", "name": "Jo"}])
+        );
+        assert_eq!(CsvToJson::new().convert("").unwrap(), json!([]));
+    }
+
+    #[test]
+    fn test_csv_invalid() {
+        assert!(CsvToJson::new().convert("id,name\n1\n").is_err());
+        assert!(CsvToJson::new().convert("id\n\"1\n").is_err());
+    }
+
+    #[test]
+    fn test_transform_other_content_type() {
+        let payload = RawPayload::new("application/json".to_string(), b"{}".to_vec());
+
+        assert_eq!(
+            XmlToJson::new().transform(payload.clone()).unwrap(),
+            payload
+        );
+        assert_eq!(
+            CsvToJson::new().transform(payload.clone()).unwrap(),
+            payload
+        );
+    }
+}
@@ -0,0 +1,422 @@
+//! Cross-Origin Resource Sharing (CORS) for the DaaS services, so web applications can submit and retrieve the data
+//! directly from the browser.
+//!
+//! The `Cors` middleware answers the preflight requests (OPTIONS) of the allowed origins itself, before they reach
+//! the other middleware, (e.g.: the `DUAEnforcer` would reject them because the browser doesn't send the custom
+//! headers with the preflight). It should therefore be the last middleware that is wrapped, so it runs first.
+//! The custom headers of the DaaS services, (e.g.: the Data Usage Agreements and Data Tracker Chain) are allowed by
+//! default, and the headers of the responses that the clients need, (e.g.: ETag and Location) are exposed.
+//!
+//! # Examples
+//!
+//! ```
+//! extern crate actix_web;
+//! extern crate daas;
+//!
+//! use actix_web::{test, App};
+//! use daas::service::cors::Cors;
+//! use daas::testing;
+//!
+//! #[actix_rt::main]
+//! async fn main() {
+//!     let cors = Cors::new().with_origin("https://app.example.com".to_string());
+//!     let mut app = test::init_service(App::new().configure(testing::configure_listener).wrap(cors)).await;
+//!     let req = test::TestRequest::get()
+//!         .uri("/health")
+//!         .header("Origin", "https://app.example.com")
+//!         .to_request();
+//!     let resp = test::call_service(&mut app, req).await;
+//!
+//!     assert_eq!(resp.headers().get("Access-Control-Allow-Origin").unwrap(), "https://app.example.com");
+//! }
+//! ```
+
+use super::access::PURPOSE_HEADER;
+use super::idempotency::{IDEMPOTENCY_KEY_HEADER, IDEMPOTENT_REPLAYED_HEADER};
+use super::*;
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::Error;
+use futures::future::{ok, LocalBoxFuture, Ready};
+use pbd::dtc::DTC_HEADER;
+use pbd::dua::DUA_HEADER;
+use std::rc::Rc;
+use std::task::{Context, Poll};
+
+/// Represents the CORS configuration of the DaaS services
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cors {
+    /// The origins that are allowed, (e.g.: https://app.example.com) or `*` for any origin
+    pub allowed_origins: Vec<String>,
+    /// The methods that are allowed, (default: GET, POST, PATCH, DELETE)
+    pub allowed_methods: Vec<String>,
+    /// The request headers that are allowed, (default: the headers of the DaaS services)
+    pub allowed_headers: Vec<String>,
+    /// The response headers that the browser exposes to the web application, (default: ETag, Location, etc.)
+    pub exposed_headers: Vec<String>,
+    /// How long (in seconds) the browser caches the response of a preflight request, (default: 3600)
+    pub max_age: u64,
+    /// The indicator that represents if the browser sends the credentials, (e.g.: cookies), (default: false)
+    pub allow_credentials: bool,
+}
+
+impl Cors {
+    /// Constructs a Cors configuration that doesn't allow any origin yet
+    pub fn new() -> Cors {
+        let to_vec = |items: &[&str]| items.iter().map(|i| i.to_string()).collect();
+
+        Cors {
+            allowed_origins: Vec::new(),
+            allowed_methods: to_vec(&["GET", "POST", "PATCH", "DELETE"]),
+            allowed_headers: to_vec(&[
+                "Authorization",
+                "Content-Type",
+                "Content-Encoding",
+                "If-Match",
+                "If-None-Match",
+                "Prefer",
+                DUA_HEADER,
+                DTC_HEADER,
+                PURPOSE_HEADER,
+                IDEMPOTENCY_KEY_HEADER,
+            ]),
+            exposed_headers: to_vec(&[
+                "ETag",
+                "Location",
+                IDEMPOTENT_REPLAYED_HEADER,
+                "X-DaaS-Next-Cursor",
+            ]),
+            max_age: 3600,
+            allow_credentials: false,
+        }
+    }
+
+    /// Allows the origin, (e.g.: https://app.example.com)
+    pub fn with_origin(mut self, origin: String) -> Cors {
+        self.allowed_origins
+            .push(origin.trim_end_matches('/').to_string());
+        self
+    }
+
+    /// Allows any origin
+    pub fn with_any_origin(mut self) -> Cors {
+        self.allowed_origins.push("*".to_string());
+        self
+    }
+
+    /// Allows the request header, (e.g.: a custom header of the AuthorExtractor)
+    pub fn with_header(mut self, header: String) -> Cors {
+        self.allowed_headers.push(header);
+        self
+    }
+
+    /// Sets the methods that are allowed
+    pub fn with_methods(mut self, methods: Vec<String>) -> Cors {
+        self.allowed_methods = methods;
+        self
+    }
+
+    /// Sets how long (in seconds) the browser caches the response of a preflight request
+    pub fn with_max_age(mut self, max_age: u64) -> Cors {
+        self.max_age = max_age;
+        self
+    }
+
+    /// Sets if the browser sends the credentials, (the origin is then always echoed instead of `*`)
+    pub fn with_credentials(mut self, allow: bool) -> Cors {
+        self.allow_credentials = allow;
+        self
+    }
+
+    /// Returns true if the origin is allowed
+    ///
+    /// # Arguments
+    ///
+    /// * origin: &str - The value of the Origin header.</br>
+    pub fn is_origin_allowed(&self, origin: &str) -> bool {
+        self.allowed_origins
+            .iter()
+            .any(|o| o == "*" || o.eq_ignore_ascii_case(origin))
+    }
+
+    /// Returns true if the method and all the headers of the preflight request are allowed
+    ///
+    /// # Arguments
+    ///
+    /// * method: &str - The value of the Access-Control-Request-Method header.</br>
+    /// * headers: &str - The value of the Access-Control-Request-Headers header, (comma separated).</br>
+    pub fn is_request_allowed(&self, method: &str, headers: &str) -> bool {
+        let method_allowed = self
+            .allowed_methods
+            .iter()
+            .any(|m| m.eq_ignore_ascii_case(method));
+        let headers_allowed = headers
+            .split(',')
+            .map(|h| h.trim())
+            .filter(|h| !h.is_empty())
+            .all(|h| {
+                self.allowed_headers
+                    .iter()
+                    .any(|a| a.eq_ignore_ascii_case(h))
+            });
+
+        method_allowed && headers_allowed
+    }
+
+    // Returns the value of the Access-Control-Allow-Origin header for the allowed origin
+    fn allow_origin(&self, origin: &str) -> String {
+        match self.allowed_origins.iter().any(|o| o == "*") && !self.allow_credentials {
+            true => "*".to_string(),
+            false => origin.to_string(),
+        }
+    }
+
+    // Returns the headers of the response to an allowed preflight request
+    fn preflight_headers(&self, origin: &str) -> Vec<(&'static str, String)> {
+        let mut headers = vec![
+            ("access-control-allow-origin", self.allow_origin(origin)),
+            (
+                "access-control-allow-methods",
+                self.allowed_methods.join(", "),
+            ),
+            (
+                "access-control-allow-headers",
+                self.allowed_headers.join(", "),
+            ),
+            ("access-control-max-age", self.max_age.to_string()),
+            ("vary", "Origin".to_string()),
+        ];
+        if self.allow_credentials {
+            headers.push(("access-control-allow-credentials", "true".to_string()));
+        }
+        headers
+    }
+
+    // Returns the headers that are added to the response to an actual request of an allowed origin, (the names are
+    // lowercase as required by HeaderName::from_static)
+    fn response_headers(&self, origin: &str) -> Vec<(&'static str, String)> {
+        let mut headers = vec![
+            ("access-control-allow-origin", self.allow_origin(origin)),
+            (
+                "access-control-expose-headers",
+                self.exposed_headers.join(", "),
+            ),
+            ("vary", "Origin".to_string()),
+        ];
+        if self.allow_credentials {
+            headers.push(("access-control-allow-credentials", "true".to_string()));
+        }
+        headers
+    }
+}
+
+impl Default for Cors {
+    fn default() -> Self {
+        Cors::new()
+    }
+}
+
+impl<S, B> Transform<S> for Cors
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = CorsMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(CorsMiddleware {
+            service,
+            cors: Rc::new(self.clone()),
+        })
+    }
+}
+
+/// The middleware that applies the Cors configuration, (see `Cors`)
+pub struct CorsMiddleware<S> {
+    service: S,
+    cors: Rc<Cors>,
+}
+
+impl<S, B> Service for CorsMiddleware<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<ServiceResponse<B>, Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        let header = |name: &str| {
+            req.headers()
+                .get(name)
+                .and_then(|h| h.to_str().ok())
+                .map(|h| h.to_string())
+        };
+        // the requests without an Origin aren't cross-origin requests of a browser
+        let origin = match header("Origin") {
+            Some(o) => o,
+            None => return Box::pin(self.service.call(req)),
+        };
+        let allowed = self.cors.is_origin_allowed(&origin);
+
+        if req.method() == http::Method::OPTIONS {
+            if let Some(method) = header("Access-Control-Request-Method") {
+                let requested = header("Access-Control-Request-Headers").unwrap_or_default();
+                let rspns = match allowed && self.cors.is_request_allowed(&method, &requested) {
+                    true => {
+                        let mut builder = HttpResponse::NoContent();
+                        for (name, value) in self.cors.preflight_headers(&origin) {
+                            builder.header(name, value);
+                        }
+                        builder.finish()
+                    }
+                    false => {
+                        warn!(
+                            "Rejected the preflight request of {} for {} {}.",
+                            origin, method, requested
+                        );
+                        HttpResponse::Forbidden().finish()
+                    }
+                };
+                return Box::pin(ok(req.into_response(rspns.into_body())));
+            }
+        }
+
+        // the responses to the origins that aren't allowed don't have the CORS headers, so the browser blocks them
+        let cors = self.cors.clone();
+        let fut = self.service.call(req);
+        Box::pin(async move {
+            let mut res = fut.await?;
+            if allowed {
+                for (name, value) in cors.response_headers(&origin) {
+                    if let Ok(v) = HeaderValue::from_str(&value) {
+                        res.headers_mut().insert(HeaderName::from_static(name), v);
+                    }
+                }
+            }
+            Ok(res)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use actix_web::http::StatusCode;
+    use actix_web::{test, App};
+
+    const ORIGIN: &str = "https://app.example.com";
+
+    #[test]
+    fn test_is_request_allowed() {
+        let cors = Cors::new().with_origin(format!("{}/", ORIGIN));
+
+        assert!(cors.is_origin_allowed(ORIGIN));
+        assert!(!cors.is_origin_allowed("https://evil.example.com"));
+        assert!(cors.is_request_allowed("post", "content-type, data-usage-agreement"));
+        assert!(!cors.is_request_allowed("PUT", ""));
+        assert!(!cors.is_request_allowed("POST", "X-Unknown"));
+        assert!(Cors::new()
+            .with_header("X-Unknown".to_string())
+            .is_request_allowed("POST", "X-Unknown"));
+    }
+
+    #[test]
+    fn test_allow_origin() {
+        assert_eq!(Cors::new().with_any_origin().allow_origin(ORIGIN), "*");
+        assert_eq!(
+            Cors::new()
+                .with_any_origin()
+                .with_credentials(true)
+                .allow_origin(ORIGIN),
+            ORIGIN
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_preflight() {
+        let mut app = test::init_service(
+            App::new()
+                .configure(testing::configure_listener)
+                .wrap(Cors::new().with_origin(ORIGIN.to_string())),
+        )
+        .await;
+
+        let req = test::TestRequest::with_uri("/order/clothing/iStore/5000")
+            .method(http::Method::OPTIONS)
+            .header("Origin", ORIGIN)
+            .header("Access-Control-Request-Method", "POST")
+            .header(
+                "Access-Control-Request-Headers",
+                format!("{}, {}", DUA_HEADER, DTC_HEADER),
+            )
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+        assert_eq!(
+            resp.headers().get("Access-Control-Allow-Origin").unwrap(),
+            ORIGIN
+        );
+        assert_eq!(
+            resp.headers().get("Access-Control-Max-Age").unwrap(),
+            "3600"
+        );
+
+        let req = test::TestRequest::with_uri("/order/clothing/iStore/5000")
+            .method(http::Method::OPTIONS)
+            .header("Origin", "https://evil.example.com")
+            .header("Access-Control-Request-Method", "POST")
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[actix_rt::test]
+    async fn test_actual_request() {
+        let mut app = test::init_service(
+            App::new()
+                .configure(testing::configure_listener)
+                .wrap(Cors::new().with_origin(ORIGIN.to_string())),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/health")
+            .header("Origin", ORIGIN)
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert!(resp.status().is_success());
+        assert!(resp
+            .headers()
+            .get("Access-Control-Expose-Headers")
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .contains("ETag"));
+
+        let req = test::TestRequest::get()
+            .uri("/health")
+            .header("Origin", "https://evil.example.com")
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert!(resp.headers().get("Access-Control-Allow-Origin").is_none());
+
+        let req = test::TestRequest::get().uri("/health").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert!(resp.headers().get("Vary").is_none());
+    }
+}
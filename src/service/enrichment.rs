@@ -0,0 +1,477 @@
+//! Enriches DaaS documents with reference data, (e.g.: the customer of an order).
+//!
+//! An `Enricher` reads a key from the JSON data object of the DaaS document, looks up the reference data in a
+//! `ReferenceSource` and merges it into the data object or the metadata. The lookups are cached, a `FailurePolicy`
+//! decides what happens when the reference data can't be found, and the enricher is recorded as an actor in the
+//! Data Tracker Chain of the DaaS document.
+//!
+//! The reference data can be a static (JSON) file, an HTTP service or any other source that implements
+//! `ReferenceSource`, (e.g.: a Redis cache).
+//!
+//! # Examples
+//!
+//! ```
+//! extern crate daas;
+//! extern crate serde_json;
+//!
+//! use daas::service::enrichment::{Enricher, MergeTarget, StaticSource};
+//! use daas::service::pipeline::Pipeline;
+//! use daas::testing;
+//! use serde_json::json;
+//!
+//! fn main() {
+//!     let mut regions = std::collections::HashMap::new();
+//!     regions.insert("new".to_string(), json!("east"));
+//!     let enricher = Enricher::new(
+//!         "region-enricher".to_string(),
+//!         Box::new(StaticSource::new(regions)),
+//!         "status".to_string(),
+//!         MergeTarget::Meta("region".to_string()),
+//!     );
+//!     let pipeline = Pipeline::new().enrich(move |doc| enricher.enrich(doc));
+//!
+//!     let mut doc = testing::get_default_daas_doc();
+//!     pipeline.run(doc).unwrap();
+//! }
+//! ```
+
+use super::*;
+use crate::doc::DaaSDoc;
+use crate::errors::daaserror::DaaSProcessingError;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+
+/// Trait for the sources of reference data
+pub trait ReferenceSource: Send + Sync {
+    /// Returns the reference data of the key, or None if the source doesn't have it
+    ///
+    /// # Arguments
+    ///
+    /// * key: &str - The key of the reference data.</br>
+    fn lookup(&self, key: &str) -> Result<Option<Value>, RetrieveError>;
+}
+
+/// A source of reference data that is kept in memory, (e.g.: loaded from a static file)
+pub struct StaticSource {
+    /// The reference data of each key
+    pub entries: HashMap<String, Value>,
+}
+
+impl StaticSource {
+    /// Constructs a StaticSource
+    pub fn new(entries: HashMap<String, Value>) -> StaticSource {
+        StaticSource { entries }
+    }
+
+    /// Constructs a StaticSource from a file that has a JSON object, (key: reference data)
+    ///
+    /// # Arguments
+    ///
+    /// * path: &str - The path of the file.</br>
+    pub fn from_file(path: &str) -> Result<StaticSource, RetrieveError> {
+        match fs::read(path)
+            .ok()
+            .and_then(|c| serde_json::from_slice::<HashMap<String, Value>>(&c).ok())
+        {
+            Some(entries) => Ok(StaticSource::new(entries)),
+            None => {
+                error!("Could not load the reference data from {}.", path);
+                Err(RetrieveError)
+            }
+        }
+    }
+}
+
+impl ReferenceSource for StaticSource {
+    fn lookup(&self, key: &str) -> Result<Option<Value>, RetrieveError> {
+        Ok(self.entries.get(key).cloned())
+    }
+}
+
+/// A source of reference data that is an HTTP service which returns JSON (requires the `http` feature)
+#[cfg(feature = "http")]
+pub struct HttpSource {
+    /// The URL of the reference data, where `{key}` is replaced with the key, (e.g.: http://crm/customers/{key})
+    pub url: String,
+    client: reqwest::blocking::Client,
+}
+
+#[cfg(feature = "http")]
+impl HttpSource {
+    /// Constructs an HttpSource
+    ///
+    /// # Arguments
+    ///
+    /// * url: String - The URL of the reference data, where `{key}` is replaced with the key.</br>
+    /// * timeout: Duration - How long to wait for the service to respond.</br>
+    pub fn new(url: String, timeout: Duration) -> HttpSource {
+        HttpSource {
+            url,
+            client: reqwest::blocking::Client::builder()
+                .timeout(timeout)
+                .build()
+                .unwrap(),
+        }
+    }
+}
+
+#[cfg(feature = "http")]
+impl ReferenceSource for HttpSource {
+    fn lookup(&self, key: &str) -> Result<Option<Value>, RetrieveError> {
+        let url = self
+            .url
+            .replace("{key}", &DaaSDoc::escape_id_component(key));
+
+        match self.client.get(&url).send() {
+            Ok(r) if r.status() == reqwest::StatusCode::NOT_FOUND => Ok(None),
+            Ok(r) if r.status().is_success() => {
+                match r.bytes().ok().and_then(|b| serde_json::from_slice(&b).ok()) {
+                    Some(value) => Ok(Some(value)),
+                    None => {
+                        error!("The reference data {} is not JSON.", url);
+                        Err(RetrieveError)
+                    }
+                }
+            }
+            Ok(r) => {
+                error!("The reference data {} responded with {}.", url, r.status());
+                Err(RetrieveError)
+            }
+            Err(err) => {
+                error!("Could not get the reference data {}. Error: {}", url, err);
+                Err(RetrieveError)
+            }
+        }
+    }
+}
+
+/// Where the reference data is merged into the DaaS document
+#[derive(Debug, Clone, PartialEq)]
+pub enum MergeTarget {
+    /// The field of the JSON data object that is set to the reference data
+    Data(String),
+    /// The metadata key that is set to the reference data. The entries of a JSON object are added as `key.entry`.
+    Meta(String),
+}
+
+/// What happens when the reference data can't be looked up or found
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum FailurePolicy {
+    /// The DaaS document isn't processed
+    #[default]
+    Fail,
+    /// The DaaS document is processed without the reference data
+    Skip,
+    /// The DaaS document is processed using the default reference data
+    UseDefault(Value),
+}
+
+/// Represents a stage that enriches DaaS documents with reference data
+pub struct Enricher {
+    /// The name of the enricher that is recorded in the Data Tracker Chain
+    pub actor: String,
+    /// The field of the data object that has the key, (a JSON pointer if it starts with `/`, e.g.: /customer/id)
+    pub key_field: String,
+    /// Where the reference data is merged
+    pub target: MergeTarget,
+    /// What happens when the reference data can't be found
+    pub policy: FailurePolicy,
+    /// How long a lookup is cached, (None if lookups aren't cached)
+    pub cache_ttl: Option<Duration>,
+    source: Box<dyn ReferenceSource>,
+    cache: Mutex<HashMap<String, (Option<Value>, Instant)>>,
+}
+
+impl Enricher {
+    /// Constructs an Enricher that fails when the reference data can't be found and caches the lookups for 5 minutes
+    ///
+    /// # Arguments
+    ///
+    /// * actor: String - The name of the enricher that is recorded in the Data Tracker Chain.</br>
+    /// * source: Box<dyn ReferenceSource> - The source of the reference data.</br>
+    /// * key_field: String - The field of the data object that has the key.</br>
+    /// * target: MergeTarget - Where the reference data is merged.</br>
+    pub fn new(
+        actor: String,
+        source: Box<dyn ReferenceSource>,
+        key_field: String,
+        target: MergeTarget,
+    ) -> Enricher {
+        Enricher {
+            actor,
+            key_field,
+            target,
+            policy: FailurePolicy::default(),
+            cache_ttl: Some(Duration::from_secs(300)),
+            source,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Sets what happens when the reference data can't be found
+    pub fn with_policy(mut self, policy: FailurePolicy) -> Enricher {
+        self.policy = policy;
+        self
+    }
+
+    /// Sets how long a lookup is cached, (None to not cache the lookups)
+    pub fn with_cache_ttl(mut self, cache_ttl: Option<Duration>) -> Enricher {
+        self.cache_ttl = cache_ttl;
+        self
+    }
+
+    /// Merges the reference data into the DaaS document and records the enricher in the Data Tracker Chain
+    ///
+    /// # Arguments
+    ///
+    /// * doc: &mut DaaSDoc - The DaaS document to enrich.</br>
+    pub fn enrich(&self, doc: &mut DaaSDoc) -> Result<(), DaaSProcessingError> {
+        let reference = match self
+            .get_key(doc)
+            .ok_or(RetrieveError)
+            .and_then(|key| self.lookup(&key))
+        {
+            Ok(Some(value)) => value,
+            Ok(None) | Err(_) => match &self.policy {
+                FailurePolicy::Fail => {
+                    warn!(
+                        "Could not find the reference data for DaaS document {}.",
+                        doc._id
+                    );
+                    return Err(DaaSProcessingError::RetrieveError);
+                }
+                FailurePolicy::Skip => return Ok(()),
+                FailurePolicy::UseDefault(value) => value.clone(),
+            },
+        };
+
+        match &self.target {
+            MergeTarget::Data(field) => {
+                let mut data: Value = match serde_json::from_slice(&doc.data_obj) {
+                    Ok(Value::Object(obj)) => Value::Object(obj),
+                    _ => {
+                        error!(
+                            "The data object of DaaS document {} is not a JSON object.",
+                            doc._id
+                        );
+                        return Err(DaaSProcessingError::UpsertError);
+                    }
+                };
+                data[field.as_str()] = reference;
+                doc.data_obj = serde_json::to_vec(&data).unwrap();
+            }
+            MergeTarget::Meta(key) => match reference {
+                Value::Object(entries) => {
+                    for (k, v) in entries.iter() {
+                        doc.add_meta(format!("{}.{}", key, k), Enricher::to_meta_value(v));
+                    }
+                }
+                value => doc.add_meta(key.clone(), Enricher::to_meta_value(&value)),
+            },
+        }
+
+        doc.data_tracker
+            .add(get_unix_now!(), self.actor.clone(), doc._id.clone());
+        Ok(())
+    }
+
+    // Reads the key from the JSON data object of the DaaS document
+    fn get_key(&self, doc: &DaaSDoc) -> Option<String> {
+        let data: Value = serde_json::from_slice(&doc.data_obj).ok()?;
+        let value = match self.key_field.starts_with('/') {
+            true => data.pointer(&self.key_field)?,
+            false => data.get(&self.key_field)?,
+        };
+
+        match value {
+            Value::String(s) => Some(s.clone()),
+            Value::Number(n) => Some(n.to_string()),
+            _ => None,
+        }
+    }
+
+    // Looks up the reference data using the cache
+    fn lookup(&self, key: &str) -> Result<Option<Value>, RetrieveError> {
+        if let Some(ttl) = self.cache_ttl {
+            if let Some((value, cached)) = self.cache.lock().unwrap().get(key) {
+                if cached.elapsed() < ttl {
+                    return Ok(value.clone());
+                }
+            }
+        }
+
+        let value = self.source.lookup(key)?;
+        if self.cache_ttl.is_some() {
+            self.cache
+                .lock()
+                .unwrap()
+                .insert(key.to_string(), (value.clone(), Instant::now()));
+        }
+
+        Ok(value)
+    }
+
+    fn to_meta_value(value: &Value) -> String {
+        match value {
+            Value::String(s) => s.clone(),
+            other => other.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use serde_json::json;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+
+    // A source that counts the lookups
+    struct CountingSource {
+        lookups: Arc<AtomicUsize>,
+    }
+
+    impl ReferenceSource for CountingSource {
+        fn lookup(&self, key: &str) -> Result<Option<Value>, RetrieveError> {
+            self.lookups.fetch_add(1, Ordering::SeqCst);
+            match key {
+                "new" => Ok(Some(json!({"label": "New order", "rank": 1}))),
+                _ => Ok(None),
+            }
+        }
+    }
+
+    fn get_enricher(target: MergeTarget) -> (Enricher, Arc<AtomicUsize>) {
+        let lookups = Arc::new(AtomicUsize::new(0));
+        let source = CountingSource {
+            lookups: lookups.clone(),
+        };
+
+        (
+            Enricher::new(
+                "status-enricher".to_string(),
+                Box::new(source),
+                "status".to_string(),
+                target,
+            ),
+            lookups,
+        )
+    }
+
+    #[test]
+    fn test_enrich_meta() {
+        let (enricher, _) = get_enricher(MergeTarget::Meta("status".to_string()));
+        let mut doc = testing::get_default_daas_doc();
+
+        assert!(enricher.enrich(&mut doc).is_ok());
+        assert_eq!(
+            doc.get_meta("status.label".to_string()),
+            "New order".to_string()
+        );
+        assert_eq!(doc.get_meta("status.rank".to_string()), "1".to_string());
+        testing::assert_tracker_valid(&doc.data_tracker);
+        assert!(doc.data_tracker.serialize().contains("status-enricher"));
+    }
+
+    #[test]
+    fn test_enrich_data() {
+        let (enricher, _) = get_enricher(MergeTarget::Data("status_info".to_string()));
+        let mut doc = testing::get_default_daas_doc();
+
+        assert!(enricher.enrich(&mut doc).is_ok());
+        let data: Value = serde_json::from_slice(&doc.data_obj).unwrap();
+        assert_eq!(data["status"], json!("new"));
+        assert_eq!(data["status_info"]["rank"], json!(1));
+    }
+
+    #[test]
+    fn test_enrich_cached() {
+        let (enricher, lookups) = get_enricher(MergeTarget::Meta("status".to_string()));
+
+        enricher
+            .enrich(&mut testing::get_default_daas_doc())
+            .unwrap();
+        enricher
+            .enrich(&mut testing::get_default_daas_doc())
+            .unwrap();
+        assert_eq!(lookups.load(Ordering::SeqCst), 1);
+
+        let (enricher, lookups) = get_enricher(MergeTarget::Meta("status".to_string()));
+        let enricher = enricher.with_cache_ttl(None);
+        enricher
+            .enrich(&mut testing::get_default_daas_doc())
+            .unwrap();
+        enricher
+            .enrich(&mut testing::get_default_daas_doc())
+            .unwrap();
+        assert_eq!(lookups.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_enrich_failure_policy() {
+        let mut doc = testing::get_default_daas_doc();
+        doc.data_obj = br#"{"status": "returned"}"#.to_vec();
+
+        let (enricher, _) = get_enricher(MergeTarget::Meta("status".to_string()));
+        assert!(enricher.enrich(&mut doc.clone()).is_err());
+
+        let enricher = enricher.with_policy(FailurePolicy::Skip);
+        let mut skipped = doc.clone();
+        assert!(enricher.enrich(&mut skipped).is_ok());
+        assert!(skipped.meta_data.is_empty());
+
+        let enricher = enricher.with_policy(FailurePolicy::UseDefault(json!("unknown")));
+        assert!(enricher.enrich(&mut doc).is_ok());
+        assert_eq!(doc.get_meta("status".to_string()), "unknown".to_string());
+    }
+
+    #[test]
+    fn test_static_source_from_file() {
+        let path = format!("./tmp/reference-{}.json", rand::random::<u32>());
+        fs::create_dir_all("./tmp").unwrap();
+        fs::write(&path, r#"{"new": "east"}"#).unwrap();
+        let source = StaticSource::from_file(&path).unwrap();
+
+        assert_eq!(source.lookup("new").unwrap(), Some(json!("east")));
+        assert_eq!(source.lookup("old").unwrap(), None);
+        assert!(StaticSource::from_file("./tmp/missing.json").is_err());
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn test_http_source() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/status/{{key}}", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0; 8192];
+            let len = stream.read(&mut buf).unwrap();
+            let body = r#"{"label": "New order"}"#;
+            stream
+                .write_all(
+                    format!(
+                        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}",
+                        body.len(),
+                        body
+                    )
+                    .as_bytes(),
+                )
+                .unwrap();
+            String::from_utf8_lossy(&buf[..len]).to_string()
+        });
+
+        let source = HttpSource::new(url, Duration::from_secs(5));
+        assert_eq!(
+            source.lookup("new").unwrap(),
+            Some(json!({"label": "New order"}))
+        );
+        assert!(server.join().unwrap().starts_with("GET /status/new"));
+    }
+}
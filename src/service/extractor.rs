@@ -1,7 +1,171 @@
+//! The Author Extractors identify the author of the requests to the DaaS services.
+//!
+//! All the extractors implement the `AuthorExtractor` trait, (e.g.: the `Base64Author` of the Authorization header
+//! and the `ChainedAuthor` that falls back between extractors) and are passed to the services as a generic,
+//! (e.g.: DaaSListener::index::<Base64Author>). A custom extractor only implements `extract_author()` and uses the
+//! exported macros for the structure, the default functions and the `FromRequest` trait of actix-web, or derives
+//! them with `#[derive(AuthorExtractor)]` and implements the `ExtractAuthor` trait.
+//!
+//! # Examples
+//!
+//! ```
+//! #[macro_use]
+//! extern crate daas;
+//! #[macro_use]
+//! extern crate serde_derive;
+//!
+//! use actix_web::HttpRequest;
+//! use daas::errors::MissingAuthorError;
+//! use daas::service::extractor::AuthorExtractor;
+//!
+//! author_struct!(HeaderAuthor);
+//!
+//! impl AuthorExtractor for HeaderAuthor {
+//!     fn extract_author(
+//!         &mut self,
+//!         req: &HttpRequest,
+//!         _payload: &mut actix_web::dev::Payload,
+//!     ) -> Result<String, MissingAuthorError> {
+//!         match req.headers().get("X-Author") {
+//!             Some(hdr) => Ok(hdr.to_str().unwrap_or("").to_string()),
+//!             None => Err(MissingAuthorError),
+//!         }
+//!     }
+//!
+//!     author_fn_get_name!();
+//!     author_fn_new!();
+//!     author_fn_set_name!();
+//! }
+//!
+//! author_from_request!(HeaderAuthor);
+//!
+//! fn main() {
+//!     assert_eq!(HeaderAuthor::new().get_name(), "Anonymous".to_string());
+//! }
+//! ```
+
 use super::*;
-use actix_web::{FromRequest, HttpRequest};
+use crate::doc::DaaSDoc;
+use actix_web::{FromRequest, HttpRequest, ResponseError};
 use base64::decode;
+use serde_json::Value;
+use std::collections::BTreeMap;
 use std::fmt;
+use std::marker::PhantomData;
+
+/// Derives the default functions of the AuthorExtractor trait and the FromRequest trait, (see the daas-derive crate)
+pub use daas_derive::AuthorExtractor;
+
+/// The metadata key of the identity of the author of a DaaS document, (see `AuthorIdentity`)
+pub const AUTHOR_IDENTITY_META: &str = "author-identity";
+
+//
+// The identity of an Author
+//
+
+/// Represents the identity of the author of a request, so the policy decisions and auditing can use more than the name
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AuthorIdentity {
+    /// The unique identifier of the author, (i.e.: the author of the DaaS document)
+    pub id: String,
+    /// The name of the author to display
+    pub display_name: String,
+    /// The roles of the author, (e.g.: finance)
+    #[serde(default)]
+    pub roles: Vec<String>,
+    /// The claims about the author, (e.g.: the issuer of a token)
+    #[serde(default)]
+    pub claims: BTreeMap<String, Value>,
+}
+
+impl AuthorIdentity {
+    /// Constructs an AuthorIdentity without roles and claims
+    ///
+    /// # Arguments
+    ///
+    /// * id: String - The unique identifier of the author, (also used as the display name).</br>
+    ///
+    /// #Example
+    ///
+    /// ```rust
+    /// extern crate daas;
+    ///
+    /// use daas::service::extractor::AuthorIdentity;
+    ///
+    /// fn main() {
+    ///     let identity = AuthorIdentity::new("istore_app".to_string())
+    ///         .with_display_name("iStore".to_string())
+    ///         .with_role("finance".to_string())
+    ///         .with_claim("iss".to_string(), serde_json::json!("https://auth.example.com"));
+    ///
+    ///     assert!(identity.has_role("finance"));
+    ///     assert_eq!(identity.claims.get("iss").unwrap(), "https://auth.example.com");
+    /// }
+    /// ```
+    pub fn new(id: String) -> AuthorIdentity {
+        AuthorIdentity {
+            display_name: id.clone(),
+            id,
+            roles: Vec::new(),
+            claims: BTreeMap::new(),
+        }
+    }
+
+    /// Sets the name of the author to display
+    pub fn with_display_name(mut self, display_name: String) -> AuthorIdentity {
+        self.display_name = display_name;
+        self
+    }
+
+    /// Adds a role of the author
+    pub fn with_role(mut self, role: String) -> AuthorIdentity {
+        self.roles.push(role);
+        self
+    }
+
+    /// Adds a claim about the author
+    ///
+    /// # Arguments
+    ///
+    /// * name: String - The name of the claim, (e.g.: iss).</br>
+    /// * value: Value - The JSON value of the claim.</br>
+    pub fn with_claim(mut self, name: String, value: Value) -> AuthorIdentity {
+        self.claims.insert(name, value);
+        self
+    }
+
+    /// Determines if the author has the role
+    pub fn has_role(&self, role: &str) -> bool {
+        self.roles.iter().any(|r| r == role)
+    }
+
+    /// Sets the author of the DaaS document and records the identity in its metadata
+    ///
+    /// # Arguments
+    ///
+    /// * doc: &mut DaaSDoc - The DaaS document the author sent.</br>
+    pub fn add_to(&self, doc: &mut DaaSDoc) {
+        doc.author = self.id.clone();
+        doc.add_meta(
+            AUTHOR_IDENTITY_META.to_string(),
+            serde_json::to_string(self).unwrap(),
+        );
+    }
+
+    /// Returns the identity of the author that is recorded in the metadata of the DaaS document, (see `add_to()`)
+    ///
+    /// # Arguments
+    ///
+    /// * doc: &DaaSDoc - The DaaS document.</br>
+    pub fn from_doc(doc: &DaaSDoc) -> Option<AuthorIdentity> {
+        doc.meta_data
+            .get(AUTHOR_IDENTITY_META)
+            .and_then(|m| serde_json::from_str(m).ok())
+    }
+}
+
+// the HTTP response of the requests without an author
+impl ResponseError for MissingAuthorError {}
 
 //
 // The common trait for all Author Extractors
@@ -14,6 +178,19 @@ pub trait AuthorExtractor {
         req: &HttpRequest,
         _payload: &mut actix_web::dev::Payload,
     ) -> Result<String, MissingAuthorError>;
+    /// Extracts the identity of the author, (by default only the name from `extract_author()`).
+    /// The extractors that know the roles or claims of the author override it together with `get_identity()`.
+    fn extract_identity(
+        &mut self,
+        req: &HttpRequest,
+        payload: &mut actix_web::dev::Payload,
+    ) -> Result<AuthorIdentity, MissingAuthorError> {
+        self.extract_author(req, payload).map(AuthorIdentity::new)
+    }
+    /// Returns the identity of the author that was extracted, (by default only the name)
+    fn get_identity(&self) -> AuthorIdentity {
+        AuthorIdentity::new(self.get_name())
+    }
     fn get_name(&self) -> String;
     fn new() -> Self;
     fn set_name(&mut self, name: String) -> Result<Self, MissingAuthorError>
@@ -21,6 +198,54 @@ pub trait AuthorExtractor {
         Self: std::marker::Sized;
 }
 
+/// The extraction of the author that is implemented for the structures that derive the AuthorExtractor,
+/// (e.g.: #[derive(AuthorExtractor, Debug, Clone)])
+///
+/// #Example
+///
+/// ```rust
+/// extern crate actix_web;
+/// extern crate daas;
+///
+/// use actix_web::{test, FromRequest, HttpRequest};
+/// use daas::errors::MissingAuthorError;
+/// use daas::service::extractor::{AuthorExtractor, ExtractAuthor};
+///
+/// #[derive(AuthorExtractor, Debug, Clone)]
+/// pub struct HeaderAuthor {
+///     name: String,
+/// }
+///
+/// impl ExtractAuthor for HeaderAuthor {
+///     fn extract_author(
+///         &mut self,
+///         req: &HttpRequest,
+///         _payload: &mut actix_web::dev::Payload,
+///     ) -> Result<String, MissingAuthorError> {
+///         match req.headers().get("X-Author") {
+///             Some(hdr) => Ok(hdr.to_str().unwrap_or("").to_string()),
+///             None => Err(MissingAuthorError),
+///         }
+///     }
+/// }
+///
+/// #[actix_rt::main]
+/// async fn main() {
+///     let req = test::TestRequest::with_header("X-Author", "myname").to_http_request();
+///     let mut payload = actix_web::dev::Payload::None;
+///     let author = HeaderAuthor::from_request(&req, &mut payload).await;
+///
+///     assert_eq!(author.unwrap().get_name(), "myname".to_string());
+/// }
+/// ```
+pub trait ExtractAuthor {
+    fn extract_author(
+        &mut self,
+        req: &HttpRequest,
+        payload: &mut actix_web::dev::Payload,
+    ) -> Result<String, MissingAuthorError>;
+}
+
 //
 // The Base64Author Extractor
 //
@@ -73,9 +298,124 @@ impl AuthorExtractor for Base64Author {
 // Use macros to write the implmentation of the FromRequest trait
 author_from_request!(Base64Author);
 
+//
+// The Chained Author Extractor
+//
+
+/// Represents an Author Extractor that tries the first extractor and falls back to the second one, so an endpoint
+/// can support clients that identify themselves differently. More extractors are chained by nesting them,
+/// (e.g.: ChainedAuthor<JwtAuthor, ChainedAuthor<ApiKeyAuthor, Base64Author>>).
+///
+/// The extractors of the chain should only read the headers of the request, since the payload can only be consumed once.
+///
+/// #Example
+///
+/// ```rust
+/// extern crate actix_web;
+/// extern crate daas;
+///
+/// use actix_web::{test, FromRequest};
+/// use daas::service::extractor::{AuthorExtractor, Base64Author, ChainedAuthor};
+/// use daas::testing::{MockAuthor, MOCK_AUTHOR};
+///
+/// #[actix_rt::main]
+/// async fn main() {
+///     let req = test::TestRequest::get().to_http_request();
+///     let mut payload = actix_web::dev::Payload::None;
+///     let author = ChainedAuthor::<Base64Author, MockAuthor>::from_request(&req, &mut payload).await;
+///
+///     // the request doesn't have an Authorization header, so the MockAuthor is used
+///     assert_eq!(author.unwrap().get_name(), MOCK_AUTHOR.to_string());
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct ChainedAuthor<A, B> {
+    name: String,
+    identity: Option<AuthorIdentity>,
+    extractors: PhantomData<(A, B)>,
+}
+
+impl<A, B> AuthorExtractor for ChainedAuthor<A, B>
+where
+    A: AuthorExtractor + Clone,
+    B: AuthorExtractor + Clone,
+{
+    fn extract_author(
+        &mut self,
+        req: &HttpRequest,
+        payload: &mut actix_web::dev::Payload,
+    ) -> Result<String, MissingAuthorError> {
+        self.extract_identity(req, payload).map(|i| i.id)
+    }
+
+    fn extract_identity(
+        &mut self,
+        req: &HttpRequest,
+        payload: &mut actix_web::dev::Payload,
+    ) -> Result<AuthorIdentity, MissingAuthorError> {
+        let identity = match A::new().extract_identity(req, payload) {
+            Ok(identity) => identity,
+            Err(err) => {
+                debug!("{} Trying the next Author Extractor.", err);
+                B::new().extract_identity(req, payload)?
+            }
+        };
+        self.identity = Some(identity.clone());
+        Ok(identity)
+    }
+
+    fn get_identity(&self) -> AuthorIdentity {
+        match &self.identity {
+            Some(identity) => identity.clone(),
+            None => AuthorIdentity::new(self.get_name()),
+        }
+    }
+
+    fn get_name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn new() -> Self {
+        Self {
+            name: "Anonymous".to_string(),
+            identity: None,
+            extractors: PhantomData,
+        }
+    }
+
+    // Use macros to write the default functions
+    author_fn_set_name!();
+}
+
+impl<A, B> FromRequest for ChainedAuthor<A, B>
+where
+    A: AuthorExtractor + Clone,
+    B: AuthorExtractor + Clone,
+{
+    type Config = ();
+    type Future = Ready<Result<Self, Self::Error>>;
+    type Error = LocalError;
+
+    fn from_request(req: &HttpRequest, payload: &mut actix_web::dev::Payload) -> Self::Future {
+        let mut author = Self::new();
+
+        match author.extract_identity(req, payload) {
+            Ok(identity) => match author.set_name(identity.id) {
+                Ok(_) => ok(author),
+                Err(e) => err(e),
+            },
+            Err(e) => {
+                error!("{}", e);
+                err(e)
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::testing::{MockAuthor, MOCK_AUTHOR};
     use actix_web::dev::Payload;
     use actix_web::test;
 
@@ -114,6 +454,106 @@ mod tests {
         assert_eq!(expected.unwrap().get_name(), "myname".to_string());
     }
 
+    #[test]
+    fn test_author_identity_doc() {
+        let identity = AuthorIdentity::new("myname".to_string())
+            .with_display_name("My Name".to_string())
+            .with_role("finance".to_string())
+            .with_claim(
+                "iss".to_string(),
+                serde_json::json!("https://auth.example.com"),
+            );
+        let mut doc = crate::testing::get_default_daas_doc();
+
+        assert_eq!(AuthorIdentity::from_doc(&doc), None);
+        identity.add_to(&mut doc);
+        assert_eq!(doc.author, "myname".to_string());
+        assert_eq!(AuthorIdentity::from_doc(&doc), Some(identity));
+    }
+
+    #[actix_rt::test]
+    async fn test_base64auth_identity() {
+        let req = test::TestRequest::with_header("Authorization", "bXluYW1l").to_http_request();
+        let mut payload = Payload::None;
+        let author = Base64Author::from_request(&req, &mut payload)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            author.get_identity(),
+            AuthorIdentity::new("myname".to_string())
+        );
+    }
+
+    #[derive(AuthorExtractor, Debug, Clone)]
+    struct HeaderAuthor {
+        name: String,
+        header: Option<String>,
+    }
+
+    impl ExtractAuthor for HeaderAuthor {
+        fn extract_author(
+            &mut self,
+            req: &HttpRequest,
+            _payload: &mut Payload,
+        ) -> Result<String, MissingAuthorError> {
+            match req.headers().get("X-Author") {
+                Some(hdr) => {
+                    self.header = Some("X-Author".to_string());
+                    Ok(hdr.to_str().unwrap().to_string())
+                }
+                None => Err(MissingAuthorError),
+            }
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_derived_from_request() {
+        let author = HeaderAuthor::new();
+        assert_eq!(author.get_name(), "Anonymous".to_string());
+        assert_eq!(author.header, None);
+
+        let req = test::TestRequest::with_header("X-Author", "myname").to_http_request();
+        let mut payload = Payload::None;
+        let author = HeaderAuthor::from_request(&req, &mut payload)
+            .await
+            .unwrap();
+        assert_eq!(author.get_name(), "myname".to_string());
+        assert_eq!(author.header, Some("X-Author".to_string()));
+
+        let req = test::TestRequest::get().to_http_request();
+        assert!(HeaderAuthor::from_request(&req, &mut payload)
+            .await
+            .is_err());
+    }
+
+    #[actix_rt::test]
+    async fn test_chainedauth_from_request() {
+        let req = test::TestRequest::with_header("Authorization", "bXluYW1l").to_http_request();
+        let mut payload = Payload::None;
+        let author =
+            ChainedAuthor::<Base64Author, MockAuthor>::from_request(&req, &mut payload).await;
+        assert_eq!(author.unwrap().get_name(), "myname".to_string());
+
+        let req = test::TestRequest::with_header("Authorization", "myname").to_http_request();
+        let author =
+            ChainedAuthor::<Base64Author, MockAuthor>::from_request(&req, &mut payload).await;
+        assert_eq!(author.unwrap().get_name(), MOCK_AUTHOR.to_string());
+    }
+
+    #[actix_rt::test]
+    async fn test_chainedauth_from_request_missing() {
+        let req = test::TestRequest::get().to_http_request();
+        let mut payload = Payload::None;
+        let author =
+            ChainedAuthor::<Base64Author, ChainedAuthor<Base64Author, Base64Author>>::from_request(
+                &req,
+                &mut payload,
+            )
+            .await;
+        assert!(author.is_err());
+    }
+
     #[actix_rt::test]
     async fn test_base64auth_from_request_noencode() {
         let req = test::TestRequest::with_header("Authorization", "myname").to_http_request();
@@ -0,0 +1,465 @@
+//! One-time decryption grants, so that a data owner can share the encrypted data of a DaaS document with a consumer.
+//!
+//! The data owner mints a `DecryptionGrant` for a consumer and a DaaS document. The grant holds the symmetric key,
+//! wrapped with the public key of the consumer, (see `DaaSSecurityGuard::wrap_symmetric_key_any()`) and the policy:
+//! the consumer, the DaaS document and the time the grant expires. The grant is signed with the secret of the data owner
+//! and sent to the consumer as a token.
+//!
+//! The consumer registers the tokens with its `DecryptionGrants`, and wraps the handler of its processor with
+//! `DecryptionGrants::handler()`. The DaaS documents are then decrypted before they are handled, as long as there is
+//! a valid grant for the consumer and the DaaS document. Each grant can only be redeemed once.
+//!
+//! # Examples
+//!
+//! ```
+//! extern crate daas;
+//!
+//! use daas::security::DaaSSecurityGuard;
+//! use daas::service::grant::{DecryptionGrant, DecryptionGrants};
+//! use daas::testing;
+//!
+//! fn main() {
+//!     let key = DaaSSecurityGuard::generate_symmetric_key();
+//!     let (private_key, public_key) = DaaSSecurityGuard::generate_x25519_keypair().unwrap();
+//!     let doc = testing::get_default_daas_doc();
+//!
+//!     // the data owner mints a grant for the consumer that is valid for an hour
+//!     let grant = DecryptionGrant::mint(b"owner-secret", &key, "billing".to_string(), &public_key, doc._id.clone(), 3600).unwrap();
+//!
+//!     // the consumer registers the token and redeems the grant
+//!     let grants = DecryptionGrants::new("billing".to_string(), private_key, b"owner-secret".to_vec());
+//!     grants.add_token(&grant.to_token()).unwrap();
+//!
+//!     assert_eq!(grants.redeem(&doc._id).unwrap(), key);
+//!     assert!(grants.redeem(&doc._id).is_err());
+//! }
+//! ```
+
+use super::*;
+use crate::doc::DaaSDoc;
+use crate::errors::daaserror::DaaSProcessingError;
+use crate::security::{DaaSSecurityGuard, StaticKeyProvider};
+use crate::service::processor::{DaaSDocHandler, DaaSDocHandlerRef, DaaSProcessorMessage};
+use kafka::client::KafkaClient;
+use openssl::hash::MessageDigest;
+use openssl::memcmp;
+use openssl::pkey::PKey;
+use openssl::rand::rand_bytes;
+use openssl::sign::Signer;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+/// Represents the permission of a consumer to decrypt the data of a DaaS document once, before the grant expires
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DecryptionGrant {
+    /// The unique identifier of the grant
+    pub grant_id: String,
+    /// The consumer that can decrypt the data, (e.g.: billing)
+    pub consumer: String,
+    /// The unique identifier of the DaaS document
+    pub doc_id: String,
+    /// The Unix Epoch time when the grant expires
+    pub expires_at: u64,
+    /// The base64 encoded symmetric key, wrapped with the public key of the consumer
+    pub wrapped_key: String,
+    /// The hex encoded HMAC-SHA256 signature of the grant by the data owner
+    pub signature: String,
+}
+
+impl DecryptionGrant {
+    /// Mints a signed grant for the consumer to decrypt the data of the DaaS document
+    ///
+    /// # Arguments
+    ///
+    /// * owner_secret: &[u8] - The secret the data owner signs the grants with.</br>
+    /// * key: &[u8] - The symmetric key the data of the DaaS document was encrypted with.</br>
+    /// * consumer: String - The consumer that can decrypt the data, (e.g.: billing).</br>
+    /// * consumer_public_key: &[u8] - The RSA or X25519 public key of the consumer as PEM.</br>
+    /// * doc_id: String - The unique identifier of the DaaS document.</br>
+    /// * ttl: u64 - The number of seconds the grant is valid.</br>
+    pub fn mint(
+        owner_secret: &[u8],
+        key: &[u8],
+        consumer: String,
+        consumer_public_key: &[u8],
+        doc_id: String,
+        ttl: u64,
+    ) -> Result<DecryptionGrant, GrantError> {
+        let wrapped = match DaaSSecurityGuard::wrap_symmetric_key_any(consumer_public_key, key) {
+            Ok(w) => w,
+            Err(_err) => return Err(GrantError),
+        };
+        let mut id = [0; 16];
+        if rand_bytes(&mut id).is_err() {
+            return Err(GrantError);
+        }
+
+        let mut grant = DecryptionGrant {
+            grant_id: id.iter().map(|b| format!("{:02x}", b)).collect(),
+            consumer,
+            doc_id,
+            expires_at: get_unix_now!() + ttl,
+            wrapped_key: base64::encode(&wrapped),
+            signature: String::new(),
+        };
+        grant.signature = grant.sign(owner_secret);
+
+        Ok(grant)
+    }
+
+    /// Reads the grant from the token that was created using `to_token()`
+    ///
+    /// # Arguments
+    ///
+    /// * token: &str - The token of the grant.</br>
+    pub fn from_token(token: &str) -> Result<DecryptionGrant, GrantError> {
+        match base64::decode(token).map(|t| serde_json::from_slice(&t)) {
+            Ok(Ok(grant)) => Ok(grant),
+            _ => {
+                warn!("The decryption grant token could not be read.");
+                Err(GrantError)
+            }
+        }
+    }
+
+    /// Returns the grant as a token that can be sent to the consumer
+    pub fn to_token(&self) -> String {
+        base64::encode(&serde_json::to_vec(self).unwrap())
+    }
+
+    /// Determines if the grant was signed by the data owner and hasn't been altered
+    ///
+    /// # Arguments
+    ///
+    /// * owner_secret: &[u8] - The secret the data owner signs the grants with.</br>
+    pub fn verify(&self, owner_secret: &[u8]) -> bool {
+        let expected = self.sign(owner_secret);
+
+        expected.len() == self.signature.len()
+            && memcmp::eq(expected.as_bytes(), self.signature.as_bytes())
+    }
+
+    // Calculates the hex encoded HMAC-SHA256 signature of the policy and the wrapped key
+    fn sign(&self, owner_secret: &[u8]) -> String {
+        let key = PKey::hmac(owner_secret).unwrap();
+        let mut signer = Signer::new(MessageDigest::sha256(), &key).unwrap();
+        signer
+            .update(
+                format!(
+                    "{}\n{}\n{}\n{}\n{}",
+                    self.grant_id, self.consumer, self.doc_id, self.expires_at, self.wrapped_key
+                )
+                .as_bytes(),
+            )
+            .unwrap();
+
+        signer
+            .sign_to_vec()
+            .unwrap()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+}
+
+/// Holds the decryption grants of a consumer and redeems them, (each grant can only be redeemed once).
+/// Clones of the `DecryptionGrants` share the same grants.
+#[derive(Clone)]
+pub struct DecryptionGrants {
+    /// The consumer the grants were minted for, (e.g.: billing)
+    pub consumer: String,
+    private_key: Vec<u8>,
+    owner_secret: Vec<u8>,
+    grants: Arc<Mutex<HashMap<String, DecryptionGrant>>>,
+    redeemed: Arc<Mutex<HashSet<String>>>,
+}
+
+impl DecryptionGrants {
+    /// Constructor
+    ///
+    /// # Arguments
+    ///
+    /// * consumer: String - The consumer the grants were minted for, (e.g.: billing).</br>
+    /// * private_key: Vec<u8> - The RSA or X25519 private key of the consumer as PEM, (see `DaaSSecurityGuard::decrypt_private_key()`).</br>
+    /// * owner_secret: Vec<u8> - The secret the data owner signs the grants with.</br>
+    pub fn new(consumer: String, private_key: Vec<u8>, owner_secret: Vec<u8>) -> DecryptionGrants {
+        DecryptionGrants {
+            consumer,
+            private_key,
+            owner_secret,
+            grants: Arc::new(Mutex::new(HashMap::new())),
+            redeemed: Arc::new(Mutex::new(HashSet::new())),
+        }
+    }
+
+    /// Verifies and adds the grant of the token, (replacing a grant of the same DaaS document that hasn't been redeemed)
+    ///
+    /// # Arguments
+    ///
+    /// * token: &str - The token of the grant, (see `DecryptionGrant::to_token()`).</br>
+    pub fn add_token(&self, token: &str) -> Result<(), GrantError> {
+        let grant = DecryptionGrant::from_token(token)?;
+
+        if !grant.verify(&self.owner_secret) {
+            warn!(
+                "Rejected decryption grant {}. The signature is invalid.",
+                grant.grant_id
+            );
+            return Err(GrantError);
+        }
+        if grant.consumer != self.consumer {
+            warn!(
+                "Rejected decryption grant {}. It was minted for consumer {}.",
+                grant.grant_id, grant.consumer
+            );
+            return Err(GrantError);
+        }
+        if self.redeemed.lock().unwrap().contains(&grant.grant_id) {
+            warn!(
+                "Rejected decryption grant {}. It has already been redeemed.",
+                grant.grant_id
+            );
+            return Err(GrantError);
+        }
+
+        self.grants
+            .lock()
+            .unwrap()
+            .insert(grant.doc_id.clone(), grant);
+        Ok(())
+    }
+
+    /// Redeems the grant of the DaaS document, returning the unwrapped symmetric key
+    ///
+    /// # Arguments
+    ///
+    /// * doc_id: &str - The unique identifier of the DaaS document.</br>
+    pub fn redeem(&self, doc_id: &str) -> Result<Vec<u8>, GrantError> {
+        self.redeem_at(doc_id, get_unix_now!())
+    }
+
+    /// Redeems the grant of the DaaS document at the time, returning the unwrapped symmetric key
+    ///
+    /// # Arguments
+    ///
+    /// * doc_id: &str - The unique identifier of the DaaS document.</br>
+    /// * now: u64 - The current Unix Epoch time.</br>
+    pub fn redeem_at(&self, doc_id: &str, now: u64) -> Result<Vec<u8>, GrantError> {
+        // the grant is removed whether or not it is valid, so it can never be redeemed twice
+        let grant = match self.grants.lock().unwrap().remove(doc_id) {
+            Some(g) => g,
+            None => {
+                warn!("There is no decryption grant for DaaS document {}.", doc_id);
+                return Err(GrantError);
+            }
+        };
+        self.redeemed.lock().unwrap().insert(grant.grant_id.clone());
+
+        if now > grant.expires_at {
+            warn!("Decryption grant {} has expired.", grant.grant_id);
+            return Err(GrantError);
+        }
+
+        match base64::decode(&grant.wrapped_key)
+            .map(|w| DaaSSecurityGuard::unwrap_symmetric_key_any(&self.private_key, &w))
+        {
+            Ok(Ok(key)) => Ok(key),
+            _ => {
+                warn!(
+                    "The key of decryption grant {} could not be unwrapped.",
+                    grant.grant_id
+                );
+                Err(GrantError)
+            }
+        }
+    }
+
+    /// Wraps the handler so that the data of the DaaS documents is decrypted using the grants before it is handled.
+    /// The DaaS documents without a valid grant fail and are not handled.
+    ///
+    /// # Arguments
+    ///
+    /// * handler: DaaSDocHandlerRef - The handler that processes the decrypted DaaS documents.</br>
+    pub fn handler(&self, handler: DaaSDocHandlerRef) -> DaaSDocHandlerRef {
+        Arc::new(GrantedHandler {
+            grants: self.clone(),
+            handler,
+        })
+    }
+
+    // Decrypts the data of the DaaS document with the key of its grant
+    fn decrypt(&self, doc: &mut DaaSDoc) -> Result<(), GrantError> {
+        let key = self.redeem(&doc._id)?;
+        let guard = DaaSSecurityGuard::new(Box::new(StaticKeyProvider::new(key)));
+
+        match guard.decrypt_data(&doc.data_obj) {
+            Ok(data) => {
+                doc.data_obj = data;
+                Ok(())
+            }
+            Err(_err) => Err(GrantError),
+        }
+    }
+}
+
+// Decrypts the DaaS documents with the grants before they are handled
+struct GrantedHandler {
+    grants: DecryptionGrants,
+    handler: DaaSDocHandlerRef,
+}
+
+impl DaaSDocHandler for GrantedHandler {
+    fn on_start(&self) {
+        self.handler.on_start();
+    }
+
+    fn handle(
+        &self,
+        mut msg: DaaSProcessorMessage,
+        client: Option<KafkaClient>,
+    ) -> Result<i32, DaaSProcessingError> {
+        if let Err(err) = self.grants.decrypt(&mut msg.doc) {
+            warn!("Could not decrypt DaaS document {}. {}", msg.doc._id, err);
+            return Err(DaaSProcessingError::RetrieveError);
+        }
+
+        self.handler.handle(msg, client)
+    }
+
+    fn on_error(&self, doc: &DaaSDoc, err: &DaaSProcessingError) {
+        self.handler.on_error(doc, err);
+    }
+
+    fn on_shutdown(&self) {
+        self.handler.on_shutdown();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eventing::headers::DaaSMessageHeaders;
+    use crate::testing;
+
+    // Records the data of the handled DaaS documents
+    struct Recorder {
+        data: Mutex<Vec<Vec<u8>>>,
+    }
+
+    impl DaaSDocHandler for Recorder {
+        fn handle(
+            &self,
+            msg: DaaSProcessorMessage,
+            _client: Option<KafkaClient>,
+        ) -> Result<i32, DaaSProcessingError> {
+            self.data.lock().unwrap().push(msg.doc.data_obj);
+            Ok(1)
+        }
+    }
+
+    fn get_message(doc: DaaSDoc) -> DaaSProcessorMessage<'static> {
+        DaaSProcessorMessage {
+            offset: 0,
+            key: &[],
+            headers: DaaSMessageHeaders::from_doc(&doc),
+            doc,
+            topic: "genesis",
+            partition: 0,
+        }
+    }
+
+    #[test]
+    fn test_grant_token() {
+        let (_private_key, public_key) = DaaSSecurityGuard::generate_x25519_keypair().unwrap();
+        let key = DaaSSecurityGuard::generate_symmetric_key();
+        let grant = DecryptionGrant::mint(
+            b"secret",
+            &key,
+            "billing".to_string(),
+            &public_key,
+            "doc-1".to_string(),
+            60,
+        )
+        .unwrap();
+        let read = DecryptionGrant::from_token(&grant.to_token()).unwrap();
+
+        assert_eq!(read, grant);
+        assert!(read.verify(b"secret"));
+        assert!(!read.verify(b"other"));
+        assert!(DecryptionGrant::from_token("not a token").is_err());
+    }
+
+    #[test]
+    fn test_grant_rejected() {
+        let (private_key, public_key) = DaaSSecurityGuard::generate_rsa_keypair(2048).unwrap();
+        let key = DaaSSecurityGuard::generate_symmetric_key();
+        let mint = |consumer: &str, doc_id: &str| {
+            DecryptionGrant::mint(
+                b"secret",
+                &key,
+                consumer.to_string(),
+                &public_key,
+                doc_id.to_string(),
+                60,
+            )
+            .unwrap()
+        };
+        let grants = DecryptionGrants::new("billing".to_string(), private_key, b"secret".to_vec());
+
+        // minted for another consumer
+        assert!(grants
+            .add_token(&mint("shipping", "doc-1").to_token())
+            .is_err());
+
+        // altered policy
+        let mut altered = mint("billing", "doc-1");
+        altered.doc_id = "doc-2".to_string();
+        assert!(grants.add_token(&altered.to_token()).is_err());
+
+        // expired
+        let grant = mint("billing", "doc-1");
+        grants.add_token(&grant.to_token()).unwrap();
+        assert!(grants.redeem_at("doc-1", grant.expires_at + 1).is_err());
+
+        // redeemed once
+        let grant = mint("billing", "doc-1");
+        grants.add_token(&grant.to_token()).unwrap();
+        assert_eq!(grants.redeem("doc-1").unwrap(), key);
+        assert!(grants.redeem("doc-1").is_err());
+        assert!(grants.add_token(&grant.to_token()).is_err());
+    }
+
+    #[test]
+    fn test_granted_handler() {
+        let key = DaaSSecurityGuard::generate_symmetric_key();
+        let guard = DaaSSecurityGuard::new(Box::new(StaticKeyProvider::new(key.clone())));
+        let (private_key, public_key) = DaaSSecurityGuard::generate_x25519_keypair().unwrap();
+        let mut doc = testing::get_default_daas_doc();
+        let data = doc.data_obj.clone();
+        doc.data_obj = guard.encrypt_data(&data).unwrap();
+
+        let grants = DecryptionGrants::new("billing".to_string(), private_key, b"secret".to_vec());
+        let recorder = Arc::new(Recorder {
+            data: Mutex::new(Vec::new()),
+        });
+        let handler = grants.handler(recorder.clone());
+
+        // without a grant the DaaS document isn't handled
+        assert!(handler.handle(get_message(doc.clone()), None).is_err());
+
+        let grant = DecryptionGrant::mint(
+            b"secret",
+            &key,
+            "billing".to_string(),
+            &public_key,
+            doc._id.clone(),
+            60,
+        )
+        .unwrap();
+        grants.add_token(&grant.to_token()).unwrap();
+
+        assert!(handler.handle(get_message(doc.clone()), None).is_ok());
+        assert!(handler.handle(get_message(doc), None).is_err());
+        assert_eq!(*recorder.data.lock().unwrap(), vec![data]);
+    }
+}
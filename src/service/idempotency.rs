@@ -0,0 +1,373 @@
+//! Idempotency keys for the listener, so the retries of a client don't create duplicate revisions and events.
+//!
+//! A client that sends an `Idempotency-Key` header with its POST can safely retry the request, (e.g.: after a
+//! timeout). The first request with the key is processed and its response is remembered. The retries with the same
+//! key receive the remembered response, (with the `Idempotent-Replayed` header) instead of creating a new revision.
+//!
+//! The keys are scoped to the author, and a key can only be reused for the same request, (the same path and body).
+//! A retry that arrives while the first request is still being processed is rejected with 409 Conflict, and a key
+//! that is reused for a different request is rejected with 422 Unprocessable Entity. Only the successful responses
+//! are remembered, so a request that failed can be retried with the same key. The listener holds the key as an
+//! `IdempotencyReservation`, which releases the key when the request is rejected or fails, (see `reserve()`).
+//!
+//! The keys are enabled by registering an `IdempotencyStore` as application data,
+//! (e.g.: App::new().data(IdempotencyStore::new()))
+//!
+//! # Examples
+//!
+//! ```
+//! extern crate daas;
+//!
+//! use daas::service::idempotency::{IdempotencyStore, Idempotency, StoredResponse};
+//!
+//! fn main() {
+//!     let store = IdempotencyStore::new().with_ttl(3600);
+//!     let fingerprint = IdempotencyStore::fingerprint("/order/clothing/iStore/5000", b"{}");
+//!
+//!     assert_eq!(store.begin("myself", "abc", &fingerprint), Idempotency::Proceed);
+//!     store.complete("myself", "abc", StoredResponse::new(200, r#"{"status":"ok"}"#.to_string()));
+//!
+//!     match store.begin("myself", "abc", &fingerprint) {
+//!         Idempotency::Replay(rspns) => assert_eq!(rspns.status, 200),
+//!         _ => panic!("the response should be replayed"),
+//!     }
+//! }
+//! ```
+
+use super::*;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// The name of the header of the idempotency key
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+/// The name of the header that marks a replayed response
+pub const IDEMPOTENT_REPLAYED_HEADER: &str = "Idempotent-Replayed";
+
+/// Represents a remembered response
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredResponse {
+    /// The HTTP status code
+    pub status: u16,
+    /// The JSON body
+    pub body: String,
+}
+
+impl StoredResponse {
+    /// Constructs a StoredResponse
+    ///
+    /// # Arguments
+    ///
+    /// * status: u16 - The HTTP status code.</br>
+    /// * body: String - The JSON body.</br>
+    pub fn new(status: u16, body: String) -> StoredResponse {
+        StoredResponse { status, body }
+    }
+}
+
+/// Represents how a request with an idempotency key is handled
+#[derive(Debug, Clone, PartialEq)]
+pub enum Idempotency {
+    /// The key is new, so the request is processed
+    Proceed,
+    /// The request was already processed, so its response is returned
+    Replay(StoredResponse),
+    /// The first request with the key is still being processed
+    InFlight,
+    /// The key was used for a different request
+    Mismatch,
+}
+
+// The state of a key, (the fingerprint of its request, its response once completed, and when it was first seen)
+struct Entry {
+    fingerprint: String,
+    response: Option<StoredResponse>,
+    seen: u64,
+}
+
+/// Represents the idempotency keys that have been received
+pub struct IdempotencyStore {
+    /// How long (in seconds) the responses are remembered, (default: 86400)
+    pub ttl: u64,
+    entries: Mutex<HashMap<(String, String), Entry>>,
+}
+
+impl IdempotencyStore {
+    /// Constructs an IdempotencyStore
+    pub fn new() -> IdempotencyStore {
+        IdempotencyStore {
+            ttl: 86400,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Sets how long (in seconds) the responses are remembered
+    pub fn with_ttl(mut self, ttl: u64) -> IdempotencyStore {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Returns the fingerprint (SHA-256 hex) of a request
+    ///
+    /// # Arguments
+    ///
+    /// * path: &str - The path of the request.</br>
+    /// * body: &[u8] - The body of the request.</br>
+    pub fn fingerprint(path: &str, body: &[u8]) -> String {
+        let mut content = path.as_bytes().to_vec();
+        content.push(b'\n');
+        content.extend_from_slice(body);
+
+        Sha256::digest(&content)
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+
+    /// Starts a request with the idempotency key that is received now
+    ///
+    /// # Arguments
+    ///
+    /// * author: &str - The author of the request, (the keys are scoped to the author).</br>
+    /// * key: &str - The idempotency key.</br>
+    /// * fingerprint: &str - The fingerprint of the request, (see `fingerprint()`).</br>
+    pub fn begin(&self, author: &str, key: &str, fingerprint: &str) -> Idempotency {
+        self.begin_at(author, key, fingerprint, get_unix_now!())
+    }
+
+    /// Starts a request with the idempotency key at the time it is received
+    ///
+    /// # Arguments
+    ///
+    /// * author: &str - The author of the request, (the keys are scoped to the author).</br>
+    /// * key: &str - The idempotency key.</br>
+    /// * fingerprint: &str - The fingerprint of the request, (see `fingerprint()`).</br>
+    /// * now: u64 - The time (Unix seconds) the request is received.</br>
+    pub fn begin_at(&self, author: &str, key: &str, fingerprint: &str, now: u64) -> Idempotency {
+        let mut entries = self.entries.lock().unwrap();
+        let ttl = self.ttl;
+        entries.retain(|_k, e| now.saturating_sub(e.seen) <= ttl);
+
+        match entries.get(&(author.to_string(), key.to_string())) {
+            Some(e) if e.fingerprint != fingerprint => {
+                warn!(
+                    "The idempotency key {} of {} was reused for a different request.",
+                    key, author
+                );
+                Idempotency::Mismatch
+            }
+            Some(e) => match &e.response {
+                Some(r) => {
+                    info!(
+                        "Replaying the response of the idempotency key {} of {}.",
+                        key, author
+                    );
+                    Idempotency::Replay(r.clone())
+                }
+                None => Idempotency::InFlight,
+            },
+            None => {
+                entries.insert(
+                    (author.to_string(), key.to_string()),
+                    Entry {
+                        fingerprint: fingerprint.to_string(),
+                        response: None,
+                        seen: now,
+                    },
+                );
+                Idempotency::Proceed
+            }
+        }
+    }
+
+    /// Remembers the response of the request with the idempotency key
+    ///
+    /// # Arguments
+    ///
+    /// * author: &str - The author of the request.</br>
+    /// * key: &str - The idempotency key.</br>
+    /// * response: StoredResponse - The response to return to the retries.</br>
+    pub fn complete(&self, author: &str, key: &str, response: StoredResponse) {
+        if let Some(e) = self
+            .entries
+            .lock()
+            .unwrap()
+            .get_mut(&(author.to_string(), key.to_string()))
+        {
+            e.response = Some(response);
+        }
+    }
+
+    /// Returns the reservation of the idempotency key that `begin()` returned `Idempotency::Proceed` for.
+    /// The key is released when the reservation is dropped before it is completed, (e.g.: the request is rejected).
+    ///
+    /// # Arguments
+    ///
+    /// * author: &str - The author of the request.</br>
+    /// * key: &str - The idempotency key.</br>
+    pub fn reserve(&self, author: &str, key: &str) -> IdempotencyReservation<'_> {
+        IdempotencyReservation {
+            store: self,
+            author: author.to_string(),
+            key: key.to_string(),
+            completed: false,
+        }
+    }
+
+    /// Forgets the idempotency key, so the request can be retried, (e.g.: because it failed)
+    ///
+    /// # Arguments
+    ///
+    /// * author: &str - The author of the request.</br>
+    /// * key: &str - The idempotency key.</br>
+    pub fn release(&self, author: &str, key: &str) {
+        self.entries
+            .lock()
+            .unwrap()
+            .remove(&(author.to_string(), key.to_string()));
+    }
+}
+
+/// Represents an idempotency key that is held while its request is processed
+pub struct IdempotencyReservation<'a> {
+    store: &'a IdempotencyStore,
+    author: String,
+    key: String,
+    completed: bool,
+}
+
+impl<'a> IdempotencyReservation<'a> {
+    /// Remembers the response of the request, so the key isn't released
+    ///
+    /// # Arguments
+    ///
+    /// * response: StoredResponse - The response to return to the retries.</br>
+    pub fn complete(mut self, response: StoredResponse) {
+        self.store.complete(&self.author, &self.key, response);
+        self.completed = true;
+    }
+}
+
+impl<'a> Drop for IdempotencyReservation<'a> {
+    fn drop(&mut self) {
+        if !self.completed {
+            self.store.release(&self.author, &self.key);
+        }
+    }
+}
+
+impl Default for IdempotencyStore {
+    fn default() -> Self {
+        IdempotencyStore::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AUTHOR: &str = "myself";
+
+    fn get_response() -> StoredResponse {
+        StoredResponse::new(200, r#"{"status":"ok"}"#.to_string())
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let fp = IdempotencyStore::fingerprint("/order/clothing/iStore/5000", b"{}");
+
+        assert_eq!(fp.len(), 64);
+        assert_ne!(
+            fp,
+            IdempotencyStore::fingerprint("/order/clothing/iStore/5001", b"{}")
+        );
+    }
+
+    #[test]
+    fn test_replay() {
+        let store = IdempotencyStore::new();
+
+        assert_eq!(
+            store.begin_at(AUTHOR, "k1", "fp", 100),
+            Idempotency::Proceed
+        );
+        assert_eq!(
+            store.begin_at(AUTHOR, "k1", "fp", 101),
+            Idempotency::InFlight
+        );
+        store.complete(AUTHOR, "k1", get_response());
+        assert_eq!(
+            store.begin_at(AUTHOR, "k1", "fp", 102),
+            Idempotency::Replay(get_response())
+        );
+    }
+
+    #[test]
+    fn test_mismatch() {
+        let store = IdempotencyStore::new();
+
+        assert_eq!(
+            store.begin_at(AUTHOR, "k1", "fp", 100),
+            Idempotency::Proceed
+        );
+        assert_eq!(
+            store.begin_at(AUTHOR, "k1", "other", 101),
+            Idempotency::Mismatch
+        );
+    }
+
+    #[test]
+    fn test_scoped_to_author() {
+        let store = IdempotencyStore::new();
+
+        assert_eq!(
+            store.begin_at(AUTHOR, "k1", "fp", 100),
+            Idempotency::Proceed
+        );
+        assert_eq!(
+            store.begin_at("someone", "k1", "fp", 100),
+            Idempotency::Proceed
+        );
+    }
+
+    #[test]
+    fn test_reservation() {
+        let store = IdempotencyStore::new();
+
+        assert_eq!(
+            store.begin_at(AUTHOR, "k1", "fp", 100),
+            Idempotency::Proceed
+        );
+        // the key is released when the request is rejected
+        drop(store.reserve(AUTHOR, "k1"));
+        assert_eq!(
+            store.begin_at(AUTHOR, "k1", "fp", 101),
+            Idempotency::Proceed
+        );
+        store.reserve(AUTHOR, "k1").complete(get_response());
+        assert_eq!(
+            store.begin_at(AUTHOR, "k1", "fp", 102),
+            Idempotency::Replay(get_response())
+        );
+    }
+
+    #[test]
+    fn test_release_and_expire() {
+        let store = IdempotencyStore::new().with_ttl(60);
+
+        assert_eq!(
+            store.begin_at(AUTHOR, "k1", "fp", 100),
+            Idempotency::Proceed
+        );
+        store.release(AUTHOR, "k1");
+        assert_eq!(
+            store.begin_at(AUTHOR, "k1", "fp", 101),
+            Idempotency::Proceed
+        );
+        store.complete(AUTHOR, "k1", get_response());
+        assert_eq!(
+            store.begin_at(AUTHOR, "k1", "fp", 200),
+            Idempotency::Proceed
+        );
+    }
+}
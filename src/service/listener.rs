@@ -1,9 +1,28 @@
+use super::access::{check_agreements, AccessDecision, AccessPolicy, PURPOSE_HEADER};
 use super::extractor::AuthorExtractor;
+use super::idempotency::{
+    Idempotency, IdempotencyStore, StoredResponse, IDEMPOTENCY_KEY_HEADER,
+    IDEMPOTENT_REPLAYED_HEADER,
+};
+use super::registry::CategoryRegistry;
+use super::replay::ReplayGuard;
+#[cfg(feature = "security")]
+use super::signature::SignatureVerifier;
+use super::transform::{RawPayload, Transformations, TRANSFORMATIONS_META};
 use super::*;
+use crate::audit::{AuditAction, AuditEvent, AuditLog, AuditOutcome, AuditedBroker};
+use crate::config::DaasConfig;
 use crate::doc::*;
 use crate::eventing::broker::{DaaSKafkaBroker, DaaSKafkaProcessor};
+use crate::eventing::latency::{self, BROKERED_AT_META, INGESTED_AT_META};
+use crate::eventing::pool::BrokerPool;
+use crate::eventing::sequence::Sequencer;
+use crate::eventing::sla::SlaWatchdog;
+use crate::storage::duplicates::{DuplicateKind, DuplicateReport};
 use crate::storage::local::LocalStorage;
 use crate::storage::DaaSDocStorage;
+use actix_web::web::{Data, Query};
+use serde_json::{json, Value};
 use std::thread;
 
 pub trait DaaSListenerService {
@@ -13,6 +32,12 @@ pub trait DaaSListenerService {
     fn get_service_path() -> String {
         "/{category}/{subcategory}/{source_name}/{source_uid}".to_string()
     }
+    fn get_service_status_path() -> String {
+        "/status/{doc_id}".to_string()
+    }
+    fn get_service_duplicates_path() -> String {
+        "/duplicates".to_string()
+    }
     fn health(_req: HttpRequest) -> HttpResponse {
         return HttpResponse::Ok()
             .header(http::header::CONTENT_TYPE, "application/json")
@@ -34,15 +59,167 @@ pub struct Info {
     category: String,
     subcategory: String,
     source_name: String,
-    source_uid: usize,
+    source_uid: String,
+}
+
+/// The query string parameters of the retrieve service
+#[derive(Deserialize)]
+pub struct RetrieveParams {
+    /// The comma separated JSON Pointers of the fields to return, (e.g.: `/status,/customer/name`)
+    pub fields: Option<String>,
+}
+
+impl RetrieveParams {
+    // Returns the JSON Pointers of the fields, or None if the entire data object is requested
+    fn pointers(&self) -> Result<Option<Vec<JsonPointer>>, DaaSDocError> {
+        match &self.fields {
+            Some(fields) => fields
+                .split(',')
+                .map(|f| f.trim().parse::<JsonPointer>())
+                .collect::<Result<Vec<JsonPointer>, DaaSDocError>>()
+                .map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+/// The metadata key of the number of Markers that the Data Tracker Chain had when the listener received the data
+pub const INGESTED_MARKERS_META: &str = "ingested-markers";
+
+/// The modes of responding to the data that is sent to the index service
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum IngestMode {
+    /// 200 once the data has been saved, (default)
+    #[default]
+    Sync,
+    /// 202 once the data has been saved, with the URL of the status service in the `Location` header, for clients
+    /// that poll the status instead of waiting for the data to be brokered
+    Async,
+}
+
+/// The bodies of the responses of the index and patch services
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum ResponseBody {
+    /// Only the status, (default) e.g.: {"status":"ok"}
+    #[default]
+    Status,
+    /// The status, _id, _rev, a summary of the Data Tracker Chain and the location of the saved DaaS document, so
+    /// the clients can correlate the downstream events
+    Summary,
+    /// The status and the envelope of the saved DaaS document, (everything but the data)
+    Envelope,
+}
+
+impl ResponseBody {
+    /// Returns the body of the response for the saved DaaS document
+    ///
+    /// # Arguments
+    ///
+    /// * doc: &DaaSDoc - The saved DaaS document.</br>
+    /// * status: &str - The status of the request, (e.g.: ok).</br>
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// extern crate daas;
+    ///
+    /// use daas::service::listener::ResponseBody;
+    /// use daas::testing;
+    ///
+    /// fn main() {
+    ///     let doc = testing::get_default_daas_doc();
+    ///     let body = ResponseBody::Summary.render(&doc, "ok");
+    ///
+    ///     assert_eq!(body["_id"], "order~clothing~iStore~5000");
+    ///     assert_eq!(body["tracker"]["markers"], 1);
+    /// }
+    /// ```
+    pub fn render(&self, doc: &DaaSDoc, status: &str) -> Value {
+        match self {
+            ResponseBody::Status => json!({ "status": status }),
+            ResponseBody::Summary => {
+                let latest = doc
+                    .data_tracker
+                    .len()
+                    .checked_sub(1)
+                    .and_then(|i| doc.data_tracker.get(i));
+                let location = doc._rev.clone().map(|rev| {
+                    LocalStorage::new(LocalStorage::get_local_path())
+                        .get_doc_location(doc._id.clone(), rev)
+                });
+
+                json!({
+                    "status": status,
+                    "_id": doc._id,
+                    "_rev": doc._rev,
+                    "tracker": {
+                        "markers": doc.data_tracker.len(),
+                        "latest_actor": latest.as_ref().map(|m| m.identifier.actor_id.clone()),
+                        "latest_hash": latest.as_ref().map(|m| m.hash.clone()),
+                        "is_valid": doc.data_tracker.is_valid(),
+                    },
+                    "location": location
+                })
+            }
+            ResponseBody::Envelope => {
+                let mut envelope = serde_json::to_value(doc).unwrap();
+                let obj = envelope.as_object_mut().unwrap();
+                obj.remove("data_obj");
+                obj.insert("status".to_string(), Value::from(status));
+                envelope
+            }
+        }
+    }
+}
+
+/// The states of the data that was sent to the index service, (see the status service)
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum IngestState {
+    /// The data has been saved and is waiting to be brokered
+    Stored,
+    /// The data has been sent to the broker, (process_ind = true)
+    Brokered,
+    /// A processor has added a Marker to the Data Tracker Chain after the data was brokered
+    Processed,
+}
+
+impl IngestState {
+    /// Returns the state of the DaaS document
+    ///
+    /// # Arguments
+    ///
+    /// * doc: &DaaSDoc - The latest revision of the DaaS document.</br>
+    pub fn of(doc: &DaaSDoc) -> IngestState {
+        let ingested = doc
+            .meta_data
+            .get(INGESTED_MARKERS_META)
+            .and_then(|m| m.parse::<usize>().ok());
+
+        match (doc.process_ind, ingested) {
+            (false, _) => IngestState::Stored,
+            (true, Some(n)) if doc.data_tracker.len() > n => IngestState::Processed,
+            (true, _) => IngestState::Brokered,
+        }
+    }
 }
 
 pub struct DaaSListener {}
 
 impl DaaSListener {
-    fn broker_document(mut doc: DaaSDoc, topic: String) -> Result<DaaSDoc, BrokerError> {
+    fn broker_document<B: DaaSKafkaProcessor>(
+        my_broker: &B,
+        mut doc: DaaSDoc,
+        topic: String,
+    ) -> Result<DaaSDoc, BrokerError> {
         let daas_id = doc._id.clone();
-        let my_broker = DaaSKafkaBroker::default();
+
+        // the consumers receive the DaaS document as it is after it is brokered
+        if doc.set_status(DocStatus::Brokered).is_err() {
+            return Err(BrokerError);
+        }
+
+        latency::stamp(&mut doc, BROKERED_AT_META);
 
         debug!(
             "Sending document [{}] to broker using topic [{}]. Waiting for response...",
@@ -79,9 +256,444 @@ impl DaaSListener {
         }
     }
 
+    // Returns the identifier of the DaaS document of the path, or the Bad Request response if it is illegal
+    fn get_doc_id(params: &Info) -> Result<String, HttpResponse> {
+        if [
+            &params.category,
+            &params.subcategory,
+            &params.source_name,
+            &params.source_uid,
+        ]
+        .iter()
+        .any(|c| !DaaSDoc::is_valid_id_component(c))
+        {
+            return Err(HttpResponse::BadRequest()
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .body(
+                    r#"{"error":"illegal characters in the category, subcategory, source name or source uid"}"#,
+                ));
+        }
+
+        let srcuid: SourceId = match params.source_uid.parse() {
+            Ok(u) => u,
+            Err(_e) => SourceId::Text(params.source_uid.clone()),
+        };
+        Ok(DaaSDoc::make_id(
+            params.category.clone(),
+            params.subcategory.clone(),
+            params.source_name.clone(),
+            srcuid,
+        ))
+    }
+
+    /// Returns the entity tag of the DaaS document, which is its quoted revision, (e.g.: "3")
+    ///
+    /// # Arguments
+    ///
+    /// * doc: &DaaSDoc - The DaaS document.</br>
+    pub fn make_etag(doc: &DaaSDoc) -> String {
+        format!("\"{}\"", doc._rev.clone().unwrap_or_default())
+    }
+
+    // Returns true if the entity tag is in the list of the header, (the weak entity tags only match when weak is true)
+    fn etag_listed(list: &str, etag: &str, weak: bool) -> bool {
+        list.split(',').map(|t| t.trim()).any(|t| {
+            t == "*"
+                || t == etag
+                || (weak && t.strip_prefix("W/").map(|w| w == etag).unwrap_or(false))
+        })
+    }
+
+    // Evaluates the If-Match and If-None-Match headers of the request against the entity tag of the DaaS document,
+    // returning the response when a precondition fails, (see RFC 9110 section 13.2.2)
+    fn check_preconditions(req: &HttpRequest, etag: &str) -> Option<HttpResponse> {
+        let header = |name: http::header::HeaderName| {
+            req.headers()
+                .get(name)
+                .and_then(|h| h.to_str().ok())
+                .map(|h| h.to_string())
+        };
+
+        if let Some(list) = header(http::header::IF_MATCH) {
+            if !DaaSListener::etag_listed(&list, etag, false) {
+                return Some(
+                    HttpResponse::PreconditionFailed()
+                        .header(http::header::CONTENT_TYPE, "application/json")
+                        .header(http::header::ETAG, etag)
+                        .body(r#"{"error":"the data has been modified"}"#),
+                );
+            }
+        }
+
+        if let Some(list) = header(http::header::IF_NONE_MATCH) {
+            if DaaSListener::etag_listed(&list, etag, true) {
+                return Some(match req.method() == http::Method::GET {
+                    true => HttpResponse::NotModified()
+                        .header(http::header::ETAG, etag)
+                        .finish(),
+                    false => HttpResponse::PreconditionFailed()
+                        .header(http::header::CONTENT_TYPE, "application/json")
+                        .header(http::header::ETAG, etag)
+                        .body(r#"{"error":"the data has not been modified"}"#),
+                });
+            }
+        }
+
+        None
+    }
+
+    /// The RESTful service that returns the data of the DaaS document, (GET on the service path).
+    /// The requester declares the purpose in the `X-DaaS-Purpose` header, which must match a data usage agreement
+    /// of the DaaS document and, when an `AccessPolicy` is registered as application data, a role of the requester.
+    /// The `fields` query string parameter limits the (JSON) data that is returned to the fields of the comma
+    /// separated JSON Pointers, (e.g.: `?fields=/status,/customer/name`).
+    ///
+    /// # Arguments
+    ///
+    /// * params: Path<Info> - The category, subcategory, source name and source uid of the DaaS document.</br>
+    /// * author: A - The requester.</br>
+    /// * req: HttpRequest - The request.</br>
+    pub fn retrieve<A: AuthorExtractor>(
+        params: Path<Info>,
+        author: A,
+        req: HttpRequest,
+    ) -> HttpResponse {
+        let doc_id = match DaaSListener::get_doc_id(&params) {
+            Ok(id) => id,
+            Err(rspns) => return rspns,
+        };
+
+        let purpose = match req
+            .headers()
+            .get(PURPOSE_HEADER)
+            .and_then(|h| h.to_str().ok())
+        {
+            Some(p) if !p.trim().is_empty() => p.trim().to_string(),
+            _ => {
+                return HttpResponse::BadRequest()
+                    .header(http::header::CONTENT_TYPE, "application/json")
+                    .body(r#"{"error":"missing purpose"}"#)
+            }
+        };
+
+        let fields =
+            match Query::<RetrieveParams>::from_query(req.query_string())
+                .map_err(|_e| DaaSDocError)
+                .and_then(|q| q.pointers())
+            {
+                Ok(f) => f,
+                Err(_e) => return HttpResponse::BadRequest()
+                    .header(http::header::CONTENT_TYPE, "application/json")
+                    .body(
+                        r#"{"error":"the fields must be JSON Pointers, (e.g.: /customer/name)"}"#,
+                    ),
+            };
+
+        let identity = author.get_identity();
+        let requester = identity.id.clone();
+        let audit = req
+            .app_data::<Data<AuditLog>>()
+            .map(|a| a.get_ref().clone());
+        let record = |outcome: AuditOutcome, detail: String| {
+            if let Some(log) = audit.as_ref() {
+                let _ = log.record(
+                    AuditEvent::new(
+                        requester.clone(),
+                        AuditAction::Retrieve,
+                        doc_id.clone(),
+                        outcome,
+                    )
+                    .with_detail(detail),
+                );
+            }
+        };
+
+        let storage = LocalStorage::new(LocalStorage::get_local_path());
+        let mut doc = match storage.get_doc_by_id(doc_id.clone(), None) {
+            Ok(d) if !d.deleted => d,
+            _ => {
+                record(AuditOutcome::Failure, "not found".to_string());
+                return HttpResponse::NotFound()
+                    .header(http::header::CONTENT_TYPE, "application/json")
+                    .body(r#"{"error":"data not found"}"#);
+            }
+        };
+
+        // the roles of the requesters can be registered as application data, (e.g.: App::new().data(AccessPolicy::new()))
+        let decision = match req.app_data::<Data<AccessPolicy>>() {
+            Some(policy) => policy.authorize_identity(&identity, &purpose, &doc),
+            None => check_agreements(&purpose, &doc),
+        };
+
+        match decision {
+            AccessDecision::Granted => {
+                record(AuditOutcome::Success, purpose);
+                // the revision is the entity tag, so the clients can cache the data, (If-None-Match)
+                let etag = DaaSListener::make_etag(&doc);
+                if let Some(rspns) = DaaSListener::check_preconditions(&req, &etag) {
+                    return rspns;
+                }
+                if let Some(fields) = fields {
+                    return match doc.project(&fields) {
+                        Value::Null => HttpResponse::UnprocessableEntity()
+                            .header(http::header::CONTENT_TYPE, "application/json")
+                            .body(r#"{"error":"the fields can only be selected from JSON data"}"#),
+                        projection => HttpResponse::Ok()
+                            .header(http::header::CONTENT_TYPE, "application/json")
+                            .header(http::header::ETAG, etag)
+                            .body(projection.to_string()),
+                    };
+                }
+                let content_type = match doc.get_meta("content-type".to_string()) {
+                    ct if ct.is_empty() => "application/octet-stream".to_string(),
+                    ct => ct,
+                };
+                HttpResponse::Ok()
+                    .header(http::header::CONTENT_TYPE, content_type)
+                    .header(http::header::ETAG, etag)
+                    .body(doc.data_obj)
+            }
+            AccessDecision::Denied(agreement) => {
+                record(AuditOutcome::Failure, format!("denied by {}", agreement));
+                HttpResponse::Forbidden()
+                    .header(http::header::CONTENT_TYPE, "application/json")
+                    .body(
+                        json!({
+                            "error": "access denied by the data usage agreement",
+                            "agreement": agreement
+                        })
+                        .to_string(),
+                    )
+            }
+        }
+    }
+
+    /// The RESTful service that reports the state of the data that was sent to the index service, (GET on the
+    /// status path, e.g.: `/status/order~clothing~iStore~5000`), so the clients of the asynchronous ingestion mode
+    /// can poll whether the data has been stored, brokered or processed.
+    ///
+    /// # Arguments
+    ///
+    /// * doc_id: Path<String> - The unique identifier of the DaaS document.</br>
+    /// * _req: HttpRequest - The request.</br>
+    pub fn status(doc_id: Path<String>, _req: HttpRequest) -> HttpResponse {
+        let components: Vec<&str> = doc_id.split(DELIMITER).collect();
+        if components.len() != 4
+            || components
+                .iter()
+                .any(|c| !DaaSDoc::is_valid_id_component(c))
+        {
+            return HttpResponse::BadRequest()
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .body(r#"{"error":"illegal identifier of the DaaS document"}"#);
+        }
+
+        let doc = match DaaSListener::get_latest_doc(&doc_id) {
+            Ok(d) => d,
+            Err(rspns) => return rspns,
+        };
+
+        HttpResponse::Ok()
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .header(http::header::ETAG, DaaSListener::make_etag(&doc))
+            .body(
+                json!({
+                    "_id": doc._id,
+                    "_rev": doc._rev,
+                    "state": IngestState::of(&doc),
+                    "markers": doc.data_tracker.len(),
+                    "last_updated": doc.last_updated
+                })
+                .to_string(),
+            )
+    }
+
+    /// The RESTful service that summarizes the duplicate submissions (the counts per source and the most recent
+    /// examples), (GET on the duplicates path, i.e.: `/duplicates`) so the producers whose retry logic is
+    /// misbehaving can be notified. The duplicates are reported when a DuplicateReport is registered as application
+    /// data, (e.g.: App::new().data(DuplicateReport::new())).
+    ///
+    /// # Arguments
+    ///
+    /// * req: HttpRequest - The request.</br>
+    pub fn duplicates(req: HttpRequest) -> HttpResponse {
+        match req.app_data::<Data<DuplicateReport>>() {
+            Some(report) => HttpResponse::Ok()
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .body(serde_json::to_string(&report.summary()).unwrap()),
+            None => HttpResponse::NotFound()
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .body(r#"{"error":"the duplicate submissions aren't reported"}"#),
+        }
+    }
+
+    /// Returns the URL of the status service of the DaaS document, (e.g.: /status/order~clothing~iStore~5000)
+    ///
+    /// # Arguments
+    ///
+    /// * doc_id: &str - The unique identifier of the DaaS document.</br>
+    pub fn make_status_url(doc_id: &str) -> String {
+        DaaSListener::get_service_status_path().replace("{doc_id}", doc_id)
+    }
+
+    // Returns the latest revision of the DaaS document, or the Not Found response if it doesn't exist or is deleted
+    fn get_latest_doc(doc_id: &str) -> Result<DaaSDoc, HttpResponse> {
+        let storage = LocalStorage::new(LocalStorage::get_local_path());
+        match storage.get_doc_by_id(doc_id.to_string(), None) {
+            Ok(d) if !d.deleted => Ok(d),
+            _ => Err(HttpResponse::NotFound()
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .body(r#"{"error":"data not found"}"#)),
+        }
+    }
+
+    /// The RESTful service that updates the (JSON) data of the DaaS document with a JSON Merge Patch, (PATCH on the
+    /// service path). The new revision is processed like the data that is sent to the index service, and its entity
+    /// tag is returned in the `ETag` header. The update is only applied if the `If-Match` header (when present) holds
+    /// the entity tag of the latest revision, so concurrent updates aren't lost.
+    ///
+    /// # Arguments
+    ///
+    /// * params: Path<Info> - The category, subcategory, source name and source uid of the DaaS document.</br>
+    /// * author: A - The requester.</br>
+    /// * body: String - The JSON Merge Patch.</br>
+    /// * req: HttpRequest - The request.</br>
+    pub fn patch<A: AuthorExtractor>(
+        params: Path<Info>,
+        author: A,
+        body: String,
+        req: HttpRequest,
+    ) -> HttpResponse {
+        let doc_id = match DaaSListener::get_doc_id(&params) {
+            Ok(id) => id,
+            Err(rspns) => return rspns,
+        };
+        let patch: Value = match serde_json::from_str(&body) {
+            Ok(p) => p,
+            Err(_e) => {
+                return HttpResponse::BadRequest()
+                    .header(http::header::CONTENT_TYPE, "application/json")
+                    .body(r#"{"error":"the body must be a JSON Merge Patch"}"#)
+            }
+        };
+        let mut doc = match DaaSListener::get_latest_doc(&doc_id) {
+            Ok(d) => d,
+            Err(rspns) => return rspns,
+        };
+        if let Some(rspns) = DaaSListener::check_preconditions(&req, &DaaSListener::make_etag(&doc))
+        {
+            return rspns;
+        }
+        if doc.merge_patch(&patch).is_err() {
+            return HttpResponse::UnprocessableEntity()
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .body(r#"{"error":"only JSON data can be patched"}"#);
+        }
+
+        // the patched DaaS document is a new revision that still needs to be processed
+        author.get_identity().add_to(&mut doc);
+        doc.process_ind = false;
+        doc.last_updated = get_unix_now!();
+
+        let audit = req
+            .app_data::<Data<AuditLog>>()
+            .map(|a| a.get_ref().clone());
+        let actor = doc.author.clone();
+        let processed = DaaSListener::process_request(doc, &req, audit.clone());
+
+        if let Some(log) = audit {
+            let _ = log.record(AuditEvent::new(
+                actor,
+                AuditAction::Ingest,
+                doc_id,
+                AuditOutcome::from(&processed),
+            ));
+        }
+
+        let body_mode = req
+            .app_data::<Data<ResponseBody>>()
+            .map(|b| *b.get_ref())
+            .unwrap_or_default();
+
+        match processed {
+            Ok(d) => HttpResponse::Ok()
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .header(http::header::ETAG, DaaSListener::make_etag(&d))
+                .body(body_mode.render(&d, "ok").to_string()),
+            // the storage rejects the revision if another update was saved in the meantime
+            Err(_e) => HttpResponse::Conflict()
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .body(r#"{"error":"unable to update data"}"#),
+        }
+    }
+
+    /// The RESTful service that (soft) deletes the DaaS document, (DELETE on the service path).
+    /// The DaaS document is only deleted if the `If-Match` header (when present) holds the entity tag of the latest
+    /// revision, so the deletion doesn't discard an update that the requester hasn't seen.
+    ///
+    /// # Arguments
+    ///
+    /// * params: Path<Info> - The category, subcategory, source name and source uid of the DaaS document.</br>
+    /// * author: A - The requester.</br>
+    /// * req: HttpRequest - The request.</br>
+    pub fn delete<A: AuthorExtractor>(
+        params: Path<Info>,
+        author: A,
+        req: HttpRequest,
+    ) -> HttpResponse {
+        let doc_id = match DaaSListener::get_doc_id(&params) {
+            Ok(id) => id,
+            Err(rspns) => return rspns,
+        };
+        let mut doc = match DaaSListener::get_latest_doc(&doc_id) {
+            Ok(d) => d,
+            Err(rspns) => return rspns,
+        };
+        if let Some(rspns) = DaaSListener::check_preconditions(&req, &DaaSListener::make_etag(&doc))
+        {
+            return rspns;
+        }
+
+        // the revision of the DaaS document is kept, so the storage rejects the deletion if it was updated meanwhile
+        doc.mark_deleted();
+        let deleted = LocalStorage::new(LocalStorage::get_local_path()).upsert_daas_doc(doc);
+
+        if let Some(log) = req.app_data::<Data<AuditLog>>() {
+            let _ = log.record(AuditEvent::new(
+                author.get_name(),
+                AuditAction::Delete,
+                doc_id,
+                AuditOutcome::from(&deleted),
+            ));
+        }
+
+        match deleted {
+            Ok(_d) => HttpResponse::NoContent().finish(),
+            Err(_e) => HttpResponse::Conflict()
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .body(r#"{"error":"unable to delete data"}"#),
+        }
+    }
+
     pub fn process_data(
+        doc: DaaSDoc,
+        broker_topic: Option<String>,
+    ) -> Result<DaaSDoc, UpsertError> {
+        DaaSListener::process_data_with_broker(doc, broker_topic, DaaSKafkaBroker::default())
+    }
+
+    /// Validates and saves the DaaS document, and then brokers it in a separate thread using the provided broker,
+    /// (e.g.: InMemoryBroker for testing without Kafka)
+    ///
+    /// # Arguments
+    ///
+    /// * doc: DaaSDoc - The DaaS document to process.</br>
+    /// * broker_topic: Option<String> - The topic to broker the DaaS document to, (default: category.subcategory.source_name), with the topic prefix of the environment.</br>
+    /// * broker: B - The broker.</br>
+    pub fn process_data_with_broker<B: DaaSKafkaProcessor + Send + 'static>(
         mut doc: DaaSDoc,
         broker_topic: Option<String>,
+        broker: B,
     ) -> Result<DaaSDoc, UpsertError> {
         // validate the document
         doc = match doc.validate() {
@@ -89,6 +701,10 @@ impl DaaSListener {
             Err(_err) => return Err(UpsertError),
         };
 
+        if doc.set_status(DocStatus::Stored).is_err() {
+            return Err(UpsertError);
+        }
+
         // store a local copy so data isn't lost
         let storage = LocalStorage::new(LocalStorage::get_local_path());
         let doc = match storage.upsert_daas_doc(doc) {
@@ -108,30 +724,39 @@ impl DaaSListener {
         // start a detached thread to broker the document
         let doc2broker = doc.clone();
         let topic = match broker_topic {
-            Some(t) => t,
-            None => DaaSKafkaBroker::make_topic(doc.clone()),
+            Some(t) => DaasConfig::from_env().topic(&t),
+            None => DaaSKafkaBroker::make_topic(doc.clone_envelope()),
         };
         thread::spawn(move || {
-            match DaaSListener::broker_document(doc2broker.clone(), topic) {
+            // only the envelope is kept to report the outcome, so the data object isn't copied again
+            let envelope = doc2broker.clone_envelope();
+            match DaaSListener::broker_document(&broker, doc2broker, topic) {
                 Ok(d) => {
                     // based on cofiguration, should the local document be (1) updated or (2) deleted after processes
                     match DaaSListener::mark_doc_as_processed(storage, d) {
                         Ok(_d2) => {
                             info!(
                                 "DaaS docoument {} has been successfully sent to the broker.",
-                                doc2broker._id
+                                envelope._id
                             );
                         }
                         Err(e2) => {
-                            error!("Could not mark the DaaS document {} as processed. Error message: [{}]", doc2broker._id, e2);
+                            error!("Could not mark the DaaS document {} as processed. Error message: [{}]", envelope._id, e2);
                         }
                     }
                 }
                 Err(e) => {
                     error!(
                         "Could not broker the DaaS document {}. Error message: [{}]",
-                        doc2broker._id, e
+                        envelope._id, e
                     );
+                    // the DaaS document is Failed until it is brokered again
+                    if let Err(e2) = storage.mark_doc_as_failed(envelope.clone()) {
+                        error!(
+                            "Could not mark the DaaS document {} as failed. Error message: [{}]",
+                            envelope._id, e2
+                        );
+                    }
                 }
             }
         });
@@ -139,6 +764,36 @@ impl DaaSListener {
         // return
         Ok(doc)
     }
+
+    // Processes the DaaS document of the request with the broker pool and audit log that are registered as
+    // application data
+    fn process_request(
+        doc: DaaSDoc,
+        req: &HttpRequest,
+        audit: Option<AuditLog>,
+    ) -> Result<DaaSDoc, UpsertError> {
+        let actor = doc.author.clone();
+
+        // a broker pool can be registered as application data so the connections are reused across requests,
+        // (e.g.: App::new().data(BrokerPool::new(hosts, 4)))
+        let topic = Some("genesis".to_string());
+        match (req.app_data::<Data<BrokerPool>>(), audit) {
+            (Some(pool), Some(log)) => DaaSListener::process_data_with_broker(
+                doc,
+                topic,
+                AuditedBroker::new(pool.get_ref().clone(), log, actor),
+            ),
+            (Some(pool), None) => {
+                DaaSListener::process_data_with_broker(doc, topic, pool.get_ref().clone())
+            }
+            (None, Some(log)) => DaaSListener::process_data_with_broker(
+                doc,
+                topic,
+                AuditedBroker::new(DaaSKafkaBroker::default(), log, actor),
+            ),
+            (None, None) => DaaSListener::process_data(doc, topic),
+        }
+    }
 }
 
 impl DaaSListenerService for DaaSListener {
@@ -153,44 +808,362 @@ impl DaaSListenerService for DaaSListener {
         let cat: String = params.category.clone();
         let subcat: String = params.subcategory.clone();
         let srcnme: String = params.source_name.clone();
-        let srcuid: usize = params.source_uid;
+        let srcuid: SourceId = match params.source_uid.parse() {
+            Ok(u) => u,
+            Err(_e) => SourceId::Text(params.source_uid.clone()),
+        };
+
+        // reject identifier components that would be ambiguous or unsafe as a storage path
+        if [&cat, &subcat, &srcnme, &params.source_uid]
+            .iter()
+            .any(|c| !DaaSDoc::is_valid_id_component(c))
+        {
+            warn!(
+                "Rejected the request because of an illegal identifier component in {}/{}/{}/{}.",
+                cat, subcat, srcnme, params.source_uid
+            );
+            return HttpResponse::BadRequest()
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .body(
+                    r#"{"error":"illegal characters in the category, subcategory, source name or source uid"}"#,
+                );
+        }
+
+        // only the registered categories and subcategories are accepted when a CategoryRegistry is registered as
+        // application data, (e.g.: App::new().data(CategoryRegistry::from_file(path).unwrap()))
+        let registry = req.app_data::<Data<CategoryRegistry>>();
+        if let Some(registry) = &registry {
+            if registry.get_rule(&cat, &subcat).is_none() {
+                warn!(
+                    "Rejected the request because {}/{} isn't a registered category.",
+                    cat, subcat
+                );
+                return HttpResponse::BadRequest()
+                    .header(http::header::CONTENT_TYPE, "application/json")
+                    .body(r#"{"error":"unknown category or subcategory"}"#);
+            }
+        }
+
+        // trusted producers that can't use mutual TLS sign their requests when a SignatureVerifier is registered as
+        // application data, (e.g.: App::new().data(SignatureVerifier::new().with_secret(source_name, secret))).
+        // The signature is only recorded as seen after the Idempotency-Key is looked up, like the ReplayGuard, and it
+        // covers the Data Tracker Chain header.
+        #[cfg(feature = "security")]
+        let signed = match req.app_data::<Data<SignatureVerifier>>() {
+            Some(verifier) => match verifier.authenticate_request(&req, &srcnme, body.as_bytes()) {
+                Ok(s) => Some((verifier, s)),
+                Err(_e) => {
+                    return HttpResponse::Unauthorized()
+                        .header(http::header::CONTENT_TYPE, "application/json")
+                        .body(r#"{"error":"invalid or missing request signature"}"#)
+                }
+            },
+            None => None,
+        };
 
         let content_type = match req.headers().get("Content-Type") {
             Some(ct) => ct.to_str().unwrap(),
             None => "unknown",
         };
 
+        // the payloads of each category are transformed before the DaaS document is constructed when Transformations
+        // are registered as application data, (e.g.: App::new().data(Transformations::new()))
+        let payload = RawPayload::new(content_type.to_string(), body.as_bytes().to_vec());
+        let (payload, transformers) = match req.app_data::<Data<Transformations>>() {
+            Some(transformations) => match transformations.apply(&cat, payload) {
+                Ok(transformed) => transformed,
+                Err(_e) => {
+                    return HttpResponse::UnprocessableEntity()
+                        .header(http::header::CONTENT_TYPE, "application/json")
+                        .body(r#"{"error":"the payload could not be transformed"}"#)
+                }
+            },
+            None => (payload, Vec::new()),
+        };
+
+        if let Some(rule) = registry.as_ref().and_then(|r| r.get_rule(&cat, &subcat)) {
+            if rule.validate_payload(&payload.data).is_err() {
+                return HttpResponse::UnprocessableEntity()
+                    .header(http::header::CONTENT_TYPE, "application/json")
+                    .body(r#"{"error":"the payload doesn't match the schema of the category"}"#);
+            }
+        }
+
+        // the retries of a client with the same Idempotency-Key receive the original response when an IdempotencyStore
+        // is registered as application data, (e.g.: App::new().data(IdempotencyStore::new())). This is checked before
+        // the signature is recorded as seen and before the ReplayGuard, since a retry carries the same signature and
+        // Data Tracker Chain as the original request. The key is held
+        // by a reservation, which releases it on every return of a rejected or failed request.
+        let idempotency = match (
+            req.app_data::<Data<IdempotencyStore>>(),
+            req.headers()
+                .get(IDEMPOTENCY_KEY_HEADER)
+                .and_then(|k| k.to_str().ok()),
+        ) {
+            (Some(store), Some(key)) => {
+                let fingerprint = IdempotencyStore::fingerprint(req.path(), body.as_bytes());
+                match store.begin(&author.get_name(), key, &fingerprint) {
+                    Idempotency::Proceed => Some(store.reserve(&author.get_name(), key)),
+                    Idempotency::Replay(rspns) => {
+                        // the retries are reported when a DuplicateReport is registered as application data,
+                        // (e.g.: App::new().data(DuplicateReport::new()))
+                        if let Some(report) = req.app_data::<Data<DuplicateReport>>() {
+                            report.record(&srcnme, DuplicateKind::IdempotencyKey, key);
+                        }
+                        return HttpResponse::build(
+                            http::StatusCode::from_u16(rspns.status).unwrap(),
+                        )
+                        .header(http::header::CONTENT_TYPE, "application/json")
+                        .header(IDEMPOTENT_REPLAYED_HEADER, "true")
+                        .body(rspns.body);
+                    }
+                    Idempotency::InFlight => return HttpResponse::Conflict()
+                        .header(http::header::CONTENT_TYPE, "application/json")
+                        .body(
+                            r#"{"error":"a request with the idempotency key is being processed"}"#,
+                        ),
+                    Idempotency::Mismatch => return HttpResponse::UnprocessableEntity()
+                        .header(http::header::CONTENT_TYPE, "application/json")
+                        .body(
+                            r#"{"error":"the idempotency key was used for a different request"}"#,
+                        ),
+                }
+            }
+            _ => None,
+        };
+
+        // the signature of the request is held by a reservation, which forgets it on every return of a rejected or
+        // failed request, so the producer can retry the same signed request
+        #[cfg(feature = "security")]
+        let signature = match &signed {
+            Some((verifier, s)) => match verifier.reserve(s, get_unix_now!()) {
+                Ok(reservation) => Some(reservation),
+                Err(_e) => {
+                    return HttpResponse::Unauthorized()
+                        .header(http::header::CONTENT_TYPE, "application/json")
+                        .body(r#"{"error":"invalid or missing request signature"}"#)
+                }
+            },
+            None => None,
+        };
+
+        // captured requests can't be re-submitted when a ReplayGuard is registered as application data,
+        // (e.g.: App::new().data(ReplayGuard::new())). The signature of a signed request covers its Data Tracker
+        // Chain, so the signed requests are protected by the SignatureVerifier instead.
+        #[cfg(feature = "security")]
+        let guarded = signed.is_none();
+        #[cfg(not(feature = "security"))]
+        let guarded = true;
+        if let (Some(guard), true) = (req.app_data::<Data<ReplayGuard>>(), guarded) {
+            if guard.check(&tracker).is_err() {
+                return HttpResponse::Unauthorized()
+                    .header(http::header::CONTENT_TYPE, "application/json")
+                    .body(r#"{"error":"expired or replayed data tracker chain"}"#);
+            }
+        }
+
+        // the ID strategy can be selected by registering it as application data, (e.g.: App::new().data(IdStrategy::UuidV7))
+        let strategy = req
+            .app_data::<Data<IdStrategy>>()
+            .map(|s| *s.get_ref())
+            .unwrap_or_default();
+
         let usr = author.get_name();
         let mut doc = DaaSDoc::new(
             srcnme,
             srcuid,
             cat,
             subcat,
-            usr,
+            usr.clone(),
             duas.vec(),
             tracker.clone(),
-            body.as_bytes().to_vec(),
+            payload.data,
+        )
+        .with_id_strategy(strategy);
+
+        // the transformers are recorded as actors in the Data Tracker Chain
+        if !transformers.is_empty() {
+            for name in transformers.iter() {
+                doc.data_tracker
+                    .add(get_unix_now!(), name.clone(), doc._id.clone());
+            }
+            doc.add_meta(TRANSFORMATIONS_META.to_string(), transformers.join(","));
+        }
+
+        // the retention policy can be registered as application data, (e.g.: App::new().data(RetentionPolicy::new()))
+        if let Some(policy) = req.app_data::<Data<RetentionPolicy>>() {
+            doc.apply_retention(policy.get_ref());
+        }
+        if let Some(registry) = registry {
+            registry.govern(&mut doc);
+        }
+
+        // the DaaS documents are numbered per source when a Sequencer is registered as application data,
+        // (e.g.: App::new().data(Sequencer::new(Arc::new(FileSequenceStore::new(path))))).
+        // The number is given back if the DaaS document can't be processed, so it doesn't leave a gap.
+        let sequencer = req.app_data::<Data<Sequencer>>();
+        if let Some(sequencer) = sequencer {
+            if sequencer.assign(&mut doc).is_err() {
+                return HttpResponse::ServiceUnavailable()
+                    .header(http::header::CONTENT_TYPE, "application/json")
+                    .body(r#"{"error":"unable to allocate the sequence number"}"#);
+            }
+        }
+        author.get_identity().add_to(&mut doc);
+        doc.add_meta("content-type".to_string(), payload.content_type);
+        latency::stamp(&mut doc, INGESTED_AT_META);
+        doc.add_meta(
+            INGESTED_MARKERS_META.to_string(),
+            doc.data_tracker.len().to_string(),
         );
-        doc.add_meta("content-type".to_string(), content_type.to_string());
 
-        match DaaSListener::process_data(doc, Some("genesis".to_string())) {
-            Ok(_d) => HttpResponse::Ok()
-                .header(http::header::CONTENT_TYPE, "application/json")
-                .body(r#"{"status":"ok"}"#),
-            Err(_e) => HttpResponse::UnprocessableEntity()
-                .header(http::header::CONTENT_TYPE, "application/json")
-                .body(r#"{"error":"unable to process data"}"#),
+        // the DaaS document is watched until it reaches the stages of the SLA rules when an SlaWatchdog is registered
+        // as application data, (e.g.: App::new().data(SlaWatchdog::new().with_rule(rule)))
+        if let Some(watchdog) = req.app_data::<Data<SlaWatchdog>>() {
+            watchdog.watch(&doc);
         }
+
+        // the ingestion and brokering are recorded when an AuditLog is registered as application data,
+        // (e.g.: App::new().data(AuditLog::new(FileAuditSink::new(path))))
+        let audit = req
+            .app_data::<Data<AuditLog>>()
+            .map(|a| a.get_ref().clone());
+        let doc_id = doc._id.clone();
+        let actor = doc.author.clone();
+        let sequenced = sequencer.map(|s| (s, doc.clone_envelope()));
+
+        let processed = DaaSListener::process_request(doc, &req, audit.clone());
+
+        if let (Some((sequencer, envelope)), Err(_e)) = (sequenced, &processed) {
+            if !sequencer.release(&envelope).unwrap_or(false) {
+                warn!(
+                    "The sequence number {:?} of {} couldn't be given back.",
+                    envelope.sequence, envelope._id
+                );
+            }
+        }
+
+        if let Some(log) = audit {
+            let _ = log.record(AuditEvent::new(
+                actor,
+                AuditAction::Ingest,
+                doc_id,
+                AuditOutcome::from(&processed),
+            ));
+        }
+
+        // the ingestion mode can be selected by registering it as application data, (e.g.: App::new().data(IngestMode::Async)),
+        // or by the client with the `Prefer: respond-async` header (RFC 7240)
+        let respond_async = req
+            .app_data::<Data<IngestMode>>()
+            .map(|m| *m.get_ref() == IngestMode::Async)
+            .unwrap_or(false)
+            || req
+                .headers()
+                .get("Prefer")
+                .and_then(|p| p.to_str().ok())
+                .map(|p| p.split(',').any(|t| t.trim() == "respond-async"))
+                .unwrap_or(false);
+
+        // the body of the response can be selected by registering it as application data,
+        // (e.g.: App::new().data(ResponseBody::Summary))
+        let body_mode = req
+            .app_data::<Data<ResponseBody>>()
+            .map(|b| *b.get_ref())
+            .unwrap_or_default();
+
+        let rspns = match &processed {
+            Ok(d) if respond_async => {
+                let mut body = body_mode.render(d, "accepted");
+                body["_id"] = Value::from(d._id.clone());
+                body["status_url"] = Value::from(DaaSListener::make_status_url(&d._id));
+                StoredResponse::new(202, body.to_string())
+            }
+            Ok(d) => StoredResponse::new(200, body_mode.render(d, "ok").to_string()),
+            Err(_e) => {
+                StoredResponse::new(422, r#"{"error":"unable to process data"}"#.to_string())
+            }
+        };
+
+        // only the successful responses are remembered, so a failed request can be retried with the same key
+        if let (Some(reservation), true) = (idempotency, processed.is_ok()) {
+            reservation.complete(rspns.clone());
+        }
+        #[cfg(feature = "security")]
+        if let (Some(reservation), true) = (signature, processed.is_ok()) {
+            reservation.keep();
+        }
+
+        let mut builder = HttpResponse::build(http::StatusCode::from_u16(rspns.status).unwrap());
+        if let (Ok(d), true) = (&processed, respond_async) {
+            builder.header(
+                http::header::LOCATION,
+                DaaSListener::make_status_url(&d._id),
+            );
+        }
+        builder
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .body(rspns.body)
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::eventing::memory::InMemoryBroker;
     use actix_web::http::StatusCode;
     use actix_web::test::TestRequest;
     use std::time::Duration;
 
+    #[test]
+    fn test_ingest_state() {
+        let mut doc = crate::testing::get_default_daas_doc();
+        assert_eq!(IngestState::of(&doc), IngestState::Stored);
+
+        doc.add_meta(
+            INGESTED_MARKERS_META.to_string(),
+            doc.data_tracker.len().to_string(),
+        );
+        doc.process_ind = true;
+        assert_eq!(IngestState::of(&doc), IngestState::Brokered);
+
+        doc.data_tracker
+            .add(get_unix_now!(), "processor".to_string(), doc._id.clone());
+        assert_eq!(IngestState::of(&doc), IngestState::Processed);
+    }
+
+    #[test]
+    fn test_response_body() {
+        let mut doc = crate::testing::get_default_daas_doc();
+        doc._rev = Some("2".to_string());
+
+        assert_eq!(
+            ResponseBody::Status.render(&doc, "ok").to_string(),
+            r#"{"status":"ok"}"#.to_string()
+        );
+
+        let summary = ResponseBody::Summary.render(&doc, "ok");
+        assert_eq!(summary["_rev"], json!("2"));
+        assert_eq!(summary["tracker"]["is_valid"], json!(true));
+        assert!(summary["location"]
+            .as_str()
+            .unwrap()
+            .ends_with("order~clothing~iStore~5000~2"));
+
+        let envelope = ResponseBody::Envelope.render(&doc, "accepted");
+        assert_eq!(envelope["status"], json!("accepted"));
+        assert_eq!(envelope["category"], json!("order"));
+        assert!(envelope.get("data_obj").is_none());
+    }
+
+    #[test]
+    fn test_status_path() {
+        assert_eq!(
+            DaaSListener::make_status_url("order~clothing~iStore~5000"),
+            "/status/order~clothing~iStore~5000".to_string()
+        );
+    }
+
     #[test]
     fn test_health() {
         let req = test::TestRequest::get().to_http_request();
@@ -238,6 +1211,32 @@ mod test {
         handle.join().unwrap();
     }
 
+    #[test]
+    fn test_process_data_with_broker() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let serialized = r#"{"_id":"order~clothing~iStore~15000","_rev":null,"source_name":"iStore","source_uid":15000,"category":"order","subcategory":"clothing","author":"iStore_app","process_ind":false,"last_updated":1553988607,"data_usage_agreements":[{"agreement_name":"billing","location":"www.dua.org/billing.pdf","agreed_dtm":1553988607}],"data_tracker":{"chain":[{"identifier":{"data_id":"order~clothing~iStore~15000","index":0,"timestamp":0,"actor_id":"","previous_hash":"0"},"hash":"33962353871142597622255173163773323410","nonce":5}]},"meta_data":{},"tags":[],"data_obj":[123,34,115,116,97,116,117,115,34,58,32,34,110,101,119,34,125]}"#;
+        let doc = DaaSDoc::from_serialized(&serialized.as_bytes()).unwrap();
+        let broker = InMemoryBroker::new();
+
+        assert!(DaaSListener::process_data_with_broker(
+            doc,
+            Some("genesis".to_string()),
+            broker.clone()
+        )
+        .is_ok());
+
+        let mut attempts = 0;
+        while broker.messages("genesis").is_empty() && attempts < 50 {
+            thread::sleep(Duration::from_millis(100));
+            attempts += 1;
+        }
+        assert_eq!(
+            broker.messages("genesis")[0]._id,
+            "order~clothing~iStore~15000".to_string()
+        );
+        assert!(latency::stamped_at(&broker.messages("genesis")[0], BROKERED_AT_META).is_some());
+    }
+
     #[test]
     fn test_process_data_tampered_with() {
         let _ = env_logger::builder().is_test(true).try_init();
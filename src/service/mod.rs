@@ -1,10 +1,40 @@
 use super::*;
 use crate::errors::*;
+#[cfg(feature = "service")]
 use actix_web::web::Path;
+#[cfg(feature = "service")]
 use actix_web::{http, HttpRequest, HttpResponse};
 use pbd::dtc::Tracker;
+#[cfg(feature = "service")]
 use pbd::dua::extractor::actix::DUAs;
 
+#[cfg(feature = "service")]
+pub mod access;
+#[cfg(feature = "service")]
+pub mod compression;
+pub mod convert;
+#[cfg(feature = "service")]
+pub mod cors;
+pub mod enrichment;
+#[cfg(feature = "service")]
 pub mod extractor;
+#[cfg(all(feature = "kafka", feature = "security"))]
+pub mod grant;
+pub mod idempotency;
+#[cfg(feature = "service")]
 pub mod listener;
+#[cfg(feature = "kafka")]
+pub mod monitor;
+#[cfg(feature = "kafka")]
+pub mod pipeline;
+#[cfg(feature = "kafka")]
 pub mod processor;
+#[cfg(feature = "service")]
+pub mod protobuf;
+pub mod registry;
+pub mod replay;
+#[cfg(all(feature = "service", feature = "security"))]
+pub mod signature;
+#[cfg(all(feature = "service", feature = "security"))]
+pub mod tls;
+pub mod transform;
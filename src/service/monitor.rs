@@ -0,0 +1,385 @@
+//! Monitors the lag of the consumer group of a processor, (the messages that have been brokered but not yet consumed).
+//!
+//! The `ProcessorMonitor` periodically compares the latest offsets of the topics with the offsets committed by the
+//! consumer group, passes the `LagReport` to the lag callback and calls the scale-up or scale-down hooks when the
+//! total lag crosses the thresholds, (e.g.: to spawn more processor threads).
+
+use super::*;
+use kafka::client::{FetchOffset, KafkaClient, PartitionOffset};
+use std::collections::HashMap;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// The function that is called with the lag of the consumer group
+pub type LagHook = Arc<dyn Fn(&LagReport) + Send + Sync>;
+
+/// Represents the lag of a partition of a topic
+#[derive(Debug, Clone, PartialEq)]
+pub struct PartitionLag {
+    /// The name of the topic
+    pub topic: String,
+    /// The partition of the topic
+    pub partition: i32,
+    /// The offset of the next message that will be brokered to the partition
+    pub latest_offset: i64,
+    /// The offset committed by the consumer group, (-1 if the group hasn't committed an offset)
+    pub committed_offset: i64,
+    /// The number of messages that haven't been consumed
+    pub lag: i64,
+}
+
+/// Represents the lag of the consumer group on all the partitions of the topics
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LagReport {
+    /// The lag of each partition
+    pub partitions: Vec<PartitionLag>,
+}
+
+impl LagReport {
+    /// Constructs the report from the latest offsets of the topics and the offsets committed by the consumer group
+    ///
+    /// # Arguments
+    ///
+    /// * latest: &HashMap<String, Vec<PartitionOffset>> - The latest offsets of the partitions of each topic.</br>
+    /// * committed: &HashMap<String, Vec<PartitionOffset>> - The committed offsets of the partitions of each topic.</br>
+    pub fn from_offsets(
+        latest: &HashMap<String, Vec<PartitionOffset>>,
+        committed: &HashMap<String, Vec<PartitionOffset>>,
+    ) -> LagReport {
+        let mut partitions = Vec::new();
+
+        for (topic, offsets) in latest.iter() {
+            for latest in offsets.iter() {
+                let committed_offset = committed
+                    .get(topic)
+                    .and_then(|c| c.iter().find(|p| p.partition == latest.partition))
+                    .map_or(-1, |p| p.offset);
+
+                partitions.push(PartitionLag {
+                    topic: topic.clone(),
+                    partition: latest.partition,
+                    latest_offset: latest.offset,
+                    committed_offset,
+                    lag: (latest.offset - committed_offset.max(0)).max(0),
+                });
+            }
+        }
+
+        partitions.sort_by(|a, b| (&a.topic, a.partition).cmp(&(&b.topic, b.partition)));
+        LagReport { partitions }
+    }
+
+    /// Returns the total lag of all the partitions
+    pub fn total(&self) -> i64 {
+        self.partitions.iter().map(|p| p.lag).sum()
+    }
+
+    /// Returns the total lag of the partitions of the topic
+    ///
+    /// # Arguments
+    ///
+    /// * topic: &str - The name of the topic.</br>
+    pub fn topic_lag(&self, topic: &str) -> i64 {
+        self.partitions
+            .iter()
+            .filter(|p| p.topic == topic)
+            .map(|p| p.lag)
+            .sum()
+    }
+}
+
+// The scaling state of the processors, so that the hooks are only called when the lag crosses a threshold
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Scale {
+    Normal,
+    Up,
+    Down,
+}
+
+/// Represents a monitor of the lag of the consumer group of a processor
+#[derive(Clone)]
+pub struct ProcessorMonitor {
+    /// The hosts of the Kafka brokers, (e.g.: localhost:9092)
+    pub hosts: Vec<String>,
+    /// The consumer group of the processor, (e.g.: genesis-consumers)
+    pub group: String,
+    /// The topics that the processor consumes
+    pub topics: Vec<String>,
+    /// How often the lag is checked, (default: 30 seconds)
+    pub interval: Duration,
+    /// The total lag at (or above) which the scale-up hook is called
+    pub scale_up_lag: Option<i64>,
+    /// The total lag at (or below) which the scale-down hook is called
+    pub scale_down_lag: Option<i64>,
+    on_lag: Option<LagHook>,
+    on_scale_up: Option<LagHook>,
+    on_scale_down: Option<LagHook>,
+    scale: Arc<Mutex<Scale>>,
+    last_report: Arc<Mutex<Option<LagReport>>>,
+}
+
+impl ProcessorMonitor {
+    /// Constructs a ProcessorMonitor
+    ///
+    /// # Arguments
+    ///
+    /// * hosts: Vec<String> - The hosts of the Kafka brokers.</br>
+    /// * group: String - The consumer group of the processor.</br>
+    /// * topics: Vec<String> - The topics that the processor consumes.</br>
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// extern crate daas;
+    ///
+    /// use daas::service::monitor::ProcessorMonitor;
+    /// use std::time::Duration;
+    ///
+    /// fn main() {
+    ///     let monitor = ProcessorMonitor::new(
+    ///         vec!["localhost:9092".to_string()],
+    ///         "genesis-consumers".to_string(),
+    ///         vec!["genesis".to_string()],
+    ///     )
+    ///     .with_interval(Duration::from_secs(10))
+    ///     .with_thresholds(1000, 10)
+    ///     .on_lag(|report| println!("lag: {}", report.total()))
+    ///     .on_scale_up(|_report| println!("spawning another processor thread ..."));
+    ///
+    ///     assert!(monitor.last_report().is_none());
+    /// }
+    /// ```
+    pub fn new(hosts: Vec<String>, group: String, topics: Vec<String>) -> ProcessorMonitor {
+        ProcessorMonitor {
+            hosts,
+            group,
+            topics,
+            interval: Duration::from_secs(30),
+            scale_up_lag: None,
+            scale_down_lag: None,
+            on_lag: None,
+            on_scale_up: None,
+            on_scale_down: None,
+            scale: Arc::new(Mutex::new(Scale::Normal)),
+            last_report: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Sets how often the lag is checked
+    pub fn with_interval(mut self, interval: Duration) -> ProcessorMonitor {
+        self.interval = interval;
+        self
+    }
+
+    /// Sets the total lag at (or above) which to scale up and at (or below) which to scale down
+    pub fn with_thresholds(mut self, scale_up_lag: i64, scale_down_lag: i64) -> ProcessorMonitor {
+        self.scale_up_lag = Some(scale_up_lag);
+        self.scale_down_lag = Some(scale_down_lag);
+        self
+    }
+
+    /// Sets the function that is called with every LagReport
+    pub fn on_lag<F: Fn(&LagReport) + Send + Sync + 'static>(mut self, f: F) -> ProcessorMonitor {
+        self.on_lag = Some(Arc::new(f));
+        self
+    }
+
+    /// Sets the function that is called when the total lag rises to the scale-up threshold
+    pub fn on_scale_up<F: Fn(&LagReport) + Send + Sync + 'static>(
+        mut self,
+        f: F,
+    ) -> ProcessorMonitor {
+        self.on_scale_up = Some(Arc::new(f));
+        self
+    }
+
+    /// Sets the function that is called when the total lag falls to the scale-down threshold
+    pub fn on_scale_down<F: Fn(&LagReport) + Send + Sync + 'static>(
+        mut self,
+        f: F,
+    ) -> ProcessorMonitor {
+        self.on_scale_down = Some(Arc::new(f));
+        self
+    }
+
+    /// Returns the most recent LagReport, (None if the lag hasn't been checked yet)
+    pub fn last_report(&self) -> Option<LagReport> {
+        self.last_report.lock().unwrap().clone()
+    }
+
+    /// Fetches the latest and committed offsets and returns the lag of the consumer group
+    pub fn check(&self) -> Result<LagReport, kafka::error::ErrorKind> {
+        let mut client = KafkaClient::new(self.hosts.clone());
+        client.load_metadata(&self.topics)?;
+
+        let latest = client.fetch_offsets(&self.topics, FetchOffset::Latest)?;
+        let mut committed = HashMap::new();
+        for topic in self.topics.iter() {
+            committed.insert(
+                topic.clone(),
+                client.fetch_group_topic_offsets(&self.group, topic)?,
+            );
+        }
+
+        Ok(LagReport::from_offsets(&latest, &committed))
+    }
+
+    /// Records the LagReport and calls the lag callback and, if the total lag crossed a threshold, the scaling hook
+    ///
+    /// # Arguments
+    ///
+    /// * report: LagReport - The lag of the consumer group.</br>
+    pub fn evaluate(&self, report: LagReport) {
+        let total = report.total();
+        let next = match (self.scale_up_lag, self.scale_down_lag) {
+            (Some(up), _) if total >= up => Scale::Up,
+            (_, Some(down)) if total <= down => Scale::Down,
+            _ => Scale::Normal,
+        };
+        let previous = std::mem::replace(&mut *self.scale.lock().unwrap(), next);
+
+        if let Some(f) = &self.on_lag {
+            f(&report);
+        }
+
+        if next != previous {
+            let hook = match next {
+                Scale::Up => &self.on_scale_up,
+                Scale::Down => &self.on_scale_down,
+                Scale::Normal => &None,
+            };
+            if let Some(f) = hook {
+                info!("Lag of {} crossed a threshold ({:?}).", self.group, next);
+                f(&report);
+            }
+        }
+
+        *self.last_report.lock().unwrap() = Some(report);
+    }
+
+    /// Starts checking the lag in a separate thread until a message is sent to stop, (see `stop()`)
+    pub fn start(&self) -> Sender<bool> {
+        let (tx, rx) = channel();
+        let monitor = self.clone();
+
+        thread::spawn(move || monitor.run(&rx));
+
+        tx
+    }
+
+    /// Stops the ProcessorMonitor
+    ///
+    /// # Arguments
+    ///
+    /// * tx: Sender<bool> - The sender that was returned by `start()`.</br>
+    pub fn stop(tx: Sender<bool>) {
+        let _ = tx.send(true);
+    }
+
+    fn run(&self, rx: &Receiver<bool>) {
+        loop {
+            match self.check() {
+                Ok(report) => self.evaluate(report),
+                Err(err) => warn!(
+                    "Could not check the lag of {}. Error: {:?}",
+                    self.group, err
+                ),
+            }
+
+            // wait for the next check, unless a message is sent to stop
+            match rx.recv_timeout(self.interval) {
+                Err(std::sync::mpsc::RecvTimeoutError::Timeout) => {}
+                _ => {
+                    info!("Shutting down ProcessorMonitor ...");
+                    break;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn get_report(latest: i64, committed: i64) -> LagReport {
+        let mut l = HashMap::new();
+        l.insert(
+            "genesis".to_string(),
+            vec![
+                PartitionOffset {
+                    partition: 0,
+                    offset: latest,
+                },
+                PartitionOffset {
+                    partition: 1,
+                    offset: 5,
+                },
+            ],
+        );
+        let mut c = HashMap::new();
+        c.insert(
+            "genesis".to_string(),
+            vec![PartitionOffset {
+                partition: 0,
+                offset: committed,
+            }],
+        );
+
+        LagReport::from_offsets(&l, &c)
+    }
+
+    fn get_monitor() -> ProcessorMonitor {
+        ProcessorMonitor::new(
+            vec!["localhost:9092".to_string()],
+            "genesis-consumers".to_string(),
+            vec!["genesis".to_string()],
+        )
+    }
+
+    #[test]
+    fn test_lag_report() {
+        let report = get_report(100, 40);
+
+        assert_eq!(report.partitions[0].lag, 60);
+        // the partition without a committed offset lags by all its messages
+        assert_eq!(report.partitions[1].committed_offset, -1);
+        assert_eq!(report.partitions[1].lag, 5);
+        assert_eq!(report.total(), 65);
+        assert_eq!(report.topic_lag("genesis"), 65);
+        assert_eq!(report.topic_lag("other"), 0);
+    }
+
+    #[test]
+    fn test_evaluate_hooks() {
+        let lags = Arc::new(AtomicUsize::new(0));
+        let ups = Arc::new(AtomicUsize::new(0));
+        let downs = Arc::new(AtomicUsize::new(0));
+        let (l, u, d) = (lags.clone(), ups.clone(), downs.clone());
+        let monitor = get_monitor()
+            .with_thresholds(50, 10)
+            .on_lag(move |_r| {
+                l.fetch_add(1, Ordering::SeqCst);
+            })
+            .on_scale_up(move |_r| {
+                u.fetch_add(1, Ordering::SeqCst);
+            })
+            .on_scale_down(move |_r| {
+                d.fetch_add(1, Ordering::SeqCst);
+            });
+
+        monitor.evaluate(get_report(100, 40));
+        monitor.evaluate(get_report(110, 40));
+        monitor.evaluate(get_report(100, 98));
+        monitor.evaluate(get_report(100, 70));
+
+        assert_eq!(lags.load(Ordering::SeqCst), 4);
+        // the hooks are only called when the lag crosses the threshold
+        assert_eq!(ups.load(Ordering::SeqCst), 1);
+        assert_eq!(downs.load(Ordering::SeqCst), 1);
+        assert_eq!(monitor.last_report().unwrap().total(), 35);
+    }
+}
@@ -0,0 +1,323 @@
+//! A declarative pipeline of DaaS document transformations that ends in a sink, (e.g.: storage, a topic or a webhook).
+//!
+//! A `Pipeline` is a `DaaSDocHandler`, so it runs on any processor that accepts a handler, (see `start_listening_with_handler()`).
+//!
+//! # Examples
+//!
+//! ```
+//! extern crate daas;
+//!
+//! use daas::eventing::memory::InMemoryBroker;
+//! use daas::service::pipeline::{Pipeline, TopicSink};
+//! use daas::testing;
+//!
+//! fn main() {
+//!     let broker = InMemoryBroker::new();
+//!     let pipeline = Pipeline::new()
+//!         .filter(|doc| doc.category == "order")
+//!         .map(|mut doc| {
+//!             doc.add_tag("forwarded".to_string());
+//!             doc
+//!         })
+//!         .sink(TopicSink::new(broker.clone(), "orders".to_string()));
+//!
+//!     assert!(pipeline.run(testing::get_default_daas_doc()).unwrap());
+//!     assert!(broker.messages("orders")[0].has_tag("forwarded".to_string()));
+//! }
+//! ```
+
+use super::*;
+use crate::doc::DaaSDoc;
+use crate::errors::daaserror::DaaSProcessingError;
+use crate::eventing::broker::DaaSKafkaProcessor;
+use crate::service::processor::{DaaSDocHandler, DaaSProcessorMessage};
+use crate::storage::DaaSDocStorage;
+use kafka::client::KafkaClient;
+use std::time::Duration;
+
+// A stage of the pipeline, which returns None if the DaaS document is dropped
+type Stage = Box<dyn Fn(DaaSDoc) -> Result<Option<DaaSDoc>, DaaSProcessingError> + Send + Sync>;
+
+/// Trait for the destinations of the DaaS documents at the end of a pipeline
+pub trait DaaSDocSink {
+    /// Sends the DaaS document to the destination
+    ///
+    /// # Arguments
+    ///
+    /// * doc: DaaSDoc - The transformed DaaS document.</br>
+    fn send(&self, doc: DaaSDoc) -> Result<(), DaaSProcessingError>;
+    /// Sends the DaaS documents that the sink has buffered, (e.g.: for batching)
+    fn flush(&self) -> Result<(), DaaSProcessingError> {
+        Ok(())
+    }
+}
+
+/// A sink that saves the DaaS documents to a storage device
+pub struct StorageSink<S: DaaSDocStorage> {
+    /// The storage device
+    pub storage: S,
+}
+
+impl<S: DaaSDocStorage> StorageSink<S> {
+    /// Constructs a StorageSink
+    pub fn new(storage: S) -> StorageSink<S> {
+        StorageSink { storage }
+    }
+}
+
+impl<S: DaaSDocStorage> DaaSDocSink for StorageSink<S> {
+    fn send(&self, doc: DaaSDoc) -> Result<(), DaaSProcessingError> {
+        match self.storage.upsert_daas_doc(doc) {
+            Ok(_d) => Ok(()),
+            Err(err) => {
+                error!("Could not save the DaaS document. Error: {}", err);
+                Err(DaaSProcessingError::UpsertError)
+            }
+        }
+    }
+}
+
+/// A sink that brokers the DaaS documents to a topic
+pub struct TopicSink<B: DaaSKafkaProcessor> {
+    /// The broker
+    pub broker: B,
+    /// The name of the topic
+    pub topic: String,
+}
+
+impl<B: DaaSKafkaProcessor> TopicSink<B> {
+    /// Constructs a TopicSink
+    pub fn new(broker: B, topic: String) -> TopicSink<B> {
+        TopicSink { broker, topic }
+    }
+}
+
+impl<B: DaaSKafkaProcessor> DaaSDocSink for TopicSink<B> {
+    fn send(&self, mut doc: DaaSDoc) -> Result<(), DaaSProcessingError> {
+        match self.broker.broker_message(&mut doc, &self.topic) {
+            Ok(_v) => Ok(()),
+            Err(err) => {
+                error!(
+                    "Failed to broker message to {}. Error: {:?}",
+                    self.topic, err
+                );
+                Err(DaaSProcessingError::BrokerError)
+            }
+        }
+    }
+}
+
+/// A sink that POSTs the serialized DaaS documents to a webhook
+pub struct WebhookSink {
+    /// The URL of the webhook
+    pub url: String,
+    client: reqwest::blocking::Client,
+}
+
+impl WebhookSink {
+    /// Constructs a WebhookSink
+    ///
+    /// # Arguments
+    ///
+    /// * url: String - The URL of the webhook, (e.g.: https://example.com/hooks/orders).</br>
+    /// * timeout: Duration - How long to wait for the webhook to respond.</br>
+    pub fn new(url: String, timeout: Duration) -> WebhookSink {
+        WebhookSink {
+            url,
+            client: reqwest::blocking::Client::builder()
+                .timeout(timeout)
+                .build()
+                .unwrap(),
+        }
+    }
+}
+
+impl DaaSDocSink for WebhookSink {
+    fn send(&self, mut doc: DaaSDoc) -> Result<(), DaaSProcessingError> {
+        let rspns = self
+            .client
+            .post(&self.url)
+            .header("Content-Type", "application/json")
+            .body(doc.serialize())
+            .send();
+
+        match rspns {
+            Ok(r) if r.status().is_success() => Ok(()),
+            Ok(r) => {
+                error!("The webhook {} responded with {}.", self.url, r.status());
+                Err(DaaSProcessingError::BrokerError)
+            }
+            Err(err) => {
+                error!("Could not call the webhook {}. Error: {}", self.url, err);
+                Err(DaaSProcessingError::BrokerError)
+            }
+        }
+    }
+}
+
+/// Represents a chain of stages that transform the DaaS documents, and the sink that receives them
+#[derive(Default)]
+pub struct Pipeline {
+    stages: Vec<Stage>,
+    sink: Option<Box<dyn DaaSDocSink + Send + Sync>>,
+}
+
+impl Pipeline {
+    /// Constructs a Pipeline without any stages or sink
+    pub fn new() -> Pipeline {
+        Pipeline::default()
+    }
+
+    /// Adds a stage that drops the DaaS documents that don't match the predicate
+    pub fn filter<F>(mut self, predicate: F) -> Pipeline
+    where
+        F: Fn(&DaaSDoc) -> bool + Send + Sync + 'static,
+    {
+        self.stages.push(Box::new(move |doc| match predicate(&doc) {
+            true => Ok(Some(doc)),
+            false => Ok(None),
+        }));
+        self
+    }
+
+    /// Adds a stage that transforms the DaaS documents
+    pub fn map<F>(mut self, f: F) -> Pipeline
+    where
+        F: Fn(DaaSDoc) -> DaaSDoc + Send + Sync + 'static,
+    {
+        self.stages.push(Box::new(move |doc| Ok(Some(f(doc)))));
+        self
+    }
+
+    /// Adds a stage that enriches the DaaS documents and may fail, (e.g.: a lookup of reference data)
+    pub fn enrich<F>(mut self, f: F) -> Pipeline
+    where
+        F: Fn(&mut DaaSDoc) -> Result<(), DaaSProcessingError> + Send + Sync + 'static,
+    {
+        self.stages.push(Box::new(move |mut doc| {
+            f(&mut doc)?;
+            Ok(Some(doc))
+        }));
+        self
+    }
+
+    /// Sets the sink that receives the transformed DaaS documents
+    pub fn sink<K: DaaSDocSink + Send + Sync + 'static>(mut self, sink: K) -> Pipeline {
+        self.sink = Some(Box::new(sink));
+        self
+    }
+
+    /// Passes the DaaS document through the stages to the sink.
+    /// Returns false if the DaaS document was dropped by a stage.
+    ///
+    /// # Arguments
+    ///
+    /// * doc: DaaSDoc - The DaaS document to process.</br>
+    pub fn run(&self, doc: DaaSDoc) -> Result<bool, DaaSProcessingError> {
+        let mut current = doc;
+
+        for stage in self.stages.iter() {
+            current = match stage(current)? {
+                Some(d) => d,
+                None => return Ok(false),
+            };
+        }
+
+        if let Some(sink) = &self.sink {
+            sink.send(current)?;
+        }
+
+        Ok(true)
+    }
+
+    /// Sends the DaaS documents that the sink has buffered
+    pub fn flush(&self) -> Result<(), DaaSProcessingError> {
+        match &self.sink {
+            Some(sink) => sink.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl DaaSDocHandler for Pipeline {
+    fn handle(
+        &self,
+        msg: DaaSProcessorMessage,
+        _client: Option<KafkaClient>,
+    ) -> Result<i32, DaaSProcessingError> {
+        match self.run(msg.doc)? {
+            true => Ok(1),
+            false => Ok(0),
+        }
+    }
+
+    fn on_shutdown(&self) {
+        if let Err(err) = self.flush() {
+            error!("Could not flush the sink of the pipeline. Error: {:?}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::memory::InMemoryStorage;
+    use crate::testing;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    #[test]
+    fn test_run_to_storage() {
+        let storage = InMemoryStorage::new();
+        let pipeline = Pipeline::new()
+            .enrich(|doc| {
+                doc.add_meta("region".to_string(), "east".to_string());
+                Ok(())
+            })
+            .sink(StorageSink::new(storage.clone()));
+        let doc = testing::get_default_daas_doc();
+
+        assert!(pipeline.run(doc.clone()).unwrap());
+        let mut saved = storage.get_doc_by_id(doc._id, None).unwrap();
+        assert_eq!(saved.get_meta("region".to_string()), "east".to_string());
+    }
+
+    #[test]
+    fn test_run_filtered() {
+        let storage = InMemoryStorage::new();
+        let pipeline = Pipeline::new()
+            .filter(|doc| doc.has_tag("priority".to_string()))
+            .sink(StorageSink::new(storage.clone()));
+        let doc = testing::get_default_daas_doc();
+
+        assert!(!pipeline.run(doc.clone()).unwrap());
+        assert!(storage.get_doc_by_id(doc._id, None).is_err());
+    }
+
+    #[test]
+    fn test_run_enrich_error() {
+        let pipeline = Pipeline::new().enrich(|_doc| Err(DaaSProcessingError::RetrieveError));
+
+        assert!(pipeline.run(testing::get_default_daas_doc()).is_err());
+    }
+
+    #[test]
+    fn test_webhook_sink() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0; 8192];
+            let len = stream.read(&mut buf).unwrap();
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n")
+                .unwrap();
+            String::from_utf8_lossy(&buf[..len]).to_string()
+        });
+
+        let sink = WebhookSink::new(url, Duration::from_secs(5));
+        assert!(sink.send(testing::get_default_daas_doc()).is_ok());
+        assert!(server.join().unwrap().starts_with("POST /hook"));
+    }
+}
@@ -1,24 +1,299 @@
 use super::*;
+use crate::config::DaasConfig;
+use crate::doc::deidentify::DeidentificationRules;
 use crate::doc::*;
 use crate::errors::daaserror::DaaSProcessingError;
 use crate::eventing::broker::{DaaSKafkaBroker, DaaSKafkaProcessor};
+use crate::eventing::headers::DaaSMessageHeaders;
+use crate::eventing::ledger::FanOutLedger;
+use crate::eventing::redact::RedactingBroker;
+#[cfg(feature = "s3")]
 use crate::storage::s3::*;
+use crate::storage::{CreateOutcome, ObjectStore};
 use futures::executor::block_on;
+use futures::stream::{self, StreamExt};
 use kafka::client::KafkaClient;
 use kafka::consumer::{Consumer, FetchOffset, GroupOffsetStorage};
-use rusoto_s3::StreamingBody;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::sync::Arc;
 use std::thread;
+use tokio::runtime::Runtime;
 
 pub struct DaaSProcessorMessage<'a> {
     pub offset: i64,
     pub key: &'a [u8],
     pub doc: DaaSDoc,
     pub topic: &'a str,
+    /// The partition of the topic that the message came from, (always 0 for the InMemoryBroker)
+    pub partition: i32,
+    /// The headers of the message, (see `daas::eventing::headers`)
+    pub headers: DaaSMessageHeaders,
+}
+
+/// The function that processes the DaaS document of a message
+pub type DaaSProcessorCallback<T> =
+    fn(DaaSProcessorMessage, Option<KafkaClient>, Option<&T>) -> Result<i32, DaaSProcessingError>;
+
+/// Handles the DaaS documents of the messages and the lifecycle of a processor.
+/// Unlike a `DaaSProcessorCallback`, a handler can hold state, (e.g.: database pools or caches).
+/// A handler is passed to the processor as a `DaaSDocHandlerRef`, (a boxed handler can be converted using `Arc::from()`).
+pub trait DaaSDocHandler {
+    /// Called once before the processor starts listening
+    fn on_start(&self) {}
+
+    /// Processes the DaaS document of a message. The message is consumed when Ok is returned.
+    ///
+    /// # Arguments
+    ///
+    /// * msg: DaaSProcessorMessage - The message that has the DaaS document.</br>
+    /// * client: Option<KafkaClient> - The client of the broker that the message came from, (if there is one).</br>
+    fn handle(
+        &self,
+        msg: DaaSProcessorMessage,
+        client: Option<KafkaClient>,
+    ) -> Result<i32, DaaSProcessingError>;
+
+    /// Called when the DaaS document of a message could not be processed
+    ///
+    /// # Arguments
+    ///
+    /// * doc: &DaaSDoc - The DaaS document that could not be processed.</br>
+    /// * err: &DaaSProcessingError - The error that was returned by `handle()`.</br>
+    fn on_error(&self, _doc: &DaaSDoc, _err: &DaaSProcessingError) {}
+
+    /// Called once after the processor stops listening
+    fn on_shutdown(&self) {}
+}
+
+/// Handles the DaaS documents of the messages asynchronously, so that handlers can await HTTP, S3 or database calls.
+/// The messages of a poll are handled concurrently on a tokio runtime, (see `DaaSProcessor::start_listening_async()`).
+#[async_trait]
+pub trait DaaSAsyncDocHandler: Send + Sync {
+    /// Called once before the processor starts listening
+    async fn on_start(&self) {}
+
+    /// Processes the DaaS document of a message. The message is consumed when Ok is returned.
+    ///
+    /// # Arguments
+    ///
+    /// * msg: DaaSProcessorMessage - The message that has the DaaS document.</br>
+    async fn handle(&self, msg: DaaSProcessorMessage<'_>) -> Result<i32, DaaSProcessingError>;
+
+    /// Called when the DaaS document of a message could not be processed
+    ///
+    /// # Arguments
+    ///
+    /// * doc: &DaaSDoc - The DaaS document that could not be processed.</br>
+    /// * err: &DaaSProcessingError - The error that was returned by `handle()`.</br>
+    async fn on_error(&self, _doc: &DaaSDoc, _err: &DaaSProcessingError) {}
+
+    /// Called once after the processor stops listening
+    async fn on_shutdown(&self) {}
+}
+
+// Handles the messages using at most `concurrency` handlers at a time and
+// returns if each message was processed, (in the order of the messages)
+pub(crate) async fn handle_concurrently(
+    handler: &dyn DaaSAsyncDocHandler,
+    msgs: Vec<DaaSProcessorMessage<'_>>,
+    concurrency: usize,
+) -> Vec<bool> {
+    stream::iter(msgs)
+        .map(|msg| async move {
+            let doc = msg.doc.clone_envelope();
+            let topic = msg.topic.to_string();
+            let offset = msg.offset;
+
+            match handler.handle(msg).await {
+                Ok(_i) => true,
+                Err(err) => {
+                    warn!(
+                        "Could not process the DaasDoc {} [topic:{}, offset:{}]. Error: {:?}",
+                        doc._id, topic, offset, err
+                    );
+                    handler.on_error(&doc, &err).await;
+                    false
+                }
+            }
+        })
+        .buffered(concurrency.max(1))
+        .collect()
+        .await
+}
+
+/// Selects the DaaS documents that are passed to a handler. The criteria that are set must all match.
+/// The other DaaS documents are consumed without being handled and are counted, (see `filtered_count()`).
+#[derive(Clone, Default)]
+pub struct DaaSDocFilter {
+    /// The category of the DaaS documents
+    pub category: Option<String>,
+    /// The subcategory of the DaaS documents
+    pub subcategory: Option<String>,
+    /// The tags that the DaaS documents must have
+    pub tags: Vec<String>,
+    /// The metadata keys that the DaaS documents must have
+    pub meta_keys: Vec<String>,
+    /// The author of the DaaS documents
+    pub author: Option<String>,
+    filtered: Arc<AtomicUsize>,
+}
+
+impl DaaSDocFilter {
+    /// Constructs a DaaSDocFilter that matches all the DaaS documents
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// extern crate daas;
+    ///
+    /// use daas::service::processor::DaaSDocFilter;
+    /// use daas::testing;
+    ///
+    /// fn main() {
+    ///     let filter = DaaSDocFilter::new()
+    ///         .with_category("order".to_string())
+    ///         .with_tag("priority".to_string());
+    ///     let mut doc = testing::get_default_daas_doc();
+    ///
+    ///     assert!(!filter.matches(&doc));
+    ///     doc.add_tag("priority".to_string());
+    ///     assert!(filter.matches(&doc));
+    /// }
+    /// ```
+    pub fn new() -> DaaSDocFilter {
+        DaaSDocFilter::default()
+    }
+
+    /// Only matches the DaaS documents of the category
+    pub fn with_category(mut self, category: String) -> DaaSDocFilter {
+        self.category = Some(category);
+        self
+    }
+
+    /// Only matches the DaaS documents of the subcategory
+    pub fn with_subcategory(mut self, subcategory: String) -> DaaSDocFilter {
+        self.subcategory = Some(subcategory);
+        self
+    }
+
+    /// Only matches the DaaS documents that have the tag
+    pub fn with_tag(mut self, tag: String) -> DaaSDocFilter {
+        self.tags.push(tag);
+        self
+    }
+
+    /// Only matches the DaaS documents that have the metadata key
+    pub fn with_meta_key(mut self, key: String) -> DaaSDocFilter {
+        self.meta_keys.push(key);
+        self
+    }
+
+    /// Only matches the DaaS documents of the author
+    pub fn with_author(mut self, author: String) -> DaaSDocFilter {
+        self.author = Some(author);
+        self
+    }
+
+    /// Determines if the DaaS document matches all the criteria of the filter
+    ///
+    /// # Arguments
+    ///
+    /// * doc: &DaaSDoc - The DaaS document to check.</br>
+    pub fn matches(&self, doc: &DaaSDoc) -> bool {
+        self.category.iter().all(|c| *c == doc.category)
+            && self.subcategory.iter().all(|s| *s == doc.subcategory)
+            && self.author.iter().all(|a| *a == doc.author)
+            && self.tags.iter().all(|t| doc.tags.contains(t))
+            && self.meta_keys.iter().all(|k| doc.meta_data.contains_key(k))
+    }
+
+    /// Returns the number of DaaS documents that didn't match the filter, (shared by the clones of the filter)
+    pub fn filtered_count(&self) -> usize {
+        self.filtered.load(Ordering::Relaxed)
+    }
+
+    /// Wraps the handler so that it is only passed the DaaS documents that match the filter
+    ///
+    /// # Arguments
+    ///
+    /// * handler: DaaSDocHandlerRef - The handler that processes the matching DaaS documents.</br>
+    pub fn handler(&self, handler: DaaSDocHandlerRef) -> DaaSDocHandlerRef {
+        Arc::new(FilteredHandler {
+            filter: self.clone(),
+            handler,
+        })
+    }
+}
+
+// Passes the DaaS documents that match the filter to the handler
+struct FilteredHandler {
+    filter: DaaSDocFilter,
+    handler: DaaSDocHandlerRef,
+}
+
+impl DaaSDocHandler for FilteredHandler {
+    fn on_start(&self) {
+        self.handler.on_start();
+    }
+
+    fn handle(
+        &self,
+        msg: DaaSProcessorMessage,
+        client: Option<KafkaClient>,
+    ) -> Result<i32, DaaSProcessingError> {
+        match self.filter.matches(&msg.doc) {
+            true => self.handler.handle(msg, client),
+            false => {
+                debug!("Filtered out DaaS document {}.", msg.doc._id);
+                self.filter.filtered.fetch_add(1, Ordering::Relaxed);
+                Ok(0)
+            }
+        }
+    }
+
+    fn on_error(&self, doc: &DaaSDoc, err: &DaaSProcessingError) {
+        self.handler.on_error(doc, err);
+    }
+
+    fn on_shutdown(&self) {
+        self.handler.on_shutdown();
+    }
+}
+
+/// A handler that can be shared with the threads of the processors
+pub type DaaSDocHandlerRef = Arc<dyn DaaSDocHandler + Send + Sync>;
+
+// Handles the messages using a DaaSProcessorCallback, so that callbacks and handlers share the same processing
+pub(crate) struct CallbackHandler<'a, T> {
+    pub(crate) o: Option<&'a T>,
+    pub(crate) callback: DaaSProcessorCallback<T>,
+}
+
+impl<'a, T> DaaSDocHandler for CallbackHandler<'a, T> {
+    fn handle(
+        &self,
+        msg: DaaSProcessorMessage,
+        client: Option<KafkaClient>,
+    ) -> Result<i32, DaaSProcessingError> {
+        (self.callback)(msg, client, self.o)
+    }
 }
 
 pub trait DaaSProcessorService {
     fn keep_listening(rx: &Receiver<bool>) -> bool;
+    /// Processes the messages of the consumer using the handler until a message is sent to stop listening
+    ///
+    /// # Arguments
+    ///
+    /// * consumer: Consumer - The consumer of the topics.</br>
+    /// * rx: &Receiver<bool> - The receiver of the message to stop listening.</br>
+    /// * handler: DaaSDocHandlerRef - The handler that processes the DaaS documents.</br>
+    fn start_listening_with_handler(
+        consumer: Consumer,
+        rx: &Receiver<bool>,
+        handler: DaaSDocHandlerRef,
+    );
     fn start_listening<T>(
         consumer: Consumer,
         rx: &Receiver<bool>,
@@ -35,82 +310,114 @@ pub trait DaaSProcessorService {
 #[async_trait]
 pub trait DaaSGenesisProcessorService {
     fn default_topics(doc: &DaaSDoc) -> Vec<String> {
+        let config = DaasConfig::from_env();
         let mut topics = Vec::new();
-        topics.push(DaaSKafkaBroker::make_topic(doc.clone()));
-        topics.push(doc.category.clone());
-        topics.push(format!("{}.{}", doc.category, doc.subcategory));
-        topics.push(doc.source_name.clone());
+        topics.push(DaaSKafkaBroker::make_topic(doc.clone_envelope()));
+        topics.push(config.topic(&doc.category));
+        topics.push(config.topic(&format!("{}.{}", doc.category, doc.subcategory)));
+        topics.push(config.topic(&doc.source_name));
 
         topics
     }
 
+    /// Returns the topics with the topic prefix of the environment, (see `DaasConfig`)
+    fn prefix_topics(topics: Vec<String>) -> Vec<String> {
+        let config = DaasConfig::from_env();
+        topics.iter().map(|t| config.topic(t)).collect()
+    }
+
     fn broker_document(
         client: KafkaClient,
-        doc: DaaSDoc,
+        mut doc: DaaSDoc,
         send_to: Option<Vec<String>>,
     ) -> Result<i32, DaaSProcessingError> {
         let hosts = client.hosts().to_vec();
 
         // if a send to topic is not provided, then use the default topics
         let topics = match send_to {
-            Some(t) => t,
+            Some(t) => Self::prefix_topics(t),
             None => {
                 let v = Self::default_topics(&doc);
                 v
             }
         };
 
-        for topic in topics.iter() {
-            match DaaSKafkaBroker::broker_message_with_client(
-                KafkaClient::new(hosts.clone()),
-                &mut doc.clone(),
-                &topic.clone(),
-            ) {
-                Ok(_v) => {}
-                Err(e) => {
-                    error!("Failed to broker message to {:?}. Error: {:?}", topic, e);
-                    return Err(DaaSProcessingError::BrokerError);
-                }
+        // a single broker so that the producer is reused and the DaaS document is serialized once for all the topics
+        let broker = DaaSKafkaBroker::new(hosts);
+
+        match broker.broker_message_to_topics(&mut doc, &topics) {
+            Ok(_v) => Ok(1),
+            Err(e) => {
+                error!("Failed to broker message to {:?}. Error: {:?}", topics, e);
+                Err(DaaSProcessingError::BrokerError)
             }
         }
+    }
 
-        Ok(1)
+    /// Brokers the DaaS document to the topics (default: `default_topics()`) using the fan-out ledger,
+    /// so a fan-out that stops midway is completed by `FanOutLedger::recover()`
+    ///
+    /// # Arguments
+    ///
+    /// * client: KafkaClient - The client of the Kafka brokers.</br>
+    /// * doc: DaaSDoc - The DaaS document to broker.</br>
+    /// * send_to: Option<Vec<String>> - The topics to broker the DaaS document to.</br>
+    /// * ledger: &FanOutLedger - The ledger that records the delivery of each topic.</br>
+    fn broker_document_with_ledger(
+        client: KafkaClient,
+        doc: DaaSDoc,
+        send_to: Option<Vec<String>>,
+        ledger: &FanOutLedger,
+    ) -> Result<i32, DaaSProcessingError> {
+        let topics = send_to
+            .map(Self::prefix_topics)
+            .unwrap_or_else(|| Self::default_topics(&doc));
+        let broker = DaaSKafkaBroker::new(client.hosts().to_vec());
+
+        match ledger.fan_out(&broker, &doc, topics) {
+            Ok(_) => Ok(1),
+            Err(e) => {
+                error!(
+                    "Failed to fan out DaaS document {}. Error: {:?}",
+                    doc._id, e
+                );
+                Err(DaaSProcessingError::BrokerError)
+            }
+        }
     }
 
-    fn provision_document<
-        'a,
-        T: S3BucketManager + Clone + std::marker::Send + std::marker::Sync,
-    >(
+    fn provision_document<'a, T: ObjectStore + std::marker::Send + std::marker::Sync>(
         mut msg: DaaSProcessorMessage<'a>,
         client: Option<KafkaClient>,
-        s3_bucket: Option<&T>,
+        store: Option<&T>,
     ) -> Result<i32, DaaSProcessingError> {
         //let send_to_topic: Option<&str> = Some("newbie");
+        mark_provisioned(&mut msg.doc);
 
-        // 1. Store the DaaSDoc in S3 Bucket
-        info!("Putting document {} in S3", msg.doc._id);
-
-        let content: StreamingBody = msg.doc.serialize().into_bytes().into();
+        // 1. Store the DaaSDoc in the object store (e.g.: S3 Bucket)
+        info!("Putting document {} in the object store", msg.doc._id);
 
-        match s3_bucket
+        // the key is deterministic, so a redelivered message never replaces the object that was provisioned,
+        // but the DaaS document is still brokered in case the previous delivery stopped before brokering it
+        match store
             .unwrap()
-            .clone()
-            .upload_file(format!("{}/{}.daas", msg.topic, msg.doc._id), content)
+            .create_daas_doc(provision_key(msg.topic, &msg.doc), &msg.doc)
         {
-            Ok(_s) => {
+            Ok(outcome) => {
                 // 2. Broker the DaaSDoc if a Client is provided and use dynamic topic
                 match client {
                     Some(clnt) => {
                         info!("Brokering document {} ... ", msg.doc._id);
                         // this needs to await this call
-                        Self::broker_document(clnt, msg.doc.clone(), None)
+                        Self::broker_document(clnt, msg.doc, None)
+                            .map(|_| provision_outcome(outcome))
                     }
-                    None => Ok(1),
+                    None => Ok(provision_outcome(outcome)),
                 }
             }
             Err(e) => {
                 error!(
-                    "Could not place DaasDoc {} in S3 storage. Error: {:?}",
+                    "Could not place DaasDoc {} in the object store. Error: {:?}",
                     msg.doc._id, e
                 );
                 return Err(DaaSProcessingError::UpsertError);
@@ -118,17 +425,93 @@ pub trait DaaSGenesisProcessorService {
         }
     }
 
+    /// Brokers a redacted copy of the DaaS document to the topics (default: `default_topics()`), so the sensitive
+    /// fields don't reach the consumers of the analytics topics
+    ///
+    /// # Arguments
+    ///
+    /// * client: KafkaClient - The client of the Kafka brokers.</br>
+    /// * doc: DaaSDoc - The DaaS document to broker.</br>
+    /// * send_to: Option<Vec<String>> - The topics to broker the redacted copy to.</br>
+    /// * rules: &DeidentificationRules - Which fields and metadata are removed.</br>
+    fn broker_redacted_document(
+        client: KafkaClient,
+        mut doc: DaaSDoc,
+        send_to: Option<Vec<String>>,
+        rules: &DeidentificationRules,
+    ) -> Result<i32, DaaSProcessingError> {
+        let topics = send_to
+            .map(Self::prefix_topics)
+            .unwrap_or_else(|| Self::default_topics(&doc));
+        let broker =
+            RedactingBroker::new(DaaSKafkaBroker::new(client.hosts().to_vec()), rules.clone());
+
+        match broker.broker_message_to_topics(&mut doc, &topics) {
+            Ok(_v) => Ok(1),
+            Err(e) => {
+                error!("Failed to broker message to {:?}. Error: {:?}", topics, e);
+                Err(DaaSProcessingError::BrokerError)
+            }
+        }
+    }
+
+    /// Provisions the full DaaS document to the object store and brokers a redacted copy to the default topics
+    fn provision_redacted_document<'a, T: ObjectStore + std::marker::Send + std::marker::Sync>(
+        mut msg: DaaSProcessorMessage<'a>,
+        client: Option<KafkaClient>,
+        publishing: Option<&RedactedPublishing<T>>,
+    ) -> Result<i32, DaaSProcessingError> {
+        let publishing = publishing.unwrap();
+        mark_provisioned(&mut msg.doc);
+
+        match publishing
+            .store
+            .create_daas_doc(provision_key(msg.topic, &msg.doc), &msg.doc)
+        {
+            Ok(outcome) => match client {
+                Some(clnt) => {
+                    info!("Brokering a redacted copy of document {} ... ", msg.doc._id);
+                    Self::broker_redacted_document(clnt, msg.doc, None, &publishing.rules)
+                        .map(|_| provision_outcome(outcome))
+                }
+                None => Ok(provision_outcome(outcome)),
+            },
+            Err(e) => {
+                error!(
+                    "Could not place DaasDoc {} in the object store. Error: {:?}",
+                    msg.doc._id, e
+                );
+                Err(DaaSProcessingError::UpsertError)
+            }
+        }
+    }
+
+    /// Starts the Genesis processor provisioning the DaaS documents to the S3 bucket (requires the `s3` feature)
+    #[cfg(feature = "s3")]
     fn run(
         hosts: Vec<String>,
         fallback_offset: FetchOffset,
         group_offset: GroupOffsetStorage,
         bucket: S3BucketMngr,
+    ) -> Sender<bool> {
+        Self::run_with_store(hosts, fallback_offset, group_offset, bucket)
+    }
+
+    /// Starts the Genesis processor provisioning the DaaS documents to any object store,
+    /// (e.g.: LocalStorage for running the pipeline without an AWS account, or a ManifestedStore for writing the
+    /// daily manifests of the provisioned DaaS documents)
+    fn run_with_store<T: ObjectStore + std::marker::Send + std::marker::Sync + 'static>(
+        hosts: Vec<String>,
+        fallback_offset: FetchOffset,
+        group_offset: GroupOffsetStorage,
+        store: T,
     ) -> Sender<bool> {
         let (tx, rx) = channel();
+        let config = DaasConfig::from_env();
         let consumer = Consumer::from_hosts(hosts)
-            .with_topic("genesis".to_string())
+            .with_topic(config.topic("genesis"))
             .with_fallback_offset(fallback_offset)
-            .with_group("genesis-consumers".to_string())
+            .with_group(config.topic("genesis-consumers"))
             .with_offset_storage(group_offset)
             .create()
             .unwrap();
@@ -137,7 +520,7 @@ pub trait DaaSGenesisProcessorService {
             DaaSProcessor::start_listening(
                 consumer,
                 &rx,
-                Some(&bucket),
+                Some(&store),
                 DaasGenesisProcessor::provision_document,
             );
         });
@@ -145,11 +528,101 @@ pub trait DaaSGenesisProcessorService {
         tx
     }
 
+    /// Starts the Genesis processor provisioning the full DaaS documents to the object store (the archival path)
+    /// while only redacted copies are brokered to the analytics topics
+    ///
+    /// # Arguments
+    ///
+    /// * hosts: Vec<String> - The hosts of the Kafka brokers.</br>
+    /// * fallback_offset: FetchOffset - Where to start consuming when there is no committed offset.</br>
+    /// * group_offset: GroupOffsetStorage - Where the offsets of the consumer group are stored.</br>
+    /// * store: T - The object store of the full DaaS documents.</br>
+    /// * rules: DeidentificationRules - Which fields and metadata are removed from the brokered copies.</br>
+    fn run_with_redaction<T: ObjectStore + std::marker::Send + std::marker::Sync + 'static>(
+        hosts: Vec<String>,
+        fallback_offset: FetchOffset,
+        group_offset: GroupOffsetStorage,
+        store: T,
+        rules: DeidentificationRules,
+    ) -> Sender<bool> {
+        let (tx, rx) = channel();
+        let config = DaasConfig::from_env();
+        let consumer = Consumer::from_hosts(hosts)
+            .with_topic(config.topic("genesis"))
+            .with_fallback_offset(fallback_offset)
+            .with_group(config.topic("genesis-consumers"))
+            .with_offset_storage(group_offset)
+            .create()
+            .unwrap();
+        let publishing = RedactedPublishing { store, rules };
+
+        let _handler = thread::spawn(move || {
+            DaaSProcessor::start_listening(
+                consumer,
+                &rx,
+                Some(&publishing),
+                DaasGenesisProcessor::provision_redacted_document,
+            );
+        });
+
+        tx
+    }
+
     fn stop(tx: Sender<bool>) {
         DaaSProcessor::stop_listening(&tx);
     }
 }
 
+// Changes the status of the consumed DaaS document, (which has been brokered) to Provisioned
+/// Represents the outcome of provisioning a DaaS document, (returned as the `i32` of the Genesis processor's callbacks)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProvisionOutcome {
+    /// The DaaS document was placed in the object store
+    Provisioned = 1,
+    /// The revision of the DaaS document was already in the object store, (e.g.: the message was redelivered)
+    AlreadyProvisioned = 2,
+}
+
+// The key of the object of the revision of the DaaS document, (e.g.: "genesis/order~clothing~iStore~5000~0.daas")
+fn provision_key(topic: &str, doc: &DaaSDoc) -> String {
+    format!(
+        "{}/{}{}{}.daas",
+        topic,
+        doc._id,
+        DELIMITER,
+        doc._rev.as_deref().unwrap_or("0")
+    )
+}
+
+// Returns the code of the provisioning outcome of the object store's outcome
+fn provision_outcome(outcome: CreateOutcome) -> i32 {
+    match outcome {
+        CreateOutcome::Created => ProvisionOutcome::Provisioned as i32,
+        CreateOutcome::AlreadyExists => ProvisionOutcome::AlreadyProvisioned as i32,
+    }
+}
+
+fn mark_provisioned(doc: &mut DaaSDoc) {
+    if doc
+        .set_status(DocStatus::Brokered)
+        .and_then(|_| doc.set_status(DocStatus::Provisioned))
+        .is_err()
+    {
+        warn!(
+            "DaaS document {} is provisioned with the status {:?}.",
+            doc._id, doc.status
+        );
+    }
+}
+
+/// Represents the object store of the full DaaS documents and the rules for redacting the brokered copies
+pub struct RedactedPublishing<T: ObjectStore> {
+    /// The object store of the full DaaS documents
+    pub store: T,
+    /// Which fields and metadata are removed from the brokered copies
+    pub rules: DeidentificationRules,
+}
+
 pub struct DaaSProcessor {}
 
 impl DaaSProcessorService for DaaSProcessor {
@@ -163,8 +636,16 @@ impl DaaSProcessorService for DaaSProcessor {
         }
     }
 
+    fn start_listening_with_handler(
+        consumer: Consumer,
+        rx: &Receiver<bool>,
+        handler: DaaSDocHandlerRef,
+    ) {
+        DaaSProcessor::listen(consumer, rx, handler.as_ref());
+    }
+
     fn start_listening<T>(
-        mut consumer: Consumer,
+        consumer: Consumer,
         rx: &Receiver<bool>,
         o: Option<&T>,
         callback: fn(
@@ -173,12 +654,115 @@ impl DaaSProcessorService for DaaSProcessor {
             Option<&T>,
         ) -> Result<i32, DaaSProcessingError>,
     ) {
+        DaaSProcessor::listen(consumer, rx, &CallbackHandler { o, callback });
+    }
+
+    fn stop_listening(controller: &Sender<bool>) {
+        controller.send(true).unwrap();
+    }
+}
+
+impl DaaSProcessor {
+    /// Processes the messages of the consumer using the asynchronous handler on a tokio runtime until a message is sent to stop listening.
+    /// The messages of each poll are handled concurrently, and are consumed in order once they have all been handled.
+    ///
+    /// # Arguments
+    ///
+    /// * consumer: Consumer - The consumer of the topics.</br>
+    /// * rx: &Receiver<bool> - The receiver of the message to stop listening.</br>
+    /// * handler: Arc<dyn DaaSAsyncDocHandler> - The handler that processes the DaaS documents.</br>
+    /// * concurrency: usize - The maximum number of messages that are handled at the same time.</br>
+    pub fn start_listening_async(
+        mut consumer: Consumer,
+        rx: &Receiver<bool>,
+        handler: Arc<dyn DaaSAsyncDocHandler>,
+        concurrency: usize,
+    ) {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(handler.on_start());
+
+        while DaaSProcessor::keep_listening(rx) {
+            for messageset in consumer.poll().unwrap().iter() {
+                let mut msgs = Vec::new();
+
+                for message in messageset.messages() {
+                    // the headers are read from the envelope, or derived from the DaaS document
+                    let (headers, value) = match DaaSMessageHeaders::from_envelope(message.value) {
+                        Some((h, v)) => (Some(h), v),
+                        None => (None, message.value),
+                    };
+                    let document = match DaaSDoc::from_serialized(value) {
+                        Ok(d) => d,
+                        Err(err) => {
+                            error!("Coud not create DaaSDoc. Error: {}", err);
+                            continue;
+                        }
+                    };
+
+                    // expired data must no longer be used, (see `DaaSProcessorService::start_listening()`)
+                    if document.is_expired() {
+                        info!(
+                            "Skipping expired DaaS document {} [topic:{}, offset:{}].",
+                            document._id,
+                            messageset.topic(),
+                            message.offset
+                        );
+                        if let Err(err) = consumer.consume_message(
+                            messageset.topic(),
+                            messageset.partition(),
+                            message.offset,
+                        ) {
+                            error!("{}", err);
+                        }
+                        continue;
+                    }
+
+                    msgs.push(DaaSProcessorMessage {
+                        offset: message.offset,
+                        key: message.key,
+                        headers: headers.unwrap_or_else(|| DaaSMessageHeaders::from_doc(&document)),
+                        doc: document,
+                        topic: messageset.topic(),
+                        partition: messageset.partition(),
+                    });
+                }
+
+                let offsets: Vec<i64> = msgs.iter().map(|m| m.offset).collect();
+                let processed =
+                    rt.block_on(handle_concurrently(handler.as_ref(), msgs, concurrency));
+
+                for (offset, _) in offsets.iter().zip(processed).filter(|(_, ok)| *ok) {
+                    if let Err(err) = consumer.consume_message(
+                        messageset.topic(),
+                        messageset.partition(),
+                        *offset,
+                    ) {
+                        error!("{}", err);
+                        panic!("{}", err);
+                    }
+                }
+            }
+            consumer.commit_consumed().unwrap();
+        }
+
+        rt.block_on(handler.on_shutdown());
+    }
+
+    // Processes the messages of the consumer using the handler until a message is sent to stop listening
+    fn listen(mut consumer: Consumer, rx: &Receiver<bool>, handler: &dyn DaaSDocHandler) {
+        handler.on_start();
+
         while DaaSProcessor::keep_listening(rx) {
             for messageset in consumer.poll().unwrap().iter() {
                 for message in messageset.messages() {
                     debug!("... {}", String::from_utf8(message.value.to_vec()).unwrap());
 
-                    let document = match DaaSDoc::from_serialized(message.value) {
+                    // the headers are read from the envelope, or derived from the DaaS document
+                    let (headers, value) = match DaaSMessageHeaders::from_envelope(message.value) {
+                        Some((h, v)) => (Some(h), v),
+                        None => (None, message.value),
+                    };
+                    let document = match DaaSDoc::from_serialized(value) {
                         Ok(d) => d,
                         Err(err) => {
                             error!("Coud not create DaaSDoc. Error: {}", err);
@@ -186,15 +770,36 @@ impl DaaSProcessorService for DaaSProcessor {
                             continue;
                         }
                     };
-                    match callback(
+
+                    // expired data must no longer be used, so it is consumed without being processed
+                    if document.is_expired() {
+                        info!(
+                            "Skipping expired DaaS document {} [topic:{}, offset:{}].",
+                            document._id,
+                            messageset.topic(),
+                            message.offset
+                        );
+                        if let Err(err) = consumer.consume_message(
+                            messageset.topic(),
+                            messageset.partition(),
+                            message.offset,
+                        ) {
+                            error!("{}", err);
+                        }
+                        continue;
+                    }
+
+                    match handler.handle(
                         DaaSProcessorMessage {
                             offset: message.offset,
                             key: message.key,
                             doc: document.clone(),
                             topic: messageset.topic(),
+                            partition: messageset.partition(),
+                            headers: headers
+                                .unwrap_or_else(|| DaaSMessageHeaders::from_doc(&document)),
                         },
                         Some(KafkaClient::new(consumer.client().hosts().to_vec())),
-                        o,
                     ) {
                         Ok(_i) => {
                             match consumer.consume_message(
@@ -216,21 +821,18 @@ impl DaaSProcessorService for DaaSProcessor {
                                     messageset.partition(),
                                     message.offset,
                                     err);
+                            handler.on_error(&document, &err);
                         }
                     }
                 }
             }
             consumer.commit_consumed().unwrap();
         }
-    }
 
-    fn stop_listening(controller: &Sender<bool>) {
-        controller.send(true).unwrap();
+        handler.on_shutdown();
     }
 }
 
-impl DaaSProcessor {}
-
 pub struct DaasGenesisProcessor {}
 
 impl DaaSGenesisProcessorService for DaasGenesisProcessor {}
@@ -239,6 +841,7 @@ impl DaaSGenesisProcessorService for DaasGenesisProcessor {}
 mod test {
     use super::*;
     use crate::eventing::broker::{DaaSKafkaBroker, DaaSKafkaProcessor};
+    use crate::storage::local::LocalStorage;
     use pbd::dtc::Tracker;
     use pbd::dua::DUA;
     use rusoto_core::Region;
@@ -330,6 +933,91 @@ mod test {
         DaasGenesisProcessor::stop(stopper);
     }
 
+    #[test]
+    fn test_provision_document_local() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let store = LocalStorage::new(format!("./tmp/genesis-{}", rand::random::<u32>()));
+        let doc = get_default_daasdoc();
+        let msg = DaaSProcessorMessage {
+            offset: 0,
+            key: &[],
+            doc: doc.clone(),
+            topic: "genesis",
+            partition: 0,
+            headers: DaaSMessageHeaders::from_doc(&doc),
+        };
+
+        assert_eq!(
+            DaasGenesisProcessor::provision_document(msg, None, Some(&store)).unwrap(),
+            1
+        );
+
+        let path = store.get_object_path(provision_key("genesis", &doc));
+        let saved = DaaSDoc::from_serialized(&std::fs::read(path).unwrap()).unwrap();
+        assert_eq!(saved._id, doc._id);
+    }
+
+    #[test]
+    fn test_provision_document_redelivered() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let store = LocalStorage::new(format!("./tmp/genesis-{}", rand::random::<u32>()));
+        let doc = get_default_daasdoc();
+        let msg = |doc: DaaSDoc| DaaSProcessorMessage {
+            offset: 0,
+            key: &[],
+            headers: DaaSMessageHeaders::from_doc(&doc),
+            doc,
+            topic: "genesis",
+            partition: 0,
+        };
+
+        assert_eq!(
+            DaasGenesisProcessor::provision_document(msg(doc.clone()), None, Some(&store)).unwrap(),
+            ProvisionOutcome::Provisioned as i32
+        );
+        assert_eq!(
+            DaasGenesisProcessor::provision_document(msg(doc.clone()), None, Some(&store)).unwrap(),
+            ProvisionOutcome::AlreadyProvisioned as i32
+        );
+
+        let mut next = doc.clone();
+        next._rev = Some("1".to_string());
+        assert_eq!(
+            DaasGenesisProcessor::provision_document(msg(next), None, Some(&store)).unwrap(),
+            ProvisionOutcome::Provisioned as i32
+        );
+    }
+
+    #[test]
+    fn test_provision_redacted_document_keeps_full_copy() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let publishing = RedactedPublishing {
+            store: LocalStorage::new(format!("./tmp/genesis-{}", rand::random::<u32>())),
+            rules: DeidentificationRules::new().with_field("status".to_string()),
+        };
+        let doc = get_default_daasdoc();
+        let msg = DaaSProcessorMessage {
+            offset: 0,
+            key: &[],
+            doc: doc.clone(),
+            topic: "genesis",
+            partition: 0,
+            headers: DaaSMessageHeaders::from_doc(&doc),
+        };
+
+        assert_eq!(
+            DaasGenesisProcessor::provision_redacted_document(msg, None, Some(&publishing))
+                .unwrap(),
+            1
+        );
+
+        let path = publishing
+            .store
+            .get_object_path(provision_key("genesis", &doc));
+        let saved = DaaSDoc::from_serialized(&std::fs::read(path).unwrap()).unwrap();
+        assert_eq!(saved.data_obj, doc.data_obj);
+    }
+
     #[test]
     fn test_process_data() {
         let _ = env_logger::builder().is_test(true).try_init();
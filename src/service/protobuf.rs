@@ -0,0 +1,490 @@
+//! Decoding of the Protocol Buffers payloads, so the embedded and gRPC-native producers can send their data in the
+//! compact binary encoding.
+//!
+//! The messages are decoded using the descriptor sets of their `.proto` files, (the output of
+//! `protoc --include_imports --descriptor_set_out=order.desc order.proto`), so the listener doesn't need generated
+//! code for each message type. The `ProtobufToJson` transformer validates the `application/x-protobuf` payloads of
+//! a category against its message type and (optionally) transcodes them to JSON, using the JSON mapping of proto3,
+//! (e.g.: the keys are the lowerCamelCase JSON names, the 64-bit integers are strings and the bytes are base64).
+//! The message type can also be named by the producer in the `messageType` parameter of the content type,
+//! (e.g.: application/x-protobuf; messageType=shop.Order).
+//!
+//! The descriptor sets and the messages are decoded with `prost-reflect`, (a `DescriptorPool` and `DynamicMessage`),
+//! and transcoded with its serde JSON mapping, so the well-known types have their special JSON mappings,
+//! (e.g.: a `google.protobuf.Timestamp` is an RFC 3339 string). The messages that are missing a required field, (of
+//! a proto2 message type), are rejected as well.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! extern crate daas;
+//!
+//! use daas::service::protobuf::{ProtoDescriptors, ProtobufToJson};
+//! use daas::service::transform::Transformations;
+//!
+//! fn main() {
+//!     let descriptors = ProtoDescriptors::from_file("./proto/order.desc").unwrap();
+//!     let transformations = Transformations::new().with_transformer(
+//!         "order".to_string(),
+//!         Box::new(ProtobufToJson::new(descriptors, "shop.Order".to_string())),
+//!     );
+//! }
+//! ```
+
+use super::convert::JSON_CONTENT_TYPE;
+use super::transform::{PayloadTransformer, RawPayload};
+use super::*;
+use prost_reflect::{
+    Cardinality, DescriptorPool, DynamicMessage, ReflectMessage, Value as ProtoValue,
+};
+use serde_json::Value;
+use std::fs;
+
+// Returns the full name of the first required field that is missing from the message or its nested messages
+fn missing_required(message: &DynamicMessage) -> Option<String> {
+    let descriptor = message.descriptor();
+    if let Some(field) = descriptor
+        .fields()
+        .find(|f| f.cardinality() == Cardinality::Required && !message.has_field(f))
+    {
+        return Some(field.full_name().to_string());
+    }
+
+    message.fields().find_map(|(_field, value)| match value {
+        ProtoValue::Message(m) => missing_required(m),
+        ProtoValue::List(values) => values
+            .iter()
+            .find_map(|v| v.as_message().and_then(missing_required)),
+        ProtoValue::Map(entries) => entries
+            .values()
+            .find_map(|v| v.as_message().and_then(missing_required)),
+        _ => None,
+    })
+}
+
+/// Represents the message and enum types of a descriptor set
+#[derive(Debug, Clone, Default)]
+pub struct ProtoDescriptors {
+    pool: DescriptorPool,
+}
+
+impl ProtoDescriptors {
+    /// Constructs the ProtoDescriptors from an encoded descriptor set, (a FileDescriptorSet)
+    ///
+    /// # Arguments
+    ///
+    /// * data: &[u8] - The descriptor set, (e.g.: the output of protoc --descriptor_set_out).</br>
+    pub fn from_bytes(data: &[u8]) -> Result<ProtoDescriptors, TransformError> {
+        match DescriptorPool::decode(data) {
+            Ok(pool) => Ok(ProtoDescriptors { pool }),
+            Err(err) => {
+                error!("Could not read the descriptor set. Error: {}", err);
+                Err(TransformError)
+            }
+        }
+    }
+
+    /// Constructs the ProtoDescriptors from the file of a descriptor set
+    ///
+    /// # Arguments
+    ///
+    /// * path: &str - The path of the descriptor set.</br>
+    pub fn from_file(path: &str) -> Result<ProtoDescriptors, TransformError> {
+        match fs::read(path) {
+            Ok(data) => ProtoDescriptors::from_bytes(&data),
+            Err(err) => {
+                error!("Could not read the descriptor set {}. Error: {}", path, err);
+                Err(TransformError)
+            }
+        }
+    }
+
+    /// Determines if the descriptor set has the message type
+    ///
+    /// # Arguments
+    ///
+    /// * message_type: &str - The full name of the message type, (e.g.: shop.Order).</br>
+    pub fn has_message(&self, message_type: &str) -> bool {
+        self.pool
+            .get_message_by_name(message_type.trim_start_matches('.'))
+            .is_some()
+    }
+
+    /// Decodes the message to JSON, validating it against the message type
+    ///
+    /// # Arguments
+    ///
+    /// * message_type: &str - The full name of the message type, (e.g.: shop.Order).</br>
+    /// * data: &[u8] - The encoded message.</br>
+    pub fn decode(&self, message_type: &str, data: &[u8]) -> Result<Value, TransformError> {
+        let descriptor = match self
+            .pool
+            .get_message_by_name(message_type.trim_start_matches('.'))
+        {
+            Some(d) => d,
+            None => {
+                warn!("The descriptor set has no message type {}.", message_type);
+                return Err(TransformError);
+            }
+        };
+
+        let message = match DynamicMessage::decode(descriptor, data) {
+            Ok(m) => m,
+            Err(err) => {
+                warn!(
+                    "Could not decode the protobuf message {}. Error: {}",
+                    message_type, err
+                );
+                return Err(TransformError);
+            }
+        };
+
+        if let Some(missing) = missing_required(&message) {
+            warn!(
+                "The protobuf message {} is missing the required field {}.",
+                message_type, missing
+            );
+            return Err(TransformError);
+        }
+
+        serde_json::to_value(&message).map_err(|err| {
+            warn!(
+                "Could not transcode the protobuf message {} to JSON. Error: {}",
+                message_type, err
+            );
+            TransformError
+        })
+    }
+}
+
+/// A transformer that validates the Protocol Buffers payloads and transcodes them to JSON
+pub struct ProtobufToJson {
+    /// The descriptors of the message types
+    pub descriptors: ProtoDescriptors,
+    /// The full name of the message type of the payloads, (e.g.: shop.Order)
+    pub message_type: String,
+    /// The indicator that represents if the payloads are transcoded to JSON, (default: true)
+    pub transcode: bool,
+}
+
+impl ProtobufToJson {
+    /// Constructs a ProtobufToJson transformer that transcodes the payloads to JSON
+    ///
+    /// # Arguments
+    ///
+    /// * descriptors: ProtoDescriptors - The descriptors of the message types.</br>
+    /// * message_type: String - The full name of the message type of the payloads, (e.g.: shop.Order).</br>
+    pub fn new(descriptors: ProtoDescriptors, message_type: String) -> ProtobufToJson {
+        ProtobufToJson {
+            descriptors,
+            message_type,
+            transcode: true,
+        }
+    }
+
+    /// Sets if the payloads are transcoded to JSON, or only validated and stored in the binary encoding
+    pub fn with_transcode(mut self, transcode: bool) -> ProtobufToJson {
+        self.transcode = transcode;
+        self
+    }
+
+    /// Determines if the content type is Protocol Buffers, (e.g.: application/x-protobuf)
+    pub fn is_protobuf(content_type: &str) -> bool {
+        let mime = content_type
+            .split(';')
+            .next()
+            .unwrap_or("")
+            .trim()
+            .to_lowercase();
+        mime == "application/x-protobuf"
+            || mime == "application/protobuf"
+            || mime == "application/vnd.google.protobuf"
+    }
+
+    // Returns the message type that is named in the content type, (e.g.: application/x-protobuf; messageType=shop.Order)
+    fn named_message_type(content_type: &str) -> Option<String> {
+        content_type.split(';').skip(1).find_map(|param| {
+            let mut pair = param.splitn(2, '=');
+            match (pair.next(), pair.next()) {
+                (Some(k), Some(v)) if k.trim().eq_ignore_ascii_case("messagetype") => {
+                    Some(v.trim().trim_matches('"').to_string())
+                }
+                _ => None,
+            }
+        })
+    }
+}
+
+impl PayloadTransformer for ProtobufToJson {
+    fn name(&self) -> String {
+        "protobuf-to-json".to_string()
+    }
+
+    fn transform(&self, payload: RawPayload) -> Result<RawPayload, TransformError> {
+        if !ProtobufToJson::is_protobuf(&payload.content_type) {
+            return Ok(payload);
+        }
+
+        let message_type = ProtobufToJson::named_message_type(&payload.content_type)
+            .unwrap_or_else(|| self.message_type.clone());
+        let value = self.descriptors.decode(&message_type, &payload.data)?;
+
+        match self.transcode {
+            true => Ok(RawPayload::new(
+                JSON_CONTENT_TYPE.to_string(),
+                serde_json::to_vec(&value).unwrap(),
+            )),
+            false => Ok(payload),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const WIRE_FIXED64: u64 = 1;
+    const WIRE_LEN: u64 = 2;
+
+    // the types and labels of the fields, (see FieldDescriptorProto)
+    const TYPE_DOUBLE: u64 = 1;
+    const TYPE_INT64: u64 = 3;
+    const TYPE_INT32: u64 = 5;
+    const TYPE_STRING: u64 = 9;
+    const TYPE_MESSAGE: u64 = 11;
+    const TYPE_BYTES: u64 = 12;
+    const TYPE_ENUM: u64 = 14;
+    const TYPE_SINT32: u64 = 17;
+    const LABEL_REQUIRED: u64 = 2;
+    const LABEL_REPEATED: u64 = 3;
+
+    // Encodes the fields of a message
+    fn varint(mut v: u64) -> Vec<u8> {
+        let mut out = Vec::new();
+        loop {
+            let byte = (v & 0x7f) as u8;
+            v >>= 7;
+            match v {
+                0 => {
+                    out.push(byte);
+                    return out;
+                }
+                _ => out.push(byte | 0x80),
+            }
+        }
+    }
+
+    fn field_varint(number: u64, v: u64) -> Vec<u8> {
+        let mut out = varint(number << 3);
+        out.extend(varint(v));
+        out
+    }
+
+    fn field_len(number: u64, bytes: &[u8]) -> Vec<u8> {
+        let mut out = varint((number << 3) | WIRE_LEN);
+        out.extend(varint(bytes.len() as u64));
+        out.extend_from_slice(bytes);
+        out
+    }
+
+    fn field_descriptor(
+        name: &str,
+        number: u64,
+        label: u64,
+        kind: u64,
+        type_name: &str,
+    ) -> Vec<u8> {
+        let mut out = field_len(1, name.as_bytes());
+        out.extend(field_varint(3, number));
+        out.extend(field_varint(4, label));
+        out.extend(field_varint(5, kind));
+        if !type_name.is_empty() {
+            out.extend(field_len(6, type_name.as_bytes()));
+        }
+        out
+    }
+
+    // The descriptor set of:
+    //   package shop;
+    //   message Order {
+    //     required string order_id = 1; repeated Item items = 2; Status status = 3; int64 total_cents = 4;
+    //     repeated int32 codes = 5; map<string, string> labels = 6; bytes receipt = 7; sint32 delta = 8;
+    //     enum Status { NEW = 0; SHIPPED = 1; }
+    //     message Item { string sku = 1; double price = 2; }
+    //     message LabelsEntry { option map_entry = true; string key = 1; string value = 2; }
+    //   }
+    fn get_descriptor_set() -> Vec<u8> {
+        let mut item = field_len(1, b"Item");
+        item.extend(field_len(
+            2,
+            &field_descriptor("sku", 1, 1, TYPE_STRING, ""),
+        ));
+        item.extend(field_len(
+            2,
+            &field_descriptor("price", 2, 1, TYPE_DOUBLE, ""),
+        ));
+
+        let mut entry = field_len(1, b"LabelsEntry");
+        entry.extend(field_len(
+            2,
+            &field_descriptor("key", 1, 1, TYPE_STRING, ""),
+        ));
+        entry.extend(field_len(
+            2,
+            &field_descriptor("value", 2, 1, TYPE_STRING, ""),
+        ));
+        entry.extend(field_len(7, &field_varint(7, 1)));
+
+        let mut status = field_len(1, b"Status");
+        let mut new = field_len(1, b"NEW");
+        new.extend(field_varint(2, 0));
+        let mut shipped = field_len(1, b"SHIPPED");
+        shipped.extend(field_varint(2, 1));
+        status.extend(field_len(2, &new));
+        status.extend(field_len(2, &shipped));
+
+        let mut order = field_len(1, b"Order");
+        order.extend(field_len(
+            2,
+            &field_descriptor("order_id", 1, LABEL_REQUIRED, TYPE_STRING, ""),
+        ));
+        order.extend(field_len(
+            2,
+            &field_descriptor("items", 2, LABEL_REPEATED, TYPE_MESSAGE, ".shop.Order.Item"),
+        ));
+        order.extend(field_len(
+            2,
+            &field_descriptor("status", 3, 1, TYPE_ENUM, ".shop.Order.Status"),
+        ));
+        order.extend(field_len(
+            2,
+            &field_descriptor("total_cents", 4, 1, TYPE_INT64, ""),
+        ));
+        order.extend(field_len(
+            2,
+            &field_descriptor("codes", 5, LABEL_REPEATED, TYPE_INT32, ""),
+        ));
+        order.extend(field_len(
+            2,
+            &field_descriptor(
+                "labels",
+                6,
+                LABEL_REPEATED,
+                TYPE_MESSAGE,
+                ".shop.Order.LabelsEntry",
+            ),
+        ));
+        order.extend(field_len(
+            2,
+            &field_descriptor("receipt", 7, 1, TYPE_BYTES, ""),
+        ));
+        order.extend(field_len(
+            2,
+            &field_descriptor("delta", 8, 1, TYPE_SINT32, ""),
+        ));
+        order.extend(field_len(3, &item));
+        order.extend(field_len(3, &entry));
+        order.extend(field_len(4, &status));
+
+        let mut file = field_len(1, b"order.proto");
+        file.extend(field_len(2, b"shop"));
+        file.extend(field_len(4, &order));
+
+        field_len(1, &file)
+    }
+
+    fn get_order() -> Vec<u8> {
+        let mut item = field_len(1, b"a-1");
+        item.extend(varint((2 << 3) | WIRE_FIXED64));
+        item.extend_from_slice(&9.5f64.to_le_bytes());
+
+        let mut label = field_len(1, b"channel");
+        label.extend(field_len(2, b"web"));
+
+        let mut order = field_len(1, b"5000");
+        order.extend(field_len(2, &item));
+        order.extend(field_varint(3, 1));
+        order.extend(field_varint(4, 1950));
+        // packed
+        let mut codes = varint(7);
+        codes.extend(varint(300));
+        order.extend(field_len(5, &codes));
+        order.extend(field_len(6, &label));
+        order.extend(field_len(7, b"hi"));
+        // the zigzag encoding of -2
+        order.extend(field_varint(8, 3));
+        // an unknown field
+        order.extend(field_varint(99, 1));
+        order
+    }
+
+    #[test]
+    fn test_decode() {
+        let descriptors = ProtoDescriptors::from_bytes(&get_descriptor_set()).unwrap();
+
+        assert!(descriptors.has_message("shop.Order"));
+        assert!(descriptors.has_message(".shop.Order.Item"));
+        assert_eq!(
+            descriptors.decode("shop.Order", &get_order()).unwrap(),
+            json!({
+                "orderId": "5000",
+                "items": [{"sku": "a-1", "price": 9.5}],
+                "status": "SHIPPED",
+                "totalCents": "1950",
+                "codes": [7, 300],
+                "labels": {"channel": "web"},
+                "receipt": "aGk=",
+                "delta": -2
+            })
+        );
+    }
+
+    #[test]
+    fn test_decode_invalid() {
+        let descriptors = ProtoDescriptors::from_bytes(&get_descriptor_set()).unwrap();
+
+        // the required order_id is missing
+        assert!(descriptors
+            .decode("shop.Order", &field_varint(3, 1))
+            .is_err());
+        // truncated
+        let order = get_order();
+        assert!(descriptors
+            .decode("shop.Order", &order[..order.len() - 6])
+            .is_err());
+        // the wire type of order_id is wrong
+        assert!(descriptors
+            .decode("shop.Order", &field_varint(1, 1))
+            .is_err());
+        assert!(descriptors.decode("shop.Unknown", &order).is_err());
+    }
+
+    #[test]
+    fn test_transform() {
+        let descriptors = ProtoDescriptors::from_bytes(&get_descriptor_set()).unwrap();
+        let transformer = ProtobufToJson::new(descriptors.clone(), "shop.Order".to_string());
+
+        let payload = RawPayload::new("application/x-protobuf".to_string(), get_order());
+        let transformed = transformer.transform(payload.clone()).unwrap();
+        assert_eq!(transformed.content_type, "application/json".to_string());
+        assert_eq!(
+            serde_json::from_slice::<Value>(&transformed.data).unwrap()["orderId"],
+            json!("5000")
+        );
+
+        // only validated
+        let transformer = transformer.with_transcode(false);
+        assert_eq!(transformer.transform(payload).unwrap().data, get_order());
+
+        // the message type is named in the content type
+        let item = RawPayload::new(
+            "application/x-protobuf; messageType=shop.Order.Item".to_string(),
+            field_len(1, b"a-1"),
+        );
+        assert!(transformer.transform(item).is_ok());
+
+        let json = RawPayload::new("application/json".to_string(), b"{}".to_vec());
+        assert_eq!(transformer.transform(json.clone()).unwrap(), json);
+    }
+}
@@ -0,0 +1,383 @@
+//! Governance of the categories and subcategories that the listener accepts, preventing the sprawl of topics and
+//! storage paths from typos, (e.g.: order/clothing vs. orders/clothes).
+//!
+//! When a `CategoryRegistry` is registered as application data, the listener rejects the requests for unknown
+//! categories or subcategories with 400 Bad Request. Each category has an owner, the Data Usage Agreements that are
+//! added to its DaaS documents, a retention class, (i.e.: how long its data is kept) and a schema of its payloads.
+//! The registry can be maintained in a JSON file, (see `CategoryRegistry::from_file`).
+//!
+//! The schemas are a subset of JSON Schema: `type`, `properties`, `required`, `items` and `enum`.
+//!
+//! # Examples
+//!
+//! ```
+//! extern crate daas;
+//! extern crate serde_json;
+//!
+//! use daas::service::registry::{CategoryRegistry, CategoryRule};
+//! use serde_json::json;
+//!
+//! fn main() {
+//!     let registry = CategoryRegistry::new()
+//!         .with_retention_class("short".to_string(), 86400)
+//!         .with_category(
+//!             "order".to_string(),
+//!             CategoryRule::new("sales@istore.com".to_string())
+//!                 .with_subcategory("clothing".to_string())
+//!                 .with_retention_class("short".to_string())
+//!                 .with_schema(json!({"type": "object", "required": ["status"]})),
+//!         );
+//!
+//!     assert!(registry.get_rule("order", "clothing").is_some());
+//!     assert!(registry.get_rule("orders", "clothing").is_none());
+//! }
+//! ```
+
+use super::*;
+use crate::doc::DaaSDoc;
+use pbd::dua::DUA;
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+
+/// The key of the metadata that holds the owner of the category of the DaaS document
+pub const CATEGORY_OWNER_META: &str = "category-owner";
+/// The key of the metadata that holds the retention class of the DaaS document
+pub const RETENTION_CLASS_META: &str = "retention-class";
+/// The subcategory that allows any subcategory of the category
+pub const ANY_SUBCATEGORY: &str = "*";
+
+/// Represents the governance rules of a category
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct CategoryRule {
+    /// The owner of the category, (e.g.: sales@istore.com)
+    pub owner: String,
+    /// The subcategories that are allowed, (`*` allows any subcategory)
+    #[serde(default)]
+    pub subcategories: BTreeSet<String>,
+    /// The Data Usage Agreements that are added to the DaaS documents, unless they already have an agreement with the
+    /// same name
+    #[serde(default)]
+    pub default_duas: Vec<DUA>,
+    /// The retention class of the DaaS documents, (see `CategoryRegistry::retention_classes`)
+    #[serde(default)]
+    pub retention_class: Option<String>,
+    /// The schema of the payloads
+    #[serde(default)]
+    pub schema: Option<Value>,
+}
+
+impl CategoryRule {
+    /// Constructs a CategoryRule without any subcategories
+    ///
+    /// # Arguments
+    ///
+    /// * owner: String - The owner of the category, (e.g.: sales@istore.com).</br>
+    pub fn new(owner: String) -> CategoryRule {
+        CategoryRule {
+            owner,
+            ..Default::default()
+        }
+    }
+
+    /// Allows the subcategory, (`*` allows any subcategory)
+    pub fn with_subcategory(mut self, subcategory: String) -> CategoryRule {
+        self.subcategories.insert(subcategory);
+        self
+    }
+
+    /// Adds a Data Usage Agreement that is added to the DaaS documents
+    pub fn with_default_dua(mut self, dua: DUA) -> CategoryRule {
+        self.default_duas.push(dua);
+        self
+    }
+
+    /// Sets the retention class of the DaaS documents
+    pub fn with_retention_class(mut self, retention_class: String) -> CategoryRule {
+        self.retention_class = Some(retention_class);
+        self
+    }
+
+    /// Sets the schema of the payloads
+    pub fn with_schema(mut self, schema: Value) -> CategoryRule {
+        self.schema = Some(schema);
+        self
+    }
+
+    /// Determines if the subcategory is allowed
+    pub fn allows(&self, subcategory: &str) -> bool {
+        self.subcategories.contains(subcategory) || self.subcategories.contains(ANY_SUBCATEGORY)
+    }
+
+    /// Validates the payload against the schema of the category, (any payload is valid when there is no schema)
+    ///
+    /// # Arguments
+    ///
+    /// * data: &[u8] - The payload, which must be JSON when there is a schema.</br>
+    pub fn validate_payload(&self, data: &[u8]) -> Result<(), ValidationError> {
+        let schema = match &self.schema {
+            Some(s) => s,
+            None => return Ok(()),
+        };
+
+        match serde_json::from_slice::<Value>(data) {
+            Ok(value) if matches_schema(schema, &value) => Ok(()),
+            _ => Err(ValidationError),
+        }
+    }
+}
+
+/// Represents the registry of the categories that the listener accepts
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct CategoryRegistry {
+    /// The governance rules of each category
+    #[serde(default)]
+    pub categories: BTreeMap<String, CategoryRule>,
+    /// How long the data of each retention class is kept (seconds after it is received)
+    #[serde(default)]
+    pub retention_classes: BTreeMap<String, u64>,
+}
+
+impl CategoryRegistry {
+    /// Constructs an empty CategoryRegistry, (no category is allowed)
+    pub fn new() -> CategoryRegistry {
+        CategoryRegistry::default()
+    }
+
+    /// Constructs the CategoryRegistry from a JSON file, (e.g.: {"categories": {"order": {"owner": "sales",
+    /// "subcategories": ["clothing"]}}, "retention_classes": {"short": 86400}})
+    ///
+    /// # Arguments
+    ///
+    /// * path: &str - The path of the JSON file.</br>
+    pub fn from_file(path: &str) -> Result<CategoryRegistry, RegistryError> {
+        let data = fs::read(path).map_err(|err| {
+            error!(
+                "Could not read the category registry {}. Error: {}",
+                path, err
+            );
+            RegistryError
+        })?;
+
+        serde_json::from_slice(&data).map_err(|err| {
+            error!(
+                "Could not parse the category registry {}. Error: {}",
+                path, err
+            );
+            RegistryError
+        })
+    }
+
+    /// Adds the governance rules of a category
+    pub fn with_category(mut self, category: String, rule: CategoryRule) -> CategoryRegistry {
+        self.categories.insert(category, rule);
+        self
+    }
+
+    /// Sets how long the data of a retention class is kept
+    pub fn with_retention_class(mut self, name: String, seconds: u64) -> CategoryRegistry {
+        self.retention_classes.insert(name, seconds);
+        self
+    }
+
+    /// Returns the governance rules of the category, or None if the category or subcategory isn't allowed
+    ///
+    /// # Arguments
+    ///
+    /// * category: &str - The category, (e.g.: order).</br>
+    /// * subcategory: &str - The subcategory, (e.g.: clothing).</br>
+    pub fn get_rule(&self, category: &str, subcategory: &str) -> Option<&CategoryRule> {
+        self.categories
+            .get(category)
+            .filter(|rule| rule.allows(subcategory))
+    }
+
+    /// Applies the governance rules of the category to the DaaS document: the owner, the default Data Usage
+    /// Agreements and the retention class, (when the retention class expires before the DaaS document does)
+    ///
+    /// # Arguments
+    ///
+    /// * doc: &mut DaaSDoc - The DaaS document.</br>
+    pub fn govern(&self, doc: &mut DaaSDoc) {
+        let rule = match self.get_rule(&doc.category, &doc.subcategory) {
+            Some(r) => r.clone(),
+            None => return,
+        };
+
+        for dua in rule.default_duas {
+            if !doc
+                .data_usage_agreements
+                .iter()
+                .any(|d| d.agreement_name == dua.agreement_name)
+            {
+                doc.data_usage_agreements.push(dua);
+            }
+        }
+        doc.add_meta(CATEGORY_OWNER_META.to_string(), rule.owner);
+
+        if let Some(class) = rule.retention_class {
+            if let Some(seconds) = self.retention_classes.get(&class) {
+                let expires_at = get_unix_now!() + seconds;
+                doc.expires_at = Some(doc.expires_at.map_or(expires_at, |e| e.min(expires_at)));
+            }
+            doc.add_meta(RETENTION_CLASS_META.to_string(), class);
+        }
+    }
+}
+
+// Determines if the value matches the schema, (a subset of JSON Schema)
+fn matches_schema(schema: &Value, value: &Value) -> bool {
+    let type_matches = |t: &Value| match t.as_str() {
+        Some("object") => value.is_object(),
+        Some("array") => value.is_array(),
+        Some("string") => value.is_string(),
+        Some("number") => value.is_number(),
+        Some("integer") => value.is_i64() || value.is_u64(),
+        Some("boolean") => value.is_boolean(),
+        Some("null") => value.is_null(),
+        _ => false,
+    };
+
+    let valid_type = match schema.get("type") {
+        Some(Value::Array(types)) => types.iter().any(type_matches),
+        Some(t) => type_matches(t),
+        None => true,
+    };
+    let valid_enum = match schema.get("enum").and_then(|e| e.as_array()) {
+        Some(values) => values.contains(value),
+        None => true,
+    };
+    let valid_required = match (schema.get("required").and_then(|r| r.as_array()), value) {
+        (Some(keys), Value::Object(obj)) => keys
+            .iter()
+            .filter_map(|k| k.as_str())
+            .all(|k| obj.contains_key(k)),
+        _ => true,
+    };
+    let valid_properties = match (schema.get("properties").and_then(|p| p.as_object()), value) {
+        (Some(props), Value::Object(obj)) => props
+            .iter()
+            .all(|(k, s)| obj.get(k).is_none_or(|v| matches_schema(s, v))),
+        _ => true,
+    };
+    let valid_items = match (schema.get("items"), value) {
+        (Some(s), Value::Array(items)) => items.iter().all(|v| matches_schema(s, v)),
+        _ => true,
+    };
+
+    valid_type && valid_enum && valid_required && valid_properties && valid_items
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use serde_json::json;
+
+    fn get_registry() -> CategoryRegistry {
+        CategoryRegistry::new()
+            .with_retention_class("short".to_string(), 60)
+            .with_category(
+                "order".to_string(),
+                CategoryRule::new("sales@istore.com".to_string())
+                    .with_subcategory("clothing".to_string())
+                    .with_default_dua(DUA::new(
+                        "governance".to_string(),
+                        "https://istore.com/dua/governance.pdf".to_string(),
+                        1553988607,
+                    ))
+                    .with_retention_class("short".to_string())
+                    .with_schema(json!({
+                        "type": "object",
+                        "required": ["status"],
+                        "properties": {
+                            "status": {"type": "string", "enum": ["new", "paid"]},
+                            "items": {"type": "array", "items": {"type": "integer"}}
+                        }
+                    })),
+            )
+            .with_category(
+                "music".to_string(),
+                CategoryRule::new("media@istore.com".to_string())
+                    .with_subcategory(ANY_SUBCATEGORY.to_string()),
+            )
+    }
+
+    #[test]
+    fn test_get_rule() {
+        let registry = get_registry();
+
+        assert!(registry.get_rule("order", "clothing").is_some());
+        assert!(registry.get_rule("order", "clothes").is_none());
+        assert!(registry.get_rule("orders", "clothing").is_none());
+        assert!(registry.get_rule("music", "vinyl").is_some());
+    }
+
+    #[test]
+    fn test_validate_payload() {
+        let registry = get_registry();
+        let rule = registry.get_rule("order", "clothing").unwrap();
+
+        assert!(rule
+            .validate_payload(br#"{"status": "new", "items": [1, 2]}"#)
+            .is_ok());
+        assert!(rule.validate_payload(br#"{"items": [1]}"#).is_err());
+        assert!(rule.validate_payload(br#"{"status": "lost"}"#).is_err());
+        assert!(rule
+            .validate_payload(br#"{"status": "new", "items": ["a"]}"#)
+            .is_err());
+        assert!(rule.validate_payload(b"status=new").is_err());
+        assert!(registry
+            .get_rule("music", "vinyl")
+            .unwrap()
+            .validate_payload(b"anything")
+            .is_ok());
+    }
+
+    #[test]
+    fn test_govern() {
+        let registry = get_registry();
+        let mut doc = testing::get_daas_doc(
+            "iStore".to_string(),
+            5000,
+            "order".to_string(),
+            "clothing".to_string(),
+        );
+        let agreements = doc.data_usage_agreements.len();
+
+        registry.govern(&mut doc);
+        assert_eq!(doc.data_usage_agreements.len(), agreements + 1);
+        assert_eq!(
+            doc.get_meta(CATEGORY_OWNER_META.to_string()),
+            "sales@istore.com".to_string()
+        );
+        assert_eq!(
+            doc.get_meta(RETENTION_CLASS_META.to_string()),
+            "short".to_string()
+        );
+        assert!(doc.expires_at.unwrap() <= get_unix_now!() + 60);
+
+        // the default agreements aren't added twice
+        registry.govern(&mut doc);
+        assert_eq!(doc.data_usage_agreements.len(), agreements + 1);
+    }
+
+    #[test]
+    fn test_from_file() {
+        let path = format!("./tmp/registry-{}.json", rand::random::<u32>());
+        fs::create_dir_all("./tmp").unwrap();
+        fs::write(
+            &path,
+            r#"{"categories": {"order": {"owner": "sales", "subcategories": ["clothing"]}}, "retention_classes": {"short": 86400}}"#,
+        )
+        .unwrap();
+
+        let registry = CategoryRegistry::from_file(&path).unwrap();
+        assert_eq!(
+            registry.get_rule("order", "clothing").unwrap().owner,
+            "sales".to_string()
+        );
+        assert_eq!(registry.retention_classes.get("short"), Some(&86400));
+        assert!(CategoryRegistry::from_file("./tmp/registry-missing.json").is_err());
+    }
+}
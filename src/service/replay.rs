@@ -0,0 +1,189 @@
+//! Protection of the listener against the re-submission of captured requests.
+//!
+//! The producer adds the first Marker after the genesis Marker to the Data Tracker Chain when it sends the data, so
+//! that origin Marker holds the time the data was sent. The `ReplayGuard` rejects the Data Tracker Chains whose
+//! origin Marker is outside of the allowed clock skew, or whose (data id, origin Marker hash) has already been seen
+//! within the skew. The Markers that are appended later don't change the origin Marker, so a replayer can't make a
+//! captured Data Tracker Chain look fresh by appending a new Marker.
+//!
+//! The genesis Marker of a Data Tracker Chain always has a timestamp of 0 and the same nonce, so a Data Tracker Chain
+//! that only has its genesis Marker can't be checked and is rejected. The producers that send such Data Tracker
+//! Chains sign their requests instead, (see `signature`): the signature covers the Data Tracker Chain, so the
+//! listener relies on the signed timestamp and the signature to reject the replays of a signed request, and doesn't
+//! check its Data Tracker Chain with the guard.
+//!
+//! The guard is enabled by registering it as application data, (e.g.: App::new().data(ReplayGuard::new()))
+//!
+//! # Examples
+//!
+//! ```
+//! extern crate daas;
+//! extern crate pbd;
+//!
+//! use daas::service::replay::ReplayGuard;
+//! use pbd::dtc::Tracker;
+//!
+//! fn main() {
+//!     let guard = ReplayGuard::new().with_skew(60);
+//!     let mut tracker = Tracker::new("order~clothing~iStore~5000".to_string());
+//!     tracker.add(1553988607, "iStore_app".to_string(), "order~clothing~iStore~5000".to_string());
+//!
+//!     assert!(guard.check_at(&tracker, 1553988607).is_ok());
+//!     assert!(guard.check_at(&tracker, 1553988610).is_err());
+//!
+//!     // appending a fresh Marker doesn't make the replayed Data Tracker Chain fresh
+//!     tracker.add(1553988900, "replayer".to_string(), "order~clothing~iStore~5000".to_string());
+//!     assert!(guard.check_at(&tracker, 1553988900).is_err());
+//! }
+//! ```
+
+use super::*;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Represents the guard against replayed Data Tracker Chains
+pub struct ReplayGuard {
+    /// How far (in seconds) the timestamp of the origin Marker can be from the time it is received, (default: 300)
+    pub skew: u64,
+    // the (data id, origin Marker hash) that have been seen within the skew, and the timestamp of their Marker
+    seen: Mutex<HashMap<(String, String), u64>>,
+}
+
+impl ReplayGuard {
+    /// Constructs a ReplayGuard
+    pub fn new() -> ReplayGuard {
+        ReplayGuard {
+            skew: 300,
+            seen: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Sets how far (in seconds) the timestamp of the origin Marker can be from the time it is received
+    pub fn with_skew(mut self, skew: u64) -> ReplayGuard {
+        self.skew = skew;
+        self
+    }
+
+    /// Checks the Data Tracker Chain of a request that is received now
+    ///
+    /// # Arguments
+    ///
+    /// * tracker: &Tracker - The Data Tracker Chain of the request.</br>
+    pub fn check(&self, tracker: &Tracker) -> Result<(), ReplayedDataError> {
+        self.check_at(tracker, get_unix_now!())
+    }
+
+    /// Checks the Data Tracker Chain of a request at the time it is received
+    ///
+    /// # Arguments
+    ///
+    /// * tracker: &Tracker - The Data Tracker Chain of the request.</br>
+    /// * now: u64 - The time (Unix seconds) the request is received.</br>
+    pub fn check_at(&self, tracker: &Tracker, now: u64) -> Result<(), ReplayedDataError> {
+        // the origin Marker is added by the producer right after the genesis Marker
+        let marker = match tracker.get(1) {
+            Some(m) => m,
+            None => {
+                warn!("Rejected the Data Tracker Chain because it only has the genesis Marker.");
+                return Err(ReplayedDataError);
+            }
+        };
+        let timestamp = marker.identifier.timestamp;
+
+        if timestamp.max(now) - timestamp.min(now) > self.skew {
+            warn!(
+                "Rejected the Data Tracker Chain of {} because the timestamp {} is outside of the skew.",
+                marker.identifier.data_id, timestamp
+            );
+            return Err(ReplayedDataError);
+        }
+
+        // the Markers outside of the skew are rejected anyway, so they don't need to be remembered
+        let mut seen = self.seen.lock().unwrap();
+        let skew = self.skew;
+        seen.retain(|_k, t| now.saturating_sub(*t) <= skew);
+
+        let key = (marker.identifier.data_id, marker.hash);
+        if seen.insert(key.clone(), timestamp).is_some() {
+            warn!("Rejected the replayed Data Tracker Chain of {}.", key.0);
+            return Err(ReplayedDataError);
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for ReplayGuard {
+    fn default() -> Self {
+        ReplayGuard::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DATA_ID: &str = "order~clothing~iStore~5000";
+
+    fn get_tracker(timestamp: u64) -> Tracker {
+        let mut tracker = Tracker::new(DATA_ID.to_string());
+        tracker.add(timestamp, "iStore_app".to_string(), DATA_ID.to_string());
+        tracker
+    }
+
+    #[test]
+    fn test_check_ok() {
+        let guard = ReplayGuard::new();
+
+        assert!(guard.check(&get_tracker(get_unix_now!())).is_ok());
+        assert!(guard.check(&get_tracker(get_unix_now!() - 10)).is_ok());
+    }
+
+    #[test]
+    fn test_check_genesis_only() {
+        // the genesis Marker has no timestamp to check, (the signed requests aren't checked by the guard)
+        let guard = ReplayGuard::new();
+
+        assert!(guard
+            .check_at(&Tracker::new(DATA_ID.to_string()), 0)
+            .is_err());
+    }
+
+    #[test]
+    fn test_check_skew() {
+        let guard = ReplayGuard::new().with_skew(60);
+
+        assert!(guard
+            .check_at(&get_tracker(1553988607), 1553988668)
+            .is_err());
+        assert!(guard
+            .check_at(&get_tracker(1553988700), 1553988607)
+            .is_err());
+        assert!(guard.check_at(&get_tracker(1553988607), 1553988667).is_ok());
+    }
+
+    #[test]
+    fn test_check_replayed() {
+        let guard = ReplayGuard::new().with_skew(60);
+        let tracker = get_tracker(1553988607);
+
+        assert!(guard.check_at(&tracker, 1553988607).is_ok());
+        assert!(guard.check_at(&tracker, 1553988637).is_err());
+        // a new request for the same data has a new Marker
+        assert!(guard.check_at(&get_tracker(1553988608), 1553988637).is_ok());
+    }
+
+    #[test]
+    fn test_check_appended_marker() {
+        let guard = ReplayGuard::new().with_skew(60);
+        let mut tracker = get_tracker(1553988607);
+        assert!(guard.check_at(&tracker, 1553988607).is_ok());
+
+        // the replayer appends a fresh Marker to the captured (or expired) Data Tracker Chain
+        tracker.add(1553988637, "replayer".to_string(), DATA_ID.to_string());
+        assert!(guard.check_at(&tracker, 1553988637).is_err());
+        let mut expired = get_tracker(1553988000);
+        expired.add(1553988637, "replayer".to_string(), DATA_ID.to_string());
+        assert!(guard.check_at(&expired, 1553988637).is_err());
+    }
+}
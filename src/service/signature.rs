@@ -0,0 +1,522 @@
+//! HMAC request signing for the producers that can't use mutual TLS.
+//!
+//! A trusted producer shares a secret with the listener (per source name) and signs every request with
+//! HMAC-SHA256 over the method, path, timestamp, Data Tracker Chain header and body. The signature is sent (hex
+//! encoded) in the `X-DaaS-Signature` header and the timestamp (Unix seconds) in the `X-DaaS-Timestamp` header.
+//!
+//! Since the signature covers the Data Tracker Chain, the listener doesn't need the `ReplayGuard` to check the
+//! Data Tracker Chain of a signed request: the freshness comes from the signed timestamp and the replays are
+//! rejected by the signature, (see `replay`).
+//!
+//! The verification is enabled by registering a `SignatureVerifier` as application data,
+//! (e.g.: App::new().data(SignatureVerifier::new().with_secret("iStore".to_string(), secret))).
+//! The listener then rejects the requests that are unsigned, signed with the wrong secret, outside of the
+//! tolerated clock skew or that have already been seen (replayed).
+//!
+//! The listener authenticates the signature before anything else, but only records it as seen after the
+//! Idempotency-Key of the request was looked up, (see `authenticate_request()` and `reserve()`), so the retry of a
+//! signed request receives its original response. The signature of a request that failed is forgotten, so the
+//! producer can retry the same signed request.
+//!
+//! # Examples
+//!
+//! ```
+//! extern crate daas;
+//!
+//! use daas::service::signature::{sign, SignatureVerifier};
+//!
+//! fn main() {
+//!     let verifier = SignatureVerifier::new().with_secret("iStore".to_string(), b"secret".to_vec());
+//!     let body = br#"{"status": "new"}"#;
+//!     let timestamp = 1553988607;
+//!     let tracker = "eyJjaGFpbiI6W119";
+//!     let signature = sign(b"secret", "POST", "/order/clothing/iStore/5000", timestamp, tracker, body);
+//!
+//!     assert!(verifier
+//!         .verify_at("iStore", "POST", "/order/clothing/iStore/5000", Some(&timestamp.to_string()), Some(&signature), Some(tracker), body, timestamp)
+//!         .is_ok());
+//! }
+//! ```
+
+use super::*;
+use openssl::hash::MessageDigest;
+use openssl::memcmp;
+use openssl::pkey::PKey;
+use openssl::sign::Signer;
+use pbd::dtc::DTC_HEADER;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// The header that holds the signature of the request
+pub const SIGNATURE_HEADER: &str = "X-DaaS-Signature";
+/// The header that holds the time (Unix seconds) the request was signed
+pub const TIMESTAMP_HEADER: &str = "X-DaaS-Timestamp";
+
+/// Calculates the hex encoded HMAC-SHA256 signature of a request
+///
+/// # Arguments
+///
+/// * secret: &[u8] - The secret shared by the producer and the listener.</br>
+/// * method: &str - The HTTP method, (e.g.: POST).</br>
+/// * path: &str - The path of the request, (e.g.: /order/clothing/iStore/5000).</br>
+/// * timestamp: u64 - The time (Unix seconds) the request is signed.</br>
+/// * tracker: &str - The value of the Data Tracker Chain header of the request.</br>
+/// * body: &[u8] - The body of the request.</br>
+pub fn sign(
+    secret: &[u8],
+    method: &str,
+    path: &str,
+    timestamp: u64,
+    tracker: &str,
+    body: &[u8],
+) -> String {
+    let key = PKey::hmac(secret).unwrap();
+    let mut signer = Signer::new(MessageDigest::sha256(), &key).unwrap();
+    signer
+        .update(
+            format!(
+                "{}\n{}\n{}\n{}\n",
+                method.to_uppercase(),
+                path,
+                timestamp,
+                tracker
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+    signer.update(body).unwrap();
+
+    signer
+        .sign_to_vec()
+        .unwrap()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Represents the verification of the signed requests of the trusted producers
+pub struct SignatureVerifier {
+    /// How far (in seconds) the timestamp of a request can be from the time it is received, (default: 300)
+    pub tolerance: u64,
+    secrets: HashMap<String, Vec<u8>>,
+    // the signatures that have been seen within the tolerance, and when they were signed
+    seen: Mutex<HashMap<String, u64>>,
+}
+
+impl SignatureVerifier {
+    /// Constructs a SignatureVerifier without any secrets, (so all the requests are rejected)
+    pub fn new() -> SignatureVerifier {
+        SignatureVerifier {
+            tolerance: 300,
+            secrets: HashMap::new(),
+            seen: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Sets the secret of the source, (i.e.: producer)
+    ///
+    /// # Arguments
+    ///
+    /// * source_name: String - The name of the source, as it appears in the path of the request.</br>
+    /// * secret: Vec<u8> - The secret shared by the producer and the listener.</br>
+    pub fn with_secret(mut self, source_name: String, secret: Vec<u8>) -> SignatureVerifier {
+        self.secrets.insert(source_name, secret);
+        self
+    }
+
+    /// Sets how far (in seconds) the timestamp of a request can be from the time it is received
+    pub fn with_tolerance(mut self, tolerance: u64) -> SignatureVerifier {
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// Verifies the signature headers of the request to the listener
+    ///
+    /// # Arguments
+    ///
+    /// * req: &HttpRequest - The request.</br>
+    /// * source_name: &str - The name of the source the request is for.</br>
+    /// * body: &[u8] - The body of the request.</br>
+    pub fn verify_request(
+        &self,
+        req: &HttpRequest,
+        source_name: &str,
+        body: &[u8],
+    ) -> Result<(), InvalidSignatureError> {
+        let signed = self.authenticate_request(req, source_name, body)?;
+        self.reserve(&signed, get_unix_now!())?.keep();
+        Ok(())
+    }
+
+    /// Verifies the signature headers of the request to the listener like `verify_request()`, without recording the
+    /// signature as seen, (see `reserve()`)
+    ///
+    /// # Arguments
+    ///
+    /// * req: &HttpRequest - The request.</br>
+    /// * source_name: &str - The name of the source the request is for.</br>
+    /// * body: &[u8] - The body of the request.</br>
+    pub fn authenticate_request(
+        &self,
+        req: &HttpRequest,
+        source_name: &str,
+        body: &[u8],
+    ) -> Result<SignedRequest, InvalidSignatureError> {
+        let header = |name: &str| {
+            req.headers()
+                .get(name)
+                .and_then(|h| h.to_str().ok())
+                .map(|h| h.to_string())
+        };
+
+        self.authenticate_at(
+            source_name,
+            req.method().as_str(),
+            req.path(),
+            header(TIMESTAMP_HEADER).as_deref(),
+            header(SIGNATURE_HEADER).as_deref(),
+            header(DTC_HEADER).as_deref(),
+            body,
+            get_unix_now!(),
+        )
+    }
+
+    /// Verifies the signature of a request at the time it is received
+    ///
+    /// # Arguments
+    ///
+    /// * source_name: &str - The name of the source the request is for.</br>
+    /// * method: &str - The HTTP method.</br>
+    /// * path: &str - The path of the request.</br>
+    /// * timestamp: Option<&str> - The value of the timestamp header.</br>
+    /// * signature: Option<&str> - The value of the signature header.</br>
+    /// * tracker: Option<&str> - The value of the Data Tracker Chain header.</br>
+    /// * body: &[u8] - The body of the request.</br>
+    /// * now: u64 - The time (Unix seconds) the request is received.</br>
+    #[allow(clippy::too_many_arguments)]
+    pub fn verify_at(
+        &self,
+        source_name: &str,
+        method: &str,
+        path: &str,
+        timestamp: Option<&str>,
+        signature: Option<&str>,
+        tracker: Option<&str>,
+        body: &[u8],
+        now: u64,
+    ) -> Result<(), InvalidSignatureError> {
+        let signed = self.authenticate_at(
+            source_name,
+            method,
+            path,
+            timestamp,
+            signature,
+            tracker,
+            body,
+            now,
+        )?;
+        self.reserve(&signed, now)?.keep();
+        Ok(())
+    }
+
+    /// Verifies the signature of a request at the time it is received like `verify_at()`, without recording the
+    /// signature as seen, (see `reserve()`)
+    ///
+    /// # Arguments
+    ///
+    /// * source_name: &str - The name of the source the request is for.</br>
+    /// * method: &str - The HTTP method.</br>
+    /// * path: &str - The path of the request.</br>
+    /// * timestamp: Option<&str> - The value of the timestamp header.</br>
+    /// * signature: Option<&str> - The value of the signature header.</br>
+    /// * tracker: Option<&str> - The value of the Data Tracker Chain header.</br>
+    /// * body: &[u8] - The body of the request.</br>
+    /// * now: u64 - The time (Unix seconds) the request is received.</br>
+    #[allow(clippy::too_many_arguments)]
+    pub fn authenticate_at(
+        &self,
+        source_name: &str,
+        method: &str,
+        path: &str,
+        timestamp: Option<&str>,
+        signature: Option<&str>,
+        tracker: Option<&str>,
+        body: &[u8],
+        now: u64,
+    ) -> Result<SignedRequest, InvalidSignatureError> {
+        let secret = match self.secrets.get(source_name) {
+            Some(s) => s,
+            None => {
+                warn!(
+                    "Rejected the request because {} has no secret.",
+                    source_name
+                );
+                return Err(InvalidSignatureError);
+            }
+        };
+
+        let (timestamp, signature) =
+            match (timestamp.and_then(|t| t.parse::<u64>().ok()), signature) {
+                (Some(t), Some(s)) => (t, s.to_lowercase()),
+                _ => {
+                    warn!("Rejected the unsigned request for {}.", source_name);
+                    return Err(InvalidSignatureError);
+                }
+            };
+
+        if timestamp.max(now) - timestamp.min(now) > self.tolerance {
+            warn!(
+                "Rejected the request for {} because the timestamp {} is outside of the tolerance.",
+                source_name, timestamp
+            );
+            return Err(InvalidSignatureError);
+        }
+
+        let expected = sign(
+            secret,
+            method,
+            path,
+            timestamp,
+            tracker.unwrap_or_default(),
+            body,
+        );
+        if expected.len() != signature.len()
+            || !memcmp::eq(expected.as_bytes(), signature.as_bytes())
+        {
+            warn!(
+                "Rejected the request for {} because of an invalid signature.",
+                source_name
+            );
+            return Err(InvalidSignatureError);
+        }
+
+        Ok(SignedRequest {
+            source_name: source_name.to_string(),
+            signature,
+            timestamp,
+        })
+    }
+
+    /// Records the signature of an authenticated request as seen, rejecting the request if the signature has already
+    /// been seen (replayed). The signature is forgotten when the reservation is dropped without being kept, so a
+    /// request that failed can be retried.
+    ///
+    /// # Arguments
+    ///
+    /// * signed: &SignedRequest - The authenticated request, (see `authenticate_request()`).</br>
+    /// * now: u64 - The time (Unix seconds) the request is received.</br>
+    pub fn reserve(
+        &self,
+        signed: &SignedRequest,
+        now: u64,
+    ) -> Result<SignatureReservation<'_>, InvalidSignatureError> {
+        // the signatures outside of the tolerance are rejected anyway, so they don't need to be remembered
+        let mut seen = self.seen.lock().unwrap();
+        let tolerance = self.tolerance;
+        seen.retain(|_s, t| now.saturating_sub(*t) <= tolerance);
+        if seen.contains_key(&signed.signature) {
+            warn!("Rejected the replayed request for {}.", signed.source_name);
+            return Err(InvalidSignatureError);
+        }
+        seen.insert(signed.signature.clone(), signed.timestamp);
+
+        Ok(SignatureReservation {
+            verifier: self,
+            signature: signed.signature.clone(),
+            kept: false,
+        })
+    }
+}
+
+/// Represents a request whose signature has been authenticated, (see `SignatureVerifier::authenticate_request()`)
+#[derive(Debug, Clone, PartialEq)]
+pub struct SignedRequest {
+    /// The name of the source the request is for
+    pub source_name: String,
+    /// The (lower case) hex encoded signature of the request
+    pub signature: String,
+    /// The time (Unix seconds) the request was signed
+    pub timestamp: u64,
+}
+
+/// Represents the signature of a request that is recorded as seen, which is forgotten when the reservation is
+/// dropped without being kept, (e.g.: when the request failed)
+pub struct SignatureReservation<'a> {
+    verifier: &'a SignatureVerifier,
+    signature: String,
+    kept: bool,
+}
+
+impl<'a> SignatureReservation<'a> {
+    /// Keeps the signature as seen, so the request can't be replayed
+    pub fn keep(mut self) {
+        self.kept = true;
+    }
+}
+
+impl<'a> Drop for SignatureReservation<'a> {
+    fn drop(&mut self) {
+        if !self.kept {
+            self.verifier.seen.lock().unwrap().remove(&self.signature);
+        }
+    }
+}
+
+impl Default for SignatureVerifier {
+    fn default() -> Self {
+        SignatureVerifier::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    const PATH: &str = "/order/clothing/iStore/5000";
+    const BODY: &[u8] = br#"{"status": "new"}"#;
+    const TRACKER: &str = "eyJjaGFpbiI6W119";
+
+    fn get_verifier() -> SignatureVerifier {
+        SignatureVerifier::new().with_secret("iStore".to_string(), b"secret".to_vec())
+    }
+
+    #[test]
+    fn test_sign() {
+        let signature = sign(b"secret", "post", PATH, 1553988607, TRACKER, BODY);
+
+        assert_eq!(signature.len(), 64);
+        assert_eq!(
+            signature,
+            sign(b"secret", "POST", PATH, 1553988607, TRACKER, BODY)
+        );
+        assert_ne!(
+            signature,
+            sign(b"other", "POST", PATH, 1553988607, TRACKER, BODY)
+        );
+        assert_ne!(
+            signature,
+            sign(b"secret", "POST", PATH, 1553988608, TRACKER, BODY)
+        );
+        assert_ne!(
+            signature,
+            sign(b"secret", "POST", PATH, 1553988607, "e30=", BODY)
+        );
+    }
+
+    #[test]
+    fn test_verify_ok() {
+        let now = get_unix_now!();
+        let signature = sign(b"secret", "POST", PATH, now, TRACKER, BODY);
+        let req = TestRequest::post()
+            .uri(PATH)
+            .header(TIMESTAMP_HEADER, now.to_string())
+            .header(SIGNATURE_HEADER, signature)
+            .header(DTC_HEADER, TRACKER)
+            .to_http_request();
+
+        assert!(get_verifier().verify_request(&req, "iStore", BODY).is_ok());
+    }
+
+    #[test]
+    fn test_verify_rejected() {
+        let verifier = get_verifier();
+        let ts = "1553988607";
+        let signature = sign(b"secret", "POST", PATH, 1553988607, TRACKER, BODY);
+        let verify = |src: &str, t: Option<&str>, s: Option<&str>, body: &[u8], now: u64| {
+            verifier.verify_at(src, "POST", PATH, t, s, Some(TRACKER), body, now)
+        };
+
+        // unsigned, unknown source, tampered body, expired and wrong secret
+        assert!(verify("iStore", None, None, BODY, 1553988607).is_err());
+        assert!(verify("iStore", Some(ts), None, BODY, 1553988607).is_err());
+        assert!(verify("other", Some(ts), Some(&signature), BODY, 1553988607).is_err());
+        assert!(verify("iStore", Some(ts), Some(&signature), b"{}", 1553988607).is_err());
+        assert!(verify("iStore", Some(ts), Some(&signature), BODY, 1553988907 + 1).is_err());
+        let wrong = sign(b"other", "POST", PATH, 1553988607, TRACKER, BODY);
+        assert!(verify("iStore", Some(ts), Some(&wrong), BODY, 1553988607).is_err());
+    }
+
+    #[test]
+    fn test_verify_tampered_tracker() {
+        let verifier = get_verifier();
+        let signature = sign(b"secret", "POST", PATH, 1553988607, TRACKER, BODY);
+        let verify = |tracker: Option<&str>| {
+            verifier.authenticate_at(
+                "iStore",
+                "POST",
+                PATH,
+                Some("1553988607"),
+                Some(&signature),
+                tracker,
+                BODY,
+                1553988607,
+            )
+        };
+
+        // the Data Tracker Chain can't be replaced or dropped, (e.g.: by appending a fresh Marker)
+        assert!(verify(Some(TRACKER)).is_ok());
+        assert!(verify(Some("e30=")).is_err());
+        assert!(verify(None).is_err());
+    }
+
+    #[test]
+    fn test_verify_replayed() {
+        let verifier = get_verifier().with_tolerance(60);
+        let signature = sign(b"secret", "POST", PATH, 1553988607, TRACKER, BODY);
+        let verify = |now: u64| {
+            verifier.verify_at(
+                "iStore",
+                "POST",
+                PATH,
+                Some("1553988607"),
+                Some(&signature),
+                Some(TRACKER),
+                BODY,
+                now,
+            )
+        };
+
+        assert!(verify(1553988607).is_ok());
+        assert!(verify(1553988610).is_err());
+    }
+
+    #[test]
+    fn test_reserve() {
+        let verifier = get_verifier();
+        let signature = sign(b"secret", "POST", PATH, 1553988607, TRACKER, BODY);
+        let signed = verifier
+            .authenticate_at(
+                "iStore",
+                "POST",
+                PATH,
+                Some("1553988607"),
+                Some(&signature),
+                Some(TRACKER),
+                BODY,
+                1553988607,
+            )
+            .unwrap();
+
+        // authenticating doesn't record the signature
+        assert!(verifier
+            .authenticate_at(
+                "iStore",
+                "POST",
+                PATH,
+                Some("1553988607"),
+                Some(&signature),
+                Some(TRACKER),
+                BODY,
+                1553988607,
+            )
+            .is_ok());
+
+        // the signature of a failed request is forgotten
+        let reservation = verifier.reserve(&signed, 1553988607).unwrap();
+        assert!(verifier.reserve(&signed, 1553988607).is_err());
+        drop(reservation);
+        verifier.reserve(&signed, 1553988607).unwrap().keep();
+        assert!(verifier.reserve(&signed, 1553988607).is_err());
+    }
+}
@@ -0,0 +1,215 @@
+//! Transport security for the DaaS services, so the listener doesn't have to sit behind a separate proxy to terminate
+//! TLS.
+//!
+//! The `TlsConfig` builds the OpenSSL acceptor from the PEM files of the certificate (chain) and private key of the
+//! service, using the Mozilla intermediate settings (TLS 1.2 and 1.3). It can also verify the certificates of the
+//! clients against a CA, (mutual TLS), either requiring them or only verifying them when they are presented.
+//!
+//! The acceptor is bound with `HttpServer::bind_openssl()`, which is available when the `openssl` feature of
+//! actix-web is enabled in the Cargo.toml of the service, (e.g.: `actix-web = { version = "3", features = ["openssl"] }`).
+//!
+//! ```ignore
+//! let tls = TlsConfig::from_env().expect("DAAS_TLS_CERT and DAAS_TLS_KEY must be set");
+//!
+//! HttpServer::new(|| App::new().configure(configure_listener))
+//!     .bind_openssl("0.0.0.0:8443", tls.acceptor_builder()?)?
+//!     .run()
+//!     .await
+//! ```
+//!
+//! # Examples
+//!
+//! ```
+//! extern crate daas;
+//!
+//! use daas::service::tls::TlsConfig;
+//!
+//! fn main() {
+//!     let tls = TlsConfig::new("./certs/daas.pem".to_string(), "./certs/daas.key".to_string())
+//!         .with_client_ca("./certs/producers-ca.pem".to_string(), true);
+//!
+//!     assert!(tls.require_client_cert);
+//!     // the certificate files don't exist
+//!     assert!(tls.acceptor_builder().is_err());
+//! }
+//! ```
+
+use super::*;
+use openssl::ssl::{SslAcceptor, SslAcceptorBuilder, SslFiletype, SslMethod, SslVerifyMode};
+use openssl::x509::X509Name;
+use std::env;
+
+/// Represents the TLS configuration of the DaaS services
+#[derive(Debug, Clone, PartialEq)]
+pub struct TlsConfig {
+    /// The path of the PEM file of the certificate chain of the service
+    pub cert_path: String,
+    /// The path of the PEM file of the private key of the service
+    pub key_path: String,
+    /// The path of the PEM file of the CA that issues the certificates of the clients, (mutual TLS)
+    pub client_ca_path: Option<String>,
+    /// The indicator that represents if the clients must present a certificate, (default: false)
+    pub require_client_cert: bool,
+}
+
+impl TlsConfig {
+    /// Constructs a TlsConfig that doesn't verify the certificates of the clients
+    ///
+    /// # Arguments
+    ///
+    /// * cert_path: String - The path of the PEM file of the certificate chain of the service.</br>
+    /// * key_path: String - The path of the PEM file of the private key of the service.</br>
+    pub fn new(cert_path: String, key_path: String) -> TlsConfig {
+        TlsConfig {
+            cert_path,
+            key_path,
+            client_ca_path: None,
+            require_client_cert: false,
+        }
+    }
+
+    /// Constructs a TlsConfig from the environment variables `DAAS_TLS_CERT`, `DAAS_TLS_KEY` and (optionally)
+    /// `DAAS_TLS_CLIENT_CA` and `DAAS_TLS_CLIENT_CERT_REQUIRED` (true or false), or None if TLS isn't configured
+    pub fn from_env() -> Option<TlsConfig> {
+        let tls = TlsConfig::new(
+            env::var("DAAS_TLS_CERT").ok()?,
+            env::var("DAAS_TLS_KEY").ok()?,
+        );
+
+        Some(match env::var("DAAS_TLS_CLIENT_CA") {
+            Ok(ca) => {
+                let required = env::var("DAAS_TLS_CLIENT_CERT_REQUIRED")
+                    .map(|r| r.eq_ignore_ascii_case("true"))
+                    .unwrap_or(false);
+                tls.with_client_ca(ca, required)
+            }
+            Err(_e) => tls,
+        })
+    }
+
+    /// Verifies the certificates of the clients against the CA
+    ///
+    /// # Arguments
+    ///
+    /// * ca_path: String - The path of the PEM file of the CA that issues the certificates of the clients.</br>
+    /// * required: bool - If the clients without a certificate are rejected.</br>
+    pub fn with_client_ca(mut self, ca_path: String, required: bool) -> TlsConfig {
+        self.client_ca_path = Some(ca_path);
+        self.require_client_cert = required;
+        self
+    }
+
+    /// Returns the OpenSSL acceptor of the configuration, (see `HttpServer::bind_openssl()`)
+    pub fn acceptor_builder(&self) -> Result<SslAcceptorBuilder, TlsConfigError> {
+        let map_err = |err: openssl::error::ErrorStack, what: &str| {
+            error!("Could not load the {} for TLS. Error: {}", what, err);
+            TlsConfigError
+        };
+
+        let mut builder = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls())
+            .map_err(|e| map_err(e, "acceptor"))?;
+        builder
+            .set_certificate_chain_file(&self.cert_path)
+            .map_err(|e| map_err(e, &format!("certificate {}", self.cert_path)))?;
+        builder
+            .set_private_key_file(&self.key_path, SslFiletype::PEM)
+            .map_err(|e| map_err(e, &format!("private key {}", self.key_path)))?;
+        builder
+            .check_private_key()
+            .map_err(|e| map_err(e, "private key of the certificate"))?;
+
+        if let Some(ca_path) = &self.client_ca_path {
+            builder
+                .set_ca_file(ca_path)
+                .map_err(|e| map_err(e, &format!("client CA {}", ca_path)))?;
+            // the names of the CA are sent to the clients, so they know which certificate to present
+            let names = X509Name::load_client_ca_file(ca_path)
+                .map_err(|e| map_err(e, &format!("client CA {}", ca_path)))?;
+            builder.set_client_ca_list(names);
+
+            let mode = match self.require_client_cert {
+                true => SslVerifyMode::PEER | SslVerifyMode::FAIL_IF_NO_PEER_CERT,
+                false => SslVerifyMode::PEER,
+            };
+            builder.set_verify(mode);
+        }
+
+        Ok(builder)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use openssl::asn1::Asn1Time;
+    use openssl::hash::MessageDigest;
+    use openssl::pkey::PKey;
+    use openssl::rsa::Rsa;
+    use openssl::x509::{X509NameBuilder, X509};
+    use std::fs;
+
+    // Writes a self-signed certificate and its private key to the directory, returning their paths
+    fn write_self_signed(dir: &str) -> (String, String) {
+        let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let mut name = X509NameBuilder::new().unwrap();
+        name.append_entry_by_text("CN", "localhost").unwrap();
+        let name = name.build();
+
+        let mut cert = X509::builder().unwrap();
+        cert.set_version(2).unwrap();
+        cert.set_subject_name(&name).unwrap();
+        cert.set_issuer_name(&name).unwrap();
+        cert.set_pubkey(&key).unwrap();
+        cert.set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        cert.set_not_after(&Asn1Time::days_from_now(1).unwrap())
+            .unwrap();
+        cert.sign(&key, MessageDigest::sha256()).unwrap();
+
+        fs::create_dir_all(dir).unwrap();
+        let cert_path = format!("{}/cert.pem", dir);
+        let key_path = format!("{}/key.pem", dir);
+        fs::write(&cert_path, cert.build().to_pem().unwrap()).unwrap();
+        fs::write(&key_path, key.private_key_to_pem_pkcs8().unwrap()).unwrap();
+
+        (cert_path, key_path)
+    }
+
+    #[test]
+    fn test_acceptor_builder() {
+        let dir = format!("./tmp/tls-{}", rand::random::<u32>());
+        let (cert_path, key_path) = write_self_signed(&dir);
+
+        let builder = TlsConfig::new(cert_path.clone(), key_path.clone())
+            .acceptor_builder()
+            .unwrap();
+        assert_eq!(builder.build().context().verify_mode(), SslVerifyMode::NONE);
+
+        // the self-signed certificate is its own CA
+        let builder = TlsConfig::new(cert_path.clone(), key_path)
+            .with_client_ca(cert_path, true)
+            .acceptor_builder()
+            .unwrap();
+        assert_eq!(
+            builder.build().context().verify_mode(),
+            SslVerifyMode::PEER | SslVerifyMode::FAIL_IF_NO_PEER_CERT
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_acceptor_builder_mismatched_key() {
+        let dir_a = format!("./tmp/tls-{}", rand::random::<u32>());
+        let dir_b = format!("./tmp/tls-{}", rand::random::<u32>());
+        let (cert_path, _key) = write_self_signed(&dir_a);
+        let (_cert, key_path) = write_self_signed(&dir_b);
+
+        assert!(TlsConfig::new(cert_path, key_path)
+            .acceptor_builder()
+            .is_err());
+
+        fs::remove_dir_all(dir_a).unwrap();
+        fs::remove_dir_all(dir_b).unwrap();
+    }
+}
@@ -0,0 +1,445 @@
+//! Transformations of the payloads that the listener receives, before the DaaS documents are constructed.
+//!
+//! A `PayloadTransformer` rewrites the payload of a request, (e.g.: normalizes the casing of the JSON keys or strips
+//! the fields that shouldn't be stored) and can change its content type, (e.g.: converts XML to JSON). The
+//! `Transformations` select the transformers of each category, and are enabled by registering them as application
+//! data, (e.g.: App::new().data(Transformations::new())).
+//!
+//! The names of the transformers that were applied are recorded in the `transformations` metadata of the DaaS
+//! document, and each transformer is recorded as an actor in its Data Tracker Chain. A payload that can't be
+//! transformed is rejected with 422 Unprocessable Entity.
+//!
+//! # Examples
+//!
+//! ```
+//! extern crate daas;
+//!
+//! use daas::service::transform::{KeyCase, NormalizeKeys, RawPayload, StripFields, Transformations};
+//!
+//! fn main() {
+//!     let transformations = Transformations::new()
+//!         .with_transformer("order".to_string(), Box::new(NormalizeKeys::new(KeyCase::Snake)))
+//!         .with_transformer("order".to_string(), Box::new(StripFields::new(vec!["/card_number".to_string()])));
+//!     let payload = RawPayload::new(
+//!         "application/json".to_string(),
+//!         br#"{"orderId": 5000, "CardNumber": "4111"}"#.to_vec(),
+//!     );
+//!     let (payload, applied) = transformations.apply("order", payload).unwrap();
+//!
+//!     assert_eq!(payload.data, br#"{"order_id":5000}"#.to_vec());
+//!     assert_eq!(applied, vec!["normalize-keys".to_string(), "strip-fields".to_string()]);
+//! }
+//! ```
+
+use super::*;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+
+/// The metadata key of the names of the transformers that were applied to the payload
+pub const TRANSFORMATIONS_META: &str = "transformations";
+
+/// Represents the payload of a request and its content type
+#[derive(Debug, Clone, PartialEq)]
+pub struct RawPayload {
+    /// The content type of the payload, (e.g.: application/json)
+    pub content_type: String,
+    /// The bytes of the payload
+    pub data: Vec<u8>,
+}
+
+impl RawPayload {
+    /// Constructs a RawPayload
+    ///
+    /// # Arguments
+    ///
+    /// * content_type: String - The content type of the payload, (e.g.: application/json).</br>
+    /// * data: Vec<u8> - The bytes of the payload.</br>
+    pub fn new(content_type: String, data: Vec<u8>) -> RawPayload {
+        RawPayload { content_type, data }
+    }
+
+    /// Determines if the content type is JSON, (e.g.: application/json or application/ld+json; charset=utf-8)
+    pub fn is_json(&self) -> bool {
+        let mime = self.content_type.split(';').next().unwrap_or("").trim();
+        mime.ends_with("/json") || mime.ends_with("+json")
+    }
+}
+
+/// Trait for the transformations of the payloads
+pub trait PayloadTransformer: Send + Sync {
+    /// Returns the name of the transformer that is recorded in the metadata and the Data Tracker Chain
+    fn name(&self) -> String;
+
+    /// Transforms the payload, or returns it unchanged if the transformer doesn't apply to its content type
+    ///
+    /// # Arguments
+    ///
+    /// * payload: RawPayload - The payload of the request.</br>
+    fn transform(&self, payload: RawPayload) -> Result<RawPayload, TransformError>;
+}
+
+// Parses the JSON payload, or returns None if the payload isn't JSON
+fn parse_json(payload: &RawPayload, transformer: &str) -> Result<Option<Value>, TransformError> {
+    if !payload.is_json() {
+        return Ok(None);
+    }
+
+    match serde_json::from_slice(&payload.data) {
+        Ok(value) => Ok(Some(value)),
+        Err(err) => {
+            warn!(
+                "The {} transformer could not parse the JSON payload. Error: {}",
+                transformer, err
+            );
+            Err(TransformError)
+        }
+    }
+}
+
+/// The casings of the JSON keys
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KeyCase {
+    /// e.g.: order_id
+    Snake,
+    /// e.g.: orderId
+    Camel,
+    /// e.g.: orderid
+    Lower,
+}
+
+impl KeyCase {
+    /// Returns the key in the casing
+    ///
+    /// # Arguments
+    ///
+    /// * key: &str - The JSON key, (e.g.: OrderID, order-id or orderId).</br>
+    ///
+    /// #Example
+    ///
+    /// ```rust
+    /// extern crate daas;
+    ///
+    /// use daas::service::transform::KeyCase;
+    ///
+    /// fn main() {
+    ///     assert_eq!(KeyCase::Snake.convert("OrderId"), "order_id".to_string());
+    ///     assert_eq!(KeyCase::Camel.convert("order-id"), "orderId".to_string());
+    /// }
+    /// ```
+    pub fn convert(&self, key: &str) -> String {
+        if *self == KeyCase::Lower {
+            return key.to_lowercase();
+        }
+
+        // split the key into its words, (at the separators and the start of a capitalized word)
+        let chars: Vec<char> = key.chars().collect();
+        let mut words: Vec<String> = Vec::new();
+        let mut word = String::new();
+        for (i, c) in chars.iter().enumerate() {
+            if *c == '_' || *c == '-' || c.is_whitespace() {
+                if !word.is_empty() {
+                    words.push(word.clone());
+                    word.clear();
+                }
+                continue;
+            }
+            let starts_word = c.is_uppercase()
+                && i > 0
+                && (chars[i - 1].is_lowercase()
+                    || chars[i - 1].is_numeric()
+                    || chars.get(i + 1).map(|n| n.is_lowercase()).unwrap_or(false));
+            if starts_word && !word.is_empty() {
+                words.push(word.clone());
+                word.clear();
+            }
+            word.extend(c.to_lowercase());
+        }
+        if !word.is_empty() {
+            words.push(word);
+        }
+
+        match self {
+            KeyCase::Camel => words
+                .iter()
+                .enumerate()
+                .map(|(i, w)| match i {
+                    0 => w.clone(),
+                    _ => {
+                        let mut c = w.chars();
+                        match c.next() {
+                            Some(f) => f.to_uppercase().chain(c).collect(),
+                            None => String::new(),
+                        }
+                    }
+                })
+                .collect(),
+            _ => words.join("_"),
+        }
+    }
+
+    fn convert_value(&self, value: Value) -> Value {
+        match value {
+            Value::Object(obj) => Value::Object(
+                obj.into_iter()
+                    .map(|(k, v)| (self.convert(&k), self.convert_value(v)))
+                    .collect::<Map<String, Value>>(),
+            ),
+            Value::Array(items) => {
+                Value::Array(items.into_iter().map(|v| self.convert_value(v)).collect())
+            }
+            other => other,
+        }
+    }
+}
+
+/// A transformer that normalizes the casing of the keys of a JSON payload
+pub struct NormalizeKeys {
+    /// The casing of the keys
+    pub case: KeyCase,
+}
+
+impl NormalizeKeys {
+    /// Constructs a NormalizeKeys transformer
+    pub fn new(case: KeyCase) -> NormalizeKeys {
+        NormalizeKeys { case }
+    }
+}
+
+impl PayloadTransformer for NormalizeKeys {
+    fn name(&self) -> String {
+        "normalize-keys".to_string()
+    }
+
+    fn transform(&self, payload: RawPayload) -> Result<RawPayload, TransformError> {
+        match parse_json(&payload, &self.name())? {
+            Some(value) => Ok(RawPayload::new(
+                payload.content_type,
+                serde_json::to_vec(&self.case.convert_value(value)).unwrap(),
+            )),
+            None => Ok(payload),
+        }
+    }
+}
+
+/// A transformer that removes fields from a JSON payload
+pub struct StripFields {
+    /// The JSON pointers of the fields, (e.g.: /customer/card_number)
+    pub fields: Vec<String>,
+}
+
+impl StripFields {
+    /// Constructs a StripFields transformer
+    ///
+    /// # Arguments
+    ///
+    /// * fields: Vec<String> - The JSON pointers of the fields, (e.g.: /customer/card_number).</br>
+    pub fn new(fields: Vec<String>) -> StripFields {
+        StripFields { fields }
+    }
+}
+
+impl PayloadTransformer for StripFields {
+    fn name(&self) -> String {
+        "strip-fields".to_string()
+    }
+
+    fn transform(&self, payload: RawPayload) -> Result<RawPayload, TransformError> {
+        let mut value = match parse_json(&payload, &self.name())? {
+            Some(value) => value,
+            None => return Ok(payload),
+        };
+
+        for field in self.fields.iter() {
+            let (parent, name) = match field.rfind('/') {
+                Some(i) => (
+                    &field[..i],
+                    field[i + 1..].replace("~1", "/").replace("~0", "~"),
+                ),
+                None => continue,
+            };
+            match value.pointer_mut(parent) {
+                Some(Value::Object(obj)) => {
+                    obj.remove(&name);
+                }
+                Some(Value::Array(items)) => {
+                    if let Ok(i) = name.parse::<usize>() {
+                        if i < items.len() {
+                            items.remove(i);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(RawPayload::new(
+            payload.content_type,
+            serde_json::to_vec(&value).unwrap(),
+        ))
+    }
+}
+
+/// Represents the transformers of the payloads of each category
+#[derive(Default)]
+pub struct Transformations {
+    // the transformers of each category, (in the order they are applied)
+    categories: HashMap<String, Vec<Box<dyn PayloadTransformer>>>,
+    // the transformers that are applied to all the categories, (before the transformers of the category)
+    all: Vec<Box<dyn PayloadTransformer>>,
+}
+
+impl Transformations {
+    /// Constructs Transformations without any transformers
+    pub fn new() -> Transformations {
+        Transformations::default()
+    }
+
+    /// Adds a transformer of the payloads of the category
+    ///
+    /// # Arguments
+    ///
+    /// * category: String - The category of the DaaS documents, (e.g.: order).</br>
+    /// * transformer: Box<dyn PayloadTransformer> - The transformer.</br>
+    pub fn with_transformer(
+        mut self,
+        category: String,
+        transformer: Box<dyn PayloadTransformer>,
+    ) -> Transformations {
+        self.categories
+            .entry(category)
+            .or_default()
+            .push(transformer);
+        self
+    }
+
+    /// Adds a transformer of the payloads of all the categories
+    pub fn with_default_transformer(
+        mut self,
+        transformer: Box<dyn PayloadTransformer>,
+    ) -> Transformations {
+        self.all.push(transformer);
+        self
+    }
+
+    /// Applies the transformers of the category to the payload.
+    /// Returns the transformed payload and the names of the transformers that were applied.
+    ///
+    /// # Arguments
+    ///
+    /// * category: &str - The category of the DaaS document.</br>
+    /// * payload: RawPayload - The payload of the request.</br>
+    pub fn apply(
+        &self,
+        category: &str,
+        payload: RawPayload,
+    ) -> Result<(RawPayload, Vec<String>), TransformError> {
+        let mut current = payload;
+        let mut applied = Vec::new();
+
+        for transformer in self
+            .all
+            .iter()
+            .chain(self.categories.get(category).into_iter().flatten())
+        {
+            current = transformer.transform(current)?;
+            applied.push(transformer.name());
+        }
+
+        Ok((current, applied))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn get_payload(value: Value) -> RawPayload {
+        RawPayload::new(
+            "application/json; charset=utf-8".to_string(),
+            serde_json::to_vec(&value).unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_key_case() {
+        assert_eq!(KeyCase::Snake.convert("orderId"), "order_id".to_string());
+        assert_eq!(KeyCase::Snake.convert("OrderID"), "order_id".to_string());
+        assert_eq!(
+            KeyCase::Snake.convert("HTTPStatus"),
+            "http_status".to_string()
+        );
+        assert_eq!(KeyCase::Camel.convert("order_id"), "orderId".to_string());
+        assert_eq!(KeyCase::Camel.convert("Order Id"), "orderId".to_string());
+        assert_eq!(KeyCase::Lower.convert("OrderId"), "orderid".to_string());
+    }
+
+    #[test]
+    fn test_normalize_keys() {
+        let payload = get_payload(json!({"OrderId": 1, "Items": [{"SkuCode": "a"}]}));
+        let transformed = NormalizeKeys::new(KeyCase::Snake)
+            .transform(payload)
+            .unwrap();
+
+        assert_eq!(
+            serde_json::from_slice::<Value>(&transformed.data).unwrap(),
+            json!({"order_id": 1, "items": [{"sku_code": "a"}]})
+        );
+    }
+
+    #[test]
+    fn test_strip_fields() {
+        let payload =
+            get_payload(json!({"customer": {"name": "Jo", "card": "4111"}, "ids": [1, 2]}));
+        let transformed = StripFields::new(vec![
+            "/customer/card".to_string(),
+            "/ids/0".to_string(),
+            "/missing/field".to_string(),
+        ])
+        .transform(payload)
+        .unwrap();
+
+        assert_eq!(
+            serde_json::from_slice::<Value>(&transformed.data).unwrap(),
+            json!({"customer": {"name": "Jo"}, "ids": [2]})
+        );
+    }
+
+    #[test]
+    fn test_not_json() {
+        let payload = RawPayload::new("text/plain".to_string(), b"OrderId".to_vec());
+        assert_eq!(
+            NormalizeKeys::new(KeyCase::Snake)
+                .transform(payload.clone())
+                .unwrap(),
+            payload
+        );
+
+        let invalid = RawPayload::new("application/json".to_string(), b"{".to_vec());
+        assert!(StripFields::new(Vec::new()).transform(invalid).is_err());
+    }
+
+    #[test]
+    fn test_apply() {
+        let transformations = Transformations::new()
+            .with_default_transformer(Box::new(NormalizeKeys::new(KeyCase::Snake)))
+            .with_transformer(
+                "order".to_string(),
+                Box::new(StripFields::new(vec!["/card".to_string()])),
+            );
+
+        let (payload, applied) = transformations
+            .apply("order", get_payload(json!({"Card": "4111", "Id": 1})))
+            .unwrap();
+        assert_eq!(payload.data, br#"{"id":1}"#.to_vec());
+        assert_eq!(
+            applied,
+            vec!["normalize-keys".to_string(), "strip-fields".to_string()]
+        );
+
+        let (_payload, applied) = transformations
+            .apply("music", get_payload(json!({"Card": "4111"})))
+            .unwrap();
+        assert_eq!(applied, vec!["normalize-keys".to_string()]);
+    }
+}
@@ -0,0 +1,577 @@
+//! A sink that registers the data that flows through the service in an external data catalog, (e.g.: AWS Glue or a
+//! REST catalog), so there is a machine-readable inventory of the categories, subcategories and sources.
+//!
+//! Each combination of category, subcategory and source name is registered the first time it is seen, along with the
+//! fingerprint of the schema of its JSON data and a summary of its Data Usage Agreements. The combination is
+//! registered again when the fingerprint of the schema changes, so the catalog follows the schema as it evolves.
+//!
+//! # Examples
+//!
+//! ```
+//! extern crate daas;
+//!
+//! use daas::sinks::catalog::{schema_fingerprint, CatalogSink, RestCatalog};
+//!
+//! fn main() {
+//!     let sink = CatalogSink::new(RestCatalog::new("http://localhost:8080/catalog".to_string()));
+//!
+//!     assert!(sink.inventory().is_empty());
+//!     // the fingerprint depends on the fields and their types, not the values
+//!     assert_eq!(
+//!         schema_fingerprint(br#"{"status": "new", "qty": 1}"#),
+//!         schema_fingerprint(br#"{"qty": 5, "status": "shipped"}"#)
+//!     );
+//! }
+//! ```
+
+use super::*;
+use crate::doc::DaaSDoc;
+use crate::errors::daaserror::DaaSProcessingError;
+use crate::service::pipeline::DaaSDocSink;
+use rusoto_core::signature::SignedRequest;
+use rusoto_core::{Client, Region};
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+use tokio::runtime::Runtime;
+
+/// Returns the schema of the JSON data, where the values are replaced with the names of their types and the arrays
+/// with the merged schema of their elements, (None if the data isn't JSON)
+///
+/// # Arguments
+///
+/// * data: &[u8] - The data object of the DaaS document.</br>
+pub fn infer_schema(data: &[u8]) -> Option<Value> {
+    serde_json::from_slice::<Value>(data)
+        .ok()
+        .map(|v| schema_of(&v))
+}
+
+/// Returns the SHA-256 fingerprint (hex) of the schema of the JSON data, (None if the data isn't JSON)
+///
+/// # Arguments
+///
+/// * data: &[u8] - The data object of the DaaS document.</br>
+pub fn schema_fingerprint(data: &[u8]) -> Option<String> {
+    // the keys of serde_json maps are sorted, so the serialized schema doesn't depend on the order of the fields
+    infer_schema(data).map(|schema| {
+        Sha256::digest(schema.to_string().as_bytes())
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    })
+}
+
+fn schema_of(value: &Value) -> Value {
+    match value {
+        Value::Null => Value::from("null"),
+        Value::Bool(_) => Value::from("boolean"),
+        Value::Number(n) if n.is_f64() => Value::from("double"),
+        Value::Number(_) => Value::from("integer"),
+        Value::String(_) => Value::from("string"),
+        Value::Array(items) => Value::Array(vec![items
+            .iter()
+            .map(schema_of)
+            .fold(Value::Null, merge_schema)]),
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(k, v)| (k.clone(), schema_of(v)))
+                .collect::<Map<String, Value>>(),
+        ),
+    }
+}
+
+// Merges the schemas of the elements of an array, (the fields of the objects are combined)
+fn merge_schema(merged: Value, schema: Value) -> Value {
+    match (merged, schema) {
+        (Value::Null, schema) => schema,
+        (Value::Object(mut a), Value::Object(b)) => {
+            for (k, v) in b {
+                let m = match a.remove(&k) {
+                    Some(existing) => merge_schema(existing, v),
+                    None => v,
+                };
+                a.insert(k, m);
+            }
+            Value::Object(a)
+        }
+        (a, b) if a == b => a,
+        _ => Value::from("mixed"),
+    }
+}
+
+/// Represents the summary of a Data Usage Agreement in the data catalog
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DuaSummary {
+    /// The common name of the Data Usage Agreement, (e.g.: billing)
+    pub agreement_name: String,
+    /// The URI where the version of the Data Usage Agreement can be found
+    pub location: String,
+}
+
+/// Represents a combination of category, subcategory and source name in the data catalog
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CatalogEntry {
+    /// The name of the category (e.g.: order)
+    pub category: String,
+    /// The name of the subcategory (e.g.: clothing)
+    pub subcategory: String,
+    /// The name of the data source
+    pub source_name: String,
+    /// The fingerprint of the schema of the data, (None if the data isn't JSON)
+    pub schema_fingerprint: Option<String>,
+    /// The schema of the data, (see `infer_schema()`)
+    pub schema: Option<Value>,
+    /// The Data Usage Agreements of the data
+    pub data_usage_agreements: Vec<DuaSummary>,
+    /// The Unix Epoch time when the entry was registered
+    pub registered: u64,
+}
+
+impl CatalogEntry {
+    /// Constructs a CatalogEntry from the DaaS document
+    ///
+    /// # Arguments
+    ///
+    /// * doc: &DaaSDoc - The DaaS document that represents the data.</br>
+    pub fn from_doc(doc: &DaaSDoc) -> CatalogEntry {
+        CatalogEntry {
+            category: doc.category.clone(),
+            subcategory: doc.subcategory.clone(),
+            source_name: doc.source_name.clone(),
+            schema_fingerprint: schema_fingerprint(&doc.data_obj),
+            schema: infer_schema(&doc.data_obj),
+            data_usage_agreements: doc
+                .data_usage_agreements
+                .iter()
+                .map(|dua| DuaSummary {
+                    agreement_name: dua.agreement_name.clone(),
+                    location: dua.location.clone(),
+                })
+                .collect(),
+            registered: get_unix_now!(),
+        }
+    }
+
+    /// Returns the key of the entry, (e.g.: order~clothing~iStore)
+    pub fn key(&self) -> String {
+        format!(
+            "{}{}{}{}{}",
+            self.category, DELIMITER, self.subcategory, DELIMITER, self.source_name
+        )
+    }
+}
+
+/// Trait for the external data catalogs
+pub trait DataCatalog {
+    /// Registers the entry, replacing the entry of the same combination if it exists
+    ///
+    /// # Arguments
+    ///
+    /// * entry: &CatalogEntry - The entry to register.</br>
+    fn register(&self, entry: &CatalogEntry) -> Result<(), DaaSProcessingError>;
+}
+
+/// Represents a generic REST catalog, where the entries are PUT as JSON to `{url}/{category}/{subcategory}/{source_name}`
+pub struct RestCatalog {
+    /// The URL of the catalog, (e.g.: https://catalog.example.com/api/datasets)
+    pub url: String,
+    /// The bearer token of the catalog
+    pub token: Option<String>,
+    client: reqwest::blocking::Client,
+}
+
+impl RestCatalog {
+    /// Constructs a RestCatalog
+    ///
+    /// # Arguments
+    ///
+    /// * url: String - The URL of the catalog.</br>
+    pub fn new(url: String) -> RestCatalog {
+        RestCatalog {
+            url: url.trim_end_matches('/').to_string(),
+            token: None,
+            client: reqwest::blocking::Client::builder()
+                .timeout(Duration::from_secs(30))
+                .build()
+                .unwrap(),
+        }
+    }
+
+    /// Sets the bearer token of the catalog
+    pub fn with_token(mut self, token: String) -> RestCatalog {
+        self.token = Some(token);
+        self
+    }
+}
+
+impl DataCatalog for RestCatalog {
+    fn register(&self, entry: &CatalogEntry) -> Result<(), DaaSProcessingError> {
+        let url = format!(
+            "{}/{}/{}/{}",
+            self.url, entry.category, entry.subcategory, entry.source_name
+        );
+        let mut request = self
+            .client
+            .put(&url)
+            .header("Content-Type", "application/json")
+            .body(serde_json::to_string(entry).unwrap());
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+
+        match request.send() {
+            Ok(r) if r.status().is_success() => Ok(()),
+            Ok(r) => {
+                error!("The catalog {} responded with {}.", url, r.status());
+                Err(DaaSProcessingError::BrokerError)
+            }
+            Err(err) => {
+                error!("Could not call the catalog {}. Error: {}", url, err);
+                Err(DaaSProcessingError::BrokerError)
+            }
+        }
+    }
+}
+
+/// Represents the AWS Glue Data Catalog, where each entry is a table of the database
+pub struct GlueCatalog {
+    /// The enum that represents the AWS region of the catalog, (e.g.: Region::UsEast1)
+    pub region: Region,
+    /// The name of the Glue database
+    pub database: String,
+}
+
+impl GlueCatalog {
+    /// Constructs a GlueCatalog, which uses the default AWS credentials
+    ///
+    /// # Arguments
+    ///
+    /// * region: Region - The enum that represents the AWS region of the catalog.</br>
+    /// * database: String - The name of the Glue database, (which must exist).</br>
+    pub fn new(region: Region, database: String) -> GlueCatalog {
+        GlueCatalog { region, database }
+    }
+
+    /// Returns the name of the table of the entry, (Glue only allows lowercase letters, numbers and underscores)
+    ///
+    /// # Arguments
+    ///
+    /// * entry: &CatalogEntry - The entry to register.</br>
+    pub fn table_name(entry: &CatalogEntry) -> String {
+        format!(
+            "{}_{}_{}",
+            entry.category, entry.subcategory, entry.source_name
+        )
+        .to_lowercase()
+        .chars()
+        .map(|c| match c.is_ascii_alphanumeric() {
+            true => c,
+            false => '_',
+        })
+        .collect()
+    }
+
+    /// Returns the TableInput of the entry, where the top level fields of the schema are the columns
+    ///
+    /// # Arguments
+    ///
+    /// * entry: &CatalogEntry - The entry to register.</br>
+    pub fn table_input(entry: &CatalogEntry) -> Value {
+        let columns: Vec<Value> = match &entry.schema {
+            Some(Value::Object(fields)) => fields
+                .iter()
+                .map(|(name, schema)| {
+                    let glue_type = match schema.as_str() {
+                        Some("boolean") => "boolean",
+                        Some("integer") => "bigint",
+                        Some("double") => "double",
+                        _ => "string",
+                    };
+                    json!({"Name": name, "Type": glue_type, "Comment": schema.to_string()})
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+        let agreements: Vec<String> = entry
+            .data_usage_agreements
+            .iter()
+            .map(|dua| format!("{} ({})", dua.agreement_name, dua.location))
+            .collect();
+
+        json!({
+            "Name": GlueCatalog::table_name(entry),
+            "Description": format!("DaaS documents of {}", entry.key()),
+            "StorageDescriptor": {"Columns": columns},
+            "Parameters": {
+                "daas_category": entry.category,
+                "daas_subcategory": entry.subcategory,
+                "daas_source_name": entry.source_name,
+                "daas_schema_fingerprint": entry.schema_fingerprint.clone().unwrap_or_default(),
+                "daas_data_usage_agreements": agreements.join(", "),
+            }
+        })
+    }
+
+    // Calls the action of the Glue API and returns the status and body of the response
+    async fn call(&self, action: &str, body: Value) -> Result<(u16, String), DaaSProcessingError> {
+        let mut request = SignedRequest::new("POST", "glue", &self.region, "/");
+        request.set_content_type("application/x-amz-json-1.1".to_string());
+        request.add_header("x-amz-target", &format!("AWSGlue.{}", action));
+        request.set_payload(Some(body.to_string().into_bytes()));
+
+        let mut response = match Client::shared().sign_and_dispatch(request).await {
+            Ok(r) => r,
+            Err(err) => {
+                error!("Could not call AWS Glue {}. Error: {:?}", action, err);
+                return Err(DaaSProcessingError::BrokerError);
+            }
+        };
+        match response.buffer().await {
+            Ok(r) => Ok((
+                r.status.as_u16(),
+                String::from_utf8_lossy(&r.body).to_string(),
+            )),
+            Err(err) => {
+                error!(
+                    "Could not read the response of AWS Glue {}. Error: {}",
+                    action, err
+                );
+                Err(DaaSProcessingError::BrokerError)
+            }
+        }
+    }
+}
+
+impl DataCatalog for GlueCatalog {
+    fn register(&self, entry: &CatalogEntry) -> Result<(), DaaSProcessingError> {
+        let body =
+            json!({"DatabaseName": self.database, "TableInput": GlueCatalog::table_input(entry)});
+        let rt = Runtime::new().unwrap();
+
+        // the table is updated when the combination was registered before, (e.g.: the schema has changed)
+        let result = match rt.block_on(self.call("CreateTable", body.clone()))? {
+            (status, resp) if status == 400 && resp.contains("AlreadyExistsException") => {
+                rt.block_on(self.call("UpdateTable", body))?
+            }
+            other => other,
+        };
+
+        match result {
+            (status, _) if (200..300).contains(&status) => Ok(()),
+            (status, resp) => {
+                error!(
+                    "AWS Glue could not register the table {}. Status: {} Error: {}",
+                    GlueCatalog::table_name(entry),
+                    status,
+                    resp
+                );
+                Err(DaaSProcessingError::BrokerError)
+            }
+        }
+    }
+}
+
+/// Represents a sink that registers the new combinations of category, subcategory and source name in a data catalog
+pub struct CatalogSink<C: DataCatalog> {
+    /// The data catalog
+    pub catalog: C,
+    registered: Mutex<HashMap<String, CatalogEntry>>,
+}
+
+impl<C: DataCatalog> CatalogSink<C> {
+    /// Constructs a CatalogSink that hasn't registered any combination yet
+    ///
+    /// # Arguments
+    ///
+    /// * catalog: C - The data catalog.</br>
+    pub fn new(catalog: C) -> CatalogSink<C> {
+        CatalogSink {
+            catalog,
+            registered: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the entries that have been registered, sorted by their key
+    pub fn inventory(&self) -> Vec<CatalogEntry> {
+        let mut entries: Vec<CatalogEntry> =
+            self.registered.lock().unwrap().values().cloned().collect();
+        entries.sort_by_key(|e| e.key());
+        entries
+    }
+}
+
+impl<C: DataCatalog> DaaSDocSink for CatalogSink<C> {
+    fn send(&self, doc: DaaSDoc) -> Result<(), DaaSProcessingError> {
+        let entry = CatalogEntry::from_doc(&doc);
+        let mut registered = self.registered.lock().unwrap();
+
+        let known = match registered.get(&entry.key()) {
+            Some(e) => e.schema_fingerprint == entry.schema_fingerprint,
+            None => false,
+        };
+        if known {
+            return Ok(());
+        }
+
+        // the entry is only remembered once it is registered, so a failed registration is retried with the next document
+        self.catalog.register(&entry)?;
+        info!(
+            "Registered {} in the data catalog with schema {:?}.",
+            entry.key(),
+            entry.schema_fingerprint
+        );
+        registered.insert(entry.key(), entry);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[derive(Clone, Default)]
+    struct TestCatalog {
+        entries: Arc<Mutex<Vec<CatalogEntry>>>,
+    }
+
+    impl DataCatalog for TestCatalog {
+        fn register(&self, entry: &CatalogEntry) -> Result<(), DaaSProcessingError> {
+            self.entries.lock().unwrap().push(entry.clone());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_infer_schema() {
+        let schema = infer_schema(
+            br#"{"id": 1, "price": 9.99, "tags": ["a", "b"], "items": [{"sku": 1}, {"qty": 2, "sku": 3}], "note": null}"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            schema,
+            json!({
+                "id": "integer",
+                "price": "double",
+                "tags": ["string"],
+                "items": [{"qty": "integer", "sku": "integer"}],
+                "note": "null"
+            })
+        );
+        assert_eq!(infer_schema(br#"[1, "a"]"#).unwrap(), json!(["mixed"]));
+        assert!(infer_schema(b"name=jdoe").is_none());
+    }
+
+    #[test]
+    fn test_schema_fingerprint() {
+        let fp = schema_fingerprint(br#"{"status": "new", "qty": 1}"#).unwrap();
+
+        assert_eq!(fp.len(), 64);
+        assert_eq!(
+            Some(fp.clone()),
+            schema_fingerprint(br#"{"qty": 7, "status": "shipped"}"#)
+        );
+        assert_ne!(
+            Some(fp),
+            schema_fingerprint(br#"{"status": "new", "qty": "1"}"#)
+        );
+    }
+
+    #[test]
+    fn test_catalog_entry() {
+        let entry = CatalogEntry::from_doc(&testing::get_default_daas_doc());
+
+        assert_eq!(entry.key(), "order~clothing~iStore".to_string());
+        assert_eq!(entry.schema, Some(json!({"status": "string"})));
+        assert_eq!(entry.data_usage_agreements[0].agreement_name, "billing");
+    }
+
+    #[test]
+    fn test_sink_registers_new_combinations() {
+        let catalog = TestCatalog::default();
+        let sink = CatalogSink::new(catalog.clone());
+        let mut doc = testing::get_default_daas_doc();
+
+        sink.send(doc.clone()).unwrap();
+        sink.send(doc.clone()).unwrap();
+        assert_eq!(catalog.entries.lock().unwrap().len(), 1);
+
+        // a new schema of the same combination
+        doc.data_obj = br#"{"status": "new", "qty": 1}"#.to_vec();
+        sink.send(doc.clone()).unwrap();
+        assert_eq!(catalog.entries.lock().unwrap().len(), 2);
+
+        // a new combination
+        doc.source_name = "eStore".to_string();
+        sink.send(doc).unwrap();
+        assert_eq!(catalog.entries.lock().unwrap().len(), 3);
+
+        let inventory = sink.inventory();
+        assert_eq!(inventory.len(), 2);
+        assert_eq!(inventory[0].key(), "order~clothing~eStore".to_string());
+        assert_eq!(
+            inventory[1].schema,
+            Some(json!({"qty": "integer", "status": "string"}))
+        );
+    }
+
+    #[test]
+    fn test_glue_table_input() {
+        let mut doc = testing::get_default_daas_doc();
+        doc.data_obj = br#"{"status": "new", "qty": 1, "lines": [{"sku": 1}]}"#.to_vec();
+        let input = GlueCatalog::table_input(&CatalogEntry::from_doc(&doc));
+
+        assert_eq!(input["Name"], json!("order_clothing_istore"));
+        assert_eq!(
+            input["StorageDescriptor"]["Columns"][1],
+            json!({"Name": "qty", "Type": "bigint", "Comment": "\"integer\""})
+        );
+        assert_eq!(
+            input["StorageDescriptor"]["Columns"][0]["Type"],
+            json!("string")
+        );
+        assert_eq!(
+            input["Parameters"]["daas_schema_fingerprint"],
+            json!(schema_fingerprint(&doc.data_obj).unwrap())
+        );
+    }
+
+    #[test]
+    fn test_rest_catalog_register() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/catalog/", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0; 16384];
+            let len = stream.read(&mut buf).unwrap();
+            stream
+                .write_all(b"HTTP/1.1 204 No Content\r\nContent-Length: 0\r\n\r\n")
+                .unwrap();
+            String::from_utf8_lossy(&buf[..len]).to_string()
+        });
+
+        let catalog = RestCatalog::new(url).with_token("secret".to_string());
+        let entry = CatalogEntry::from_doc(&testing::get_default_daas_doc());
+        assert!(catalog.register(&entry).is_ok());
+
+        let request = server.join().unwrap();
+        assert!(request.starts_with("PUT /catalog/order/clothing/iStore"));
+        assert!(request.contains("Bearer secret"));
+    }
+
+    #[test]
+    fn test_rest_catalog_unreachable() {
+        let sink = CatalogSink::new(RestCatalog::new("http://127.0.0.1:1".to_string()));
+
+        assert!(sink.send(testing::get_default_daas_doc()).is_err());
+        assert!(sink.inventory().is_empty());
+    }
+}
@@ -0,0 +1,366 @@
+//! A sink that indexes DaaS documents into Elasticsearch or OpenSearch using the bulk API.
+//!
+//! Each DaaS document is indexed as its envelope, (identifier, category, author, metadata, tags, etc.) with the JSON
+//! data object as the `payload` field, so it can be searched and used in dashboards, (e.g.: Kibana).
+//! The name of the index is a pattern of the category, subcategory, source name and date of the DaaS document.
+//!
+//! # Examples
+//!
+//! ```
+//! extern crate daas;
+//!
+//! use daas::sinks::elasticsearch::ElasticsearchSink;
+//! use daas::testing;
+//!
+//! fn main() {
+//!     let sink = ElasticsearchSink::new("http://localhost:9200".to_string())
+//!         .with_index_pattern("daas-{category}-{date}".to_string())
+//!         .with_batch_size(500);
+//!     let doc = testing::get_default_daas_doc();
+//!
+//!     assert!(sink.index_name(&doc).starts_with("daas-order-"));
+//! }
+//! ```
+
+use super::*;
+use crate::doc::DaaSDoc;
+use crate::errors::daaserror::DaaSProcessingError;
+use crate::service::pipeline::DaaSDocSink;
+use serde_json::{json, Value};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+/// Represents a sink that indexes the DaaS documents in batches
+pub struct ElasticsearchSink {
+    /// The URL of the Elasticsearch cluster, (e.g.: http://localhost:9200)
+    pub url: String,
+    /// The name of the index, where `{category}`, `{subcategory}`, `{source_name}` and `{date}` (YYYY.MM.DD) are
+    /// replaced with the values of the DaaS document, (default: daas-{category}-{subcategory})
+    pub index_pattern: String,
+    /// The number of buffered DaaS documents that are indexed together, (default: 1)
+    pub batch_size: usize,
+    /// The number of times a failed bulk request is retried, (default: 3)
+    pub retries: u8,
+    /// The username and password of the cluster
+    pub auth: Option<(String, String)>,
+    buffer: Mutex<Vec<DaaSDoc>>,
+    client: reqwest::blocking::Client,
+}
+
+impl ElasticsearchSink {
+    /// Constructs an ElasticsearchSink that indexes each DaaS document immediately
+    ///
+    /// # Arguments
+    ///
+    /// * url: String - The URL of the Elasticsearch cluster.</br>
+    pub fn new(url: String) -> ElasticsearchSink {
+        ElasticsearchSink {
+            url: url.trim_end_matches('/').to_string(),
+            index_pattern: "daas-{category}-{subcategory}".to_string(),
+            batch_size: 1,
+            retries: 3,
+            auth: None,
+            buffer: Mutex::new(Vec::new()),
+            client: reqwest::blocking::Client::builder()
+                .timeout(Duration::from_secs(30))
+                .build()
+                .unwrap(),
+        }
+    }
+
+    /// Sets the pattern of the index names
+    pub fn with_index_pattern(mut self, pattern: String) -> ElasticsearchSink {
+        self.index_pattern = pattern;
+        self
+    }
+
+    /// Sets the number of buffered DaaS documents that are indexed together
+    pub fn with_batch_size(mut self, batch_size: usize) -> ElasticsearchSink {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Sets the number of times a failed bulk request is retried
+    pub fn with_retries(mut self, retries: u8) -> ElasticsearchSink {
+        self.retries = retries;
+        self
+    }
+
+    /// Sets the username and password of the cluster
+    pub fn with_auth(mut self, user: String, password: String) -> ElasticsearchSink {
+        self.auth = Some((user, password));
+        self
+    }
+
+    /// Returns the name of the index of the DaaS document, (lowercase as required by Elasticsearch)
+    ///
+    /// # Arguments
+    ///
+    /// * doc: &DaaSDoc - The DaaS document to index.</br>
+    pub fn index_name(&self, doc: &DaaSDoc) -> String {
+        self.index_pattern
+            .replace("{category}", &doc.category)
+            .replace("{subcategory}", &doc.subcategory)
+            .replace("{source_name}", &doc.source_name)
+            .replace("{date}", &format_date(doc.last_updated, '.'))
+            .to_lowercase()
+    }
+
+    /// Returns the source of the indexed DaaS document, which is the envelope with the data object as `payload`
+    ///
+    /// # Arguments
+    ///
+    /// * doc: &DaaSDoc - The DaaS document to index.</br>
+    pub fn to_source(doc: &DaaSDoc) -> Value {
+        let mut source = serde_json::to_value(doc).unwrap();
+        let obj = source.as_object_mut().unwrap();
+
+        // the identifier and revision are metadata fields of Elasticsearch
+        obj.remove("_id");
+        obj.remove("_rev");
+        obj.remove("data_obj");
+        obj.insert("doc_id".to_string(), Value::from(doc._id.clone()));
+        obj.insert(
+            "payload".to_string(),
+            match serde_json::from_slice::<Value>(&doc.data_obj) {
+                Ok(v) => v,
+                Err(_) => Value::from(String::from_utf8_lossy(&doc.data_obj).to_string()),
+            },
+        );
+
+        source
+    }
+
+    /// Returns the body of the bulk request that indexes the DaaS documents, (newline delimited JSON)
+    ///
+    /// # Arguments
+    ///
+    /// * docs: &[DaaSDoc] - The DaaS documents to index.</br>
+    pub fn bulk_body(&self, docs: &[DaaSDoc]) -> String {
+        docs.iter()
+            .map(|d| {
+                let action = json!({"index": {"_index": self.index_name(d), "_id": d._id}});
+                format!("{}\n{}\n", action, ElasticsearchSink::to_source(d))
+            })
+            .collect()
+    }
+
+    // Sends the bulk request, retrying the DaaS documents that were rejected because the cluster was busy
+    fn index(&self, mut docs: Vec<DaaSDoc>) -> Result<(), DaaSProcessingError> {
+        let mut attempt = 0;
+
+        loop {
+            let failed = match self.send_bulk(&docs) {
+                Ok(failed) => failed,
+                Err(_) => (0..docs.len()).collect(),
+            };
+
+            if failed.is_empty() {
+                return Ok(());
+            }
+            if attempt >= self.retries {
+                error!(
+                    "Could not index {} DaaS documents into {}.",
+                    failed.len(),
+                    self.url
+                );
+                return Err(DaaSProcessingError::UpsertError);
+            }
+
+            attempt += 1;
+            docs = failed.iter().map(|i| docs[*i].clone()).collect();
+            warn!(
+                "Retrying to index {} DaaS documents (attempt {}).",
+                docs.len(),
+                attempt
+            );
+            thread::sleep(Duration::from_millis(100 * attempt as u64));
+        }
+    }
+
+    // Returns the positions of the DaaS documents that should be retried
+    fn send_bulk(&self, docs: &[DaaSDoc]) -> Result<Vec<usize>, DaaSProcessingError> {
+        let mut request = self
+            .client
+            .post(format!("{}/_bulk", self.url))
+            .header("Content-Type", "application/x-ndjson")
+            .body(self.bulk_body(docs));
+        if let Some((user, password)) = &self.auth {
+            request = request.basic_auth(user, Some(password));
+        }
+
+        let response = match request.send() {
+            Ok(r) if r.status().is_success() => r,
+            Ok(r) => {
+                warn!("Elasticsearch {} responded with {}.", self.url, r.status());
+                return Err(DaaSProcessingError::BrokerError);
+            }
+            Err(err) => {
+                warn!("Could not call Elasticsearch {}. Error: {}", self.url, err);
+                return Err(DaaSProcessingError::BrokerError);
+            }
+        };
+        let result: Value = response
+            .bytes()
+            .ok()
+            .and_then(|b| serde_json::from_slice(&b).ok())
+            .unwrap_or(Value::Null);
+
+        Ok(ElasticsearchSink::get_retryable(&result))
+    }
+
+    // Returns the positions of the items of the bulk response that were rejected because the cluster was busy.
+    // The items that failed for other reasons, (e.g.: a mapping conflict) can't be fixed by retrying.
+    fn get_retryable(result: &Value) -> Vec<usize> {
+        if result["errors"] != Value::Bool(true) {
+            return Vec::new();
+        }
+
+        result["items"]
+            .as_array()
+            .map(|items| {
+                items
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(i, item)| {
+                        let status = item["index"]["status"].as_u64().unwrap_or(0);
+                        match status {
+                            429 | 500..=599 => Some(i),
+                            s if s >= 300 => {
+                                error!(
+                                    "Elasticsearch rejected the DaaS document {}. Error: {}",
+                                    item["index"]["_id"], item["index"]["error"]
+                                );
+                                None
+                            }
+                            _ => None,
+                        }
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+impl DaaSDocSink for ElasticsearchSink {
+    fn send(&self, doc: DaaSDoc) -> Result<(), DaaSProcessingError> {
+        let batch = {
+            let mut buffer = self.buffer.lock().unwrap();
+            buffer.push(doc);
+            match buffer.len() >= self.batch_size {
+                true => buffer.drain(..).collect(),
+                false => Vec::new(),
+            }
+        };
+
+        match batch.is_empty() {
+            true => Ok(()),
+            false => self.index(batch),
+        }
+    }
+
+    fn flush(&self) -> Result<(), DaaSProcessingError> {
+        let batch: Vec<DaaSDoc> = self.buffer.lock().unwrap().drain(..).collect();
+
+        match batch.is_empty() {
+            true => Ok(()),
+            false => self.index(batch),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_format_date() {
+        assert_eq!(format_date(0, '.'), "1970.01.01".to_string());
+        assert_eq!(format_date(1553988607, '.'), "2019.03.30".to_string());
+        assert_eq!(format_date(951782400, '.'), "2000.02.29".to_string());
+    }
+
+    #[test]
+    fn test_index_name() {
+        let sink = ElasticsearchSink::new("http://localhost:9200/".to_string())
+            .with_index_pattern("{source_name}-{category}-{date}".to_string());
+        let mut doc = testing::get_default_daas_doc();
+        doc.last_updated = 1553988607;
+
+        assert_eq!(sink.url, "http://localhost:9200".to_string());
+        assert_eq!(sink.index_name(&doc), "istore-order-2019.03.30".to_string());
+    }
+
+    #[test]
+    fn test_bulk_body() {
+        let sink = ElasticsearchSink::new("http://localhost:9200".to_string());
+        let body = sink.bulk_body(&[testing::get_default_daas_doc()]);
+        let lines: Vec<Value> = body
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["index"]["_index"], json!("daas-order-clothing"));
+        assert_eq!(
+            lines[0]["index"]["_id"],
+            json!("order~clothing~iStore~5000")
+        );
+        assert_eq!(lines[1]["doc_id"], json!("order~clothing~iStore~5000"));
+        assert_eq!(lines[1]["payload"]["status"], json!("new"));
+        assert!(lines[1].get("_id").is_none());
+    }
+
+    #[test]
+    fn test_get_retryable() {
+        let result = json!({"errors": true, "items": [
+            {"index": {"_id": "a", "status": 201}},
+            {"index": {"_id": "b", "status": 429}},
+            {"index": {"_id": "c", "status": 400, "error": "mapper_parsing_exception"}}
+        ]});
+
+        assert_eq!(ElasticsearchSink::get_retryable(&result), vec![1]);
+        assert!(ElasticsearchSink::get_retryable(&json!({"errors": false})).is_empty());
+    }
+
+    #[test]
+    fn test_send_batched() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0; 16384];
+            let len = stream.read(&mut buf).unwrap();
+            let body = r#"{"errors": false, "items": []}"#;
+            stream
+                .write_all(
+                    format!(
+                        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}",
+                        body.len(),
+                        body
+                    )
+                    .as_bytes(),
+                )
+                .unwrap();
+            String::from_utf8_lossy(&buf[..len]).to_string()
+        });
+
+        let sink = ElasticsearchSink::new(url).with_batch_size(2);
+        assert!(sink.send(testing::get_default_daas_doc()).is_ok());
+        assert_eq!(sink.buffer.lock().unwrap().len(), 1);
+        assert!(sink.flush().is_ok());
+        assert!(sink.buffer.lock().unwrap().is_empty());
+        assert!(server.join().unwrap().starts_with("POST /_bulk"));
+    }
+
+    #[test]
+    fn test_send_unreachable() {
+        let sink = ElasticsearchSink::new("http://127.0.0.1:1".to_string()).with_retries(1);
+
+        assert!(sink.send(testing::get_default_daas_doc()).is_err());
+    }
+}
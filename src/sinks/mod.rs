@@ -0,0 +1,11 @@
+//! The sinks module contains the destinations that DaaS documents consumed from the topics are written to,
+//! (e.g.: the rows of a relational database table or the documents of an Elasticsearch index).
+//!
+//! The sinks implement `DaaSDocSink`, so they are used at the end of a `Pipeline`, which runs on any processor.
+
+use super::*;
+
+#[cfg(feature = "s3")]
+pub mod catalog;
+pub mod elasticsearch;
+pub mod sql;
@@ -0,0 +1,339 @@
+//! A sink that maps the JSON data objects of DaaS documents into the rows of a Postgres or MySQL table.
+//!
+//! The `TableMapping` configures which column is set to which field of the DaaS document and generates the
+//! parameterized upsert statement of the dialect. The statement is executed by a `SqlExecutor`, which wraps the
+//! database driver of the application, (e.g.: the postgres or mysql crate).
+//!
+//! # Examples
+//!
+//! ```
+//! extern crate daas;
+//!
+//! use daas::sinks::sql::{ColumnValue, SqlDialect, TableMapping};
+//!
+//! fn main() {
+//!     let mapping = TableMapping::new("orders".to_string(), SqlDialect::Postgres)
+//!         .with_column("id".to_string(), ColumnValue::DocId)
+//!         .with_column("status".to_string(), ColumnValue::Data("/status".to_string()))
+//!         .with_key("id".to_string());
+//!
+//!     assert_eq!(
+//!         mapping.upsert_statement(),
+//!         r#"INSERT INTO "orders" ("id", "status") VALUES ($1, $2) ON CONFLICT ("id") DO UPDATE SET "status" = EXCLUDED."status""#.to_string()
+//!     );
+//! }
+//! ```
+
+use super::*;
+use crate::doc::DaaSDoc;
+use crate::errors::daaserror::DaaSProcessingError;
+use crate::errors::UpsertError;
+use crate::service::pipeline::DaaSDocSink;
+use serde_json::Value;
+
+/// The SQL dialect of the database
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SqlDialect {
+    /// PostgreSQL, (placeholders $1, $2 ... and ON CONFLICT)
+    Postgres,
+    /// MySQL, (placeholders ? and ON DUPLICATE KEY UPDATE)
+    MySql,
+}
+
+/// The field of the DaaS document that a column is set to
+#[derive(Debug, Clone, PartialEq)]
+pub enum ColumnValue {
+    /// The value at the JSON pointer of the data object, (e.g.: /customer/id) or NULL if it is missing
+    Data(String),
+    /// The identifier of the DaaS document
+    DocId,
+    /// The author of the DaaS document
+    Author,
+    /// When the DaaS document was last updated, (Unix seconds)
+    LastUpdated,
+}
+
+/// Represents how the DaaS documents are mapped into the rows of a table
+#[derive(Debug, Clone)]
+pub struct TableMapping {
+    /// The name of the table
+    pub table: String,
+    /// The SQL dialect of the database
+    pub dialect: SqlDialect,
+    /// The columns of the table and the fields they are set to
+    pub columns: Vec<(String, ColumnValue)>,
+    /// The columns of the unique key, which determine if a row is inserted or updated
+    pub keys: Vec<String>,
+}
+
+impl TableMapping {
+    /// Constructs a TableMapping without any columns
+    ///
+    /// # Arguments
+    ///
+    /// * table: String - The name of the table.</br>
+    /// * dialect: SqlDialect - The SQL dialect of the database.</br>
+    pub fn new(table: String, dialect: SqlDialect) -> TableMapping {
+        TableMapping {
+            table,
+            dialect,
+            columns: Vec::new(),
+            keys: Vec::new(),
+        }
+    }
+
+    /// Adds a column that is set to the field of the DaaS document
+    pub fn with_column(mut self, column: String, value: ColumnValue) -> TableMapping {
+        self.columns.push((column, value));
+        self
+    }
+
+    /// Adds a column to the unique key. Without a unique key the rows are always inserted.
+    pub fn with_key(mut self, column: String) -> TableMapping {
+        self.keys.push(column);
+        self
+    }
+
+    /// Returns the parameterized statement that inserts the row, or updates it if the unique key already exists
+    pub fn upsert_statement(&self) -> String {
+        let columns: Vec<String> = self.columns.iter().map(|(c, _)| self.quote(c)).collect();
+        let placeholders: Vec<String> = (1..=self.columns.len())
+            .map(|i| match self.dialect {
+                SqlDialect::Postgres => format!("${}", i),
+                SqlDialect::MySql => "?".to_string(),
+            })
+            .collect();
+        let updates: Vec<&String> = self
+            .columns
+            .iter()
+            .map(|(c, _)| c)
+            .filter(|c| !self.keys.contains(c))
+            .collect();
+        let insert = format!(
+            "INTO {} ({}) VALUES ({})",
+            self.quote(&self.table),
+            columns.join(", "),
+            placeholders.join(", ")
+        );
+
+        if self.keys.is_empty() {
+            return format!("INSERT {}", insert);
+        }
+
+        match self.dialect {
+            SqlDialect::Postgres => {
+                let keys: Vec<String> = self.keys.iter().map(|k| self.quote(k)).collect();
+                let action = match updates.is_empty() {
+                    true => "DO NOTHING".to_string(),
+                    false => format!(
+                        "DO UPDATE SET {}",
+                        updates
+                            .iter()
+                            .map(|c| format!("{} = EXCLUDED.{}", self.quote(c), self.quote(c)))
+                            .collect::<Vec<String>>()
+                            .join(", ")
+                    ),
+                };
+                format!(
+                    "INSERT {} ON CONFLICT ({}) {}",
+                    insert,
+                    keys.join(", "),
+                    action
+                )
+            }
+            SqlDialect::MySql => match updates.is_empty() {
+                true => format!("INSERT IGNORE {}", insert),
+                false => format!(
+                    "INSERT {} ON DUPLICATE KEY UPDATE {}",
+                    insert,
+                    updates
+                        .iter()
+                        .map(|c| format!("{} = VALUES({})", self.quote(c), self.quote(c)))
+                        .collect::<Vec<String>>()
+                        .join(", ")
+                ),
+            },
+        }
+    }
+
+    /// Returns the values of the columns for the DaaS document, in the order of the columns
+    ///
+    /// # Arguments
+    ///
+    /// * doc: &DaaSDoc - The DaaS document to map.</br>
+    pub fn row(&self, doc: &DaaSDoc) -> Result<Vec<Value>, DaaSProcessingError> {
+        let data: Value = match serde_json::from_slice(&doc.data_obj) {
+            Ok(d) => d,
+            Err(_err) => {
+                error!("The data object of DaaS document {} is not JSON.", doc._id);
+                return Err(DaaSProcessingError::RetrieveError);
+            }
+        };
+
+        Ok(self
+            .columns
+            .iter()
+            .map(|(_, value)| match value {
+                ColumnValue::Data(path) => data.pointer(path).cloned().unwrap_or(Value::Null),
+                ColumnValue::DocId => Value::from(doc._id.clone()),
+                ColumnValue::Author => Value::from(doc.author.clone()),
+                ColumnValue::LastUpdated => Value::from(doc.last_updated),
+            })
+            .collect())
+    }
+
+    // Quotes the identifier for the dialect, escaping the quotes in the identifier
+    fn quote(&self, identifier: &str) -> String {
+        match self.dialect {
+            SqlDialect::Postgres => format!("\"{}\"", identifier.replace('"', "\"\"")),
+            SqlDialect::MySql => format!("`{}`", identifier.replace('`', "``")),
+        }
+    }
+}
+
+/// Trait for executing the statements with the database driver of the application
+pub trait SqlExecutor {
+    /// Executes the parameterized statement and returns the number of affected rows
+    ///
+    /// # Arguments
+    ///
+    /// * statement: &str - The statement, (see `TableMapping::upsert_statement()`).</br>
+    /// * params: &[Value] - The values of the placeholders, (see `TableMapping::row()`).</br>
+    fn execute(&self, statement: &str, params: &[Value]) -> Result<u64, UpsertError>;
+}
+
+/// A sink that upserts the DaaS documents as the rows of a table
+pub struct TableSink<E: SqlExecutor> {
+    /// How the DaaS documents are mapped into rows
+    pub mapping: TableMapping,
+    /// The executor of the statements
+    pub executor: E,
+    statement: String,
+}
+
+impl<E: SqlExecutor> TableSink<E> {
+    /// Constructs a TableSink
+    ///
+    /// # Arguments
+    ///
+    /// * mapping: TableMapping - How the DaaS documents are mapped into rows.</br>
+    /// * executor: E - The executor of the statements.</br>
+    pub fn new(mapping: TableMapping, executor: E) -> TableSink<E> {
+        TableSink {
+            statement: mapping.upsert_statement(),
+            mapping,
+            executor,
+        }
+    }
+}
+
+impl<E: SqlExecutor> DaaSDocSink for TableSink<E> {
+    fn send(&self, doc: DaaSDoc) -> Result<(), DaaSProcessingError> {
+        let row = self.mapping.row(&doc)?;
+
+        match self.executor.execute(&self.statement, &row) {
+            Ok(_n) => Ok(()),
+            Err(err) => {
+                error!(
+                    "Could not upsert DaaS document {} into {}. Error: {}",
+                    doc._id, self.mapping.table, err
+                );
+                Err(DaaSProcessingError::UpsertError)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use std::sync::{Arc, Mutex};
+
+    // An executor that records the statements
+    #[derive(Clone, Default)]
+    struct RecordingExecutor {
+        executed: Arc<Mutex<Vec<(String, Vec<Value>)>>>,
+    }
+
+    impl SqlExecutor for RecordingExecutor {
+        fn execute(&self, statement: &str, params: &[Value]) -> Result<u64, UpsertError> {
+            self.executed
+                .lock()
+                .unwrap()
+                .push((statement.to_string(), params.to_vec()));
+            Ok(1)
+        }
+    }
+
+    fn get_mapping(dialect: SqlDialect) -> TableMapping {
+        TableMapping::new("orders".to_string(), dialect)
+            .with_column("id".to_string(), ColumnValue::DocId)
+            .with_column(
+                "status".to_string(),
+                ColumnValue::Data("/status".to_string()),
+            )
+            .with_column("total".to_string(), ColumnValue::Data("/total".to_string()))
+            .with_key("id".to_string())
+    }
+
+    #[test]
+    fn test_upsert_statement_mysql() {
+        assert_eq!(
+            get_mapping(SqlDialect::MySql).upsert_statement(),
+            "INSERT INTO `orders` (`id`, `status`, `total`) VALUES (?, ?, ?) ON DUPLICATE KEY UPDATE `status` = VALUES(`status`), `total` = VALUES(`total`)".to_string()
+        );
+    }
+
+    #[test]
+    fn test_upsert_statement_keys_only() {
+        let mapping = TableMapping::new("seen".to_string(), SqlDialect::Postgres)
+            .with_column("id".to_string(), ColumnValue::DocId)
+            .with_key("id".to_string());
+
+        assert_eq!(
+            mapping.upsert_statement(),
+            r#"INSERT INTO "seen" ("id") VALUES ($1) ON CONFLICT ("id") DO NOTHING"#.to_string()
+        );
+    }
+
+    #[test]
+    fn test_upsert_statement_without_keys() {
+        let mapping = TableMapping::new("my\"table".to_string(), SqlDialect::Postgres)
+            .with_column("id".to_string(), ColumnValue::DocId);
+
+        assert_eq!(
+            mapping.upsert_statement(),
+            r#"INSERT INTO "my""table" ("id") VALUES ($1)"#.to_string()
+        );
+    }
+
+    #[test]
+    fn test_row() {
+        let doc = testing::get_default_daas_doc();
+        let row = get_mapping(SqlDialect::Postgres).row(&doc).unwrap();
+
+        assert_eq!(
+            row,
+            vec![
+                Value::from("order~clothing~iStore~5000"),
+                Value::from("new"),
+                Value::Null
+            ]
+        );
+    }
+
+    #[test]
+    fn test_table_sink() {
+        let executor = RecordingExecutor::default();
+        let sink = TableSink::new(get_mapping(SqlDialect::Postgres), executor.clone());
+        let mut doc = testing::get_default_daas_doc();
+
+        assert!(sink.send(doc.clone()).is_ok());
+        assert_eq!(executor.executed.lock().unwrap().len(), 1);
+
+        doc.data_obj = b"not json".to_vec();
+        assert!(sink.send(doc).is_err());
+        assert_eq!(executor.executed.lock().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,225 @@
+//! A connector that watches a directory, where each file is a record whose unique identifier is the file name
+//! (without the extension). The files that were added, modified or removed since they were last committed are the
+//! records that changed.
+
+use super::*;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Represents a connector that watches the files of a directory
+pub struct FileSystemConnector {
+    /// The directory to watch
+    pub path: String,
+    /// The name of the source, (e.g.: iStore)
+    pub source_name: String,
+    /// The category of the records, (e.g.: order)
+    pub category: String,
+    /// The subcategory of the records, (e.g.: clothing)
+    pub subcategory: String,
+    seen: HashMap<PathBuf, SystemTime>,
+    // the modification time of the files of the last poll, (None if the file was removed)
+    polled: HashMap<PathBuf, Option<SystemTime>>,
+}
+
+impl FileSystemConnector {
+    /// Constructs a FileSystemConnector
+    ///
+    /// # Arguments
+    ///
+    /// * path: String - The directory to watch.</br>
+    /// * source_name: String - The name of the source.</br>
+    /// * category: String - The category of the records.</br>
+    /// * subcategory: String - The subcategory of the records.</br>
+    pub fn new(
+        path: String,
+        source_name: String,
+        category: String,
+        subcategory: String,
+    ) -> FileSystemConnector {
+        FileSystemConnector {
+            path,
+            source_name,
+            category,
+            subcategory,
+            seen: HashMap::new(),
+            polled: HashMap::new(),
+        }
+    }
+
+    /// Returns the content type of the file based on its extension
+    ///
+    /// # Arguments
+    ///
+    /// * path: &Path - The path of the file.</br>
+    pub fn get_content_type(path: &Path) -> String {
+        match path.extension().and_then(|x| x.to_str()) {
+            Some("json") => "application/json",
+            Some("xml") => "application/xml",
+            Some("csv") => "text/csv",
+            Some("txt") => "text/plain",
+            _ => "application/octet-stream",
+        }
+        .to_string()
+    }
+
+    fn make_record(&self, path: &Path, data: Vec<u8>, deleted: bool) -> SourceRecord {
+        let uid = path
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        SourceRecord {
+            category: self.category.clone(),
+            subcategory: self.subcategory.clone(),
+            source_name: None,
+            source_uid: uid.parse().unwrap_or(SourceId::Text(uid)),
+            content_type: FileSystemConnector::get_content_type(path),
+            data,
+            deleted,
+            receipt: Some(path.to_string_lossy().to_string()),
+        }
+    }
+}
+
+impl DataSourceConnector for FileSystemConnector {
+    fn source_name(&self) -> String {
+        self.source_name.clone()
+    }
+
+    fn poll(&mut self) -> Result<Vec<SourceRecord>, RetrieveError> {
+        let entries = match fs::read_dir(&self.path) {
+            Ok(e) => e,
+            Err(err) => {
+                error!("Could not read the directory {}. Error: {}", self.path, err);
+                return Err(RetrieveError);
+            }
+        };
+        let mut records = Vec::new();
+        let mut current = HashMap::new();
+        self.polled.clear();
+
+        for entry in entries.filter_map(|e| e.ok()) {
+            let path = entry.path();
+            let modified = match entry.metadata() {
+                Ok(m) if m.is_file() => m.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+                _ => continue,
+            };
+
+            if self.seen.get(&path) != Some(&modified) {
+                match fs::read(&path) {
+                    Ok(data) => {
+                        records.push(self.make_record(&path, data, false));
+                        self.polled.insert(path.clone(), Some(modified));
+                    }
+                    Err(err) => {
+                        warn!("Could not read the file {:?}. Error: {}", path, err);
+                        continue;
+                    }
+                }
+            }
+            current.insert(path, modified);
+        }
+
+        for path in self.seen.keys().filter(|p| !current.contains_key(*p)) {
+            records.push(self.make_record(path, Vec::new(), true));
+            self.polled.insert(path.clone(), None);
+        }
+
+        Ok(records)
+    }
+
+    fn commit(&mut self, record: &SourceRecord) -> Result<(), RetrieveError> {
+        let path = PathBuf::from(record.receipt.clone().ok_or(RetrieveError)?);
+        match self.polled.remove(&path) {
+            Some(Some(modified)) => {
+                self.seen.insert(path, modified);
+            }
+            Some(None) => {
+                self.seen.remove(&path);
+            }
+            None => return Err(RetrieveError),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_connector() -> FileSystemConnector {
+        let path = format!("./tmp/sources-{}", rand::random::<u32>());
+        fs::create_dir_all(&path).unwrap();
+
+        FileSystemConnector::new(
+            path,
+            "iStore".to_string(),
+            "order".to_string(),
+            "clothing".to_string(),
+        )
+    }
+
+    #[test]
+    fn test_content_type() {
+        assert_eq!(
+            FileSystemConnector::get_content_type(Path::new("5000.json")),
+            "application/json".to_string()
+        );
+        assert_eq!(
+            FileSystemConnector::get_content_type(Path::new("5000")),
+            "application/octet-stream".to_string()
+        );
+    }
+
+    #[test]
+    fn test_poll_changes() {
+        let mut connector = get_connector();
+        let file = Path::new(&connector.path).join("5000.json");
+        fs::write(&file, r#"{"status": "new"}"#).unwrap();
+
+        let records = connector.poll().unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].source_uid, SourceId::from(5000));
+        assert_eq!(records[0].data, br#"{"status": "new"}"#.to_vec());
+        assert!(connector.commit(&records[0]).is_ok());
+        assert!(connector.poll().unwrap().is_empty());
+
+        fs::remove_file(&file).unwrap();
+        let records = connector.poll().unwrap();
+        assert_eq!(records.len(), 1);
+        assert!(records[0].deleted);
+        assert!(connector.commit(&records[0]).is_ok());
+        assert!(connector.poll().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_poll_uncommitted() {
+        let mut connector = get_connector();
+        fs::write(
+            Path::new(&connector.path).join("5000.json"),
+            r#"{"status": "new"}"#,
+        )
+        .unwrap();
+
+        assert_eq!(connector.poll().unwrap().len(), 1);
+        // the file is returned again until its record is committed
+        let records = connector.poll().unwrap();
+        assert_eq!(records.len(), 1);
+        assert!(connector.commit(&records[0]).is_ok());
+        assert!(connector.poll().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_poll_missing_dir() {
+        let mut connector = FileSystemConnector::new(
+            "./tmp/sources-missing".to_string(),
+            "iStore".to_string(),
+            "order".to_string(),
+            "clothing".to_string(),
+        );
+
+        assert!(connector.poll().is_err());
+    }
+}
@@ -0,0 +1,856 @@
+//! A connector that pulls the files of a directory on an FTP or SFTP server, where each file is a record whose unique
+//! identifier is the file name (without the extension).
+//!
+//! The files that match the glob of the connector, (e.g.: *.json) and were added or modified since they were last
+//! pulled, (based on their size and modification time) are the records that changed. A file is recorded as pulled in the
+//! checkpoint file once its record is committed, (the sink accepted its DaaS document), so it isn't pulled again when
+//! the connector is restarted, and a file whose record wasn't emitted is pulled again. The schedule of each
+//! source is the interval of its connector, (e.g.: every 15 minutes).
+//!
+//! The files are pulled with SFTP when the `sftp` feature is enabled, (see `with_sftp()`), which authenticates with a
+//! password or a private key, and verifies the host key of the server against the known hosts, (e.g.: ~/.ssh/known_hosts).
+//! The SFTP and FTP transports share the polling and the checkpoint of the pulled files.
+//!
+//! The plain FTP transport has its own minimal FTP client, (passive mode over plain TCP), which sends everything in
+//! clear, so it only logs in anonymously: a connector that has credentials but uses FTP refuses to connect. FTPS isn't
+//! supported.
+//!
+//! # Examples
+//!
+//! ```ignore
+//! extern crate daas;
+//!
+//! use daas::eventing::broker::DaaSKafkaBroker;
+//! use daas::sources::ftp::FtpConnector;
+//! use daas::sources::{ListenerSink, SourceAgent};
+//! use daas::testing;
+//! use std::time::Duration;
+//!
+//! fn main() {
+//!     let connector = FtpConnector::new(
+//!         "sftp.istore.com:22".to_string(),
+//!         "/outbound/orders/*.json".to_string(),
+//!         "iStore".to_string(),
+//!         "order".to_string(),
+//!         "clothing".to_string(),
+//!     )
+//!     .with_sftp()
+//!     .with_private_key("daas".to_string(), "/etc/daas/id_ed25519".to_string(), None)
+//!     .with_schedule(Duration::from_secs(900))
+//!     .with_checkpoint("./checkpoints/istore-orders.json".to_string());
+//!     let agent = SourceAgent::new(
+//!         Box::new(connector),
+//!         "istore_agent".to_string(),
+//!         testing::get_dua(),
+//!         Box::new(ListenerSink::new(DaaSKafkaBroker::default(), None)),
+//!     );
+//!     let tx = agent.start();
+//! }
+//! ```
+
+use super::filesystem::FileSystemConnector;
+use super::*;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::path::Path;
+
+// the username of the anonymous login
+const ANONYMOUS: &str = "anonymous";
+
+/// The transfer protocols of the file servers
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FileProtocol {
+    /// FTP over plain TCP, (only the anonymous login)
+    Ftp,
+    /// SFTP, (the SSH File Transfer Protocol)
+    #[cfg(feature = "sftp")]
+    Sftp,
+}
+
+/// Represents a connector that pulls the files of a directory on an FTP or SFTP server
+pub struct FtpConnector {
+    /// The address of the server, (e.g.: ftp.istore.com:21)
+    pub address: String,
+    /// The transfer protocol of the server, (default: FTP)
+    pub protocol: FileProtocol,
+    /// The glob of the files to pull, (e.g.: /outbound/orders/*.json)
+    pub path: String,
+    /// The name of the source, (e.g.: iStore)
+    pub source_name: String,
+    /// The category of the records, (e.g.: order)
+    pub category: String,
+    /// The subcategory of the records, (e.g.: clothing)
+    pub subcategory: String,
+    /// The username and password of the server, (default: anonymous)
+    pub credentials: (String, String),
+    /// The path and passphrase of the private key of the SFTP user, which is used instead of the password
+    pub private_key: Option<(String, Option<String>)>,
+    /// The known hosts file that has the host key of the SFTP server, (default: ~/.ssh/known_hosts)
+    pub known_hosts: Option<String>,
+    /// How long to wait between the pulls
+    pub schedule: Option<Duration>,
+    /// The file that records the files that were pulled
+    pub checkpoint: Option<String>,
+    // the size and modification time of the files that were pulled
+    pulled: Option<HashMap<String, String>>,
+    // the size and modification time of the files of the last poll, which are pulled once their records are committed
+    polled: HashMap<String, String>,
+}
+
+impl FtpConnector {
+    /// Constructs an FtpConnector
+    ///
+    /// # Arguments
+    ///
+    /// * address: String - The address of the FTP server, (e.g.: ftp.istore.com:21).</br>
+    /// * path: String - The glob of the files to pull, where * and ? match the file names, (e.g.: /outbound/*.json).</br>
+    /// * source_name: String - The name of the source.</br>
+    /// * category: String - The category of the records.</br>
+    /// * subcategory: String - The subcategory of the records.</br>
+    pub fn new(
+        address: String,
+        path: String,
+        source_name: String,
+        category: String,
+        subcategory: String,
+    ) -> FtpConnector {
+        FtpConnector {
+            address,
+            protocol: FileProtocol::Ftp,
+            path,
+            source_name,
+            category,
+            subcategory,
+            credentials: (ANONYMOUS.to_string(), ANONYMOUS.to_string()),
+            private_key: None,
+            known_hosts: None,
+            schedule: None,
+            checkpoint: None,
+            pulled: None,
+            polled: HashMap::new(),
+        }
+    }
+
+    /// Sets the username and password of the server, (which are only sent to an SFTP server)
+    pub fn with_credentials(mut self, username: String, password: String) -> FtpConnector {
+        self.credentials = (username, password);
+        self
+    }
+
+    /// Pulls the files with SFTP, (the address is of the SSH server, e.g.: sftp.istore.com:22)
+    #[cfg(feature = "sftp")]
+    pub fn with_sftp(mut self) -> FtpConnector {
+        self.protocol = FileProtocol::Sftp;
+        self
+    }
+
+    /// Sets the username and private key of the SFTP user, which authenticates instead of a password
+    ///
+    /// # Arguments
+    ///
+    /// * username: String - The username of the SFTP user.</br>
+    /// * private_key: String - The path of the private key file, (e.g.: /etc/daas/id_ed25519).</br>
+    /// * passphrase: Option<String> - The passphrase of the private key, if it is encrypted.</br>
+    #[cfg(feature = "sftp")]
+    pub fn with_private_key(
+        mut self,
+        username: String,
+        private_key: String,
+        passphrase: Option<String>,
+    ) -> FtpConnector {
+        self.credentials = (username, String::new());
+        self.private_key = Some((private_key, passphrase));
+        self
+    }
+
+    /// Sets the known hosts file that has the host key of the SFTP server
+    #[cfg(feature = "sftp")]
+    pub fn with_known_hosts(mut self, known_hosts: String) -> FtpConnector {
+        self.known_hosts = Some(known_hosts);
+        self
+    }
+
+    /// Sets how long to wait between the pulls
+    pub fn with_schedule(mut self, schedule: Duration) -> FtpConnector {
+        self.schedule = Some(schedule);
+        self
+    }
+
+    /// Sets the file that records the files that were pulled
+    pub fn with_checkpoint(mut self, checkpoint: String) -> FtpConnector {
+        self.checkpoint = Some(checkpoint);
+        self
+    }
+
+    /// Returns the directory and the glob of the file names of the path, (e.g.: (/outbound, *.json))
+    pub fn get_dir_and_glob(&self) -> (String, String) {
+        match self.path.rfind('/') {
+            Some(0) => ("/".to_string(), self.path[1..].to_string()),
+            Some(i) => (self.path[..i].to_string(), self.path[i + 1..].to_string()),
+            None => (".".to_string(), self.path.clone()),
+        }
+    }
+
+    /// Determines if the file name matches the glob, where * matches any characters and ? matches one character
+    ///
+    /// # Arguments
+    ///
+    /// * glob: &str - The glob, (e.g.: *.json).</br>
+    /// * name: &str - The file name, (e.g.: 5000.json).</br>
+    ///
+    /// #Example
+    ///
+    /// ```rust
+    /// extern crate daas;
+    ///
+    /// use daas::sources::ftp::FtpConnector;
+    ///
+    /// fn main() {
+    ///     assert!(FtpConnector::glob_matches("order-?.json", "order-1.json"));
+    ///     assert!(!FtpConnector::glob_matches("*.json", "5000.csv"));
+    /// }
+    /// ```
+    pub fn glob_matches(glob: &str, name: &str) -> bool {
+        let glob: Vec<char> = glob.chars().collect();
+        let name: Vec<char> = name.chars().collect();
+        let (mut g, mut n) = (0, 0);
+        // the position of the last * and the name position it matched up to
+        let mut star: Option<(usize, usize)> = None;
+
+        while n < name.len() {
+            match glob.get(g) {
+                Some('*') => {
+                    star = Some((g, n));
+                    g += 1;
+                }
+                Some(c) if *c == '?' || *c == name[n] => {
+                    g += 1;
+                    n += 1;
+                }
+                _ => match star {
+                    Some((sg, sn)) => {
+                        g = sg + 1;
+                        n = sn + 1;
+                        star = Some((sg, sn + 1));
+                    }
+                    None => return false,
+                },
+            }
+        }
+
+        glob[g..].iter().all(|c| *c == '*')
+    }
+
+    fn load_checkpoint(&self) -> HashMap<String, String> {
+        self.checkpoint
+            .as_ref()
+            .and_then(|c| fs::read(c).ok())
+            .and_then(|data| serde_json::from_slice(&data).ok())
+            .unwrap_or_default()
+    }
+
+    fn save_checkpoint(&self, pulled: &HashMap<String, String>) {
+        if let Some(checkpoint) = &self.checkpoint {
+            if let Some(dir) = Path::new(checkpoint).parent() {
+                let _ = fs::create_dir_all(dir);
+            }
+            if let Err(err) = fs::write(checkpoint, serde_json::to_vec(pulled).unwrap()) {
+                error!(
+                    "Could not save the checkpoint {}. Error: {}",
+                    checkpoint, err
+                );
+            }
+        }
+    }
+
+    // Opens a session with the server using the transfer protocol
+    fn open_session(&self) -> Result<Box<dyn FileSession>, RetrieveError> {
+        match self.protocol {
+            FileProtocol::Ftp => {
+                if self.credentials.0 != ANONYMOUS || self.private_key.is_some() {
+                    error!(
+                        "Refused to send the credentials to the FTP server {} in clear, (see with_sftp()).",
+                        self.address
+                    );
+                    return Err(RetrieveError);
+                }
+                Ok(Box::new(FtpSession::open(
+                    &self.address,
+                    &self.credentials,
+                )?))
+            }
+            #[cfg(feature = "sftp")]
+            FileProtocol::Sftp => Ok(Box::new(SftpSession::open(self)?)),
+        }
+    }
+
+    fn make_record(&self, name: &str, remote_path: String, data: Vec<u8>) -> SourceRecord {
+        let path = Path::new(name);
+        let uid = path
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        SourceRecord {
+            category: self.category.clone(),
+            subcategory: self.subcategory.clone(),
+            source_name: None,
+            source_uid: uid.parse().unwrap_or(SourceId::Text(uid)),
+            content_type: FileSystemConnector::get_content_type(path),
+            data,
+            deleted: false,
+            receipt: Some(remote_path),
+        }
+    }
+}
+
+impl DataSourceConnector for FtpConnector {
+    fn source_name(&self) -> String {
+        self.source_name.clone()
+    }
+
+    fn poll(&mut self) -> Result<Vec<SourceRecord>, RetrieveError> {
+        if self.pulled.is_none() {
+            self.pulled = Some(self.load_checkpoint());
+        }
+        let pulled = self.pulled.clone().unwrap_or_default();
+        let (dir, glob) = self.get_dir_and_glob();
+        let mut records = Vec::new();
+        let mut polled = HashMap::new();
+
+        let result = self.open_session().and_then(|mut session| {
+            for name in session.list(&dir)? {
+                let file_name = name.rsplit('/').next().unwrap_or(&name).to_string();
+                if !FtpConnector::glob_matches(&glob, &file_name) {
+                    continue;
+                }
+
+                let path = format!("{}/{}", dir.trim_end_matches('/'), file_name);
+                let version = session.version(&path)?;
+                if pulled.get(&path) == Some(&version) {
+                    continue;
+                }
+
+                records.push(self.make_record(&file_name, path.clone(), session.retrieve(&path)?));
+                polled.insert(path, version);
+            }
+            session.quit();
+            Ok(())
+        });
+
+        // the files that were retrieved before an error are returned, so they are emitted
+        self.polled = polled;
+        match result {
+            Err(err) if records.is_empty() => Err(err),
+            _ => Ok(records),
+        }
+    }
+
+    fn commit(&mut self, record: &SourceRecord) -> Result<(), RetrieveError> {
+        let path = record.receipt.as_ref().ok_or(RetrieveError)?;
+        let version = self.polled.remove(path).ok_or(RetrieveError)?;
+        let mut pulled = self.pulled.take().unwrap_or_default();
+
+        pulled.insert(path.clone(), version);
+        self.save_checkpoint(&pulled);
+        self.pulled = Some(pulled);
+        Ok(())
+    }
+
+    fn interval(&self) -> Option<Duration> {
+        self.schedule
+    }
+}
+
+// A session of a file server, (FTP or SFTP)
+trait FileSession {
+    // Returns the names of the files of the directory
+    fn list(&mut self, dir: &str) -> Result<Vec<String>, RetrieveError>;
+    // Returns the size and modification time of the file, (e.g.: 17@20201015093000)
+    fn version(&mut self, path: &str) -> Result<String, RetrieveError>;
+    fn retrieve(&mut self, path: &str) -> Result<Vec<u8>, RetrieveError>;
+    fn quit(&mut self);
+}
+
+// A session of the FTP control connection, (RFC 959)
+struct FtpSession {
+    reader: BufReader<TcpStream>,
+    stream: TcpStream,
+}
+
+impl FtpSession {
+    fn open(address: &str, credentials: &(String, String)) -> Result<FtpSession, RetrieveError> {
+        let stream = TcpStream::connect(address).map_err(|err| {
+            error!(
+                "Could not connect to the FTP server {}. Error: {}",
+                address, err
+            );
+            RetrieveError
+        })?;
+        stream
+            .set_read_timeout(Some(Duration::from_secs(30)))
+            .map_err(|_e| RetrieveError)?;
+        let mut session = FtpSession {
+            reader: BufReader::new(stream.try_clone().map_err(|_e| RetrieveError)?),
+            stream,
+        };
+
+        session.expect(&[220])?;
+        if session.command(&format!("USER {}", credentials.0), &[230, 331])? == 331 {
+            session.command(&format!("PASS {}", credentials.1), &[230])?;
+        }
+        session.command("TYPE I", &[200])?;
+        Ok(session)
+    }
+
+    // Reads a reply, (the multiline replies end with the line that starts with the code and a space)
+    fn reply(&mut self) -> Result<(u16, String), RetrieveError> {
+        let mut line = String::new();
+        let mut text = String::new();
+        loop {
+            line.clear();
+            match self.reader.read_line(&mut line) {
+                Ok(0) | Err(_) => {
+                    error!("The FTP server closed the connection.");
+                    return Err(RetrieveError);
+                }
+                Ok(_) => text.push_str(&line),
+            }
+
+            let code = line.get(0..3).and_then(|c| c.parse::<u16>().ok());
+            if let (Some(code), Some(" ")) = (code, line.get(3..4)) {
+                return Ok((code, text));
+            }
+        }
+    }
+
+    fn expect(&mut self, codes: &[u16]) -> Result<(u16, String), RetrieveError> {
+        let (code, text) = self.reply()?;
+        match codes.contains(&code) {
+            true => Ok((code, text)),
+            false => {
+                error!("Unexpected reply of the FTP server: {}", text.trim());
+                Err(RetrieveError)
+            }
+        }
+    }
+
+    fn command(&mut self, command: &str, codes: &[u16]) -> Result<u16, RetrieveError> {
+        self.send(command)?;
+        self.expect(codes).map(|(code, _text)| code)
+    }
+
+    fn send(&mut self, command: &str) -> Result<(), RetrieveError> {
+        self.stream
+            .write_all(format!("{}\r\n", command).as_bytes())
+            .map_err(|err| {
+                error!("Could not write to the FTP server. Error: {}", err);
+                RetrieveError
+            })
+    }
+
+    // Transfers the data of the command using a passive data connection
+    fn transfer(&mut self, command: &str) -> Result<Vec<u8>, RetrieveError> {
+        self.send("PASV")?;
+        let (_code, text) = self.expect(&[227])?;
+
+        // the port of (h1,h2,h3,h4,p1,p2), the host is the one of the control connection
+        let numbers: Vec<u16> = text
+            .rsplit('(')
+            .next()
+            .unwrap_or("")
+            .split(|c: char| !c.is_ascii_digit())
+            .filter_map(|n| n.parse().ok())
+            .collect();
+        if numbers.len() < 6 {
+            error!(
+                "Invalid passive mode reply of the FTP server: {}",
+                text.trim()
+            );
+            return Err(RetrieveError);
+        }
+        let mut address: SocketAddr = self.stream.peer_addr().map_err(|_e| RetrieveError)?;
+        address.set_port(numbers[4] * 256 + numbers[5]);
+
+        let mut data_stream = TcpStream::connect(address).map_err(|err| {
+            error!("Could not open the FTP data connection. Error: {}", err);
+            RetrieveError
+        })?;
+        self.command(command, &[125, 150])?;
+
+        let mut data = Vec::new();
+        data_stream
+            .read_to_end(&mut data)
+            .map_err(|_e| RetrieveError)?;
+        self.expect(&[226, 250])?;
+        Ok(data)
+    }
+}
+
+impl FileSession for FtpSession {
+    fn list(&mut self, dir: &str) -> Result<Vec<String>, RetrieveError> {
+        let data = self.transfer(&format!("NLST {}", dir))?;
+        Ok(String::from_utf8_lossy(&data)
+            .lines()
+            .map(|l| l.trim().to_string())
+            .filter(|l| !l.is_empty())
+            .collect())
+    }
+
+    fn version(&mut self, path: &str) -> Result<String, RetrieveError> {
+        self.send(&format!("SIZE {}", path))?;
+        let (_code, size) = self.expect(&[213])?;
+        self.send(&format!("MDTM {}", path))?;
+        let (_code, modified) = self.expect(&[213])?;
+        Ok(format!(
+            "{}@{}",
+            size.trim().get(4..).unwrap_or(""),
+            modified.trim().get(4..).unwrap_or("")
+        ))
+    }
+
+    fn retrieve(&mut self, path: &str) -> Result<Vec<u8>, RetrieveError> {
+        self.transfer(&format!("RETR {}", path))
+    }
+
+    fn quit(&mut self) {
+        let _ = self.command("QUIT", &[221]);
+    }
+}
+
+// A session of the SFTP subsystem of an SSH server
+#[cfg(feature = "sftp")]
+struct SftpSession {
+    session: ssh2::Session,
+    sftp: ssh2::Sftp,
+}
+
+#[cfg(feature = "sftp")]
+impl SftpSession {
+    fn open(connector: &FtpConnector) -> Result<SftpSession, RetrieveError> {
+        let address = &connector.address;
+        let fail = |action: &str, err: ssh2::Error| {
+            error!(
+                "Could not {} the SFTP server {}. Error: {}",
+                action, address, err
+            );
+            RetrieveError
+        };
+        let stream = TcpStream::connect(address).map_err(|err| {
+            error!(
+                "Could not connect to the SFTP server {}. Error: {}",
+                address, err
+            );
+            RetrieveError
+        })?;
+
+        let mut session = ssh2::Session::new().map_err(|e| fail("connect to", e))?;
+        session.set_tcp_stream(stream);
+        session.set_timeout(30_000);
+        session.handshake().map_err(|e| fail("connect to", e))?;
+        SftpSession::verify_host_key(connector, &session)?;
+
+        let username = &connector.credentials.0;
+        match &connector.private_key {
+            Some((key, passphrase)) => session
+                .userauth_pubkey_file(username, None, Path::new(key), passphrase.as_deref())
+                .map_err(|e| fail("authenticate with", e))?,
+            None => session
+                .userauth_password(username, &connector.credentials.1)
+                .map_err(|e| fail("authenticate with", e))?,
+        }
+        if !session.authenticated() {
+            error!("The SFTP server {} refused the login.", address);
+            return Err(RetrieveError);
+        }
+
+        let sftp = session
+            .sftp()
+            .map_err(|e| fail("open the SFTP subsystem of", e))?;
+        Ok(SftpSession { session, sftp })
+    }
+
+    // Verifies that the host key of the server is in the known hosts, so the credentials aren't sent to an impostor
+    fn verify_host_key(
+        connector: &FtpConnector,
+        session: &ssh2::Session,
+    ) -> Result<(), RetrieveError> {
+        let known_hosts = match &connector.known_hosts {
+            Some(k) => k.clone(),
+            None => format!(
+                "{}/.ssh/known_hosts",
+                std::env::var("HOME").unwrap_or_default()
+            ),
+        };
+        let (host, port) = match connector.address.rsplit_once(':') {
+            Some((h, p)) => (h, p.parse::<u16>().unwrap_or(22)),
+            None => (connector.address.as_str(), 22),
+        };
+        let (key, _key_type) = session.host_key().ok_or(RetrieveError)?;
+
+        let mut hosts = session.known_hosts().map_err(|_e| RetrieveError)?;
+        if let Err(err) = hosts.read_file(Path::new(&known_hosts), ssh2::KnownHostFileKind::OpenSSH)
+        {
+            error!(
+                "Could not read the known hosts {}. Error: {}",
+                known_hosts, err
+            );
+            return Err(RetrieveError);
+        }
+        match hosts.check_port(host, port, key) {
+            ssh2::CheckResult::Match => Ok(()),
+            result => {
+                error!(
+                    "The host key of the SFTP server {} isn't known, ({:?} in {}).",
+                    connector.address, result, known_hosts
+                );
+                Err(RetrieveError)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "sftp")]
+impl FileSession for SftpSession {
+    fn list(&mut self, dir: &str) -> Result<Vec<String>, RetrieveError> {
+        let entries = self.sftp.readdir(Path::new(dir)).map_err(|err| {
+            error!("Could not list the SFTP directory {}. Error: {}", dir, err);
+            RetrieveError
+        })?;
+        Ok(entries
+            .into_iter()
+            .filter(|(_path, stat)| stat.is_file())
+            .filter_map(|(path, _stat)| path.file_name().map(|n| n.to_string_lossy().to_string()))
+            .collect())
+    }
+
+    fn version(&mut self, path: &str) -> Result<String, RetrieveError> {
+        let stat = self.sftp.stat(Path::new(path)).map_err(|err| {
+            error!("Could not stat the SFTP file {}. Error: {}", path, err);
+            RetrieveError
+        })?;
+        Ok(format!(
+            "{}@{}",
+            stat.size.unwrap_or(0),
+            stat.mtime.unwrap_or(0)
+        ))
+    }
+
+    fn retrieve(&mut self, path: &str) -> Result<Vec<u8>, RetrieveError> {
+        let mut file = self.sftp.open(Path::new(path)).map_err(|err| {
+            error!("Could not open the SFTP file {}. Error: {}", path, err);
+            RetrieveError
+        })?;
+        let mut data = Vec::new();
+        file.read_to_end(&mut data).map_err(|err| {
+            error!("Could not retrieve the SFTP file {}. Error: {}", path, err);
+            RetrieveError
+        })?;
+        Ok(data)
+    }
+
+    fn quit(&mut self) {
+        let _ = self.session.disconnect(None, "", None);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+
+    // The files (name, content, modification time) of the FTP server
+    type Files = Arc<Mutex<Vec<(String, String, String)>>>;
+
+    // An FTP server that serves the files of the /outbound directory
+    fn start_server(files: Files) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut data = None;
+                stream.write_all(b"220-Welcome\r\n220 Ready\r\n").unwrap();
+
+                loop {
+                    let mut line = String::new();
+                    if reader.read_line(&mut line).unwrap_or(0) == 0 {
+                        break;
+                    }
+                    let mut parts = line.trim().splitn(2, ' ');
+                    let command = parts.next().unwrap().to_string();
+                    let arg = parts.next().unwrap_or("").to_string();
+                    let file = files
+                        .lock()
+                        .unwrap()
+                        .iter()
+                        .find(|f| format!("/outbound/{}", f.0) == arg)
+                        .cloned();
+
+                    let reply = match command.as_str() {
+                        "USER" => "331 Password required".to_string(),
+                        "PASS" if arg == ANONYMOUS => "230 Logged in".to_string(),
+                        "PASS" => "530 Login incorrect".to_string(),
+                        "TYPE" => "200 Binary".to_string(),
+                        "PASV" => {
+                            let data_listener = TcpListener::bind("127.0.0.1:0").unwrap();
+                            let port = data_listener.local_addr().unwrap().port();
+                            data = Some(data_listener);
+                            format!(
+                                "227 Entering Passive Mode (127,0,0,1,{},{})",
+                                port / 256,
+                                port % 256
+                            )
+                        }
+                        "NLST" | "RETR" => {
+                            let content = match command.as_str() {
+                                "NLST" => files
+                                    .lock()
+                                    .unwrap()
+                                    .iter()
+                                    .map(|f| format!("{}\r\n", f.0))
+                                    .collect::<String>(),
+                                _ => file.map(|f| f.1).unwrap_or_default(),
+                            };
+                            stream
+                                .write_all(b"150 Opening data connection\r\n")
+                                .unwrap();
+                            let (mut data_stream, _) = data.take().unwrap().accept().unwrap();
+                            data_stream.write_all(content.as_bytes()).unwrap();
+                            drop(data_stream);
+                            "226 Transfer complete".to_string()
+                        }
+                        "SIZE" => format!("213 {}", file.map(|f| f.1.len()).unwrap_or(0)),
+                        "MDTM" => format!("213 {}", file.map(|f| f.2).unwrap_or_default()),
+                        "QUIT" => {
+                            stream.write_all(b"221 Bye\r\n").unwrap();
+                            break;
+                        }
+                        _ => "502 Not implemented".to_string(),
+                    };
+                    stream
+                        .write_all(format!("{}\r\n", reply).as_bytes())
+                        .unwrap();
+                }
+            }
+        });
+
+        address
+    }
+
+    fn get_file(name: &str, content: &str, modified: &str) -> (String, String, String) {
+        (name.to_string(), content.to_string(), modified.to_string())
+    }
+
+    fn get_connector(address: String) -> FtpConnector {
+        FtpConnector::new(
+            address,
+            "/outbound/*.json".to_string(),
+            "iStore".to_string(),
+            "order".to_string(),
+            "clothing".to_string(),
+        )
+    }
+
+    #[test]
+    fn test_glob_matches() {
+        assert!(FtpConnector::glob_matches("*.json", "5000.json"));
+        assert!(FtpConnector::glob_matches("*", "5000.json"));
+        assert!(FtpConnector::glob_matches(
+            "order-*-?.csv",
+            "order-2020-1.csv"
+        ));
+        assert!(!FtpConnector::glob_matches("*.json", "5000.json.tmp"));
+        assert!(!FtpConnector::glob_matches("order-?.csv", "order-10.csv"));
+    }
+
+    #[test]
+    fn test_dir_and_glob() {
+        let connector = get_connector("localhost:21".to_string());
+        assert_eq!(
+            connector.get_dir_and_glob(),
+            ("/outbound".to_string(), "*.json".to_string())
+        );
+    }
+
+    #[test]
+    fn test_poll() {
+        let files = Arc::new(Mutex::new(vec![
+            get_file("5000.json", r#"{"status":"new"}"#, "20201015093000"),
+            get_file("5000.csv", "status\nnew\n", "20201015093000"),
+        ]));
+        let mut connector = get_connector(start_server(files.clone()));
+
+        let records = connector.poll().unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].source_uid, SourceId::from(5000));
+        assert_eq!(records[0].data, br#"{"status":"new"}"#.to_vec());
+        assert_eq!(records[0].content_type, "application/json".to_string());
+        // the file is pulled again until its record is committed
+        let records = connector.poll().unwrap();
+        assert_eq!(records.len(), 1);
+        assert!(connector.commit(&records[0]).is_ok());
+        assert!(connector.poll().unwrap().is_empty());
+
+        // a modified file is pulled again
+        files.lock().unwrap()[0] = get_file("5000.json", r#"{"status":"paid"}"#, "20201015100000");
+        files
+            .lock()
+            .unwrap()
+            .push(get_file("5001.json", "{}", "20201015100000"));
+        assert_eq!(connector.poll().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_poll_checkpoint() {
+        let files = Arc::new(Mutex::new(vec![get_file(
+            "5000.json",
+            "{}",
+            "20201015093000",
+        )]));
+        let address = start_server(files);
+        let checkpoint = format!("./tmp/ftp-{}/checkpoint.json", rand::random::<u32>());
+
+        let mut connector = get_connector(address.clone()).with_checkpoint(checkpoint.clone());
+        let records = connector.poll().unwrap();
+        assert_eq!(records.len(), 1);
+        assert!(connector.commit(&records[0]).is_ok());
+
+        // the restarted connector doesn't pull the file again
+        let mut connector = get_connector(address).with_checkpoint(checkpoint);
+        assert!(connector.poll().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_poll_credentials_in_clear() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let mut connector =
+            get_connector(address).with_credentials("daas".to_string(), "secret".to_string());
+
+        assert!(connector.poll().is_err());
+        // the credentials weren't sent, since the connector didn't even connect
+        assert!(listener.accept().is_err());
+    }
+
+    #[cfg(feature = "sftp")]
+    #[test]
+    fn test_poll_sftp_no_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        drop(listener);
+        let mut connector = get_connector(address)
+            .with_sftp()
+            .with_private_key("daas".to_string(), "./tmp/id_ed25519".to_string(), None)
+            .with_known_hosts("./tmp/known_hosts".to_string());
+
+        assert_eq!(connector.protocol, FileProtocol::Sftp);
+        assert!(connector.poll().is_err());
+    }
+}
@@ -0,0 +1,372 @@
+//! A connector that polls a REST endpoint which returns the records as JSON.
+//!
+//! The URL can contain `{since}`, which is replaced with the time (Unix seconds) of the last poll whose records were all
+//! committed, so that the endpoint only returns the records that changed, (e.g.: http://crm/orders?updated_after={since}).
+//! The records, their unique identifiers and (optionally) their categories are extracted using JSON pointers.
+
+use super::*;
+use serde_json::Value;
+use std::time::SystemTime;
+
+/// How the HttpConnector authenticates with the endpoint
+#[derive(Debug, Clone, PartialEq)]
+pub enum HttpAuth {
+    /// No authentication
+    None,
+    /// Basic authentication, (username, password)
+    Basic(String, String),
+    /// A bearer token
+    Bearer(String),
+    /// A custom header, (name, value) e.g.: (X-Api-Key, secret)
+    Header(String, String),
+}
+
+/// Represents a connector that polls a REST endpoint
+pub struct HttpConnector {
+    /// The URL of the endpoint, where `{since}` is replaced with the time of the last committed poll
+    pub url: String,
+    /// The name of the source, (e.g.: iStore)
+    pub source_name: String,
+    /// The category of the records, unless it is extracted from the record, (e.g.: order)
+    pub category: String,
+    /// The subcategory of the records, unless it is extracted from the record, (e.g.: clothing)
+    pub subcategory: String,
+    /// How the connector authenticates with the endpoint, (default: None)
+    pub auth: HttpAuth,
+    /// The JSON pointer of the records in the response, (default: "" the response is the array of records)
+    pub records_path: String,
+    /// The JSON pointer of the unique identifier in a record, (default: /id)
+    pub uid_path: String,
+    /// The JSON pointer of the category in a record
+    pub category_path: Option<String>,
+    /// The JSON pointer of the subcategory in a record
+    pub subcategory_path: Option<String>,
+    /// How long to wait between the polls
+    pub interval: Option<Duration>,
+    since: u64,
+    // the time of the last poll and the receipts of its records that aren't committed yet
+    pending: Option<(u64, Vec<String>)>,
+    client: reqwest::blocking::Client,
+}
+
+impl HttpConnector {
+    /// Constructs an HttpConnector
+    ///
+    /// # Arguments
+    ///
+    /// * url: String - The URL of the endpoint, (e.g.: http://crm/orders?updated_after={since}).</br>
+    /// * source_name: String - The name of the source.</br>
+    /// * category: String - The category of the records.</br>
+    /// * subcategory: String - The subcategory of the records.</br>
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// extern crate daas;
+    ///
+    /// use daas::sources::http::{HttpAuth, HttpConnector};
+    ///
+    /// fn main() {
+    ///     let connector = HttpConnector::new(
+    ///         "http://localhost:8080/orders?updated_after={since}".to_string(),
+    ///         "iStore".to_string(),
+    ///         "order".to_string(),
+    ///         "clothing".to_string(),
+    ///     )
+    ///     .with_auth(HttpAuth::Bearer("secret".to_string()))
+    ///     .with_records_path("/data".to_string())
+    ///     .with_uid_path("/order_id".to_string());
+    ///
+    ///     assert_eq!(connector.uid_path, "/order_id".to_string());
+    /// }
+    /// ```
+    pub fn new(
+        url: String,
+        source_name: String,
+        category: String,
+        subcategory: String,
+    ) -> HttpConnector {
+        HttpConnector {
+            url,
+            source_name,
+            category,
+            subcategory,
+            auth: HttpAuth::None,
+            records_path: "".to_string(),
+            uid_path: "/id".to_string(),
+            category_path: None,
+            subcategory_path: None,
+            interval: None,
+            since: 0,
+            pending: None,
+            client: reqwest::blocking::Client::builder()
+                .timeout(Duration::from_secs(30))
+                .build()
+                .unwrap(),
+        }
+    }
+
+    /// Sets how the connector authenticates with the endpoint
+    pub fn with_auth(mut self, auth: HttpAuth) -> HttpConnector {
+        self.auth = auth;
+        self
+    }
+
+    /// Sets the JSON pointer of the records in the response
+    pub fn with_records_path(mut self, path: String) -> HttpConnector {
+        self.records_path = path;
+        self
+    }
+
+    /// Sets the JSON pointer of the unique identifier in a record
+    pub fn with_uid_path(mut self, path: String) -> HttpConnector {
+        self.uid_path = path;
+        self
+    }
+
+    /// Sets the JSON pointer of the category in a record
+    pub fn with_category_path(mut self, path: String) -> HttpConnector {
+        self.category_path = Some(path);
+        self
+    }
+
+    /// Sets the JSON pointer of the subcategory in a record
+    pub fn with_subcategory_path(mut self, path: String) -> HttpConnector {
+        self.subcategory_path = Some(path);
+        self
+    }
+
+    /// Sets how long to wait between the polls
+    pub fn with_interval(mut self, interval: Duration) -> HttpConnector {
+        self.interval = Some(interval);
+        self
+    }
+
+    /// Returns the records of the response, (a single record if the JSON pointer refers to an object)
+    ///
+    /// # Arguments
+    ///
+    /// * response: &Value - The JSON response of the endpoint.</br>
+    pub fn extract_records(&self, response: &Value) -> Vec<SourceRecord> {
+        let records = match response.pointer(&self.records_path) {
+            Some(Value::Array(a)) => a.clone(),
+            Some(Value::Object(o)) => vec![Value::Object(o.clone())],
+            _ => {
+                warn!(
+                    "The response of {} doesn't have records at {}.",
+                    self.url, self.records_path
+                );
+                Vec::new()
+            }
+        };
+
+        records
+            .iter()
+            .filter_map(|r| {
+                let uid = match get_text(r, Some(&self.uid_path)) {
+                    Some(u) => u,
+                    None => {
+                        warn!(
+                            "Skipped a record without a unique identifier at {}.",
+                            self.uid_path
+                        );
+                        return None;
+                    }
+                };
+
+                Some(SourceRecord {
+                    category: get_text(r, self.category_path.as_ref())
+                        .unwrap_or_else(|| self.category.clone()),
+                    subcategory: get_text(r, self.subcategory_path.as_ref())
+                        .unwrap_or_else(|| self.subcategory.clone()),
+                    source_name: None,
+                    source_uid: uid.parse().unwrap_or(SourceId::Text(uid.clone())),
+                    content_type: "application/json".to_string(),
+                    data: serde_json::to_vec(r).unwrap(),
+                    deleted: false,
+                    receipt: Some(uid),
+                })
+            })
+            .collect()
+    }
+}
+
+// Returns the string or number at the JSON pointer
+fn get_text(value: &Value, path: Option<&String>) -> Option<String> {
+    match value.pointer(path?)? {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+impl DataSourceConnector for HttpConnector {
+    fn source_name(&self) -> String {
+        self.source_name.clone()
+    }
+
+    fn poll(&mut self) -> Result<Vec<SourceRecord>, RetrieveError> {
+        let started = get_unix_now!();
+        let url = self.url.replace("{since}", &self.since.to_string());
+        let request = match &self.auth {
+            HttpAuth::None => self.client.get(&url),
+            HttpAuth::Basic(user, pass) => self.client.get(&url).basic_auth(user, Some(pass)),
+            HttpAuth::Bearer(token) => self.client.get(&url).bearer_auth(token),
+            HttpAuth::Header(name, value) => {
+                self.client.get(&url).header(name.as_str(), value.as_str())
+            }
+        };
+
+        let response: Value = match request.send() {
+            Ok(r) if r.status().is_success() => {
+                match r.bytes().ok().and_then(|b| serde_json::from_slice(&b).ok()) {
+                    Some(v) => v,
+                    None => {
+                        error!("The response of {} is not JSON.", url);
+                        return Err(RetrieveError);
+                    }
+                }
+            }
+            Ok(r) => {
+                error!("The endpoint {} responded with {}.", url, r.status());
+                return Err(RetrieveError);
+            }
+            Err(err) => {
+                error!("Could not poll the endpoint {}. Error: {}", url, err);
+                return Err(RetrieveError);
+            }
+        };
+
+        let records = self.extract_records(&response);
+        let receipts: Vec<String> = records.iter().filter_map(|r| r.receipt.clone()).collect();
+        match receipts.is_empty() {
+            true => {
+                self.since = started;
+                self.pending = None;
+            }
+            false => self.pending = Some((started, receipts)),
+        }
+        Ok(records)
+    }
+
+    // the time of the poll becomes `since` once all of its records are committed
+    fn commit(&mut self, record: &SourceRecord) -> Result<(), RetrieveError> {
+        let (started, receipts) = self.pending.as_mut().ok_or(RetrieveError)?;
+        let receipt = record.receipt.as_ref().ok_or(RetrieveError)?;
+        match receipts.iter().position(|r| r == receipt) {
+            Some(i) => {
+                receipts.remove(i);
+            }
+            None => return Err(RetrieveError),
+        }
+
+        if receipts.is_empty() {
+            self.since = *started;
+            self.pending = None;
+        }
+        Ok(())
+    }
+
+    fn interval(&self) -> Option<Duration> {
+        self.interval
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    fn get_connector(url: String) -> HttpConnector {
+        HttpConnector::new(
+            url,
+            "iStore".to_string(),
+            "order".to_string(),
+            "clothing".to_string(),
+        )
+    }
+
+    #[test]
+    fn test_extract_records() {
+        let connector = get_connector("http://localhost".to_string())
+            .with_records_path("/data".to_string())
+            .with_uid_path("/order/id".to_string())
+            .with_subcategory_path("/order/kind".to_string());
+        let response = json!({"data": [
+            {"order": {"id": 5000, "kind": "shoes"}},
+            {"order": {"id": "A-1"}},
+            {"order": {}}
+        ]});
+        let records = connector.extract_records(&response);
+
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].source_uid, SourceId::from(5000));
+        assert_eq!(records[0].category, "order".to_string());
+        assert_eq!(records[0].subcategory, "shoes".to_string());
+        assert_eq!(records[1].source_uid, SourceId::from("A-1"));
+        assert_eq!(records[1].subcategory, "clothing".to_string());
+    }
+
+    #[test]
+    fn test_extract_single_record() {
+        let connector = get_connector("http://localhost".to_string());
+        let records = connector.extract_records(&json!({"id": 5000, "status": "new"}));
+
+        assert_eq!(records.len(), 1);
+        assert_eq!(
+            serde_json::from_slice::<Value>(&records[0].data).unwrap()["status"],
+            json!("new")
+        );
+    }
+
+    #[test]
+    fn test_poll() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!(
+            "http://{}/orders?since={{since}}",
+            listener.local_addr().unwrap()
+        );
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0; 8192];
+            let len = stream.read(&mut buf).unwrap();
+            let body = r#"[{"id": 5000, "status": "new"}]"#;
+            stream
+                .write_all(
+                    format!(
+                        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}",
+                        body.len(),
+                        body
+                    )
+                    .as_bytes(),
+                )
+                .unwrap();
+            String::from_utf8_lossy(&buf[..len]).to_string()
+        });
+
+        let mut connector = get_connector(url).with_auth(HttpAuth::Header(
+            "X-Api-Key".to_string(),
+            "secret".to_string(),
+        ));
+        let records = connector.poll().unwrap();
+        let request = server.join().unwrap().to_lowercase();
+
+        assert_eq!(records.len(), 1);
+        assert!(request.starts_with("get /orders?since=0"));
+        assert!(request.contains("x-api-key: secret"));
+        // the next poll is since the time of this poll only once its records are committed
+        assert_eq!(connector.since, 0);
+        assert!(connector.commit(&records[0]).is_ok());
+        assert!(connector.since > 0);
+    }
+
+    #[test]
+    fn test_poll_unreachable() {
+        let mut connector = get_connector("http://127.0.0.1:1/orders".to_string());
+
+        assert!(connector.poll().is_err());
+        assert_eq!(connector.since, 0);
+    }
+}
@@ -0,0 +1,435 @@
+//! The sources module contains the connectors that ingest data from external systems, (e.g.: change data capture).
+//!
+//! A `DataSourceConnector` polls an external system for the records that changed. The `SourceAgent` turns the records
+//! into DaaS documents with the same author, Data Usage Agreements and Data Tracker Chain handling as the listener,
+//! and emits them to a sink, (e.g.: the listener's processing using `ListenerSink`, or a topic using `TopicSink`).
+//!
+//! A record is committed to its connector, (e.g.: the checkpoint is advanced or the message is acknowledged) only after
+//! the sink accepted its DaaS document. When the sink fails, the agent stops emitting the records of the poll, so the
+//! record and the ones after it are returned again by the next poll.
+//!
+//! # Examples
+//!
+//! ```
+//! extern crate daas;
+//!
+//! use daas::eventing::memory::InMemoryBroker;
+//! use daas::service::pipeline::TopicSink;
+//! use daas::sources::filesystem::FileSystemConnector;
+//! use daas::sources::SourceAgent;
+//! use daas::testing;
+//!
+//! fn main() {
+//!     let dir = std::env::temp_dir().join(format!("daas-sources-{}", std::process::id()));
+//!     std::fs::create_dir_all(&dir).unwrap();
+//!     std::fs::write(dir.join("5000.json"), r#"{"status": "new"}"#).unwrap();
+//!
+//!     let broker = InMemoryBroker::new();
+//!     let connector = FileSystemConnector::new(
+//!         dir.to_string_lossy().to_string(),
+//!         "iStore".to_string(),
+//!         "order".to_string(),
+//!         "clothing".to_string(),
+//!     );
+//!     let mut agent = SourceAgent::new(
+//!         Box::new(connector),
+//!         "istore_agent".to_string(),
+//!         testing::get_dua(),
+//!         Box::new(TopicSink::new(broker.clone(), "genesis".to_string())),
+//!     );
+//!
+//!     assert_eq!(agent.run_once().unwrap(), 1);
+//!     assert_eq!(broker.messages("genesis")[0]._id, "order~clothing~iStore~5000".to_string());
+//!     std::fs::remove_dir_all(&dir).unwrap();
+//! }
+//! ```
+
+use super::*;
+use crate::doc::{DaaSDoc, SourceId};
+use crate::errors::daaserror::DaaSProcessingError;
+use crate::errors::RetrieveError;
+use crate::eventing::broker::DaaSKafkaProcessor;
+use crate::service::listener::DaaSListener;
+use crate::service::pipeline::DaaSDocSink;
+use pbd::dtc::Tracker;
+use pbd::dua::DUA;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+
+pub mod filesystem;
+pub mod ftp;
+pub mod http;
+
+/// Represents a record that changed in the external system
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceRecord {
+    /// The category of the data, (e.g.: order)
+    pub category: String,
+    /// The subcategory of the data, (e.g.: clothing)
+    pub subcategory: String,
+    /// The name of the source, which overrides the source name of the connector, (e.g.: the MQTT topic of a device)
+    pub source_name: Option<String>,
+    /// The unique identifier of the record in the external system
+    pub source_uid: SourceId,
+    /// The content type of the data, (e.g.: application/json)
+    pub content_type: String,
+    /// The data of the record
+    pub data: Vec<u8>,
+    /// Determines if the record was deleted in the external system
+    pub deleted: bool,
+    /// What the connector commits the record with, (e.g.: the path of the file or the packet identifier of the message)
+    pub receipt: Option<String>,
+}
+
+/// Trait for the connectors that ingest the records of an external system
+pub trait DataSourceConnector {
+    /// Returns the name of the external system, which is the source name of the DaaS documents
+    fn source_name(&self) -> String;
+    /// Returns the records that changed since the last poll
+    fn poll(&mut self) -> Result<Vec<SourceRecord>, RetrieveError>;
+    /// Commits the record once the sink accepted its DaaS document, (e.g.: advances the checkpoint or acknowledges
+    /// the message), so that it isn't returned by the next poll
+    fn commit(&mut self, _record: &SourceRecord) -> Result<(), RetrieveError> {
+        Ok(())
+    }
+    /// Returns how long to wait between the polls, which overrides the interval of the SourceAgent
+    fn interval(&self) -> Option<Duration> {
+        None
+    }
+}
+
+/// A sink that processes the DaaS documents like the listener does, (validated, saved locally and then brokered)
+pub struct ListenerSink<B: DaaSKafkaProcessor + Clone + Send + 'static> {
+    /// The broker
+    pub broker: B,
+    /// The topic to broker the DaaS documents to, (default: category.subcategory.source_name)
+    pub topic: Option<String>,
+}
+
+impl<B: DaaSKafkaProcessor + Clone + Send + 'static> ListenerSink<B> {
+    /// Constructs a ListenerSink
+    ///
+    /// # Arguments
+    ///
+    /// * broker: B - The broker.</br>
+    /// * topic: Option<String> - The topic to broker the DaaS documents to, (e.g.: genesis).</br>
+    pub fn new(broker: B, topic: Option<String>) -> ListenerSink<B> {
+        ListenerSink { broker, topic }
+    }
+}
+
+impl<B: DaaSKafkaProcessor + Clone + Send + 'static> DaaSDocSink for ListenerSink<B> {
+    fn send(&self, doc: DaaSDoc) -> Result<(), DaaSProcessingError> {
+        match DaaSListener::process_data_with_broker(doc, self.topic.clone(), self.broker.clone()) {
+            Ok(_d) => Ok(()),
+            Err(err) => {
+                error!("Could not process the DaaS document. Error: {}", err);
+                Err(DaaSProcessingError::UpsertError)
+            }
+        }
+    }
+}
+
+/// Represents an ingestion agent that polls a connector and emits the records as DaaS documents to a sink
+pub struct SourceAgent {
+    /// The author of the DaaS documents
+    pub author: String,
+    /// The Data Usage Agreements of the DaaS documents
+    pub duas: Vec<DUA>,
+    /// How long to wait between the polls, unless the connector has its own interval, (default: 5 seconds)
+    pub interval: Duration,
+    connector: Box<dyn DataSourceConnector + Send>,
+    sink: Box<dyn DaaSDocSink + Send + Sync>,
+}
+
+impl SourceAgent {
+    /// Constructs a SourceAgent
+    ///
+    /// # Arguments
+    ///
+    /// * connector: Box<dyn DataSourceConnector + Send> - The connector of the external system.</br>
+    /// * author: String - The author of the DaaS documents.</br>
+    /// * duas: Vec<DUA> - The Data Usage Agreements of the DaaS documents.</br>
+    /// * sink: Box<dyn DaaSDocSink + Send + Sync> - Where the DaaS documents are emitted.</br>
+    pub fn new(
+        connector: Box<dyn DataSourceConnector + Send>,
+        author: String,
+        duas: Vec<DUA>,
+        sink: Box<dyn DaaSDocSink + Send + Sync>,
+    ) -> SourceAgent {
+        SourceAgent {
+            author,
+            duas,
+            interval: Duration::from_secs(5),
+            connector,
+            sink,
+        }
+    }
+
+    /// Sets how long to wait between the polls
+    pub fn with_interval(mut self, interval: Duration) -> SourceAgent {
+        self.interval = interval;
+        self
+    }
+
+    /// Returns the DaaS document of the record, with a new Data Tracker Chain
+    ///
+    /// # Arguments
+    ///
+    /// * record: SourceRecord - The record that changed in the external system.</br>
+    pub fn make_doc(&self, record: SourceRecord) -> DaaSDoc {
+        let src_name = record
+            .source_name
+            .clone()
+            .unwrap_or_else(|| self.connector.source_name());
+        let tracker = Tracker::new(DaaSDoc::make_id(
+            record.category.clone(),
+            record.subcategory.clone(),
+            src_name.clone(),
+            record.source_uid.clone(),
+        ));
+        let mut doc = DaaSDoc::new(
+            src_name,
+            record.source_uid,
+            record.category,
+            record.subcategory,
+            self.author.clone(),
+            self.duas.clone(),
+            tracker,
+            record.data,
+        );
+
+        doc.add_meta("content-type".to_string(), record.content_type);
+        if record.deleted {
+            doc.mark_deleted();
+        }
+
+        doc
+    }
+
+    /// Polls the connector once, emits the DaaS documents to the sink and commits their records.
+    /// Returns the number of DaaS documents that were emitted, or the error of the first DaaS document that the sink
+    /// didn't accept, (its record and the ones after it aren't committed, so they are polled again).
+    pub fn run_once(&mut self) -> Result<usize, DaaSProcessingError> {
+        let records = match self.connector.poll() {
+            Ok(r) => r,
+            Err(err) => {
+                error!(
+                    "Could not poll {}. Error: {}",
+                    self.connector.source_name(),
+                    err
+                );
+                return Err(DaaSProcessingError::RetrieveError);
+            }
+        };
+        let mut emitted = 0;
+
+        for record in records {
+            let doc = self.make_doc(record.clone());
+            let daas_id = doc._id.clone();
+            if let Err(err) = self.sink.send(doc) {
+                error!(
+                    "Could not emit the DaaS document {}. Error: {:?}",
+                    daas_id, err
+                );
+                return Err(err);
+            }
+
+            emitted += 1;
+            if let Err(err) = self.connector.commit(&record) {
+                warn!(
+                    "Could not commit the record of the DaaS document {}, so it may be emitted again. Error: {}",
+                    daas_id, err
+                );
+            }
+        }
+
+        Ok(emitted)
+    }
+
+    /// Starts polling the connector in a separate thread until a message is sent to stop, (see `stop()`)
+    pub fn start(mut self) -> Sender<bool> {
+        let (tx, rx) = channel();
+
+        thread::spawn(move || self.run(&rx));
+
+        tx
+    }
+
+    /// Stops the SourceAgent
+    ///
+    /// # Arguments
+    ///
+    /// * tx: Sender<bool> - The sender that was returned by `start()`.</br>
+    pub fn stop(tx: Sender<bool>) {
+        let _ = tx.send(true);
+    }
+
+    fn run(&mut self, rx: &Receiver<bool>) {
+        loop {
+            let _ = self.run_once();
+
+            // wait for the next poll, unless a message is sent to stop
+            match rx.recv_timeout(self.connector.interval().unwrap_or(self.interval)) {
+                Err(std::sync::mpsc::RecvTimeoutError::Timeout) => {}
+                _ => {
+                    info!("Shutting down SourceAgent ...");
+                    break;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eventing::memory::InMemoryBroker;
+    use crate::service::pipeline::TopicSink;
+    use crate::testing;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    // A connector that returns its records until they are committed
+    struct QueueConnector {
+        records: Vec<SourceRecord>,
+    }
+
+    impl DataSourceConnector for QueueConnector {
+        fn source_name(&self) -> String {
+            "iStore".to_string()
+        }
+
+        fn poll(&mut self) -> Result<Vec<SourceRecord>, RetrieveError> {
+            Ok(self.records.clone())
+        }
+
+        fn commit(&mut self, record: &SourceRecord) -> Result<(), RetrieveError> {
+            self.records.retain(|r| r.receipt != record.receipt);
+            Ok(())
+        }
+    }
+
+    // A sink that doesn't accept the DaaS documents until it is fixed
+    struct BrokenSink {
+        broken: Arc<AtomicBool>,
+        sink: TopicSink<InMemoryBroker>,
+    }
+
+    impl DaaSDocSink for BrokenSink {
+        fn send(&self, doc: DaaSDoc) -> Result<(), DaaSProcessingError> {
+            match self.broken.load(Ordering::SeqCst) {
+                true => Err(DaaSProcessingError::BrokerError),
+                false => self.sink.send(doc),
+            }
+        }
+    }
+
+    fn get_record(uid: usize, deleted: bool) -> SourceRecord {
+        SourceRecord {
+            category: "order".to_string(),
+            subcategory: "clothing".to_string(),
+            source_name: None,
+            source_uid: SourceId::from(uid),
+            content_type: "application/json".to_string(),
+            data: br#"{"status": "new"}"#.to_vec(),
+            deleted,
+            receipt: Some(uid.to_string()),
+        }
+    }
+
+    fn get_agent(records: Vec<SourceRecord>, broker: InMemoryBroker) -> SourceAgent {
+        SourceAgent::new(
+            Box::new(QueueConnector { records }),
+            testing::MOCK_AUTHOR.to_string(),
+            testing::get_dua(),
+            Box::new(TopicSink::new(broker, "genesis".to_string())),
+        )
+    }
+
+    #[test]
+    fn test_make_doc() {
+        let agent = get_agent(Vec::new(), InMemoryBroker::new());
+        let mut doc = agent.make_doc(get_record(5000, false));
+
+        assert_eq!(doc._id, "order~clothing~iStore~5000".to_string());
+        assert_eq!(doc.author, testing::MOCK_AUTHOR.to_string());
+        assert_eq!(
+            doc.get_meta("content-type".to_string()),
+            "application/json".to_string()
+        );
+        testing::assert_tracker_valid(&doc.data_tracker);
+        assert!(doc.validate().is_ok());
+        assert!(agent.make_doc(get_record(5001, true)).deleted);
+    }
+
+    #[test]
+    fn test_run_once() {
+        let broker = InMemoryBroker::new();
+        let mut agent = get_agent(
+            vec![get_record(1, false), get_record(2, false)],
+            broker.clone(),
+        );
+
+        assert_eq!(agent.run_once().unwrap(), 2);
+        assert_eq!(agent.run_once().unwrap(), 0);
+        assert_eq!(broker.messages("genesis").len(), 2);
+    }
+
+    #[test]
+    fn test_run_once_sink_failure() {
+        let broker = InMemoryBroker::new();
+        let broken = Arc::new(AtomicBool::new(true));
+        let mut agent = SourceAgent::new(
+            Box::new(QueueConnector {
+                records: vec![get_record(1, false), get_record(2, false)],
+            }),
+            testing::MOCK_AUTHOR.to_string(),
+            testing::get_dua(),
+            Box::new(BrokenSink {
+                broken: broken.clone(),
+                sink: TopicSink::new(broker.clone(), "genesis".to_string()),
+            }),
+        );
+
+        assert!(agent.run_once().is_err());
+        assert!(broker.messages("genesis").is_empty());
+
+        // the records weren't committed, so they are emitted once the sink is fixed
+        broken.store(false, Ordering::SeqCst);
+        assert_eq!(agent.run_once().unwrap(), 2);
+        assert_eq!(agent.run_once().unwrap(), 0);
+        assert_eq!(broker.messages("genesis").len(), 2);
+    }
+
+    #[test]
+    fn test_listener_sink() {
+        let broker = InMemoryBroker::new();
+        let sink = ListenerSink::new(broker.clone(), Some("genesis".to_string()));
+        let mut attempts = 0;
+
+        assert!(sink.send(testing::get_default_daas_doc()).is_ok());
+        while broker.messages("genesis").is_empty() && attempts < 50 {
+            thread::sleep(Duration::from_millis(10));
+            attempts += 1;
+        }
+        assert_eq!(broker.messages("genesis").len(), 1);
+    }
+
+    #[test]
+    fn test_start_stop() {
+        let broker = InMemoryBroker::new();
+        let agent = get_agent(vec![get_record(1, false)], broker.clone())
+            .with_interval(Duration::from_millis(10));
+        let tx = agent.start();
+        let mut attempts = 0;
+
+        while broker.messages("genesis").is_empty() && attempts < 50 {
+            thread::sleep(Duration::from_millis(10));
+            attempts += 1;
+        }
+        SourceAgent::stop(tx);
+        assert_eq!(broker.messages("genesis").len(), 1);
+    }
+}
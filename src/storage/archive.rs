@@ -0,0 +1,311 @@
+//! Tiering of the DaaS documents, where the DaaS documents that haven't been updated for a while are moved from the
+//! primary storage device to a cold (cheaper) storage device.
+//!
+//! The `Archiver` copies the latest revision of an old DaaS document to the cold storage device and then saves a stub
+//! revision in the primary storage device. The stub has no data, is tagged `archived` and points to the revision in
+//! the cold storage device. When a stub is retrieved through the `Archiver`, the DaaS document is rehydrated from the
+//! cold storage device. Saving the rehydrated DaaS document makes it a regular DaaS document of the primary again.
+//!
+//! The earlier revisions remain in the primary storage device until they are removed by the storage device.
+
+use super::*;
+
+/// The tag of the stubs of the archived DaaS documents
+pub const ARCHIVED_TAG: &str = "archived";
+/// The metadata key of when the DaaS document was archived, (Unix seconds)
+pub const META_ARCHIVED_AT: &str = "archived_at";
+/// The metadata key of the revision of the DaaS document in the cold storage device
+pub const META_ARCHIVE_REV: &str = "archive_rev";
+
+/// Represents when the DaaS documents are archived
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ArchivePolicy {
+    /// How long (in seconds) since the DaaS document was last updated before it is archived
+    pub age: u64,
+}
+
+impl ArchivePolicy {
+    /// Constructs an ArchivePolicy
+    ///
+    /// # Arguments
+    ///
+    /// * age: u64 - How long (in seconds) since the DaaS document was last updated before it is archived.</br>
+    pub fn new(age: u64) -> ArchivePolicy {
+        ArchivePolicy { age }
+    }
+
+    /// Determines if the DaaS document should be archived at the time
+    ///
+    /// # Arguments
+    ///
+    /// * doc: &DaaSDoc - The latest revision of the DaaS document.</br>
+    /// * now: u64 - The time (Unix seconds) to evaluate the policy at.</br>
+    pub fn is_due(&self, doc: &DaaSDoc, now: u64) -> bool {
+        !doc.deleted && !is_stub(doc) && doc.last_updated.saturating_add(self.age) <= now
+    }
+}
+
+/// Determines if the DaaS document is the stub of an archived DaaS document
+pub fn is_stub(doc: &DaaSDoc) -> bool {
+    doc.has_tag(ARCHIVED_TAG.to_string())
+}
+
+/// Represents a storage device that moves the old DaaS documents of the primary storage device to a cold storage device
+pub struct Archiver<P: DaaSDocStorage, C: DaaSDocStorage> {
+    /// The storage device of the recent DaaS documents
+    pub primary: P,
+    /// The storage device of the archived DaaS documents
+    pub cold: C,
+    /// When the DaaS documents are archived
+    pub policy: ArchivePolicy,
+}
+
+impl<P: DaaSDocStorage, C: DaaSDocStorage> Archiver<P, C> {
+    /// Constructs an Archiver
+    ///
+    /// # Arguments
+    ///
+    /// * primary: P - The storage device of the recent DaaS documents.</br>
+    /// * cold: C - The storage device of the archived DaaS documents.</br>
+    /// * policy: ArchivePolicy - When the DaaS documents are archived.</br>
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// extern crate daas;
+    ///
+    /// use daas::storage::DaaSDocStorage;
+    /// use daas::storage::archive::{ArchivePolicy, Archiver};
+    /// use daas::storage::memory::InMemoryStorage;
+    /// use daas::testing;
+    ///
+    /// fn main() {
+    ///     let mut doc = testing::get_default_daas_doc();
+    ///     doc.last_updated = 1553988607;
+    ///     let archiver = Archiver::new(InMemoryStorage::new(), InMemoryStorage::new(), ArchivePolicy::new(86400 * 365));
+    ///     let doc = archiver.upsert_daas_doc(doc).unwrap();
+    ///
+    ///     assert_eq!(archiver.archive().unwrap(), vec![doc._id.clone()]);
+    ///     assert_eq!(archiver.get_doc_by_id(doc._id, None).unwrap().data_obj, doc.data_obj);
+    /// }
+    /// ```
+    pub fn new(primary: P, cold: C, policy: ArchivePolicy) -> Archiver<P, C> {
+        Archiver {
+            primary,
+            cold,
+            policy,
+        }
+    }
+
+    /// Archives the DaaS documents of the primary storage device that are due according to the policy.
+    /// Returns the identifiers of the archived DaaS documents.
+    pub fn archive(&self) -> Result<Vec<String>, RetrieveError> {
+        let now = get_unix_now!();
+        let mut archived = Vec::new();
+
+        for doc_id in self.primary.list_doc_ids()? {
+            let due = match self.primary.get_doc_by_id(doc_id.clone(), None) {
+                Ok(doc) => self.policy.is_due(&doc, now),
+                Err(_err) => false,
+            };
+
+            if due && self.archive_doc(doc_id.clone()).is_ok() {
+                archived.push(doc_id);
+            }
+        }
+
+        info!("Archived {} DaaS documents.", archived.len());
+        Ok(archived)
+    }
+
+    /// Moves the latest revision of the DaaS document to the cold storage device, leaving a stub in the primary
+    ///
+    /// # Arguments
+    ///
+    /// * doc_id: String - The identifier of the DaaS document.</br>
+    pub fn archive_doc(&self, doc_id: String) -> Result<DaaSDoc, UpsertError> {
+        let doc = match self.primary.get_doc_by_id(doc_id.clone(), None) {
+            Ok(d) if !is_stub(&d) => d,
+            _ => {
+                warn!("DaaS document {} can't be archived.", doc_id);
+                return Err(UpsertError);
+            }
+        };
+
+        // copy the DaaS document on top of the latest revision in the cold storage device
+        let mut copy = doc.clone();
+        copy._rev = self
+            .cold
+            .get_doc_by_id(doc_id.clone(), None)
+            .ok()
+            .and_then(|d| d._rev);
+        let copy = self.cold.upsert_daas_doc(copy)?;
+
+        let mut stub = doc;
+        stub.data_obj = Vec::new();
+        stub.add_tag(ARCHIVED_TAG.to_string());
+        stub.set_status(DocStatus::Archived)
+            .map_err(|_e| UpsertError)?;
+        stub.add_meta(META_ARCHIVED_AT.to_string(), get_unix_now!().to_string());
+        stub.add_meta(
+            META_ARCHIVE_REV.to_string(),
+            copy._rev.clone().unwrap_or_default(),
+        );
+
+        self.primary.upsert_daas_doc(stub)
+    }
+
+    // Replaces the stub with the archived DaaS document, keeping the revision of the stub
+    fn rehydrate(&self, mut stub: DaaSDoc) -> Result<DaaSDoc, RetrieveError> {
+        let rev = stub.get_meta(META_ARCHIVE_REV.to_string());
+        let mut doc = self.cold.get_doc_by_id(
+            stub._id.clone(),
+            match rev.is_empty() {
+                true => None,
+                false => Some(rev),
+            },
+        )?;
+
+        debug!("Rehydrated DaaS document {} from the archive.", doc._id);
+        doc._rev = stub._rev;
+        Ok(doc)
+    }
+}
+
+impl<P: DaaSDocStorage, C: DaaSDocStorage> DaaSDocStorage for Archiver<P, C> {
+    fn upsert_daas_doc(&self, daas_doc: DaaSDoc) -> Result<DaaSDoc, UpsertError> {
+        self.primary.upsert_daas_doc(daas_doc)
+    }
+
+    fn get_doc_by_id(
+        &self,
+        doc_id: String,
+        doc_rev: Option<String>,
+    ) -> Result<DaaSDoc, RetrieveError> {
+        let doc = self.primary.get_doc_by_id(doc_id, doc_rev)?;
+
+        match is_stub(&doc) {
+            true => self.rehydrate(doc),
+            false => Ok(doc),
+        }
+    }
+
+    fn list_doc_ids(&self) -> Result<Vec<String>, RetrieveError> {
+        self.primary.list_doc_ids()
+    }
+
+    // the status of the stubs, (i.e.: Archived) and not of the rehydrated DaaS documents
+    fn list_doc_ids_by_status(&self, status: DocStatus) -> Result<Vec<String>, RetrieveError> {
+        self.primary.list_doc_ids_by_status(status)
+    }
+
+    fn list_doc_ids_page(
+        &self,
+        cursor: Option<String>,
+        page_size: usize,
+    ) -> Result<Page<String>, RetrieveError> {
+        self.primary.list_doc_ids_page(cursor, page_size)
+    }
+
+    fn soft_delete_daas_doc(&self, doc_id: String) -> Result<DaaSDoc, UpsertError> {
+        self.primary.soft_delete_daas_doc(doc_id)
+    }
+
+    fn restore_daas_doc(&self, doc_id: String) -> Result<DaaSDoc, UpsertError> {
+        self.primary.restore_daas_doc(doc_id)
+    }
+
+    fn set_legal_hold(&self, doc_id: String, hold: bool) -> Result<DaaSDoc, UpsertError> {
+        self.primary.set_legal_hold(doc_id, hold)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::memory::InMemoryStorage;
+    use crate::testing;
+
+    fn get_archiver() -> Archiver<InMemoryStorage, InMemoryStorage> {
+        Archiver::new(
+            InMemoryStorage::new(),
+            InMemoryStorage::new(),
+            ArchivePolicy::new(3600),
+        )
+    }
+
+    #[test]
+    fn test_policy_is_due() {
+        let policy = ArchivePolicy::new(3600);
+        let mut doc = testing::get_default_daas_doc();
+        doc.last_updated = 1000;
+
+        assert!(!policy.is_due(&doc, 4599));
+        assert!(policy.is_due(&doc, 4600));
+        doc.mark_deleted();
+        assert!(!policy.is_due(&doc, 4600));
+    }
+
+    #[test]
+    fn test_archive() {
+        let archiver = get_archiver();
+        let mut old = testing::get_default_daas_doc();
+        old.last_updated = 1553988607;
+        archiver.upsert_daas_doc(old.clone()).unwrap();
+        archiver
+            .upsert_daas_doc(testing::get_daas_doc(
+                "iStore".to_string(),
+                6000,
+                "order".to_string(),
+                "clothing".to_string(),
+            ))
+            .unwrap();
+
+        assert_eq!(archiver.archive().unwrap(), vec![old._id.clone()]);
+        assert!(archiver.archive().unwrap().is_empty());
+
+        let mut stub = archiver
+            .primary
+            .get_doc_by_id(old._id.clone(), None)
+            .unwrap();
+        assert!(is_stub(&stub));
+        assert!(stub.data_obj.is_empty());
+        assert_eq!(stub.get_meta(META_ARCHIVE_REV.to_string()), "1".to_string());
+        assert_eq!(
+            archiver.cold.get_doc_by_id(old._id, None).unwrap().data_obj,
+            old.data_obj
+        );
+    }
+
+    #[test]
+    fn test_rehydrate() {
+        let archiver = get_archiver();
+        let doc = archiver
+            .upsert_daas_doc(testing::get_default_daas_doc())
+            .unwrap();
+        archiver.archive_doc(doc._id.clone()).unwrap();
+
+        let mut rehydrated = archiver.get_doc_by_id(doc._id.clone(), None).unwrap();
+        assert_eq!(rehydrated._rev, Some("2".to_string()));
+        assert_eq!(rehydrated.data_obj, doc.data_obj);
+        assert!(!is_stub(&rehydrated));
+
+        // saving the rehydrated DaaS document brings it back to the primary storage device
+        rehydrated.add_tag("restored".to_string());
+        archiver.upsert_daas_doc(rehydrated).unwrap();
+        let latest = archiver.primary.get_doc_by_id(doc._id, None).unwrap();
+        assert!(!is_stub(&latest));
+        assert_eq!(latest.data_obj, doc.data_obj);
+    }
+
+    #[test]
+    fn test_archive_stub_fails() {
+        let archiver = get_archiver();
+        let doc = archiver
+            .upsert_daas_doc(testing::get_default_daas_doc())
+            .unwrap();
+
+        assert!(archiver.archive_doc(doc._id.clone()).is_ok());
+        assert!(archiver.archive_doc(doc._id).is_err());
+    }
+}
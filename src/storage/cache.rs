@@ -0,0 +1,336 @@
+//! Storage that keeps the recently retrieved DaaS documents in memory, so that processors looking up the same
+//! (hot) DaaS documents don't wait on the storage device every time.
+//!
+//! The cache is a least recently used (LRU) list of DaaS documents. The DaaS documents that couldn't be found are
+//! also cached for a short time (negative caching), so repeated lookups of missing DaaS documents are cheap too.
+//! The DaaS documents that are saved through the CachedStorage replace the cached revisions.
+
+use super::*;
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// The identifier and revision (None for the latest) of a cached DaaS document
+type CacheKey = (String, Option<String>);
+
+// A least recently used list of DaaS documents, where None is a DaaS document that couldn't be found
+struct Lru {
+    entries: HashMap<CacheKey, (Option<DaaSDoc>, Instant)>,
+    order: VecDeque<CacheKey>,
+}
+
+impl Lru {
+    fn new() -> Lru {
+        Lru {
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    fn get(&mut self, key: &CacheKey) -> Option<&(Option<DaaSDoc>, Instant)> {
+        if self.entries.contains_key(key) {
+            self.touch(key);
+        }
+        self.entries.get(key)
+    }
+
+    fn put(&mut self, key: CacheKey, value: Option<DaaSDoc>, capacity: usize) {
+        if self
+            .entries
+            .insert(key.clone(), (value, Instant::now()))
+            .is_some()
+        {
+            self.touch(&key);
+            return;
+        }
+
+        self.order.push_back(key);
+        while self.order.len() > capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.entries.remove(&oldest);
+            }
+        }
+    }
+
+    fn remove(&mut self, key: &CacheKey) {
+        if self.entries.remove(key).is_some() {
+            self.order.retain(|k| k != key);
+        }
+    }
+
+    // Moves the key to the end of the list, (the most recently used)
+    fn touch(&mut self, key: &CacheKey) {
+        self.order.retain(|k| k != key);
+        self.order.push_back(key.clone());
+    }
+}
+
+/// Represents a storage device that caches the recently retrieved DaaS documents of another storage device
+pub struct CachedStorage<S: DaaSDocStorage> {
+    /// The storage device that manages the DaaS documents
+    pub storage: S,
+    /// The maximum number of cached DaaS documents, (default: 1000)
+    pub capacity: usize,
+    /// How long a DaaS document is cached, (default: None until it is evicted)
+    pub ttl: Option<Duration>,
+    /// How long a DaaS document that couldn't be found is cached, (default: 5 seconds)
+    pub negative_ttl: Duration,
+    cache: Mutex<Lru>,
+    hits: AtomicUsize,
+    misses: AtomicUsize,
+}
+
+impl<S: DaaSDocStorage> CachedStorage<S> {
+    /// Constructs a CachedStorage
+    ///
+    /// # Arguments
+    ///
+    /// * storage: S - The storage device that manages the DaaS documents.</br>
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// extern crate daas;
+    ///
+    /// use daas::storage::DaaSDocStorage;
+    /// use daas::storage::cache::CachedStorage;
+    /// use daas::storage::memory::InMemoryStorage;
+    /// use daas::testing;
+    ///
+    /// fn main() {
+    ///     let storage = CachedStorage::new(InMemoryStorage::new()).with_capacity(100);
+    ///     let doc = storage.upsert_daas_doc(testing::get_default_daas_doc()).unwrap();
+    ///
+    ///     storage.get_doc_by_id(doc._id.clone(), None).unwrap();
+    ///     assert_eq!(storage.hits(), 1);
+    /// }
+    /// ```
+    pub fn new(storage: S) -> CachedStorage<S> {
+        CachedStorage {
+            storage,
+            capacity: 1000,
+            ttl: None,
+            negative_ttl: Duration::from_secs(5),
+            cache: Mutex::new(Lru::new()),
+            hits: AtomicUsize::new(0),
+            misses: AtomicUsize::new(0),
+        }
+    }
+
+    /// Sets the maximum number of cached DaaS documents
+    pub fn with_capacity(mut self, capacity: usize) -> CachedStorage<S> {
+        self.capacity = capacity.max(1);
+        self
+    }
+
+    /// Sets how long a DaaS document is cached, (e.g.: when other services also update the storage device)
+    pub fn with_ttl(mut self, ttl: Option<Duration>) -> CachedStorage<S> {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Sets how long a DaaS document that couldn't be found is cached
+    pub fn with_negative_ttl(mut self, negative_ttl: Duration) -> CachedStorage<S> {
+        self.negative_ttl = negative_ttl;
+        self
+    }
+
+    /// Returns the number of lookups that were answered by the cache
+    pub fn hits(&self) -> usize {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of lookups that were passed to the storage device
+    pub fn misses(&self) -> usize {
+        self.misses.load(Ordering::Relaxed)
+    }
+
+    /// Removes all the cached revisions of the DaaS document, (e.g.: when it was updated by another service)
+    ///
+    /// # Arguments
+    ///
+    /// * doc_id: &str - The identifier of the DaaS document.</br>
+    pub fn invalidate(&self, doc_id: &str) {
+        let mut cache = self.cache.lock().unwrap();
+        let keys: Vec<CacheKey> = cache
+            .entries
+            .keys()
+            .filter(|(id, _)| id == doc_id)
+            .cloned()
+            .collect();
+
+        for key in keys.iter() {
+            cache.remove(key);
+        }
+    }
+
+    // Replaces the cached revisions of the DaaS document with the saved revision
+    fn refresh(&self, doc: &DaaSDoc) {
+        self.invalidate(&doc._id);
+
+        let mut cache = self.cache.lock().unwrap();
+        cache.put((doc._id.clone(), None), Some(doc.clone()), self.capacity);
+        cache.put(
+            (doc._id.clone(), doc._rev.clone()),
+            Some(doc.clone()),
+            self.capacity,
+        );
+    }
+}
+
+impl<S: DaaSDocStorage> DaaSDocStorage for CachedStorage<S> {
+    fn upsert_daas_doc(&self, daas_doc: DaaSDoc) -> Result<DaaSDoc, UpsertError> {
+        let doc = self.storage.upsert_daas_doc(daas_doc)?;
+        self.refresh(&doc);
+        Ok(doc)
+    }
+
+    fn get_doc_by_id(
+        &self,
+        doc_id: String,
+        doc_rev: Option<String>,
+    ) -> Result<DaaSDoc, RetrieveError> {
+        let key = (doc_id, doc_rev);
+
+        if let Some((cached, at)) = self.cache.lock().unwrap().get(&key) {
+            let fresh = match cached {
+                Some(_) => self.ttl.iter().all(|ttl| at.elapsed() < *ttl),
+                None => at.elapsed() < self.negative_ttl,
+            };
+            if fresh {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                return cached.clone().ok_or(RetrieveError);
+            }
+        }
+
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let rslt = self.storage.get_doc_by_id(key.0.clone(), key.1.clone());
+        self.cache
+            .lock()
+            .unwrap()
+            .put(key, rslt.as_ref().ok().cloned(), self.capacity);
+
+        rslt
+    }
+
+    fn list_doc_ids(&self) -> Result<Vec<String>, RetrieveError> {
+        self.storage.list_doc_ids()
+    }
+
+    fn list_doc_ids_page(
+        &self,
+        cursor: Option<String>,
+        page_size: usize,
+    ) -> Result<Page<String>, RetrieveError> {
+        self.storage.list_doc_ids_page(cursor, page_size)
+    }
+
+    fn soft_delete_daas_doc(&self, doc_id: String) -> Result<DaaSDoc, UpsertError> {
+        let doc = self.storage.soft_delete_daas_doc(doc_id)?;
+        self.refresh(&doc);
+        Ok(doc)
+    }
+
+    fn restore_daas_doc(&self, doc_id: String) -> Result<DaaSDoc, UpsertError> {
+        let doc = self.storage.restore_daas_doc(doc_id)?;
+        self.refresh(&doc);
+        Ok(doc)
+    }
+
+    fn set_legal_hold(&self, doc_id: String, hold: bool) -> Result<DaaSDoc, UpsertError> {
+        let doc = self.storage.set_legal_hold(doc_id, hold)?;
+        self.refresh(&doc);
+        Ok(doc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::memory::InMemoryStorage;
+    use crate::testing;
+
+    #[test]
+    fn test_get_cached() {
+        let backend = InMemoryStorage::new();
+        let storage = CachedStorage::new(backend.clone());
+        let doc = backend
+            .upsert_daas_doc(testing::get_default_daas_doc())
+            .unwrap();
+
+        storage.get_doc_by_id(doc._id.clone(), None).unwrap();
+        storage.get_doc_by_id(doc._id.clone(), None).unwrap();
+        storage
+            .get_doc_by_id(doc._id.clone(), doc._rev.clone())
+            .unwrap();
+        assert_eq!(storage.misses(), 2);
+        assert_eq!(storage.hits(), 1);
+    }
+
+    #[test]
+    fn test_get_negative_cached() {
+        let backend = InMemoryStorage::new();
+        let storage = CachedStorage::new(backend.clone());
+        let doc = testing::get_default_daas_doc();
+
+        assert!(storage.get_doc_by_id(doc._id.clone(), None).is_err());
+        backend.upsert_daas_doc(doc.clone()).unwrap();
+        assert!(storage.get_doc_by_id(doc._id.clone(), None).is_err());
+        assert_eq!(storage.hits(), 1);
+
+        let storage = CachedStorage::new(backend).with_negative_ttl(Duration::from_secs(0));
+        assert!(storage.get_doc_by_id("missing".to_string(), None).is_err());
+        assert!(storage.get_doc_by_id("missing".to_string(), None).is_err());
+        assert_eq!(storage.misses(), 2);
+    }
+
+    #[test]
+    fn test_upsert_refreshes() {
+        let storage = CachedStorage::new(InMemoryStorage::new());
+        let doc = testing::get_default_daas_doc();
+
+        // the miss is replaced by the saved DaaS document
+        assert!(storage.get_doc_by_id(doc._id.clone(), None).is_err());
+        let mut saved = storage.upsert_daas_doc(doc.clone()).unwrap();
+        saved.add_tag("priority".to_string());
+        storage.upsert_daas_doc(saved).unwrap();
+
+        let latest = storage.get_doc_by_id(doc._id.clone(), None).unwrap();
+        assert_eq!(latest._rev, Some("2".to_string()));
+        assert!(latest.has_tag("priority".to_string()));
+        assert_eq!(storage.hits(), 1);
+
+        let deleted = storage.soft_delete_daas_doc(doc._id.clone()).unwrap();
+        assert!(deleted.deleted);
+        assert!(storage.get_doc_by_id(doc._id, None).unwrap().deleted);
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let backend = InMemoryStorage::new();
+        let storage = CachedStorage::new(backend.clone()).with_capacity(2);
+        let ids: Vec<String> = (1..=3)
+            .map(|uid| {
+                let doc = testing::get_daas_doc(
+                    "iStore".to_string(),
+                    uid,
+                    "order".to_string(),
+                    "clothing".to_string(),
+                );
+                backend.upsert_daas_doc(doc).unwrap()._id
+            })
+            .collect();
+
+        storage.get_doc_by_id(ids[0].clone(), None).unwrap();
+        storage.get_doc_by_id(ids[1].clone(), None).unwrap();
+        storage.get_doc_by_id(ids[0].clone(), None).unwrap();
+        storage.get_doc_by_id(ids[2].clone(), None).unwrap();
+        assert_eq!(storage.hits(), 1);
+
+        // the second DaaS document was the least recently used
+        storage.get_doc_by_id(ids[0].clone(), None).unwrap();
+        storage.get_doc_by_id(ids[1].clone(), None).unwrap();
+        assert_eq!(storage.hits(), 2);
+    }
+}
@@ -0,0 +1,380 @@
+//! Compaction of the revisions of the DaaS documents in the `LocalStorage`.
+//!
+//! Every upsert adds a new revision, so the revision directories keep growing. The `RevisionCompactor` keeps the
+//! latest revisions of each DaaS document (per category, see `CompactionPolicy`) and removes the older revisions,
+//! optionally archiving them to an `ObjectStore` first, (e.g.: a S3 Bucket). A dry run reports which revisions would
+//! be compacted without changing anything. DaaS documents under a legal hold are never compacted.
+//!
+//! The compaction can be run on demand with `compact()`, or on a schedule with `start()` and `stop()`.
+//!
+//! # Examples
+//!
+//! ```
+//! extern crate daas;
+//!
+//! use daas::storage::compaction::{CompactionPolicy, RevisionCompactor};
+//! use daas::storage::local::LocalStorage;
+//!
+//! fn main() {
+//!     let policy = CompactionPolicy::new(10).with_category("order".to_string(), 3);
+//!     let compactor = RevisionCompactor::new(LocalStorage::new("./tests".to_string()), policy).with_dry_run(true);
+//!     let report = compactor.compact().unwrap();
+//!
+//!     println!("{} revisions would be compacted", report.total());
+//! }
+//! ```
+
+use super::local::LocalStorage;
+use super::*;
+use std::collections::BTreeMap;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// Represents how many revisions of the DaaS documents are kept
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CompactionPolicy {
+    /// The number of revisions that are kept of the DaaS documents of the categories without their own setting
+    pub keep: usize,
+    /// The number of revisions that are kept of the DaaS documents of each category (category, revisions)
+    pub categories: BTreeMap<String, usize>,
+}
+
+impl CompactionPolicy {
+    /// Constructs a CompactionPolicy
+    ///
+    /// # Arguments
+    ///
+    /// * keep: usize - The number of revisions that are kept, (at least the latest revision is always kept).</br>
+    pub fn new(keep: usize) -> CompactionPolicy {
+        CompactionPolicy {
+            keep,
+            categories: BTreeMap::new(),
+        }
+    }
+
+    /// Sets the number of revisions that are kept of the DaaS documents of the category
+    ///
+    /// # Arguments
+    ///
+    /// * category: String - The category of the DaaS documents, (e.g.: order).</br>
+    /// * keep: usize - The number of revisions that are kept.</br>
+    pub fn with_category(mut self, category: String, keep: usize) -> CompactionPolicy {
+        self.categories.insert(category, keep);
+        self
+    }
+
+    /// Returns the number of revisions that are kept of the DaaS documents of the category
+    ///
+    /// # Arguments
+    ///
+    /// * category: &str - The category of the DaaS documents.</br>
+    pub fn keep_for(&self, category: &str) -> usize {
+        self.categories
+            .get(category)
+            .cloned()
+            .unwrap_or(self.keep)
+            .max(1)
+    }
+}
+
+/// Represents the outcome of a compaction
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct CompactionReport {
+    /// The indicator that represents if the revisions were only reported, (i.e.: a dry run)
+    pub dry_run: bool,
+    /// The revisions that were compacted of each DaaS document (_id, revisions)
+    pub revisions: BTreeMap<String, Vec<String>>,
+    /// The number of revisions that were archived before they were removed
+    pub archived: usize,
+    /// The _id of the DaaS documents that weren't compacted because they are under a legal hold
+    pub held: Vec<String>,
+    /// The revisions that couldn't be archived or removed (_id, revisions)
+    pub failed: BTreeMap<String, Vec<String>>,
+}
+
+impl CompactionReport {
+    /// Returns the number of revisions that were compacted
+    pub fn total(&self) -> usize {
+        self.revisions.values().map(|r| r.len()).sum()
+    }
+}
+
+/// Represents the job that compacts the revisions of the DaaS documents in the LocalStorage
+pub struct RevisionCompactor {
+    /// The storage of the DaaS documents
+    pub storage: LocalStorage,
+    /// How many revisions are kept
+    pub policy: CompactionPolicy,
+    /// How often the compaction runs when it is started, (default: 1 day)
+    pub interval: Duration,
+    dry_run: bool,
+    archive: Option<(Arc<dyn ObjectStore + Send + Sync>, String)>,
+}
+
+impl RevisionCompactor {
+    /// Constructor
+    ///
+    /// # Arguments
+    ///
+    /// * storage: LocalStorage - The storage of the DaaS documents.</br>
+    /// * policy: CompactionPolicy - How many revisions are kept.</br>
+    pub fn new(storage: LocalStorage, policy: CompactionPolicy) -> RevisionCompactor {
+        RevisionCompactor {
+            storage,
+            policy,
+            interval: Duration::from_secs(86_400),
+            dry_run: false,
+            archive: None,
+        }
+    }
+
+    /// Archives the compacted revisions to the object store before they are removed. The revisions are saved under
+    /// the prefix, (e.g.: compacted/order~clothing~iStore~5000~1.daas).
+    ///
+    /// # Arguments
+    ///
+    /// * store: Arc<dyn ObjectStore + Send + Sync> - The object store of the archived revisions.</br>
+    /// * prefix: String - The prefix of the keys of the archived revisions, (e.g.: compacted).</br>
+    pub fn with_archive(
+        mut self,
+        store: Arc<dyn ObjectStore + Send + Sync>,
+        prefix: String,
+    ) -> RevisionCompactor {
+        self.archive = Some((store, prefix));
+        self
+    }
+
+    /// Only reports the revisions that would be compacted, without archiving or removing them
+    pub fn with_dry_run(mut self, dry_run: bool) -> RevisionCompactor {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Sets how often the compaction runs when it is started
+    pub fn with_interval(mut self, interval: Duration) -> RevisionCompactor {
+        self.interval = interval;
+        self
+    }
+
+    /// Compacts the revisions of all the DaaS documents according to the policy
+    pub fn compact(&self) -> Result<CompactionReport, RetrieveError> {
+        let mut report = CompactionReport {
+            dry_run: self.dry_run,
+            ..Default::default()
+        };
+        let mut cursor = None;
+
+        loop {
+            let page = self.storage.list_doc_ids_page(cursor, 1000)?;
+            for doc_id in page.items {
+                self.compact_doc(doc_id, &mut report);
+            }
+            cursor = page.next_cursor;
+            if cursor.is_none() {
+                break;
+            }
+        }
+
+        info!(
+            "Compacted {} revisions{}.",
+            report.total(),
+            if self.dry_run { " (dry run)" } else { "" }
+        );
+        Ok(report)
+    }
+
+    // Compacts the revisions of the DaaS document, recording the outcome in the report
+    fn compact_doc(&self, doc_id: String, report: &mut CompactionReport) {
+        let latest = match self.storage.get_doc_by_id(doc_id.clone(), None) {
+            Ok(d) => d,
+            Err(_e) => {
+                warn!("Skipping {} while compacting.", doc_id);
+                return;
+            }
+        };
+        if latest.legal_hold {
+            report.held.push(doc_id);
+            return;
+        }
+
+        let revs = self.storage.list_revisions(doc_id.clone());
+        let keep = self.policy.keep_for(&latest.category);
+        if revs.len() <= keep {
+            return;
+        }
+
+        for rev in revs[..revs.len() - keep].iter() {
+            match self.dry_run || self.compact_rev(&doc_id, rev, report).is_ok() {
+                true => report.revisions.entry(doc_id.clone()).or_default(),
+                false => report.failed.entry(doc_id.clone()).or_default(),
+            }
+            .push(rev.clone());
+        }
+    }
+
+    // Archives (if configured) and removes the revision of the DaaS document
+    fn compact_rev(
+        &self,
+        doc_id: &str,
+        rev: &str,
+        report: &mut CompactionReport,
+    ) -> Result<(), UpsertError> {
+        if let Some((store, prefix)) = &self.archive {
+            let doc = self
+                .storage
+                .get_doc_by_id(doc_id.to_string(), Some(rev.to_string()))
+                .map_err(|_e| UpsertError)?;
+            let key = format!("{}/{}{}{}.daas", prefix, doc_id, DELIMITER, rev);
+            if store.put_daas_doc(key, &doc).is_err() {
+                error!(
+                    "Could not archive revision {} of DaaS document {}.",
+                    rev, doc_id
+                );
+                return Err(UpsertError);
+            }
+            report.archived += 1;
+        }
+
+        self.storage
+            .remove_revision(doc_id.to_string(), rev.to_string())
+    }
+
+    /// Starts compacting the revisions on the interval in a separate thread until a message is sent to stop,
+    /// (see `stop()`). The reports are passed to the function.
+    ///
+    /// # Arguments
+    ///
+    /// * on_report: F - The function that is called with the report of every compaction.</br>
+    pub fn start<F: Fn(&CompactionReport) + Send + 'static>(self, on_report: F) -> Sender<bool> {
+        let (tx, rx) = channel();
+
+        thread::spawn(move || self.run(&rx, on_report));
+
+        tx
+    }
+
+    /// Stops the RevisionCompactor
+    ///
+    /// # Arguments
+    ///
+    /// * tx: Sender<bool> - The sender that was returned by `start()`.</br>
+    pub fn stop(tx: Sender<bool>) {
+        let _ = tx.send(true);
+    }
+
+    fn run<F: Fn(&CompactionReport)>(&self, rx: &Receiver<bool>, on_report: F) {
+        loop {
+            match self.compact() {
+                Ok(report) => on_report(&report),
+                Err(_e) => warn!("Could not compact the revisions."),
+            }
+
+            // wait for the next compaction, unless a message is sent to stop
+            match rx.recv_timeout(self.interval) {
+                Err(std::sync::mpsc::RecvTimeoutError::Timeout) => {}
+                _ => {
+                    info!("Shutting down RevisionCompactor ...");
+                    break;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use std::sync::mpsc;
+
+    fn get_storage(revisions: usize) -> (String, LocalStorage, String) {
+        let path = format!("./tmp/compaction-{}", rand::random::<u32>());
+        let storage = LocalStorage::new(path.clone());
+        let mut doc = storage
+            .upsert_daas_doc(testing::get_default_daas_doc())
+            .unwrap();
+        for _ in 1..revisions {
+            doc = storage.upsert_daas_doc(doc).unwrap();
+        }
+        (path, storage, doc._id)
+    }
+
+    #[test]
+    fn test_policy_keep_for() {
+        let policy = CompactionPolicy::new(5)
+            .with_category("order".to_string(), 2)
+            .with_category("invoice".to_string(), 0);
+
+        assert_eq!(policy.keep_for("order"), 2);
+        assert_eq!(policy.keep_for("invoice"), 1);
+        assert_eq!(policy.keep_for("music"), 5);
+    }
+
+    #[test]
+    fn test_compact_dry_run() {
+        let (path, storage, doc_id) = get_storage(4);
+        let compactor =
+            RevisionCompactor::new(storage, CompactionPolicy::new(2)).with_dry_run(true);
+        let report = compactor.compact().unwrap();
+
+        assert!(report.dry_run);
+        assert_eq!(
+            report.revisions.get(&doc_id),
+            Some(&vec!["1".to_string(), "2".to_string()])
+        );
+        assert_eq!(LocalStorage::new(path).list_revisions(doc_id).len(), 4);
+    }
+
+    #[test]
+    fn test_compact_and_archive() {
+        let (path, storage, doc_id) = get_storage(4);
+        let archive = LocalStorage::new(format!("{}-archive", path));
+        let archive_path =
+            archive.get_object_path("compacted/order~clothing~iStore~5000~1.daas".to_string());
+        let compactor = RevisionCompactor::new(
+            storage,
+            CompactionPolicy::new(10).with_category("order".to_string(), 1),
+        )
+        .with_archive(Arc::new(archive), "compacted".to_string());
+        let report = compactor.compact().unwrap();
+
+        assert_eq!(report.total(), 3);
+        assert_eq!(report.archived, 3);
+        assert!(report.failed.is_empty());
+        assert!(std::path::Path::new(&archive_path).is_file());
+        let storage = LocalStorage::new(path);
+        assert_eq!(
+            storage.list_revisions(doc_id.clone()),
+            vec!["4".to_string()]
+        );
+        assert!(storage.get_doc_by_id(doc_id, None).is_ok());
+    }
+
+    #[test]
+    fn test_compact_legal_hold() {
+        let (_path, storage, doc_id) = get_storage(3);
+        storage.set_legal_hold(doc_id.clone(), true).unwrap();
+        let compactor = RevisionCompactor::new(storage, CompactionPolicy::new(1));
+        let report = compactor.compact().unwrap();
+
+        assert_eq!(report.held, vec![doc_id.clone()]);
+        assert_eq!(report.total(), 0);
+        assert_eq!(compactor.storage.list_revisions(doc_id).len(), 4);
+    }
+
+    #[test]
+    fn test_start_stop() {
+        let (_path, storage, _doc_id) = get_storage(3);
+        let (reports_tx, reports_rx) = mpsc::channel();
+        let compactor = RevisionCompactor::new(storage, CompactionPolicy::new(1))
+            .with_interval(Duration::from_millis(50));
+
+        let tx = compactor.start(move |report| {
+            let _ = reports_tx.send(report.total());
+        });
+        assert_eq!(reports_rx.recv_timeout(Duration::from_secs(5)), Ok(2));
+        RevisionCompactor::stop(tx);
+    }
+}
@@ -0,0 +1,424 @@
+//! Storage that keeps identical data objects only once, (i.e.: content-addressable storage of the payloads).
+//!
+//! Sources often resend unchanged payloads, which adds a new revision of the DaaS document with the same data every
+//! time. The `DedupStorage` stores the data object in a `ContentStore` keyed by its SHA-256 hash, and only the
+//! envelope of the DaaS document (with the hash in the `content-sha256` metadata) is stored in the storage device.
+//! Every revision that refers to the data object holds a reference to it, and the data object is removed when the
+//! last reference is released, (e.g.: when the DaaS documents are purged).
+//!
+//! # Examples
+//!
+//! ```
+//! extern crate daas;
+//!
+//! use daas::storage::DaaSDocStorage;
+//! use daas::storage::dedup::{content_hash, ContentStore, DedupStorage, LocalContentStore};
+//! use daas::storage::memory::InMemoryStorage;
+//! use daas::testing;
+//!
+//! fn main() {
+//!     let storage = DedupStorage::new(InMemoryStorage::new(), LocalContentStore::new("./tmp/content".to_string()));
+//!     let doc = storage.upsert_daas_doc(testing::get_default_daas_doc()).unwrap();
+//!     // the source resends the same payload
+//!     let resent = storage.upsert_daas_doc(doc.clone()).unwrap();
+//!
+//!     let hash = content_hash(&resent.data_obj);
+//!     assert!(storage.content.ref_count(&hash) >= 2);
+//! }
+//! ```
+
+use super::duplicates::{DuplicateKind, DuplicateReport};
+#[cfg(feature = "local-storage")]
+use super::local::LocalStorage;
+use super::*;
+use sha2::{Digest, Sha256};
+#[cfg(feature = "local-storage")]
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+
+/// The metadata key that holds the SHA-256 hash of the data object of the stored DaaS documents
+pub const CONTENT_HASH_META: &str = "content-sha256";
+
+/// Returns the SHA-256 hash (hex) that the data object is stored by
+///
+/// # Arguments
+///
+/// * data: &[u8] - The data object.</br>
+pub fn content_hash(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Trait for stores that keep the data objects by their hash, counting the references to each data object
+pub trait ContentStore {
+    /// Adds a reference to the data object, storing it if it doesn't exist yet. Returns the number of references.
+    fn put_content(&self, hash: &str, data: &[u8]) -> Result<u64, UpsertError>;
+    /// Returns the data object
+    fn get_content(&self, hash: &str) -> Result<Vec<u8>, RetrieveError>;
+    /// Removes a reference to the data object, removing the data object with its last reference. Returns the number of
+    /// references that are left.
+    fn release_content(&self, hash: &str) -> Result<u64, UpsertError>;
+    /// Returns the number of references to the data object
+    fn ref_count(&self, hash: &str) -> u64;
+}
+
+/// Represents a content store in a local directory, where each data object is a file named by its hash
+pub struct LocalContentStore {
+    /// The directory of the data objects
+    pub path: String,
+    // serializes the updates of the reference counts
+    lock: Mutex<()>,
+}
+
+impl LocalContentStore {
+    /// Constructor
+    ///
+    /// # Arguments
+    ///
+    /// * dir_path: String - The directory of the data objects, (e.g.: ./tmp/content).</br>
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// extern crate daas;
+    ///
+    /// use daas::storage::dedup::{ContentStore, LocalContentStore};
+    ///
+    /// fn main() {
+    ///     let content = LocalContentStore::new("./tmp/content".to_string());
+    ///
+    ///     assert_eq!(content.ref_count("0000"), 0);
+    /// }
+    /// ```
+    pub fn new(dir_path: String) -> LocalContentStore {
+        LocalContentStore {
+            path: dir_path,
+            lock: Mutex::new(()),
+        }
+    }
+
+    // Returns the path of the data object, (spread over subdirectories by the first 2 characters of the hash)
+    fn get_content_path(&self, hash: &str) -> String {
+        format!("{}/{}/{}", self.path, &hash[..2.min(hash.len())], hash)
+    }
+
+    fn get_refs_path(&self, hash: &str) -> String {
+        format!("{}.refs", self.get_content_path(hash))
+    }
+
+    fn write_ref_count(&self, hash: &str, count: u64) -> Result<(), UpsertError> {
+        match fs::write(self.get_refs_path(hash), count.to_string()) {
+            Ok(_) => Ok(()),
+            Err(err) => {
+                error!(
+                    "Could not update the references to data object {}. {}",
+                    hash, err
+                );
+                Err(UpsertError)
+            }
+        }
+    }
+}
+
+impl ContentStore for LocalContentStore {
+    fn put_content(&self, hash: &str, data: &[u8]) -> Result<u64, UpsertError> {
+        let _guard = self.lock.lock().unwrap();
+        let content_path = self.get_content_path(hash);
+
+        if !Path::new(&content_path).exists() {
+            let stored = Path::new(&content_path)
+                .parent()
+                .map_or(Ok(()), fs::create_dir_all)
+                .and_then(|_| fs::write(&content_path, data));
+            if let Err(err) = stored {
+                error!("Could not store data object {}. {}", hash, err);
+                return Err(UpsertError);
+            }
+            debug!("Stored data object {}.", hash);
+        }
+
+        let count = self.ref_count(hash) + 1;
+        self.write_ref_count(hash, count)?;
+        Ok(count)
+    }
+
+    fn get_content(&self, hash: &str) -> Result<Vec<u8>, RetrieveError> {
+        match fs::read(self.get_content_path(hash)) {
+            Ok(data) => Ok(data),
+            Err(err) => {
+                error!("Could not read data object {}. {}", hash, err);
+                Err(RetrieveError)
+            }
+        }
+    }
+
+    fn release_content(&self, hash: &str) -> Result<u64, UpsertError> {
+        let _guard = self.lock.lock().unwrap();
+        let count = self.ref_count(hash).saturating_sub(1);
+
+        if count > 0 {
+            self.write_ref_count(hash, count)?;
+            return Ok(count);
+        }
+
+        for path in [self.get_content_path(hash), self.get_refs_path(hash)].iter() {
+            if Path::new(path).exists() && fs::remove_file(path).is_err() {
+                error!("Could not remove {}.", path);
+                return Err(UpsertError);
+            }
+        }
+        debug!("Removed data object {}.", hash);
+        Ok(0)
+    }
+
+    fn ref_count(&self, hash: &str) -> u64 {
+        fs::read_to_string(self.get_refs_path(hash))
+            .ok()
+            .and_then(|c| c.trim().parse().ok())
+            .unwrap_or(0)
+    }
+}
+
+/// Represents a storage device that stores each distinct data object only once
+pub struct DedupStorage<S: DaaSDocStorage, C: ContentStore> {
+    /// The storage device that manages the envelopes of the DaaS documents
+    pub storage: S,
+    /// The store of the data objects
+    pub content: C,
+    // the report of the DaaS documents that are resent with the same data object, (see `with_report()`)
+    report: Option<DuplicateReport>,
+}
+
+impl<S: DaaSDocStorage, C: ContentStore> DedupStorage<S, C> {
+    /// Constructor
+    ///
+    /// # Arguments
+    ///
+    /// * storage: S - The storage device that manages the envelopes of the DaaS documents.</br>
+    /// * content: C - The store of the data objects.</br>
+    pub fn new(storage: S, content: C) -> DedupStorage<S, C> {
+        DedupStorage {
+            storage,
+            content,
+            report: None,
+        }
+    }
+
+    /// Records the DaaS documents that are resent with the same data object as their latest revision in the report,
+    /// (see `DuplicateReport`)
+    ///
+    /// # Arguments
+    ///
+    /// * report: DuplicateReport - The shared report of the duplicate submissions.</br>
+    pub fn with_report(mut self, report: DuplicateReport) -> DedupStorage<S, C> {
+        self.report = Some(report);
+        self
+    }
+
+    // Replaces the envelope's reference with the data object that it refers to
+    fn resolve_doc(&self, mut doc: DaaSDoc) -> Result<DaaSDoc, RetrieveError> {
+        if let Some(hash) = doc.remove_meta(CONTENT_HASH_META.to_string()) {
+            doc.data_obj = self.content.get_content(&hash)?;
+        }
+        Ok(doc)
+    }
+
+    // Returns the hashes of the data objects of all the revisions of the DaaS document
+    #[cfg(feature = "local-storage")]
+    fn revision_hashes(&self, doc_id: &str, revs: Vec<String>) -> Vec<String> {
+        revs.into_iter()
+            .filter_map(|rev| {
+                self.storage
+                    .get_doc_by_id(doc_id.to_string(), Some(rev))
+                    .ok()
+            })
+            .filter_map(|d| d.meta_data.get(CONTENT_HASH_META).cloned())
+            .collect()
+    }
+}
+
+#[cfg(feature = "local-storage")]
+impl<C: ContentStore> DedupStorage<LocalStorage, C> {
+    /// Physically removes the (soft) deleted DaaS documents and releases their data objects,
+    /// (see `LocalStorage::purge_deleted()`)
+    pub fn purge_deleted(&self) -> Result<Vec<String>, UpsertError> {
+        self.purge_with(is_purgeable, |s| s.purge_deleted())
+    }
+
+    /// Physically removes the expired DaaS documents and releases their data objects,
+    /// (see `LocalStorage::purge_expired()`)
+    pub fn purge_expired(&self) -> Result<Vec<String>, UpsertError> {
+        self.purge_with(is_expired_purgeable, |s| s.purge_expired())
+    }
+
+    // Collects the data objects of the DaaS documents that will be purged, so they can be released afterwards
+    fn purge_with(
+        &self,
+        predicate: fn(&DaaSDoc) -> bool,
+        purge: fn(&LocalStorage) -> Result<Vec<String>, UpsertError>,
+    ) -> Result<Vec<String>, UpsertError> {
+        let mut hashes = BTreeMap::new();
+        for doc_id in self.storage.list_doc_ids().unwrap_or_default() {
+            match self.storage.get_doc_by_id(doc_id.clone(), None) {
+                Ok(doc) if predicate(&doc) => {
+                    let revs = self.storage.list_revisions(doc_id.clone());
+                    hashes.insert(doc_id.clone(), self.revision_hashes(&doc_id, revs));
+                }
+                _ => {}
+            }
+        }
+
+        let purged = purge(&self.storage)?;
+        for hash in purged.iter().filter_map(|id| hashes.get(id)).flatten() {
+            self.content.release_content(hash)?;
+        }
+
+        Ok(purged)
+    }
+}
+
+impl<S: DaaSDocStorage, C: ContentStore> DaaSDocStorage for DedupStorage<S, C> {
+    fn upsert_daas_doc(&self, mut doc: DaaSDoc) -> Result<DaaSDoc, UpsertError> {
+        let data = std::mem::take(&mut doc.data_obj);
+        let hash = content_hash(&data);
+
+        if let Some(report) = &self.report {
+            let resent = self
+                .storage
+                .get_doc_by_id(doc._id.clone(), None)
+                .map(|latest| latest.meta_data.get(CONTENT_HASH_META) == Some(&hash))
+                .unwrap_or(false);
+            if resent {
+                report.record(&doc.source_name, DuplicateKind::Payload, &doc._id);
+            }
+        }
+
+        self.content.put_content(&hash, &data)?;
+        doc.add_meta(CONTENT_HASH_META.to_string(), hash.clone());
+
+        match self.storage.upsert_daas_doc(doc) {
+            Ok(mut d) => {
+                d.remove_meta(CONTENT_HASH_META.to_string());
+                d.data_obj = data;
+                Ok(d)
+            }
+            Err(err) => {
+                // the revision wasn't stored, so it doesn't refer to the data object
+                self.content.release_content(&hash)?;
+                Err(err)
+            }
+        }
+    }
+
+    fn get_doc_by_id(
+        &self,
+        doc_id: String,
+        doc_rev: Option<String>,
+    ) -> Result<DaaSDoc, RetrieveError> {
+        let doc = self.storage.get_doc_by_id(doc_id, doc_rev)?;
+        self.resolve_doc(doc)
+    }
+
+    fn list_doc_ids(&self) -> Result<Vec<String>, RetrieveError> {
+        self.storage.list_doc_ids()
+    }
+
+    fn list_doc_ids_page(
+        &self,
+        cursor: Option<String>,
+        page_size: usize,
+    ) -> Result<Page<String>, RetrieveError> {
+        self.storage.list_doc_ids_page(cursor, page_size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::memory::InMemoryStorage;
+    use crate::testing;
+
+    fn get_content_store() -> LocalContentStore {
+        LocalContentStore::new(format!("./tmp/content-{}", rand::random::<u32>()))
+    }
+
+    #[test]
+    fn test_content_ref_counting() {
+        let content = get_content_store();
+        let hash = content_hash(b"abc");
+
+        assert_eq!(content.put_content(&hash, b"abc").unwrap(), 1);
+        assert_eq!(content.put_content(&hash, b"abc").unwrap(), 2);
+        assert_eq!(content.get_content(&hash).unwrap(), b"abc".to_vec());
+        assert_eq!(content.release_content(&hash).unwrap(), 1);
+        assert_eq!(content.release_content(&hash).unwrap(), 0);
+        assert!(content.get_content(&hash).is_err());
+    }
+
+    #[test]
+    fn test_upsert_identical_data_stored_once() {
+        let storage = DedupStorage::new(InMemoryStorage::new(), get_content_store());
+        let doc = storage
+            .upsert_daas_doc(testing::get_default_daas_doc())
+            .unwrap();
+        let resent = storage.upsert_daas_doc(doc.clone()).unwrap();
+        let hash = content_hash(&doc.data_obj);
+
+        assert_eq!(resent.data_obj, doc.data_obj);
+        assert!(!resent.meta_data.contains_key(CONTENT_HASH_META));
+        assert_eq!(storage.content.ref_count(&hash), 2);
+
+        // only the envelope is in the storage device
+        let envelope = storage
+            .storage
+            .get_doc_by_id(doc._id.clone(), None)
+            .unwrap();
+        assert!(envelope.data_obj.is_empty());
+        assert_eq!(envelope.meta_data.get(CONTENT_HASH_META), Some(&hash));
+
+        let found = storage
+            .get_doc_by_id(doc._id.clone(), Some("1".to_string()))
+            .unwrap();
+        assert_eq!(found.data_obj, doc.data_obj);
+        assert!(!found.meta_data.contains_key(CONTENT_HASH_META));
+    }
+
+    #[test]
+    fn test_upsert_resent_data_reported() {
+        let report = DuplicateReport::new();
+        let storage = DedupStorage::new(InMemoryStorage::new(), get_content_store())
+            .with_report(report.clone());
+        let mut doc = storage
+            .upsert_daas_doc(testing::get_default_daas_doc())
+            .unwrap();
+        doc = storage.upsert_daas_doc(doc).unwrap();
+        doc.data_obj = br#"{"status": "shipped"}"#.to_vec();
+        storage.upsert_daas_doc(doc.clone()).unwrap();
+
+        // only the resent data object is a duplicate
+        let summary = report.summary();
+        assert_eq!(summary.total, 1);
+        assert_eq!(summary.recent[0].kind, DuplicateKind::Payload);
+        assert_eq!(summary.recent[0].reference, doc._id);
+    }
+
+    #[test]
+    fn test_purge_deleted_releases_content() {
+        let path = format!("./tmp/dedup-{}", rand::random::<u32>());
+        let storage = DedupStorage::new(LocalStorage::new(path), get_content_store());
+        let doc = storage
+            .upsert_daas_doc(testing::get_default_daas_doc())
+            .unwrap();
+        let hash = content_hash(&doc.data_obj);
+        storage.soft_delete_daas_doc(doc._id.clone()).unwrap();
+        assert_eq!(storage.content.ref_count(&hash), 2);
+
+        assert_eq!(storage.purge_deleted().unwrap(), vec![doc._id]);
+        assert_eq!(storage.content.ref_count(&hash), 0);
+        assert!(storage.content.get_content(&hash).is_err());
+    }
+}
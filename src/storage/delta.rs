@@ -0,0 +1,438 @@
+//! Storage that keeps the revisions of large data objects as deltas against the previous revision.
+//!
+//! Every upsert adds a new revision of the DaaS document, so the revision history of multi-MB data objects
+//! quickly becomes expensive. For the configured categories, the `DeltaStorage` stores only the changes against the
+//! previous revision: a JSON Patch (RFC 6902) when both data objects are JSON, otherwise a binary delta of the bytes
+//! that changed. The data object is reconstructed when the revision is read. A full copy of the data object is
+//! stored every `snapshot_interval` revisions so the reconstruction doesn't have to replay the entire history.
+//!
+//! # Examples
+//!
+//! ```
+//! extern crate daas;
+//!
+//! use daas::storage::DaaSDocStorage;
+//! use daas::storage::delta::DeltaStorage;
+//! use daas::storage::memory::InMemoryStorage;
+//! use daas::testing;
+//!
+//! fn main() {
+//!     let storage = DeltaStorage::new(InMemoryStorage::new()).with_category("order".to_string());
+//!     let mut doc = storage.upsert_daas_doc(testing::get_default_daas_doc()).unwrap();
+//!     doc.data_obj = br#"{"status": "shipped"}"#.to_vec();
+//!     storage.upsert_daas_doc(doc.clone()).unwrap();
+//!
+//!     let latest = storage.get_doc_by_id(doc._id.clone(), None).unwrap();
+//!     assert_eq!(latest.data_obj, doc.data_obj);
+//! }
+//! ```
+
+use super::dedup::content_hash;
+use super::*;
+use serde_json::{json, Value};
+
+/// The metadata key that holds the revision that the delta of the stored DaaS document is based upon
+pub const DELTA_BASE_META: &str = "delta-base";
+/// The metadata key that holds the encoding of the delta, (`json-patch` or `binary`)
+pub const DELTA_ENCODING_META: &str = "delta-encoding";
+/// The metadata key that holds the number of deltas since the last full copy of the data object
+pub const DELTA_DEPTH_META: &str = "delta-depth";
+/// The metadata key that holds the SHA-256 hash of the reconstructed data object
+pub const DELTA_HASH_META: &str = "delta-sha256";
+
+const JSON_PATCH: &str = "json-patch";
+const BINARY: &str = "binary";
+
+/// Represents a storage device that stores the revisions of the DaaS documents as deltas
+pub struct DeltaStorage<S: DaaSDocStorage> {
+    /// The storage device that manages the (delta) revisions of the DaaS documents
+    pub storage: S,
+    /// The categories of the DaaS documents that are stored as deltas
+    pub categories: Vec<String>,
+    /// The size (bytes) a data object must have before it is stored as a delta (default: 0)
+    pub min_size: usize,
+    /// The maximum number of consecutive deltas before a full copy of the data object is stored (default: 10)
+    pub snapshot_interval: usize,
+}
+
+impl<S: DaaSDocStorage> DeltaStorage<S> {
+    /// Constructs a DeltaStorage without any categories, (so all the revisions are stored in full)
+    ///
+    /// # Arguments
+    ///
+    /// * storage: S - The storage device that manages the (delta) revisions of the DaaS documents.</br>
+    pub fn new(storage: S) -> DeltaStorage<S> {
+        DeltaStorage {
+            storage,
+            categories: Vec::new(),
+            min_size: 0,
+            snapshot_interval: 10,
+        }
+    }
+
+    /// Stores the revisions of the DaaS documents of the category as deltas
+    ///
+    /// # Arguments
+    ///
+    /// * category: String - The category of the DaaS documents, (e.g.: order).</br>
+    pub fn with_category(mut self, category: String) -> DeltaStorage<S> {
+        self.categories.push(category);
+        self
+    }
+
+    /// Only stores the data objects of at least the size as deltas
+    ///
+    /// # Arguments
+    ///
+    /// * bytes: usize - The minimum size of the data object.</br>
+    pub fn with_min_size(mut self, bytes: usize) -> DeltaStorage<S> {
+        self.min_size = bytes;
+        self
+    }
+
+    /// Stores a full copy of the data object after the number of consecutive deltas
+    ///
+    /// # Arguments
+    ///
+    /// * revisions: usize - The maximum number of consecutive deltas.</br>
+    pub fn with_snapshot_interval(mut self, revisions: usize) -> DeltaStorage<S> {
+        self.snapshot_interval = revisions;
+        self
+    }
+
+    // Determines if the revision of the DaaS document can be stored as a delta
+    fn is_delta_candidate(&self, doc: &DaaSDoc) -> bool {
+        self.categories.contains(&doc.category) && doc.data_obj.len() >= self.min_size
+    }
+
+    // Returns the delta that transforms the base into the data object, and its encoding
+    fn make_delta(base: &[u8], data: &[u8]) -> (Vec<u8>, &'static str) {
+        match (
+            serde_json::from_slice::<Value>(base),
+            serde_json::from_slice::<Value>(data),
+        ) {
+            (Ok(old), Ok(new)) => {
+                let mut ops = Vec::new();
+                crate::doc::json_patch("", &old, &new, &mut ops);
+                (serde_json::to_vec(&ops).unwrap(), JSON_PATCH)
+            }
+            _ => {
+                let prefix = base
+                    .iter()
+                    .zip(data.iter())
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                let suffix = base[prefix..]
+                    .iter()
+                    .rev()
+                    .zip(data[prefix..].iter().rev())
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                let delta = json!({
+                    "prefix": prefix,
+                    "suffix": suffix,
+                    "insert": base64::encode(&data[prefix..data.len() - suffix]),
+                });
+                (serde_json::to_vec(&delta).unwrap(), BINARY)
+            }
+        }
+    }
+
+    // Reconstructs the data object by applying the delta to the base
+    fn apply_delta(base: &[u8], delta: &[u8], encoding: &str) -> Result<Vec<u8>, RetrieveError> {
+        let delta: Value = serde_json::from_slice(delta).map_err(|_e| RetrieveError)?;
+
+        match encoding {
+            JSON_PATCH => {
+                let mut value: Value = serde_json::from_slice(base).map_err(|_e| RetrieveError)?;
+                for op in delta.as_array().ok_or(RetrieveError)?.iter() {
+                    apply_patch_op(&mut value, op)?;
+                }
+                Ok(serde_json::to_vec(&value).unwrap())
+            }
+            BINARY => {
+                let prefix = delta["prefix"].as_u64().ok_or(RetrieveError)? as usize;
+                let suffix = delta["suffix"].as_u64().ok_or(RetrieveError)? as usize;
+                let insert = delta["insert"]
+                    .as_str()
+                    .and_then(|i| base64::decode(i).ok())
+                    .ok_or(RetrieveError)?;
+                if prefix + suffix > base.len() {
+                    return Err(RetrieveError);
+                }
+                let mut data = base[..prefix].to_vec();
+                data.extend(insert);
+                data.extend_from_slice(&base[base.len() - suffix..]);
+                Ok(data)
+            }
+            _ => Err(RetrieveError),
+        }
+    }
+
+    // Reconstructs the data object of the stored revision from its base revision
+    fn resolve_doc(&self, mut doc: DaaSDoc) -> Result<DaaSDoc, RetrieveError> {
+        let hash = doc.remove_meta(DELTA_HASH_META.to_string());
+        let encoding = doc.remove_meta(DELTA_ENCODING_META.to_string());
+        doc.remove_meta(DELTA_DEPTH_META.to_string());
+
+        if let Some(base_rev) = doc.remove_meta(DELTA_BASE_META.to_string()) {
+            let base = self.get_doc_by_id(doc._id.clone(), Some(base_rev))?;
+            doc.data_obj =
+                Self::apply_delta(&base.data_obj, &doc.data_obj, &encoding.unwrap_or_default())?;
+
+            if hash.iter().any(|h| *h != content_hash(&doc.data_obj)) {
+                error!(
+                    "The reconstructed data of DaaS document {} doesn't match its hash.",
+                    doc._id
+                );
+                return Err(RetrieveError);
+            }
+        }
+
+        Ok(doc)
+    }
+}
+
+// Applies a JSON Patch (RFC 6902) operation, (only the add, remove and replace operations are supported)
+fn apply_patch_op(value: &mut Value, op: &Value) -> Result<(), RetrieveError> {
+    let path = op["path"].as_str().ok_or(RetrieveError)?;
+    let new_value = op.get("value").cloned().unwrap_or(Value::Null);
+
+    if path.is_empty() {
+        *value = new_value;
+        return Ok(());
+    }
+
+    let (parent_path, token) = split_pointer(path)?;
+    let parent = value.pointer_mut(parent_path).ok_or(RetrieveError)?;
+
+    match (op["op"].as_str(), parent) {
+        (Some("add"), Value::Object(map)) | (Some("replace"), Value::Object(map)) => {
+            map.insert(token, new_value);
+        }
+        (Some("remove"), Value::Object(map)) => {
+            map.remove(&token).ok_or(RetrieveError)?;
+        }
+        (Some("add"), Value::Array(items)) if token == "-" => items.push(new_value),
+        (Some(action), Value::Array(items)) => {
+            let idx: usize = token.parse().map_err(|_e| RetrieveError)?;
+            match action {
+                "add" if idx <= items.len() => items.insert(idx, new_value),
+                "replace" if idx < items.len() => items[idx] = new_value,
+                "remove" if idx < items.len() => {
+                    items.remove(idx);
+                }
+                _ => return Err(RetrieveError),
+            }
+        }
+        _ => return Err(RetrieveError),
+    }
+
+    Ok(())
+}
+
+// Splits a JSON Pointer into the pointer of the parent and the (unescaped) last reference token
+fn split_pointer(path: &str) -> Result<(&str, String), RetrieveError> {
+    match path.rfind('/') {
+        Some(idx) => Ok((
+            &path[..idx],
+            path[idx + 1..].replace("~1", "/").replace("~0", "~"),
+        )),
+        None => Err(RetrieveError),
+    }
+}
+
+impl<S: DaaSDocStorage> DaaSDocStorage for DeltaStorage<S> {
+    fn upsert_daas_doc(&self, mut doc: DaaSDoc) -> Result<DaaSDoc, UpsertError> {
+        for key in [
+            DELTA_BASE_META,
+            DELTA_ENCODING_META,
+            DELTA_DEPTH_META,
+            DELTA_HASH_META,
+        ]
+        .iter()
+        {
+            doc.remove_meta(key.to_string());
+        }
+
+        if !self.is_delta_candidate(&doc) {
+            return self.storage.upsert_daas_doc(doc);
+        }
+
+        let latest = self.storage.get_doc_by_id(doc._id.clone(), None).ok();
+        let depth = latest
+            .as_ref()
+            .and_then(|l| l.meta_data.get(DELTA_DEPTH_META))
+            .and_then(|d| d.parse::<usize>().ok())
+            .unwrap_or(0);
+        let base = match latest {
+            Some(l) if depth < self.snapshot_interval => self.resolve_doc(l).ok(),
+            _ => None,
+        };
+
+        let data = doc.data_obj.clone();
+        if let Some(base) = base {
+            let (delta, encoding) = Self::make_delta(&base.data_obj, &data);
+            if delta.len() < data.len() {
+                // the delta is only valid on top of its base, so the base must still be the latest revision
+                doc._rev = base._rev.clone();
+                doc.data_obj = delta;
+                doc.add_meta(DELTA_BASE_META.to_string(), base._rev.unwrap_or_default());
+                doc.add_meta(DELTA_ENCODING_META.to_string(), encoding.to_string());
+                doc.add_meta(DELTA_DEPTH_META.to_string(), (depth + 1).to_string());
+                doc.add_meta(DELTA_HASH_META.to_string(), content_hash(&data));
+            }
+        }
+
+        let mut stored = self.storage.upsert_daas_doc(doc)?;
+        for key in [
+            DELTA_BASE_META,
+            DELTA_ENCODING_META,
+            DELTA_DEPTH_META,
+            DELTA_HASH_META,
+        ]
+        .iter()
+        {
+            stored.remove_meta(key.to_string());
+        }
+        stored.data_obj = data;
+        Ok(stored)
+    }
+
+    fn get_doc_by_id(
+        &self,
+        doc_id: String,
+        doc_rev: Option<String>,
+    ) -> Result<DaaSDoc, RetrieveError> {
+        let doc = self.storage.get_doc_by_id(doc_id, doc_rev)?;
+        self.resolve_doc(doc)
+    }
+
+    fn list_doc_ids(&self) -> Result<Vec<String>, RetrieveError> {
+        self.storage.list_doc_ids()
+    }
+
+    fn list_doc_ids_page(
+        &self,
+        cursor: Option<String>,
+        page_size: usize,
+    ) -> Result<Page<String>, RetrieveError> {
+        self.storage.list_doc_ids_page(cursor, page_size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::local::LocalStorage;
+    use crate::storage::memory::InMemoryStorage;
+    use crate::testing;
+
+    fn get_large_doc() -> DaaSDoc {
+        let mut doc = testing::get_default_daas_doc();
+        let items: Vec<Value> = (0..100).map(|i| json!({"sku": i, "qty": 1})).collect();
+        doc.data_obj = serde_json::to_vec(&json!({"status": "new", "items": items})).unwrap();
+        doc
+    }
+
+    #[test]
+    fn test_json_delta_revisions() {
+        let storage = DeltaStorage::new(InMemoryStorage::new()).with_category("order".to_string());
+        let mut doc = storage.upsert_daas_doc(get_large_doc()).unwrap();
+        let original = doc.data_obj.clone();
+
+        let mut data: Value = serde_json::from_slice(&doc.data_obj).unwrap();
+        data["status"] = json!("shipped");
+        data["items"][3]["qty"] = json!(2);
+        data["items"].as_array_mut().unwrap().pop();
+        doc.data_obj = serde_json::to_vec(&data).unwrap();
+        let doc = storage.upsert_daas_doc(doc).unwrap();
+        assert!(!doc.meta_data.contains_key(DELTA_BASE_META));
+
+        // only the changes are stored
+        let stored = storage
+            .storage
+            .get_doc_by_id(doc._id.clone(), None)
+            .unwrap();
+        assert_eq!(
+            stored.meta_data.get(DELTA_ENCODING_META).unwrap(),
+            JSON_PATCH
+        );
+        assert!(stored.data_obj.len() < doc.data_obj.len());
+
+        let latest = storage.get_doc_by_id(doc._id.clone(), None).unwrap();
+        assert_eq!(latest.data_obj, doc.data_obj);
+        assert!(latest.meta_data.keys().all(|k| !k.starts_with("delta-")));
+        let first = storage
+            .get_doc_by_id(doc._id.clone(), Some("1".to_string()))
+            .unwrap();
+        assert_eq!(first.data_obj, original);
+    }
+
+    #[test]
+    fn test_binary_delta_revisions() {
+        let path = format!("./tmp/delta-{}", rand::random::<u32>());
+        let storage = DeltaStorage::new(LocalStorage::new(path)).with_category("order".to_string());
+        let mut doc = testing::get_default_daas_doc();
+        doc.data_obj = vec![7u8; 4096];
+        let mut doc = storage.upsert_daas_doc(doc).unwrap();
+        doc.data_obj.splice(2048..2049, b"changed".iter().cloned());
+        let doc = storage.upsert_daas_doc(doc).unwrap();
+
+        let stored = storage
+            .storage
+            .get_doc_by_id(doc._id.clone(), None)
+            .unwrap();
+        assert_eq!(stored.meta_data.get(DELTA_ENCODING_META).unwrap(), BINARY);
+        assert!(stored.data_obj.len() < 100);
+        assert_eq!(
+            storage.get_doc_by_id(doc._id, None).unwrap().data_obj,
+            doc.data_obj
+        );
+    }
+
+    #[test]
+    fn test_snapshot_interval_and_other_categories() {
+        let storage = DeltaStorage::new(InMemoryStorage::new())
+            .with_category("order".to_string())
+            .with_snapshot_interval(2);
+        let mut doc = storage.upsert_daas_doc(get_large_doc()).unwrap();
+        for status in ["a", "b", "c"].iter() {
+            let mut data: Value = serde_json::from_slice(&doc.data_obj).unwrap();
+            data["status"] = json!(status);
+            doc.data_obj = serde_json::to_vec(&data).unwrap();
+            doc = storage.upsert_daas_doc(doc).unwrap();
+        }
+
+        // revisions 2 and 3 are deltas, revision 4 is a full copy
+        let stored = storage
+            .storage
+            .get_doc_by_id(doc._id.clone(), None)
+            .unwrap();
+        assert!(!stored.meta_data.contains_key(DELTA_BASE_META));
+        assert_eq!(stored.data_obj, doc.data_obj);
+        let third = storage
+            .get_doc_by_id(doc._id.clone(), Some("3".to_string()))
+            .unwrap();
+        assert!(String::from_utf8(third.data_obj)
+            .unwrap()
+            .contains(r#""status":"b""#));
+
+        let storage =
+            DeltaStorage::new(InMemoryStorage::new()).with_category("invoice".to_string());
+        let mut doc = storage.upsert_daas_doc(get_large_doc()).unwrap();
+        doc.data_obj = br#"{"status": "shipped"}"#.to_vec();
+        storage.upsert_daas_doc(doc.clone()).unwrap();
+        let stored = storage.storage.get_doc_by_id(doc._id, None).unwrap();
+        assert_eq!(stored.data_obj, doc.data_obj);
+    }
+
+    #[test]
+    fn test_apply_patch_op_invalid() {
+        let mut value = json!({"items": [1]});
+
+        assert!(apply_patch_op(&mut value, &json!({"op": "remove", "path": "/items/5"})).is_err());
+        assert!(apply_patch_op(&mut value, &json!({"op": "move", "path": "/items/0"})).is_err());
+        assert!(apply_patch_op(&mut value, &json!({"op": "add", "path": "/missing/a"})).is_err());
+    }
+}
@@ -0,0 +1,231 @@
+//! A report of the duplicate submissions that were detected, so that the producers whose retry logic is misbehaving
+//! can be notified.
+//!
+//! The `DuplicateReport` counts the duplicates of each source and keeps the most recent ones as examples. It is fed by
+//! - the `DedupStorage`, when a DaaS document is resent with the same data object as its latest revision,
+//!   (see `DedupStorage::with_report()`)
+//! - the DaaS listener, when a request is answered with the original response of its Idempotency-Key,
+//!   (see `IdempotencyStore`)
+//!
+//! The DaaS listener serves the summary (GET on `/duplicates`) when the report is registered as application data,
+//! (e.g.: App::new().data(DuplicateReport::new())). The report is cloned (it is shared) to feed it from the
+//! `DedupStorage` too.
+//!
+//! # Examples
+//!
+//! ```
+//! extern crate daas;
+//!
+//! use daas::storage::DaaSDocStorage;
+//! use daas::storage::dedup::{DedupStorage, LocalContentStore};
+//! use daas::storage::duplicates::DuplicateReport;
+//! use daas::storage::memory::InMemoryStorage;
+//! use daas::testing;
+//!
+//! fn main() {
+//!     let report = DuplicateReport::new();
+//!     let storage = DedupStorage::new(InMemoryStorage::new(), LocalContentStore::new("./tmp/content".to_string()))
+//!         .with_report(report.clone());
+//!     let doc = storage.upsert_daas_doc(testing::get_default_daas_doc()).unwrap();
+//!     // the source resends the same payload
+//!     storage.upsert_daas_doc(doc).unwrap();
+//!
+//!     let summary = report.summary();
+//!     assert_eq!(summary.sources.get("iStore").unwrap().count, 1);
+//!     assert_eq!(summary.recent.len(), 1);
+//! }
+//! ```
+
+use super::*;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+/// The default number of recent duplicates that are kept as examples
+pub const DUPLICATE_EXAMPLES: usize = 20;
+
+/// Represents how a duplicate submission was detected
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicateKind {
+    /// The data object is the same as the one of the latest revision of the DaaS document
+    Payload,
+    /// The request was already answered for its Idempotency-Key
+    IdempotencyKey,
+}
+
+/// Represents a duplicate submission
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DuplicateExample {
+    /// The source that submitted the data
+    pub source: String,
+    /// How the duplicate was detected
+    pub kind: DuplicateKind,
+    /// What identifies the submission, (e.g.: the identifier of the DaaS document or the Idempotency-Key)
+    pub reference: String,
+    /// The Unix time when the duplicate was detected
+    pub timestamp: u64,
+}
+
+/// Represents the duplicates of a source
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct SourceDuplicates {
+    /// The number of duplicates
+    pub count: u64,
+    /// The number of duplicates by how they were detected
+    pub kinds: BTreeMap<DuplicateKind, u64>,
+    /// The Unix time when the latest duplicate was detected
+    pub last_seen: u64,
+}
+
+/// Represents the summary of the duplicates, (the body of the `/duplicates` service)
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct DuplicateSummary {
+    /// The number of duplicates of all the sources
+    pub total: u64,
+    /// The duplicates by source
+    pub sources: BTreeMap<String, SourceDuplicates>,
+    /// The most recent duplicates, (newest first)
+    pub recent: Vec<DuplicateExample>,
+}
+
+/// Represents the shared report of the duplicate submissions
+#[derive(Clone)]
+pub struct DuplicateReport {
+    /// The number of recent duplicates that are kept as examples, (default: 20)
+    pub examples: usize,
+    tally: Arc<Mutex<Tally>>,
+}
+
+// the duplicates by source and the most recent duplicates, (newest first)
+#[derive(Default)]
+struct Tally {
+    sources: BTreeMap<String, SourceDuplicates>,
+    recent: VecDeque<DuplicateExample>,
+}
+
+impl Default for DuplicateReport {
+    fn default() -> Self {
+        DuplicateReport::new()
+    }
+}
+
+impl DuplicateReport {
+    /// Constructs an empty DuplicateReport
+    pub fn new() -> DuplicateReport {
+        DuplicateReport {
+            examples: DUPLICATE_EXAMPLES,
+            tally: Arc::new(Mutex::new(Tally::default())),
+        }
+    }
+
+    /// Sets the number of recent duplicates that are kept as examples
+    ///
+    /// # Arguments
+    ///
+    /// * examples: usize - The number of examples.</br>
+    pub fn with_examples(mut self, examples: usize) -> DuplicateReport {
+        self.examples = examples;
+        self
+    }
+
+    /// Records a duplicate submission that is detected now
+    ///
+    /// # Arguments
+    ///
+    /// * source: &str - The source that submitted the data, (e.g.: iStore).</br>
+    /// * kind: DuplicateKind - How the duplicate was detected.</br>
+    /// * reference: &str - What identifies the submission, (e.g.: order~clothing~iStore~5000).</br>
+    pub fn record(&self, source: &str, kind: DuplicateKind, reference: &str) {
+        self.record_at(source, kind, reference, get_unix_now!())
+    }
+
+    /// Records a duplicate submission that was detected at the time
+    ///
+    /// # Arguments
+    ///
+    /// * source: &str - The source that submitted the data, (e.g.: iStore).</br>
+    /// * kind: DuplicateKind - How the duplicate was detected.</br>
+    /// * reference: &str - What identifies the submission, (e.g.: order~clothing~iStore~5000).</br>
+    /// * timestamp: u64 - The Unix time when the duplicate was detected.</br>
+    pub fn record_at(&self, source: &str, kind: DuplicateKind, reference: &str, timestamp: u64) {
+        debug!(
+            "Duplicate submission {} from {} ({:?}).",
+            reference, source, kind
+        );
+        let mut tally = self.tally.lock().unwrap();
+
+        let dups = tally.sources.entry(source.to_string()).or_default();
+        dups.count += 1;
+        *dups.kinds.entry(kind).or_insert(0) += 1;
+        dups.last_seen = dups.last_seen.max(timestamp);
+
+        tally.recent.push_front(DuplicateExample {
+            source: source.to_string(),
+            kind,
+            reference: reference.to_string(),
+            timestamp,
+        });
+        tally.recent.truncate(self.examples);
+    }
+
+    /// Returns the summary of the duplicates that have been recorded
+    pub fn summary(&self) -> DuplicateSummary {
+        let tally = self.tally.lock().unwrap();
+
+        DuplicateSummary {
+            total: tally.sources.values().map(|s| s.count).sum(),
+            sources: tally.sources.clone(),
+            recent: tally.recent.iter().cloned().collect(),
+        }
+    }
+
+    /// Clears the report, (e.g.: once the producers have been notified)
+    pub fn reset(&self) {
+        let mut tally = self.tally.lock().unwrap();
+        tally.sources.clear();
+        tally.recent.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary() {
+        let report = DuplicateReport::new().with_examples(2);
+        report.record_at("iStore", DuplicateKind::Payload, "a", 10);
+        report.record_at("iStore", DuplicateKind::IdempotencyKey, "key-1", 20);
+        report.record_at("myStore", DuplicateKind::Payload, "b", 30);
+
+        let summary = report.summary();
+        let istore = summary.sources.get("iStore").unwrap();
+        assert_eq!(summary.total, 3);
+        assert_eq!(istore.count, 2);
+        assert_eq!(istore.kinds.get(&DuplicateKind::Payload), Some(&1));
+        assert_eq!(istore.last_seen, 20);
+        assert_eq!(summary.recent.len(), 2);
+        assert_eq!(summary.recent[0].reference, "b".to_string());
+        assert_eq!(summary.recent[1].reference, "key-1".to_string());
+    }
+
+    #[test]
+    fn test_reset() {
+        let report = DuplicateReport::new();
+        report.clone().record("iStore", DuplicateKind::Payload, "a");
+        assert_eq!(report.summary().total, 1);
+
+        report.reset();
+        assert_eq!(report.summary(), DuplicateSummary::default());
+    }
+
+    #[test]
+    fn test_serialize() {
+        let report = DuplicateReport::new();
+        report.record_at("iStore", DuplicateKind::IdempotencyKey, "key-1", 10);
+
+        let json = serde_json::to_value(report.summary()).unwrap();
+        assert_eq!(json["sources"]["iStore"]["kinds"]["idempotency_key"], 1);
+        assert_eq!(json["recent"][0]["kind"], "idempotency_key");
+    }
+}
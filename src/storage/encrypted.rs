@@ -0,0 +1,209 @@
+//! Local storage that keeps the DaaS documents encrypted at rest.
+//!
+//! The whole serialized DaaS document, (data object, metadata, usage agreements, tracker, tags and the rest of the
+//! envelope) is encrypted by the `DaaSSecurityGuard` before it is written to the file of its revision and to the
+//! write-ahead log, and decrypted when it is read, (see `LocalStorage::with_codec()`).
+//!
+//! What remains readable on disk:
+//!
+//! + The _id and revision of the DaaS documents, since the files of the revisions are stored under the
+//!   category/subcategory/source name/source uid directories and named after the _id and revision
+//! + The index entries (`IndexEntry`) that the lookups, (e.g.: `find_by_tag()` and `find_by_status()`) use: the
+//!   _id, latest revision, category, subcategory, source name, tags, processed indicator, status and last updated time
+//!
+//! Don't put sensitive information in the identifiers or tags of DaaS documents that are kept in the encrypted storage.
+
+use super::local::{DocCodec, LocalStorage, UsageReport};
+use super::*;
+use crate::security::DaaSSecurityGuard;
+
+/// Represents a LocalStorage that encrypts the DaaS documents, (only the identifiers and the index entries are
+/// readable on disk)
+pub struct EncryptedLocalStorage {
+    /// The local storage that manages the encrypted DaaS documents
+    pub storage: LocalStorage,
+}
+
+// The codec that encrypts the serialized DaaS documents with the security guard
+struct EncryptionCodec {
+    guard: DaaSSecurityGuard,
+}
+
+impl DocCodec for EncryptionCodec {
+    fn encode(&self, content: &[u8]) -> Result<Vec<u8>, UpsertError> {
+        self.guard.encrypt_data(content).map_err(|err| {
+            error!("Could not encrypt the DaaS document. {}", err);
+            UpsertError
+        })
+    }
+
+    fn decode(&self, content: &[u8]) -> Result<Vec<u8>, RetrieveError> {
+        self.guard.decrypt_data(content).map_err(|err| {
+            error!("Could not decrypt the DaaS document. {}", err);
+            RetrieveError
+        })
+    }
+}
+
+impl EncryptedLocalStorage {
+    /// Constructor
+    ///
+    /// # Arguments
+    ///
+    /// * storage: LocalStorage - The local storage that manages the encrypted DaaS documents.</br>
+    /// * guard: DaaSSecurityGuard - The security guard that encrypts and decrypts the data.</br>
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// extern crate daas;
+    ///
+    /// use daas::security::{DaaSSecurityGuard, StaticKeyProvider};
+    /// use daas::storage::local::LocalStorage;
+    /// use daas::storage::encrypted::EncryptedLocalStorage;
+    ///
+    /// fn main() {
+    ///     let key = DaaSSecurityGuard::generate_symmetric_key();
+    ///     let guard = DaaSSecurityGuard::new(Box::new(StaticKeyProvider::new(key)));
+    ///     let storage = EncryptedLocalStorage::new(LocalStorage::new("./tmp".to_string()), guard);
+    /// }
+    /// ```
+    pub fn new(storage: LocalStorage, guard: DaaSSecurityGuard) -> EncryptedLocalStorage {
+        EncryptedLocalStorage {
+            storage: storage.with_codec(Box::new(EncryptionCodec { guard })),
+        }
+    }
+
+    /// Marks the DaaS document as processed, (see `LocalStorage::mark_doc_as_processed()`)
+    ///
+    /// # Arguments
+    ///
+    /// * doc: DaaSDoc - The DaaS document to mark as processed.</br>
+    pub fn mark_doc_as_processed(&self, doc: DaaSDoc) -> Result<DaaSDoc, UpsertError> {
+        self.storage.mark_doc_as_processed(doc)
+    }
+
+    /// Returns the revisions of the DaaS document in numerical order, (see `LocalStorage::list_revisions()`)
+    pub fn list_revisions(&self, doc_id: String) -> Vec<String> {
+        self.storage.list_revisions(doc_id)
+    }
+
+    /// Returns the disk usage of the storage directory and of each category, (see `LocalStorage::usage_report()`)
+    pub fn usage_report(&self) -> UsageReport {
+        self.storage.usage_report()
+    }
+
+    /// Physically removes the (soft) deleted DaaS documents, (see `LocalStorage::purge_deleted()`)
+    pub fn purge_deleted(&self) -> Result<Vec<String>, UpsertError> {
+        self.storage.purge_deleted()
+    }
+
+    /// Physically removes the expired DaaS documents, (see `LocalStorage::purge_expired()`)
+    pub fn purge_expired(&self) -> Result<Vec<String>, UpsertError> {
+        self.storage.purge_expired()
+    }
+}
+
+impl DaaSDocStorage for EncryptedLocalStorage {
+    fn upsert_daas_doc(&self, doc: DaaSDoc) -> Result<DaaSDoc, UpsertError> {
+        self.storage.upsert_daas_doc(doc)
+    }
+
+    fn get_doc_by_id(
+        &self,
+        doc_id: String,
+        doc_rev: Option<String>,
+    ) -> Result<DaaSDoc, RetrieveError> {
+        self.storage.get_doc_by_id(doc_id, doc_rev)
+    }
+
+    fn list_doc_ids(&self) -> Result<Vec<String>, RetrieveError> {
+        self.storage.list_doc_ids()
+    }
+
+    fn list_doc_ids_page(
+        &self,
+        cursor: Option<String>,
+        page_size: usize,
+    ) -> Result<Page<String>, RetrieveError> {
+        self.storage.list_doc_ids_page(cursor, page_size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::security::StaticKeyProvider;
+    use crate::testing;
+    use std::fs;
+
+    fn get_guard(key: Vec<u8>) -> DaaSSecurityGuard {
+        DaaSSecurityGuard::new(Box::new(StaticKeyProvider::new(key)))
+    }
+
+    fn get_daas_doc() -> DaaSDoc {
+        let mut doc = testing::get_daas_doc(
+            "iStore".to_string(),
+            7000,
+            "order".to_string(),
+            "clothing".to_string(),
+        );
+        doc.add_meta("customer".to_string(), "Jane Doe".to_string());
+        doc.add_tag("priority".to_string());
+        doc
+    }
+
+    #[test]
+    fn test_upsert_and_get_encrypted() {
+        let path = format!("./tmp/encrypted-{}", rand::random::<u32>());
+        let storage = EncryptedLocalStorage::new(
+            LocalStorage::new(path.clone()),
+            get_guard(DaaSSecurityGuard::generate_symmetric_key()),
+        );
+        let doc = storage.upsert_daas_doc(get_daas_doc()).unwrap();
+        assert_eq!(doc.data_obj, get_daas_doc().data_obj);
+
+        // the DaaS document on disk is not readable, (only its index entry)
+        let plain = LocalStorage::new(path.clone());
+        assert!(plain.get_doc_by_id(doc._id.clone(), None).is_err());
+        let revision = format!(
+            "{}/order/clothing/iStore/7000/{}~{}",
+            path,
+            doc._id,
+            doc._rev.clone().unwrap()
+        );
+        let on_disk = fs::read(&revision).unwrap();
+        for clear in ["Jane Doe", "customer", "istore_app", "billing", "priority"].iter() {
+            assert!(!on_disk.windows(clear.len()).any(|w| w == clear.as_bytes()));
+        }
+        assert_eq!(
+            plain.get_index_entry(doc._id.clone()).unwrap().tags,
+            doc.tags
+        );
+
+        let found = storage.get_doc_by_id(doc._id.clone(), None).unwrap();
+        assert_eq!(found.data_obj, doc.data_obj);
+        assert_eq!(found.meta_data, doc.meta_data);
+
+        let processed = storage.mark_doc_as_processed(found).unwrap();
+        assert!(processed.process_ind);
+        assert_eq!(processed.data_obj, doc.data_obj);
+        assert_eq!(processed.meta_data, doc.meta_data);
+    }
+
+    #[test]
+    fn test_get_wrong_key() {
+        let path = format!("./tmp/encrypted-{}", rand::random::<u32>());
+        let storage = EncryptedLocalStorage::new(
+            LocalStorage::new(path.clone()),
+            get_guard(DaaSSecurityGuard::generate_symmetric_key()),
+        );
+        let doc = storage.upsert_daas_doc(get_daas_doc()).unwrap();
+
+        let other = EncryptedLocalStorage::new(
+            LocalStorage::new(path),
+            get_guard(DaaSSecurityGuard::generate_symmetric_key()),
+        );
+        assert!(other.get_doc_by_id(doc._id, None).is_err());
+    }
+}
@@ -1,14 +1,262 @@
 use super::*;
+use std::collections::{BTreeMap, HashMap};
 use std::env;
 use std::fs;
-use std::fs::File;
+use std::fs::OpenOptions;
 use std::io::prelude::*;
+use std::io::ErrorKind;
 use std::path::Path;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// The directory (relative to the storage path) that holds the write-ahead log
+const WAL_DIR: &str = ".wal";
+// The log entry extension for a new revision of a DaaS document
+const WAL_NEW: &str = "new";
+// The log entry extension for an update to an existing revision of a DaaS document
+const WAL_UPDATE: &str = "upd";
+
+// The directory (relative to the storage path) that holds the index
+const INDEX_DIR: &str = ".index";
+
+// The directory (relative to the storage path) that holds the provisioned objects
+const OBJECT_DIR: &str = ".objects";
+
+// The directory (relative to the storage path) where fsck moves the files it couldn't make sense of
+const LOST_DIR: &str = ".lost+found";
+
+// The directory (relative to the storage path) that holds the locks of the DaaS documents being upserted
+const LOCK_DIR: &str = ".locks";
+// How long to wait for the lock of a DaaS document before the upsert fails
+const LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+// How old a lock can be before it is considered abandoned, (e.g.: by a process that crashed)
+const LOCK_STALE: Duration = Duration::from_secs(30);
+// How long to wait before trying to acquire the lock again
+const LOCK_RETRY: Duration = Duration::from_millis(5);
+
+/// Represents the indexed attributes of the latest revision of a DaaS document managed by LocalStorage
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct IndexEntry {
+    /// The unique identifier
+    pub _id: String,
+    /// The latest revision number
+    pub latest_rev: String,
+    /// The name of the category (e.g.: order)
+    pub category: String,
+    /// The name of the subcategory (e.g.: clothing)
+    pub subcategory: String,
+    /// The name of the data source
+    pub source_name: String,
+    /// List of tags to provide context about the data object
+    pub tags: Vec<String>,
+    /// The indicator that represents if the document has been processed
+    pub process_ind: bool,
+    /// The status of the document, (see `DocStatus`)
+    #[serde(default)]
+    pub status: DocStatus,
+    /// The Unix Epoch time when the document was last updated, (e.g.: 1555972752)
+    pub last_updated: u64,
+}
+
+impl IndexEntry {
+    // Builds the index entry from a saved DaaS document
+    fn from_doc(doc: &DaaSDoc) -> IndexEntry {
+        IndexEntry {
+            _id: doc._id.clone(),
+            latest_rev: doc._rev.clone().unwrap_or_else(|| "0".to_string()),
+            category: doc.category.clone(),
+            subcategory: doc.subcategory.clone(),
+            source_name: doc.source_name.clone(),
+            tags: doc.tags.clone(),
+            process_ind: doc.process_ind,
+            status: doc.status,
+            last_updated: doc.last_updated,
+        }
+    }
+}
+
+/// Represents an inconsistency found by `LocalStorage::fsck()`
+#[derive(Debug, Clone, PartialEq)]
+pub enum FsckIssue {
+    /// A file in the directory tree that isn't a revision of a DaaS document
+    OrphanedFile(String),
+    /// The revisions that are missing between the first and latest revision of a DaaS document
+    RevisionGap {
+        /// The _id of the DaaS document
+        doc_id: String,
+        /// The missing revisions
+        missing: Vec<String>,
+    },
+    /// A revision file that can't be read as a DaaS document
+    Unserializable(String),
+    /// A revision file whose DaaS document has a different _id or _rev than its path
+    MismatchedId {
+        /// The path of the revision file
+        path: String,
+        /// The _id and _rev of the DaaS document in the file, (e.g.: order~clothing~iStore~5000~3)
+        found: String,
+    },
+    /// An index entry of a DaaS document that doesn't have any revisions
+    OrphanedIndexEntry(String),
+}
+
+/// The outcome of checking the directory tree of a LocalStorage
+#[derive(Debug, Clone, Default)]
+pub struct FsckReport {
+    /// The number of DaaS documents that were checked
+    pub docs: usize,
+    /// The number of revisions that were checked
+    pub revisions: usize,
+    /// The inconsistencies that were found
+    pub issues: Vec<FsckIssue>,
+    /// The files that were moved to the lost+found directory and the index entries that were removed
+    pub repaired: Vec<String>,
+}
+
+impl FsckReport {
+    /// Determines if no inconsistencies were found
+    pub fn is_clean(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+/// Represents the disk quotas of a LocalStorage, in bytes
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StorageQuota {
+    /// The maximum size of the storage directory, (default: None for no limit)
+    pub total: Option<u64>,
+    /// The maximum size of the directory of the categories, (e.g.: order)
+    pub categories: HashMap<String, u64>,
+    /// Whether to purge the deleted and expired DaaS documents before rejecting an upsert that exceeds a quota
+    pub purge_when_exceeded: bool,
+}
+
+impl StorageQuota {
+    /// Constructs a StorageQuota without any limits
+    pub fn new() -> StorageQuota {
+        StorageQuota::default()
+    }
+
+    /// Sets the maximum size of the storage directory
+    pub fn with_total(mut self, bytes: u64) -> StorageQuota {
+        self.total = Some(bytes);
+        self
+    }
+
+    /// Sets the maximum size of the directory of the category
+    pub fn with_category(mut self, category: String, bytes: u64) -> StorageQuota {
+        self.categories.insert(category, bytes);
+        self
+    }
+
+    /// Sets whether to purge the deleted and expired DaaS documents before rejecting an upsert
+    pub fn with_purge_when_exceeded(mut self, purge: bool) -> StorageQuota {
+        self.purge_when_exceeded = purge;
+        self
+    }
+}
+
+/// Trait for the codecs that transform the serialized DaaS documents before they are written to the files of the
+/// revisions and the write-ahead log, (e.g.: to encrypt them, see `EncryptedLocalStorage`)
+pub trait DocCodec {
+    /// Encodes the serialized DaaS document
+    ///
+    /// # Arguments
+    ///
+    /// * content: &[u8] - The serialized DaaS document.</br>
+    fn encode(&self, content: &[u8]) -> Result<Vec<u8>, UpsertError>;
+
+    /// Decodes the content that was encoded using `encode()` back into the serialized DaaS document
+    ///
+    /// # Arguments
+    ///
+    /// * content: &[u8] - The content of the file.</br>
+    fn decode(&self, content: &[u8]) -> Result<Vec<u8>, RetrieveError>;
+}
+
+/// The disk usage of a LocalStorage, in bytes
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UsageReport {
+    /// The size of the storage directory, (including the index and write-ahead log, but not the locks)
+    pub total: u64,
+    /// The size of the directory of each category
+    pub categories: BTreeMap<String, u64>,
+}
+
+/// The outcome of replaying the write-ahead log of a LocalStorage
+#[derive(Debug, Clone)]
+pub struct RecoveryReport {
+    /// The unique identifiers (including the revision) of the DaaS documents that were recovered
+    pub recovered: Vec<String>,
+    /// The names of the write-ahead log entries that were discarded
+    pub discarded: Vec<String>,
+}
+
+// The lock of a DaaS document, which is released when it is dropped. The lock file holds the owner token of the
+// writer, (its process id and a random nonce), so a writer whose abandoned lock was broken doesn't release the lock
+// of the next writer.
+struct DocLock {
+    path: String,
+    token: String,
+}
+
+impl DocLock {
+    // Removes the lock file if it still holds the owner token. The lock file is first moved aside by an atomic
+    // rename, so only one writer takes it, and it is put back (unless a new lock was created meanwhile) if it
+    // belongs to another writer.
+    fn take(path: &str, token: &str) -> std::io::Result<bool> {
+        let taken = format!(
+            "{}.{}-{}.taken",
+            path,
+            std::process::id(),
+            rand::random::<u64>()
+        );
+        fs::rename(path, &taken)?;
+
+        let owned = fs::read_to_string(&taken)
+            .map(|t| t == token)
+            .unwrap_or(false);
+        if !owned {
+            if let Err(e) = fs::hard_link(&taken, path) {
+                warn!(
+                    "Could not put back the lock {} of another writer because of {}.",
+                    path, e
+                );
+            }
+        }
+        fs::remove_file(&taken)?;
+        Ok(owned)
+    }
+}
+
+impl Drop for DocLock {
+    fn drop(&mut self) {
+        match DocLock::take(&self.path, &self.token) {
+            Ok(true) => {}
+            Ok(false) => warn!(
+                "The lock {} was broken and is now held by another writer.",
+                self.path
+            ),
+            Err(e) => warn!("Could not release the lock {} because of {}.", self.path, e),
+        }
+    }
+}
 
 /// A document storage management solution
 pub struct LocalStorage {
     /// The directory path where to storage the DaaS documents (default: "./")
     pub path: String,
+    /// The number of digits the revisions are zero-padded to in the file names, so the files of the revisions list
+    /// in order, (default: 0 for no padding)
+    pub rev_width: usize,
+    /// The disk quotas that the upserts are checked against, (default: no limits)
+    pub quota: StorageQuota,
+    // the disk usage that the quotas are checked against, which is scanned once and then kept up to date by the
+    // writes and removals of this LocalStorage, (see `fsck()` to rescan it)
+    usage: Mutex<Option<UsageReport>>,
+    // the codec of the content of the files of the DaaS documents, (None for plain JSON)
+    codec: Option<Box<dyn DocCodec + Send + Sync>>,
 }
 
 impl Default for LocalStorage {
@@ -16,6 +264,10 @@ impl Default for LocalStorage {
     fn default() -> Self {
         LocalStorage {
             path: ".".to_string(),
+            rev_width: 0,
+            quota: StorageQuota::default(),
+            usage: Mutex::new(None),
+            codec: None,
         }
     }
 }
@@ -59,89 +311,65 @@ impl DaaSDocStorage for LocalStorage {
     ///     assert!(storage.upsert_daas_doc(doc).is_ok());
     /// }
     /// ```
-    fn upsert_daas_doc(&self, mut doc: DaaSDoc) -> Result<DaaSDoc, UpsertError> {
-        // make sure the DaaS document provided is the latest revision
-        let latest_rev = self.latest_rev(doc._id.clone());
-
-        match doc._rev.clone() {
-            Some(r) => {
-                if latest_rev != r {
-                    warn!("The DaaSDoc doesn't have the latest revision!");
-                    return Err(UpsertError);
-                }
-            }
-            None => {}
-        }
-
-        // get the latest revision number and increment it
-        let file_rev = match LocalStorage::next_rev(Some(latest_rev)) {
-            Ok(r) => r,
-            Err(_e) => {
-                warn!("Couldn't get the next revision for the DaaSDoc!");
-                return Err(UpsertError);
-            }
-        };
-
-        // Calculate the file name for the DaaS document
-        let file_uuid = LocalStorage::make_doc_uuid(doc._id.clone(), file_rev.clone());
+    fn upsert_daas_doc(&self, doc: DaaSDoc) -> Result<DaaSDoc, UpsertError> {
+        self.make_room(&doc);
+        // only one writer (thread or process) can allocate the next revision of the DaaS document at a time
+        let _lock = self.lock_doc(&doc._id)?;
+        self.upsert_locked(doc)
+    }
 
-        //create the full directory path if doesn't exists
-        let doc_dir_path = self.get_dir_path(file_uuid.clone());
-        match LocalStorage::ensure_dir_path(doc_dir_path.clone()) {
-            Err(_e) => {
-                error!(
-                    "Could not create dynamic directory path {} to store DaaS document {}",
-                    doc_dir_path.clone(),
-                    file_uuid.clone()
-                );
-                return Err(UpsertError);
-            }
-            Ok(_) => {
-                debug!(
-                    "Created dynamic directory path {} ...",
-                    doc_dir_path.clone()
-                );
+    /// Saves the DaaS documents grouped by their _id, so the lock and the directory of each DaaS document are only
+    /// acquired once for all its revisions in the batch. The results are returned in the order of the DaaS documents.
+    ///
+    /// # Arguments
+    ///
+    /// * docs: Vec<DaaSDoc> - The DaaS documents to save.</br>
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// extern crate daas;
+    ///
+    /// use daas::storage::DaaSDocStorage;
+    /// use daas::storage::local::LocalStorage;
+    /// use daas::testing;
+    ///
+    /// fn main() {
+    ///     let storage = LocalStorage::new("./tmp".to_string());
+    ///     let docs = vec![testing::get_default_daas_doc(), testing::get_default_daas_doc()];
+    ///
+    ///     assert!(storage.upsert_many(docs).iter().all(|r| r.is_ok()));
+    /// }
+    /// ```
+    fn upsert_many(&self, docs: Vec<DaaSDoc>) -> Vec<Result<DaaSDoc, UpsertError>> {
+        let mut results: Vec<Option<Result<DaaSDoc, UpsertError>>> =
+            docs.iter().map(|_d| None).collect();
+        let mut groups: Vec<(String, Vec<(usize, DaaSDoc)>)> = Vec::new();
+
+        for (idx, doc) in docs.into_iter().enumerate() {
+            self.make_room(&doc);
+            match groups.iter_mut().find(|(id, _g)| *id == doc._id) {
+                Some((_id, group)) => group.push((idx, doc)),
+                None => groups.push((doc._id.clone(), vec![(idx, doc)])),
             }
         }
 
-        // update the revision number of the DaaS document
-        doc._rev = Some(file_rev.clone());
-
-        // Try to create the file
-        let json_doc = doc.serialize();
-        let mut file = match File::create(self.get_doc_path(file_uuid.clone())) {
-            Ok(f) => {
-                debug!("Created file {}", self.get_doc_path(file_uuid.clone()));
-                f
-            }
-            Err(e) => {
-                error!(
-                    "Could not create DaaS document file {} because of {}.",
-                    self.get_doc_path(file_uuid.clone()),
-                    e
-                );
-                return Err(UpsertError);
-            }
-        };
-
-        // write the DaaS document content to the file
-        match file.write_all(json_doc.as_bytes()) {
-            Ok(_) => {
-                info!(
-                    "Successfully inserted DaaS document {}",
-                    self.get_doc_path(file_uuid.clone())
-                );
-            }
-            Err(_e) => {
-                error!(
-                    "Could not write content to the Daas document {}",
-                    self.get_doc_path(file_uuid.clone())
-                )
+        for (doc_id, group) in groups {
+            match self.lock_doc(&doc_id) {
+                Ok(_lock) => {
+                    for (idx, doc) in group {
+                        results[idx] = Some(self.upsert_locked(doc));
+                    }
+                }
+                Err(err) => {
+                    for (idx, _doc) in group {
+                        results[idx] = Some(Err(err.clone()));
+                    }
+                }
             }
         }
 
-        // return a Ok Result with the new/updated DaaS document
-        Ok(doc)
+        results.into_iter().map(|r| r.unwrap()).collect()
     }
 
     /// Retrieves a saved Daas document from storage
@@ -173,16 +401,13 @@ impl DaaSDocStorage for LocalStorage {
         doc_rev: Option<String>,
     ) -> Result<DaaSDoc, RetrieveError> {
         let path = match doc_rev {
-            Some(r) => LocalStorage::make_doc_uuid(self.get_doc_path(doc_id), r),
-            None => LocalStorage::make_doc_uuid(
-                self.get_doc_path(doc_id.clone()),
-                self.latest_rev(doc_id),
-            ),
+            Some(r) => self.get_doc_path(self.make_rev_uuid(doc_id, r)),
+            None => self.get_doc_path(self.make_rev_uuid(doc_id.clone(), self.latest_rev(doc_id))),
         };
 
         info!("Retrieving DaaS document {} ...", path.clone());
 
-        let serialized: String = match fs::read_to_string(path.clone()) {
+        let content = match fs::read(path.clone()) {
             Ok(c) => c,
             Err(e) => {
                 error!(
@@ -193,17 +418,233 @@ impl DaaSDocStorage for LocalStorage {
             }
         };
 
-        match DaaSDoc::from_serialized(&serialized.as_bytes()) {
+        match self.decode_doc(&content) {
             Ok(doc) => Ok(doc),
             Err(err) => {
-                error!("{}", err);
+                error!("Could not read the DaaS document {}. {}", path, err);
                 return Err(RetrieveError);
             }
         }
     }
+
+    fn list_doc_ids(&self) -> Result<Vec<String>, RetrieveError> {
+        Ok(self.doc_ids())
+    }
+
+    fn list_doc_ids_by_status(&self, status: DocStatus) -> Result<Vec<String>, RetrieveError> {
+        let mut ids: Vec<String> = self
+            .find_by_status(status)
+            .into_iter()
+            .map(|e| e._id)
+            .collect();
+        ids.sort();
+        Ok(ids)
+    }
+
+    /// Returns a page of the identifiers of the stored DaaS documents, ordered by category, subcategory, source name
+    /// and source uid. Only the directories that come after the cursor are read, and only until the page is full.
+    ///
+    /// # Arguments
+    ///
+    /// * cursor: Option<String> - The cursor of the previous page, or None for the first page.</br>
+    /// * page_size: usize - The maximum number of identifiers of the page.</br>
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// extern crate daas;
+    ///
+    /// use daas::storage::DaaSDocStorage;
+    /// use daas::storage::local::LocalStorage;
+    ///
+    /// fn main() {
+    ///     let storage = LocalStorage::new("./tests".to_string());
+    ///     let mut page = storage.list_doc_ids_page(None, 100).unwrap();
+    ///
+    ///     while let Some(cursor) = page.next_cursor {
+    ///         page = storage.list_doc_ids_page(Some(cursor), 100).unwrap();
+    ///     }
+    /// }
+    /// ```
+    fn list_doc_ids_page(
+        &self,
+        cursor: Option<String>,
+        page_size: usize,
+    ) -> Result<Page<String>, RetrieveError> {
+        let after: Vec<String> = match cursor {
+            Some(c) => decode_cursor(&c)?
+                .split(DELIMITER)
+                .map(|c| c.to_string())
+                .collect(),
+            None => Vec::new(),
+        };
+        let mut ids = Vec::new();
+        self.collect_doc_ids(&mut Vec::new(), &after, page_size.max(1) + 1, &mut ids);
+
+        Ok(make_page(ids, page_size))
+    }
+}
+
+impl ObjectStore for LocalStorage {
+    /// Saves the DaaS document as a file in the `.objects` directory of the storage path,
+    /// (e.g.: so the Genesis processor can provision documents without S3)
+    ///
+    /// # Arguments
+    ///
+    /// * content_key: String - The key of the object, (e.g.: "genesis/order~clothing~iStore~5000.daas").</br>
+    /// * doc: &DaaSDoc - The DaaS document to save.</br>
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// extern crate pbd;
+    /// extern crate daas;
+    ///
+    /// use pbd::dua::DUA;
+    /// use pbd::dtc::Tracker;
+    /// use daas::doc::DaaSDoc;
+    /// use daas::storage::ObjectStore;
+    /// use daas::storage::local::LocalStorage;
+    ///
+    /// fn main() {
+    ///     let src = "iStore".to_string();
+    ///     let uid = 5002;
+    ///     let cat = "order".to_string();
+    ///     let sub = "clothing".to_string();
+    ///     let auth = "istore_app".to_string();
+    ///     let mut dua = Vec::new();
+    ///     dua.push(DUA::new("billing".to_string(),"https://dua.org/agreements/v1/billing.pdf".to_string(),1553988607));
+    ///     let tracker = Tracker::new(DaaSDoc::make_id(cat.clone(), sub.clone(), src.clone(), uid.clone()));
+    ///     let data = String::from(r#"{"status": "new"}"#).as_bytes().to_vec();
+    ///     let doc = DaaSDoc::new(src, uid, cat, sub, auth, dua, tracker, data);
+    ///     let storage = LocalStorage::new("./tmp".to_string());
+    ///
+    ///     assert!(storage.put_daas_doc(format!("genesis/{}.daas", doc._id), &doc).is_ok());
+    /// }
+    /// ```
+    fn put_daas_doc(
+        &self,
+        content_key: String,
+        doc: &DaaSDoc,
+    ) -> Result<i8, daaserror::DaaSStorageError> {
+        self.put_object(content_key, doc.to_bytes())
+    }
+
+    /// Saves the DaaS document as a file in the `.objects` directory of the storage path, unless the file already exists.
+    /// The file is linked into place, so only one of the concurrent creates of the same key succeeds.
+    ///
+    /// # Arguments
+    ///
+    /// * content_key: String - The key of the object, (e.g.: "genesis/order~clothing~iStore~5000~0.daas").</br>
+    /// * doc: &DaaSDoc - The DaaS document to save.</br>
+    fn create_daas_doc(
+        &self,
+        content_key: String,
+        doc: &DaaSDoc,
+    ) -> Result<CreateOutcome, daaserror::DaaSStorageError> {
+        let (path, tmp) = self.write_object_tmp(&content_key, &doc.to_bytes())?;
+        let rslt = fs::hard_link(&tmp, &path);
+        let _ = fs::remove_file(&tmp);
+
+        match rslt {
+            Ok(_) => Ok(CreateOutcome::Created),
+            Err(ref err) if err.kind() == ErrorKind::AlreadyExists => {
+                info!("Object {} already exists.", content_key);
+                Ok(CreateOutcome::AlreadyExists)
+            }
+            Err(err) => {
+                error!("Could not create object {}. Error: {}", content_key, err);
+                Err(daaserror::DaaSStorageError::UpsertError)
+            }
+        }
+    }
+
+    /// Saves the content as a file in the `.objects` directory of the storage path
+    ///
+    /// # Arguments
+    ///
+    /// * content_key: String - The key of the object, (e.g.: "manifests/order/2019-03-30.ndjson").</br>
+    /// * content: Vec<u8> - The content of the object.</br>
+    fn put_object(
+        &self,
+        content_key: String,
+        content: Vec<u8>,
+    ) -> Result<i8, daaserror::DaaSStorageError> {
+        let (path, tmp) = self.write_object_tmp(&content_key, &content)?;
+
+        match fs::rename(&tmp, &path) {
+            Ok(_) => Ok(1),
+            Err(err) => {
+                error!("Could not save object {}. Error: {}", content_key, err);
+                let _ = fs::remove_file(&tmp);
+                Err(daaserror::DaaSStorageError::UpsertError)
+            }
+        }
+    }
+
+    /// Returns the content of the file of the object, or None if the object was never saved
+    ///
+    /// # Arguments
+    ///
+    /// * content_key: String - The key of the object, (e.g.: "manifests/order/2019-03-30.ndjson").</br>
+    fn get_object(
+        &self,
+        content_key: String,
+    ) -> Result<Option<Vec<u8>>, daaserror::DaaSStorageError> {
+        match fs::read(self.get_object_path(content_key.clone())) {
+            Ok(content) => Ok(Some(content)),
+            Err(ref err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => {
+                error!("Could not read object {}. Error: {}", content_key, err);
+                Err(daaserror::DaaSStorageError::RetrieveError)
+            }
+        }
+    }
 }
 
 impl LocalStorage {
+    /// Returns the path of the file for an object saved using `put_daas_doc()`
+    ///
+    /// # Arguments
+    ///
+    /// * content_key: String - The key of the object, (e.g.: "genesis/order~clothing~iStore~5000.daas").</br>
+    pub fn get_object_path(&self, content_key: String) -> String {
+        format!("{}/{}/{}", self.path, OBJECT_DIR, content_key)
+    }
+
+    // Writes the content to a temporary file next to the object and returns the paths of the object and the file
+    fn write_object_tmp(
+        &self,
+        content_key: &str,
+        content: &[u8],
+    ) -> Result<(String, String), daaserror::DaaSStorageError> {
+        // the object must stay inside the storage path
+        if Path::new(content_key)
+            .components()
+            .any(|c| !matches!(c, std::path::Component::Normal(_)))
+        {
+            error!("Invalid object key {}.", content_key);
+            return Err(daaserror::DaaSStorageError::UpsertError);
+        }
+
+        let path = self.get_object_path(content_key.to_string());
+        let tmp = format!("{}.{}.tmp", path, rand::random::<u64>());
+
+        let rslt = Path::new(&path)
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::write(&tmp, content));
+
+        match rslt {
+            Ok(_) => Ok((path, tmp)),
+            Err(err) => {
+                error!("Could not save object {}. Error: {}", content_key, err);
+                let _ = fs::remove_file(&tmp);
+                Err(daaserror::DaaSStorageError::UpsertError)
+            }
+        }
+    }
+
     /// Delimiter used for building the unique identifier value for the DaaS document
     //pub const DELIMITER: &'static str = "~";
 
@@ -234,19 +675,312 @@ impl LocalStorage {
                 warn!("Using default settings ...");
                 LocalStorage::default()
             }
-            _ => LocalStorage { path: dir_path },
+            _ => LocalStorage {
+                path: dir_path,
+                rev_width: 0,
+                quota: StorageQuota::default(),
+                usage: Mutex::new(None),
+                codec: None,
+            },
         }
     }
 
-    // Ensures that the directory path where the DaaS documents exists - if not create the entire path
-    fn ensure_dir_path(dir_path: String) -> std::io::Result<()> {
-        fs::create_dir_all(dir_path)
+    /// Sets the number of digits the revisions are zero-padded to in the file names of the new revisions.
+    /// The revisions that were saved without padding can still be retrieved.
+    ///
+    /// # Arguments
+    ///
+    /// * rev_width: usize - The number of digits, (e.g.: 10 for "order~clothing~iStore~5000~0000000001").</br>
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// extern crate daas;
+    ///
+    /// use daas::storage::local::LocalStorage;
+    ///
+    /// fn main() {
+    ///     let storage = LocalStorage::new("./tmp".to_string()).with_rev_width(10);
+    ///     assert_eq!(storage.rev_width, 10);
+    /// }
+    /// ```
+    pub fn with_rev_width(mut self, rev_width: usize) -> LocalStorage {
+        self.rev_width = rev_width;
+        self
     }
 
-    // Determines if the Daas document file exists
-    #[allow(dead_code)]
-    fn doc_exists(&self, file_uuid: String) -> bool {
-        println!(
+    /// Sets the disk quotas that the upserts are checked against, so a misbehaving source can't fill the disk
+    ///
+    /// # Arguments
+    ///
+    /// * quota: StorageQuota - The disk quotas.</br>
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// extern crate daas;
+    ///
+    /// use daas::storage::local::{LocalStorage, StorageQuota};
+    ///
+    /// fn main() {
+    ///     let quota = StorageQuota::new()
+    ///         .with_total(10 * 1024 * 1024 * 1024)
+    ///         .with_category("order".to_string(), 1024 * 1024 * 1024)
+    ///         .with_purge_when_exceeded(true);
+    ///     let storage = LocalStorage::new("./tmp".to_string()).with_quota(quota);
+    ///
+    ///     assert_eq!(storage.quota.total, Some(10 * 1024 * 1024 * 1024));
+    /// }
+    /// ```
+    pub fn with_quota(mut self, quota: StorageQuota) -> LocalStorage {
+        self.quota = quota;
+        self
+    }
+
+    /// Sets the codec that transforms the serialized DaaS documents before they are written to the files of the
+    /// revisions and the write-ahead log, (e.g.: to encrypt them). The documents that were written with another codec
+    /// can't be read anymore.
+    ///
+    /// # Arguments
+    ///
+    /// * codec: Box<dyn DocCodec + Send + Sync> - The codec.</br>
+    pub fn with_codec(mut self, codec: Box<dyn DocCodec + Send + Sync>) -> LocalStorage {
+        self.codec = Some(codec);
+        self
+    }
+
+    // Serializes the DaaS document into the content of the file of its revision, (encoded by the codec)
+    fn encode_doc(&self, doc: &mut DaaSDoc) -> Result<Vec<u8>, UpsertError> {
+        let serialized = doc.serialize().into_bytes();
+        match &self.codec {
+            Some(codec) => codec.encode(&serialized),
+            None => Ok(serialized),
+        }
+    }
+
+    // Reads the DaaS document from the content of the file of its revision, (decoded by the codec)
+    fn decode_doc(&self, content: &[u8]) -> Result<DaaSDoc, String> {
+        let decoded;
+        let serialized = match &self.codec {
+            Some(codec) => {
+                decoded = codec
+                    .decode(content)
+                    .map_err(|err| format!("Could not decode the content. {}", err))?;
+                &decoded[..]
+            }
+            None => content,
+        };
+        DaaSDoc::from_serialized(serialized).map_err(|err| err.to_string())
+    }
+
+    /// Returns the disk usage of the storage directory and of each category
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// extern crate daas;
+    ///
+    /// use daas::storage::local::LocalStorage;
+    ///
+    /// fn main() {
+    ///     let storage = LocalStorage::new("./tests".to_string());
+    ///     let usage = storage.usage_report();
+    ///
+    ///     assert!(usage.categories["order"] > 0);
+    /// }
+    /// ```
+    pub fn usage_report(&self) -> UsageReport {
+        let mut report = UsageReport::default();
+
+        for entry in fs::read_dir(&self.path)
+            .into_iter()
+            .flatten()
+            .filter_map(|e| e.ok())
+        {
+            let name = entry.file_name().into_string().unwrap_or_default();
+            // the locks are only held while the DaaS documents are written
+            if name == LOCK_DIR {
+                continue;
+            }
+            let size = LocalStorage::disk_usage(&entry.path());
+            if entry.path().is_dir() && !name.starts_with('.') {
+                report.categories.insert(name, size);
+            }
+            report.total += size;
+        }
+
+        report
+    }
+
+    // Calculates the size of the files in the directory tree, (or of the file)
+    fn disk_usage(path: &Path) -> u64 {
+        match fs::symlink_metadata(path) {
+            Ok(m) if m.is_dir() => fs::read_dir(path)
+                .into_iter()
+                .flatten()
+                .filter_map(|e| e.ok())
+                .map(|e| LocalStorage::disk_usage(&e.path()))
+                .sum(),
+            Ok(m) => m.len(),
+            Err(_e) => 0,
+        }
+    }
+
+    // Determines if the quotas would be exceeded by adding the bytes to the category
+    fn exceeds_quota(&self, category: &str, bytes: u64) -> bool {
+        if self.quota.total.is_none() && !self.quota.categories.contains_key(category) {
+            return false;
+        }
+
+        let usage = self.current_usage();
+        let total = self.quota.total.iter().any(|q| usage.total + bytes > *q);
+        let cat = self
+            .quota
+            .categories
+            .get(category)
+            .iter()
+            .any(|q| usage.categories.get(category).copied().unwrap_or(0) + bytes > **q);
+        total || cat
+    }
+
+    // Determines if the quotas allow the bytes to be added to the category, (the caller holds the lock on the
+    // DaaS document, so the deleted and expired DaaS documents aren't purged here, see `make_room()`)
+    fn check_quota(&self, category: &str, bytes: u64) -> Result<(), UpsertError> {
+        if self.exceeds_quota(category, bytes) {
+            warn!(
+                "Rejecting the DaaS document of category {} because the disk quota has been exceeded.",
+                category
+            );
+            return Err(UpsertError);
+        }
+        Ok(())
+    }
+
+    // Purges the deleted and expired DaaS documents when the DaaS document would exceed a quota and the quota allows
+    // it. This is done before the lock on the DaaS document is acquired, since the purge takes the lock of each
+    // DaaS document it removes in turn.
+    fn make_room(&self, doc: &DaaSDoc) {
+        if !self.quota.purge_when_exceeded
+            || (self.quota.total.is_none() && !self.quota.categories.contains_key(&doc.category))
+        {
+            return;
+        }
+
+        let bytes = self
+            .encode_doc(&mut doc.clone())
+            .map(|c| c.len())
+            .unwrap_or(0);
+        if self.exceeds_quota(&doc.category, bytes as u64) {
+            info!("The disk quota has been exceeded, purging the deleted and expired DaaS documents ...");
+            let _ = self.purge_deleted();
+            let _ = self.purge_expired();
+        }
+    }
+
+    // Returns the disk usage that the quotas are checked against, scanning the storage directory the first time
+    fn current_usage(&self) -> UsageReport {
+        self.usage
+            .lock()
+            .unwrap()
+            .get_or_insert_with(|| self.usage_report())
+            .clone()
+    }
+
+    // Adds the change of the size of the files of the DaaS document (identified by its _id) to the disk usage, or
+    // to the total only for the files outside of the categories, (if the disk usage has been scanned)
+    fn track_usage(&self, doc_id: Option<&str>, delta: i64) {
+        if let Some(usage) = self.usage.lock().unwrap().as_mut() {
+            let apply = |size: u64| (size as i64 + delta).max(0) as u64;
+            usage.total = apply(usage.total);
+            if let Some(category) = doc_id.and_then(|id| id.split(DELIMITER).next()) {
+                let size = usage.categories.entry(category.to_string()).or_insert(0);
+                *size = apply(*size);
+            }
+        }
+    }
+
+    // Saves the DaaS document as the next revision, (the caller holds the lock on the DaaS document)
+    fn upsert_locked(&self, mut doc: DaaSDoc) -> Result<DaaSDoc, UpsertError> {
+        // make sure the DaaS document provided is the latest revision
+        let latest_rev = self.latest_rev(doc._id.clone());
+
+        match doc._rev.clone() {
+            Some(r) => {
+                if latest_rev != r {
+                    warn!("The DaaSDoc doesn't have the latest revision!");
+                    return Err(UpsertError);
+                }
+            }
+            None => {}
+        }
+
+        // a deleted DaaS document can only be restored or have its legal hold changed
+        let latest_path =
+            self.get_doc_path(self.make_rev_uuid(doc._id.clone(), latest_rev.clone()));
+        if Path::new(&latest_path).is_file() {
+            if let Ok(latest) = self.get_doc_by_id(doc._id.clone(), Some(latest_rev.clone())) {
+                check_lifecycle(&latest, &doc)?;
+            }
+        }
+
+        // a DaaS document is Stored once it is saved
+        if doc.status == DocStatus::Received {
+            doc.status = DocStatus::Stored;
+        }
+
+        // get the latest revision number and increment it
+        let file_rev = match LocalStorage::next_rev(Some(latest_rev)) {
+            Ok(r) => r,
+            Err(_e) => {
+                warn!("Couldn't get the next revision for the DaaSDoc!");
+                return Err(UpsertError);
+            }
+        };
+
+        // Calculate the file name for the DaaS document
+        let file_uuid = self.make_rev_uuid(doc._id.clone(), file_rev.clone());
+
+        //create the full directory path if doesn't exists
+        let doc_dir_path = self.get_dir_path(file_uuid.clone());
+        match LocalStorage::ensure_dir_path(doc_dir_path.clone()) {
+            Err(_e) => {
+                error!(
+                    "Could not create dynamic directory path {} to store DaaS document {}",
+                    doc_dir_path.clone(),
+                    file_uuid.clone()
+                );
+                return Err(UpsertError);
+            }
+            Ok(_) => {
+                debug!(
+                    "Created dynamic directory path {} ...",
+                    doc_dir_path.clone()
+                );
+            }
+        }
+
+        // update the revision number of the DaaS document
+        doc._rev = Some(file_rev.clone());
+
+        // Try to create the file for the new revision (compare-and-swap on the revision)
+        let content = self.encode_doc(&mut doc)?;
+        self.check_quota(&doc.category, content.len() as u64)?;
+        self.write_new_revision(file_uuid, content)?;
+        self.index_doc(&doc);
+
+        // return a Ok Result with the new/updated DaaS document
+        Ok(doc)
+    }
+
+    // Ensures that the directory path where the DaaS documents exists - if not create the entire path
+    fn ensure_dir_path(dir_path: String) -> std::io::Result<()> {
+        fs::create_dir_all(dir_path)
+    }
+
+    // Determines if the Daas document file exists
+    #[allow(dead_code)]
+    fn doc_exists(&self, file_uuid: String) -> bool {
+        println!(
             "Searching for DaaS document {} ...",
             self.get_doc_path(file_uuid.clone())
         );
@@ -255,6 +989,284 @@ impl LocalStorage {
         doc.is_file()
     }
 
+    // Writes the content of a new revision of a DaaS document.
+    // The content is first written to the write-ahead log and then linked into place, which only
+    // succeeds if the revision doesn't already exist. So when two writers race for the same revision
+    // only the first one succeeds, and a crash never leaves a truncated document file behind.
+    fn write_new_revision(&self, file_uuid: String, content: Vec<u8>) -> Result<(), UpsertError> {
+        let doc_path = self.get_doc_path(file_uuid.clone());
+        let wal_path = self.write_ahead(&content, WAL_NEW)?;
+
+        let rslt = match fs::hard_link(&wal_path, &doc_path) {
+            Ok(_) => {
+                info!("Successfully inserted DaaS document {}", doc_path);
+                self.track_usage(Some(&file_uuid), content.len() as i64);
+                Ok(())
+            }
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                warn!(
+                    "Revision conflict: DaaS document file {} has already been written by another process.",
+                    doc_path
+                );
+                Err(UpsertError)
+            }
+            Err(e) => {
+                error!(
+                    "Could not create DaaS document file {} because of {}.",
+                    doc_path, e
+                );
+                Err(UpsertError)
+            }
+        };
+
+        // the write has either been committed or rejected, so the log entry is no longer needed
+        let _ = fs::remove_file(&wal_path);
+        rslt
+    }
+
+    // Replaces the content of an existing revision of a DaaS document.
+    // The content is first written to the write-ahead log and then atomically renamed over the document file.
+    fn write_existing_revision(
+        &self,
+        file_uuid: String,
+        content: Vec<u8>,
+    ) -> Result<(), UpsertError> {
+        let doc_path = self.get_doc_path(file_uuid.clone());
+        let replaced = fs::metadata(&doc_path).map(|m| m.len()).unwrap_or(0);
+        let wal_path = self.write_ahead(&content, WAL_UPDATE)?;
+
+        match fs::rename(&wal_path, &doc_path) {
+            Ok(_) => {
+                info!("Successfully updated DaaS document {}", doc_path);
+                self.track_usage(Some(&file_uuid), content.len() as i64 - replaced as i64);
+                Ok(())
+            }
+            Err(e) => {
+                error!(
+                    "Could not update DaaS document file {} because of {}.",
+                    doc_path, e
+                );
+                let _ = fs::remove_file(&wal_path);
+                Err(UpsertError)
+            }
+        }
+    }
+
+    // Writes the content to a new entry in the write-ahead log and flushes it to disk
+    fn write_ahead(&self, content: &[u8], mode: &str) -> Result<String, UpsertError> {
+        let wal_dir = self.get_wal_path();
+        if let Err(e) = LocalStorage::ensure_dir_path(wal_dir.clone()) {
+            error!(
+                "Could not create the write-ahead log {} because of {}.",
+                wal_dir, e
+            );
+            return Err(UpsertError);
+        }
+
+        let wal_path = format!(
+            "{}/{}-{}.{}",
+            wal_dir,
+            get_unix_now!(),
+            rand::random::<u64>(),
+            mode
+        );
+        let rslt = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&wal_path)
+            .and_then(|mut f| {
+                f.write_all(content)?;
+                f.sync_all()
+            });
+
+        match rslt {
+            Ok(_) => {
+                debug!("Created write-ahead log entry {}", wal_path);
+                Ok(wal_path)
+            }
+            Err(e) => {
+                error!(
+                    "Could not write the write-ahead log entry {} because of {}.",
+                    wal_path, e
+                );
+                let _ = fs::remove_file(&wal_path);
+                Err(UpsertError)
+            }
+        }
+    }
+
+    // Acquires the lock of the DaaS document by creating its lock file, which only succeeds if the file doesn't
+    // already exist, and writing the owner token into it. Waits for the lock to be released by the other writer, and
+    // breaks the abandoned locks, (only if they still hold the owner token that was found to be abandoned).
+    fn lock_doc(&self, doc_id: &str) -> Result<DocLock, UpsertError> {
+        let lock_dir = self.get_lock_path();
+        if let Err(e) = LocalStorage::ensure_dir_path(lock_dir.clone()) {
+            error!(
+                "Could not create the lock directory {} because of {}.",
+                lock_dir, e
+            );
+            return Err(UpsertError);
+        }
+
+        let lock_path = format!("{}/{}.lock", lock_dir, doc_id);
+        let token = format!("{}:{}", std::process::id(), rand::random::<u64>());
+        let started = Instant::now();
+
+        loop {
+            match OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&lock_path)
+            {
+                Ok(mut f) => {
+                    let lock = DocLock {
+                        path: lock_path.clone(),
+                        token: token.clone(),
+                    };
+                    if let Err(e) = f.write_all(token.as_bytes()).and_then(|_| f.sync_all()) {
+                        error!("Could not write the lock {} because of {}.", lock_path, e);
+                        return Err(UpsertError);
+                    }
+                    return Ok(lock);
+                }
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                    let stale = fs::metadata(&lock_path)
+                        .and_then(|m| m.modified())
+                        .ok()
+                        .and_then(|m| m.elapsed().ok())
+                        .iter()
+                        .any(|age| *age > LOCK_STALE);
+                    if stale {
+                        if let Ok(abandoned) = fs::read_to_string(&lock_path) {
+                            if let Ok(true) = DocLock::take(&lock_path, &abandoned) {
+                                warn!("Broke the abandoned lock {} of {}.", lock_path, abandoned);
+                            }
+                        }
+                        continue;
+                    }
+                    if started.elapsed() > LOCK_TIMEOUT {
+                        warn!("Timed out waiting for the lock {}.", lock_path);
+                        return Err(UpsertError);
+                    }
+                    thread::sleep(LOCK_RETRY);
+                }
+                Err(e) => {
+                    error!("Could not create the lock {} because of {}.", lock_path, e);
+                    return Err(UpsertError);
+                }
+            }
+        }
+    }
+
+    // Calculates the directory path of the locks
+    fn get_lock_path(&self) -> String {
+        format!("{}/{}", &self.path, LOCK_DIR)
+    }
+
+    // Calculates the directory path of the write-ahead log
+    fn get_wal_path(&self) -> String {
+        format!("{}/{}", &self.path, WAL_DIR)
+    }
+
+    /// Replays or discards the incomplete writes found in the write-ahead log, (e.g.: after a crash or power failure).
+    /// This should be called once when the service starts and before any documents are upserted.
+    ///
+    /// + Complete log entries whose document file is missing or corrupt are replayed
+    /// + Complete log entries whose document file was already written are discarded
+    /// + Incomplete (unreadable) log entries are discarded
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// extern crate daas;
+    ///
+    /// use daas::storage::local::LocalStorage;
+    ///
+    /// fn main() {
+    ///     let storage = LocalStorage::new("./tmp".to_string());
+    ///     let report = storage.recover().unwrap();
+    ///
+    ///     println!("Recovered DaaS documents: {:?}", report.recovered);
+    /// }
+    /// ```
+    pub fn recover(&self) -> Result<RecoveryReport, RetrieveError> {
+        let mut report = RecoveryReport {
+            recovered: Vec::new(),
+            discarded: Vec::new(),
+        };
+        let wal_dir = self.get_wal_path();
+
+        if !Path::new(&wal_dir).is_dir() {
+            return Ok(report);
+        }
+
+        let entries = match fs::read_dir(&wal_dir) {
+            Ok(e) => e,
+            Err(e) => {
+                error!(
+                    "Could not read the write-ahead log {} because of {}.",
+                    wal_dir, e
+                );
+                return Err(RetrieveError);
+            }
+        };
+
+        for entry in entries.filter_map(|e| e.ok()) {
+            let wal_path = entry.path();
+            let wal_name = entry.file_name().into_string().unwrap_or_default();
+            let mode = wal_path
+                .extension()
+                .and_then(|x| x.to_str())
+                .unwrap_or_default()
+                .to_string();
+
+            let doc = match fs::read(&wal_path)
+                .ok()
+                .and_then(|c| self.decode_doc(&c).ok())
+            {
+                Some(d) if d._rev.is_some() => d,
+                _ => {
+                    warn!("Discarding incomplete write-ahead log entry {}", wal_name);
+                    let _ = fs::remove_file(&wal_path);
+                    report.discarded.push(wal_name);
+                    continue;
+                }
+            };
+
+            let file_uuid = self.make_rev_uuid(doc._id.clone(), doc._rev.clone().unwrap());
+            let doc_path = self.get_doc_path(file_uuid.clone());
+            let committed = fs::read(&doc_path)
+                .ok()
+                .and_then(|c| self.decode_doc(&c).ok())
+                .is_some();
+
+            if mode == WAL_NEW && committed {
+                debug!("Write-ahead log entry {} was already committed.", wal_name);
+                let _ = fs::remove_file(&wal_path);
+                report.discarded.push(wal_name);
+                continue;
+            }
+
+            let _ = LocalStorage::ensure_dir_path(self.get_dir_path(file_uuid.clone()));
+            match fs::rename(&wal_path, &doc_path) {
+                Ok(_) => {
+                    info!("Recovered DaaS document {}", doc_path);
+                    self.index_doc(&doc);
+                    report.recovered.push(file_uuid);
+                }
+                Err(e) => {
+                    error!(
+                        "Could not recover DaaS document {} because of {}.",
+                        doc_path, e
+                    );
+                    return Err(RetrieveError);
+                }
+            }
+        }
+
+        Ok(report)
+    }
+
     // Calculates the full path where the DaaS document will be located
     fn get_doc_path(&self, doc_uuid: String) -> String {
         let dir: Vec<&str> = doc_uuid.split(DELIMITER).collect();
@@ -298,6 +1310,26 @@ impl LocalStorage {
         format!("{}{}{}", doc_id, DELIMITER, rev)
     }
 
+    // Calculates the file name of the revision of the DaaS document, which is zero-padded based upon the rev_width
+    // unless the revision was already saved without the padding
+    fn make_rev_uuid(&self, doc_id: String, rev: String) -> String {
+        let plain = LocalStorage::make_doc_uuid(doc_id.clone(), rev.clone());
+        let padded = match rev.parse::<usize>() {
+            Ok(r) if self.rev_width > 0 => LocalStorage::make_doc_uuid(
+                doc_id,
+                format!("{:0width$}", r, width = self.rev_width),
+            ),
+            _ => return plain,
+        };
+
+        match !Path::new(&self.get_doc_path(padded.clone())).exists()
+            && Path::new(&self.get_doc_path(plain.clone())).exists()
+        {
+            true => plain,
+            false => padded,
+        }
+    }
+
     pub fn mark_doc_as_processed(&self, doc: DaaSDoc) -> Result<DaaSDoc, UpsertError> {
         let mut doc = match self.get_doc_by_id(doc._id, doc._rev) {
             Ok(d) => d,
@@ -308,106 +1340,744 @@ impl LocalStorage {
         };
 
         doc.process_ind = true;
+        if doc.set_status(DocStatus::Brokered).is_err() {
+            return Err(UpsertError);
+        }
 
         // overwrite the file
 
         // Calculate the file name for the DaaS document
-        let file_uuid = LocalStorage::make_doc_uuid(doc._id.clone(), doc._rev.clone().unwrap());
+        let file_uuid = self.make_rev_uuid(doc._id.clone(), doc._rev.clone().unwrap());
+        let content = self.encode_doc(&mut doc)?;
+        self.write_existing_revision(file_uuid, content)?;
+        self.index_doc(&doc);
+
+        Ok(doc)
+    }
 
-        // Try to create the file
-        let json_doc = doc.serialize();
-        let mut file = match File::create(self.get_doc_path(file_uuid.clone())) {
-            Ok(f) => {
-                debug!("Created file {}", self.get_doc_path(file_uuid.clone()));
-                f
+    // Calculates the next version of the DaaS document
+    fn next_rev(revision: Option<String>) -> Result<String, DaaSDocError> {
+        match revision {
+            None => Ok("0".to_string()),
+            Some(rev) => match rev.parse::<usize>() {
+                Ok(r) => {
+                    let new_rev = r + 1;
+                    Ok(new_rev.to_string())
+                }
+                Err(_e) => {
+                    error!("Could not increment the revision of the DaaS document!");
+                    Err(DaaSDocError)
+                }
+            },
+        }
+    }
+
+    // find the latest revision for the DaaS document based on the doc._id
+    fn latest_rev(&self, doc_id: String) -> String {
+        // use the index if it is up to date (i.e.: there isn't a newer revision than the indexed one)
+        if let Some(entry) = self.get_index_entry(doc_id.clone()) {
+            if let Ok(next) = LocalStorage::next_rev(Some(entry.latest_rev.clone())) {
+                let current =
+                    self.get_doc_path(self.make_rev_uuid(doc_id.clone(), entry.latest_rev.clone()));
+                let newer = self.get_doc_path(self.make_rev_uuid(doc_id.clone(), next));
+
+                if Path::new(&current).is_file() && !Path::new(&newer).exists() {
+                    return entry.latest_rev;
+                }
+            }
+            debug!("The index entry for {} is out of date.", doc_id);
+        }
+
+        self.scan_latest_rev(doc_id)
+    }
+
+    // find the latest revision for the DaaS document by searching the document's directory
+    fn scan_latest_rev(&self, doc_id: String) -> String {
+        debug!("Searching for the latest revision of {} ...", doc_id);
+
+        match self.list_revisions(doc_id).pop() {
+            Some(rev) => rev,
+            // set to zero for not existing document
+            None => "0".to_string(),
+        }
+    }
+
+    /// Returns the path of the file of the revision of the DaaS document
+    ///
+    /// # Arguments
+    ///
+    /// * doc_id: String - The _id of the DaaS document.</br>
+    /// * rev: String - The revision of the DaaS document.</br>
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// extern crate daas;
+    ///
+    /// use daas::storage::local::LocalStorage;
+    ///
+    /// fn main() {
+    ///     let storage = LocalStorage::new("./tests".to_string());
+    ///     let path = storage.get_doc_location("order~clothing~iStore~5000".to_string(), "3".to_string());
+    ///
+    ///     assert_eq!(path, "./tests/order/clothing/iStore/5000/order~clothing~iStore~5000~3".to_string());
+    /// }
+    /// ```
+    pub fn get_doc_location(&self, doc_id: String, rev: String) -> String {
+        self.get_doc_path(self.make_rev_uuid(doc_id, rev))
+    }
+
+    /// Returns the revisions of the DaaS document in numerical order, (e.g.: "9" before "10").
+    /// An empty list is returned if the DaaS document doesn't exist.
+    ///
+    /// # Arguments
+    ///
+    /// * doc_id: String - The _id of the DaaS document.</br>
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// extern crate daas;
+    ///
+    /// use daas::storage::local::LocalStorage;
+    ///
+    /// fn main() {
+    ///     let storage = LocalStorage::new("./tests".to_string());
+    ///     let revs = storage.list_revisions("order~clothing~iStore~5000".to_string());
+    ///
+    ///     assert_eq!(revs.last(), Some(&"3".to_string()));
+    /// }
+    /// ```
+    pub fn list_revisions(&self, doc_id: String) -> Vec<String> {
+        let prefix = format!("{}{}", doc_id, DELIMITER);
+        let mut revs: Vec<usize> = match fs::read_dir(self.get_dir_path(doc_id)) {
+            Ok(entries) => entries
+                .filter_map(|e| e.ok())
+                .filter(|e| e.path().is_file())
+                .filter_map(|e| e.file_name().into_string().ok())
+                .filter_map(|name| {
+                    name.strip_prefix(&prefix)
+                        .and_then(|rev| rev.parse::<usize>().ok())
+                })
+                .collect(),
+            Err(_e) => Vec::new(),
+        };
+
+        revs.sort_unstable();
+        revs.dedup();
+        revs.iter().map(|r| r.to_string()).collect()
+    }
+
+    // Calculates the directory path of the index
+    fn get_index_path(&self) -> String {
+        format!("{}/{}", &self.path, INDEX_DIR)
+    }
+
+    // Calculates the file path of the index entry for the DaaS document
+    fn get_index_entry_path(&self, doc_id: String) -> String {
+        format!("{}/{}.json", self.get_index_path(), doc_id)
+    }
+
+    // Updates the index entry for the DaaS document.
+    // The index is only a lookup accelerator, so failures are logged but don't fail the storage operation.
+    fn index_doc(&self, doc: &DaaSDoc) {
+        let entry = IndexEntry::from_doc(doc);
+
+        // never replace the entry of a newer revision
+        if let Some(existing) = self.get_index_entry(doc._id.clone()) {
+            let existing_rev = existing.latest_rev.parse::<usize>().unwrap_or(0);
+            let rev = entry.latest_rev.parse::<usize>().unwrap_or(0);
+            if existing_rev > rev {
+                return;
             }
+        }
+
+        let index_dir = self.get_index_path();
+        let entry_path = self.get_index_entry_path(doc._id.clone());
+        let tmp_path = format!("{}.{}.tmp", entry_path, rand::random::<u64>());
+        let content = serde_json::to_string(&entry).unwrap();
+        let replaced = fs::metadata(&entry_path).map(|m| m.len()).unwrap_or(0);
+        let rslt = LocalStorage::ensure_dir_path(index_dir)
+            .and_then(|_| fs::write(&tmp_path, &content))
+            .and_then(|_| fs::rename(&tmp_path, &entry_path));
+
+        if rslt.is_ok() {
+            self.track_usage(None, content.len() as i64 - replaced as i64);
+        }
+        if let Err(e) = rslt {
+            warn!(
+                "Could not update the index entry {} because of {}.",
+                entry_path, e
+            );
+            let _ = fs::remove_file(&tmp_path);
+        }
+    }
+
+    /// Returns the index entry for a DaaS document, if the document has been indexed
+    ///
+    /// # Arguments
+    ///
+    /// * doc_id: String - The _id of the DaaS document.</br>
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// extern crate daas;
+    ///
+    /// use daas::storage::local::LocalStorage;
+    ///
+    /// fn main() {
+    ///     let storage = LocalStorage::new("./tmp".to_string());
+    ///
+    ///     assert!(storage.get_index_entry("order~clothing~iStore~notindexed".to_string()).is_none());
+    /// }
+    /// ```
+    pub fn get_index_entry(&self, doc_id: String) -> Option<IndexEntry> {
+        match fs::read(self.get_index_entry_path(doc_id)) {
+            Ok(content) => serde_json::from_slice(&content).ok(),
+            Err(_e) => None,
+        }
+    }
+
+    // Returns all the entries in the index
+    fn index_entries(&self) -> Vec<IndexEntry> {
+        match fs::read_dir(self.get_index_path()) {
+            Ok(entries) => entries
+                .filter_map(|e| e.ok())
+                .filter(|e| e.path().extension().and_then(|x| x.to_str()) == Some("json"))
+                .filter_map(|e| fs::read(e.path()).ok())
+                .filter_map(|c| serde_json::from_slice(&c).ok())
+                .collect(),
+            Err(_e) => Vec::new(),
+        }
+    }
+
+    /// Returns the index entries of the DaaS documents (latest revision) that have the tag
+    ///
+    /// # Arguments
+    ///
+    /// * tag: String - The textual label used as a tag.</br>
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// extern crate daas;
+    ///
+    /// use daas::storage::local::LocalStorage;
+    ///
+    /// fn main() {
+    ///     let storage = LocalStorage::new("./tmp".to_string());
+    ///
+    ///     for entry in storage.find_by_tag("priority".to_string()) {
+    ///         println!("{} has revision {}", entry._id, entry.latest_rev);
+    ///     }
+    /// }
+    /// ```
+    pub fn find_by_tag(&self, tag: String) -> Vec<IndexEntry> {
+        self.index_entries()
+            .into_iter()
+            .filter(|e| e.tags.contains(&tag))
+            .collect()
+    }
+
+    /// Returns the index entries of the DaaS documents (latest revision) that have (or have not) been processed
+    ///
+    /// # Arguments
+    ///
+    /// * process_ind: bool - The indicator that represents if the document has been processed.</br>
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// extern crate daas;
+    ///
+    /// use daas::storage::local::LocalStorage;
+    ///
+    /// fn main() {
+    ///     let storage = LocalStorage::new("./tmp".to_string());
+    ///
+    ///     println!("There are {} documents waiting to be processed", storage.find_by_process_ind(false).len());
+    /// }
+    /// ```
+    pub fn find_by_process_ind(&self, process_ind: bool) -> Vec<IndexEntry> {
+        self.index_entries()
+            .into_iter()
+            .filter(|e| e.process_ind == process_ind)
+            .collect()
+    }
+
+    /// Returns the index entries of the DaaS documents (latest revision) that have the status, (e.g.: the DaaS
+    /// documents that are stuck in Failed)
+    ///
+    /// # Arguments
+    ///
+    /// * status: DocStatus - The status of the DaaS documents.</br>
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// extern crate daas;
+    ///
+    /// use daas::doc::DocStatus;
+    /// use daas::storage::local::LocalStorage;
+    ///
+    /// fn main() {
+    ///     let storage = LocalStorage::new("./tmp".to_string());
+    ///
+    ///     println!("There are {} documents that failed", storage.find_by_status(DocStatus::Failed).len());
+    /// }
+    /// ```
+    pub fn find_by_status(&self, status: DocStatus) -> Vec<IndexEntry> {
+        self.index_entries()
+            .into_iter()
+            .filter(|e| e.status == status)
+            .collect()
+    }
+
+    /// Marks the latest revision of the DaaS document as failed, (e.g.: it couldn't be brokered) by overwriting it
+    ///
+    /// # Arguments
+    ///
+    /// * doc: DaaSDoc - The DaaS document that failed.</br>
+    pub fn mark_doc_as_failed(&self, doc: DaaSDoc) -> Result<DaaSDoc, UpsertError> {
+        let mut doc = match self.get_doc_by_id(doc._id, doc._rev) {
+            Ok(d) => d,
             Err(e) => {
-                error!(
-                    "Could not create DaaS document file {} because of {}.",
-                    self.get_doc_path(file_uuid.clone()),
-                    e
-                );
+                error!("Error: cannot mark DaaS document as failed. {}", e);
                 return Err(UpsertError);
             }
         };
 
-        // write the DaaS document content to the file
-        match file.write_all(json_doc.as_bytes()) {
+        if doc.set_status(DocStatus::Failed).is_err() {
+            return Err(UpsertError);
+        }
+
+        let file_uuid = self.make_rev_uuid(doc._id.clone(), doc._rev.clone().unwrap());
+        let content = self.encode_doc(&mut doc)?;
+        self.write_existing_revision(file_uuid, content)?;
+        self.index_doc(&doc);
+
+        Ok(doc)
+    }
+
+    /// Rebuilds the index by searching the entire storage directory tree, (e.g.: for storage created before the index existed).
+    /// Returns the number of DaaS documents that were indexed.
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// extern crate daas;
+    ///
+    /// use daas::storage::local::LocalStorage;
+    ///
+    /// fn main() {
+    ///     let storage = LocalStorage::new("./tmp".to_string());
+    ///
+    ///     println!("Indexed {} documents", storage.rebuild_index().unwrap());
+    /// }
+    /// ```
+    pub fn rebuild_index(&self) -> Result<usize, RetrieveError> {
+        let mut count = 0;
+
+        for doc_id in self.doc_ids() {
+            let doc_rev = self.scan_latest_rev(doc_id.clone());
+            match self.get_doc_by_id(doc_id.clone(), Some(doc_rev)) {
+                Ok(doc) => {
+                    self.index_doc(&doc);
+                    count += 1;
+                }
+                Err(_e) => {
+                    warn!("Skipping {} while rebuilding the index.", doc_id);
+                }
+            }
+        }
+
+        Ok(count)
+    }
+
+    /// Checks the directory tree for inconsistencies, (e.g.: when recovering from disk issues).
+    /// When repair is true, the orphaned, unserializable and mismatched files are moved to the `.lost+found`
+    /// directory, the orphaned index entries are removed and the index is rebuilt. Revision gaps can't be repaired
+    /// and are only reported.
+    ///
+    /// + Files in the directory tree that aren't revisions of a DaaS document are orphaned
+    /// + Revision files missing between the first and latest revision file of a DaaS document are gaps
+    /// + Revision files that can't be deserialized are unserializable
+    /// + Revision files whose _id or _rev doesn't match their path are mismatched
+    ///
+    /// # Arguments
+    ///
+    /// * repair: bool - Whether to fix the inconsistencies that can be fixed.</br>
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// extern crate daas;
+    ///
+    /// use daas::storage::local::LocalStorage;
+    ///
+    /// fn main() {
+    ///     let storage = LocalStorage::new("./tmp".to_string());
+    ///     let report = storage.fsck(false).unwrap();
+    ///
+    ///     println!("Found {} issues in {} documents", report.issues.len(), report.docs);
+    /// }
+    /// ```
+    pub fn fsck(&self, repair: bool) -> Result<FsckReport, RetrieveError> {
+        let mut report = FsckReport::default();
+        let mut quarantine = Vec::new();
+
+        // the files found above the directories of the DaaS documents
+        let mut dirs = vec![String::new()];
+        for _level in 0..3 {
+            let mut next = Vec::new();
+            for dir in dirs.iter() {
+                let path = format!("{}/{}", self.path, dir);
+                for entry in fs::read_dir(&path)
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|e| e.ok())
+                {
+                    let name = entry.file_name().into_string().unwrap_or_default();
+                    if name.starts_with('.') {
+                        continue;
+                    }
+                    match entry.path().is_dir() {
+                        true => next.push(format!("{}{}/", dir, name)),
+                        false if !dir.is_empty() => {
+                            quarantine.push(FsckIssue::OrphanedFile(format!("{}{}", path, name)))
+                        }
+                        false => {}
+                    }
+                }
+            }
+            dirs = next;
+        }
+
+        for doc_id in self.doc_ids() {
+            let prefix = format!("{}{}", doc_id, DELIMITER);
+            let mut revs = Vec::new();
+
+            for entry in fs::read_dir(self.get_dir_path(doc_id.clone()))
+                .into_iter()
+                .flatten()
+                .filter_map(|e| e.ok())
+            {
+                let path = entry.path().to_string_lossy().to_string();
+                let rev = entry
+                    .file_name()
+                    .into_string()
+                    .ok()
+                    .and_then(|n| n.strip_prefix(&prefix).map(|r| r.to_string()))
+                    .and_then(|r| r.parse::<usize>().ok());
+                let rev = match rev {
+                    Some(r) if entry.path().is_file() => r,
+                    _ => {
+                        quarantine.push(FsckIssue::OrphanedFile(path));
+                        continue;
+                    }
+                };
+
+                report.revisions += 1;
+                revs.push(rev);
+                match fs::read(&path).ok().and_then(|c| self.decode_doc(&c).ok()) {
+                    Some(doc) if doc._id == doc_id && doc._rev == Some(rev.to_string()) => {}
+                    Some(doc) => quarantine.push(FsckIssue::MismatchedId {
+                        path,
+                        found: LocalStorage::make_doc_uuid(doc._id, doc._rev.unwrap_or_default()),
+                    }),
+                    None => quarantine.push(FsckIssue::Unserializable(path)),
+                }
+            }
+
+            revs.sort_unstable();
+            if let (Some(first), Some(last)) = (revs.first(), revs.last()) {
+                let missing: Vec<String> = (*first..*last)
+                    .filter(|r| revs.binary_search(r).is_err())
+                    .map(|r| r.to_string())
+                    .collect();
+                if !missing.is_empty() {
+                    report.issues.push(FsckIssue::RevisionGap {
+                        doc_id: doc_id.clone(),
+                        missing,
+                    });
+                }
+            }
+            report.docs += 1;
+        }
+
+        let orphaned_entries: Vec<FsckIssue> = self
+            .index_entries()
+            .into_iter()
+            .filter(|e| !Path::new(&self.get_dir_path(e._id.clone())).is_dir())
+            .map(|e| FsckIssue::OrphanedIndexEntry(e._id))
+            .collect();
+        quarantine.extend(orphaned_entries);
+
+        if repair {
+            for issue in quarantine.iter() {
+                if let Some(fixed) = self.repair_issue(issue) {
+                    report.repaired.push(fixed);
+                }
+            }
+
+            // the index entries may point to revisions that were moved, so the index is rebuilt from scratch
+            if !report.repaired.is_empty() {
+                let _ = fs::remove_dir_all(self.get_index_path());
+                self.rebuild_index()?;
+            }
+        }
+
+        report.issues.extend(quarantine);
+
+        // the disk usage that the quotas are checked against is rescanned, (e.g.: after other processes wrote to the
+        // storage directory)
+        *self.usage.lock().unwrap() = Some(self.usage_report());
+
+        info!(
+            "Checked {} revisions of {} DaaS documents and found {} issues.",
+            report.revisions,
+            report.docs,
+            report.issues.len()
+        );
+        Ok(report)
+    }
+
+    // Moves the file of the issue to the lost+found directory, or removes the orphaned index entry.
+    // Returns what was repaired.
+    fn repair_issue(&self, issue: &FsckIssue) -> Option<String> {
+        let path = match issue {
+            FsckIssue::OrphanedFile(p) => p,
+            FsckIssue::Unserializable(p) => p,
+            FsckIssue::MismatchedId { path, .. } => path,
+            FsckIssue::OrphanedIndexEntry(doc_id) => {
+                let entry_path = self.get_index_entry_path(doc_id.clone());
+                return match fs::remove_file(&entry_path) {
+                    Ok(_) => Some(entry_path),
+                    Err(e) => {
+                        warn!("Could not remove the index entry {}. {}", entry_path, e);
+                        None
+                    }
+                };
+            }
+            FsckIssue::RevisionGap { .. } => return None,
+        };
+
+        let lost_dir = format!("{}/{}", self.path, LOST_DIR);
+        let name = Path::new(path)
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let lost_path = format!("{}/{}-{}", lost_dir, get_unix_now!(), name);
+
+        match LocalStorage::ensure_dir_path(lost_dir).and_then(|_| fs::rename(path, &lost_path)) {
             Ok(_) => {
-                info!(
-                    "Successfully inserted DaaS document {}",
-                    self.get_doc_path(file_uuid.clone())
-                );
-                Ok(doc)
+                warn!("Moved {} to {}.", path, lost_path);
+                // the directory of a DaaS document without any revisions left is removed
+                if let Some(dir) = Path::new(path).parent() {
+                    let _ = fs::remove_dir(dir);
+                }
+                Some(path.clone())
             }
-            Err(_e) => {
+            Err(e) => {
+                error!("Could not move {} to {}. {}", path, lost_path, e);
+                None
+            }
+        }
+    }
+
+    /// Physically removes an earlier revision of the DaaS document, (e.g.: when the revisions are compacted).
+    /// The latest revision can't be removed.
+    ///
+    /// # Arguments
+    ///
+    /// * doc_id: String - The _id of the DaaS document.</br>
+    /// * rev: String - The revision to remove.</br>
+    pub fn remove_revision(&self, doc_id: String, rev: String) -> Result<(), UpsertError> {
+        let _lock = self.lock_doc(&doc_id)?;
+
+        if self.latest_rev(doc_id.clone()) == rev {
+            warn!(
+                "The latest revision of DaaS document {} can't be removed.",
+                doc_id
+            );
+            return Err(UpsertError);
+        }
+
+        let rev_path = self.get_doc_path(self.make_rev_uuid(doc_id.clone(), rev.clone()));
+        let size = fs::metadata(&rev_path).map(|m| m.len()).unwrap_or(0);
+        match fs::remove_file(&rev_path) {
+            Ok(_) => {
+                debug!("Removed revision {} of DaaS document {}.", rev, doc_id);
+                self.track_usage(Some(&doc_id), -(size as i64));
+                Ok(())
+            }
+            Err(e) => {
                 error!(
-                    "Could not write content to the Daas document {}",
-                    self.get_doc_path(file_uuid.clone())
+                    "Could not remove revision {} of DaaS document {}. {}",
+                    rev, doc_id, e
                 );
-                return Err(UpsertError);
+                Err(UpsertError)
             }
         }
     }
 
-    // Calculates the next version of the DaaS document
-    fn next_rev(revision: Option<String>) -> Result<String, DaaSDocError> {
-        match revision {
-            None => Ok("0".to_string()),
-            Some(rev) => match rev.parse::<usize>() {
-                Ok(r) => {
-                    let new_rev = r + 1;
-                    Ok(new_rev.to_string())
+    /// Physically removes all the revisions of the DaaS documents that have been (soft) deleted, unless they are
+    /// under a legal hold. Returns the _id of the DaaS documents that were purged.
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// extern crate daas;
+    ///
+    /// use daas::storage::local::LocalStorage;
+    ///
+    /// fn main() {
+    ///     let storage = LocalStorage::new("./tmp".to_string());
+    ///
+    ///     println!("Purged {} documents", storage.purge_deleted().unwrap().len());
+    /// }
+    /// ```
+    pub fn purge_deleted(&self) -> Result<Vec<String>, UpsertError> {
+        self.purge_where(is_purgeable)
+    }
+
+    /// Physically removes all the revisions of the DaaS documents whose data has expired, unless they are
+    /// under a legal hold (see `RetentionPolicy`). Returns the _id of the DaaS documents that were purged.
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// extern crate daas;
+    ///
+    /// use daas::storage::local::LocalStorage;
+    ///
+    /// fn main() {
+    ///     let storage = LocalStorage::new("./tmp".to_string());
+    ///
+    ///     println!("Purged {} expired documents", storage.purge_expired().unwrap().len());
+    /// }
+    /// ```
+    pub fn purge_expired(&self) -> Result<Vec<String>, UpsertError> {
+        self.purge_where(is_expired_purgeable)
+    }
+
+    // Removes the DaaS documents whose latest revision matches the predicate
+    fn purge_where(&self, predicate: fn(&DaaSDoc) -> bool) -> Result<Vec<String>, UpsertError> {
+        let mut purged = Vec::new();
+
+        for doc_id in self.doc_ids() {
+            // the lock of each DaaS document is taken in turn, so it isn't purged while a revision is being written
+            let _lock = match self.lock_doc(&doc_id) {
+                Ok(l) => l,
+                Err(_e) => {
+                    warn!("Skipping {} while purging because it is locked.", doc_id);
+                    continue;
+                }
+            };
+            let doc_rev = self.scan_latest_rev(doc_id.clone());
+            match self.get_doc_by_id(doc_id.clone(), Some(doc_rev)) {
+                Ok(doc) if predicate(&doc) => {
+                    self.remove_doc(doc_id.clone())?;
+                    info!("Purged DaaS document {}.", doc_id);
+                    purged.push(doc_id);
                 }
+                Ok(_doc) => {}
                 Err(_e) => {
-                    error!("Could not increment the revision of the DaaS document!");
-                    Err(DaaSDocError)
+                    warn!("Skipping {} while purging.", doc_id);
                 }
-            },
+            }
         }
+
+        Ok(purged)
     }
 
-    // find the latest revision for the DaaS document based on the doc._id
-    fn latest_rev(&self, doc_id: String) -> String {
-        //otherwise find latest revision
+    // Removes all the revisions and the index entry of the DaaS document
+    fn remove_doc(&self, doc_id: String) -> Result<(), UpsertError> {
         let dir_path = self.get_dir_path(doc_id.clone());
-        let base_dir = Path::new(&dir_path);
+        let size = LocalStorage::disk_usage(Path::new(&dir_path));
+        if let Err(e) = fs::remove_dir_all(&dir_path) {
+            error!("Could not remove DaaS document {}. {}", doc_id, e);
+            return Err(UpsertError);
+        }
+        self.track_usage(Some(&doc_id), -(size as i64));
 
-        match base_dir.is_dir() {
-            true => {
-                debug!(
-                    "Searching in {} for latest version for {} ...",
-                    dir_path.clone(),
-                    doc_id
-                );
-                let mut latest_rev = "0".to_string();
-                let mut paths: Vec<_> = fs::read_dir(dir_path)
-                    .unwrap()
-                    .filter_map(|r| r.ok())
-                    .collect();
+        let entry_path = self.get_index_entry_path(doc_id.clone());
+        let entry_size = fs::metadata(&entry_path).map(|m| m.len()).unwrap_or(0);
+        match fs::remove_file(&entry_path) {
+            Err(e) if e.kind() != ErrorKind::NotFound => {
+                warn!("Could not remove the index entry of {}. {}", doc_id, e);
+            }
+            _ => self.track_usage(None, -(entry_size as i64)),
+        }
 
-                paths.sort_by_key(|dir| dir.path());
-                latest_rev = format!(
-                    "{}",
-                    paths
-                        .pop()
-                        .unwrap()
-                        .file_name()
-                        .into_string()
-                        .unwrap()
-                        .split(DELIMITER)
-                        .collect::<Vec<&str>>()
-                        .last()
-                        .unwrap()
-                );
+        Ok(())
+    }
 
-                latest_rev
+    // Collects (up to the limit) the identifiers that come after the components of the cursor, walking the
+    // category, subcategory, source name and source uid directories in order
+    fn collect_doc_ids(
+        &self,
+        prefix: &mut Vec<String>,
+        after: &[String],
+        limit: usize,
+        ids: &mut Vec<String>,
+    ) {
+        let mut names: Vec<String> =
+            match fs::read_dir(format!("{}/{}", self.path, prefix.join("/"))) {
+                Ok(entries) => entries
+                    .filter_map(|e| e.ok())
+                    .filter(|e| e.path().is_dir())
+                    .filter_map(|e| e.file_name().into_string().ok())
+                    .filter(|n| !n.starts_with('.'))
+                    .collect(),
+                Err(_e) => return,
+            };
+        names.sort();
+
+        let bound = &after[..after.len().min(prefix.len() + 1)];
+        for name in names {
+            if ids.len() >= limit {
+                return;
             }
-            false => {
-                // set to zero for not existing document
-                "0".to_string()
+            prefix.push(name);
+            // the directories before the cursor have already been listed
+            if prefix[..] >= *bound {
+                match prefix.len() {
+                    4 if prefix[..] > *after => ids.push(prefix.join(DELIMITER)),
+                    4 => {}
+                    _ => self.collect_doc_ids(prefix, after, limit, ids),
+                }
+            }
+            prefix.pop();
+        }
+    }
+
+    // Returns the _id of all the DaaS documents in the storage directory tree (category/subcategory/source_name/source_uid)
+    fn doc_ids(&self) -> Vec<String> {
+        let mut ids = vec![String::new()];
+
+        for _level in 0..4 {
+            let mut next = Vec::new();
+            for id in ids.iter() {
+                let dir = format!("{}/{}", self.path, id.replace(DELIMITER, "/"));
+                if let Ok(entries) = fs::read_dir(dir) {
+                    for entry in entries.filter_map(|e| e.ok()) {
+                        let name = entry.file_name().into_string().unwrap_or_default();
+                        if entry.path().is_dir() && !name.starts_with('.') {
+                            match id.is_empty() {
+                                true => next.push(name),
+                                false => next.push(format!("{}{}{}", id, DELIMITER, name)),
+                            }
+                        }
+                    }
+                }
             }
+            ids = next;
         }
+
+        ids.sort();
+        ids
     }
 }
 
@@ -416,6 +2086,7 @@ mod tests {
     use super::*;
     use pbd::dtc::Tracker;
     use pbd::dua::DUA;
+    use std::fs::File;
 
     fn get_dua() -> Vec<DUA> {
         let mut v = Vec::new();
@@ -459,6 +2130,84 @@ mod tests {
         doc
     }
 
+    fn get_other_doc(cat: String, uid: usize) -> DaaSDoc {
+        let src = "iStore".to_string();
+        let sub = "clothing".to_string();
+
+        DaaSDoc::new(
+            src.clone(),
+            uid,
+            cat.clone(),
+            sub.clone(),
+            "istore_app".to_string(),
+            get_dua(),
+            get_dtc(src, uid, cat, sub),
+            String::from(r#"{"status": "new"}"#).as_bytes().to_vec(),
+        )
+    }
+
+    #[test]
+    fn test_put_daas_doc() {
+        let loc = LocalStorage::new(format!("./tmp/objects-{}", rand::random::<u32>()));
+        let doc = get_daas_doc();
+
+        assert!(loc
+            .put_daas_doc("genesis/doc.daas".to_string(), &doc)
+            .is_ok());
+        let content = fs::read(loc.get_object_path("genesis/doc.daas".to_string())).unwrap();
+        assert_eq!(DaaSDoc::from_serialized(&content).unwrap()._id, doc._id);
+
+        assert!(loc
+            .put_daas_doc("../escaped.daas".to_string(), &doc)
+            .is_err());
+        assert!(loc.put_daas_doc("/abs.daas".to_string(), &doc).is_err());
+    }
+
+    #[test]
+    fn test_create_daas_doc() {
+        let loc = LocalStorage::new(format!("./tmp/objects-{}", rand::random::<u32>()));
+        let doc = get_daas_doc();
+        let mut other = get_daas_doc();
+        other.data_obj = b"{}".to_vec();
+
+        assert_eq!(
+            loc.create_daas_doc("genesis/doc.daas".to_string(), &doc)
+                .unwrap(),
+            CreateOutcome::Created
+        );
+        assert_eq!(
+            loc.create_daas_doc("genesis/doc.daas".to_string(), &other)
+                .unwrap(),
+            CreateOutcome::AlreadyExists
+        );
+
+        // the existing object is left as it is, and no temporary files are left behind
+        let content = fs::read(loc.get_object_path("genesis/doc.daas".to_string())).unwrap();
+        assert_eq!(
+            DaaSDoc::from_serialized(&content).unwrap().data_obj,
+            doc.data_obj
+        );
+        let dir = Path::new(&loc.get_object_path("genesis".to_string())).to_path_buf();
+        assert_eq!(fs::read_dir(dir).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_get_object() {
+        let loc = LocalStorage::new(format!("./tmp/objects-{}", rand::random::<u32>()));
+
+        assert_eq!(
+            loc.get_object("manifests/a.ndjson".to_string()).unwrap(),
+            None
+        );
+        assert!(loc
+            .put_object("manifests/a.ndjson".to_string(), b"{}\n".to_vec())
+            .is_ok());
+        assert_eq!(
+            loc.get_object("manifests/a.ndjson".to_string()).unwrap(),
+            Some(b"{}\n".to_vec())
+        );
+    }
+
     #[test]
     fn test_get_local_storage_path_env_set() {
         env::set_var("DAAS_LOCAL_STORAGE", "C:\tmp");
@@ -542,6 +2291,36 @@ mod tests {
         assert!(rslt);
     }
 
+    #[test]
+    fn test_diff_revisions() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let loc = LocalStorage::new("./tests".to_string());
+        let diff = loc
+            .diff_revisions(
+                "order~clothing~iStore~5000".to_string(),
+                "0".to_string(),
+                "3".to_string(),
+            )
+            .unwrap();
+
+        assert_eq!(diff.from_rev, Some("0".to_string()));
+        assert_eq!(diff.to_rev, Some("3".to_string()));
+        assert!(!diff.data_changed);
+    }
+
+    #[test]
+    fn test_diff_revisions_not_found() {
+        let loc = LocalStorage::new("./tests".to_string());
+
+        assert!(loc
+            .diff_revisions(
+                "order~clothing~iStore~5000".to_string(),
+                "0".to_string(),
+                "15".to_string(),
+            )
+            .is_err());
+    }
+
     #[test]
     fn test_get_doc_path() {
         let loc = LocalStorage::new("./tmp".to_string());
@@ -582,11 +2361,10 @@ mod tests {
     #[test]
     fn test_upsert_new() {
         let _ = env_logger::builder().is_test(true).try_init();
-        let loc = LocalStorage::new("./tests".to_string());
-        let doc = get_daas_doc();
-        let file_name = LocalStorage::make_doc_uuid(doc._id.clone(), 0.to_string());
+        let loc = LocalStorage::new(format!("./tmp/upsert-{}", rand::random::<u32>()));
+        let doc = loc.upsert_daas_doc(get_daas_doc()).unwrap();
+        let file_name = LocalStorage::make_doc_uuid(doc._id.clone(), doc._rev.unwrap());
 
-        assert!(loc.upsert_daas_doc(doc).is_ok());
         assert!(Path::new(&format!(
             "{}/order/clothing/iStore/6000/{}",
             loc.path, file_name
@@ -594,6 +2372,546 @@ mod tests {
         .is_file());
     }
 
+    #[test]
+    fn test_upsert_many() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let loc = LocalStorage::new(format!("./tmp/batch-{}", rand::random::<u32>()));
+        let mut other = get_daas_doc();
+        other._id = DaaSDoc::make_id(
+            "order".to_string(),
+            "clothing".to_string(),
+            "iStore".to_string(),
+            6001,
+        );
+        let mut stale = get_daas_doc();
+        stale._rev = Some("9".to_string());
+
+        let results = loc.upsert_many(vec![get_daas_doc(), other.clone(), stale, get_daas_doc()]);
+        let revs: Vec<Option<String>> = results
+            .iter()
+            .map(|r| r.as_ref().ok().and_then(|d| d._rev.clone()))
+            .collect();
+        assert_eq!(
+            revs,
+            vec![
+                Some("1".to_string()),
+                Some("1".to_string()),
+                None,
+                Some("2".to_string())
+            ]
+        );
+        assert_eq!(loc.list_revisions(other._id).len(), 1);
+    }
+
+    #[test]
+    fn test_list_doc_ids_page() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let loc = LocalStorage::new(format!("./tmp/paging-{}", rand::random::<u32>()));
+        let docs: Vec<DaaSDoc> = vec![("order", 1), ("order", 22), ("order", 3), ("invoice", 1)]
+            .into_iter()
+            .map(|(cat, uid)| {
+                crate::testing::get_daas_doc(
+                    "iStore".to_string(),
+                    uid,
+                    cat.to_string(),
+                    "clothing".to_string(),
+                )
+            })
+            .collect();
+        assert!(loc.upsert_many(docs).iter().all(|r| r.is_ok()));
+
+        let mut ids = Vec::new();
+        let mut cursor = None;
+        loop {
+            let page = loc.list_doc_ids_page(cursor, 1).unwrap();
+            assert_eq!(page.items.len(), 1);
+            ids.extend(page.items);
+            cursor = page.next_cursor;
+            if cursor.is_none() {
+                break;
+            }
+        }
+        assert_eq!(
+            ids,
+            vec![
+                "invoice~clothing~iStore~1".to_string(),
+                "order~clothing~iStore~1".to_string(),
+                "order~clothing~iStore~22".to_string(),
+                "order~clothing~iStore~3".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_write_new_revision_conflict() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let loc = LocalStorage::new("./tmp".to_string());
+        let file_uuid = format!("order~clothing~iStore~{}~0", get_unix_now!());
+        LocalStorage::ensure_dir_path(loc.get_dir_path(file_uuid.clone())).unwrap();
+
+        assert!(loc
+            .write_new_revision(file_uuid.clone(), b"{}".to_vec())
+            .is_ok());
+        assert!(loc.write_new_revision(file_uuid, b"{}".to_vec()).is_err());
+    }
+
+    #[test]
+    fn test_upsert_concurrent() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let path = format!("./tmp/concurrent-{}", rand::random::<u32>());
+        let doc = get_daas_doc();
+
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let loc = LocalStorage::new(path.clone());
+                let doc = doc.clone();
+                thread::spawn(move || loc.upsert_daas_doc(doc).unwrap()._rev.unwrap())
+            })
+            .collect();
+        let mut revs: Vec<usize> = handles
+            .into_iter()
+            .map(|h| h.join().unwrap().parse().unwrap())
+            .collect();
+        revs.sort();
+
+        assert_eq!(revs, (1..=8).collect::<Vec<usize>>());
+        assert!(Path::new(&format!("{}/{}", path, LOCK_DIR))
+            .read_dir()
+            .unwrap()
+            .next()
+            .is_none());
+    }
+
+    #[test]
+    fn test_lock_doc_waits() {
+        let loc = LocalStorage::new(format!("./tmp/lock-{}", rand::random::<u32>()));
+        let lock = loc.lock_doc("order~clothing~iStore~5000").unwrap();
+        let started = Instant::now();
+
+        let waiter = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            drop(lock);
+        });
+        assert!(loc.lock_doc("order~clothing~iStore~5000").is_ok());
+        assert!(started.elapsed() >= Duration::from_millis(50));
+        waiter.join().unwrap();
+    }
+
+    #[test]
+    fn test_lock_doc_owner_token() {
+        let loc = LocalStorage::new(format!("./tmp/lock-{}", rand::random::<u32>()));
+        let lock_path = format!("{}/order~clothing~iStore~5000.lock", loc.get_lock_path());
+        let lock = loc.lock_doc("order~clothing~iStore~5000").unwrap();
+        assert_eq!(fs::read_to_string(&lock_path).unwrap(), lock.token);
+
+        // the abandoned lock was broken and taken by another writer, whose lock isn't released
+        fs::write(&lock_path, "another:writer").unwrap();
+        drop(lock);
+        assert_eq!(fs::read_to_string(&lock_path).unwrap(), "another:writer");
+
+        // the lock is only broken if it still holds the abandoned owner token
+        assert!(!DocLock::take(&lock_path, "abandoned:writer").unwrap());
+        assert_eq!(fs::read_to_string(&lock_path).unwrap(), "another:writer");
+        assert!(DocLock::take(&lock_path, "another:writer").unwrap());
+        assert!(!Path::new(&lock_path).exists());
+        assert_eq!(fs::read_dir(loc.get_lock_path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_list_revisions_numeric() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let loc = LocalStorage::new(format!("./tmp/revisions-{}", rand::random::<u32>()));
+        let mut doc = get_daas_doc();
+
+        for _i in 0..11 {
+            doc = loc.upsert_daas_doc(doc).unwrap();
+        }
+
+        let revs = loc.list_revisions(doc._id.clone());
+        assert_eq!(revs.len(), 11);
+        assert_eq!(
+            revs[8..],
+            ["9".to_string(), "10".to_string(), "11".to_string()]
+        );
+        assert_eq!(loc.scan_latest_rev(doc._id.clone()), "11".to_string());
+        assert_eq!(
+            loc.get_doc_by_id(doc._id.clone(), None).unwrap()._rev,
+            Some("11".to_string())
+        );
+        assert!(loc
+            .list_revisions("order~clothing~iStore~0".to_string())
+            .is_empty());
+    }
+
+    #[test]
+    fn test_rev_width() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let path = format!("./tmp/rev-width-{}", rand::random::<u32>());
+        let doc = LocalStorage::new(path.clone())
+            .upsert_daas_doc(get_daas_doc())
+            .unwrap();
+        let loc = LocalStorage::new(path).with_rev_width(4);
+        let doc = loc.upsert_daas_doc(doc).unwrap();
+
+        assert_eq!(doc._rev, Some("2".to_string()));
+        assert!(Path::new(&loc.get_doc_path(format!("{}~0002", doc._id))).is_file());
+        assert_eq!(
+            loc.list_revisions(doc._id.clone()),
+            vec!["1".to_string(), "2".to_string()]
+        );
+        assert!(loc
+            .get_doc_by_id(doc._id.clone(), Some("1".to_string()))
+            .is_ok());
+        assert!(
+            loc.mark_doc_as_processed(loc.get_doc_by_id(doc._id.clone(), None).unwrap())
+                .unwrap()
+                .process_ind
+        );
+    }
+
+    #[test]
+    fn test_recover_nothing_to_recover() {
+        let loc = LocalStorage::new(format!("./tmp/recover-{}", rand::random::<u32>()));
+        let report = loc.recover().unwrap();
+
+        assert!(report.recovered.is_empty());
+        assert!(report.discarded.is_empty());
+    }
+
+    #[test]
+    fn test_recover_replays_incomplete_write() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let loc = LocalStorage::new(format!("./tmp/recover-{}", rand::random::<u32>()));
+        let mut doc = get_daas_doc();
+        doc._rev = Some("0".to_string());
+        let file_uuid = LocalStorage::make_doc_uuid(doc._id.clone(), "0".to_string());
+
+        // simulate a crash after the log entry was written but while the document was being written
+        loc.write_ahead(doc.serialize().as_bytes(), WAL_NEW)
+            .unwrap();
+        LocalStorage::ensure_dir_path(loc.get_dir_path(file_uuid.clone())).unwrap();
+        fs::write(loc.get_doc_path(file_uuid.clone()), "{\"_id\":\"ord").unwrap();
+
+        let report = loc.recover().unwrap();
+        assert_eq!(report.recovered, vec![file_uuid]);
+        assert!(loc
+            .get_doc_by_id(doc._id.clone(), Some("0".to_string()))
+            .is_ok());
+        assert!(loc.recover().unwrap().recovered.is_empty());
+    }
+
+    // Reverses the bytes, (a codec for the tests)
+    struct ReverseCodec;
+
+    impl DocCodec for ReverseCodec {
+        fn encode(&self, content: &[u8]) -> Result<Vec<u8>, UpsertError> {
+            Ok(content.iter().rev().cloned().collect())
+        }
+
+        fn decode(&self, content: &[u8]) -> Result<Vec<u8>, RetrieveError> {
+            Ok(content.iter().rev().cloned().collect())
+        }
+    }
+
+    #[test]
+    fn test_codec() {
+        let path = format!("./tmp/codec-{}", rand::random::<u32>());
+        let loc = LocalStorage::new(path.clone()).with_codec(Box::new(ReverseCodec));
+        let doc = loc.upsert_daas_doc(get_daas_doc()).unwrap();
+        let file_uuid = LocalStorage::make_doc_uuid(doc._id.clone(), doc._rev.clone().unwrap());
+
+        // the files of the revisions hold the encoded DaaS documents
+        let content = fs::read(loc.get_doc_path(file_uuid)).unwrap();
+        assert_eq!(
+            content,
+            ReverseCodec
+                .encode(doc.clone().serialize().as_bytes())
+                .unwrap()
+        );
+        assert!(LocalStorage::new(path)
+            .get_doc_by_id(doc._id.clone(), None)
+            .is_err());
+        assert_eq!(
+            loc.get_doc_by_id(doc._id.clone(), None).unwrap().data_obj,
+            doc.data_obj
+        );
+        assert!(loc.fsck(false).unwrap().is_clean());
+
+        // the write-ahead log holds the encoded DaaS documents
+        let mut next = doc.clone();
+        next._rev = Some("9".to_string());
+        let encoded = loc.encode_doc(&mut next).unwrap();
+        loc.write_ahead(&encoded, WAL_NEW).unwrap();
+        let report = loc.recover().unwrap();
+        assert_eq!(report.recovered.len(), 1);
+        assert_eq!(
+            loc.get_doc_by_id(doc._id.clone(), Some("9".to_string()))
+                .unwrap()
+                ._rev,
+            Some("9".to_string())
+        );
+    }
+
+    #[test]
+    fn test_recover_discards_committed_and_incomplete() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let loc = LocalStorage::new(format!("./tmp/recover-{}", rand::random::<u32>()));
+        let mut doc = loc.upsert_daas_doc(get_daas_doc()).unwrap();
+
+        // simulate a crash after the document was written but before the log entry was removed
+        loc.write_ahead(doc.serialize().as_bytes(), WAL_NEW)
+            .unwrap();
+        // simulate a crash while the log entry was being written
+        fs::write(
+            format!("{}/0-0.{}", loc.get_wal_path(), WAL_NEW),
+            "{\"_id\"",
+        )
+        .unwrap();
+
+        let report = loc.recover().unwrap();
+        assert!(report.recovered.is_empty());
+        assert_eq!(report.discarded.len(), 2);
+        assert_eq!(fs::read_dir(loc.get_wal_path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_index_upsert_and_find() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let loc = LocalStorage::new(format!("./tmp/index-{}", rand::random::<u32>()));
+        let mut doc = get_daas_doc();
+        doc.add_tag("priority".to_string());
+        let doc = loc.upsert_daas_doc(doc).unwrap();
+
+        let entry = loc.get_index_entry(doc._id.clone()).unwrap();
+        assert_eq!(entry.latest_rev, doc._rev.clone().unwrap());
+        assert_eq!(loc.find_by_tag("priority".to_string()).len(), 1);
+        assert_eq!(loc.find_by_tag("other".to_string()).len(), 0);
+        assert_eq!(loc.find_by_process_ind(false).len(), 1);
+
+        loc.mark_doc_as_processed(doc).unwrap();
+        assert_eq!(loc.find_by_process_ind(false).len(), 0);
+        assert_eq!(loc.find_by_process_ind(true).len(), 1);
+    }
+
+    #[test]
+    fn test_index_find_by_status() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let loc = LocalStorage::new(format!("./tmp/index-{}", rand::random::<u32>()));
+        let doc = loc.upsert_daas_doc(get_daas_doc()).unwrap();
+        assert_eq!(doc.status, DocStatus::Stored);
+        assert_eq!(loc.find_by_status(DocStatus::Stored).len(), 1);
+
+        let doc = loc.mark_doc_as_failed(doc).unwrap();
+        assert_eq!(doc._rev, Some("1".to_string()));
+        assert_eq!(loc.find_by_status(DocStatus::Stored).len(), 0);
+        assert_eq!(
+            loc.list_doc_ids_by_status(DocStatus::Failed).unwrap(),
+            vec![doc._id.clone()]
+        );
+
+        loc.mark_doc_as_processed(doc).unwrap();
+        assert_eq!(loc.find_by_status(DocStatus::Failed).len(), 0);
+        assert_eq!(loc.find_by_status(DocStatus::Brokered).len(), 1);
+    }
+
+    #[test]
+    fn test_index_out_of_date() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let loc = LocalStorage::new(format!("./tmp/index-{}", rand::random::<u32>()));
+        let doc = loc.upsert_daas_doc(get_daas_doc()).unwrap();
+        let stale = loc.get_index_entry(doc._id.clone()).unwrap();
+        let doc = loc.upsert_daas_doc(doc).unwrap();
+
+        // put back the old index entry to simulate a missed index update
+        fs::write(
+            loc.get_index_entry_path(doc._id.clone()),
+            serde_json::to_string(&stale).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(loc.latest_rev(doc._id.clone()), doc._rev.unwrap());
+    }
+
+    #[test]
+    fn test_rebuild_index() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let loc = LocalStorage::new(format!("./tmp/index-{}", rand::random::<u32>()));
+        let doc = loc.upsert_daas_doc(get_daas_doc()).unwrap();
+        fs::remove_dir_all(loc.get_index_path()).unwrap();
+
+        assert!(loc.get_index_entry(doc._id.clone()).is_none());
+        assert_eq!(loc.rebuild_index().unwrap(), 1);
+        assert_eq!(
+            loc.get_index_entry(doc._id.clone()).unwrap().latest_rev,
+            doc._rev.unwrap()
+        );
+    }
+
+    #[test]
+    fn test_fsck_clean() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let loc = LocalStorage::new(format!("./tmp/fsck-{}", rand::random::<u32>()));
+        let doc = loc.upsert_daas_doc(get_daas_doc()).unwrap();
+        loc.upsert_daas_doc(doc).unwrap();
+
+        let report = loc.fsck(false).unwrap();
+        assert!(report.is_clean());
+        assert_eq!(report.docs, 1);
+        assert_eq!(report.revisions, 2);
+    }
+
+    #[test]
+    fn test_fsck_repair() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let loc = LocalStorage::new(format!("./tmp/fsck-{}", rand::random::<u32>()));
+        let mut doc = get_daas_doc();
+        for _i in 0..3 {
+            doc = loc.upsert_daas_doc(doc).unwrap();
+        }
+        let other = loc
+            .upsert_daas_doc(DaaSDoc::new(
+                "iStore".to_string(),
+                7000,
+                "order".to_string(),
+                "clothing".to_string(),
+                "istore_app".to_string(),
+                get_dua(),
+                get_dtc(
+                    "iStore".to_string(),
+                    7000,
+                    "order".to_string(),
+                    "clothing".to_string(),
+                ),
+                b"{}".to_vec(),
+            ))
+            .unwrap();
+        let rev_path = |id: &str, rev: &str| {
+            loc.get_doc_path(LocalStorage::make_doc_uuid(id.to_string(), rev.to_string()))
+        };
+
+        // a missing revision, a corrupt revision, a copied revision, a stray file and a removed DaaS document
+        fs::remove_file(rev_path(&doc._id, "2")).unwrap();
+        fs::write(rev_path(&doc._id, "3"), "{\"truncated\": ").unwrap();
+        fs::copy(rev_path(&doc._id, "1"), rev_path(&doc._id, "4")).unwrap();
+        fs::write(format!("{}/order/clothing/notes.txt", loc.path), "notes").unwrap();
+        fs::remove_dir_all(loc.get_dir_path(other._id.clone())).unwrap();
+
+        let report = loc.fsck(false).unwrap();
+        assert_eq!(report.issues.len(), 5);
+        assert!(report.issues.contains(&FsckIssue::RevisionGap {
+            doc_id: doc._id.clone(),
+            missing: vec!["2".to_string()]
+        }));
+        assert!(report
+            .issues
+            .contains(&FsckIssue::Unserializable(rev_path(&doc._id, "3"))));
+        assert!(report.issues.contains(&FsckIssue::MismatchedId {
+            path: rev_path(&doc._id, "4"),
+            found: format!("{}~1", doc._id)
+        }));
+        assert!(report
+            .issues
+            .contains(&FsckIssue::OrphanedIndexEntry(other._id.clone())));
+        assert!(report.repaired.is_empty());
+
+        let report = loc.fsck(true).unwrap();
+        assert_eq!(report.repaired.len(), 4);
+        assert_eq!(loc.list_revisions(doc._id.clone()), vec!["1".to_string()]);
+        assert!(loc.get_index_entry(other._id).is_none());
+        assert_eq!(
+            loc.get_index_entry(doc._id).unwrap().latest_rev,
+            "1".to_string()
+        );
+
+        assert!(loc.fsck(false).unwrap().is_clean());
+    }
+
+    #[test]
+    fn test_usage_report() {
+        let loc = LocalStorage::new(format!("./tmp/usage-{}", rand::random::<u32>()));
+        assert_eq!(loc.usage_report(), UsageReport::default());
+
+        let doc = loc.upsert_daas_doc(get_daas_doc()).unwrap();
+        let size = fs::metadata(loc.get_doc_path(format!("{}~1", doc._id)))
+            .unwrap()
+            .len();
+
+        let usage = loc.usage_report();
+        assert_eq!(usage.categories.len(), 1);
+        assert!(usage.categories["order"] >= size);
+        assert!(usage.total > usage.categories["order"]);
+    }
+
+    #[test]
+    fn test_quota_category_exceeded() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let mut doc = get_daas_doc();
+        let size = doc.serialize().len() as u64;
+        let loc = LocalStorage::new(format!("./tmp/quota-{}", rand::random::<u32>())).with_quota(
+            StorageQuota::new().with_category("order".to_string(), size * 2 + size / 2),
+        );
+
+        let doc = loc.upsert_daas_doc(doc).unwrap();
+        let doc = loc.upsert_daas_doc(doc).unwrap();
+        assert!(loc.upsert_daas_doc(doc.clone()).is_err());
+        assert_eq!(
+            loc.list_revisions(doc._id),
+            vec!["1".to_string(), "2".to_string()]
+        );
+
+        // other categories aren't limited
+        assert!(loc
+            .upsert_daas_doc(get_other_doc("return".to_string(), 6000))
+            .is_ok());
+    }
+
+    #[test]
+    fn test_quota_total_purges() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let path = format!("./tmp/quota-{}", rand::random::<u32>());
+        let loc = LocalStorage::new(path.clone());
+        let doc = loc.upsert_daas_doc(get_daas_doc()).unwrap();
+        loc.soft_delete_daas_doc(doc._id.clone()).unwrap();
+        let usage = loc.usage_report().total;
+
+        let other = get_other_doc("order".to_string(), 7000);
+
+        let loc = LocalStorage::new(path.clone()).with_quota(StorageQuota::new().with_total(usage));
+        assert!(loc.upsert_daas_doc(other.clone()).is_err());
+
+        let loc = LocalStorage::new(path).with_quota(
+            StorageQuota::new()
+                .with_total(usage)
+                .with_purge_when_exceeded(true),
+        );
+        assert!(loc.upsert_daas_doc(other).is_ok());
+        assert!(loc.get_doc_by_id(doc._id, None).is_err());
+    }
+
+    #[test]
+    fn test_quota_usage_counters() {
+        let path = format!("./tmp/quota-{}", rand::random::<u32>());
+        let loc =
+            LocalStorage::new(path.clone()).with_quota(StorageQuota::new().with_total(u64::MAX));
+        let first = loc.upsert_daas_doc(get_daas_doc()).unwrap();
+
+        // the counters are kept up to date by the writes and removals without rescanning the directory
+        let doc = loc.upsert_daas_doc(first.clone()).unwrap();
+        loc.mark_doc_as_processed(doc.clone()).unwrap();
+        assert_eq!(loc.current_usage(), loc.usage_report());
+        loc.remove_revision(doc._id.clone(), first._rev.unwrap())
+            .unwrap();
+        assert_eq!(loc.current_usage(), loc.usage_report());
+        loc.remove_doc(doc._id.clone()).unwrap();
+        assert_eq!(loc.current_usage(), loc.usage_report());
+
+        // the files written by other processes are only counted once fsck() rescans the directory
+        let other = LocalStorage::new(path);
+        other.upsert_daas_doc(get_daas_doc()).unwrap();
+        assert!(loc.current_usage().total < loc.usage_report().total);
+        loc.fsck(false).unwrap();
+        assert_eq!(loc.current_usage(), loc.usage_report());
+    }
+
     #[test]
     fn test_upsert_binary_new() {
         // prepare the DaaS data
@@ -617,7 +2935,7 @@ mod tests {
 
         // store the DaaSDoc
         let _ = env_logger::builder().is_test(true).try_init();
-        let loc = LocalStorage::new("./tests".to_string());
+        let loc = LocalStorage::new(format!("./tmp/audio-{}", rand::random::<u32>()));
         let doc = DaaSDoc::new(
             src.clone(),
             uid,
@@ -628,9 +2946,9 @@ mod tests {
             dtc,
             data,
         );
-        let file_name = LocalStorage::make_doc_uuid(doc._id.clone(), 0.to_string());
+        let doc = loc.upsert_daas_doc(doc).unwrap();
+        let file_name = LocalStorage::make_doc_uuid(doc._id.clone(), doc._rev.unwrap());
 
-        assert!(loc.upsert_daas_doc(doc).is_ok());
         assert!(Path::new(&format!(
             "{}/order/music/iStore/16500/{}",
             loc.path, file_name
@@ -686,4 +3004,40 @@ mod tests {
 
         assert!(loc.upsert_daas_doc(doc).is_err());
     }
+
+    #[test]
+    fn test_soft_delete_and_restore() {
+        let loc = LocalStorage::new(format!("./tmp/lifecycle-{}", rand::random::<u32>()));
+        let doc = loc.upsert_daas_doc(get_daas_doc()).unwrap();
+
+        let deleted = loc.soft_delete_daas_doc(doc._id.clone()).unwrap();
+        assert!(deleted.deleted);
+
+        // a deleted DaaS document can't be updated
+        let mut update = deleted.clone();
+        update.add_tag("foo".to_string());
+        assert!(loc.upsert_daas_doc(update).is_err());
+        assert!(loc.upsert_daas_doc(get_daas_doc()).is_err());
+
+        let restored = loc.restore_daas_doc(doc._id.clone()).unwrap();
+        assert!(!restored.deleted);
+        assert!(loc.upsert_daas_doc(restored).is_ok());
+    }
+
+    #[test]
+    fn test_purge_deleted() {
+        let loc = LocalStorage::new(format!("./tmp/lifecycle-{}", rand::random::<u32>()));
+        let doc = loc.upsert_daas_doc(get_daas_doc()).unwrap();
+        loc.soft_delete_daas_doc(doc._id.clone()).unwrap();
+        loc.set_legal_hold(doc._id.clone(), true).unwrap();
+
+        // the legal hold keeps the deleted DaaS document
+        assert!(loc.purge_deleted().unwrap().is_empty());
+        assert!(loc.get_doc_by_id(doc._id.clone(), None).is_ok());
+
+        loc.set_legal_hold(doc._id.clone(), false).unwrap();
+        assert_eq!(loc.purge_deleted().unwrap(), vec![doc._id.clone()]);
+        assert!(loc.get_doc_by_id(doc._id.clone(), None).is_err());
+        assert!(loc.get_index_entry(doc._id).is_none());
+    }
 }
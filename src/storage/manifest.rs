@@ -0,0 +1,290 @@
+//! An object store that keeps a manifest of the provisioned DaaS documents, so that downstream batch jobs can discover
+//! the new data without listing the entire bucket.
+//!
+//! For each DaaS document that is provisioned, the `ManifestedStore` appends an entry (the identifier, revision, key,
+//! SHA-256 checksum, size and time of provisioning) to the manifest of its category for the day, which is an NDJSON
+//! object under `<prefix>/<category>/<YYYY-MM-DD>.ndjson`, (e.g.: "manifests/order/2019-03-30.ndjson").
+//! A DaaS document that is provisioned again (e.g.: when a message is redelivered) is only listed once a day.
+//!
+//! The Genesis processor writes the manifests when it is started with a `ManifestedStore`, (see
+//! `DaaSGenesisProcessorService::run_with_store()`). The manifest is rewritten for every entry, so the Genesis
+//! processors that provision to the same object store concurrently should each use their own prefix.
+//!
+//! # Examples
+//!
+//! ```
+//! extern crate daas;
+//!
+//! use daas::storage::ObjectStore;
+//! use daas::storage::local::LocalStorage;
+//! use daas::storage::manifest::ManifestedStore;
+//! use daas::testing;
+//!
+//! fn main() {
+//!     let store = ManifestedStore::new(LocalStorage::new("./tmp".to_string()));
+//!     let doc = testing::get_default_daas_doc();
+//!
+//!     assert!(store.put_daas_doc(format!("genesis/{}.daas", doc._id), &doc).is_ok());
+//!     assert!(store.manifest_key("order", 1553988607).ends_with("/order/2019-03-30.ndjson"));
+//! }
+//! ```
+
+use super::dedup::content_hash;
+use super::*;
+use crate::errors::daaserror::DaaSStorageError;
+use std::sync::Mutex;
+
+/// The default prefix of the keys of the manifests
+pub const MANIFEST_PREFIX: &str = "manifests";
+
+/// Represents an entry of a manifest, (a line of the NDJSON object)
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ManifestEntry {
+    /// The identifier of the DaaS document
+    pub id: String,
+    /// The revision of the DaaS document
+    pub rev: Option<String>,
+    /// The key of the object of the DaaS document
+    pub key: String,
+    /// The SHA-256 checksum (hex) of the object
+    pub checksum: String,
+    /// The size of the object in bytes
+    pub size: usize,
+    /// The Unix time when the DaaS document was provisioned
+    pub timestamp: u64,
+}
+
+impl ManifestEntry {
+    /// Constructs the ManifestEntry of the object of the DaaS document
+    ///
+    /// # Arguments
+    ///
+    /// * content_key: &str - The key of the object, (e.g.: "genesis/order~clothing~iStore~5000~0.daas").</br>
+    /// * doc: &DaaSDoc - The DaaS document of the object.</br>
+    /// * content: &[u8] - The content of the object.</br>
+    /// * timestamp: u64 - The Unix time when the DaaS document was provisioned.</br>
+    pub fn new(content_key: &str, doc: &DaaSDoc, content: &[u8], timestamp: u64) -> ManifestEntry {
+        ManifestEntry {
+            id: doc._id.clone(),
+            rev: doc._rev.clone(),
+            key: content_key.to_string(),
+            checksum: content_hash(content),
+            size: content.len(),
+            timestamp,
+        }
+    }
+}
+
+/// Represents an object store that appends an entry to the daily manifest of the category of every provisioned DaaS document
+pub struct ManifestedStore<T: ObjectStore> {
+    /// The object store of the DaaS documents and the manifests
+    pub store: T,
+    prefix: String,
+    lock: Mutex<()>,
+}
+
+impl<T: ObjectStore> ManifestedStore<T> {
+    /// Constructs a ManifestedStore that keeps the manifests under the `MANIFEST_PREFIX`
+    ///
+    /// # Arguments
+    ///
+    /// * store: T - The object store of the DaaS documents and the manifests.</br>
+    pub fn new(store: T) -> ManifestedStore<T> {
+        ManifestedStore {
+            store,
+            prefix: MANIFEST_PREFIX.to_string(),
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Keeps the manifests under another prefix, (e.g.: one per Genesis processor)
+    ///
+    /// # Arguments
+    ///
+    /// * prefix: String - The prefix of the keys of the manifests.</br>
+    pub fn with_prefix(mut self, prefix: String) -> ManifestedStore<T> {
+        self.prefix = prefix;
+        self
+    }
+
+    /// Returns the key of the manifest of the category for the day of the Unix time
+    ///
+    /// # Arguments
+    ///
+    /// * category: &str - The category of the DaaS documents.</br>
+    /// * timestamp: u64 - A Unix time of the day.</br>
+    pub fn manifest_key(&self, category: &str, timestamp: u64) -> String {
+        format!(
+            "{}/{}/{}.ndjson",
+            self.prefix,
+            category,
+            format_date(timestamp, '-')
+        )
+    }
+
+    /// Returns the entries of the manifest of the category for the day of the Unix time, (e.g.: for a batch job)
+    ///
+    /// # Arguments
+    ///
+    /// * category: &str - The category of the DaaS documents.</br>
+    /// * timestamp: u64 - A Unix time of the day.</br>
+    pub fn entries(
+        &self,
+        category: &str,
+        timestamp: u64,
+    ) -> Result<Vec<ManifestEntry>, DaaSStorageError> {
+        let content = self
+            .store
+            .get_object(self.manifest_key(category, timestamp))?
+            .unwrap_or_default();
+
+        content
+            .split(|b| *b == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(|line| {
+                serde_json::from_slice(line).map_err(|err| {
+                    error!("Invalid manifest entry. Error: {}", err);
+                    DaaSStorageError::RetrieveError
+                })
+            })
+            .collect()
+    }
+
+    // Appends the entry of the object to today's manifest of its category, unless it is already listed
+    fn record(
+        &self,
+        content_key: &str,
+        doc: &DaaSDoc,
+        content: &[u8],
+    ) -> Result<(), DaaSStorageError> {
+        let entry = ManifestEntry::new(content_key, doc, content, get_unix_now!());
+        let key = self.manifest_key(&doc.category, entry.timestamp);
+        let _guard = self.lock.lock().unwrap();
+
+        let mut content = self.store.get_object(key.clone())?.unwrap_or_default();
+        if content
+            .split(|b| *b == b'\n')
+            .filter_map(|line| serde_json::from_slice::<ManifestEntry>(line).ok())
+            .any(|e| e.key == entry.key && e.checksum == entry.checksum)
+        {
+            return Ok(());
+        }
+
+        content.extend(serde_json::to_vec(&entry).unwrap());
+        content.push(b'\n');
+
+        match self.store.put_object(key.clone(), content) {
+            Ok(_) => Ok(()),
+            Err(err) => {
+                error!(
+                    "Could not add {} to the manifest {}. Error: {:?}",
+                    content_key, key, err
+                );
+                Err(DaaSStorageError::UpsertError)
+            }
+        }
+    }
+}
+
+impl<T: ObjectStore> ObjectStore for ManifestedStore<T> {
+    fn put_daas_doc(&self, content_key: String, doc: &DaaSDoc) -> Result<i8, DaaSStorageError> {
+        let rslt = self.store.put_daas_doc(content_key.clone(), doc)?;
+        self.record(&content_key, doc, &doc.to_bytes())?;
+        Ok(rslt)
+    }
+
+    /// Creates the object of the DaaS document and lists it in the manifest. An object that already exists is listed
+    /// too, (with the checksum of the existing object) in case the previous attempt stopped before the manifest was written.
+    fn create_daas_doc(
+        &self,
+        content_key: String,
+        doc: &DaaSDoc,
+    ) -> Result<CreateOutcome, DaaSStorageError> {
+        let outcome = self.store.create_daas_doc(content_key.clone(), doc)?;
+        let content = match outcome {
+            CreateOutcome::Created => None,
+            CreateOutcome::AlreadyExists => self.store.get_object(content_key.clone())?,
+        }
+        .unwrap_or_else(|| doc.to_bytes());
+
+        self.record(&content_key, doc, &content)?;
+        Ok(outcome)
+    }
+
+    fn put_object(&self, content_key: String, content: Vec<u8>) -> Result<i8, DaaSStorageError> {
+        self.store.put_object(content_key, content)
+    }
+
+    fn get_object(&self, content_key: String) -> Result<Option<Vec<u8>>, DaaSStorageError> {
+        self.store.get_object(content_key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::local::LocalStorage;
+    use crate::testing;
+
+    fn get_store() -> ManifestedStore<LocalStorage> {
+        ManifestedStore::new(LocalStorage::new(format!(
+            "./tmp/manifest-{}",
+            rand::random::<u32>()
+        )))
+    }
+
+    #[test]
+    fn test_manifest_key() {
+        let store = get_store().with_prefix("genesis-manifests".to_string());
+
+        assert_eq!(
+            store.manifest_key("order", 1553988607),
+            "genesis-manifests/order/2019-03-30.ndjson".to_string()
+        );
+    }
+
+    #[test]
+    fn test_create_daas_doc() {
+        let store = get_store();
+        let doc = testing::get_default_daas_doc();
+        let mut other = testing::get_daas_doc(
+            "iStore".to_string(),
+            6000,
+            "order".to_string(),
+            "clothing".to_string(),
+        );
+        other._rev = Some("1".to_string());
+        let mut redelivered = doc.clone();
+        redelivered.last_updated += 1;
+
+        assert!(store
+            .create_daas_doc("genesis/a.daas".to_string(), &doc)
+            .is_ok());
+        // a redelivered DaaS document is listed once, with the checksum of the existing object
+        assert_eq!(
+            store
+                .create_daas_doc("genesis/a.daas".to_string(), &redelivered)
+                .unwrap(),
+            CreateOutcome::AlreadyExists
+        );
+        assert!(store
+            .create_daas_doc("genesis/b.daas".to_string(), &other)
+            .is_ok());
+
+        let entries = store.entries("order", get_unix_now!()).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].id, doc._id);
+        assert_eq!(entries[0].key, "genesis/a.daas".to_string());
+        assert_eq!(entries[0].size, doc.to_bytes().len());
+        assert_eq!(entries[0].checksum, content_hash(&doc.to_bytes()));
+        assert_eq!(entries[1].key, "genesis/b.daas".to_string());
+        assert_eq!(entries[1].rev, Some("1".to_string()));
+    }
+
+    #[test]
+    fn test_entries_missing_manifest() {
+        let store = get_store();
+
+        assert!(store.entries("order", 0).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,401 @@
+//! Storage that keeps the revisions of the DaaS documents in memory, (e.g.: for unit tests and short-lived caching)
+//!
+//! The revisioning follows the `LocalStorage`: a DaaS document that has a revision can only be upserted if it
+//! is the latest revision, and every upsert adds a new revision. Clones of the `InMemoryStorage` share the same
+//! DaaS documents.
+
+use super::*;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, RwLock};
+
+// The revisions (ordered by revision number) of each DaaS document
+type Revisions = Arc<RwLock<HashMap<String, BTreeMap<usize, DaaSDoc>>>>;
+
+/// Represents a storage device that keeps the DaaS documents in memory
+#[derive(Clone, Default)]
+pub struct InMemoryStorage {
+    docs: Revisions,
+}
+
+impl DaaSDocStorage for InMemoryStorage {
+    /// Save a Daas document into memory based upon the revision of the document
+    ///
+    /// # Arguments
+    ///
+    /// * doc: DaaSDoc - The new DaaS document to save.</br>
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// extern crate pbd;
+    /// extern crate daas;
+    ///
+    /// use pbd::dua::DUA;
+    /// use pbd::dtc::Tracker;
+    /// use daas::doc::DaaSDoc;
+    /// use daas::storage::DaaSDocStorage;
+    /// use daas::storage::memory::InMemoryStorage;
+    ///
+    /// fn main() {
+    ///     let src = "iStore".to_string();
+    ///     let uid = 5000;
+    ///     let cat = "order".to_string();
+    ///     let sub = "clothing".to_string();
+    ///     let auth = "istore_app".to_string();
+    ///     let mut dua = Vec::new();
+    ///     dua.push(DUA::new("billing".to_string(),"https://dua.org/agreements/v1/billing.pdf".to_string(),1553988607));
+    ///     let tracker = Tracker::new(DaaSDoc::make_id(cat.clone(), sub.clone(), src.clone(), uid.clone()));
+    ///     let data = String::from(r#"{"status": "new"}"#).as_bytes().to_vec();
+    ///     let doc = DaaSDoc::new(src, uid, cat, sub, auth, dua, tracker, data);
+    ///
+    ///     let storage = InMemoryStorage::new();
+    ///     let doc = storage.upsert_daas_doc(doc).unwrap();
+    ///
+    ///     assert_eq!(doc._rev, Some("1".to_string()));
+    /// }
+    /// ```
+    fn upsert_daas_doc(&self, doc: DaaSDoc) -> Result<DaaSDoc, UpsertError> {
+        let mut docs = self.docs.write().unwrap();
+        InMemoryStorage::upsert_locked(&mut docs, doc)
+    }
+
+    /// Saves the DaaS documents while holding the lock on the DaaS documents only once
+    fn upsert_many(&self, docs: Vec<DaaSDoc>) -> Vec<Result<DaaSDoc, UpsertError>> {
+        let mut stored = self.docs.write().unwrap();
+        docs.into_iter()
+            .map(|doc| InMemoryStorage::upsert_locked(&mut stored, doc))
+            .collect()
+    }
+
+    fn get_doc_by_id(
+        &self,
+        doc_id: String,
+        doc_rev: Option<String>,
+    ) -> Result<DaaSDoc, RetrieveError> {
+        let docs = self.docs.read().unwrap();
+        let revisions = match docs.get(&doc_id) {
+            Some(r) => r,
+            None => {
+                debug!("DaaS document {} was not found.", doc_id);
+                return Err(RetrieveError);
+            }
+        };
+
+        let doc = match doc_rev {
+            None => revisions.values().next_back(),
+            Some(rev) => rev.parse::<usize>().ok().and_then(|r| revisions.get(&r)),
+        };
+
+        match doc {
+            Some(d) => Ok(d.clone()),
+            None => {
+                debug!("Revision of DaaS document {} was not found.", doc_id);
+                Err(RetrieveError)
+            }
+        }
+    }
+
+    fn list_doc_ids(&self) -> Result<Vec<String>, RetrieveError> {
+        let mut ids: Vec<String> = self.docs.read().unwrap().keys().cloned().collect();
+        ids.sort();
+        Ok(ids)
+    }
+}
+
+impl InMemoryStorage {
+    /// Constructs an InMemoryStorage without any DaaS documents
+    pub fn new() -> InMemoryStorage {
+        InMemoryStorage::default()
+    }
+
+    // Saves the DaaS document as the next revision, (the caller holds the lock on the DaaS documents)
+    fn upsert_locked(
+        docs: &mut HashMap<String, BTreeMap<usize, DaaSDoc>>,
+        mut doc: DaaSDoc,
+    ) -> Result<DaaSDoc, UpsertError> {
+        let latest_rev = docs
+            .get(&doc._id)
+            .and_then(|revisions| revisions.keys().next_back().cloned())
+            .unwrap_or(0);
+
+        // make sure the DaaS document provided is the latest revision
+        if let Some(r) = doc._rev.clone() {
+            if r.parse::<usize>().ok() != Some(latest_rev) {
+                warn!("The DaaSDoc doesn't have the latest revision!");
+                return Err(UpsertError);
+            }
+        }
+
+        // a deleted DaaS document can only be restored or have its legal hold changed
+        if let Some(latest) = docs
+            .get(&doc._id)
+            .and_then(|revisions| revisions.values().next_back())
+        {
+            check_lifecycle(latest, &doc)?;
+        }
+
+        // a DaaS document is Stored once it is saved
+        if doc.status == DocStatus::Received {
+            doc.status = DocStatus::Stored;
+        }
+
+        doc._rev = Some((latest_rev + 1).to_string());
+        docs.entry(doc._id.clone())
+            .or_default()
+            .insert(latest_rev + 1, doc.clone());
+
+        Ok(doc)
+    }
+
+    /// Returns the number of DaaS documents (not revisions) that are stored
+    pub fn len(&self) -> usize {
+        self.docs.read().unwrap().len()
+    }
+
+    /// Returns true if no DaaS documents are stored
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Marks the revision of the DaaS document as processed (without creating a new revision)
+    ///
+    /// # Arguments
+    ///
+    /// * doc: DaaSDoc - The DaaS document to mark as processed.</br>
+    pub fn mark_doc_as_processed(&self, doc: DaaSDoc) -> Result<DaaSDoc, UpsertError> {
+        let mut docs = self.docs.write().unwrap();
+        let rev = doc._rev.as_ref().and_then(|r| r.parse::<usize>().ok());
+
+        match (docs.get_mut(&doc._id), rev) {
+            (Some(revisions), Some(r)) => match revisions.get_mut(&r) {
+                Some(d) => {
+                    d.process_ind = true;
+                    Ok(d.clone())
+                }
+                None => Err(UpsertError),
+            },
+            _ => {
+                error!("Error: cannot mark DaaS document {} as processed.", doc._id);
+                Err(UpsertError)
+            }
+        }
+    }
+
+    /// Removes all the revisions of the DaaS documents that have been (soft) deleted, unless they are
+    /// under a legal hold. Returns the _id of the DaaS documents that were purged.
+    pub fn purge_deleted(&self) -> Vec<String> {
+        self.purge_where(is_purgeable)
+    }
+
+    /// Removes all the revisions of the DaaS documents whose data has expired, unless they are
+    /// under a legal hold. Returns the _id of the DaaS documents that were purged.
+    pub fn purge_expired(&self) -> Vec<String> {
+        self.purge_where(is_expired_purgeable)
+    }
+
+    // Removes the DaaS documents whose latest revision matches the predicate
+    fn purge_where(&self, predicate: fn(&DaaSDoc) -> bool) -> Vec<String> {
+        let mut docs = self.docs.write().unwrap();
+        let purged: Vec<String> = docs
+            .iter()
+            .filter(|(_, revisions)| revisions.values().next_back().is_some_and(predicate))
+            .map(|(id, _)| id.clone())
+            .collect();
+
+        for id in purged.iter() {
+            docs.remove(id);
+        }
+
+        purged
+    }
+
+    /// Removes the DaaS document and all its revisions
+    ///
+    /// # Arguments
+    ///
+    /// * doc_id: String - The unique identifier of the DaaS document.</br>
+    pub fn remove(&self, doc_id: String) -> Option<DaaSDoc> {
+        self.docs
+            .write()
+            .unwrap()
+            .remove(&doc_id)
+            .and_then(|mut revisions| revisions.pop_last().map(|(_, d)| d))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    fn get_daas_doc() -> DaaSDoc {
+        get_daas_doc_uid(8000)
+    }
+
+    fn get_daas_doc_uid(uid: usize) -> DaaSDoc {
+        testing::get_daas_doc(
+            "iStore".to_string(),
+            uid,
+            "order".to_string(),
+            "clothing".to_string(),
+        )
+    }
+
+    #[test]
+    fn test_upsert_revisions() {
+        let storage = InMemoryStorage::new();
+        let doc = storage.upsert_daas_doc(get_daas_doc()).unwrap();
+        assert_eq!(doc._rev, Some("1".to_string()));
+
+        let doc = storage.upsert_daas_doc(doc).unwrap();
+        assert_eq!(doc._rev, Some("2".to_string()));
+        assert_eq!(storage.len(), 1);
+
+        // a stale revision is rejected
+        let mut stale = doc.clone();
+        stale._rev = Some("1".to_string());
+        assert!(storage.upsert_daas_doc(stale).is_err());
+
+        // a rejected DaaS document isn't stored
+        let mut unknown = get_daas_doc();
+        unknown._id = "order~clothing~iStore~8001".to_string();
+        unknown._rev = Some("5".to_string());
+        assert!(storage.upsert_daas_doc(unknown).is_err());
+        assert_eq!(storage.len(), 1);
+    }
+
+    #[test]
+    fn test_upsert_many() {
+        let storage = InMemoryStorage::new();
+        let mut stale = get_daas_doc();
+        stale._rev = Some("5".to_string());
+        let results = storage.upsert_many(vec![
+            get_daas_doc(),
+            get_daas_doc_uid(8001),
+            stale,
+            get_daas_doc(),
+        ]);
+
+        assert_eq!(results.len(), 4);
+        assert_eq!(results[0].as_ref().unwrap()._rev, Some("1".to_string()));
+        assert_eq!(results[1].as_ref().unwrap()._rev, Some("1".to_string()));
+        assert!(results[2].is_err());
+        assert_eq!(results[3].as_ref().unwrap()._rev, Some("2".to_string()));
+        assert_eq!(storage.len(), 2);
+    }
+
+    #[test]
+    fn test_list_doc_ids_page() {
+        let storage = InMemoryStorage::new();
+        for uid in 8000..8005 {
+            storage.upsert_daas_doc(get_daas_doc_uid(uid)).unwrap();
+        }
+
+        let first = storage.list_doc_ids_page(None, 3).unwrap();
+        assert_eq!(first.items.len(), 3);
+        let second = storage
+            .list_doc_ids_page(first.next_cursor.clone(), 3)
+            .unwrap();
+        assert_eq!(second.items.len(), 2);
+        assert!(second.next_cursor.is_none());
+        assert_eq!(
+            [first.items, second.items].concat(),
+            storage.list_doc_ids().unwrap()
+        );
+        assert!(storage
+            .list_doc_ids_page(Some("%%%".to_string()), 3)
+            .is_err());
+    }
+
+    #[test]
+    fn test_get_doc_by_id() {
+        let storage = InMemoryStorage::new();
+        let doc = storage.upsert_daas_doc(get_daas_doc()).unwrap();
+        storage.upsert_daas_doc(doc.clone()).unwrap();
+
+        let latest = storage.get_doc_by_id(doc._id.clone(), None).unwrap();
+        assert_eq!(latest._rev, Some("2".to_string()));
+        let first = storage
+            .get_doc_by_id(doc._id.clone(), Some("1".to_string()))
+            .unwrap();
+        assert_eq!(first._rev, Some("1".to_string()));
+
+        assert!(storage
+            .get_doc_by_id(doc._id.clone(), Some("9".to_string()))
+            .is_err());
+        assert!(storage.get_doc_by_id("missing".to_string(), None).is_err());
+    }
+
+    #[test]
+    fn test_shared_and_processed() {
+        let storage = InMemoryStorage::new();
+        let shared = storage.clone();
+        let doc = storage.upsert_daas_doc(get_daas_doc()).unwrap();
+
+        assert!(
+            shared
+                .mark_doc_as_processed(doc.clone())
+                .unwrap()
+                .process_ind
+        );
+        assert!(
+            storage
+                .get_doc_by_id(doc._id.clone(), None)
+                .unwrap()
+                .process_ind
+        );
+
+        assert!(shared.remove(doc._id.clone()).is_some());
+        assert!(storage.is_empty());
+    }
+
+    #[test]
+    fn test_lifecycle() {
+        let storage = InMemoryStorage::new();
+        let doc = storage.upsert_daas_doc(get_daas_doc()).unwrap();
+        let deleted = storage.soft_delete_daas_doc(doc._id.clone()).unwrap();
+
+        assert!(storage.upsert_daas_doc(deleted).is_err());
+        storage.set_legal_hold(doc._id.clone(), true).unwrap();
+        assert!(storage.purge_deleted().is_empty());
+
+        storage.set_legal_hold(doc._id.clone(), false).unwrap();
+        assert_eq!(storage.purge_deleted(), vec![doc._id]);
+        assert!(storage.is_empty());
+    }
+
+    #[test]
+    fn test_purge_expired() {
+        let storage = InMemoryStorage::new();
+        let mut doc = get_daas_doc();
+        doc.expires_at = Some(1553988667);
+        let doc = storage.upsert_daas_doc(doc).unwrap();
+        storage.upsert_daas_doc(get_daas_doc_uid(8001)).unwrap();
+
+        assert_eq!(storage.purge_expired(), vec![doc._id]);
+        assert_eq!(storage.len(), 1);
+    }
+
+    #[test]
+    fn test_doc_status() {
+        let storage = InMemoryStorage::new();
+        let doc = storage.upsert_daas_doc(get_daas_doc()).unwrap();
+        assert_eq!(doc.status, DocStatus::Stored);
+
+        let doc = storage
+            .set_doc_status(doc._id.clone(), DocStatus::Brokered)
+            .unwrap();
+        assert_eq!(doc._rev, Some("2".to_string()));
+        assert!(storage
+            .set_doc_status(doc._id.clone(), DocStatus::Received)
+            .is_err());
+        assert_eq!(
+            storage.list_doc_ids_by_status(DocStatus::Brokered).unwrap(),
+            vec![doc._id]
+        );
+        assert!(storage
+            .list_doc_ids_by_status(DocStatus::Failed)
+            .unwrap()
+            .is_empty());
+    }
+}
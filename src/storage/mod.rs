@@ -6,15 +6,274 @@ use super::*;
 use crate::doc::*;
 use crate::errors::*;
 
+/// Represents a page of a listing, (e.g.: of the identifiers of the stored DaaS documents)
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Page<T> {
+    /// The items of the page
+    pub items: Vec<T>,
+    /// The opaque cursor of the next page, or None if this is the last page
+    pub next_cursor: Option<String>,
+}
+
 /// Trait for storage devices that manage DaaS documents
 pub trait DaaSDocStorage {
     fn upsert_daas_doc(&self, daas_doc: DaaSDoc) -> Result<DaaSDoc, UpsertError>;
+    /// Saves the DaaS documents, returning the result of each upsert in the order of the DaaS documents,
+    /// (storage devices can override it to optimize the writes of a batch)
+    fn upsert_many(&self, docs: Vec<DaaSDoc>) -> Vec<Result<DaaSDoc, UpsertError>> {
+        docs.into_iter().map(|d| self.upsert_daas_doc(d)).collect()
+    }
     fn get_doc_by_id(
         &self,
         doc_id: String,
         doc_rev: Option<String>,
     ) -> Result<DaaSDoc, RetrieveError>;
+    /// Returns the identifiers of all the stored DaaS documents, (not supported by every storage device)
+    fn list_doc_ids(&self) -> Result<Vec<String>, RetrieveError> {
+        warn!("The storage device doesn't support listing the DaaS documents.");
+        Err(RetrieveError)
+    }
+    /// Returns a page of the identifiers of the stored DaaS documents, continuing after the cursor of the previous
+    /// page, (storage devices can override it to avoid listing all the DaaS documents for every page)
+    fn list_doc_ids_page(
+        &self,
+        cursor: Option<String>,
+        page_size: usize,
+    ) -> Result<Page<String>, RetrieveError> {
+        let after = match cursor {
+            Some(c) => Some(decode_cursor(&c)?),
+            None => None,
+        };
+        let ids = self
+            .list_doc_ids()?
+            .into_iter()
+            .filter(|id| after.iter().all(|a| id > a))
+            .take(page_size.max(1) + 1)
+            .collect();
+
+        Ok(make_page(ids, page_size))
+    }
+    /// Returns the identifiers of the DaaS documents whose latest revision has the status, (storage devices can
+    /// override it to avoid retrieving all the DaaS documents)
+    fn list_doc_ids_by_status(&self, status: DocStatus) -> Result<Vec<String>, RetrieveError> {
+        Ok(self
+            .list_doc_ids()?
+            .into_iter()
+            .filter(|id| {
+                self.get_doc_by_id(id.clone(), None)
+                    .map(|d| d.status == status)
+                    .unwrap_or(false)
+            })
+            .collect())
+    }
+    /// Changes the status of the DaaS document by upserting a new revision, if the transition is allowed
+    fn set_doc_status(&self, doc_id: String, status: DocStatus) -> Result<DaaSDoc, UpsertError> {
+        let mut doc = get_latest_for_update(self, doc_id)?;
+        doc.set_status(status).map_err(|_e| UpsertError)?;
+        self.upsert_daas_doc(doc)
+    }
+    /// Returns the differences between two revisions of the same DaaS document
+    fn diff_revisions(
+        &self,
+        doc_id: String,
+        rev_a: String,
+        rev_b: String,
+    ) -> Result<DocDiff, RetrieveError> {
+        let doc_a = self.get_doc_by_id(doc_id.clone(), Some(rev_a))?;
+        let doc_b = self.get_doc_by_id(doc_id, Some(rev_b))?;
+
+        Ok(doc_a.diff(&doc_b))
+    }
+    /// Marks the latest revision of the DaaS document as (soft) deleted by upserting a new revision
+    fn soft_delete_daas_doc(&self, doc_id: String) -> Result<DaaSDoc, UpsertError> {
+        let mut doc = get_latest_for_update(self, doc_id)?;
+        doc.mark_deleted();
+        self.upsert_daas_doc(doc)
+    }
+    /// Restores a (soft) deleted DaaS document by upserting a new revision
+    fn restore_daas_doc(&self, doc_id: String) -> Result<DaaSDoc, UpsertError> {
+        let mut doc = get_latest_for_update(self, doc_id)?;
+        doc.restore();
+        self.upsert_daas_doc(doc)
+    }
+    /// Places or releases the legal hold on the DaaS document by upserting a new revision
+    fn set_legal_hold(&self, doc_id: String, hold: bool) -> Result<DaaSDoc, UpsertError> {
+        let mut doc = get_latest_for_update(self, doc_id)?;
+        doc.set_legal_hold(hold);
+        self.upsert_daas_doc(doc)
+    }
+}
+
+// Encodes the identifier of the last item of a page as the opaque cursor of the next page
+pub(crate) fn encode_cursor(last: &str) -> String {
+    base64::encode_config(last, base64::URL_SAFE_NO_PAD)
+}
+
+// Decodes the cursor of the next page into the identifier of the last item of the previous page
+pub(crate) fn decode_cursor(cursor: &str) -> Result<String, RetrieveError> {
+    match base64::decode_config(cursor, base64::URL_SAFE_NO_PAD)
+        .ok()
+        .and_then(|c| String::from_utf8(c).ok())
+    {
+        Some(last) => Ok(last),
+        None => {
+            warn!("Invalid cursor {}.", cursor);
+            Err(RetrieveError)
+        }
+    }
+}
+
+// Makes a page of the identifiers, which holds one identifier more than the page size if there is a next page
+fn make_page(mut ids: Vec<String>, page_size: usize) -> Page<String> {
+    let next_cursor = match ids.len() > page_size.max(1) {
+        true => {
+            ids.truncate(page_size.max(1));
+            ids.last().map(|last| encode_cursor(last))
+        }
+        false => None,
+    };
+
+    Page {
+        items: ids,
+        next_cursor,
+    }
+}
+
+// Retrieves the latest revision of the DaaS document in order to upsert a new revision
+fn get_latest_for_update<S: DaaSDocStorage + ?Sized>(
+    storage: &S,
+    doc_id: String,
+) -> Result<DaaSDoc, UpsertError> {
+    match storage.get_doc_by_id(doc_id, None) {
+        Ok(d) => Ok(d),
+        Err(_e) => Err(UpsertError),
+    }
+}
+
+// Verifies that the DaaS document can be upserted on top of the latest revision.
+// A (soft) deleted DaaS document can only be restored or have its legal hold changed.
+fn check_lifecycle(latest: &DaaSDoc, doc: &DaaSDoc) -> Result<(), UpsertError> {
+    if !latest.deleted {
+        return Ok(());
+    }
+
+    let restoring = !doc.deleted && doc._rev == latest._rev;
+    let holding = doc.deleted && doc.legal_hold != latest.legal_hold;
+
+    match restoring || holding {
+        true => Ok(()),
+        false => {
+            warn!(
+                "The DaaS document {} has been deleted and must be restored before it can be updated.",
+                doc._id
+            );
+            Err(UpsertError)
+        }
+    }
+}
+
+// Determines if a (soft) deleted DaaS document can be physically removed by the storage
+fn is_purgeable(doc: &DaaSDoc) -> bool {
+    doc.deleted && !doc.legal_hold
+}
+
+// Determines if an expired DaaS document can be physically removed by the storage
+fn is_expired_purgeable(doc: &DaaSDoc) -> bool {
+    doc.is_expired() && !doc.legal_hold
+}
+
+/// The outcome of creating an object that must not replace an existing object, (see `ObjectStore::create_daas_doc()`)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CreateOutcome {
+    /// The object was created
+    Created,
+    /// There already is an object under the key, which was left as it is
+    AlreadyExists,
+}
+
+/// Trait for object stores that the DaaS documents can be provisioned to, (e.g.: by the Genesis processor)
+pub trait ObjectStore {
+    /// Saves the DaaS document as an object under the key, replacing any existing object
+    fn put_daas_doc(
+        &self,
+        content_key: String,
+        doc: &DaaSDoc,
+    ) -> Result<i8, daaserror::DaaSStorageError>;
+
+    /// Saves the DaaS document as an object under the key only if there is no object under the key yet, so that
+    /// saving the same DaaS document again (e.g.: when a message is redelivered) never replaces the existing object.
+    /// The default implementation can't check for an existing object and replaces it, (see `put_daas_doc()`).
+    ///
+    /// # Arguments
+    ///
+    /// * content_key: String - The key of the object.</br>
+    /// * doc: &DaaSDoc - The DaaS document to save.</br>
+    fn create_daas_doc(
+        &self,
+        content_key: String,
+        doc: &DaaSDoc,
+    ) -> Result<CreateOutcome, daaserror::DaaSStorageError> {
+        self.put_daas_doc(content_key, doc)
+            .map(|_| CreateOutcome::Created)
+    }
+
+    /// Saves the content as an object under the key, replacing any existing object, (e.g.: a manifest file).
+    /// Not supported by every object store.
+    ///
+    /// # Arguments
+    ///
+    /// * content_key: String - The key of the object.</br>
+    /// * content: Vec<u8> - The content of the object.</br>
+    fn put_object(
+        &self,
+        content_key: String,
+        _content: Vec<u8>,
+    ) -> Result<i8, daaserror::DaaSStorageError> {
+        warn!(
+            "The object store doesn't support saving the object {}.",
+            content_key
+        );
+        Err(daaserror::DaaSStorageError::UpsertError)
+    }
+
+    /// Returns the content of the object under the key, or None if there is no object under the key.
+    /// Not supported by every object store.
+    ///
+    /// # Arguments
+    ///
+    /// * content_key: String - The key of the object.</br>
+    fn get_object(
+        &self,
+        content_key: String,
+    ) -> Result<Option<Vec<u8>>, daaserror::DaaSStorageError> {
+        warn!(
+            "The object store doesn't support retrieving the object {}.",
+            content_key
+        );
+        Err(daaserror::DaaSStorageError::RetrieveError)
+    }
 }
 
+pub mod archive;
+pub mod cache;
+#[cfg(feature = "local-storage")]
+pub mod compaction;
+pub mod dedup;
+pub mod delta;
+pub mod duplicates;
+#[cfg(all(
+    any(feature = "security", feature = "security-rustcrypto"),
+    feature = "local-storage"
+))]
+pub mod encrypted;
+#[cfg(feature = "local-storage")]
 pub mod local;
+pub mod manifest;
+pub mod memory;
+#[cfg(feature = "kafka")]
+pub mod notify;
+pub mod observer;
+pub mod replicated;
+#[cfg(feature = "s3")]
 pub mod s3;
+pub mod transfer;
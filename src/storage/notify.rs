@@ -0,0 +1,253 @@
+//! Storage that publishes a doc-updated event when the tags or metadata of a stored DaaS document change,
+//! (see `DaaSDocEvent`) so that downstream indexes stay in sync.
+//!
+//! When a control topic is set, the lifecycle events of the DaaS documents (doc-upserted, doc-processed,
+//! doc-deleted and retention-purged) are published to it as well.
+
+#[cfg(feature = "local-storage")]
+use super::local::LocalStorage;
+use super::*;
+#[cfg(feature = "local-storage")]
+use crate::eventing::event::RETENTION_PURGED;
+use crate::eventing::event::{
+    DaaSDocEvent, DaaSEventPublisher, DOC_DELETED, DOC_PROCESSED, DOC_UPSERTED,
+};
+
+/// Represents a storage device that publishes the changes to the tags and metadata of the DaaS documents
+pub struct NotifyingStorage<S: DaaSDocStorage, P: DaaSEventPublisher> {
+    /// The storage device that manages the DaaS documents
+    pub storage: S,
+    /// The broker that the events are published to
+    pub publisher: P,
+    /// The topic that the events are published to
+    pub topic: String,
+    /// The topic that the lifecycle events are published to, (None to not publish them)
+    pub control_topic: Option<String>,
+}
+
+impl<S: DaaSDocStorage, P: DaaSEventPublisher> NotifyingStorage<S, P> {
+    /// Constructor
+    ///
+    /// # Arguments
+    ///
+    /// * storage: S - The storage device that manages the DaaS documents.</br>
+    /// * publisher: P - The broker that the events are published to.</br>
+    /// * topic: String - The topic that the events are published to, (e.g.: doc-updated).</br>
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// extern crate daas;
+    ///
+    /// use daas::eventing::memory::InMemoryBroker;
+    /// use daas::storage::DaaSDocStorage;
+    /// use daas::storage::memory::InMemoryStorage;
+    /// use daas::storage::notify::NotifyingStorage;
+    /// use daas::testing;
+    ///
+    /// fn main() {
+    ///     let broker = InMemoryBroker::new();
+    ///     let storage = NotifyingStorage::new(InMemoryStorage::new(), broker.clone(), "doc-updated".to_string());
+    ///     let mut doc = storage.upsert_daas_doc(testing::get_default_daas_doc()).unwrap();
+    ///     doc.add_tag("priority".to_string());
+    ///     storage.upsert_daas_doc(doc).unwrap();
+    ///
+    ///     assert_eq!(broker.events("doc-updated").len(), 1);
+    /// }
+    /// ```
+    pub fn new(storage: S, publisher: P, topic: String) -> NotifyingStorage<S, P> {
+        NotifyingStorage {
+            storage,
+            publisher,
+            topic,
+            control_topic: None,
+        }
+    }
+
+    /// Publishes the lifecycle events of the DaaS documents to the control topic
+    ///
+    /// # Arguments
+    ///
+    /// * topic: String - The control topic, (e.g.: daas-control).</br>
+    pub fn with_control_topic(mut self, topic: String) -> NotifyingStorage<S, P> {
+        self.control_topic = Some(topic);
+        self
+    }
+
+    // Publishes the event, (the change has been saved, so a failure to publish the event is not a failure of the change)
+    fn publish(&self, event: &DaaSDocEvent, topic: &str) {
+        if let Err(err) = self.publisher.publish_event(event, topic) {
+            warn!(
+                "DaaS document {} was saved but the {} event was not published. {}",
+                event.doc_id, event.event_type, err
+            );
+        }
+    }
+
+    // Publishes the lifecycle event to the control topic, if there is one
+    fn publish_lifecycle(&self, event_type: &str, doc_id: String, doc_rev: Option<String>) {
+        if let Some(topic) = &self.control_topic {
+            self.publish(&DaaSDocEvent::lifecycle(event_type, doc_id, doc_rev), topic);
+        }
+    }
+}
+
+#[cfg(feature = "local-storage")]
+impl<P: DaaSEventPublisher> NotifyingStorage<LocalStorage, P> {
+    /// Marks the DaaS document as processed, (see `LocalStorage::mark_doc_as_processed()`), publishing a
+    /// doc-processed event
+    ///
+    /// # Arguments
+    ///
+    /// * doc: DaaSDoc - The DaaS document to mark as processed.</br>
+    pub fn mark_doc_as_processed(&self, doc: DaaSDoc) -> Result<DaaSDoc, UpsertError> {
+        let doc = self.storage.mark_doc_as_processed(doc)?;
+        self.publish_lifecycle(DOC_PROCESSED, doc._id.clone(), doc._rev.clone());
+        Ok(doc)
+    }
+
+    /// Physically removes the expired DaaS documents, (see `LocalStorage::purge_expired()`), publishing a
+    /// retention-purged event for each of them
+    pub fn purge_expired(&self) -> Result<Vec<String>, UpsertError> {
+        let purged = self.storage.purge_expired()?;
+        for doc_id in purged.iter() {
+            self.publish_lifecycle(RETENTION_PURGED, doc_id.clone(), None);
+        }
+        Ok(purged)
+    }
+}
+
+impl<S: DaaSDocStorage, P: DaaSEventPublisher> DaaSDocStorage for NotifyingStorage<S, P> {
+    fn upsert_daas_doc(&self, daas_doc: DaaSDoc) -> Result<DaaSDoc, UpsertError> {
+        // the revision that the DaaS document replaces, if it has been stored before
+        let previous = self
+            .storage
+            .get_doc_by_id(daas_doc._id.clone(), daas_doc._rev.clone())
+            .ok();
+        let doc = self.storage.upsert_daas_doc(daas_doc)?;
+
+        let event = previous
+            .as_ref()
+            .and_then(|p| DaaSDocEvent::doc_updated(&doc, &p.diff(&doc)));
+        if let Some(e) = event {
+            self.publish(&e, &self.topic);
+        }
+
+        self.publish_lifecycle(DOC_UPSERTED, doc._id.clone(), doc._rev.clone());
+        if doc.process_ind && !previous.iter().any(|p| p.process_ind) {
+            self.publish_lifecycle(DOC_PROCESSED, doc._id.clone(), doc._rev.clone());
+        }
+        if doc.deleted && !previous.iter().any(|p| p.deleted) {
+            self.publish_lifecycle(DOC_DELETED, doc._id.clone(), doc._rev.clone());
+        }
+
+        Ok(doc)
+    }
+
+    fn get_doc_by_id(
+        &self,
+        doc_id: String,
+        doc_rev: Option<String>,
+    ) -> Result<DaaSDoc, RetrieveError> {
+        self.storage.get_doc_by_id(doc_id, doc_rev)
+    }
+
+    fn list_doc_ids(&self) -> Result<Vec<String>, RetrieveError> {
+        self.storage.list_doc_ids()
+    }
+
+    fn list_doc_ids_page(
+        &self,
+        cursor: Option<String>,
+        page_size: usize,
+    ) -> Result<Page<String>, RetrieveError> {
+        self.storage.list_doc_ids_page(cursor, page_size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eventing::memory::InMemoryBroker;
+    use crate::storage::memory::InMemoryStorage;
+    use crate::testing;
+
+    #[test]
+    fn test_upsert_publishes_changes() {
+        let broker = InMemoryBroker::new();
+        let storage =
+            NotifyingStorage::new(InMemoryStorage::new(), broker.clone(), "upd".to_string());
+
+        // a new DaaS document isn't an update
+        let mut doc = storage
+            .upsert_daas_doc(testing::get_default_daas_doc())
+            .unwrap();
+        assert!(broker.events("upd").is_empty());
+
+        // a change of the data only isn't published
+        doc.data_obj = b"{}".to_vec();
+        let mut doc = storage.upsert_daas_doc(doc).unwrap();
+        assert!(broker.events("upd").is_empty());
+
+        doc.set_tags(vec!["priority".to_string()]);
+        doc.add_meta("status".to_string(), "shipped".to_string());
+        storage.upsert_daas_doc(doc).unwrap();
+
+        let events = broker.events("upd");
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].doc_rev, Some("3".to_string()));
+        assert_eq!(events[0].tags_added, vec!["priority".to_string()]);
+    }
+    #[test]
+    fn test_lifecycle_events() {
+        let broker = InMemoryBroker::new();
+        let storage =
+            NotifyingStorage::new(InMemoryStorage::new(), broker.clone(), "upd".to_string())
+                .with_control_topic("ctrl".to_string());
+
+        let mut doc = storage
+            .upsert_daas_doc(testing::get_default_daas_doc())
+            .unwrap();
+        doc.process_ind = true;
+        let doc = storage.upsert_daas_doc(doc).unwrap();
+        storage.soft_delete_daas_doc(doc._id.clone()).unwrap();
+
+        let types: Vec<String> = broker
+            .events("ctrl")
+            .into_iter()
+            .map(|e| e.event_type)
+            .collect();
+        assert_eq!(
+            types,
+            vec![
+                DOC_UPSERTED,
+                DOC_UPSERTED,
+                DOC_PROCESSED,
+                DOC_UPSERTED,
+                DOC_DELETED
+            ]
+        );
+        assert!(broker.events("upd").is_empty());
+    }
+
+    #[test]
+    fn test_lifecycle_events_local() {
+        let broker = InMemoryBroker::new();
+        let path = format!("./tmp/notify-{}", rand::random::<u32>());
+        let storage =
+            NotifyingStorage::new(LocalStorage::new(path), broker.clone(), "upd".to_string())
+                .with_control_topic("ctrl".to_string());
+        let mut doc = testing::get_default_daas_doc();
+        doc.last_updated = 1553988607;
+        doc.apply_retention(&crate::doc::RetentionPolicy::new().with_default_period(1));
+        let doc = storage.upsert_daas_doc(doc).unwrap();
+        storage.mark_doc_as_processed(doc.clone()).unwrap();
+
+        assert_eq!(storage.purge_expired().unwrap(), vec![doc._id.clone()]);
+        let events = broker.events("ctrl");
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[1].event_type, DOC_PROCESSED.to_string());
+        assert_eq!(events[2].event_type, RETENTION_PURGED.to_string());
+        assert_eq!(events[2].doc_rev, None);
+    }
+}
@@ -0,0 +1,187 @@
+//! Storage that notifies observers of the operations on the DaaS documents, so that applications can plug in
+//! cache invalidation, metrics or secondary indexing in one place instead of around every storage call.
+
+use super::*;
+
+/// Trait for the observers of the storage operations. The observers are notified after the operation succeeded.
+pub trait StorageObserver {
+    /// Called after a revision of the DaaS document was saved, (including restores and legal holds)
+    fn on_upsert(&self, _doc: &DaaSDoc) {}
+    /// Called after the DaaS document was retrieved
+    fn on_retrieve(&self, _doc: &DaaSDoc) {}
+    /// Called after the DaaS document was (soft) deleted
+    fn on_delete(&self, _doc: &DaaSDoc) {}
+}
+
+/// Represents a storage device that notifies the observers of the operations on the DaaS documents
+pub struct ObservedStorage<S: DaaSDocStorage> {
+    /// The storage device that manages the DaaS documents
+    pub storage: S,
+    observers: Vec<Box<dyn StorageObserver + Send + Sync>>,
+}
+
+impl<S: DaaSDocStorage> ObservedStorage<S> {
+    /// Constructs an ObservedStorage without any observers
+    ///
+    /// # Arguments
+    ///
+    /// * storage: S - The storage device that manages the DaaS documents.</br>
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// extern crate daas;
+    ///
+    /// use daas::doc::DaaSDoc;
+    /// use daas::storage::DaaSDocStorage;
+    /// use daas::storage::memory::InMemoryStorage;
+    /// use daas::storage::observer::{ObservedStorage, StorageObserver};
+    /// use daas::testing;
+    ///
+    /// struct CacheInvalidator {}
+    ///
+    /// impl StorageObserver for CacheInvalidator {
+    ///     fn on_upsert(&self, doc: &DaaSDoc) {
+    ///         println!("Invalidating the cached {}", doc._id);
+    ///     }
+    /// }
+    ///
+    /// fn main() {
+    ///     let storage = ObservedStorage::new(InMemoryStorage::new()).with_observer(CacheInvalidator {});
+    ///
+    ///     assert!(storage.upsert_daas_doc(testing::get_default_daas_doc()).is_ok());
+    /// }
+    /// ```
+    pub fn new(storage: S) -> ObservedStorage<S> {
+        ObservedStorage {
+            storage,
+            observers: Vec::new(),
+        }
+    }
+
+    /// Adds an observer, which is notified after the observers that were added before it
+    pub fn with_observer<O: StorageObserver + Send + Sync + 'static>(
+        mut self,
+        observer: O,
+    ) -> ObservedStorage<S> {
+        self.observers.push(Box::new(observer));
+        self
+    }
+
+    fn notify(&self, notification: fn(&dyn StorageObserver, &DaaSDoc), doc: &DaaSDoc) {
+        for observer in self.observers.iter() {
+            notification(observer.as_ref(), doc);
+        }
+    }
+}
+
+impl<S: DaaSDocStorage> DaaSDocStorage for ObservedStorage<S> {
+    fn upsert_daas_doc(&self, daas_doc: DaaSDoc) -> Result<DaaSDoc, UpsertError> {
+        let doc = self.storage.upsert_daas_doc(daas_doc)?;
+        self.notify(|o, d| o.on_upsert(d), &doc);
+        Ok(doc)
+    }
+
+    fn get_doc_by_id(
+        &self,
+        doc_id: String,
+        doc_rev: Option<String>,
+    ) -> Result<DaaSDoc, RetrieveError> {
+        let doc = self.storage.get_doc_by_id(doc_id, doc_rev)?;
+        self.notify(|o, d| o.on_retrieve(d), &doc);
+        Ok(doc)
+    }
+
+    fn list_doc_ids(&self) -> Result<Vec<String>, RetrieveError> {
+        self.storage.list_doc_ids()
+    }
+
+    fn list_doc_ids_page(
+        &self,
+        cursor: Option<String>,
+        page_size: usize,
+    ) -> Result<Page<String>, RetrieveError> {
+        self.storage.list_doc_ids_page(cursor, page_size)
+    }
+
+    fn soft_delete_daas_doc(&self, doc_id: String) -> Result<DaaSDoc, UpsertError> {
+        let doc = self.storage.soft_delete_daas_doc(doc_id)?;
+        self.notify(|o, d| o.on_delete(d), &doc);
+        Ok(doc)
+    }
+
+    fn restore_daas_doc(&self, doc_id: String) -> Result<DaaSDoc, UpsertError> {
+        let doc = self.storage.restore_daas_doc(doc_id)?;
+        self.notify(|o, d| o.on_upsert(d), &doc);
+        Ok(doc)
+    }
+
+    fn set_legal_hold(&self, doc_id: String, hold: bool) -> Result<DaaSDoc, UpsertError> {
+        let doc = self.storage.set_legal_hold(doc_id, hold)?;
+        self.notify(|o, d| o.on_upsert(d), &doc);
+        Ok(doc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::memory::InMemoryStorage;
+    use crate::testing;
+    use std::sync::{Arc, Mutex};
+
+    // An observer that records the operations
+    #[derive(Clone, Default)]
+    struct RecordingObserver {
+        operations: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl StorageObserver for RecordingObserver {
+        fn on_upsert(&self, doc: &DaaSDoc) {
+            self.operations
+                .lock()
+                .unwrap()
+                .push(format!("upsert {}", doc._rev.clone().unwrap()));
+        }
+
+        fn on_retrieve(&self, _doc: &DaaSDoc) {
+            self.operations.lock().unwrap().push("retrieve".to_string());
+        }
+
+        fn on_delete(&self, _doc: &DaaSDoc) {
+            self.operations.lock().unwrap().push("delete".to_string());
+        }
+    }
+
+    #[test]
+    fn test_observers_notified() {
+        let observer = RecordingObserver::default();
+        let storage = ObservedStorage::new(InMemoryStorage::new()).with_observer(observer.clone());
+        let doc = testing::get_default_daas_doc();
+
+        storage.upsert_daas_doc(doc.clone()).unwrap();
+        storage.get_doc_by_id(doc._id.clone(), None).unwrap();
+        storage.soft_delete_daas_doc(doc._id.clone()).unwrap();
+        storage.restore_daas_doc(doc._id.clone()).unwrap();
+
+        assert_eq!(
+            *observer.operations.lock().unwrap(),
+            vec![
+                "upsert 1".to_string(),
+                "retrieve".to_string(),
+                "delete".to_string(),
+                "upsert 3".to_string()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_observers_not_notified_on_failure() {
+        let observer = RecordingObserver::default();
+        let storage = ObservedStorage::new(InMemoryStorage::new()).with_observer(observer.clone());
+
+        assert!(storage.get_doc_by_id("missing".to_string(), None).is_err());
+        assert!(storage.soft_delete_daas_doc("missing".to_string()).is_err());
+        assert!(observer.operations.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,331 @@
+//! Storage that mirrors the DaaS documents of a primary storage device to secondary storage devices,
+//! (e.g.: LocalStorage and S3) as a simple disaster recovery for the staging store.
+//!
+//! The DaaS documents are saved to the primary storage device first, which assigns the revision. Then the saved
+//! revision is written to the secondary storage devices on top of their latest revision, either before the upsert
+//! returns (`Consistency::Sync`) or in a separate thread (`Consistency::Async`). The DaaS documents that couldn't be
+//! written to a secondary storage device are kept as pending, so they can be copied again by `repair()`.
+
+use super::*;
+use std::collections::BTreeSet;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// A secondary storage device that can be shared with the replication threads
+pub type SecondaryStorage = Arc<dyn DaaSDocStorage + Send + Sync>;
+
+/// When the DaaS documents are written to the secondary storage devices
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Consistency {
+    /// Before the upsert returns. The upsert fails if a secondary storage device can't be written to.
+    Sync,
+    /// In a separate thread after the upsert returns
+    Async,
+}
+
+/// Represents a storage device that mirrors the DaaS documents of the primary storage device to secondaries
+pub struct ReplicatedStorage<P: DaaSDocStorage> {
+    /// The storage device that assigns the revisions and serves the reads
+    pub primary: P,
+    /// When the DaaS documents are written to the secondary storage devices, (default: Sync)
+    pub consistency: Consistency,
+    secondaries: Vec<SecondaryStorage>,
+    pending: Arc<Mutex<BTreeSet<String>>>,
+}
+
+impl<P: DaaSDocStorage> ReplicatedStorage<P> {
+    /// Constructs a ReplicatedStorage without any secondary storage devices
+    ///
+    /// # Arguments
+    ///
+    /// * primary: P - The storage device that assigns the revisions and serves the reads.</br>
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// extern crate daas;
+    ///
+    /// use daas::storage::DaaSDocStorage;
+    /// use daas::storage::memory::InMemoryStorage;
+    /// use daas::storage::replicated::{Consistency, ReplicatedStorage};
+    /// use daas::testing;
+    /// use std::sync::Arc;
+    ///
+    /// fn main() {
+    ///     let backup = InMemoryStorage::new();
+    ///     let storage = ReplicatedStorage::new(InMemoryStorage::new())
+    ///         .with_secondary(Arc::new(backup.clone()))
+    ///         .with_consistency(Consistency::Sync);
+    ///     let doc = storage.upsert_daas_doc(testing::get_default_daas_doc()).unwrap();
+    ///
+    ///     assert!(backup.get_doc_by_id(doc._id, None).is_ok());
+    /// }
+    /// ```
+    pub fn new(primary: P) -> ReplicatedStorage<P> {
+        ReplicatedStorage {
+            primary,
+            consistency: Consistency::Sync,
+            secondaries: Vec::new(),
+            pending: Arc::new(Mutex::new(BTreeSet::new())),
+        }
+    }
+
+    /// Adds a secondary storage device
+    pub fn with_secondary(mut self, secondary: SecondaryStorage) -> ReplicatedStorage<P> {
+        self.secondaries.push(secondary);
+        self
+    }
+
+    /// Sets when the DaaS documents are written to the secondary storage devices
+    pub fn with_consistency(mut self, consistency: Consistency) -> ReplicatedStorage<P> {
+        self.consistency = consistency;
+        self
+    }
+
+    /// Returns the identifiers of the DaaS documents that haven't been written to all the secondary storage devices
+    pub fn pending(&self) -> Vec<String> {
+        self.pending.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// Copies the latest revision of the pending DaaS documents from the primary to the secondary storage devices.
+    /// Returns the number of DaaS documents that were repaired.
+    pub fn repair(&self) -> usize {
+        let mut repaired = 0;
+
+        for doc_id in self.pending() {
+            let doc = match self.primary.get_doc_by_id(doc_id.clone(), None) {
+                Ok(d) => d,
+                Err(err) => {
+                    warn!("Could not repair DaaS document {}. Error: {}", doc_id, err);
+                    continue;
+                }
+            };
+
+            if ReplicatedStorage::<P>::replicate(&self.secondaries, &self.pending, &doc) {
+                info!("DaaS document {} has been repaired.", doc_id);
+                repaired += 1;
+            }
+        }
+
+        repaired
+    }
+
+    // Writes the DaaS document to the secondary storage devices based upon the consistency
+    fn mirror(&self, doc: &DaaSDoc) -> Result<(), UpsertError> {
+        if self.secondaries.is_empty() {
+            return Ok(());
+        }
+
+        match self.consistency {
+            Consistency::Sync => {
+                match ReplicatedStorage::<P>::replicate(&self.secondaries, &self.pending, doc) {
+                    true => Ok(()),
+                    false => Err(UpsertError),
+                }
+            }
+            Consistency::Async => {
+                let secondaries = self.secondaries.clone();
+                let pending = self.pending.clone();
+                let doc = doc.clone();
+                thread::spawn(move || {
+                    ReplicatedStorage::<P>::replicate(&secondaries, &pending, &doc)
+                });
+                Ok(())
+            }
+        }
+    }
+
+    // Writes the DaaS document on top of the latest revision of each secondary storage device,
+    // recording the DaaS document as pending if a secondary storage device fails
+    fn replicate(
+        secondaries: &[SecondaryStorage],
+        pending: &Mutex<BTreeSet<String>>,
+        doc: &DaaSDoc,
+    ) -> bool {
+        let mut replicated = true;
+
+        for secondary in secondaries.iter() {
+            let mut copy = doc.clone();
+            copy._rev = secondary
+                .get_doc_by_id(doc._id.clone(), None)
+                .ok()
+                .and_then(|d| d._rev);
+
+            if let Err(err) = secondary.upsert_daas_doc(copy) {
+                error!(
+                    "Could not replicate DaaS document {}. Error: {}",
+                    doc._id, err
+                );
+                replicated = false;
+            }
+        }
+
+        let mut pending = pending.lock().unwrap();
+        match replicated {
+            true => pending.remove(&doc._id),
+            false => pending.insert(doc._id.clone()),
+        };
+
+        replicated
+    }
+}
+
+impl<P: DaaSDocStorage> DaaSDocStorage for ReplicatedStorage<P> {
+    fn upsert_daas_doc(&self, daas_doc: DaaSDoc) -> Result<DaaSDoc, UpsertError> {
+        let doc = self.primary.upsert_daas_doc(daas_doc)?;
+        self.mirror(&doc)?;
+        Ok(doc)
+    }
+
+    /// Retrieves the DaaS document from the primary storage device, or from the first secondary storage device that
+    /// has it if the primary storage device fails
+    fn get_doc_by_id(
+        &self,
+        doc_id: String,
+        doc_rev: Option<String>,
+    ) -> Result<DaaSDoc, RetrieveError> {
+        match self.primary.get_doc_by_id(doc_id.clone(), doc_rev.clone()) {
+            Ok(d) => Ok(d),
+            Err(err) => self
+                .secondaries
+                .iter()
+                .find_map(|s| s.get_doc_by_id(doc_id.clone(), doc_rev.clone()).ok())
+                .ok_or(err),
+        }
+    }
+
+    fn list_doc_ids(&self) -> Result<Vec<String>, RetrieveError> {
+        self.primary.list_doc_ids()
+    }
+
+    fn list_doc_ids_page(
+        &self,
+        cursor: Option<String>,
+        page_size: usize,
+    ) -> Result<Page<String>, RetrieveError> {
+        self.primary.list_doc_ids_page(cursor, page_size)
+    }
+
+    fn soft_delete_daas_doc(&self, doc_id: String) -> Result<DaaSDoc, UpsertError> {
+        let doc = self.primary.soft_delete_daas_doc(doc_id)?;
+        self.mirror(&doc)?;
+        Ok(doc)
+    }
+
+    fn restore_daas_doc(&self, doc_id: String) -> Result<DaaSDoc, UpsertError> {
+        let doc = self.primary.restore_daas_doc(doc_id)?;
+        self.mirror(&doc)?;
+        Ok(doc)
+    }
+
+    fn set_legal_hold(&self, doc_id: String, hold: bool) -> Result<DaaSDoc, UpsertError> {
+        let doc = self.primary.set_legal_hold(doc_id, hold)?;
+        self.mirror(&doc)?;
+        Ok(doc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::memory::InMemoryStorage;
+    use crate::testing;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::Duration;
+
+    // A storage device that can be taken offline
+    #[derive(Clone, Default)]
+    struct FlakyStorage {
+        storage: InMemoryStorage,
+        offline: Arc<AtomicBool>,
+    }
+
+    impl DaaSDocStorage for FlakyStorage {
+        fn upsert_daas_doc(&self, daas_doc: DaaSDoc) -> Result<DaaSDoc, UpsertError> {
+            match self.offline.load(Ordering::SeqCst) {
+                true => Err(UpsertError),
+                false => self.storage.upsert_daas_doc(daas_doc),
+            }
+        }
+
+        fn get_doc_by_id(
+            &self,
+            doc_id: String,
+            doc_rev: Option<String>,
+        ) -> Result<DaaSDoc, RetrieveError> {
+            match self.offline.load(Ordering::SeqCst) {
+                true => Err(RetrieveError),
+                false => self.storage.get_doc_by_id(doc_id, doc_rev),
+            }
+        }
+    }
+
+    #[test]
+    fn test_replicate_sync() {
+        let backup = InMemoryStorage::new();
+        let storage =
+            ReplicatedStorage::new(InMemoryStorage::new()).with_secondary(Arc::new(backup.clone()));
+        let doc = testing::get_default_daas_doc();
+
+        let mut saved = storage.upsert_daas_doc(doc.clone()).unwrap();
+        saved.add_tag("priority".to_string());
+        storage.upsert_daas_doc(saved).unwrap();
+        storage.soft_delete_daas_doc(doc._id.clone()).unwrap();
+        storage.restore_daas_doc(doc._id.clone()).unwrap();
+
+        let copy = backup.get_doc_by_id(doc._id, None).unwrap();
+        assert_eq!(copy._rev, Some("4".to_string()));
+        assert!(copy.has_tag("priority".to_string()));
+        assert!(!copy.deleted);
+    }
+
+    #[test]
+    fn test_replicate_async() {
+        let backup = InMemoryStorage::new();
+        let storage = ReplicatedStorage::new(InMemoryStorage::new())
+            .with_secondary(Arc::new(backup.clone()))
+            .with_consistency(Consistency::Async);
+        let doc = storage
+            .upsert_daas_doc(testing::get_default_daas_doc())
+            .unwrap();
+        let mut attempts = 0;
+
+        while backup.get_doc_by_id(doc._id.clone(), None).is_err() && attempts < 50 {
+            thread::sleep(Duration::from_millis(10));
+            attempts += 1;
+        }
+        assert!(backup.get_doc_by_id(doc._id, None).is_ok());
+    }
+
+    #[test]
+    fn test_repair() {
+        let backup = FlakyStorage::default();
+        let storage =
+            ReplicatedStorage::new(InMemoryStorage::new()).with_secondary(Arc::new(backup.clone()));
+        let doc = testing::get_default_daas_doc();
+
+        backup.offline.store(true, Ordering::SeqCst);
+        assert!(storage.upsert_daas_doc(doc.clone()).is_err());
+        assert_eq!(storage.pending(), vec![doc._id.clone()]);
+        assert_eq!(storage.repair(), 0);
+
+        backup.offline.store(false, Ordering::SeqCst);
+        assert_eq!(storage.repair(), 1);
+        assert!(storage.pending().is_empty());
+        assert!(backup.get_doc_by_id(doc._id, None).is_ok());
+    }
+
+    #[test]
+    fn test_get_failover() {
+        let primary = FlakyStorage::default();
+        let storage = ReplicatedStorage::new(primary.clone())
+            .with_secondary(Arc::new(InMemoryStorage::new()));
+        let doc = storage
+            .upsert_daas_doc(testing::get_default_daas_doc())
+            .unwrap();
+
+        primary.offline.store(true, Ordering::SeqCst);
+        assert!(storage.get_doc_by_id(doc._id.clone(), None).is_ok());
+        assert!(storage.get_doc_by_id("missing".to_string(), None).is_err());
+    }
+}
@@ -1,10 +1,165 @@
 use super::*;
 use crate::errors::daaserror::DaaSStorageError;
-use rusoto_core::Region;
-use rusoto_s3::{PutObjectRequest, S3Client, StreamingBody, S3};
+use rusoto_core::credential::{
+    AwsCredentials, DefaultCredentialsProvider, ProfileProvider, ProvideAwsCredentials,
+    StaticProvider,
+};
+use rusoto_core::signature::SignedRequest;
+use rusoto_core::{DispatchSignedRequest, HttpClient, Region, RusotoError};
+use rusoto_s3::util::{PreSignedRequest, PreSignedRequestOption};
+use rusoto_s3::{
+    AbortMultipartUploadRequest, CompleteMultipartUploadError, CompleteMultipartUploadRequest,
+    CompletedMultipartUpload, CompletedPart, CreateMultipartUploadRequest, GetObjectError,
+    GetObjectRequest, HeadObjectRequest, ListObjectsV2Request, PutObjectError, PutObjectRequest,
+    S3Client, StreamingBody, UploadPartRequest, S3,
+};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::future::Future;
+use std::io::Read;
+use std::time::Duration;
+use tokio::io::AsyncReadExt;
 use tokio::runtime::Runtime;
 
-/// Credentials are read from the environment vcariables AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY
+// The size of the parts of a multipart upload (content larger than a part is uploaded in parts)
+const MULTIPART_PART_SIZE: usize = 5 * 1024 * 1024;
+
+// The maximum number of keys the S3 API returns in a page
+const MAX_LIST_KEYS: usize = 1000;
+
+// The maximum time (seconds) a pre-signed URL can be valid
+const MAX_PRESIGN_EXPIRY_SECS: u64 = 604_800;
+
+// The maximum number of tags S3 allows on an object
+const MAX_TAGS: usize = 10;
+// The maximum length of a tag key
+const MAX_TAG_KEY_LEN: usize = 128;
+// The maximum length of a tag value
+const MAX_TAG_VALUE_LEN: usize = 256;
+
+// The user metadata that holds the SHA-256 of the content of the conditional uploads, (x-amz-meta-...)
+const CONTENT_HASH_META: &str = "daas-content-sha256";
+
+// The outcome of a conditional request
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Precondition {
+    // the precondition was met and the request succeeded
+    Met,
+    // S3 answered 412 Precondition Failed, (after retrying the request if `retried` is set)
+    Failed { retried: bool },
+}
+
+/// The callback that is given the number of bytes uploaded and the total number of bytes
+pub type S3ProgressFn = dyn Fn(usize, usize) + Send + Sync;
+
+/// Represents the policy for retrying failed requests to the S3 API using an exponential backoff
+#[derive(Debug, Clone, PartialEq)]
+pub struct S3RetryPolicy {
+    /// The maximum number of times a request is retried
+    pub max_retries: u32,
+    /// The delay (milliseconds) before the first retry, which doubles with each retry
+    pub base_delay_ms: u64,
+    /// The maximum delay (milliseconds) between retries
+    pub max_delay_ms: u64,
+}
+
+impl S3RetryPolicy {
+    /// Returns the delay before the retry of the attempt (starting at 0)
+    ///
+    /// # Arguments
+    ///
+    /// * attempt: u32 - The number of the failed attempt.</br>
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let delay = self
+            .base_delay_ms
+            .saturating_mul(2u64.saturating_pow(attempt));
+        Duration::from_millis(delay.min(self.max_delay_ms))
+    }
+}
+
+impl Default for S3RetryPolicy {
+    fn default() -> Self {
+        S3RetryPolicy {
+            max_retries: 3,
+            base_delay_ms: 200,
+            max_delay_ms: 10_000,
+        }
+    }
+}
+
+/// Represents the source of the AWS credentials used to access the S3 Bucket
+#[derive(Clone, PartialEq)]
+pub enum S3Credentials {
+    /// The default chain: the environment variables AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY, the credentials profile, then the container or instance metadata
+    Default,
+    /// Static credentials, (e.g.: for MinIO or LocalStack)
+    Static {
+        access_key: String,
+        secret_key: String,
+        session_token: Option<String>,
+    },
+    /// The named profile of the AWS credentials file, or the `default` profile if None
+    Profile(Option<String>),
+}
+
+impl fmt::Debug for S3Credentials {
+    // the secrets are never written to the logs
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            S3Credentials::Default => write!(f, "Default"),
+            S3Credentials::Static { access_key, .. } => {
+                write!(f, "Static {{ access_key: {:?}, .. }}", access_key)
+            }
+            S3Credentials::Profile(profile) => write!(f, "Profile({:?})", profile),
+        }
+    }
+}
+
+/// Represents the server-side encryption to apply to the uploaded objects
+#[derive(Debug, Clone, PartialEq)]
+pub enum S3Encryption {
+    /// Use the default encryption of the bucket
+    None,
+    /// Encrypt using the S3 managed keys (SSE-S3)
+    S3,
+    /// Encrypt using a KMS key (SSE-KMS), optionally providing the id of the key (otherwise the AWS managed key is used)
+    Kms(Option<String>),
+}
+
+/// Represents the Object Lock retention to apply to the uploaded objects
+#[derive(Debug, Clone, PartialEq)]
+pub struct S3ObjectLock {
+    /// The retention mode, (e.g.: GOVERNANCE or COMPLIANCE)
+    pub mode: String,
+    /// The date and time (ISO 8601) until which the object is retained, (e.g.: 2030-01-01T00:00:00Z)
+    pub retain_until: String,
+    /// Whether a legal hold is placed on the object
+    pub legal_hold: bool,
+}
+
+/// Represents the options that are applied when uploading objects to the S3 Bucket
+#[derive(Debug, Clone, PartialEq)]
+pub struct S3UploadOptions {
+    /// The canned ACL, (e.g.: private)
+    pub acl: Option<String>,
+    /// The server-side encryption
+    pub encryption: S3Encryption,
+    /// The storage class, (e.g.: STANDARD_IA), or None for the default of the bucket
+    pub storage_class: Option<String>,
+    /// The Object Lock retention, (the bucket must have Object Lock enabled)
+    pub object_lock: Option<S3ObjectLock>,
+}
+
+impl Default for S3UploadOptions {
+    fn default() -> Self {
+        S3UploadOptions {
+            acl: Some("private".to_string()),
+            encryption: S3Encryption::None,
+            storage_class: None,
+            object_lock: None,
+        }
+    }
+}
 
 /// Represents a facilitator for managing a S3 Bucket and it's content
 #[derive(Debug, Clone)]
@@ -15,6 +170,14 @@ pub struct S3BucketMngr {
     pub bucket: String,
     /// The AWS ARN of the S3 Bucket
     pub arn: String,
+    /// The options that are applied when uploading objects
+    pub options: S3UploadOptions,
+    /// The source of the AWS credentials
+    pub credentials: S3Credentials,
+    /// The S3 Buckets that the uploaded objects are replicated to
+    pub replicas: Vec<S3BucketMngr>,
+    /// The policy for retrying failed requests
+    pub retry: S3RetryPolicy,
 }
 
 pub trait S3BucketManager {
@@ -26,6 +189,13 @@ pub trait S3BucketManager {
         content_key: String,
         content: StreamingBody,
     ) -> Result<i8, DaaSStorageError>;
+    fn upload_file_conditional(
+        self,
+        content_key: String,
+        content: StreamingBody,
+        if_match: Option<String>,
+    ) -> Result<i8, DaaSStorageError>;
+    fn upload_daas_doc(self, content_key: String, doc: &DaaSDoc) -> Result<i8, DaaSStorageError>;
 }
 
 impl S3BucketManager for S3BucketMngr {
@@ -55,6 +225,10 @@ impl S3BucketManager for S3BucketMngr {
             region: region,
             bucket: bucket_name.clone(),
             arn: format!("arn:aws:s3:::{}", bucket_name).to_string(),
+            options: S3UploadOptions::default(),
+            credentials: S3Credentials::Default,
+            replicas: Vec::new(),
+            retry: S3RetryPolicy::default(),
         }
     }
 
@@ -85,6 +259,10 @@ impl S3BucketManager for S3BucketMngr {
             region: region,
             bucket: arn[5].take().unwrap(),
             arn: bucket_arn,
+            options: S3UploadOptions::default(),
+            credentials: S3Credentials::Default,
+            replicas: Vec::new(),
+            retry: S3RetryPolicy::default(),
         }
     }
 
@@ -156,26 +334,1263 @@ impl S3BucketManager for S3BucketMngr {
         content_key: String,
         content: StreamingBody,
     ) -> Result<i8, DaaSStorageError> {
-        let s3_client = S3Client::new(Region::UsEast1);
-        let req = PutObjectRequest {
-            bucket: self.bucket,
+        let content = S3BucketMngr::read_content(&content_key, content)?;
+
+        let rt = Runtime::new().unwrap();
+        rt.block_on(self.upload_async(content_key, content, None, None))
+    }
+
+    /// Uploads a file to the S3 Bucket only if the object in the bucket is still the expected version (compare-and-swap)
+    ///
+    /// The object is put with a conditional request, (`If-Match: <etag>`, or `If-None-Match: *` if the object must not
+    /// already exist), so S3 rejects the upload (412 Precondition Failed) when a concurrent writer replaced the object.
+    /// A rejected upload is a revision conflict, (`DaaSStorageError::UpsertError`). The content is put as a single
+    /// object, and it is then copied to the replicas.
+    ///
+    /// # Arguments
+    ///
+    /// * content_key: String - The S3 Bucket prefix key to use for the document, (e.g.: "myfolder/myfile.txt").</br>
+    /// * content: StreamingBody - The ByteStream that is the content of the file.</br>
+    /// * if_match: Option<String> - The expected ETag of the existing object, or None if the object must not already exist.</br>
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// extern crate daas;
+    /// extern crate rusoto_s3;
+    ///
+    /// use daas::storage::s3::{S3BucketManager, S3BucketMngr};
+    /// use rusoto_core::Region;
+    /// use rusoto_s3::{StreamingBody};
+    ///
+    /// fn main() {
+    ///     let bckt = S3BucketMngr::new(Region::UsEast1, "daas-test-bucket".to_string());
+    ///     let content: StreamingBody = String::from("this is a message....").into_bytes().into();
+    ///
+    ///     /*
+    ///     match bckt.upload_file_conditional("tmp/mystuff/new-record3.txt".to_string(), content, None) {
+    ///         Ok(_y) => assert!(true),
+    ///         Err(err) => panic!("{:?}", err),
+    ///     }
+    ///     */
+    /// }
+    /// ```
+    fn upload_file_conditional(
+        self,
+        content_key: String,
+        content: StreamingBody,
+        if_match: Option<String>,
+    ) -> Result<i8, DaaSStorageError> {
+        let content = S3BucketMngr::read_content(&content_key, content)?;
+        let rt = Runtime::new().unwrap();
+
+        if !rt.block_on(self.put_if(&content_key, content.clone(), &None, &if_match))? {
+            warn!(
+                "Revision conflict: object {} of S3 Bucket {} isn't the expected version {:?}.",
+                content_key, self.bucket, if_match
+            );
+            return Err(DaaSStorageError::UpsertError);
+        }
+
+        for replica in self.replicas.iter() {
+            rt.block_on(replica.upload_bytes(&content_key, &content, &None, None))?;
+        }
+        Ok(1)
+    }
+
+    /// Uploads a DaaS document to the S3 Bucket, tagging the object with the tags and metadata of the DaaS document
+    ///
+    /// # Arguments
+    ///
+    /// * content_key: String - The S3 Bucket prefix key to use for the document, (e.g.: "myfolder/myfile.daas").</br>
+    /// * doc: &DaaSDoc - The DaaS document to upload.</br>
+    fn upload_daas_doc(self, content_key: String, doc: &DaaSDoc) -> Result<i8, DaaSStorageError> {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(self.upload_daas_doc_async(content_key, doc, None))
+    }
+}
+
+impl ObjectStore for S3BucketMngr {
+    fn put_daas_doc(&self, content_key: String, doc: &DaaSDoc) -> Result<i8, DaaSStorageError> {
+        self.clone().upload_daas_doc(content_key, doc)
+    }
+
+    /// Uploads the DaaS document only if there is no object under the key yet, (see `create_daas_doc_async()`)
+    fn create_daas_doc(
+        &self,
+        content_key: String,
+        doc: &DaaSDoc,
+    ) -> Result<CreateOutcome, DaaSStorageError> {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(self.create_daas_doc_async(content_key, doc))
+    }
+
+    /// Uploads the content to the S3 Bucket (and its replicas), (see `upload_async()`)
+    fn put_object(&self, content_key: String, content: Vec<u8>) -> Result<i8, DaaSStorageError> {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(self.upload_async(content_key, content, None, None))
+    }
+
+    /// Downloads the content of the object from the S3 Bucket, (see `download_async()`)
+    fn get_object(&self, content_key: String) -> Result<Option<Vec<u8>>, DaaSStorageError> {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(self.download_async(content_key))
+    }
+}
+
+impl S3BucketMngr {
+    /// Sets the options that are applied when uploading objects
+    ///
+    /// # Arguments
+    ///
+    /// * options: S3UploadOptions - The upload options, (e.g.: encryption, storage class, Object Lock).</br>
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// extern crate daas;
+    ///
+    /// use rusoto_core::Region;
+    /// use daas::storage::s3::{S3BucketManager, S3BucketMngr, S3Encryption, S3UploadOptions};
+    ///
+    /// fn main() {
+    ///    let bckt = S3BucketMngr::new(Region::UsEast1, "daas-test-bucket".to_string())
+    ///        .with_options(S3UploadOptions {
+    ///            encryption: S3Encryption::Kms(Some("my-key-id".to_string())),
+    ///            storage_class: Some("STANDARD_IA".to_string()),
+    ///            ..Default::default()
+    ///        });
+    ///
+    ///    assert_eq!(bckt.options.storage_class, Some("STANDARD_IA".to_string()));
+    /// }
+    /// ```
+    pub fn with_options(mut self, options: S3UploadOptions) -> S3BucketMngr {
+        self.options = options;
+        self
+    }
+
+    /// Returns the URL encoded S3 object tagging for the DaaS document, (e.g.: `priority=&content-type=application%2Fjson`)
+    /// The tags of the DaaS document become tags without a value and the metadata become key/value tags.
+    /// Since S3 limits the number of tags on an object, any tags beyond the limit are dropped.
+    ///
+    /// # Arguments
+    ///
+    /// * doc: &DaaSDoc - The DaaS document to tag the object with.</br>
+    pub fn make_tagging(doc: &DaaSDoc) -> String {
+        let mut tags: Vec<(String, String)> = doc
+            .tags
+            .iter()
+            .map(|t| (t.clone(), "".to_string()))
+            .collect();
+        tags.extend(doc.meta_data.iter().map(|(k, v)| (k.clone(), v.clone())));
+
+        if tags.len() > MAX_TAGS {
+            warn!(
+                "DaaS document {} has more than {} tags and metadata. Only the first {} are tagged.",
+                doc._id, MAX_TAGS, MAX_TAGS
+            );
+        }
+
+        tags.iter()
+            .take(MAX_TAGS)
+            .map(|(k, v)| {
+                format!(
+                    "{}={}",
+                    url_encode(&k.chars().take(MAX_TAG_KEY_LEN).collect::<String>()),
+                    url_encode(&v.chars().take(MAX_TAG_VALUE_LEN).collect::<String>())
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("&")
+    }
+
+    // Builds the request to put the object applying the upload options
+    fn make_put_request(
+        &self,
+        content_key: String,
+        content: StreamingBody,
+        tagging: Option<String>,
+    ) -> PutObjectRequest {
+        let (sse, kms_key) = match &self.options.encryption {
+            S3Encryption::None => (None, None),
+            S3Encryption::S3 => (Some("AES256".to_string()), None),
+            S3Encryption::Kms(key) => (Some("aws:kms".to_string()), key.clone()),
+        };
+        let lock = self.options.object_lock.as_ref();
+
+        PutObjectRequest {
+            bucket: self.bucket.clone(),
             key: content_key,
             body: Some(content),
-            acl: Some("private".to_string()),
+            acl: self.options.acl.clone(),
+            server_side_encryption: sse,
+            ssekms_key_id: kms_key,
+            storage_class: self.options.storage_class.clone(),
+            object_lock_mode: lock.map(|l| l.mode.clone()),
+            object_lock_retain_until_date: lock.map(|l| l.retain_until.clone()),
+            object_lock_legal_hold_status: lock.filter(|l| l.legal_hold).map(|_| "ON".to_string()),
+            tagging: tagging.filter(|t| !t.is_empty()),
+            ..Default::default()
+        }
+    }
+
+    /// Sets a custom endpoint for the S3 API, (e.g.: MinIO or LocalStack)
+    ///
+    /// # Arguments
+    ///
+    /// * endpoint: String - The URL of the S3 API, (e.g.: http://localhost:9000).</br>
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// extern crate daas;
+    ///
+    /// use rusoto_core::Region;
+    /// use daas::storage::s3::{S3BucketManager, S3BucketMngr};
+    ///
+    /// fn main() {
+    ///    let bckt = S3BucketMngr::new(Region::UsEast1, "daas-test-bucket".to_string())
+    ///        .with_endpoint("http://localhost:9000".to_string());
+    ///
+    ///    assert_eq!(bckt.region, Region::Custom {
+    ///        name: "us-east-1".to_string(),
+    ///        endpoint: "http://localhost:9000".to_string(),
+    ///    });
+    /// }
+    /// ```
+    pub fn with_endpoint(mut self, endpoint: String) -> S3BucketMngr {
+        self.region = Region::Custom {
+            name: self.region.name().to_string(),
+            endpoint,
+        };
+        self
+    }
+
+    /// Sets the credentials used to access the S3 Bucket
+    ///
+    /// # Arguments
+    ///
+    /// * credentials: S3Credentials - The source of the AWS credentials.</br>
+    pub fn with_credentials(mut self, credentials: S3Credentials) -> S3BucketMngr {
+        self.credentials = credentials;
+        self
+    }
+
+    /// Adds a S3 Bucket (e.g.: in another region) that every uploaded object is replicated to
+    ///
+    /// # Arguments
+    ///
+    /// * replica: S3BucketMngr - The S3 Bucket to replicate the objects to.</br>
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// extern crate daas;
+    ///
+    /// use rusoto_core::Region;
+    /// use daas::storage::s3::{S3BucketManager, S3BucketMngr};
+    ///
+    /// fn main() {
+    ///    let bckt = S3BucketMngr::new(Region::UsEast1, "daas-test-bucket".to_string())
+    ///        .with_replica(S3BucketMngr::new(Region::EuWest1, "daas-test-bucket-eu".to_string()));
+    ///
+    ///    assert_eq!(bckt.replicas.len(), 1);
+    /// }
+    /// ```
+    pub fn with_replica(mut self, replica: S3BucketMngr) -> S3BucketMngr {
+        self.replicas.push(replica);
+        self
+    }
+
+    // Creates the S3 client for the region and credentials of the S3 Bucket
+    fn client(&self) -> Result<S3Client, DaaSStorageError> {
+        let region = self.region.clone();
+
+        match &self.credentials {
+            S3Credentials::Default => Ok(S3Client::new(region)),
+            S3Credentials::Static {
+                access_key,
+                secret_key,
+                session_token,
+            } => match HttpClient::new() {
+                Ok(http) => Ok(S3Client::new_with(
+                    http,
+                    StaticProvider::new(
+                        access_key.clone(),
+                        secret_key.clone(),
+                        session_token.clone(),
+                        None,
+                    ),
+                    region,
+                )),
+                Err(err) => {
+                    error!("Could not create the S3 client. Error: {}", err);
+                    Err(DaaSStorageError::UpsertError)
+                }
+            },
+            S3Credentials::Profile(profile) => match (HttpClient::new(), ProfileProvider::new()) {
+                (Ok(http), Ok(mut provider)) => {
+                    if let Some(name) = profile {
+                        provider.set_profile(name.clone());
+                    }
+                    Ok(S3Client::new_with(http, provider, region))
+                }
+                _ => {
+                    error!("Could not create the S3 client using the AWS credentials profile.");
+                    Err(DaaSStorageError::UpsertError)
+                }
+            },
+        }
+    }
+
+    /// Sets the policy for retrying failed requests to the S3 API
+    ///
+    /// # Arguments
+    ///
+    /// * retry: S3RetryPolicy - The retry policy.</br>
+    pub fn with_retry(mut self, retry: S3RetryPolicy) -> S3BucketMngr {
+        self.retry = retry;
+        self
+    }
+
+    /// Returns a pre-signed URL that allows the holder to download the object without AWS credentials
+    ///
+    /// # Arguments
+    ///
+    /// * content_key: String - The S3 Bucket prefix key of the object, (e.g.: "myfolder/myfile.txt").</br>
+    /// * expiry: Duration - How long the URL is valid (at most 7 days).</br>
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// extern crate daas;
+    ///
+    /// use daas::storage::s3::{S3BucketManager, S3BucketMngr, S3Credentials};
+    /// use rusoto_core::Region;
+    /// use std::time::Duration;
+    ///
+    /// fn main() {
+    ///     let bckt = S3BucketMngr::new(Region::UsEast1, "daas-test-bucket".to_string())
+    ///         .with_credentials(S3Credentials::Static {
+    ///             access_key: "my-access-key".to_string(),
+    ///             secret_key: "my-secret-key".to_string(),
+    ///             session_token: None,
+    ///         });
+    ///     let rt = tokio::runtime::Runtime::new().unwrap();
+    ///     let url = rt.block_on(bckt.presign_get("tmp/mystuff/large.mp4".to_string(), Duration::from_secs(900))).unwrap();
+    ///
+    ///     assert!(url.contains("X-Amz-Signature"));
+    /// }
+    /// ```
+    pub async fn presign_get(
+        &self,
+        content_key: String,
+        expiry: Duration,
+    ) -> Result<String, DaaSStorageError> {
+        let req = GetObjectRequest {
+            bucket: self.bucket.clone(),
+            key: content_key,
+            ..Default::default()
+        };
+        let (credentials, option) = self.presign_args(expiry).await?;
+
+        Ok(req.get_presigned_url(&self.region, &credentials, &option))
+    }
+
+    /// Returns a pre-signed URL that allows the holder to upload the object without AWS credentials,
+    /// (e.g.: so that very large payloads don't have to be proxied through the DaaS listener)
+    ///
+    /// The upload options of the S3BucketMngr are not part of the signature, so the default encryption
+    /// and storage class of the bucket apply to the uploaded object.
+    ///
+    /// # Arguments
+    ///
+    /// * content_key: String - The S3 Bucket prefix key of the object, (e.g.: "myfolder/myfile.txt").</br>
+    /// * expiry: Duration - How long the URL is valid (at most 7 days).</br>
+    pub async fn presign_put(
+        &self,
+        content_key: String,
+        expiry: Duration,
+    ) -> Result<String, DaaSStorageError> {
+        let req = PutObjectRequest {
+            bucket: self.bucket.clone(),
+            key: content_key,
             ..Default::default()
         };
+        let (credentials, option) = self.presign_args(expiry).await?;
 
-        let mut rt = Runtime::new().unwrap();
-        match rt.block_on(s3_client.put_object(req)) {
-            Ok(_t) => Ok(1),
-            Err(_err) => Err(DaaSStorageError::UpsertError),
+        Ok(req.get_presigned_url(&self.region, &credentials, &option))
+    }
+
+    /// Returns a page of the keys of the objects in the S3 Bucket, continuing after the cursor of the previous page,
+    /// (i.e.: the continuation token of the S3 API)
+    ///
+    /// # Arguments
+    ///
+    /// * prefix: Option<String> - The prefix of the keys to list, (e.g.: "genesis/order/").</br>
+    /// * cursor: Option<String> - The cursor of the previous page, or None for the first page.</br>
+    /// * page_size: usize - The maximum number of keys of the page (at most 1000).</br>
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// extern crate daas;
+    ///
+    /// use daas::storage::s3::{S3BucketManager, S3BucketMngr};
+    /// use rusoto_core::Region;
+    ///
+    /// fn main() {
+    ///     let bckt = S3BucketMngr::new(Region::UsEast1, "daas-test-bucket".to_string());
+    ///     let rt = tokio::runtime::Runtime::new().unwrap();
+    ///
+    ///     /*
+    ///     let mut page = rt.block_on(bckt.list_keys(Some("genesis/".to_string()), None, 1000)).unwrap();
+    ///     while let Some(cursor) = page.next_cursor {
+    ///         page = rt.block_on(bckt.list_keys(Some("genesis/".to_string()), Some(cursor), 1000)).unwrap();
+    ///     }
+    ///     */
+    /// }
+    /// ```
+    pub async fn list_keys(
+        &self,
+        prefix: Option<String>,
+        cursor: Option<String>,
+        page_size: usize,
+    ) -> Result<Page<String>, DaaSStorageError> {
+        let s3_client = self.client()?;
+        let output = match self
+            .retry("list the objects", || {
+                s3_client.list_objects_v2(self.make_list_request(
+                    prefix.clone(),
+                    cursor.clone(),
+                    page_size,
+                ))
+            })
+            .await
+        {
+            Ok(o) => o,
+            Err(err) => {
+                error!(
+                    "Could not list the objects of bucket {}. Error: {}",
+                    self.bucket, err
+                );
+                return Err(DaaSStorageError::RetrieveError);
+            }
+        };
+
+        Ok(Page {
+            items: output
+                .contents
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|o| o.key)
+                .collect(),
+            next_cursor: match output.is_truncated {
+                Some(true) => output.next_continuation_token,
+                _ => None,
+            },
+        })
+    }
+
+    // Builds the request for a page of the keys of the objects
+    fn make_list_request(
+        &self,
+        prefix: Option<String>,
+        cursor: Option<String>,
+        page_size: usize,
+    ) -> ListObjectsV2Request {
+        ListObjectsV2Request {
+            bucket: self.bucket.clone(),
+            prefix,
+            continuation_token: cursor,
+            max_keys: Some(page_size.clamp(1, MAX_LIST_KEYS) as i64),
+            ..Default::default()
         }
     }
+
+    async fn presign_args(
+        &self,
+        expiry: Duration,
+    ) -> Result<(AwsCredentials, PreSignedRequestOption), DaaSStorageError> {
+        if expiry > Duration::from_secs(MAX_PRESIGN_EXPIRY_SECS) {
+            error!(
+                "A pre-signed URL can't be valid for more than {} seconds.",
+                MAX_PRESIGN_EXPIRY_SECS
+            );
+            return Err(DaaSStorageError::RetrieveError);
+        }
+
+        match self.credentials().await {
+            Ok(c) => Ok((c, PreSignedRequestOption { expires_in: expiry })),
+            Err(err) => {
+                error!("Could not get the AWS credentials. Error: {}", err);
+                Err(DaaSStorageError::RetrieveError)
+            }
+        }
+    }
+
+    // Returns the AWS credentials of the S3 Bucket, (e.g.: for signing the requests that are built by hand)
+    async fn credentials(&self) -> Result<AwsCredentials, String> {
+        match &self.credentials {
+            S3Credentials::Default => match DefaultCredentialsProvider::new() {
+                Ok(provider) => provider.credentials().await.map_err(|e| e.to_string()),
+                Err(err) => Err(err.to_string()),
+            },
+            S3Credentials::Static {
+                access_key,
+                secret_key,
+                session_token,
+            } => Ok(AwsCredentials::new(
+                access_key.clone(),
+                secret_key.clone(),
+                session_token.clone(),
+                None,
+            )),
+            S3Credentials::Profile(profile) => match ProfileProvider::new() {
+                Ok(mut provider) => {
+                    if let Some(name) = profile {
+                        provider.set_profile(name.clone());
+                    }
+                    provider.credentials().await.map_err(|e| e.to_string())
+                }
+                Err(err) => Err(err.to_string()),
+            },
+        }
+    }
+
+    /// Uploads the content to the S3 Bucket and its replicas without blocking the thread.
+    /// Content larger than 5MB is uploaded in parts, and failed requests are retried based on the retry policy.
+    ///
+    /// # Arguments
+    ///
+    /// * content_key: String - The S3 Bucket prefix key to use for the document, (e.g.: "myfolder/myfile.txt").</br>
+    /// * content: Vec<u8> - The content of the file.</br>
+    /// * tagging: Option<String> - The URL encoded object tagging, (see `make_tagging()`).</br>
+    /// * progress: Option<&S3ProgressFn> - The callback that is given the number of bytes uploaded and the total number of bytes.</br>
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// extern crate daas;
+    ///
+    /// use daas::storage::s3::{S3BucketManager, S3BucketMngr};
+    /// use rusoto_core::Region;
+    ///
+    /// fn main() {
+    ///     let bckt = S3BucketMngr::new(Region::UsEast1, "daas-test-bucket".to_string());
+    ///     let content = String::from("this is a message....").into_bytes();
+    ///     let progress = |sent: usize, total: usize| println!("{} of {} bytes", sent, total);
+    ///
+    ///     /*
+    ///     let rt = tokio::runtime::Runtime::new().unwrap();
+    ///     let rslt = rt.block_on(bckt.upload_async("tmp/mystuff/new-record4.txt".to_string(), content, None, Some(&progress)));
+    ///     assert!(rslt.is_ok());
+    ///     */
+    /// }
+    /// ```
+    pub async fn upload_async(
+        &self,
+        content_key: String,
+        content: Vec<u8>,
+        tagging: Option<String>,
+        progress: Option<&S3ProgressFn>,
+    ) -> Result<i8, DaaSStorageError> {
+        self.upload_bytes(&content_key, &content, &tagging, progress)
+            .await?;
+
+        for replica in self.replicas.iter() {
+            replica
+                .upload_bytes(&content_key, &content, &tagging, None)
+                .await?;
+        }
+
+        Ok(1)
+    }
+
+    /// Asynchronously uploads the DaaS document to the S3 Bucket (and its replicas), serializing it as it is uploaded.
+    /// DaaS documents larger than a part (5 MiB) are uploaded in parts, so only a part of the serialized document is held in memory at a time.
+    ///
+    /// # Arguments
+    ///
+    /// * content_key: String - The S3 Bucket prefix key to use for the document, (e.g.: "myfolder/myfile.json").</br>
+    /// * doc: &DaaSDoc - The DaaS document to upload.</br>
+    /// * progress: Option<&S3ProgressFn> - The callback that is given the number of bytes uploaded and the total number of bytes.</br>
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// extern crate daas;
+    ///
+    /// use daas::storage::s3::{S3BucketManager, S3BucketMngr};
+    /// use daas::testing;
+    /// use rusoto_core::Region;
+    ///
+    /// fn main() {
+    ///     let bckt = S3BucketMngr::new(Region::UsEast1, "daas-test-bucket".to_string());
+    ///     let doc = testing::get_default_daas_doc();
+    ///
+    ///     /*
+    ///     let rt = tokio::runtime::Runtime::new().unwrap();
+    ///     let rslt = rt.block_on(bckt.upload_daas_doc_async("tmp/mystuff/mydoc.json".to_string(), &doc, None));
+    ///     assert!(rslt.is_ok());
+    ///     */
+    /// }
+    /// ```
+    pub async fn upload_daas_doc_async(
+        &self,
+        content_key: String,
+        doc: &DaaSDoc,
+        progress: Option<&S3ProgressFn>,
+    ) -> Result<i8, DaaSStorageError> {
+        let tagging = Some(S3BucketMngr::make_tagging(doc));
+        let parts = doc.to_chunks(MULTIPART_PART_SIZE);
+        let total = parts.serialized_len();
+
+        self.upload_stream(&content_key, parts, total, &tagging, progress)
+            .await?;
+
+        for replica in self.replicas.iter() {
+            let parts = doc.to_chunks(MULTIPART_PART_SIZE);
+            replica
+                .upload_stream(&content_key, parts, total, &tagging, None)
+                .await?;
+        }
+
+        Ok(1)
+    }
+
+    /// Asynchronously downloads the content of the object from the S3 Bucket, or None if there is no object under the key
+    ///
+    /// # Arguments
+    ///
+    /// * content_key: String - The S3 Bucket prefix key of the object, (e.g.: "manifests/order/2019-03-30.ndjson").</br>
+    pub async fn download_async(
+        &self,
+        content_key: String,
+    ) -> Result<Option<Vec<u8>>, DaaSStorageError> {
+        let s3_client = self.client()?;
+        let rslt = self
+            .retry("get object", || {
+                s3_client.get_object(GetObjectRequest {
+                    bucket: self.bucket.clone(),
+                    key: content_key.clone(),
+                    ..Default::default()
+                })
+            })
+            .await;
+
+        let body = match rslt {
+            Ok(obj) => obj.body,
+            Err(RusotoError::Service(GetObjectError::NoSuchKey(_))) => return Ok(None),
+            Err(RusotoError::Unknown(ref rspns)) if rspns.status.as_u16() == 404 => {
+                return Ok(None)
+            }
+            Err(err) => {
+                error!(
+                    "Could not download {} from S3 Bucket {}. Error: {}",
+                    content_key, self.bucket, err
+                );
+                return Err(DaaSStorageError::RetrieveError);
+            }
+        };
+
+        let mut content = Vec::new();
+        if let Some(stream) = body {
+            if let Err(err) = stream.into_async_read().read_to_end(&mut content).await {
+                error!(
+                    "Could not read {} from S3 Bucket {}. Error: {}",
+                    content_key, self.bucket, err
+                );
+                return Err(DaaSStorageError::RetrieveError);
+            }
+        }
+
+        Ok(Some(content))
+    }
+
+    /// Asynchronously uploads the DaaS document to the S3 Bucket (and its replicas) only if there is no object under the key yet,
+    /// so that uploading the same DaaS document again (e.g.: when a message is redelivered) never replaces the existing object.
+    /// The object is created with a conditional request, (`If-None-Match: *`). DaaS documents larger than a part (5 MiB) are
+    /// uploaded in parts, and the upload is completed with the same condition, so the parts are discarded if a concurrent
+    /// upload of the key completed first.
+    ///
+    /// # Arguments
+    ///
+    /// * content_key: String - The S3 Bucket prefix key to use for the document, (e.g.: "genesis/order~clothing~iStore~5000~0.daas").</br>
+    /// * doc: &DaaSDoc - The DaaS document to upload.</br>
+    pub async fn create_daas_doc_async(
+        &self,
+        content_key: String,
+        doc: &DaaSDoc,
+    ) -> Result<CreateOutcome, DaaSStorageError> {
+        let tagging = Some(S3BucketMngr::make_tagging(doc));
+        let total = doc.to_chunks(MULTIPART_PART_SIZE).serialized_len();
+
+        let outcome = match total > MULTIPART_PART_SIZE {
+            true => {
+                let parts = doc.to_chunks(MULTIPART_PART_SIZE);
+                let rslt = self
+                    .upload_multipart(
+                        &self.client()?,
+                        &content_key,
+                        parts,
+                        total,
+                        &tagging,
+                        None,
+                        true,
+                    )
+                    .await;
+                match rslt {
+                    Ok(true) => CreateOutcome::Created,
+                    Ok(false) => CreateOutcome::AlreadyExists,
+                    Err(err) => {
+                        error!(
+                            "Could not upload {} to S3 Bucket {}. Error: {}",
+                            content_key, self.bucket, err
+                        );
+                        return Err(DaaSStorageError::UpsertError);
+                    }
+                }
+            }
+            false => {
+                self.put_if_absent(&content_key, doc.to_bytes(), &tagging)
+                    .await?
+            }
+        };
+
+        match outcome {
+            CreateOutcome::Created => {
+                for replica in self.replicas.iter() {
+                    let parts = doc.to_chunks(MULTIPART_PART_SIZE);
+                    replica
+                        .upload_stream(&content_key, parts, total, &tagging, None)
+                        .await?;
+                }
+            }
+            CreateOutcome::AlreadyExists => {
+                info!(
+                    "Object {} already exists in S3 Bucket {}.",
+                    content_key, self.bucket
+                );
+            }
+        }
+
+        Ok(outcome)
+    }
+
+    // Puts the object only if there is no object under the key, (S3 answers 412 Precondition Failed if there is)
+    async fn put_if_absent(
+        &self,
+        content_key: &str,
+        content: Vec<u8>,
+        tagging: &Option<String>,
+    ) -> Result<CreateOutcome, DaaSStorageError> {
+        match self.put_if(content_key, content, tagging, &None).await? {
+            true => Ok(CreateOutcome::Created),
+            false => Ok(CreateOutcome::AlreadyExists),
+        }
+    }
+
+    // Puts the object only if the object under the key has the ETag, or there is no object if the ETag is None.
+    // Returns false if S3 answers 412 Precondition Failed. Since the response of an attempt that succeeded can be
+    // lost, a 412 after a retry is only reported as a conflict if the object under the key isn't the content that
+    // was sent, (compared by the content hash in its metadata).
+    async fn put_if(
+        &self,
+        content_key: &str,
+        content: Vec<u8>,
+        tagging: &Option<String>,
+        if_match: &Option<String>,
+    ) -> Result<bool, DaaSStorageError> {
+        let rslt = match self
+            .send_conditional::<PutObjectError, _>("put object conditionally", || {
+                self.make_conditional_put_request(
+                    content_key,
+                    content.clone(),
+                    tagging.clone(),
+                    if_match,
+                )
+            })
+            .await
+        {
+            Ok(Precondition::Met) => Ok(true),
+            Ok(Precondition::Failed { retried: false }) => Ok(false),
+            Ok(Precondition::Failed { retried: true }) => {
+                self.head_content_hash(content_key).await.map(|hash| {
+                    let own = hash == Some(content_hash(&content));
+                    if own {
+                        info!(
+                            "The retried upload of {} to S3 Bucket {} had already succeeded.",
+                            content_key, self.bucket
+                        );
+                    }
+                    own
+                })
+            }
+            Err(err) => Err(err),
+        };
+
+        rslt.map_err(|err| {
+            error!(
+                "Could not upload {} to S3 Bucket {}. Error: {}",
+                content_key, self.bucket, err
+            );
+            DaaSStorageError::UpsertError
+        })
+    }
+
+    // Returns the content hash in the metadata of the object, (None if the object or the hash doesn't exist)
+    async fn head_content_hash(&self, content_key: &str) -> Result<Option<String>, String> {
+        let s3_client = self
+            .client()
+            .map_err(|_err| "Could not create the S3 client.".to_string())?;
+        let rslt = self
+            .retry("head object", || {
+                s3_client.head_object(HeadObjectRequest {
+                    bucket: self.bucket.clone(),
+                    key: content_key.to_string(),
+                    ..Default::default()
+                })
+            })
+            .await;
+
+        match rslt {
+            Ok(head) => Ok(head
+                .metadata
+                .and_then(|m| m.get(CONTENT_HASH_META).cloned())),
+            Err(RusotoError::Unknown(rspns)) if rspns.status.as_u16() == 404 => Ok(None),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+
+    // Signs and sends the conditional request that is built for each attempt
+    async fn send_conditional<E, F>(
+        &self,
+        action: &str,
+        make_request: F,
+    ) -> Result<Precondition, String>
+    where
+        F: Fn() -> SignedRequest,
+        E: std::error::Error + 'static,
+    {
+        let credentials = self
+            .credentials()
+            .await
+            .map_err(|err| format!("Could not get the AWS credentials. Error: {}", err))?;
+        let http = HttpClient::new()
+            .map_err(|err| format!("Could not create the HTTP client. Error: {}", err))?;
+
+        let mut attempts = 0;
+        let rslt = self
+            .retry(action, || {
+                let mut request = make_request();
+                request.sign(&credentials);
+                let http = &http;
+                attempts += 1;
+
+                async move {
+                    let mut response = http
+                        .dispatch(request, None)
+                        .await
+                        .map_err(RusotoError::<E>::HttpDispatch)?;
+
+                    match response.status.as_u16() {
+                        412 => Ok(false),
+                        _ if response.status.is_success() => Ok(true),
+                        _ => Err(RusotoError::Unknown(
+                            response.buffer().await.map_err(RusotoError::HttpDispatch)?,
+                        )),
+                    }
+                }
+            })
+            .await
+            .map_err(|err| err.to_string())?;
+
+        Ok(match rslt {
+            true => Precondition::Met,
+            false => Precondition::Failed {
+                retried: attempts > 1,
+            },
+        })
+    }
+
+    // Builds the (unsigned) request to put the object only if the object under the key has the ETag, (or there is no
+    // object if the ETag is None) applying the upload options
+    fn make_conditional_put_request(
+        &self,
+        content_key: &str,
+        content: Vec<u8>,
+        tagging: Option<String>,
+        if_match: &Option<String>,
+    ) -> SignedRequest {
+        let put = self.make_put_request(content_key.to_string(), Vec::new().into(), tagging);
+        let mut request = SignedRequest::new(
+            "PUT",
+            "s3",
+            &self.region,
+            &format!("/{}/{}", put.bucket, put.key),
+        );
+
+        let (name, value) = precondition_header(if_match);
+        request.add_header(name, &value);
+        // the content hash identifies the content of the object when the response of a retried upload was lost
+        request.add_header(
+            format!("x-amz-meta-{}", CONTENT_HASH_META),
+            &content_hash(&content),
+        );
+        request.add_optional_header("x-amz-acl", put.acl.as_ref());
+        request.add_optional_header(
+            "x-amz-server-side-encryption",
+            put.server_side_encryption.as_ref(),
+        );
+        request.add_optional_header(
+            "x-amz-server-side-encryption-aws-kms-key-id",
+            put.ssekms_key_id.as_ref(),
+        );
+        request.add_optional_header("x-amz-storage-class", put.storage_class.as_ref());
+        request.add_optional_header("x-amz-object-lock-mode", put.object_lock_mode.as_ref());
+        request.add_optional_header(
+            "x-amz-object-lock-retain-until-date",
+            put.object_lock_retain_until_date.as_ref(),
+        );
+        request.add_optional_header(
+            "x-amz-object-lock-legal-hold",
+            put.object_lock_legal_hold_status.as_ref(),
+        );
+        request.add_optional_header("x-amz-tagging", put.tagging.as_ref());
+        request.set_payload(Some(content));
+        request
+    }
+
+    // Uploads the content to the S3 Bucket as a single object or in parts
+    async fn upload_bytes(
+        &self,
+        content_key: &str,
+        content: &[u8],
+        tagging: &Option<String>,
+        progress: Option<&S3ProgressFn>,
+    ) -> Result<(), DaaSStorageError> {
+        let parts = content.chunks(MULTIPART_PART_SIZE).map(|c| c.to_vec());
+
+        self.upload_stream(content_key, parts, content.len(), tagging, progress)
+            .await
+    }
+
+    // Uploads the parts of the content as they are produced, so that only a part is held in memory at a time
+    async fn upload_stream<I: Iterator<Item = Vec<u8>>>(
+        &self,
+        content_key: &str,
+        mut parts: I,
+        total: usize,
+        tagging: &Option<String>,
+        progress: Option<&S3ProgressFn>,
+    ) -> Result<(), DaaSStorageError> {
+        let s3_client = self.client()?;
+
+        let rslt = if total > MULTIPART_PART_SIZE {
+            self.upload_multipart(
+                &s3_client,
+                content_key,
+                parts,
+                total,
+                tagging,
+                progress,
+                false,
+            )
+            .await
+            .map(|_| ())
+        } else {
+            let content = parts.next().unwrap_or_default();
+            self.retry("put object", || {
+                s3_client.put_object(self.make_put_request(
+                    content_key.to_string(),
+                    content.clone().into(),
+                    tagging.clone(),
+                ))
+            })
+            .await
+            .map(|_| {
+                if let Some(report) = progress {
+                    report(content.len(), content.len());
+                }
+            })
+            .map_err(|err| err.to_string())
+        };
+
+        match rslt {
+            Ok(_) => Ok(()),
+            Err(err) => {
+                error!(
+                    "Could not upload {} to S3 Bucket {}. Error: {}",
+                    content_key, self.bucket, err
+                );
+                Err(DaaSStorageError::UpsertError)
+            }
+        }
+    }
+
+    // Uploads the parts and completes the upload, only if there is no object under the key when `if_absent` is set.
+    // Returns false if the upload wasn't completed because there is an object, (412 Precondition Failed).
+    #[allow(clippy::too_many_arguments)]
+    async fn upload_multipart<I: Iterator<Item = Vec<u8>>>(
+        &self,
+        s3_client: &S3Client,
+        content_key: &str,
+        parts: I,
+        total: usize,
+        tagging: &Option<String>,
+        progress: Option<&S3ProgressFn>,
+        if_absent: bool,
+    ) -> Result<bool, String> {
+        let upload_id = self
+            .retry("create multipart upload", || {
+                s3_client.create_multipart_upload(
+                    self.make_multipart_request(content_key.to_string(), tagging.clone()),
+                )
+            })
+            .await
+            .map_err(|err| err.to_string())?
+            .upload_id
+            .ok_or_else(|| "No upload id was returned.".to_string())?;
+
+        let rslt = match self
+            .upload_parts(s3_client, content_key, &upload_id, parts, total, progress)
+            .await
+        {
+            Ok(completed) if if_absent => self
+                .send_conditional::<CompleteMultipartUploadError, _>(
+                    "complete multipart upload if absent",
+                    || self.make_complete_if_absent_request(content_key, &upload_id, &completed),
+                )
+                .await
+                .map(|p| p == Precondition::Met),
+            Ok(completed) => self
+                .retry("complete multipart upload", || {
+                    s3_client.complete_multipart_upload(CompleteMultipartUploadRequest {
+                        bucket: self.bucket.clone(),
+                        key: content_key.to_string(),
+                        upload_id: upload_id.clone(),
+                        multipart_upload: Some(CompletedMultipartUpload {
+                            parts: Some(completed.clone()),
+                        }),
+                        ..Default::default()
+                    })
+                })
+                .await
+                .map(|_| true)
+                .map_err(|err| err.to_string()),
+            Err(err) => Err(err),
+        };
+
+        if rslt != Ok(true) {
+            // don't leave the uploaded parts behind (they are charged for)
+            let abort = AbortMultipartUploadRequest {
+                bucket: self.bucket.clone(),
+                key: content_key.to_string(),
+                upload_id,
+                ..Default::default()
+            };
+            if let Err(err) = s3_client.abort_multipart_upload(abort).await {
+                warn!(
+                    "Could not abort the multipart upload of {}. Error: {}",
+                    content_key, err
+                );
+            }
+        }
+
+        rslt
+    }
+
+    async fn upload_parts<I: Iterator<Item = Vec<u8>>>(
+        &self,
+        s3_client: &S3Client,
+        content_key: &str,
+        upload_id: &str,
+        parts: I,
+        total: usize,
+        progress: Option<&S3ProgressFn>,
+    ) -> Result<Vec<CompletedPart>, String> {
+        let mut completed = Vec::new();
+        let mut sent = 0;
+
+        for (idx, chunk) in parts.enumerate() {
+            let part_number = idx as i64 + 1;
+            let part = self
+                .retry("upload part", || {
+                    s3_client.upload_part(UploadPartRequest {
+                        bucket: self.bucket.clone(),
+                        key: content_key.to_string(),
+                        upload_id: upload_id.to_string(),
+                        part_number,
+                        content_length: Some(chunk.len() as i64),
+                        body: Some(chunk.clone().into()),
+                        ..Default::default()
+                    })
+                })
+                .await
+                .map_err(|err| err.to_string())?;
+
+            completed.push(CompletedPart {
+                e_tag: part.e_tag,
+                part_number: Some(part_number),
+            });
+
+            sent += chunk.len();
+            if let Some(report) = progress {
+                report(sent, total);
+            }
+        }
+
+        Ok(completed)
+    }
+
+    // Builds the (unsigned) request to complete the multipart upload only if there is no object under the key,
+    // (If-None-Match: *)
+    fn make_complete_if_absent_request(
+        &self,
+        content_key: &str,
+        upload_id: &str,
+        completed: &[CompletedPart],
+    ) -> SignedRequest {
+        let mut request = SignedRequest::new(
+            "POST",
+            "s3",
+            &self.region,
+            &format!("/{}/{}", self.bucket, content_key),
+        );
+        let mut body = String::from("<CompleteMultipartUpload>");
+        for part in completed.iter() {
+            body.push_str(&format!(
+                "<Part><PartNumber>{}</PartNumber><ETag>{}</ETag></Part>",
+                part.part_number.unwrap_or_default(),
+                part.e_tag.clone().unwrap_or_default()
+            ));
+        }
+        body.push_str("</CompleteMultipartUpload>");
+
+        request.add_param("uploadId", upload_id);
+        request.add_header("If-None-Match", "*");
+        request.set_payload(Some(body.into_bytes()));
+        request
+    }
+
+    // Builds the request to start a multipart upload applying the upload options
+    fn make_multipart_request(
+        &self,
+        content_key: String,
+        tagging: Option<String>,
+    ) -> CreateMultipartUploadRequest {
+        let put = self.make_put_request(content_key, Vec::new().into(), tagging);
+
+        CreateMultipartUploadRequest {
+            bucket: put.bucket,
+            key: put.key,
+            acl: put.acl,
+            server_side_encryption: put.server_side_encryption,
+            ssekms_key_id: put.ssekms_key_id,
+            storage_class: put.storage_class,
+            object_lock_mode: put.object_lock_mode,
+            object_lock_retain_until_date: put.object_lock_retain_until_date,
+            object_lock_legal_hold_status: put.object_lock_legal_hold_status,
+            tagging: put.tagging,
+            ..Default::default()
+        }
+    }
+
+    // Performs the request, retrying transient failures with an exponential backoff
+    async fn retry<T, E, F, Fut>(&self, action: &str, mut request: F) -> Result<T, RusotoError<E>>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, RusotoError<E>>>,
+        E: std::error::Error + 'static,
+    {
+        let mut attempt = 0;
+
+        loop {
+            match request().await {
+                Ok(t) => return Ok(t),
+                Err(err) if attempt < self.retry.max_retries && is_retryable(&err) => {
+                    let delay = self.retry.delay(attempt);
+                    warn!(
+                        "Failed to {} (attempt {}). Retrying in {:?}. Error: {}",
+                        action,
+                        attempt + 1,
+                        delay,
+                        err
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    // Reads the content stream into memory so that the requests can be retried
+    fn read_content(
+        content_key: &str,
+        content: StreamingBody,
+    ) -> Result<Vec<u8>, DaaSStorageError> {
+        let mut bytes = Vec::new();
+
+        match content.into_blocking_read().read_to_end(&mut bytes) {
+            Ok(_) => Ok(bytes),
+            Err(err) => {
+                error!(
+                    "Could not read the content of {}. Error: {}",
+                    content_key, err
+                );
+                Err(DaaSStorageError::UpsertError)
+            }
+        }
+    }
+}
+
+// Determines if the failed request is worth retrying (network failures, throttling and server errors)
+fn is_retryable<E>(err: &RusotoError<E>) -> bool {
+    match err {
+        RusotoError::HttpDispatch(_) => true,
+        RusotoError::Unknown(rspns) => {
+            rspns.status.is_server_error() || rspns.status.as_u16() == 429
+        }
+        _ => false,
+    }
+}
+
+// Percent encodes a value for use in the S3 object tagging
+fn url_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+// Returns the hex encoded SHA-256 of the content
+fn content_hash(content: &[u8]) -> String {
+    Sha256::digest(content)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+// Returns the header of the conditional request for the expected ETag, (None = must not exist)
+fn precondition_header(if_match: &Option<String>) -> (&'static str, String) {
+    match if_match {
+        Some(etag) => ("If-Match", format!("\"{}\"", etag.trim_matches('"'))),
+        None => ("If-None-Match", "*".to_string()),
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use pbd::dtc::Tracker;
+    use rusoto_s3::HeadObjectError;
 
     #[test]
     fn test_from_arn() {
@@ -196,6 +1611,449 @@ mod tests {
         assert_eq!(bckt.region, Region::UsEast1);
     }
 
+    #[test]
+    fn test_precondition_header() {
+        assert_eq!(
+            precondition_header(&None),
+            ("If-None-Match", "*".to_string())
+        );
+        assert_eq!(
+            precondition_header(&Some("abc".to_string())),
+            ("If-Match", "\"abc\"".to_string())
+        );
+        assert_eq!(
+            precondition_header(&Some("\"abc\"".to_string())),
+            ("If-Match", "\"abc\"".to_string())
+        );
+    }
+
+    #[test]
+    fn test_make_put_request_options() {
+        let bckt = S3BucketMngr::new(Region::UsEast1, "daas-test-bucket".to_string()).with_options(
+            S3UploadOptions {
+                acl: None,
+                encryption: S3Encryption::Kms(Some("my-key".to_string())),
+                storage_class: Some("GLACIER_IR".to_string()),
+                object_lock: Some(S3ObjectLock {
+                    mode: "COMPLIANCE".to_string(),
+                    retain_until: "2030-01-01T00:00:00Z".to_string(),
+                    legal_hold: true,
+                }),
+            },
+        );
+        let req = bckt.make_put_request(
+            "tmp/file.txt".to_string(),
+            String::from("data").into_bytes().into(),
+            Some("a=b".to_string()),
+        );
+
+        assert_eq!(req.acl, None);
+        assert_eq!(req.server_side_encryption, Some("aws:kms".to_string()));
+        assert_eq!(req.ssekms_key_id, Some("my-key".to_string()));
+        assert_eq!(req.storage_class, Some("GLACIER_IR".to_string()));
+        assert_eq!(req.object_lock_mode, Some("COMPLIANCE".to_string()));
+        assert_eq!(req.object_lock_legal_hold_status, Some("ON".to_string()));
+        assert_eq!(req.tagging, Some("a=b".to_string()));
+    }
+
+    #[test]
+    fn test_make_put_request_default() {
+        let bckt = S3BucketMngr::new(Region::UsEast1, "daas-test-bucket".to_string());
+        let req = bckt.make_put_request(
+            "tmp/file.txt".to_string(),
+            String::from("data").into_bytes().into(),
+            Some("".to_string()),
+        );
+
+        assert_eq!(req.acl, Some("private".to_string()));
+        assert_eq!(req.server_side_encryption, None);
+        assert_eq!(req.object_lock_mode, None);
+        assert_eq!(req.tagging, None);
+    }
+
+    #[test]
+    fn test_make_conditional_put_request() {
+        let bckt = S3BucketMngr::new(Region::UsEast1, "daas-test-bucket".to_string()).with_options(
+            S3UploadOptions {
+                acl: None,
+                encryption: S3Encryption::Kms(Some("my-key".to_string())),
+                storage_class: None,
+                object_lock: None,
+            },
+        );
+        let req = bckt.make_conditional_put_request(
+            "tmp/file.txt",
+            String::from("data").into_bytes(),
+            Some("a=b".to_string()),
+            &None,
+        );
+        let header = |key: &str| {
+            req.headers()
+                .get(key)
+                .map(|vals| String::from_utf8(vals[0].clone()).unwrap())
+        };
+
+        assert_eq!(req.method, "PUT");
+        assert_eq!(req.path, "/daas-test-bucket/tmp/file.txt");
+        assert_eq!(header("if-none-match"), Some("*".to_string()));
+        assert_eq!(
+            header("x-amz-server-side-encryption"),
+            Some("aws:kms".to_string())
+        );
+        assert_eq!(header("x-amz-tagging"), Some("a=b".to_string()));
+        assert_eq!(
+            header("x-amz-meta-daas-content-sha256"),
+            Some("3a6eb0790f39ac87c94f3856b2dd2c5d110e6811602261a9a923d3bb23adc8b7".to_string())
+        );
+        assert_eq!(header("x-amz-acl"), None);
+        assert_eq!(header("x-amz-storage-class"), None);
+
+        let req = bckt.make_conditional_put_request(
+            "tmp/file.txt",
+            String::from("data").into_bytes(),
+            None,
+            &Some("abc".to_string()),
+        );
+        assert_eq!(
+            req.headers()
+                .get("if-match")
+                .map(|vals| String::from_utf8(vals[0].clone()).unwrap()),
+            Some("\"abc\"".to_string())
+        );
+        assert!(req.headers().get("if-none-match").is_none());
+    }
+
+    #[test]
+    fn test_make_complete_if_absent_request() {
+        let bckt = S3BucketMngr::new(Region::UsEast1, "daas-test-bucket".to_string());
+        let parts = vec![
+            CompletedPart {
+                e_tag: Some("\"a1\"".to_string()),
+                part_number: Some(1),
+            },
+            CompletedPart {
+                e_tag: Some("\"b2\"".to_string()),
+                part_number: Some(2),
+            },
+        ];
+        let req = bckt.make_complete_if_absent_request("tmp/file.txt", "upload-1", &parts);
+
+        assert_eq!(req.method, "POST");
+        assert_eq!(req.path, "/daas-test-bucket/tmp/file.txt");
+        assert_eq!(
+            req.params.get("uploadId"),
+            Some(&Some("upload-1".to_string()))
+        );
+        assert_eq!(
+            req.headers()
+                .get("if-none-match")
+                .map(|vals| vals[0].clone()),
+            Some(b"*".to_vec())
+        );
+    }
+
+    #[test]
+    fn test_make_tagging() {
+        let dtc = Tracker::new(DaaSDoc::make_id(
+            "order".to_string(),
+            "clothing".to_string(),
+            "iStore".to_string(),
+            15000,
+        ));
+        let mut doc = DaaSDoc::new(
+            "iStore".to_string(),
+            15000,
+            "order".to_string(),
+            "clothing".to_string(),
+            "istore_app".to_string(),
+            Vec::new(),
+            dtc,
+            Vec::new(),
+        );
+        doc.add_tag("priority".to_string());
+        doc.add_meta("content-type".to_string(), "application/json".to_string());
+
+        assert_eq!(
+            S3BucketMngr::make_tagging(&doc),
+            "priority=&content-type=application%2Fjson".to_string()
+        );
+
+        for i in 0..20 {
+            doc.add_tag(format!("tag{}", i));
+        }
+        assert_eq!(
+            S3BucketMngr::make_tagging(&doc).split('&').count(),
+            MAX_TAGS
+        );
+    }
+
+    #[test]
+    fn test_with_endpoint() {
+        let bckt = S3BucketMngr::new(Region::EuWest1, "daas-test-bucket".to_string())
+            .with_endpoint("http://localhost:4566".to_string());
+
+        assert_eq!(
+            bckt.region,
+            Region::Custom {
+                name: "eu-west-1".to_string(),
+                endpoint: "http://localhost:4566".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_credentials_debug_hides_secret() {
+        let bckt = S3BucketMngr::new(Region::UsEast1, "daas-test-bucket".to_string())
+            .with_credentials(S3Credentials::Static {
+                access_key: "my-access-key".to_string(),
+                secret_key: "my-secret-key".to_string(),
+                session_token: None,
+            });
+
+        assert!(bckt.client().is_ok());
+        assert!(!format!("{:?}", bckt).contains("my-secret-key"));
+    }
+
+    #[test]
+    fn test_with_replica() {
+        let bckt = S3BucketMngr::new(Region::UsEast1, "daas-test-bucket".to_string()).with_replica(
+            S3BucketMngr::new(Region::UsWest2, "daas-test-bucket-west".to_string()),
+        );
+
+        assert_eq!(bckt.replicas[0].region, Region::UsWest2);
+        assert_eq!(bckt.replicas[0].bucket, "daas-test-bucket-west".to_string());
+    }
+
+    #[test]
+    fn test_retry_policy_delay() {
+        let policy = S3RetryPolicy::default();
+
+        assert_eq!(policy.delay(0), Duration::from_millis(200));
+        assert_eq!(policy.delay(2), Duration::from_millis(800));
+        assert_eq!(policy.delay(40), Duration::from_millis(10_000));
+    }
+
+    #[test]
+    fn test_is_retryable() {
+        let dispatch: RusotoError<HeadObjectError> = RusotoError::HttpDispatch(
+            rusoto_core::request::HttpDispatchError::new("timeout".to_string()),
+        );
+        let not_found: RusotoError<HeadObjectError> =
+            RusotoError::Service(HeadObjectError::NoSuchKey("key".to_string()));
+
+        assert!(is_retryable(&dispatch));
+        assert!(!is_retryable(&not_found));
+    }
+
+    #[test]
+    fn test_retry() {
+        let bckt = S3BucketMngr::new(Region::UsEast1, "daas-test-bucket".to_string()).with_retry(
+            S3RetryPolicy {
+                max_retries: 2,
+                base_delay_ms: 1,
+                max_delay_ms: 1,
+            },
+        );
+        let rt = Runtime::new().unwrap();
+
+        // succeeds on the last retry
+        let mut calls = 0;
+        let rslt: Result<i32, RusotoError<HeadObjectError>> =
+            rt.block_on(bckt.retry("test", || {
+                calls += 1;
+                let c = calls;
+                async move {
+                    match c {
+                        3 => Ok(c),
+                        _ => Err(RusotoError::HttpDispatch(
+                            rusoto_core::request::HttpDispatchError::new("timeout".to_string()),
+                        )),
+                    }
+                }
+            }));
+        assert_eq!(rslt.unwrap(), 3);
+
+        // gives up after the maximum number of retries
+        let mut calls = 0;
+        let rslt: Result<i32, RusotoError<HeadObjectError>> =
+            rt.block_on(bckt.retry("test", || {
+                calls += 1;
+                async move {
+                    Err(RusotoError::HttpDispatch(
+                        rusoto_core::request::HttpDispatchError::new("timeout".to_string()),
+                    ))
+                }
+            }));
+        assert!(rslt.is_err());
+        assert_eq!(calls, 3);
+    }
+
+    #[test]
+    fn test_make_list_request() {
+        let bckt = S3BucketMngr::new(Region::UsEast1, "daas-test-bucket".to_string());
+        let req = bckt.make_list_request(
+            Some("genesis/".to_string()),
+            Some("token".to_string()),
+            5000,
+        );
+
+        assert_eq!(req.prefix, Some("genesis/".to_string()));
+        assert_eq!(req.continuation_token, Some("token".to_string()));
+        assert_eq!(req.max_keys, Some(1000));
+        assert_eq!(bckt.make_list_request(None, None, 0).max_keys, Some(1));
+    }
+
+    #[test]
+    fn test_make_multipart_request() {
+        let bckt = S3BucketMngr::new(Region::UsEast1, "daas-test-bucket".to_string()).with_options(
+            S3UploadOptions {
+                encryption: S3Encryption::S3,
+                ..Default::default()
+            },
+        );
+        let req = bckt.make_multipart_request("tmp/large.bin".to_string(), None);
+
+        assert_eq!(req.key, "tmp/large.bin".to_string());
+        assert_eq!(req.acl, Some("private".to_string()));
+        assert_eq!(req.server_side_encryption, Some("AES256".to_string()));
+    }
+
+    #[test]
+    fn test_read_content() {
+        let content: StreamingBody = String::from("this is a message....").into_bytes().into();
+
+        assert_eq!(
+            S3BucketMngr::read_content("tmp/file.txt", content).unwrap(),
+            String::from("this is a message....").into_bytes()
+        );
+    }
+
+    #[test]
+    fn test_presign() {
+        let bckt = S3BucketMngr::new(Region::UsWest2, "daas-test-bucket".to_string())
+            .with_credentials(S3Credentials::Static {
+                access_key: "my-access-key".to_string(),
+                secret_key: "my-secret-key".to_string(),
+                session_token: None,
+            });
+        let rt = Runtime::new().unwrap();
+
+        let get = rt
+            .block_on(bckt.presign_get("tmp/file.txt".to_string(), Duration::from_secs(60)))
+            .unwrap();
+        assert!(get.contains("s3.us-west-2.amazonaws.com/daas-test-bucket/tmp/file.txt"));
+        assert!(get.contains("X-Amz-Expires=60"));
+
+        let put = rt
+            .block_on(bckt.presign_put("tmp/file.txt".to_string(), Duration::from_secs(60)))
+            .unwrap();
+        assert!(put.contains("X-Amz-Signature"));
+        assert_ne!(get, put);
+
+        assert!(rt
+            .block_on(bckt.presign_get("tmp/file.txt".to_string(), Duration::from_secs(604_801)))
+            .is_err());
+    }
+
+    // Answers the requests with the responses in turn, (one request per connection) and returns the request lines
+    fn serve(responses: Vec<&'static str>) -> (String, std::thread::JoinHandle<Vec<String>>) {
+        use std::io::{BufRead, BufReader, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        let handle = std::thread::spawn(move || {
+            let mut requests = Vec::new();
+            for response in responses {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut length = 0;
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                requests.push(line.trim().to_string());
+                loop {
+                    let mut header = String::new();
+                    reader.read_line(&mut header).unwrap();
+                    if header.trim().is_empty() {
+                        break;
+                    }
+                    if let Some(l) = header.to_lowercase().strip_prefix("content-length:") {
+                        length = l.trim().parse().unwrap();
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                reader
+                    .into_inner()
+                    .write_all(
+                        format!(
+                            "{}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                            response
+                        )
+                        .as_bytes(),
+                    )
+                    .unwrap();
+            }
+            requests
+        });
+
+        (endpoint, handle)
+    }
+
+    fn get_served_bucket(endpoint: String) -> S3BucketMngr {
+        S3BucketMngr::new(Region::UsEast1, "daas-test-bucket".to_string())
+            .with_endpoint(endpoint)
+            .with_credentials(S3Credentials::Static {
+                access_key: "my-access-key".to_string(),
+                secret_key: "my-secret-key".to_string(),
+                session_token: None,
+            })
+            .with_retry(S3RetryPolicy {
+                max_retries: 2,
+                base_delay_ms: 1,
+                max_delay_ms: 1,
+            })
+    }
+
+    #[test]
+    fn test_put_if_retried() {
+        let rt = Runtime::new().unwrap();
+        let content = String::from("data").into_bytes();
+
+        // the response of the upload that succeeded was lost, so the retry is rejected
+        let (endpoint, server) = serve(vec![
+            "HTTP/1.1 500 Internal Server Error",
+            "HTTP/1.1 412 Precondition Failed",
+            "HTTP/1.1 200 OK\r\nx-amz-meta-daas-content-sha256: 3a6eb0790f39ac87c94f3856b2dd2c5d110e6811602261a9a923d3bb23adc8b7",
+        ]);
+        let bckt = get_served_bucket(endpoint);
+        assert!(rt
+            .block_on(bckt.put_if("tmp/file.txt", content.clone(), &None, &None))
+            .unwrap());
+        let requests = server.join().unwrap();
+        assert!(requests[0].starts_with("PUT /daas-test-bucket/tmp/file.txt"));
+        assert!(requests[2].starts_with("HEAD /daas-test-bucket/tmp/file.txt"));
+
+        // another writer's object is a conflict
+        let (endpoint, server) = serve(vec![
+            "HTTP/1.1 500 Internal Server Error",
+            "HTTP/1.1 412 Precondition Failed",
+            "HTTP/1.1 200 OK\r\nx-amz-meta-daas-content-sha256: 0000",
+        ]);
+        let bckt = get_served_bucket(endpoint);
+        assert!(!rt
+            .block_on(bckt.put_if("tmp/file.txt", content.clone(), &None, &None))
+            .unwrap());
+        server.join().unwrap();
+
+        // a 412 without a retry is a conflict, without checking the object
+        let (endpoint, server) = serve(vec!["HTTP/1.1 412 Precondition Failed"]);
+        let bckt = get_served_bucket(endpoint);
+        assert!(!rt
+            .block_on(bckt.put_if("tmp/file.txt", content, &None, &None))
+            .unwrap());
+        assert_eq!(server.join().unwrap().len(), 1);
+    }
+
     #[ignore]
     #[test]
     fn test_upload_file() {
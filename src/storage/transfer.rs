@@ -0,0 +1,274 @@
+//! Bulk export and import of the DaaS documents of a storage device, (e.g.: to migrate from LocalStorage to S3 or
+//! to seed a test environment with production-like data).
+//!
+//! The archive is newline delimited JSON (NDJSON), where each line is a serialized revision of a DaaS document.
+//! The revisions of a DaaS document are exported in order, with their metadata, tags and Data Tracker Chain, so
+//! importing them into an empty storage device recreates the same revisions.
+//!
+//! # Examples
+//!
+//! ```
+//! extern crate daas;
+//!
+//! use daas::storage::DaaSDocStorage;
+//! use daas::storage::memory::InMemoryStorage;
+//! use daas::storage::transfer;
+//! use daas::testing;
+//!
+//! fn main() {
+//!     let source = InMemoryStorage::new();
+//!     let target = InMemoryStorage::new();
+//!     source.upsert_daas_doc(testing::get_default_daas_doc()).unwrap();
+//!
+//!     let mut archive = Vec::new();
+//!     transfer::export(&source, |doc| doc.category == "order", &mut archive).unwrap();
+//!     let report = transfer::import(archive.as_slice(), &target).unwrap();
+//!
+//!     assert_eq!(report.revisions, 1);
+//! }
+//! ```
+
+use super::*;
+use std::collections::BTreeSet;
+use std::io::{BufRead, Write};
+
+/// Represents the outcome of an export or import
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TransferReport {
+    /// The number of DaaS documents that were transferred
+    pub docs: usize,
+    /// The number of revisions that were transferred
+    pub revisions: usize,
+    /// The identifiers of the DaaS documents that couldn't be (completely) transferred
+    pub failed: Vec<String>,
+}
+
+/// Writes all the revisions of the DaaS documents that match the filter to the archive.
+/// The filter is applied to the latest revision of the DaaS documents.
+///
+/// # Arguments
+///
+/// * storage: &S - The storage device to export, (it must support `list_doc_ids()`).</br>
+/// * filter: F - Determines if the DaaS document is exported.</br>
+/// * archive: W - Where the NDJSON is written, (e.g.: a file).</br>
+pub fn export<S, F, W>(
+    storage: &S,
+    filter: F,
+    mut archive: W,
+) -> Result<TransferReport, RetrieveError>
+where
+    S: DaaSDocStorage + ?Sized,
+    F: Fn(&DaaSDoc) -> bool,
+    W: Write,
+{
+    let mut report = TransferReport::default();
+
+    for doc_id in storage.list_doc_ids()? {
+        let latest = match storage.get_doc_by_id(doc_id.clone(), None) {
+            Ok(d) => d,
+            Err(_err) => {
+                report.failed.push(doc_id);
+                continue;
+            }
+        };
+        if !filter(&latest) {
+            continue;
+        }
+
+        // revisions that can't be found, (e.g.: purged) are skipped
+        let revisions: Vec<DaaSDoc> =
+            match latest._rev.as_ref().and_then(|r| r.parse::<usize>().ok()) {
+                Some(n) => (1..n)
+                    .filter_map(|r| {
+                        storage
+                            .get_doc_by_id(doc_id.clone(), Some(r.to_string()))
+                            .ok()
+                    })
+                    .chain(std::iter::once(latest))
+                    .collect(),
+                None => vec![latest],
+            };
+
+        for mut doc in revisions {
+            if let Err(err) = writeln!(archive, "{}", doc.serialize()) {
+                error!("Could not write the archive. Error: {}", err);
+                return Err(RetrieveError);
+            }
+            report.revisions += 1;
+        }
+        report.docs += 1;
+    }
+
+    info!(
+        "Exported {} revisions of {} DaaS documents.",
+        report.revisions, report.docs
+    );
+    Ok(report)
+}
+
+/// Saves the revisions of the archive to the storage device, in the order of the archive.
+/// Each revision is saved on top of the latest revision of the DaaS document in the storage device.
+///
+/// # Arguments
+///
+/// * archive: R - The NDJSON that was written by `export()`.</br>
+/// * storage: &S - The storage device to import into.</br>
+pub fn import<R, S>(archive: R, storage: &S) -> Result<TransferReport, UpsertError>
+where
+    R: BufRead,
+    S: DaaSDocStorage + ?Sized,
+{
+    let mut report = TransferReport::default();
+    let mut docs = BTreeSet::new();
+
+    for (number, line) in archive.lines().enumerate() {
+        let line = match line {
+            Ok(l) if l.trim().is_empty() => continue,
+            Ok(l) => l,
+            Err(err) => {
+                error!("Could not read the archive. Error: {}", err);
+                return Err(UpsertError);
+            }
+        };
+        let mut doc = match DaaSDoc::from_serialized(line.as_bytes()) {
+            Ok(d) => d,
+            Err(err) => {
+                error!(
+                    "Line {} of the archive is not a DaaS document. {}",
+                    number + 1,
+                    err
+                );
+                return Err(UpsertError);
+            }
+        };
+
+        let doc_id = doc._id.clone();
+        doc._rev = storage
+            .get_doc_by_id(doc_id.clone(), None)
+            .ok()
+            .and_then(|d| d._rev);
+
+        match storage.upsert_daas_doc(doc) {
+            Ok(_d) => {
+                report.revisions += 1;
+                docs.insert(doc_id);
+            }
+            Err(err) => {
+                error!("Could not import DaaS document {}. {}", doc_id, err);
+                if !report.failed.contains(&doc_id) {
+                    report.failed.push(doc_id);
+                }
+            }
+        }
+    }
+
+    report.docs = docs.len();
+    info!(
+        "Imported {} revisions of {} DaaS documents.",
+        report.revisions, report.docs
+    );
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::local::LocalStorage;
+    use crate::storage::memory::InMemoryStorage;
+    use crate::testing;
+
+    fn get_source() -> InMemoryStorage {
+        let storage = InMemoryStorage::new();
+        let mut doc = storage
+            .upsert_daas_doc(testing::get_default_daas_doc())
+            .unwrap();
+        doc.add_tag("priority".to_string());
+        storage.upsert_daas_doc(doc).unwrap();
+        storage
+            .upsert_daas_doc(testing::get_daas_doc(
+                "iStore".to_string(),
+                6000,
+                "return".to_string(),
+                "clothing".to_string(),
+            ))
+            .unwrap();
+
+        storage
+    }
+
+    #[test]
+    fn test_export_filtered() {
+        let mut archive = Vec::new();
+        let report = export(&get_source(), |doc| doc.category == "order", &mut archive).unwrap();
+
+        assert_eq!(report.docs, 1);
+        assert_eq!(report.revisions, 2);
+        assert_eq!(String::from_utf8(archive).unwrap().lines().count(), 2);
+    }
+
+    #[test]
+    fn test_export_unsupported() {
+        struct UnlistedStorage {}
+
+        impl DaaSDocStorage for UnlistedStorage {
+            fn upsert_daas_doc(&self, daas_doc: DaaSDoc) -> Result<DaaSDoc, UpsertError> {
+                Ok(daas_doc)
+            }
+
+            fn get_doc_by_id(
+                &self,
+                _doc_id: String,
+                _doc_rev: Option<String>,
+            ) -> Result<DaaSDoc, RetrieveError> {
+                Err(RetrieveError)
+            }
+        }
+
+        assert!(export(&UnlistedStorage {}, |_doc| true, Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_import_preserves_revisions() {
+        let mut archive = Vec::new();
+        export(&get_source(), |_doc| true, &mut archive).unwrap();
+
+        let target = InMemoryStorage::new();
+        let report = import(archive.as_slice(), &target).unwrap();
+        assert_eq!(report.docs, 2);
+        assert_eq!(report.revisions, 3);
+        assert!(report.failed.is_empty());
+
+        let doc = target
+            .get_doc_by_id("order~clothing~iStore~5000".to_string(), None)
+            .unwrap();
+        assert_eq!(doc._rev, Some("2".to_string()));
+        assert!(doc.has_tag("priority".to_string()));
+        testing::assert_tracker_valid(&doc.data_tracker);
+        assert!(!target
+            .get_doc_by_id(doc._id, Some("1".to_string()))
+            .unwrap()
+            .has_tag("priority".to_string()));
+    }
+
+    #[test]
+    fn test_import_into_local_storage() {
+        let mut archive = Vec::new();
+        export(&get_source(), |_doc| true, &mut archive).unwrap();
+
+        let target = LocalStorage::new(format!("./tmp/transfer-{}", rand::random::<u32>()));
+        let report = import(archive.as_slice(), &target).unwrap();
+        assert_eq!(report.revisions, 3);
+
+        let mut exported = Vec::new();
+        let report = export(&target, |_doc| true, &mut exported).unwrap();
+        assert_eq!(report.docs, 2);
+        assert_eq!(report.revisions, 3);
+    }
+
+    #[test]
+    fn test_import_corrupt() {
+        let archive = "{\"not\": \"a DaaS document\"}\n";
+
+        assert!(import(archive.as_bytes(), &InMemoryStorage::new()).is_err());
+    }
+}
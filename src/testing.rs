@@ -0,0 +1,1266 @@
+//! The testing module provides the fixtures and helpers for testing DaaS services, (e.g.: in the unit tests of projects using the SDK)
+//!
+//! # Examples
+//!
+//! ```
+//! extern crate daas;
+//!
+//! use daas::testing;
+//!
+//! fn main() {
+//!     let doc = testing::get_default_daas_doc();
+//!
+//!     assert_eq!(doc._id, "order~clothing~iStore~5000".to_string());
+//!     testing::assert_tracker_valid(&doc.data_tracker);
+//! }
+//! ```
+
+use crate::doc::{DaaSDoc, SourceId};
+#[cfg(feature = "service")]
+use crate::errors::MissingAuthorError;
+#[cfg(feature = "service")]
+use crate::service::extractor::AuthorExtractor;
+#[cfg(feature = "service")]
+use crate::service::listener::{DaaSListener, DaaSListenerService};
+#[cfg(feature = "service")]
+use actix_web::test::TestRequest;
+#[cfg(feature = "service")]
+use actix_web::{web, HttpRequest};
+use pbd::dtc::Tracker;
+#[cfg(feature = "service")]
+use pbd::dtc::DTC_HEADER;
+use pbd::dua::DUA;
+#[cfg(feature = "service")]
+use pbd::dua::DUA_HEADER;
+
+/// The name of the author that is always extracted by the MockAuthor
+pub const MOCK_AUTHOR: &str = "istore_app";
+
+/// Returns the list of Data Usage Agreements used by the fixtures
+pub fn get_dua() -> Vec<DUA> {
+    vec![DUA {
+        agreement_name: "billing".to_string(),
+        location: "www.dua.org/billing.pdf".to_string(),
+        agreed_dtm: 1553988607,
+    }]
+}
+
+/// Returns a new Data Tracker Chain for the DaaS document
+///
+/// # Arguments
+///
+/// * src_name: String - The name of the data source.</br>
+/// * src_uid: usize, String or &str - The unique identifier of the data source.</br>
+/// * cat: String - The category of the data.</br>
+/// * subcat: String - The subcategory of the data.</br>
+pub fn get_dtc<U: Into<SourceId>>(
+    src_name: String,
+    src_uid: U,
+    cat: String,
+    subcat: String,
+) -> Tracker {
+    Tracker::new(DaaSDoc::make_id(cat, subcat, src_name, src_uid))
+}
+
+/// Returns a new DaaS document that has the fixture's usage agreements, author and data
+///
+/// # Arguments
+///
+/// * src_name: String - The name of the data source.</br>
+/// * src_uid: usize, String or &str - The unique identifier of the data source.</br>
+/// * cat: String - The category of the data.</br>
+/// * subcat: String - The subcategory of the data.</br>
+///
+/// #Example
+///
+/// ```
+/// extern crate daas;
+///
+/// use daas::testing;
+///
+/// fn main() {
+///     let doc = testing::get_daas_doc("iStore".to_string(), 6000, "order".to_string(), "clothing".to_string());
+///
+///     assert_eq!(doc._id, "order~clothing~iStore~6000".to_string());
+/// }
+/// ```
+pub fn get_daas_doc<U: Into<SourceId>>(
+    src_name: String,
+    src_uid: U,
+    cat: String,
+    subcat: String,
+) -> DaaSDoc {
+    let src_uid = src_uid.into();
+    let dtc = get_dtc(
+        src_name.clone(),
+        src_uid.clone(),
+        cat.clone(),
+        subcat.clone(),
+    );
+
+    DaaSDoc::new(
+        src_name,
+        src_uid,
+        cat,
+        subcat,
+        MOCK_AUTHOR.to_string(),
+        get_dua(),
+        dtc,
+        String::from(r#"{"status": "new"}"#).as_bytes().to_vec(),
+    )
+}
+
+/// Returns the DaaS document order~clothing~iStore~5000, (see `get_daas_doc()`)
+pub fn get_default_daas_doc() -> DaaSDoc {
+    get_daas_doc(
+        "iStore".to_string(),
+        5000,
+        "order".to_string(),
+        "clothing".to_string(),
+    )
+}
+
+/// Panics if the Data Tracker Chain is empty or has been tampered with
+///
+/// # Arguments
+///
+/// * tracker: &Tracker - The Data Tracker Chain to check.</br>
+pub fn assert_tracker_valid(tracker: &Tracker) {
+    assert!(!tracker.is_empty(), "The Data Tracker Chain is empty.");
+    assert!(
+        tracker.is_valid(),
+        "The Data Tracker Chain is not valid: {}",
+        tracker.serialize()
+    );
+}
+
+/// Panics if the Data Tracker Chain is valid
+///
+/// # Arguments
+///
+/// * tracker: &Tracker - The Data Tracker Chain to check.</br>
+pub fn assert_tracker_invalid(tracker: &Tracker) {
+    assert!(
+        tracker.is_empty() || !tracker.is_valid(),
+        "The Data Tracker Chain is valid: {}",
+        tracker.serialize()
+    );
+}
+
+//
+// The Mock Author Extractor and the DaaSListener requests (requires the `service` feature)
+//
+
+// Use macros to crate our MockAuthor structure
+#[cfg(feature = "service")]
+author_struct!(MockAuthor);
+
+#[cfg(feature = "service")]
+impl AuthorExtractor for MockAuthor {
+    /// Always extracts the MOCK_AUTHOR, regardless of the request
+    fn extract_author(
+        &mut self,
+        _req: &HttpRequest,
+        _payload: &mut actix_web::dev::Payload,
+    ) -> Result<String, MissingAuthorError> {
+        Ok(MOCK_AUTHOR.to_string())
+    }
+
+    // Use macros to write the default functions
+    author_fn_get_name!();
+    author_fn_new!();
+    author_fn_set_name!();
+}
+
+// Use macros to write the implmentation of the FromRequest trait
+#[cfg(feature = "service")]
+author_from_request!(MockAuthor);
+
+/// Configures the health, index and retrieve routes of the DaaSListener using the MockAuthor
+///
+/// #Example
+///
+/// ```
+/// extern crate actix_web;
+/// extern crate daas;
+///
+/// use actix_web::{test, App};
+/// use daas::testing;
+///
+/// #[actix_rt::main]
+/// async fn main() {
+///     let mut app = test::init_service(App::new().configure(testing::configure_listener)).await;
+///     let req = test::TestRequest::get().uri("/health").to_request();
+///     let resp = test::call_service(&mut app, req).await;
+///
+///     assert!(resp.status().is_success());
+/// }
+/// ```
+#[cfg(feature = "service")]
+pub fn configure_listener(cfg: &mut web::ServiceConfig) {
+    cfg.route(
+        &DaaSListener::get_service_health_path(),
+        web::get().to(DaaSListener::health),
+    )
+    .route(
+        &DaaSListener::get_service_status_path(),
+        web::get().to(DaaSListener::status),
+    )
+    .route(
+        &DaaSListener::get_service_duplicates_path(),
+        web::get().to(DaaSListener::duplicates),
+    )
+    .route(
+        &DaaSListener::get_service_path(),
+        web::post().to(DaaSListener::index::<MockAuthor>),
+    )
+    .route(
+        &DaaSListener::get_service_path(),
+        web::get().to(DaaSListener::retrieve::<MockAuthor>),
+    )
+    .route(
+        &DaaSListener::get_service_path(),
+        web::patch().to(DaaSListener::patch::<MockAuthor>),
+    )
+    .route(
+        &DaaSListener::get_service_path(),
+        web::delete().to(DaaSListener::delete::<MockAuthor>),
+    );
+}
+
+/// Returns a POST request for the DaaSListener that has the usage agreement, tracker and author headers of the DaaS document
+///
+/// # Arguments
+///
+/// * doc: &DaaSDoc - The DaaS document to send.</br>
+#[cfg(feature = "service")]
+pub fn get_listener_request(doc: &DaaSDoc) -> TestRequest {
+    get_typed_listener_request(doc, "application/json")
+}
+
+/// Returns a POST request for the DaaSListener like `get_listener_request()`, with the content type of the payload
+///
+/// # Arguments
+///
+/// * doc: &DaaSDoc - The DaaS document to send.</br>
+/// * content_type: &str - The content type of the data of the DaaS document, (e.g.: text/csv).</br>
+#[cfg(feature = "service")]
+pub fn get_typed_listener_request(doc: &DaaSDoc, content_type: &str) -> TestRequest {
+    TestRequest::post()
+        .uri(&format!(
+            "/{}/{}/{}/{}",
+            doc.category, doc.subcategory, doc.source_name, doc.source_uid
+        ))
+        .header("Content-Type", content_type)
+        .header(
+            "Authorization",
+            base64::encode(format!("{}:password", doc.author).as_bytes()),
+        )
+        .header(
+            DUA_HEADER,
+            serde_json::to_string(&doc.data_usage_agreements).unwrap(),
+        )
+        .header(
+            DTC_HEADER,
+            base64::encode(doc.data_tracker.serialize().as_bytes()),
+        )
+        .set_payload(doc.data_obj.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::{AuditLog, InMemoryAuditSink};
+    use crate::doc::{IdStrategy, RetentionPolicy};
+    use crate::eventing::sequence::{source_key, InMemorySequenceStore, SequenceStore, Sequencer};
+    use crate::eventing::sla::{SlaRule, SlaStage, SlaWatchdog};
+    use crate::service::access::{AccessPolicy, PURPOSE_HEADER};
+    use crate::service::convert::{CsvToJson, XmlToJson};
+    use crate::service::extractor::{AuthorIdentity, Base64Author, ChainedAuthor};
+    use crate::service::idempotency::{
+        IdempotencyStore, IDEMPOTENCY_KEY_HEADER, IDEMPOTENT_REPLAYED_HEADER,
+    };
+    use crate::service::listener::{IngestMode, ResponseBody};
+    use crate::service::protobuf::{ProtoDescriptors, ProtobufToJson};
+    use crate::service::registry::{CategoryRegistry, CategoryRule, CATEGORY_OWNER_META};
+    use crate::service::replay::ReplayGuard;
+    #[cfg(feature = "security")]
+    use crate::service::signature::{sign, SignatureVerifier, SIGNATURE_HEADER, TIMESTAMP_HEADER};
+    use crate::service::transform::{StripFields, Transformations, TRANSFORMATIONS_META};
+    use crate::storage::duplicates::DuplicateReport;
+    use crate::storage::local::LocalStorage;
+    use crate::storage::DaaSDocStorage;
+    use actix_web::dev::Payload;
+    use actix_web::{test, App, FromRequest};
+    #[cfg(feature = "security")]
+    use std::time::SystemTime;
+
+    #[test]
+    fn test_get_daas_doc() {
+        let doc = get_default_daas_doc();
+
+        assert_eq!(doc._id, "order~clothing~iStore~5000".to_string());
+        assert_eq!(doc.author, MOCK_AUTHOR.to_string());
+        assert_eq!(doc.data_usage_agreements.len(), 1);
+        assert_tracker_valid(&doc.data_tracker);
+    }
+
+    #[test]
+    fn test_assert_tracker_invalid() {
+        let tracker = get_default_daas_doc().data_tracker;
+        let tampered = Tracker::from_serialized(
+            &tracker
+                .serialize()
+                .replace(r#""timestamp":0"#, r#""timestamp":1"#),
+        )
+        .unwrap();
+
+        assert_tracker_invalid(&tampered);
+        assert!(std::panic::catch_unwind(|| assert_tracker_valid(&tampered)).is_err());
+        assert!(std::panic::catch_unwind(|| assert_tracker_invalid(&tracker)).is_err());
+    }
+
+    #[actix_rt::test]
+    async fn test_mock_author() {
+        let req = test::TestRequest::get().to_http_request();
+        let mut payload = Payload::None;
+        let author = MockAuthor::from_request(&req, &mut payload).await;
+
+        assert_eq!(author.unwrap().get_name(), MOCK_AUTHOR.to_string());
+    }
+
+    #[actix_rt::test]
+    async fn test_listener_request_chained_author() {
+        let mut app = test::init_service(App::new().service(
+            web::resource(DaaSListener::get_service_path()).route(
+                web::post().to(DaaSListener::index::<ChainedAuthor<Base64Author, MockAuthor>>),
+            ),
+        ))
+        .await;
+        let doc = get_daas_doc(
+            "iStore".to_string(),
+            50000 + rand::random::<u16>() as usize,
+            "order".to_string(),
+            "clothing".to_string(),
+        );
+        let resp = test::call_service(&mut app, get_listener_request(&doc).to_request()).await;
+
+        assert!(resp.status().is_success());
+    }
+
+    #[actix_rt::test]
+    async fn test_listener_request() {
+        let mut app = test::init_service(App::new().configure(configure_listener)).await;
+        let doc = get_daas_doc(
+            "iStore".to_string(),
+            5500,
+            "order".to_string(),
+            "clothing".to_string(),
+        );
+        let resp = test::call_service(&mut app, get_listener_request(&doc).to_request()).await;
+
+        assert!(resp.status().is_success());
+    }
+
+    #[actix_rt::test]
+    async fn test_listener_request_illegal_id() {
+        let mut app = test::init_service(App::new().configure(configure_listener)).await;
+        let doc = get_daas_doc(
+            "i~Store".to_string(),
+            5502,
+            "order".to_string(),
+            "clothing".to_string(),
+        );
+        let resp = test::call_service(&mut app, get_listener_request(&doc).to_request()).await;
+
+        assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_rt::test]
+    async fn test_listener_request_text_uid() {
+        let mut app = test::init_service(App::new().configure(configure_listener)).await;
+        let doc = get_daas_doc(
+            "iStore".to_string(),
+            "0b8d3c5e-8f4a-4c1a",
+            "order".to_string(),
+            "clothing".to_string(),
+        );
+        let resp = test::call_service(&mut app, get_listener_request(&doc).to_request()).await;
+
+        assert!(resp.status().is_success());
+    }
+
+    #[actix_rt::test]
+    async fn test_listener_request_expired() {
+        let mut app = test::init_service(
+            App::new()
+                .data(RetentionPolicy::new().with_period("billing".to_string(), 60))
+                .configure(configure_listener),
+        )
+        .await;
+        let doc = get_daas_doc(
+            "iStore".to_string(),
+            5503,
+            "order".to_string(),
+            "clothing".to_string(),
+        );
+        let resp = test::call_service(&mut app, get_listener_request(&doc).to_request()).await;
+
+        assert_eq!(
+            resp.status(),
+            actix_web::http::StatusCode::UNPROCESSABLE_ENTITY
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_listener_request_id_strategy() {
+        let mut app = test::init_service(
+            App::new()
+                .data(IdStrategy::UuidV7)
+                .configure(configure_listener),
+        )
+        .await;
+        let doc = get_daas_doc(
+            "iStore".to_string(),
+            5501,
+            "order".to_string(),
+            "clothing".to_string(),
+        );
+        let resp = test::call_service(&mut app, get_listener_request(&doc).to_request()).await;
+
+        assert!(resp.status().is_success());
+    }
+
+    #[actix_rt::test]
+    async fn test_listener_request_sequencer() {
+        let store = InMemorySequenceStore::new();
+        let mut app = test::init_service(
+            App::new()
+                .data(Sequencer::new(std::sync::Arc::new(store.clone())))
+                .configure(configure_listener),
+        )
+        .await;
+        let doc = get_daas_doc(
+            "iStore".to_string(),
+            5502,
+            "order".to_string(),
+            "clothing".to_string(),
+        );
+
+        for _ in 0..2 {
+            let resp = test::call_service(&mut app, get_listener_request(&doc).to_request()).await;
+            assert!(resp.status().is_success());
+        }
+
+        let source = source_key(&doc.source_name, &doc.source_uid);
+        assert_eq!(store.current(&source).unwrap(), Some(2));
+    }
+
+    // A sequence store that can't allocate the numbers, (e.g.: its file is read-only)
+    struct BrokenSequenceStore;
+
+    impl SequenceStore for BrokenSequenceStore {
+        fn next(&self, _source: &str) -> Result<u64, crate::errors::SequenceError> {
+            Err(crate::errors::SequenceError)
+        }
+
+        fn current(&self, _source: &str) -> Result<Option<u64>, crate::errors::SequenceError> {
+            Ok(None)
+        }
+
+        fn release(
+            &self,
+            _source: &str,
+            _sequence: u64,
+        ) -> Result<bool, crate::errors::SequenceError> {
+            Ok(false)
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_listener_request_sequencer_unavailable() {
+        let mut app = test::init_service(
+            App::new()
+                .data(IdempotencyStore::new())
+                .data(Sequencer::new(std::sync::Arc::new(BrokenSequenceStore)))
+                .configure(configure_listener),
+        )
+        .await;
+        let doc = get_daas_doc(
+            "iStore".to_string(),
+            5504,
+            "order".to_string(),
+            "clothing".to_string(),
+        );
+        let key = format!("key-{}", rand::random::<u32>());
+
+        // the idempotency key is released, so the retry isn't a conflict
+        for _ in 0..2 {
+            let req = get_listener_request(&doc).header(IDEMPOTENCY_KEY_HEADER, key.clone());
+            let resp = test::call_service(&mut app, req.to_request()).await;
+            assert_eq!(
+                resp.status(),
+                actix_web::http::StatusCode::SERVICE_UNAVAILABLE
+            );
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_listener_request_sla_watchdog() {
+        let watchdog = SlaWatchdog::new().with_rule(SlaRule::new(
+            "order.*".to_string(),
+            SlaStage::ProcessedBy("genesis".to_string()),
+            std::time::Duration::from_secs(60),
+        ));
+        let mut app = test::init_service(
+            App::new()
+                .data(watchdog.clone())
+                .configure(configure_listener),
+        )
+        .await;
+        let doc = get_daas_doc(
+            "iStore".to_string(),
+            5503,
+            "order".to_string(),
+            "clothing".to_string(),
+        );
+
+        let resp = test::call_service(&mut app, get_listener_request(&doc).to_request()).await;
+        assert!(resp.status().is_success());
+        assert_eq!(watchdog.pending(), 1);
+
+        // the DaaS document wasn't provisioned in time
+        let breaches = watchdog.evaluate_at(crate::eventing::latency::now_millis() + 60001);
+        assert_eq!(breaches.len(), 1);
+        assert_eq!(breaches[0].doc_id, doc._id);
+    }
+
+    #[actix_rt::test]
+    async fn test_listener_request_idempotency_key() {
+        let mut app = test::init_service(
+            App::new()
+                .data(IdempotencyStore::new())
+                .configure(configure_listener),
+        )
+        .await;
+        let mut doc = get_daas_doc(
+            "iStore".to_string(),
+            50000 + rand::random::<u16>() as usize,
+            "order".to_string(),
+            "clothing".to_string(),
+        );
+        let key = format!("key-{}", rand::random::<u32>());
+
+        let req = get_listener_request(&doc).header(IDEMPOTENCY_KEY_HEADER, key.clone());
+        let resp = test::call_service(&mut app, req.to_request()).await;
+        assert!(resp.status().is_success());
+        assert!(resp.headers().get(IDEMPOTENT_REPLAYED_HEADER).is_none());
+
+        // the retry receives the original response
+        let req = get_listener_request(&doc).header(IDEMPOTENCY_KEY_HEADER, key.clone());
+        let resp = test::call_service(&mut app, req.to_request()).await;
+        assert!(resp.status().is_success());
+        assert_eq!(
+            resp.headers().get(IDEMPOTENT_REPLAYED_HEADER).unwrap(),
+            "true"
+        );
+
+        // the key can't be reused for a different request
+        doc.data_obj = br#"{"status": "shipped"}"#.to_vec();
+        let req = get_listener_request(&doc).header(IDEMPOTENCY_KEY_HEADER, key);
+        let resp = test::call_service(&mut app, req.to_request()).await;
+        assert_eq!(
+            resp.status(),
+            actix_web::http::StatusCode::UNPROCESSABLE_ENTITY
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_listener_request_idempotency_key_rejected() {
+        let mut app = test::init_service(
+            App::new()
+                .data(IdempotencyStore::new())
+                .data(ReplayGuard::new())
+                .configure(configure_listener),
+        )
+        .await;
+        let doc = get_daas_doc(
+            "iStore".to_string(),
+            50000 + rand::random::<u16>() as usize,
+            "order".to_string(),
+            "clothing".to_string(),
+        );
+        let key = format!("key-{}", rand::random::<u32>());
+
+        // the expired Data Tracker Chain is rejected, and the key is released so the retry isn't a conflict
+        for _ in 0..2 {
+            let req = get_listener_request(&doc).header(IDEMPOTENCY_KEY_HEADER, key.clone());
+            let resp = test::call_service(&mut app, req.to_request()).await;
+            assert_eq!(resp.status(), actix_web::http::StatusCode::UNAUTHORIZED);
+        }
+    }
+
+    #[cfg(feature = "security")]
+    fn get_signed_listener_request(doc: &DaaSDoc, timestamp: u64) -> TestRequest {
+        let path = format!(
+            "/{}/{}/{}/{}",
+            doc.category, doc.subcategory, doc.source_name, doc.source_uid
+        );
+
+        get_listener_request(doc)
+            .header(TIMESTAMP_HEADER, timestamp.to_string())
+            .header(
+                SIGNATURE_HEADER,
+                sign(
+                    b"secret",
+                    "POST",
+                    &path,
+                    timestamp,
+                    &base64::encode(doc.data_tracker.serialize().as_bytes()),
+                    &doc.data_obj,
+                ),
+            )
+    }
+
+    #[cfg(feature = "security")]
+    #[actix_rt::test]
+    async fn test_listener_request_signed_idempotency_key() {
+        let mut app = test::init_service(
+            App::new()
+                .data(IdempotencyStore::new())
+                .data(
+                    SignatureVerifier::new().with_secret("iStore".to_string(), b"secret".to_vec()),
+                )
+                .configure(configure_listener),
+        )
+        .await;
+        let doc = get_daas_doc(
+            "iStore".to_string(),
+            50000 + rand::random::<u16>() as usize,
+            "order".to_string(),
+            "clothing".to_string(),
+        );
+        let key = format!("key-{}", rand::random::<u32>());
+        let timestamp = get_unix_now!();
+
+        // the retry of the same signed request receives the original response instead of being rejected as replayed
+        let mut bodies = Vec::new();
+        for _ in 0..2 {
+            let req = get_signed_listener_request(&doc, timestamp)
+                .header(IDEMPOTENCY_KEY_HEADER, key.clone());
+            let resp = test::call_service(&mut app, req.to_request()).await;
+            assert_eq!(resp.status(), actix_web::http::StatusCode::OK);
+            bodies.push(test::read_body(resp).await);
+        }
+        assert_eq!(bodies[0], bodies[1]);
+
+        // without an idempotency key the same signed request is a replay
+        let doc = get_daas_doc(
+            "iStore".to_string(),
+            50000 + rand::random::<u16>() as usize,
+            "order".to_string(),
+            "clothing".to_string(),
+        );
+        let req = get_signed_listener_request(&doc, timestamp);
+        let resp = test::call_service(&mut app, req.to_request()).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::OK);
+        let req = get_signed_listener_request(&doc, timestamp);
+        let resp = test::call_service(&mut app, req.to_request()).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::UNAUTHORIZED);
+    }
+
+    #[cfg(feature = "security")]
+    #[actix_rt::test]
+    async fn test_listener_request_signed_genesis_tracker() {
+        let mut app = test::init_service(
+            App::new()
+                .data(ReplayGuard::new())
+                .data(
+                    SignatureVerifier::new().with_secret("iStore".to_string(), b"secret".to_vec()),
+                )
+                .configure(configure_listener),
+        )
+        .await;
+        let doc = get_daas_doc(
+            "iStore".to_string(),
+            50000 + rand::random::<u16>() as usize,
+            "order".to_string(),
+            "clothing".to_string(),
+        );
+        assert_eq!(doc.data_tracker.len(), 1);
+
+        // the signature covers the Data Tracker Chain that only has its genesis Marker
+        let timestamp = get_unix_now!();
+        let req = get_signed_listener_request(&doc, timestamp);
+        let resp = test::call_service(&mut app, req.to_request()).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::OK);
+        let req = get_signed_listener_request(&doc, timestamp);
+        let resp = test::call_service(&mut app, req.to_request()).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::UNAUTHORIZED);
+
+        // appending a Marker to the signed Data Tracker Chain invalidates the signature
+        let mut tampered = doc.clone();
+        tampered
+            .data_tracker
+            .add(get_unix_now!(), "replayer".to_string(), doc._id.clone());
+        let signed = get_signed_listener_request(&doc, timestamp + 1).to_http_request();
+        let req = get_listener_request(&tampered)
+            .header(TIMESTAMP_HEADER, (timestamp + 1).to_string())
+            .header(
+                SIGNATURE_HEADER,
+                signed.headers().get(SIGNATURE_HEADER).unwrap().clone(),
+            );
+        let resp = test::call_service(&mut app, req.to_request()).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::UNAUTHORIZED);
+
+        // the unsigned request with the genesis Marker only is still rejected by the guard
+        let req = get_listener_request(&doc);
+        let resp = test::call_service(&mut app, req.to_request()).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_rt::test]
+    async fn test_listener_duplicates() {
+        let mut app = test::init_service(
+            App::new()
+                .data(IdempotencyStore::new())
+                .data(DuplicateReport::new())
+                .configure(configure_listener),
+        )
+        .await;
+        let doc = get_daas_doc(
+            "iStore".to_string(),
+            50000 + rand::random::<u16>() as usize,
+            "order".to_string(),
+            "clothing".to_string(),
+        );
+        let key = format!("key-{}", rand::random::<u32>());
+
+        for _ in 0..3 {
+            let req = get_listener_request(&doc).header(IDEMPOTENCY_KEY_HEADER, key.clone());
+            let resp = test::call_service(&mut app, req.to_request()).await;
+            assert!(resp.status().is_success());
+        }
+
+        // the retries are reported
+        let req = TestRequest::get().uri("/duplicates").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert!(resp.status().is_success());
+        let body: serde_json::Value = serde_json::from_slice(&test::read_body(resp).await).unwrap();
+        assert_eq!(body["sources"]["iStore"]["count"], 2);
+        assert_eq!(body["recent"][0]["reference"], key);
+    }
+
+    #[actix_rt::test]
+    async fn test_listener_duplicates_not_reported() {
+        let mut app = test::init_service(App::new().configure(configure_listener)).await;
+        let req = TestRequest::get().uri("/duplicates").to_request();
+        let resp = test::call_service(&mut app, req).await;
+
+        assert_eq!(resp.status(), actix_web::http::StatusCode::NOT_FOUND);
+    }
+
+    #[actix_rt::test]
+    async fn test_listener_request_async() {
+        let mut app = test::init_service(
+            App::new()
+                .data(IngestMode::Async)
+                .configure(configure_listener),
+        )
+        .await;
+        let doc = get_daas_doc(
+            "iStore".to_string(),
+            50000 + rand::random::<u16>() as usize,
+            "order".to_string(),
+            "clothing".to_string(),
+        );
+        let resp = test::call_service(&mut app, get_listener_request(&doc).to_request()).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::ACCEPTED);
+        let status_url = resp
+            .headers()
+            .get("Location")
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_string();
+        assert_eq!(status_url, format!("/status/{}", doc._id));
+
+        let req = TestRequest::get().uri(&status_url).to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert!(resp.status().is_success());
+        let body: serde_json::Value = serde_json::from_slice(&test::read_body(resp).await).unwrap();
+        assert_eq!(body["_id"], serde_json::json!(doc._id));
+        assert!(body["state"] == "stored" || body["state"] == "brokered");
+
+        let req = TestRequest::get()
+            .uri("/status/order~clothing~iStore~missing")
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::NOT_FOUND);
+
+        let req = TestRequest::get().uri("/status/missing").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_rt::test]
+    async fn test_listener_request_summary() {
+        let mut app = test::init_service(
+            App::new()
+                .data(ResponseBody::Summary)
+                .configure(configure_listener),
+        )
+        .await;
+        let doc = get_daas_doc(
+            "iStore".to_string(),
+            50000 + rand::random::<u16>() as usize,
+            "order".to_string(),
+            "clothing".to_string(),
+        );
+        let resp = test::call_service(&mut app, get_listener_request(&doc).to_request()).await;
+        assert!(resp.status().is_success());
+
+        let body: serde_json::Value = serde_json::from_slice(&test::read_body(resp).await).unwrap();
+        assert_eq!(body["status"], serde_json::json!("ok"));
+        assert_eq!(body["_id"], serde_json::json!(doc._id));
+        assert!(body["_rev"].is_string());
+        assert!(std::path::Path::new(body["location"].as_str().unwrap()).is_file());
+    }
+
+    #[actix_rt::test]
+    async fn test_listener_request_author_identity() {
+        let mut app = test::init_service(App::new().configure(configure_listener)).await;
+        let doc = get_daas_doc(
+            "iStore".to_string(),
+            50000 + rand::random::<u16>() as usize,
+            "order".to_string(),
+            "clothing".to_string(),
+        );
+        let resp = test::call_service(&mut app, get_listener_request(&doc).to_request()).await;
+        assert!(resp.status().is_success());
+
+        let saved = LocalStorage::new(LocalStorage::get_local_path())
+            .get_doc_by_id(doc._id.clone(), None)
+            .unwrap();
+        assert_eq!(saved.author, MOCK_AUTHOR.to_string());
+        assert_eq!(
+            AuthorIdentity::from_doc(&saved),
+            Some(AuthorIdentity::new(MOCK_AUTHOR.to_string()))
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_listener_request_transformed() {
+        let mut app = test::init_service(
+            App::new()
+                .data(Transformations::new().with_transformer(
+                    "order".to_string(),
+                    Box::new(StripFields::new(vec!["/status".to_string()])),
+                ))
+                .configure(configure_listener),
+        )
+        .await;
+        let doc = get_daas_doc(
+            "iStore".to_string(),
+            50000 + rand::random::<u16>() as usize,
+            "order".to_string(),
+            "clothing".to_string(),
+        );
+        let resp = test::call_service(&mut app, get_listener_request(&doc).to_request()).await;
+        assert!(resp.status().is_success());
+
+        let saved = LocalStorage::new(LocalStorage::get_local_path())
+            .get_doc_by_id(doc._id.clone(), None)
+            .unwrap();
+        assert_eq!(saved.data_obj, b"{}".to_vec());
+        assert_eq!(
+            saved.meta_data.get(TRANSFORMATIONS_META),
+            Some(&"strip-fields".to_string())
+        );
+        assert_eq!(saved.data_tracker.len(), doc.data_tracker.len() + 1);
+        assert_tracker_valid(&saved.data_tracker);
+
+        let req = get_listener_request(&doc).set_payload("{");
+        let resp = test::call_service(&mut app, req.to_request()).await;
+        assert_eq!(
+            resp.status(),
+            actix_web::http::StatusCode::UNPROCESSABLE_ENTITY
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_listener_request_converted() {
+        let mut app = test::init_service(
+            App::new()
+                .data(
+                    Transformations::new()
+                        .with_transformer("order".to_string(), Box::new(XmlToJson::new()))
+                        .with_transformer("order".to_string(), Box::new(CsvToJson::new())),
+                )
+                .configure(configure_listener),
+        )
+        .await;
+        let storage = LocalStorage::new(LocalStorage::get_local_path());
+        let mut doc = get_daas_doc(
+            "iStore".to_string(),
+            50000 + rand::random::<u16>() as usize,
+            "order".to_string(),
+            "clothing".to_string(),
+        );
+
+        doc.data_obj = b"<order><status>new</status></order>".to_vec();
+        let req = get_typed_listener_request(&doc, "text/xml");
+        let resp = test::call_service(&mut app, req.to_request()).await;
+        assert!(resp.status().is_success());
+        let mut saved = storage.get_doc_by_id(doc._id.clone(), None).unwrap();
+        assert_eq!(saved.data_obj, br#"{"order":{"status":"new"}}"#.to_vec());
+        assert_eq!(
+            saved.get_meta("content-type".to_string()),
+            "application/json".to_string()
+        );
+        assert_eq!(
+            saved.get_meta(TRANSFORMATIONS_META.to_string()),
+            "xml-to-json,csv-to-json".to_string()
+        );
+
+        // the other categories are stored as they are received
+        let mut music = get_daas_doc(
+            "iStore".to_string(),
+            50000 + rand::random::<u16>() as usize,
+            "music".to_string(),
+            "vinyl".to_string(),
+        );
+        music.data_obj = b"id,status\n1,new\n".to_vec();
+        let req = get_typed_listener_request(&music, "text/csv");
+        let resp = test::call_service(&mut app, req.to_request()).await;
+        assert!(resp.status().is_success());
+        let mut saved = storage.get_doc_by_id(music._id.clone(), None).unwrap();
+        assert_eq!(saved.data_obj, music.data_obj);
+        assert_eq!(
+            saved.get_meta("content-type".to_string()),
+            "text/csv".to_string()
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_listener_request_protobuf() {
+        // the descriptor set of: package shop; message Order { string status = 1; }
+        let field = [
+            &[0x0a, 0x06][..],
+            b"status",
+            &[0x18, 0x01, 0x20, 0x01, 0x28, 0x09],
+        ]
+        .concat();
+        let message = [
+            &[0x0a, 0x05][..],
+            b"Order",
+            &[0x12, field.len() as u8],
+            &field,
+        ]
+        .concat();
+        let file = [
+            &[0x12, 0x04][..],
+            b"shop",
+            &[0x22, message.len() as u8],
+            &message,
+        ]
+        .concat();
+        let set = [&[0x0a, file.len() as u8][..], &file].concat();
+        let descriptors = ProtoDescriptors::from_bytes(&set).unwrap();
+
+        let mut app = test::init_service(
+            App::new()
+                .data(Transformations::new().with_transformer(
+                    "order".to_string(),
+                    Box::new(ProtobufToJson::new(descriptors, "shop.Order".to_string())),
+                ))
+                .configure(configure_listener),
+        )
+        .await;
+        let storage = LocalStorage::new(LocalStorage::get_local_path());
+        let mut doc = get_daas_doc(
+            "iStore".to_string(),
+            50000 + rand::random::<u16>() as usize,
+            "order".to_string(),
+            "clothing".to_string(),
+        );
+
+        doc.data_obj = [&[0x0a, 0x03][..], b"new"].concat();
+        let req = get_typed_listener_request(&doc, "application/x-protobuf");
+        let resp = test::call_service(&mut app, req.to_request()).await;
+        assert!(resp.status().is_success());
+        let mut saved = storage.get_doc_by_id(doc._id.clone(), None).unwrap();
+        assert_eq!(saved.data_obj, br#"{"status":"new"}"#.to_vec());
+        assert_eq!(
+            saved.get_meta(TRANSFORMATIONS_META.to_string()),
+            "protobuf-to-json".to_string()
+        );
+
+        // a truncated message is rejected
+        doc.data_obj = [&[0x0a, 0x03][..], b"ne"].concat();
+        let req = get_typed_listener_request(&doc, "application/x-protobuf");
+        let resp = test::call_service(&mut app, req.to_request()).await;
+        assert_eq!(
+            resp.status(),
+            actix_web::http::StatusCode::UNPROCESSABLE_ENTITY
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_listener_request_registry() {
+        let registry = CategoryRegistry::new().with_category(
+            "order".to_string(),
+            CategoryRule::new("sales@istore.com".to_string())
+                .with_subcategory("clothing".to_string())
+                .with_schema(serde_json::json!({"type": "object", "required": ["status"]})),
+        );
+        let mut app =
+            test::init_service(App::new().data(registry).configure(configure_listener)).await;
+        let storage = LocalStorage::new(LocalStorage::get_local_path());
+        let doc = get_daas_doc(
+            "iStore".to_string(),
+            50000 + rand::random::<u16>() as usize,
+            "order".to_string(),
+            "clothing".to_string(),
+        );
+
+        let resp = test::call_service(&mut app, get_listener_request(&doc).to_request()).await;
+        assert!(resp.status().is_success());
+        let mut saved = storage.get_doc_by_id(doc._id.clone(), None).unwrap();
+        assert_eq!(
+            saved.get_meta(CATEGORY_OWNER_META.to_string()),
+            "sales@istore.com".to_string()
+        );
+
+        // a typo in the subcategory
+        let clothes = get_daas_doc(
+            "iStore".to_string(),
+            50000 + rand::random::<u16>() as usize,
+            "order".to_string(),
+            "clothes".to_string(),
+        );
+        let resp = test::call_service(&mut app, get_listener_request(&clothes).to_request()).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+
+        // the payload doesn't match the schema
+        let mut invalid = get_daas_doc(
+            "iStore".to_string(),
+            50000 + rand::random::<u16>() as usize,
+            "order".to_string(),
+            "clothing".to_string(),
+        );
+        invalid.data_obj = br#"{"state": "new"}"#.to_vec();
+        let resp = test::call_service(&mut app, get_listener_request(&invalid).to_request()).await;
+        assert_eq!(
+            resp.status(),
+            actix_web::http::StatusCode::UNPROCESSABLE_ENTITY
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_listener_request_prefer_async() {
+        let mut app = test::init_service(App::new().configure(configure_listener)).await;
+        let doc = get_daas_doc(
+            "iStore".to_string(),
+            50000 + rand::random::<u16>() as usize,
+            "order".to_string(),
+            "clothing".to_string(),
+        );
+        let req = get_listener_request(&doc).header("Prefer", "respond-async");
+        let resp = test::call_service(&mut app, req.to_request()).await;
+
+        assert_eq!(resp.status(), actix_web::http::StatusCode::ACCEPTED);
+    }
+
+    // Saves the DaaS document where the listener retrieves it from
+    fn save_for_retrieval(uid: usize) -> DaaSDoc {
+        let mut doc = get_daas_doc(
+            "iStore".to_string(),
+            uid,
+            "order".to_string(),
+            "clothing".to_string(),
+        );
+        doc.add_meta("content-type".to_string(), "application/json".to_string());
+        LocalStorage::new(LocalStorage::get_local_path())
+            .upsert_daas_doc(doc)
+            .unwrap()
+    }
+
+    #[actix_rt::test]
+    async fn test_listener_retrieve() {
+        let mut app = test::init_service(App::new().configure(configure_listener)).await;
+        let uid = 60000 + rand::random::<u16>() as usize;
+        let doc = save_for_retrieval(uid);
+        let uri = format!("/order/clothing/iStore/{}", uid);
+
+        let req = TestRequest::get()
+            .uri(&uri)
+            .header(PURPOSE_HEADER, "billing")
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert!(resp.status().is_success());
+        assert_eq!(test::read_body(resp).await.to_vec(), doc.data_obj);
+
+        let req = TestRequest::get()
+            .uri(&uri)
+            .header(PURPOSE_HEADER, "marketing")
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::FORBIDDEN);
+        let body = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+        assert!(body.contains(r#""agreement":"marketing""#));
+
+        let req = TestRequest::get().uri(&uri).to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+
+        let req = TestRequest::get()
+            .uri("/order/clothing/iStore/missing")
+            .header(PURPOSE_HEADER, "billing")
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::NOT_FOUND);
+    }
+
+    #[actix_rt::test]
+    async fn test_listener_conditional_requests() {
+        let mut app = test::init_service(App::new().configure(configure_listener)).await;
+        let uid = 60000 + rand::random::<u16>() as usize;
+        let doc = save_for_retrieval(uid);
+        let uri = format!("/order/clothing/iStore/{}", uid);
+        let etag = DaaSListener::make_etag(&doc);
+
+        let req = TestRequest::get()
+            .uri(&uri)
+            .header(PURPOSE_HEADER, "billing")
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.headers().get("ETag").unwrap(), etag.as_str());
+
+        // the cached data is still current
+        let req = TestRequest::get()
+            .uri(&uri)
+            .header(PURPOSE_HEADER, "billing")
+            .header("If-None-Match", format!("W/{}", etag))
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::NOT_MODIFIED);
+
+        // the update is rejected because it isn't based on the latest revision
+        let req = TestRequest::patch()
+            .uri(&uri)
+            .header("If-Match", r#""0""#)
+            .set_payload(r#"{"status": "shipped"}"#)
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(
+            resp.status(),
+            actix_web::http::StatusCode::PRECONDITION_FAILED
+        );
+
+        let req = TestRequest::patch()
+            .uri(&uri)
+            .header("If-Match", etag.clone())
+            .set_payload(r#"{"status": "shipped"}"#)
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert!(resp.status().is_success());
+        let new_etag = resp
+            .headers()
+            .get("ETag")
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_string();
+        assert_ne!(new_etag, etag);
+
+        let req = TestRequest::get()
+            .uri(&uri)
+            .header(PURPOSE_HEADER, "billing")
+            .header("If-None-Match", etag.clone())
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert!(resp.status().is_success());
+        assert_eq!(
+            test::read_body(resp).await.to_vec(),
+            br#"{"status":"shipped"}"#.to_vec()
+        );
+
+        // the deletion is rejected because the requester hasn't seen the update
+        let req = TestRequest::delete()
+            .uri(&uri)
+            .header("If-Match", etag)
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(
+            resp.status(),
+            actix_web::http::StatusCode::PRECONDITION_FAILED
+        );
+
+        let req = TestRequest::delete()
+            .uri(&uri)
+            .header("If-Match", new_etag)
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::NO_CONTENT);
+
+        let req = TestRequest::get()
+            .uri(&uri)
+            .header(PURPOSE_HEADER, "billing")
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::NOT_FOUND);
+    }
+
+    #[actix_rt::test]
+    async fn test_listener_retrieve_fields() {
+        let mut app = test::init_service(App::new().configure(configure_listener)).await;
+        let uid = 60000 + rand::random::<u16>() as usize;
+        save_for_retrieval(uid);
+
+        let req = TestRequest::get()
+            .uri(&format!(
+                "/order/clothing/iStore/{}?fields=/status,/missing",
+                uid
+            ))
+            .header(PURPOSE_HEADER, "billing")
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert!(resp.status().is_success());
+        assert_eq!(
+            String::from_utf8(test::read_body(resp).await.to_vec()).unwrap(),
+            r#"{"status":"new"}"#
+        );
+
+        let req = TestRequest::get()
+            .uri(&format!("/order/clothing/iStore/{}?fields=status", uid))
+            .header(PURPOSE_HEADER, "billing")
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_rt::test]
+    async fn test_listener_retrieve_with_policy() {
+        let policy = AccessPolicy::new()
+            .with_role("marketing".to_string(), vec!["campaigns".to_string()])
+            .with_member(MOCK_AUTHOR.to_string(), "marketing".to_string());
+        let mut app = test::init_service(
+            App::new()
+                .data(policy)
+                .data(AuditLog::new(InMemoryAuditSink::new()))
+                .configure(configure_listener),
+        )
+        .await;
+        let uid = 60000 + rand::random::<u16>() as usize;
+        save_for_retrieval(uid);
+
+        // the DaaS document has a billing agreement, but the requester isn't authorized for billing
+        let req = TestRequest::get()
+            .uri(&format!("/order/clothing/iStore/{}", uid))
+            .header(PURPOSE_HEADER, "billing")
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::FORBIDDEN);
+    }
+}